@@ -0,0 +1,53 @@
+/*
+ * C bindings of the ToyChain client, see src/ffi.rs. Link against the shared library built with
+ * `cargo rustc --lib --release --features cdylib --crate-type cdylib` (target/release/libtoychain.so, .dylib or .dll).
+ *
+ * Strings are NUL-terminated UTF-8 and amounts are decimal strings, e.g. "12.5". Every request returns a response,
+ * which has to be freed with toychain_response_free. A client must not be used by several threads at the same time.
+ */
+
+#ifndef TOYCHAIN_H
+#define TOYCHAIN_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* A client sending requests to a server node */
+typedef struct ToychainClient ToychainClient;
+
+/* The response of a server node to a request */
+typedef struct ToychainResponse {
+    /* Whether the node processed the request, false if it rejected it or the request couldn't be sent */
+    bool ok;
+    /* The message of the node, or why the request failed */
+    char *message;
+} ToychainResponse;
+
+/* Creates a client of the node at server_addr (HOST:PORT), null if an argument is invalid */
+ToychainClient *toychain_client_new(const char *server_addr, const char *node_id, uint64_t timeout_ms, uint32_t retries);
+
+/* Frees a client, null is ignored */
+void toychain_client_free(ToychainClient *client);
+
+/* Creates an account with a starting balance */
+ToychainResponse toychain_client_create_account(ToychainClient *client, const char *account_id, const char *starting_balance);
+
+/* Transfers an amount with the next nonce of the from account, the fee and memo may be null */
+ToychainResponse toychain_client_transfer(ToychainClient *client, const char *from_account_id, const char *to_account_id,
+                                          const char *amount, const char *fee, const char *memo);
+
+/* Gets the balance of an account, by its ID or alias */
+ToychainResponse toychain_client_get_balance(ToychainClient *client, const char *account_id);
+
+/* Frees the message of a response */
+void toychain_response_free(ToychainResponse response);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* TOYCHAIN_H */
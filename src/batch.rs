@@ -0,0 +1,71 @@
+use serde::Deserialize;
+use crate::common::{validate_memo, Amount};
+
+/// A transfer read from a batch file. Transfers without a nonce are made with the next nonce of their from account.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct BatchEntry {
+    pub from: String,
+    pub to: String,
+    pub amount: Amount,
+    #[serde(default)]
+    pub fee: Amount,
+    pub nonce: Option<u64>,
+    pub memo: Option<String>,
+}
+
+/// Reads the transfers of a batch from a file. Files ending in `.json` hold an array of transfers, e.g.
+/// `[{"from": "Alice", "to": "Bob", "amount": "12.5", "fee": "0.1", "memo": "Invoice 42"}]`, any other file is read
+/// as CSV with the columns `from,to,amount[,fee[,nonce[,memo]]]` and an optional header line. The memo is the rest of
+/// the line, so it may contain commas.
+pub fn load(path: &str) -> Result<Vec<BatchEntry>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Failed to read batch file {}: {}", path, e))?;
+
+    let entries: Vec<BatchEntry> = if path.ends_with(".json") {
+        serde_json::from_str(&text).map_err(|e| format!("Invalid batch file {}: {}", path, e))?
+    } else {
+        parse_csv(&text).map_err(|e| format!("Invalid batch file {}: {}", path, e))?
+    };
+
+    for (index, entry) in entries.iter().enumerate() {
+        if let Some(memo) = &entry.memo {
+            validate_memo(memo).map_err(|e| format!("Invalid batch file {}: transfer {}: {}", path, index + 1, e))?;
+        }
+    }
+    Ok(entries)
+}
+
+fn parse_csv(text: &str) -> Result<Vec<BatchEntry>, String> {
+    let mut entries = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let fields: Vec<&str> = line.splitn(6, ',').map(str::trim).collect();
+        // Skip empty lines and the header
+        if line.trim().is_empty() || (index == 0 && fields[0] == "from") {
+            continue;
+        }
+
+        let line_error = |e: String| format!("line {}: {}", index + 1, e);
+        if fields.len() < 3 {
+            return Err(line_error("expected from,to,amount[,fee[,nonce[,memo]]]".to_string()));
+        }
+        let fee = match fields.get(3) {
+            Some(fee) if !fee.is_empty() => fee.parse().map_err(line_error)?,
+            _ => Amount::ZERO,
+        };
+        let nonce = match fields.get(4) {
+            Some(nonce) if !nonce.is_empty() => Some(nonce.parse().map_err(|_| line_error(format!("Invalid nonce {}.", nonce)))?),
+            _ => None,
+        };
+        let memo = fields.get(5).filter(|memo| !memo.is_empty()).map(|memo| memo.to_string());
+
+        entries.push(BatchEntry {
+            from: fields[0].to_string(),
+            to: fields[1].to_string(),
+            amount: fields[2].parse().map_err(line_error)?,
+            fee,
+            nonce,
+            memo,
+        });
+    }
+    Ok(entries)
+}
@@ -0,0 +1,367 @@
+//! The load generator of `toychain bench`. Every synthetic client first creates a funded account and waits until it is
+//! minted, then sends a mix of requests at its share of the target rate for the duration of the run: 2 in 10 create
+//! an empty account, 5 in 10 transfer a small amount to the account of the next client and 3 in 10 ask for a
+//! balance. Every client has its own socket and session, so the clients send their requests concurrently. The report
+//! shows the throughput the node achieved, the latency percentiles of every kind of request and the errors.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use crate::client::Client;
+use crate::common::{random_id, Amount, Request};
+use crate::output;
+
+/// Balance every client's account is created with
+const FUNDING: Amount = Amount::from_minor_units(100_000_000);
+
+/// Amount of every transfer
+const TRANSFER_AMOUNT: Amount = Amount::from_minor_units(1);
+
+/// Time the accounts of the clients may take to be minted before the run is given up
+const SETUP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Time between two checks whether the accounts of the clients are minted
+const SETUP_POLL: Duration = Duration::from_millis(500);
+
+/// Number of distinct errors shown in the report
+const SHOWN_ERRORS: usize = 5;
+
+/// The kind of a request of the run
+#[derive(Clone, Copy)]
+enum Op {
+    Create,
+    Transfer,
+    Balance,
+}
+
+impl Op {
+    const ALL: [Op; 3] = [Op::Create, Op::Transfer, Op::Balance];
+
+    /// Returns the kind of the `count`th request of a client
+    fn nth(count: u64) -> Op {
+        match count % 10 {
+            0 | 1 => Op::Create,
+            2..=6 => Op::Transfer,
+            _ => Op::Balance,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Op::Create => "create",
+            Op::Transfer => "transfer",
+            Op::Balance => "balance",
+        }
+    }
+}
+
+/// The outcome of the requests of one kind
+#[derive(Default)]
+struct OpStats {
+    // Latencies of the requests the node answered, accepted or rejected
+    latencies: Vec<Duration>,
+    // Requests the node rejected
+    rejected: u64,
+    // Requests that weren't answered
+    failed: u64,
+}
+
+/// The outcome of the requests of a client, or of all clients once merged
+#[derive(Default)]
+struct Stats {
+    // By kind, in the order of [Op::ALL]
+    ops: [OpStats; 3],
+    // Number of times every error occurred, see [error_kind]
+    errors: HashMap<String, u64>,
+}
+
+impl Stats {
+    fn record(&mut self, op: Op, latency: Duration, result: &Result<Result<String, String>, String>) {
+        let stats = &mut self.ops[op as usize];
+        let error = match result {
+            Ok(Ok(_)) => None,
+            Ok(Err(e)) => {
+                stats.rejected += 1;
+                Some(e)
+            }
+            Err(e) => {
+                stats.failed += 1;
+                Some(e)
+            }
+        };
+        if let Some(e) = error {
+            *self.errors.entry(error_kind(e)).or_default() += 1;
+        }
+        if result.is_ok() {
+            stats.latencies.push(latency);
+        }
+    }
+
+    fn merge(&mut self, other: Stats) {
+        for (stats, other) in self.ops.iter_mut().zip(other.ops) {
+            stats.latencies.extend(other.latencies);
+            stats.rejected += other.rejected;
+            stats.failed += other.failed;
+        }
+        for (error, count) in other.errors {
+            *self.errors.entry(error).or_default() += count;
+        }
+    }
+}
+
+/// Groups errors that only differ in IDs and amounts, by replacing every word with a digit by `…`
+fn error_kind(error: &str) -> String {
+    error.split(' ').map(|word| if word.contains(|c: char| c.is_ascii_digit()) { "…" } else { word }).collect::<Vec<_>>().join(" ")
+}
+
+/// Returns the `percentile`th percentile of sorted latencies, in milliseconds
+fn percentile(sorted: &[Duration], percentile: usize) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        len => sorted[((len * percentile).div_ceil(100)).clamp(1, len) - 1].as_secs_f64() * 1000.0,
+    }
+}
+
+/// A synthetic client of the run
+struct BenchClient<'a> {
+    client: Client,
+    node_id: &'a str,
+    account_id: String,
+    // Account the client transfers to, the one of the next client
+    to_account_id: String,
+    // Nonce of the next transfer, None if it has to be fetched from the node
+    nonce: Option<u64>,
+    // Number of requests sent
+    count: u64,
+}
+
+impl BenchClient<'_> {
+    /// Sends the next request of the mix
+    fn send_next(&mut self, stats: &mut Stats) {
+        let op = Op::nth(self.count);
+        let request = match op {
+            Op::Create => {
+                let account_id = format!("{}-{}", self.account_id, self.count);
+                Request::new_create_account_request(self.node_id.to_string(), account_id, Amount::ZERO)
+            }
+            Op::Transfer => {
+                let nonce = match self.nonce {
+                    Some(nonce) => nonce,
+                    None => match self.fetch_nonce() {
+                        Ok(nonce) => nonce,
+                        Err(e) => {
+                            stats.record(op, Duration::ZERO, &Err(e));
+                            self.count += 1;
+                            return;
+                        }
+                    },
+                };
+                let (from, to) = (self.account_id.clone(), self.to_account_id.clone());
+                self.nonce = Some(nonce);
+                Request::new_transfer_funds_request(self.node_id.to_string(), from, to, TRANSFER_AMOUNT, Amount::ZERO, nonce, None)
+            }
+            Op::Balance => Request::new_get_funds_request(self.node_id.to_string(), self.account_id.clone()),
+        };
+        self.count += 1;
+
+        let start = Instant::now();
+        let result = self.client.send(&request).map(|response| response.result);
+        stats.record(op, start.elapsed(), &result);
+        if let Op::Transfer = op {
+            // The nonce is only used up by an accepted transfer, after anything else it is fetched again
+            self.nonce = match result {
+                Ok(Ok(_)) => self.nonce.map(|nonce| nonce + 1),
+                _ => None,
+            };
+        }
+    }
+
+    fn fetch_nonce(&mut self) -> Result<u64, String> {
+        let request = Request::new_get_nonce_request(self.node_id.to_string(), self.account_id.clone());
+        let nonce = self.client.send(&request)?.result?;
+        nonce.parse::<u64>().map_err(|_| format!("❌ Invalid nonce {} from the server.", nonce))
+    }
+
+    /// Whether the account of the client is minted with its funding
+    fn is_funded(&mut self) -> Result<bool, String> {
+        let request = Request::new_get_funds_request(self.node_id.to_string(), self.account_id.clone());
+        let message = self.client.send(&request)?.result?;
+        let balance = message.rsplit(' ').next().map(|balance| balance.trim_end_matches('.'));
+        Ok(balance.and_then(|balance| balance.parse::<Amount>().ok()).is_some_and(|balance| balance > Amount::ZERO))
+    }
+
+    /// Sends requests at the given interval until the deadline
+    fn run(mut self, interval: Duration, deadline: Instant) -> Stats {
+        let mut stats = Stats::default();
+        let mut next = Instant::now();
+        while next < deadline {
+            let now = Instant::now();
+            if next > now {
+                std::thread::sleep(next - now);
+            } else if now - next > interval {
+                // A client that fell behind doesn't burst to make up for it, the node is already saturated
+                next = now;
+            }
+            self.send_next(&mut stats);
+            next += interval;
+        }
+        stats
+    }
+}
+
+/// Runs the benchmark and prints its report
+///
+/// # Arguments
+///
+/// * `node_id`: the node ID of the client
+/// * `clients`: a client for every synthetic client of the run
+/// * `tps`: requests per second to send in total, 0 to send them as fast as the node answers
+/// * `duration`: how long to send requests for, after the accounts of the clients are minted
+///
+/// Returns: an error if the accounts of the clients couldn't be created
+pub fn run(node_id: &str, clients: Vec<Client>, tps: u64, duration: Duration) -> Result<(), String> {
+    let run_id = format!("{:06x}", random_id() & 0xff_ffff);
+    let count = clients.len();
+    let mut clients: Vec<BenchClient> = clients
+        .into_iter()
+        .enumerate()
+        .map(|(i, client)| BenchClient {
+            client,
+            node_id,
+            account_id: format!("bench-{}-{}", run_id, i),
+            to_account_id: format!("bench-{}-{}", run_id, (i + 1) % count),
+            nonce: None,
+            count: 0,
+        })
+        .collect();
+
+    output::progress(format!("Creating {} funded accounts bench-{}-*.", count, run_id));
+    for client in &mut clients {
+        let request = Request::new_create_account_request(node_id.to_string(), client.account_id.clone(), FUNDING);
+        if let Err(e) = client.client.send(&request)?.result {
+            return Err(format!("❌ Failed to create account {}: {}", client.account_id, e));
+        }
+    }
+    output::progress("Waiting for the accounts to be minted.");
+    let setup_deadline = Instant::now() + SETUP_TIMEOUT;
+    let mut unfunded: Vec<usize> = (0..count).collect();
+    while !unfunded.is_empty() {
+        if Instant::now() > setup_deadline {
+            return Err(format!("❌ The accounts weren't minted within {} seconds, is the node minting blocks?", SETUP_TIMEOUT.as_secs()));
+        }
+        std::thread::sleep(SETUP_POLL);
+        let mut still_unfunded = Vec::new();
+        for i in unfunded {
+            if !clients[i].is_funded()? {
+                still_unfunded.push(i);
+            }
+        }
+        unfunded = still_unfunded;
+    }
+
+    output::progress(format!(
+        "Sending {} for {} seconds with {} clients.",
+        if tps == 0 { "requests as fast as possible".to_string() } else { format!("{} requests per second", tps) },
+        duration.as_secs(),
+        count
+    ));
+    // Every client sends its share of the requests
+    let interval = if tps == 0 { Duration::ZERO } else { Duration::from_secs_f64(count as f64 / tps as f64) };
+    let start = Instant::now();
+    let deadline = start + duration;
+    let mut stats = Stats::default();
+    std::thread::scope(|scope| {
+        let threads: Vec<_> = clients.into_iter().map(|client| scope.spawn(move || client.run(interval, deadline))).collect();
+        for thread in threads {
+            stats.merge(thread.join().expect("Benchmark client panicked."));
+        }
+    });
+    report(&mut stats, tps, start.elapsed());
+    Ok(())
+}
+
+/// Prints the report of a run
+fn report(stats: &mut Stats, tps: u64, elapsed: Duration) {
+    let mut all: Vec<Duration> = stats.ops.iter().flat_map(|op| op.latencies.iter().copied()).collect();
+    all.sort();
+    let rejected: u64 = stats.ops.iter().map(|op| op.rejected).sum();
+    let failed: u64 = stats.ops.iter().map(|op| op.failed).sum();
+    let total = all.len() as u64 + failed;
+    let succeeded = all.len() as u64 - rejected;
+    let throughput = total as f64 / elapsed.as_secs_f64();
+    let error_rate = if total == 0 { 0.0 } else { (rejected + failed) as f64 * 100.0 / total as f64 };
+    // In milliseconds, to the microsecond
+    let latency_json = |sorted: &[Duration]| {
+        let millis = |percentile_of: usize| (percentile(sorted, percentile_of) * 1000.0).round() / 1000.0;
+        json!({ "p50": millis(50), "p90": millis(90), "p99": millis(99), "max": millis(100) })
+    };
+    let latency_text = |sorted: &[Duration]| {
+        format!(
+            "p50 {:.1} ms, p90 {:.1} ms, p99 {:.1} ms, max {:.1} ms",
+            percentile(sorted, 50),
+            percentile(sorted, 90),
+            percentile(sorted, 99),
+            percentile(sorted, 100)
+        )
+    };
+
+    let mut text = format!(
+        "Benchmark finished after {:.1}s:\n- Requests: {} ({:.1} per second{})\n- Succeeded: {}, rejected: {}, failed: {} ({:.1}% errors)\n- Latency: {}",
+        elapsed.as_secs_f64(),
+        total,
+        throughput,
+        if tps == 0 { String::new() } else { format!(", target {}", tps) },
+        succeeded,
+        rejected,
+        failed,
+        error_rate,
+        latency_text(&all)
+    );
+    let mut ops = serde_json::Map::new();
+    for op in Op::ALL {
+        let op_stats = &mut stats.ops[op as usize];
+        op_stats.latencies.sort();
+        let requests = op_stats.latencies.len() as u64 + op_stats.failed;
+        text.push_str(&format!(
+            "\n- {}: {} requests, {} rejected, {} failed, {}",
+            op.name(),
+            requests,
+            op_stats.rejected,
+            op_stats.failed,
+            latency_text(&op_stats.latencies)
+        ));
+        ops.insert(
+            op.name().to_string(),
+            json!({
+                "requests": requests,
+                "rejected": op_stats.rejected,
+                "failed": op_stats.failed,
+                "latency_ms": latency_json(&op_stats.latencies),
+            }),
+        );
+    }
+
+    let mut errors: Vec<(String, u64)> = stats.errors.drain().collect();
+    errors.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    for (error, count) in errors.iter().take(SHOWN_ERRORS) {
+        text.push_str(&format!("\n- {}x {}", count, error));
+    }
+    let errors: Vec<Value> = errors.iter().map(|(error, count)| json!({ "message": error, "count": count })).collect();
+
+    output::fields(
+        text,
+        json!({
+            "seconds": elapsed.as_secs_f64(),
+            "requests": total,
+            "requests_per_second": throughput,
+            "target_requests_per_second": tps,
+            "succeeded": succeeded,
+            "rejected": rejected,
+            "failed": failed,
+            "error_rate": error_rate / 100.0,
+            "latency_ms": latency_json(&all),
+            "operations": ops,
+            "errors": errors,
+        }),
+    );
+}
@@ -0,0 +1,339 @@
+use std::cell::RefCell;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+use snow::StatelessTransportState;
+use crate::common::{random_id, Compression, Format, Request, Response, MAX_MESSAGE_SIZE};
+use crate::fragment::{self, Reassembler, MAX_DATAGRAM_SIZE};
+use crate::noise::{self, PacketKind, MAX_OVERHEAD};
+use crate::shard::ShardMap;
+
+/// An encrypted session with the server, see [crate::noise]
+struct Session {
+    // Server key the session has to be established with, any key is accepted if not given
+    server_key: Option<Vec<u8>>,
+    // Private static key the client authenticates with, e.g. to a node in permissioned mode, a new one is generated
+    // for every handshake if not given
+    client_key: Option<Vec<u8>>,
+    // Keys of the session, None until the handshake is done with the first request
+    transport: Option<StatelessTransportState>,
+    // Nonce of the next request
+    next_nonce: u64,
+}
+
+/// Client sending requests to a server node over UDP, in plaintext or over an encrypted session. Requests of one client
+/// share a single socket and session.
+///
+/// Requests that aren't answered within the timeout are retried with the same request ID, so the server answers a
+/// retry of a request it already processed with the original response instead of processing it again. The timeout
+/// doubles with every retry.
+///
+/// Requests and responses that don't fit into a single datagram are sent in fragments, see [crate::fragment]. Large
+/// responses are compressed unless the client asks for uncompressed responses, and decompressed when they arrive.
+pub struct Client {
+    socket: UdpSocket,
+    server_addr: String,
+    session: Option<Session>,
+    // Fragments of responses received so far
+    fragments: RefCell<Reassembler>,
+    // Time to wait for the response to the first attempt of a request
+    timeout: Duration,
+    // Number of times a request is retried before giving up
+    retries: u32,
+    // Format requests are sent in, the server responds in the same format
+    format: Format,
+    // Compression the client accepts responses in
+    compression: Compression,
+    // Whether progress messages, e.g. about retries, are printed
+    verbose: bool,
+    // Chain on the server the requests are for, unless a request names its chain. The default chain of the server if
+    // None.
+    chain: Option<String>,
+    // Shard map of a sharded cluster, requests about an account are sent to the node of its shard instead of the server
+    shard_map: Option<ShardMap>,
+    // Address the current request is sent to, the server or the node of the shard of its account
+    target: String,
+}
+
+impl Client {
+    /// Returns a client sending plaintext requests to the server at the given address
+    pub fn new(server_addr: String, timeout: Duration, retries: u32) -> Client {
+        // UDP socket to send requests to the server. Port 0 = any available port, of the address family of the server
+        let ipv6 = server_addr.to_socket_addrs().ok().and_then(|mut addrs| addrs.next()).is_some_and(|addr| addr.is_ipv6());
+        let socket = UdpSocket::bind(if ipv6 { "[::]:0" } else { "0.0.0.0:0" }).expect("Failed to bind to address.");
+        Client {
+            socket,
+            target: server_addr.clone(),
+            server_addr,
+            session: None,
+            fragments: RefCell::new(Reassembler::default()),
+            timeout,
+            retries,
+            format: Format::Bincode,
+            compression: Compression::Zstd,
+            verbose: true,
+            chain: None,
+            shard_map: None,
+        }
+    }
+
+    /// Sends the requests in the given format instead of bincode, e.g. to debug requests in JSON
+    pub fn with_format(mut self, format: Format) -> Client {
+        self.format = format;
+        self
+    }
+
+    /// Asks the server for uncompressed responses, e.g. to inspect the responses on the wire
+    pub fn uncompressed(mut self) -> Client {
+        self.compression = Compression::None;
+        self
+    }
+
+    /// Sends the requests to the named chain of the server instead of its default chain
+    pub fn on_chain(mut self, chain: Option<String>) -> Client {
+        self.chain = chain;
+        self
+    }
+
+    /// Sends the requests about an account to the node of its shard in the shard map, see [crate::shard]. Requests
+    /// about the chain or node as a whole still go to the server.
+    pub fn sharded(mut self, shard_map: ShardMap) -> Client {
+        self.shard_map = Some(shard_map);
+        self
+    }
+
+    /// Stops the client from printing progress messages, for requests a node sends to its peer
+    pub fn quiet(mut self) -> Client {
+        self.verbose = false;
+        self
+    }
+
+    /// Returns the address of the server the client sends its requests to
+    pub fn server_addr(&self) -> &str {
+        &self.server_addr
+    }
+
+    fn progress(&self, message: &str) {
+        if self.verbose {
+            println!("{}", message);
+        }
+    }
+
+    /// Switches the client to encrypted requests, if a server key is given the session is only established with a
+    /// server that has this key. If a client key is given, the client authenticates with it.
+    pub fn encrypted(mut self, server_key: Option<Vec<u8>>, client_key: Option<Vec<u8>>) -> Client {
+        self.session = Some(Session {
+            server_key,
+            client_key,
+            transport: None,
+            next_nonce: 1,
+        });
+        self
+    }
+
+    /// Sends a request to the server and waits for its response, retrying it if no response arrives in time
+    ///
+    /// Returns: the response from the server, or a user-facing error if no response was received
+    pub fn send(&mut self, request: &Request) -> Result<Response, String> {
+        // Serialize the request once, so every attempt is the same request
+        let request_bytes = request.encode_for_chain(request.chain.as_deref().or(self.chain.as_deref()), self.format, self.compression);
+        if request_bytes.len() > MAX_MESSAGE_SIZE {
+            return Err(format!("❌ The request is too large ({} bytes, at most {}), e.g. split a batch into smaller batches.", request_bytes.len(), MAX_MESSAGE_SIZE));
+        }
+
+        let shard_node = self.shard_map.as_ref().zip(request.operation.account()).map(|(shard_map, account_id)| shard_map.node_of(account_id));
+        self.target = shard_node.unwrap_or(&self.server_addr).to_string();
+
+        let mut timeout = self.timeout;
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                self.progress(&format!("No response from server after {} ms, retrying ({}/{}).", timeout.as_millis(), attempt, self.retries));
+                timeout *= 2;
+            }
+
+            let deadline = Instant::now() + timeout;
+            let response = match self.session.take() {
+                None => self.send_plain(&request_bytes, request.request_id, deadline),
+                Some(mut session) => {
+                    let response = self.send_encrypted(&mut session, &request_bytes, request.request_id, deadline);
+                    self.session = Some(session);
+                    response
+                }
+            };
+            if let Some(response) = response? {
+                return Ok(response);
+            }
+        }
+
+        Err(format!(
+            "No response from server after {} attempts. Make sure the server is running (use `start-node`).",
+            self.retries + 1
+        ))
+    }
+
+    /// Sends a plaintext request
+    ///
+    /// Returns: the response, or None if it didn't arrive before the deadline
+    fn send_plain(&self, request: &[u8], request_id: Option<u64>, deadline: Instant) -> Result<Option<Response>, String> {
+        self.send_packet(request);
+        self.progress("Request sent to server.");
+
+        while let Some(packet) = self.receive(deadline)? {
+            // Responses to earlier requests, e.g. to a retry that was answered late, are skipped
+            let response = Response::decode(&packet)?;
+            if is_response_to(&response, request_id) {
+                return Ok(Some(response));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sends an encrypted request, establishing the session first if it isn't yet. Every attempt is encrypted with a
+    /// new nonce, since the server rejects nonces it has seen before.
+    ///
+    /// Returns: the response, or None if it didn't arrive before the deadline
+    fn send_encrypted(&self, session: &mut Session, request: &[u8], request_id: Option<u64>, deadline: Instant) -> Result<Option<Response>, String> {
+        let Some(transport) = &session.transport else {
+            return self.handshake(session, request, request_id, deadline);
+        };
+
+        let nonce = session.next_nonce;
+        let mut buf = [0u8; MAX_MESSAGE_SIZE + MAX_OVERHEAD];
+        let len = transport.write_message(nonce, request, &mut buf).map_err(|e| format!("❌ Failed to encrypt the request: {}", e))?;
+        self.send_packet(&noise::encode_transport(nonce, &buf[..len]));
+        self.progress("Request sent to server.");
+
+        let response = self.receive_encrypted(transport, request_id, deadline);
+        session.next_nonce += 1;
+        response
+    }
+
+    /// Establishes the session with the server, sending the first request with the last handshake message. If the
+    /// handshake doesn't finish before the deadline, the next attempt starts a new one.
+    ///
+    /// Returns: the response, or None if it didn't arrive before the deadline
+    fn handshake(&self, session: &mut Session, request: &[u8], request_id: Option<u64>, deadline: Instant) -> Result<Option<Response>, String> {
+        let private_key = match &session.client_key {
+            Some(client_key) => client_key.clone(),
+            None => noise::builder().generate_keypair().expect("Failed to generate the client key.").private,
+        };
+        let mut handshake = noise::builder()
+            .local_private_key(&private_key)
+            .and_then(|builder| builder.build_initiator())
+            .expect("Failed to start the handshake.");
+
+        let mut buf = [0u8; MAX_MESSAGE_SIZE + MAX_OVERHEAD];
+        let len = handshake.write_message(&[], &mut buf).expect("Failed to write the handshake.");
+        self.send_packet(&noise::encode(PacketKind::Handshake1, &buf[..len]));
+
+        let payload = loop {
+            let Some(packet) = self.receive(deadline)? else {
+                return Ok(None);
+            };
+            if let Some(payload) = expect_packet(&packet, PacketKind::Handshake2)? {
+                break payload;
+            }
+        };
+        handshake.read_message(&payload, &mut buf).map_err(|e| format!("❌ Invalid handshake from the server: {}", e))?;
+
+        // The server key is checked before sending the request, so the request is never sent to an unexpected server
+        let server_key = handshake.get_remote_static().expect("Handshake without server key.");
+        match &session.server_key {
+            Some(expected) if expected.as_slice() != server_key => {
+                return Err(format!("❌ Server key {} doesn't match the expected key {}.", noise::to_hex(server_key), noise::to_hex(expected)));
+            }
+            Some(_) => {}
+            None => self.progress(&format!("Encrypted session with server key {}.", noise::to_hex(server_key))),
+        }
+
+        let len = handshake.write_message(request, &mut buf).map_err(|e| format!("❌ Failed to encrypt the request: {}", e))?;
+        self.send_packet(&noise::encode(PacketKind::Handshake3, &buf[..len]));
+        self.progress("Request sent to server.");
+        let transport = handshake.into_stateless_transport_mode().expect("Handshake not finished.");
+
+        let response = self.receive_encrypted(&transport, request_id, deadline)?;
+        // The session is only kept once the server confirmed it with a response
+        if response.is_some() {
+            session.transport = Some(transport);
+        }
+        Ok(response)
+    }
+
+    /// Receives the encrypted response to a request. Any nonce is accepted, as the response may be to an earlier
+    /// attempt of the request, and the request ID tells it apart from responses to earlier requests.
+    ///
+    /// Returns: the response, or None if it didn't arrive before the deadline
+    fn receive_encrypted(&self, transport: &StatelessTransportState, request_id: Option<u64>, deadline: Instant) -> Result<Option<Response>, String> {
+        while let Some(packet) = self.receive(deadline)? {
+            let Some(payload) = expect_packet(&packet, PacketKind::Transport)? else {
+                continue;
+            };
+            let (nonce, ciphertext) = noise::decode_transport(&payload).ok_or("❌ Invalid encrypted response from the server.")?;
+
+            // Responses that can't be decrypted, e.g. to the session of an earlier handshake attempt, are skipped
+            let mut plaintext = vec![0u8; ciphertext.len()];
+            let Ok(len) = transport.read_message(nonce, ciphertext, &mut plaintext) else {
+                continue;
+            };
+            let response = Response::decode(&plaintext[..len])?;
+            if is_response_to(&response, request_id) {
+                return Ok(Some(response));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sends a packet to the server, in fragments if it doesn't fit into a single datagram
+    fn send_packet(&self, packet: &[u8]) {
+        for datagram in fragment::split(packet, random_id()) {
+            self.socket
+                .send_to(&datagram, &self.target)
+                .expect("Failed to send message.");
+        }
+    }
+
+    /// Receives the next packet from the server, reassembling it if it was sent in fragments
+    ///
+    /// Returns: the packet, or None if none arrived before the deadline
+    fn receive(&self, deadline: Instant) -> Result<Option<Vec<u8>>, String> {
+        let mut buf = [0; MAX_DATAGRAM_SIZE];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Ok(None);
+            }
+            self.socket.set_read_timeout(Some(remaining)).expect("Failed to set the receive timeout.");
+
+            let (datagram, src) = match self.socket.recv_from(&mut buf) {
+                Ok((amt, src)) => (&buf[..amt], src),
+                Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => return Ok(None),
+                Err(e) => return Err(format!("Failed to receive response. Make sure the server is running (use `start-node`).\nError: {}", e)),
+            };
+            if !fragment::is_fragment(datagram) {
+                return Ok(Some(datagram.to_vec()));
+            }
+            // Invalid fragments are skipped like other unexpected packets
+            if let Ok(Some(packet)) = self.fragments.borrow_mut().add(src, datagram) {
+                return Ok(Some(packet));
+            }
+        }
+    }
+}
+
+/// Checks whether a response is to the request with the given ID. Responses without an ID, e.g. from older servers or
+/// rate limit errors, are to the request that was just sent.
+fn is_response_to(response: &Response, request_id: Option<u64>) -> bool {
+    response.request_id.is_none() || response.request_id == request_id
+}
+
+/// Gets the payload of a packet of the expected kind. Errors sent by the server, e.g. when the client is rate limited
+/// or the session expired, are returned as the error.
+///
+/// Returns: None for packets of other kinds, e.g. late responses to an earlier attempt
+fn expect_packet(packet: &[u8], expected: PacketKind) -> Result<Option<Vec<u8>>, String> {
+    match noise::decode(packet) {
+        Some((kind, payload)) if kind == expected => Ok(Some(payload.to_vec())),
+        Some((PacketKind::Error, message)) => Err(String::from_utf8_lossy(message).into_owned()),
+        Some(_) => Ok(None),
+        None => Err("❌ Invalid encrypted response from the server.".to_string()),
+    }
+}
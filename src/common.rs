@@ -1,71 +1,2252 @@
-use serde::{Deserialize, Serialize};
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::keys;
+use legacy::{RequestV1, RequestV2, RequestV3, RequestV7, RequestV8, ResponseV2, ResponseV9};
+
+pub use codec::Format;
+
+mod codec;
+mod legacy;
+
+/// Maximum size in bytes of a request or response. Messages larger than a datagram are split into fragments, see
+/// [crate::fragment].
+pub const MAX_MESSAGE_SIZE: usize = 60 * 1024;
+
+/// Maximum size in bytes of a request or response of protocol versions before 5, which had to fit into a single
+/// datagram
+pub const LEGACY_MAX_MESSAGE_SIZE: usize = 1024;
+
+/// Number of bytes a response adds to its message: the version prefix, request ID and result
+pub const RESPONSE_OVERHEAD: usize = 32;
+
+/// Maximum length in bytes of an account ID
+pub const MAX_ACCOUNT_ID_LENGTH: usize = 64;
+
+/// Maximum length in bytes of the memo of a transfer
+pub const MAX_MEMO_LENGTH: usize = 100;
+
+/// Maximum starting balance of a new account, a billion units, so the balances created with accounts can't add up to
+/// more than an amount can hold
+pub const MAX_STARTING_BALANCE: Amount = Amount::from_minor_units(1_000_000_000 * 100);
+
+/// Maximum length in bytes of an account alias
+pub const MAX_ALIAS_LENGTH: usize = 32;
+
+/// Maximum length in bytes of the name of a chain hosted by a node
+pub const MAX_CHAIN_NAME_LENGTH: usize = 32;
+
+/// Maximum length in bytes of the symbol of a token
+pub const MAX_TOKEN_LENGTH: usize = 8;
+
+/// Maximum length in bytes of the metadata of an NFT
+pub const MAX_NFT_METADATA_LENGTH: usize = 256;
+
+/// Version of the request and response format. Version 1 is the original format of requests without a version and
+/// plain string responses, version 2 added the version, version 3 the request ID, version 4 transfer memos and version
+/// 5 fragmentation of messages larger than a datagram, version 6 the compression flags in the header and version 7 the
+/// format tag in the header, version 8 the name of the chain a request is for, version 9 whether the result of a query
+/// is structured and version 10 the error code of a response.
+pub const PROTOCOL_VERSION: u16 = 10;
+
+/// Oldest protocol version the server still accepts, requests of older versions down to this one are converted to the
+/// current version so that clients keep working while nodes are upgraded
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+
+/// Prefix of versioned requests and responses, followed by the protocol version as a 16-bit integer and the message in
+/// the format of that version. Version 1 requests start with the length of the requestor's node ID as a 64-bit integer
+/// instead, whose third byte is always 0 for requests that fit into a message. Messages of version 6 and later follow
+/// the version with a byte of compression flags, see [Compression], and messages of version 7 and later the flags with
+/// the tag of their format, see [Format].
+const VERSION_MAGIC: &[u8; 3] = b"TCV";
+
+/// Minimum size in bytes of a response that is compressed, smaller responses fit into a datagram either way
+pub const COMPRESSION_THRESHOLD: usize = 1024;
+
+/// Maximum size in bytes of a decompressed response. Messages in JSON can be larger than their bincode, e.g. for the
+/// escaped line breaks of a history page, and are decompressed up to twice the size of a message.
+const MAX_DECOMPRESSED_SIZE: usize = 2 * MAX_MESSAGE_SIZE;
+
+/// The zstd level responses are compressed with, responses are compressed while the client waits for them
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Compression of a response, negotiated by the flags in the message header. A request says which compression the
+/// client accepts the response in, and the response which compression its message is compressed with. Messages of
+/// versions before 6 don't have the flags and are never compressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+impl Compression {
+    fn flags(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+        }
+    }
+
+    /// Unknown flags, e.g. of compressions added by later versions, are read as no compression
+    fn from_flags(flags: u8) -> Compression {
+        match flags & 1 {
+            1 => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// An amount of funds as a fixed-point number of minor units (1.00 is 100 minor units), so that adding up amounts
+/// never accumulates rounding errors. Amounts are parsed from and formatted as decimal strings at the edges, e.g.
+/// `"12.34"`. Balances use the same type and are signed, parsed amounts are never negative.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(i64);
+
+impl Amount {
+    /// Number of decimal places of an amount
+    pub const DECIMALS: usize = 2;
+    const MINOR_UNITS_PER_UNIT: i64 = 10i64.pow(Amount::DECIMALS as u32);
+
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(i64::MAX);
+
+    pub fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    /// Returns the amount as a number of minor units, e.g. to weigh amounts against each other
+    pub fn minor_units(self) -> i64 {
+        self.0
+    }
+
+    pub const fn from_minor_units(minor_units: i64) -> Amount {
+        Amount(minor_units)
+    }
+
+    /// Returns: None if the sum doesn't fit into an amount
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    /// Returns: None if the difference doesn't fit into an amount
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    /// Adds up amounts that can overflow, e.g. the balances of all accounts
+    ///
+    /// Returns: None if the sum doesn't fit into an amount
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, Amount::checked_add)
+    }
+}
+
+// The operators panic on overflow in all builds instead of wrapping in release builds, and are only used for amounts
+// bounded by the balances they are spent from. Amounts credited to a balance are added with `checked_add`.
+impl std::ops::Add for Amount {
+    type Output = Amount;
+
+    fn add(self, other: Amount) -> Amount {
+        self.checked_add(other).expect("Amount overflowed")
+    }
+}
+
+impl std::ops::Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, other: Amount) -> Amount {
+        self.checked_sub(other).expect("Amount overflowed")
+    }
+}
+
+impl std::ops::AddAssign for Amount {
+    fn add_assign(&mut self, other: Amount) {
+        *self = *self + other;
+    }
+}
+
+impl std::ops::SubAssign for Amount {
+    fn sub_assign(&mut self, other: Amount) {
+        *self = *self - other;
+    }
+}
+
+impl std::iter::Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        iter.fold(Amount::ZERO, |total, amount| total + amount)
+    }
+}
+
+impl std::fmt::Display for Amount {
+    /// Formats the amount as a decimal number without trailing zeros, e.g. `12.5` or `100`
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let units = self.0.unsigned_abs() / Amount::MINOR_UNITS_PER_UNIT as u64;
+        let minor_units = self.0.unsigned_abs() % Amount::MINOR_UNITS_PER_UNIT as u64;
+        if minor_units == 0 {
+            return write!(f, "{}{}", sign, units);
+        }
+
+        let fraction = format!("{:0width$}", minor_units, width = Amount::DECIMALS);
+        write!(f, "{}{}.{}", sign, units, fraction.trim_end_matches('0'))
+    }
+}
+
+impl std::fmt::Debug for Amount {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::str::FromStr for Amount {
+    type Err = String;
+
+    /// Parses a non-negative decimal number with at most `DECIMALS` decimal places, e.g. `12`, `12.5` or `12.34`
+    fn from_str(text: &str) -> Result<Amount, String> {
+        let invalid = || format!("Invalid amount {}, expected a non-negative number with at most {} decimal places.", text, Amount::DECIMALS);
+
+        let (units, fraction) = text.split_once('.').unwrap_or((text, ""));
+        let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if units.is_empty() || !all_digits(units) || !all_digits(fraction) || fraction.len() > Amount::DECIMALS {
+            return Err(invalid());
+        }
+
+        let units = units.parse::<i64>().map_err(|_| invalid())?;
+        let fraction = format!("{:0<width$}", fraction, width = Amount::DECIMALS).parse::<i64>().map_err(|_| invalid())?;
+        units
+            .checked_mul(Amount::MINOR_UNITS_PER_UNIT)
+            .and_then(|minor_units| minor_units.checked_add(fraction))
+            .map(Amount)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Serialize for Amount {
+    /// Amounts are sent as minor units in binary formats (bincode) and as decimal strings in human-readable formats
+    /// (JSON) so that they can't lose precision in other languages
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        if !deserializer.is_human_readable() {
+            return i64::deserialize(deserializer).map(Amount);
+        }
+
+        // Human-readable formats accept decimal strings as well as plain numbers, e.g. "12.34" or 12.34
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum DecimalAmount {
+            Text(String),
+            Integer(u64),
+            Float(f64),
+        }
+
+        let text = match DecimalAmount::deserialize(deserializer)? {
+            DecimalAmount::Text(text) => text,
+            DecimalAmount::Integer(integer) => integer.to_string(),
+            DecimalAmount::Float(float) => float.to_string(),
+        };
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum Operation {
     CreateAccount(AccountCreationOp),
     TransferFunds(FundTransferOp),
     GetFunds(GetFundsOp),
+    GetTransaction(GetTransactionOp),
+    GetNonce(GetNonceOp),
+    History(HistoryOp),
+    // Admin operation that shuts the node down cleanly
+    Shutdown,
+    BatchTransfer(BatchTransferOp),
+    // Reports the height, mempool and uptime of the node, so operators can check on it
+    Status,
+    // Creates an account whose transfers have to be approved by a number of its keys
+    CreateMultisigAccount(MultisigAccountCreationOp),
+    // Approves a transfer from a multisig account with the signature of one of its keys
+    ApproveTransfer(ApproveTransferOp),
+    // Transfers the whole balance of an account to another account and closes it
+    CloseAccount(CloseAccountOp),
+    // Admin operation that stops transfers from an account until it is unfrozen
+    FreezeAccount(AdminAccountOp),
+    // Admin operation that allows transfers from a frozen account again
+    UnfreezeAccount(AdminAccountOp),
+    // Lists the transactions in the mempool, e.g. to check that a transfer was accepted before it is minted
+    GetPending(GetPendingOp),
+    // Removes a transaction that wasn't minted yet, signed with a key of its from account
+    CancelTransaction(CancelTransactionOp),
+    // Validates a transfer like `TransferFunds` without making it
+    Simulate(FundTransferOp),
+    // Gets a block of the ledger with its transactions
+    GetBlock(GetBlockOp),
+    // Hands a block minted by another node to the node, which adds it to the main chain or a fork
+    SubmitBlock(SubmitBlockOp),
+    // Stakes funds of an account, which registers it as a validator minting blocks in proof-of-stake mode
+    Stake(StakeOp),
+    Unstake(StakeOp),
+    // Lists the validators and their stakes
+    GetValidators,
+    // Gets block headers from a height onwards, so light nodes can follow the chain without its transactions
+    GetHeaders(GetHeadersOp),
+    // Gets the transactions of an account with their Merkle proofs, so light nodes can verify its balance
+    GetAccountProof(GetAccountProofOp),
+    // Gets a minted transaction with its Merkle proof
+    GetTransactionProof(GetTransactionOp),
+    // Registers a human-readable name for an account, which transfers and balance queries accept instead of its ID
+    RegisterAlias(RegisterAliasOp),
+    // Creates a token besides the native coin, whose whole supply goes to the issuing account
+    CreateToken(CreateTokenOp),
+    // Transfers an amount of a token between accounts, the fee is paid in the native coin
+    TransferToken(TokenTransferOp),
+    // Gets the balance of an account in a token
+    GetTokenBalance(GetTokenBalanceOp),
+    // Mints an NFT with a unique ID and metadata, owned by the minting account
+    MintNft(MintNftOp),
+    // Transfers an NFT to another account
+    TransferNft(TransferNftOp),
+    // Gets the owner and metadata of an NFT
+    OwnerOf(GetNftOp),
+    // Lists the NFTs owned by an account
+    TokensOf(GetNftsOfOp),
+    // Locks funds in escrow for a recipient, who can claim them once the escrow is released, until it expires
+    EscrowTransfer(EscrowTransferOp),
+    // Pays the funds of a released escrow to its recipient
+    ClaimEscrow(SettleEscrowOp),
+    // Pays the funds of an expired escrow back to its sender
+    RefundEscrow(SettleEscrowOp),
+    // Schedules payments of an amount to a recipient, made by the minter every interval of blocks
+    SchedulePayment(SchedulePaymentOp),
+    // Lists the payment schedules an account pays or is paid by
+    GetSchedules(GetSchedulesOp),
+    // Cancels the remaining payments of a schedule, which pays the fee from the paying account
+    CancelSchedule(CancelScheduleOp),
+    // Offers an amount of one asset to another account in exchange for an amount of another asset
+    OfferSwap(OfferSwapOp),
+    // Accepts a swap offer, which exchanges both amounts at once
+    AcceptSwap(AcceptSwapOp),
+    // Deploys a WebAssembly contract, which pays the fee from the deploying account
+    DeployContract(DeployContractOp),
+    // Calls a function of a contract, executed when the call is minted
+    CallContract(CallContractOp),
+    // Gets the owner and storage of a contract
+    GetContract(GetContractOp),
+    // Proposes a change of a chain parameter, which takes effect at the activation height if the votes approve it
+    Propose(ProposeOp),
+    // Votes for or against a proposal, weighted by the balance and stake of the voting account
+    Vote(VoteOp),
+    // Lists the open proposals and the current chain parameters
+    GetProposals,
+    // Shares the connected peers of a node with another node, which answers with its own
+    ExchangePeers(ExchangePeersOp),
+    // Lists the peers of the node and whether they are connected
+    GetPeers,
+    // Gets blocks with their transactions from a height onwards, so new nodes can catch up with the chain of a peer
+    GetBlocks(GetBlocksOp),
+    // Hands transactions another node accepted into its mempool to the node, which validates them and relays them on
+    RelayTransactions(RelayTransactionsOp),
+    // Reports whether the node accepts requests and its minting progresses, for health checks of orchestrators
+    Health,
+    // Advances the simulated clock of a node started with `--simulate`, minting the blocks due in that time
+    AdvanceTime(AdvanceTimeOp),
+    // Pays funds from the faucet of a test network to an account, see `--enable-faucet`
+    Faucet(FaucetOp),
+    // Gets the receipt of a transaction: where it was minted and the fee it paid, or whether it is pending or rejected
+    GetReceipt(GetTransactionOp),
+    // Registers a URL the node posts the activity of an account to, see `add-webhook`
+    AddWebhook(WebhookOp),
+    // Removes a URL registered for the activity of an account
+    RemoveWebhook(WebhookOp),
+    // Lists the webhooks registered for an account, or for all accounts
+    GetWebhooks(GetWebhooksOp),
+    // Verifies the audit log of a node started with `--audit-log` and lists its latest entries
+    GetAuditLog(GetAuditLogOp),
+    // Reports the supply of the chain, how much of it circulates, and the block reward of the monetary policy
+    GetSupply,
+    // Transfers funds like `TransferFunds`, dropped from the mempool if it isn't minted before it expires
+    ExpiringTransfer(ExpiringTransferOp),
+    // Admin operation that mints the pending transactions into a block right away, without waiting for the interval
+    MintNow(AdminCommandOp),
+    // Gets the minted transactions of an account that match filters, evaluated by the node
+    QueryTransactions(QueryTransactionsOp),
+    // Asks the node of the recipient's shard to credit a transfer locked on the shard of the sender, which answers
+    // whether it credited or aborted it
+    CreditCrossShard(CreditCrossShardOp),
+}
+
+impl Operation {
+    /// Returns the name of the operation, e.g. for metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::CreateAccount(_) => "create_account",
+            Operation::TransferFunds(_) => "transfer_funds",
+            Operation::GetFunds(_) => "get_funds",
+            Operation::GetTransaction(_) => "get_transaction",
+            Operation::GetNonce(_) => "get_nonce",
+            Operation::History(_) => "history",
+            Operation::Shutdown => "shutdown",
+            Operation::BatchTransfer(_) => "batch_transfer",
+            Operation::Status => "status",
+            Operation::CreateMultisigAccount(_) => "create_multisig_account",
+            Operation::ApproveTransfer(_) => "approve_transfer",
+            Operation::CloseAccount(_) => "close_account",
+            Operation::FreezeAccount(_) => "freeze_account",
+            Operation::UnfreezeAccount(_) => "unfreeze_account",
+            Operation::GetPending(_) => "get_pending",
+            Operation::CancelTransaction(_) => "cancel_transaction",
+            Operation::Simulate(_) => "simulate",
+            Operation::GetBlock(_) => "get_block",
+            Operation::SubmitBlock(_) => "submit_block",
+            Operation::Stake(_) => "stake",
+            Operation::Unstake(_) => "unstake",
+            Operation::GetValidators => "get_validators",
+            Operation::GetHeaders(_) => "get_headers",
+            Operation::GetAccountProof(_) => "get_account_proof",
+            Operation::GetTransactionProof(_) => "get_transaction_proof",
+            Operation::RegisterAlias(_) => "register_alias",
+            Operation::CreateToken(_) => "create_token",
+            Operation::TransferToken(_) => "transfer_token",
+            Operation::GetTokenBalance(_) => "get_token_balance",
+            Operation::MintNft(_) => "mint_nft",
+            Operation::TransferNft(_) => "transfer_nft",
+            Operation::OwnerOf(_) => "owner_of",
+            Operation::TokensOf(_) => "tokens_of",
+            Operation::EscrowTransfer(_) => "escrow_transfer",
+            Operation::ClaimEscrow(_) => "claim_escrow",
+            Operation::RefundEscrow(_) => "refund_escrow",
+            Operation::SchedulePayment(_) => "schedule_payment",
+            Operation::GetSchedules(_) => "get_schedules",
+            Operation::CancelSchedule(_) => "cancel_schedule",
+            Operation::OfferSwap(_) => "offer_swap",
+            Operation::AcceptSwap(_) => "accept_swap",
+            Operation::DeployContract(_) => "deploy_contract",
+            Operation::CallContract(_) => "call_contract",
+            Operation::GetContract(_) => "get_contract",
+            Operation::Propose(_) => "propose",
+            Operation::Vote(_) => "vote",
+            Operation::GetProposals => "get_proposals",
+            Operation::ExchangePeers(_) => "exchange_peers",
+            Operation::GetPeers => "get_peers",
+            Operation::GetBlocks(_) => "get_blocks",
+            Operation::RelayTransactions(_) => "relay_transactions",
+            Operation::Health => "health",
+            Operation::AdvanceTime(_) => "advance_time",
+            Operation::Faucet(_) => "faucet",
+            Operation::GetReceipt(_) => "get_receipt",
+            Operation::AddWebhook(_) => "add_webhook",
+            Operation::RemoveWebhook(_) => "remove_webhook",
+            Operation::GetWebhooks(_) => "get_webhooks",
+            Operation::GetAuditLog(_) => "get_audit_log",
+            Operation::GetSupply => "get_supply",
+            Operation::ExpiringTransfer(_) => "expiring_transfer",
+            Operation::MintNow(_) => "mint_now",
+            Operation::QueryTransactions(_) => "query_transactions",
+            Operation::CreditCrossShard(_) => "credit_cross_shard",
+        }
+    }
+
+    /// Returns the accounts the operation changes the nonce or state of, requests changing the same account are
+    /// processed in the order they arrive in
+    pub fn changed_accounts(&self) -> Vec<&str> {
+        match self {
+            Operation::CreateAccount(op) => vec![&op.account_id],
+            Operation::CreateMultisigAccount(op) => vec![&op.account_id],
+            Operation::TransferFunds(op) => vec![&op.from_account_id],
+            Operation::BatchTransfer(op) => op.transfers.iter().map(|transfer| transfer.from_account_id.as_str()).collect(),
+            Operation::TransferToken(op) => vec![&op.transfer.from_account_id],
+            Operation::EscrowTransfer(op) => vec![&op.transfer.from_account_id],
+            Operation::ExpiringTransfer(op) => vec![&op.transfer.from_account_id],
+            Operation::SchedulePayment(op) => vec![&op.transfer.from_account_id],
+            Operation::OfferSwap(op) => vec![&op.transfer.from_account_id],
+            Operation::CallContract(op) => vec![&op.transfer.from_account_id],
+            Operation::TransferNft(op) => vec![&op.from_account_id],
+            Operation::CloseAccount(op) => vec![&op.account_id],
+            Operation::FreezeAccount(op) | Operation::UnfreezeAccount(op) => vec![&op.account_id],
+            Operation::Stake(op) | Operation::Unstake(op) => vec![&op.account_id],
+            Operation::RegisterAlias(op) => vec![&op.account_id],
+            Operation::CreateToken(op) => vec![&op.account_id],
+            Operation::MintNft(op) => vec![&op.account_id],
+            Operation::CancelSchedule(op) => vec![&op.account_id],
+            Operation::AcceptSwap(op) => vec![&op.account_id],
+            Operation::DeployContract(op) => vec![&op.account_id],
+            Operation::Propose(op) => vec![&op.account_id],
+            Operation::Vote(op) => vec![&op.account_id],
+            Operation::Faucet(op) => vec![&op.account_id],
+            Operation::CreditCrossShard(op) => vec![&op.recipient],
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether the node answers the operation with a JSON object if the request asks for a structured result
+    pub fn has_structured_result(&self) -> bool {
+        matches!(self, Operation::GetFunds(_) | Operation::History(_) | Operation::QueryTransactions(_) | Operation::GetBlock(_) | Operation::Status)
+    }
+
+    /// Returns the account a request is about: the first account it changes, or else the account it queries. In sharded
+    /// mode the request is sent to the node of the shard of this account, see [crate::shard]. None for requests about
+    /// the chain or node as a whole, e.g. for a block.
+    pub fn account(&self) -> Option<&str> {
+        if let Some(account_id) = self.changed_accounts().first() {
+            return Some(account_id);
+        }
+        match self {
+            Operation::GetFunds(op) => Some(&op.account_id),
+            Operation::GetNonce(op) => Some(&op.account_id),
+            Operation::History(op) => Some(&op.account_id),
+            Operation::QueryTransactions(op) => Some(&op.account_id),
+            Operation::GetTokenBalance(op) => Some(&op.account_id),
+            Operation::TokensOf(op) => Some(&op.account_id),
+            Operation::GetSchedules(op) => Some(&op.account_id),
+            Operation::GetAccountProof(op) => Some(&op.account_id),
+            Operation::GetPending(op) => op.account_id.as_deref(),
+            Operation::Simulate(op) => Some(&op.from_account_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the fields a request from one node to another signs, together with its signature, None for operations
+    /// that aren't sent between nodes. The signature also covers the operation and request ID, so it can't be reused
+    /// for another request, see [keys::sign_node_request].
+    pub fn node_signature(&self) -> Option<(Vec<u8>, &str)> {
+        match self {
+            Operation::ExchangePeers(op) => Some((bincode::serialize(&(op.port, &op.peers)).unwrap(), &op.signature)),
+            Operation::RelayTransactions(op) => Some((op.transactions.clone(), &op.signature)),
+            Operation::GetBlocks(op) => Some((bincode::serialize(&(op.from_height, op.count)).unwrap(), &op.signature)),
+            Operation::CreditCrossShard(op) => {
+                let fields = (&op.transfer_id, op.shard, &op.sender, &op.recipient, &op.amount, op.deadline);
+                Some((bincode::serialize(&fields).unwrap(), &op.signature))
+            }
+            _ => None,
+        }
+    }
+
+    fn set_node_signature(&mut self, signature: String) {
+        match self {
+            Operation::ExchangePeers(op) => op.signature = signature,
+            Operation::RelayTransactions(op) => op.signature = signature,
+            Operation::GetBlocks(op) => op.signature = signature,
+            Operation::CreditCrossShard(op) => op.signature = signature,
+            _ => {}
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AccountCreationOp {
     pub account_id: String,
-    pub starting_balance: f64,
+    pub starting_balance: Amount,
 }
 
+/// An account whose transfers only enter the mempool once `threshold` of its public keys approved them
 #[derive(Serialize, Deserialize, Debug)]
+pub struct MultisigAccountCreationOp {
+    pub account_id: String,
+    pub starting_balance: Amount,
+    // Hex encoded Ed25519 public keys that can approve transfers from the account
+    pub public_keys: Vec<String>,
+    // Number of different keys that have to approve a transfer
+    pub threshold: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FundTransferOp {
     pub from_account_id: String,
     pub to_account_id: String,
-    pub amount: f64,
+    pub amount: Amount,
+    // Fee paid on top of the amount, transfers with higher fees are minted first
+    #[serde(default)]
+    pub fee: Amount,
+    // Must match the next nonce of the from account, so the same transfer can't be executed twice
+    pub nonce: u64,
+    // Free text the payment is referenced by, e.g. an invoice number
+    #[serde(default)]
+    pub memo: Option<String>,
+}
+
+/// Validates the memo of a transfer: at most `MAX_MEMO_LENGTH` bytes and no control characters, so that it shows up
+/// on a single line
+pub fn validate_memo(memo: &str) -> Result<(), String> {
+    if memo.len() > MAX_MEMO_LENGTH {
+        return Err(format!("❌ The memo is {} bytes long, memos can be at most {} bytes.", memo.len(), MAX_MEMO_LENGTH));
+    }
+    if memo.chars().any(char::is_control) {
+        return Err("❌ The memo can't contain control characters such as line breaks.".to_string());
+    }
+    Ok(())
 }
 
+/// Validates an account alias: 3 to `MAX_ALIAS_LENGTH` bytes of lowercase letters, digits, `-`, `_` and `.`, starting
+/// with a letter, so aliases can't be mistaken for reserved accounts like the staking account
+pub fn validate_alias(alias: &str) -> Result<(), String> {
+    if alias.len() < 3 || alias.len() > MAX_ALIAS_LENGTH {
+        return Err(format!("❌ The alias {} is {} bytes long, aliases have to be 3 to {} bytes.", alias, alias.len(), MAX_ALIAS_LENGTH));
+    }
+    if !alias.starts_with(|c: char| c.is_ascii_lowercase()) || !alias.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c)) {
+        return Err(format!("❌ Invalid alias {}, aliases start with a lowercase letter and only contain lowercase letters, digits, -, _ and .", alias));
+    }
+    Ok(())
+}
+
+/// Checks that a chain name is 1 to [MAX_CHAIN_NAME_LENGTH] bytes of lowercase letters, digits, - and _, so it can name
+/// the storage subdirectory of the chain
+pub fn validate_chain_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > MAX_CHAIN_NAME_LENGTH {
+        return Err(format!("❌ The chain name {} is {} bytes long, chain names have to be 1 to {} bytes.", name, name.len(), MAX_CHAIN_NAME_LENGTH));
+    }
+    if !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_".contains(c)) {
+        return Err(format!("❌ Invalid chain name {}, chain names only contain lowercase letters, digits, - and _.", name));
+    }
+    Ok(())
+}
+
+/// Creation of a token by an account, which pays the fee in the native coin and receives the whole supply
 #[derive(Serialize, Deserialize, Debug)]
-pub struct GetFundsOp {
+pub struct CreateTokenOp {
+    pub account_id: String,
+    pub token: String,
+    pub supply: Amount,
+    pub fee: Amount,
+    // Must match the next nonce of the account, like the nonce of a transfer
+    pub nonce: u64,
+}
+
+/// A transfer of a token, its amount is in the token and its fee in the native coin
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenTransferOp {
+    pub token: String,
+    pub transfer: FundTransferOp,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetTokenBalanceOp {
     pub account_id: String,
+    pub token: String,
 }
 
+/// Minting of an NFT by an account, which pays the fee and owns the NFT
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MintNftOp {
+    pub account_id: String,
+    // Free text describing the NFT, e.g. the URL of an image
+    pub metadata: String,
+    pub fee: Amount,
+    // Must match the next nonce of the account, like the nonce of a transfer. The ID of the NFT is derived from it.
+    pub nonce: u64,
+}
+
+/// A transfer of an NFT, which pays the fee from the current owner
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TransferNftOp {
+    pub from_account_id: String,
+    pub to_account_id: String,
+    pub nft_id: String,
+    pub fee: Amount,
+    pub nonce: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetNftOp {
+    pub nft_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetNftsOfOp {
+    pub account_id: String,
+}
+
+/// The point from which on a timelock is open, either a block height or a unix time in seconds that the timestamp of
+/// a block has to reach
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Timelock {
+    Height(u64),
+    Time(u64),
+}
+
+impl Timelock {
+    /// Whether the timelock is open for a block at the given height with the given timestamp
+    pub fn is_open(&self, height: u64, timestamp: u64) -> bool {
+        match *self {
+            Timelock::Height(open_height) => height >= open_height,
+            Timelock::Time(open_time) => timestamp >= open_time,
+        }
+    }
+
+    /// Whether a block at the given height with the given timestamp is past the timelock, e.g. when something valid
+    /// until it has expired
+    pub fn has_passed(&self, height: u64, timestamp: u64) -> bool {
+        match *self {
+            Timelock::Height(last_height) => height > last_height,
+            Timelock::Time(last_time) => timestamp > last_time,
+        }
+    }
+
+    /// Whether the timelock opens before the other one, None if one is a height and the other a time
+    pub fn is_before(&self, other: &Timelock) -> Option<bool> {
+        match (*self, *other) {
+            (Timelock::Height(height), Timelock::Height(other_height)) => Some(height < other_height),
+            (Timelock::Time(time), Timelock::Time(other_time)) => Some(time < other_time),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Timelock {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Timelock::Height(height) => write!(f, "height {}", height),
+            Timelock::Time(time) => write!(f, "{} (unix time)", time),
+        }
+    }
+}
+
+/// A transfer of funds into escrow. The recipient can claim them once the release timelock is open and until the
+/// expiry timelock is, from then on the sender can get them refunded.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EscrowTransferOp {
+    pub transfer: FundTransferOp,
+    pub release: Timelock,
+    pub expiry: Timelock,
+}
+
+/// A transfer of funds that can only be minted until the given timelock, after which it expires and is dropped from the
+/// mempool
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExpiringTransferOp {
+    pub transfer: FundTransferOp,
+    pub valid_until: Timelock,
+}
+
+/// A claim or refund of an escrow, identified by the ID of the transaction that funded it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SettleEscrowOp {
+    pub escrow_id: String,
+}
+
+/// A schedule of `count` payments of the transfer's amount, due at the start height and every `interval` blocks after
+/// it. The fee and nonce of the transfer are for creating the schedule, the payments themselves pay no fee.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SchedulePaymentOp {
+    pub transfer: FundTransferOp,
+    pub start_height: u64,
+    pub interval: u64,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetSchedulesOp {
+    pub account_id: String,
+}
+
+/// A cancellation of a payment schedule, identified by the ID of the transaction that created it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelScheduleOp {
+    pub account_id: String,
+    pub schedule_id: String,
+    pub fee: Amount,
+    pub nonce: u64,
+}
+
+/// Validates the metadata of an NFT: at most `MAX_NFT_METADATA_LENGTH` bytes and no control characters, like a memo
+pub fn validate_nft_metadata(metadata: &str) -> Result<(), String> {
+    if metadata.len() > MAX_NFT_METADATA_LENGTH {
+        return Err(format!("❌ The metadata is {} bytes long, the metadata of an NFT can be at most {} bytes.", metadata.len(), MAX_NFT_METADATA_LENGTH));
+    }
+    if metadata.chars().any(char::is_control) {
+        return Err("❌ The metadata can't contain control characters such as line breaks.".to_string());
+    }
+    Ok(())
+}
+
+/// Validates the symbol of a token: 2 to `MAX_TOKEN_LENGTH` bytes of uppercase letters and digits, starting with a
+/// letter
+pub fn validate_token(token: &str) -> Result<(), String> {
+    if token.len() < 2 || token.len() > MAX_TOKEN_LENGTH {
+        return Err(format!("❌ The token symbol {} is {} bytes long, token symbols have to be 2 to {} bytes.", token, token.len(), MAX_TOKEN_LENGTH));
+    }
+    if !token.starts_with(|c: char| c.is_ascii_uppercase()) || !token.chars().all(|c| c.is_ascii_uppercase() || c.is_ascii_digit()) {
+        return Err(format!("❌ Invalid token symbol {}, token symbols start with an uppercase letter and only contain uppercase letters and digits.", token));
+    }
+    Ok(())
+}
+
+/// An amount of a token, or of the native coin if no token is given
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Asset {
+    pub token: Option<String>,
+    pub amount: Amount,
+}
+
+impl std::fmt::Display for Asset {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.token {
+            Some(token) => write!(f, "{} {}", self.amount, token),
+            None => write!(f, "{}", self.amount),
+        }
+    }
+}
+
+/// An offer of the from account of the transfer to give its amount, in the token if one is given, to the to account in
+/// exchange for another asset. The fee and nonce of the transfer are for recording the offer, nothing moves until the
+/// counterparty accepts the offer, which it can until the block at the expiry height.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OfferSwapOp {
+    pub transfer: FundTransferOp,
+    pub give_token: Option<String>,
+    pub take: Asset,
+    pub expiry_height: u64,
+}
+
+/// An acceptance of a swap offer by its counterparty, identified by the ID of the transaction that made the offer
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AcceptSwapOp {
+    pub account_id: String,
+    pub offer_id: String,
+    pub fee: Amount,
+    pub nonce: u64,
+}
+
+/// Deployment of a contract by an account, which pays the fee and owns the contract
 #[derive(Serialize, Deserialize)]
-pub struct Request {
-    pub from_node: String,
-    pub operation: Operation,
+pub struct DeployContractOp {
+    pub account_id: String,
+    // The bytecode of the WebAssembly module
+    pub code: Vec<u8>,
+    pub fee: Amount,
+    // Must match the next nonce of the account, like the nonce of a transfer. The ID of the contract is derived from
+    // it.
+    pub nonce: u64,
 }
 
-impl Request {
-    pub fn new_get_funds_request(node_id: String, account_id: String) -> Request {
-        Request {
-            from_node: node_id,
-            operation: Operation::GetFunds(GetFundsOp { account_id }),
+impl std::fmt::Debug for DeployContractOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // Like for submitted blocks, the size of the bytecode is enough to tell requests apart
+        write!(f, "DeployContractOp {{ account_id: {:?}, code: {} bytes, fee: {}, nonce: {} }}", self.account_id, self.code.len(), self.fee, self.nonce)
+    }
+}
+
+/// A call of a function of a contract, given as a transfer of nothing to the contract that pays the fee
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CallContractOp {
+    pub transfer: FundTransferOp,
+    pub function: String,
+    // Bytes the contract can read with its input host function
+    pub input: Vec<u8>,
+    // Maximum gas the call can consume before it is aborted
+    pub gas_limit: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetContractOp {
+    pub contract_id: String,
+}
+
+/// A change of a chain parameter that governance can decide on
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ParameterChange {
+    // The minimum fee every node accepts new transactions with
+    MinFee(Amount),
+    // The maximum number of transactions in a block
+    MaxBlockTransactions(usize),
+    // The number of seconds between blocks
+    MintInterval(u64),
+    // The amount minted every epoch and distributed to the stakers in proportion to their stake
+    StakingReward(Amount),
+    // The number of blocks in an epoch
+    EpochLength(u64),
+}
+
+impl ParameterChange {
+    /// Validates the new value of the parameter, the minimum fee and the staking reward can't be negative and the other
+    /// parameters have to be at least 1
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            ParameterChange::MinFee(amount) | ParameterChange::StakingReward(amount) if amount.is_negative() => Err(format!("❌ Invalid change of {}, it can't be negative.", self)),
+            ParameterChange::MaxBlockTransactions(0) | ParameterChange::MintInterval(0) | ParameterChange::EpochLength(0) => Err(format!("❌ Invalid change of {}, it has to be at least 1.", self)),
+            _ => Ok(()),
         }
     }
+}
 
-    pub fn new_create_account_request(
-        node_id: String,
-        account_id: String,
-        starting_balance: f64,
-    ) -> Request {
-        Request {
-            from_node: node_id,
-            operation: Operation::CreateAccount(AccountCreationOp {
-                account_id,
-                starting_balance,
-            }),
+impl std::fmt::Display for ParameterChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ParameterChange::MinFee(fee) => write!(f, "min_fee to {}", fee),
+            ParameterChange::MaxBlockTransactions(count) => write!(f, "max_block_transactions to {}", count),
+            ParameterChange::MintInterval(seconds) => write!(f, "mint_interval to {}", seconds),
+            ParameterChange::StakingReward(reward) => write!(f, "staking_reward to {}", reward),
+            ParameterChange::EpochLength(blocks) => write!(f, "epoch_length to {}", blocks),
         }
     }
+}
 
-    pub fn new_transfer_funds_request(
-        node_id: String,
-        from_account_id: String,
-        to_account_id: String,
-        amount: f64,
-    ) -> Request {
-        Request {
-            from_node: node_id,
-            operation: Operation::TransferFunds(FundTransferOp {
-                from_account_id,
-                to_account_id,
-                amount,
-            }),
+impl std::str::FromStr for ParameterChange {
+    type Err = String;
+
+    /// Parses a change given as `PARAMETER=VALUE`, e.g. `min_fee=0.5`
+    fn from_str(text: &str) -> Result<ParameterChange, String> {
+        let invalid = |e: String| format!("❌ Invalid value in {}: {}", text, e);
+        match text.split_once('=') {
+            Some(("min_fee", value)) => value.parse().map(ParameterChange::MinFee).map_err(invalid),
+            Some(("max_block_transactions", value)) => value.parse().map(ParameterChange::MaxBlockTransactions).map_err(|e| invalid(format!("{}", e))),
+            Some(("mint_interval", value)) => value.parse().map(ParameterChange::MintInterval).map_err(|e| invalid(format!("{}", e))),
+            Some(("staking_reward", value)) => value.parse().map(ParameterChange::StakingReward).map_err(invalid),
+            Some(("epoch_length", value)) => value.parse().map(ParameterChange::EpochLength).map_err(|e| invalid(format!("{}", e))),
+            _ => Err(format!(
+                "❌ Invalid parameter change {}, expected min_fee=FEE, max_block_transactions=COUNT, mint_interval=SECONDS, staking_reward=AMOUNT or epoch_length=BLOCKS.",
+                text
+            )),
+        }
+    }
+}
+
+/// A proposal of a change of a chain parameter, which pays the fee from the proposing account
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ProposeOp {
+    pub account_id: String,
+    pub change: ParameterChange,
+    // Height of the first block the change applies to if the proposal passes, votes are counted right before it
+    pub activation_height: u64,
+    pub fee: Amount,
+    pub nonce: u64,
+}
+
+/// A vote on a proposal, identified by the ID of the transaction that made it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VoteOp {
+    pub account_id: String,
+    pub proposal_id: String,
+    // Whether the vote is for the proposal
+    pub approve: bool,
+    pub fee: Amount,
+    pub nonce: u64,
+}
+
+/// Approval of a pending transfer from a multisig account by one of its keys
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ApproveTransferOp {
+    pub transaction_id: String,
+    // Hex encoded public key of the approving key
+    pub public_key: String,
+    // Hex encoded signature of the approval, see [crate::keys::sign_approval]
+    pub signature: String,
+}
+
+/// Cancellation of a pending transaction by one of the keys of its from account
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CancelTransactionOp {
+    pub transaction_id: String,
+    // Hex encoded public key of the cancelling key
+    pub public_key: String,
+    // Hex encoded signature of the cancellation, see [crate::keys::sign_cancel]
+    pub signature: String,
+}
+
+/// Closing of an account, whose remaining balance goes to the beneficiary. No transfers can be made from or to the
+/// account once it is closed.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CloseAccountOp {
+    pub account_id: String,
+    pub beneficiary_id: String,
+    // Fee paid out of the remaining balance
+    pub fee: Amount,
+    // Must match the next nonce of the account, like the nonce of a transfer
+    pub nonce: u64,
+}
+
+/// Staking of funds of an account, which can't be spent anymore once they are staked, or unstaking of staked funds,
+/// which can be spent again once they are unstaked
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StakeOp {
+    pub account_id: String,
+    pub amount: Amount,
+    pub fee: Amount,
+    // Must match the next nonce of the account, like the nonce of a transfer
+    pub nonce: u64,
+}
+
+/// Registration of an alias for an account, the first account to register an alias keeps it
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RegisterAliasOp {
+    pub account_id: String,
+    pub alias: String,
+    pub fee: Amount,
+    // Must match the next nonce of the account, like the nonce of a transfer
+    pub nonce: u64,
+}
+
+/// Admin operation on an account, signed with the admin key of the node
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminAccountOp {
+    pub account_id: String,
+    // Unix time the operation was signed at, nodes only accept signatures made within a few minutes of their clock
+    pub signed_at: u64,
+    // Hex encoded signature of the operation, see [crate::keys::sign_admin]
+    pub signature: String,
+}
+
+/// Admin command to the node itself, signed with the admin key of the node
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdminCommandOp {
+    // Unix time the command was signed at, nodes only accept signatures made within a few minutes of their clock
+    pub signed_at: u64,
+    // Hex encoded signature of the command, see [crate::keys::sign_admin_command]
+    pub signature: String,
+}
+
+/// Transfers that are made all together or not at all, in the given order
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchTransferOp {
+    pub transfers: Vec<FundTransferOp>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetFundsOp {
+    pub account_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetTransactionOp {
+    pub transaction_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetNonceOp {
+    pub account_id: String,
+}
+
+/// A payment of the faucet to an account or alias, of the faucet amount if no amount is given
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FaucetOp {
+    pub account_id: String,
+    pub amount: Option<Amount>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AdvanceTimeOp {
+    pub seconds: u64,
+}
+
+/// A URL notified of the activity of an account
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WebhookOp {
+    pub account_id: String,
+    pub url: String,
+}
+
+/// The webhooks of an account, of all accounts if none is given
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetWebhooksOp {
+    pub account_id: Option<String>,
+}
+
+/// The latest entries of the audit log, only of an operation or a source address if given
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetAuditLogOp {
+    pub count: usize,
+    pub operation: Option<String>,
+    // Prefix of the source addresses, e.g. an IP address
+    pub source: Option<String>,
+}
+
+/// A block by its height or hash, the height is used if both are given
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetBlockOp {
+    pub height: Option<usize>,
+    pub hash: Option<String>,
+}
+
+/// A block minted by another node
+#[derive(Serialize, Deserialize)]
+pub struct SubmitBlockOp {
+    // The bincode encoded block, in the format of the node's ledger file
+    pub block: Vec<u8>,
+}
+
+impl std::fmt::Debug for SubmitBlockOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // The block would fill the log, its size is enough to tell requests apart
+        write!(f, "SubmitBlockOp {{ {} bytes }}", self.block.len())
+    }
+}
+
+/// Transactions accepted into the mempool of another node
+#[derive(Serialize, Deserialize)]
+pub struct RelayTransactionsOp {
+    // The bincode encoded transactions, in the format of the node's ledger file
+    pub transactions: Vec<u8>,
+    // Signature of the relaying node, see [Operation::node_signature]
+    pub signature: String,
+}
+
+impl std::fmt::Debug for RelayTransactionsOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "RelayTransactionsOp {{ {} bytes }}", self.transactions.len())
+    }
+}
+
+/// The connected peers of a node, sent to another node in a peer exchange
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ExchangePeersOp {
+    // Port the sending node listens on, its address is the one the request came from with this port
+    pub port: u16,
+    pub peers: Vec<String>,
+    // Signature of the sending node, see [Operation::node_signature]
+    pub signature: String,
+}
+
+/// Blocks from a height onwards, as many as fit into the response
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetBlocksOp {
+    pub from_height: usize,
+    // Number of blocks to return at most
+    pub count: usize,
+    // Signature of the requesting node, see [Operation::node_signature]
+    pub signature: String,
+}
+
+/// A transfer locked on the shard of its sender, to be credited to its recipient on the shard of the receiving node
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreditCrossShardOp {
+    // ID of the transaction that locked the funds on the sender's shard
+    pub transfer_id: String,
+    // Shard of the sender
+    pub shard: usize,
+    pub sender: String,
+    pub recipient: String,
+    pub amount: Amount,
+    // Unix time in seconds until which the transfer can be credited, after which it is refunded to the sender
+    pub deadline: u64,
+    // Signature of the node of the sender's shard, see [Operation::node_signature]
+    pub signature: String,
+}
+
+/// Headers of the blocks from a height onwards, as many as fit into the response
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetHeadersOp {
+    pub from_height: usize,
+    // Number of headers to return at most
+    pub count: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetAccountProofOp {
+    pub account_id: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct GetPendingOp {
+    // Account to list the pending transactions of, all pending transactions are listed if not given
+    pub account_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HistoryOp {
+    pub account_id: String,
+    // Page to return, starting at 1 with the most recent transactions
+    pub page: usize,
+    // Number of transactions per page
+    pub limit: usize,
+}
+
+/// Whether a query returns the transactions an account sent or those it received
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Query of the minted transactions of an account, filtered by the node. Every filter is optional and the bounds of
+/// the ranges are inclusive.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct QueryTransactionsOp {
+    pub account_id: String,
+    // Only the transactions the account sent or only those it received, both if not set
+    pub direction: Option<Direction>,
+    pub min_amount: Option<Amount>,
+    pub max_amount: Option<Amount>,
+    // Heights of the blocks the transactions were minted in
+    pub from_height: Option<u64>,
+    pub to_height: Option<u64>,
+    // Unix times of the blocks the transactions were minted in
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    // Page to return, starting at 1 with the most recent transactions
+    pub page: usize,
+    // Number of transactions per page
+    pub limit: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Request {
+    // Protocol version the request was made with
+    pub version: u16,
+    // Random ID the server recognizes retries of the request by, so a retried request is only processed once. None
+    // for requests of versions before 3.
+    pub request_id: Option<u64>,
+    pub from_node: String,
+    // Name of the chain the request is for, the default chain of the node if None. None for requests of versions
+    // before 8.
+    pub chain: Option<String>,
+    // Whether the node answers a query with its result as a JSON object instead of a message, for clients printing JSON
+    // output, see [Operation::has_structured_result]. False for requests of versions before 9.
+    pub structured: bool,
+    pub operation: Operation,
+}
+
+/// Why a request couldn't be decoded
+#[derive(Debug)]
+pub enum RequestError {
+    // The request isn't a valid request of any version
+    Malformed(String),
+    // The request was made with a protocol version the server doesn't support
+    UnsupportedVersion(u16),
+}
+
+impl std::fmt::Display for RequestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            RequestError::Malformed(e) => write!(f, "Malformed request: {}", e),
+            RequestError::UnsupportedVersion(version) => write!(
+                f,
+                "❌ Unsupported protocol version {}, this node supports versions {} to {}. Upgrade the client or the node.",
+                version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ),
+        }
+    }
+}
+
+/// The header of a versioned message
+struct Header {
+    version: u16,
+    compression: Compression,
+    // None for the tags of unknown formats, messages of versions before 7 are always bincode
+    format: Option<Format>,
+}
+
+/// Splits a versioned message into its header and the message
+///
+/// Returns: None if the message isn't versioned, i.e. a version 1 message
+fn split_header(bytes: &[u8]) -> Option<(Header, &[u8])> {
+    let rest = bytes.strip_prefix(VERSION_MAGIC)?;
+    if rest.len() < 2 {
+        return None;
+    }
+    let (version, mut message) = rest.split_at(2);
+    let mut header = Header {
+        version: u16::from_le_bytes([version[0], version[1]]),
+        compression: Compression::None,
+        format: Some(Format::Bincode),
+    };
+    if header.version >= 6 {
+        if let Some((&flags, rest)) = message.split_first() {
+            header.compression = Compression::from_flags(flags);
+            message = rest;
+        }
+    }
+    if header.version >= 7 {
+        if let Some((&tag, rest)) = message.split_first() {
+            header.format = Format::from_tag(tag);
+            message = rest;
+        }
+    }
+    Some((header, message))
+}
+
+/// Returns the protocol version a request or response was encoded with, without decoding it
+pub fn protocol_version(bytes: &[u8]) -> u16 {
+    split_header(bytes).map_or(1, |(header, _)| header.version)
+}
+
+/// Returns the format the response to a request is sent in and the compression the client accepts it in, without
+/// decoding the request
+pub fn response_encoding(bytes: &[u8]) -> (Format, Compression) {
+    match split_header(bytes) {
+        Some((header, _)) => (header.format.unwrap_or(Format::Bincode), header.compression),
+        None => (Format::Bincode, Compression::None),
+    }
+}
+
+/// Checks that the version inside a request matches the version it was prefixed with
+fn check_version(version: u16, prefixed: u16) -> Result<(), RequestError> {
+    if version != prefixed {
+        return Err(RequestError::Malformed(format!("version {} in a version {} request", version, prefixed)));
+    }
+    Ok(())
+}
+
+/// Prefixes a message with the magic and protocol version of versioned messages, and the compression flags and format
+/// tag in versions that have them
+fn with_version(version: u16, compression: Compression, format: Format, message: &[u8]) -> Vec<u8> {
+    let mut bytes = VERSION_MAGIC.to_vec();
+    bytes.extend_from_slice(&version.to_le_bytes());
+    if version >= 6 {
+        bytes.push(compression.flags());
+    }
+    if version >= 7 {
+        bytes.push(format.tag());
+    }
+    bytes.extend_from_slice(message);
+    bytes
+}
+
+impl Request {
+    /// Serializes the request in the current protocol version and the given format, flagged with the compression the
+    /// client accepts the response in
+    pub fn encode(&self, format: Format, accepted: Compression) -> Vec<u8> {
+        self.encode_for_chain(self.chain.as_deref(), format, accepted)
+    }
+
+    /// Serializes the request like [Request::encode], but for the given chain instead of the chain of the request, e.g.
+    /// the chain a client is sending its requests to
+    pub fn encode_for_chain(&self, chain: Option<&str>, format: Format, accepted: Compression) -> Vec<u8> {
+        // Serialized like a request, field by field
+        #[derive(Serialize)]
+        struct ChainRequest<'a> {
+            version: u16,
+            request_id: Option<u64>,
+            from_node: &'a str,
+            chain: Option<&'a str>,
+            structured: bool,
+            operation: &'a Operation,
+        }
+        let request = ChainRequest {
+            version: self.version,
+            request_id: self.request_id,
+            from_node: &self.from_node,
+            chain,
+            structured: self.structured,
+            operation: &self.operation,
+        };
+        with_version(PROTOCOL_VERSION, accepted, format, &format.serialize(&request).expect("Failed to serialize request."))
+    }
+
+    /// Deserializes a request of any supported protocol version, converting it to the current version. The version the
+    /// request was made with is kept, so the response can be sent in the same version.
+    pub fn decode(bytes: &[u8]) -> Result<Request, RequestError> {
+        let malformed = |e: bincode::Error| RequestError::Malformed(e.to_string());
+        match split_header(bytes).map(|(header, message)| (header.version, header.format, message)) {
+            None => {
+                let request: RequestV1 = bincode::deserialize(bytes).map_err(malformed)?;
+                Ok(Request {
+                    version: 1,
+                    request_id: None,
+                    from_node: request.from_node,
+                    chain: None,
+                    structured: false,
+                    operation: request.operation.into(),
+                })
+            }
+            Some((2, _, message)) => {
+                let request: RequestV2 = bincode::deserialize(message).map_err(malformed)?;
+                check_version(request.version, 2)?;
+                Ok(Request {
+                    version: 2,
+                    request_id: None,
+                    from_node: request.from_node,
+                    chain: None,
+                    structured: false,
+                    operation: request.operation.into(),
+                })
+            }
+            Some((3, _, message)) => {
+                let request: RequestV3 = bincode::deserialize(message).map_err(malformed)?;
+                check_version(request.version, 3)?;
+                Ok(Request {
+                    version: 3,
+                    request_id: request.request_id,
+                    from_node: request.from_node,
+                    chain: None,
+                    structured: false,
+                    operation: request.operation.into(),
+                })
+            }
+            // Versions 5 to 7 only changed how messages are sent, so requests of versions 4 to 7 have the same format
+            Some((version @ 4..=7, Some(format), message)) => {
+                let request: RequestV7 = format.deserialize(message).map_err(RequestError::Malformed)?;
+                check_version(request.version, version)?;
+                Ok(Request {
+                    version,
+                    request_id: request.request_id,
+                    from_node: request.from_node,
+                    chain: None,
+                    structured: false,
+                    operation: request.operation,
+                })
+            }
+            Some((8, Some(format), message)) => {
+                let request: RequestV8 = format.deserialize(message).map_err(RequestError::Malformed)?;
+                check_version(request.version, 8)?;
+                Ok(Request {
+                    version: 8,
+                    request_id: request.request_id,
+                    from_node: request.from_node,
+                    chain: request.chain,
+                    structured: false,
+                    operation: request.operation,
+                })
+            }
+            // Version 10 only changed the responses
+            Some((version @ 9..=PROTOCOL_VERSION, Some(format), message)) => {
+                let request: Request = format.deserialize(message).map_err(RequestError::Malformed)?;
+                check_version(request.version, version)?;
+                Ok(request)
+            }
+            Some((4..=PROTOCOL_VERSION, None, _)) => Err(RequestError::Malformed("unknown format".to_string())),
+            Some((version, _, _)) => Err(RequestError::UnsupportedVersion(version)),
+        }
+    }
+
+    pub fn new_get_funds_request(node_id: String, account_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetFunds(GetFundsOp { account_id }),
+        }
+    }
+
+    pub fn new_get_transaction_request(node_id: String, transaction_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetTransaction(GetTransactionOp { transaction_id }),
+        }
+    }
+
+    pub fn new_get_nonce_request(node_id: String, account_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetNonce(GetNonceOp { account_id }),
+        }
+    }
+
+    pub fn new_history_request(node_id: String, account_id: String, page: usize, limit: usize) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::History(HistoryOp { account_id, page, limit }),
+        }
+    }
+
+    pub fn new_query_transactions_request(node_id: String, query: QueryTransactionsOp) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::QueryTransactions(query),
+        }
+    }
+
+    pub fn new_get_block_request(node_id: String, height: Option<usize>, hash: Option<String>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetBlock(GetBlockOp { height, hash }),
+        }
+    }
+
+    pub fn new_submit_block_request(node_id: String, block: Vec<u8>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::SubmitBlock(SubmitBlockOp { block }),
+        }
+    }
+
+    pub fn new_get_pending_request(node_id: String, account_id: Option<String>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetPending(GetPendingOp { account_id }),
+        }
+    }
+
+    pub fn new_shutdown_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Shutdown,
+        }
+    }
+
+    /// Derives the request ID from an idempotency key, so that sending a request with the same key again (e.g. running
+    /// a command twice) is answered with the response to the first request instead of being processed again
+    pub fn with_idempotency_key(mut self, key: &str) -> Request {
+        let hash = crate::merkle::sha256_hex(format!("idempotency key {}", key).as_bytes());
+        self.request_id = Some(u64::from_str_radix(&hash[..16], 16).unwrap());
+        self
+    }
+
+    /// Asks the node for the result of a query as a JSON object instead of a message
+    pub fn structured(mut self) -> Request {
+        self.structured = true;
+        self
+    }
+
+    /// Turns a transfer request into a simulation, which the server only validates
+    pub fn simulated(mut self) -> Request {
+        if let Operation::TransferFunds(transfer_info) = self.operation {
+            self.operation = Operation::Simulate(transfer_info);
+        }
+        self
+    }
+
+    /// Turns a transfer request into a transfer of the given token instead of the native coin
+    pub fn of_token(mut self, token: String) -> Request {
+        if let Operation::TransferFunds(transfer) = self.operation {
+            self.operation = Operation::TransferToken(TokenTransferOp { token, transfer });
+        }
+        self
+    }
+
+    pub fn new_status_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Status,
+        }
+    }
+
+    pub fn new_create_account_request(
+        node_id: String,
+        account_id: String,
+        starting_balance: Amount,
+    ) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::CreateAccount(AccountCreationOp {
+                account_id,
+                starting_balance,
+            }),
+        }
+    }
+
+    pub fn new_transfer_funds_request(
+        node_id: String,
+        from_account_id: String,
+        to_account_id: String,
+        amount: Amount,
+        fee: Amount,
+        nonce: u64,
+        memo: Option<String>,
+    ) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::TransferFunds(FundTransferOp {
+                from_account_id,
+                to_account_id,
+                amount,
+                fee,
+                nonce,
+                memo,
+            }),
+        }
+    }
+
+    pub fn new_create_multisig_account_request(
+        node_id: String,
+        account_id: String,
+        starting_balance: Amount,
+        public_keys: Vec<String>,
+        threshold: usize,
+    ) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::CreateMultisigAccount(MultisigAccountCreationOp {
+                account_id,
+                starting_balance,
+                public_keys,
+                threshold,
+            }),
+        }
+    }
+
+    pub fn new_approve_transfer_request(node_id: String, transaction_id: String, public_key: String, signature: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::ApproveTransfer(ApproveTransferOp {
+                transaction_id,
+                public_key,
+                signature,
+            }),
+        }
+    }
+
+    pub fn new_cancel_transaction_request(node_id: String, transaction_id: String, public_key: String, signature: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::CancelTransaction(CancelTransactionOp {
+                transaction_id,
+                public_key,
+                signature,
+            }),
+        }
+    }
+
+    pub fn new_stake_request(node_id: String, account_id: String, amount: Amount, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Stake(StakeOp { account_id, amount, fee, nonce }),
+        }
+    }
+
+    pub fn new_unstake_request(node_id: String, account_id: String, amount: Amount, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Unstake(StakeOp { account_id, amount, fee, nonce }),
+        }
+    }
+
+    pub fn new_register_alias_request(node_id: String, account_id: String, alias: String, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::RegisterAlias(RegisterAliasOp { account_id, alias, fee, nonce }),
+        }
+    }
+
+    pub fn new_create_token_request(node_id: String, account_id: String, token: String, supply: Amount, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::CreateToken(CreateTokenOp { account_id, token, supply, fee, nonce }),
+        }
+    }
+
+    pub fn new_get_token_balance_request(node_id: String, account_id: String, token: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetTokenBalance(GetTokenBalanceOp { account_id, token }),
+        }
+    }
+
+    pub fn new_mint_nft_request(node_id: String, account_id: String, metadata: String, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::MintNft(MintNftOp { account_id, metadata, fee, nonce }),
+        }
+    }
+
+    pub fn new_transfer_nft_request(node_id: String, from_account_id: String, to_account_id: String, nft_id: String, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::TransferNft(TransferNftOp { from_account_id, to_account_id, nft_id, fee, nonce }),
+        }
+    }
+
+    pub fn new_owner_of_request(node_id: String, nft_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::OwnerOf(GetNftOp { nft_id }),
+        }
+    }
+
+    pub fn new_tokens_of_request(node_id: String, account_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::TokensOf(GetNftsOfOp { account_id }),
+        }
+    }
+
+    /// Turns a transfer request into a transfer into escrow with the given timelocks
+    pub fn into_escrow(mut self, release: Timelock, expiry: Timelock) -> Request {
+        if let Operation::TransferFunds(transfer) = self.operation {
+            self.operation = Operation::EscrowTransfer(EscrowTransferOp { transfer, release, expiry });
+        }
+        self
+    }
+
+    /// Turns a transfer request into a transfer that expires if it isn't minted until the given timelock
+    pub fn expiring(mut self, valid_until: Timelock) -> Request {
+        if let Operation::TransferFunds(transfer) = self.operation {
+            self.operation = Operation::ExpiringTransfer(ExpiringTransferOp { transfer, valid_until });
+        }
+        self
+    }
+
+    /// Returns a request claiming an escrow for its recipient, or getting it refunded to its sender if `refund` is set
+    pub fn new_settle_escrow_request(node_id: String, escrow_id: String, refund: bool) -> Request {
+        let settle_info = SettleEscrowOp { escrow_id };
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: if refund { Operation::RefundEscrow(settle_info) } else { Operation::ClaimEscrow(settle_info) },
+        }
+    }
+
+    /// Turns a transfer request into a schedule of payments of its amount
+    pub fn into_schedule(mut self, start_height: u64, interval: u64, count: u64) -> Request {
+        if let Operation::TransferFunds(transfer) = self.operation {
+            self.operation = Operation::SchedulePayment(SchedulePaymentOp { transfer, start_height, interval, count });
+        }
+        self
+    }
+
+    pub fn new_get_schedules_request(node_id: String, account_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetSchedules(GetSchedulesOp { account_id }),
+        }
+    }
+
+    pub fn new_cancel_schedule_request(node_id: String, account_id: String, schedule_id: String, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::CancelSchedule(CancelScheduleOp { account_id, schedule_id, fee, nonce }),
+        }
+    }
+
+    /// Turns a transfer request into an offer to swap its amount, in the given token or the native coin, for the asset
+    /// to take
+    pub fn into_swap(mut self, give_token: Option<String>, take: Asset, expiry_height: u64) -> Request {
+        if let Operation::TransferFunds(transfer) = self.operation {
+            self.operation = Operation::OfferSwap(OfferSwapOp { transfer, give_token, take, expiry_height });
+        }
+        self
+    }
+
+    pub fn new_accept_swap_request(node_id: String, account_id: String, offer_id: String, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::AcceptSwap(AcceptSwapOp { account_id, offer_id, fee, nonce }),
+        }
+    }
+
+    pub fn new_deploy_contract_request(node_id: String, account_id: String, code: Vec<u8>, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::DeployContract(DeployContractOp { account_id, code, fee, nonce }),
+        }
+    }
+
+    /// Turns a transfer request to a contract into a call of its function with the input
+    pub fn into_contract_call(mut self, function: String, input: Vec<u8>, gas_limit: u64) -> Request {
+        if let Operation::TransferFunds(transfer) = self.operation {
+            self.operation = Operation::CallContract(CallContractOp { transfer, function, input, gas_limit });
+        }
+        self
+    }
+
+    pub fn new_get_contract_request(node_id: String, contract_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetContract(GetContractOp { contract_id }),
+        }
+    }
+
+    pub fn new_propose_request(node_id: String, account_id: String, change: ParameterChange, activation_height: u64, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Propose(ProposeOp { account_id, change, activation_height, fee, nonce }),
+        }
+    }
+
+    pub fn new_vote_request(node_id: String, account_id: String, proposal_id: String, approve: bool, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Vote(VoteOp { account_id, proposal_id, approve, fee, nonce }),
+        }
+    }
+
+    pub fn new_get_proposals_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetProposals,
+        }
+    }
+
+    pub fn new_get_supply_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetSupply,
+        }
+    }
+
+    pub fn new_exchange_peers_request(key: &SigningKey, port: u16, peers: Vec<String>) -> Request {
+        Request::new_node_request(key, Operation::ExchangePeers(ExchangePeersOp { port, peers, signature: String::new() }))
+    }
+
+    pub fn new_relay_transactions_request(key: &SigningKey, transactions: Vec<u8>) -> Request {
+        Request::new_node_request(key, Operation::RelayTransactions(RelayTransactionsOp { transactions, signature: String::new() }))
+    }
+
+    pub fn new_get_blocks_request(key: &SigningKey, from_height: usize, count: usize) -> Request {
+        Request::new_node_request(key, Operation::GetBlocks(GetBlocksOp { from_height, count, signature: String::new() }))
+    }
+
+    pub fn new_credit_cross_shard_request(key: &SigningKey, credit: CreditCrossShardOp) -> Request {
+        Request::new_node_request(key, Operation::CreditCrossShard(credit))
+    }
+
+    /// Returns a request from one node to another, from the node ID of the given identity key and signed with it
+    fn new_node_request(key: &SigningKey, mut operation: Operation) -> Request {
+        let request_id = random_id();
+        if let Some((fields, _)) = operation.node_signature() {
+            let signature = keys::sign_node_request(key, operation.name(), request_id, &fields);
+            operation.set_node_signature(signature);
+        }
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(request_id),
+            from_node: keys::public_key_hex(key),
+            chain: None,
+            structured: false,
+            operation,
+        }
+    }
+
+    pub fn new_health_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Health,
+        }
+    }
+
+    pub fn new_advance_time_request(node_id: String, seconds: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::AdvanceTime(AdvanceTimeOp { seconds }),
+        }
+    }
+
+    pub fn new_mint_now_request(node_id: String, signed_at: u64, signature: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::MintNow(AdminCommandOp { signed_at, signature }),
+        }
+    }
+
+    pub fn new_get_receipt_request(node_id: String, transaction_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetReceipt(GetTransactionOp { transaction_id }),
+        }
+    }
+
+    pub fn new_add_webhook_request(node_id: String, account_id: String, url: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::AddWebhook(WebhookOp { account_id, url }),
+        }
+    }
+
+    pub fn new_remove_webhook_request(node_id: String, account_id: String, url: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::RemoveWebhook(WebhookOp { account_id, url }),
+        }
+    }
+
+    pub fn new_get_webhooks_request(node_id: String, account_id: Option<String>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetWebhooks(GetWebhooksOp { account_id }),
+        }
+    }
+
+    pub fn new_get_audit_log_request(node_id: String, count: usize, operation: Option<String>, source: Option<String>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetAuditLog(GetAuditLogOp { count, operation, source }),
+        }
+    }
+
+    pub fn new_faucet_request(node_id: String, account_id: String, amount: Option<Amount>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::Faucet(FaucetOp { account_id, amount }),
+        }
+    }
+
+    pub fn new_get_peers_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetPeers,
+        }
+    }
+
+    pub fn new_get_validators_request(node_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetValidators,
+        }
+    }
+
+    pub fn new_get_headers_request(node_id: String, from_height: usize, count: usize) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetHeaders(GetHeadersOp { from_height, count }),
+        }
+    }
+
+    pub fn new_get_account_proof_request(node_id: String, account_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetAccountProof(GetAccountProofOp { account_id }),
+        }
+    }
+
+    pub fn new_get_transaction_proof_request(node_id: String, transaction_id: String) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::GetTransactionProof(GetTransactionOp { transaction_id }),
+        }
+    }
+
+    pub fn new_close_account_request(node_id: String, account_id: String, beneficiary_id: String, fee: Amount, nonce: u64) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::CloseAccount(CloseAccountOp {
+                account_id,
+                beneficiary_id,
+                fee,
+                nonce,
+            }),
+        }
+    }
+
+    pub fn new_freeze_account_request(node_id: String, account_id: String, signed_at: u64, signature: String, freeze: bool) -> Request {
+        let admin_info = AdminAccountOp {
+            account_id,
+            signed_at,
+            signature,
+        };
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: if freeze { Operation::FreezeAccount(admin_info) } else { Operation::UnfreezeAccount(admin_info) },
+        }
+    }
+
+    pub fn new_batch_transfer_request(node_id: String, transfers: Vec<FundTransferOp>) -> Request {
+        Request {
+            version: PROTOCOL_VERSION,
+            request_id: Some(random_id()),
+            from_node: node_id,
+            chain: None,
+            structured: false,
+            operation: Operation::BatchTransfer(BatchTransferOp { transfers }),
+        }
+    }
+}
+
+/// Returns the maximum size of a request or response of the given protocol version
+pub fn max_message_size(version: u16) -> usize {
+    if version >= 5 {
+        MAX_MESSAGE_SIZE
+    } else {
+        LEGACY_MAX_MESSAGE_SIZE
+    }
+}
+
+/// Returns a random ID, e.g. for a new request
+pub fn random_id() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+
+    // Every RandomState is seeded differently, which is random enough to tell requests apart
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_nanos());
+    hasher.finish()
+}
+
+/// Why a request failed, for clients telling errors apart without parsing their messages
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // A field of the request is invalid, rejected before the request was processed. The reason is e.g.
+    // `negative_amount`, the field is its name in the request, e.g. `amount`.
+    InvalidRequest { reason: String, field: Option<String> },
+    // The node is in permissioned mode and doesn't accept the request, the reason is `unauthenticated` or `not_allowed`
+    Unauthorized { reason: String },
+    // The client made more requests than the rate limit allows and can retry after the milliseconds
+    RateLimited { retry_after_ms: u64 },
+}
+
+/// Response of the server to a request, in the protocol version of the request
+#[derive(Serialize, Deserialize)]
+pub struct Response {
+    pub version: u16,
+    // ID of the request the response is for, so clients can tell responses to retries of earlier requests apart
+    pub request_id: Option<u64>,
+    // A user-facing message, either the result of the request or explaining why it failed
+    pub result: Result<String, String>,
+    // Why the request failed, None if it succeeded, if the error has no code or for responses of versions before 10
+    pub error_code: Option<ErrorCode>,
+}
+
+impl Response {
+    pub fn new(version: u16, request_id: Option<u64>, result: Result<String, String>) -> Response {
+        Response { version, request_id, result, error_code: None }
+    }
+
+    /// Sets the code of the error the request failed with
+    pub fn with_error_code(mut self, error_code: ErrorCode) -> Response {
+        self.error_code = Some(error_code);
+        self
+    }
+
+    /// Serializes the response in its protocol version. Version 1 clients only understand the message.
+    pub fn encode(&self) -> Vec<u8> {
+        self.encode_as(Format::Bincode, Compression::None)
+    }
+
+    /// Serializes the response in its protocol version and the format of the request, see [response_encoding]. It is
+    /// compressed with the compression the client accepts if it is at least `COMPRESSION_THRESHOLD` bytes and gets
+    /// smaller. Clients of versions before 7 only understand bincode.
+    pub fn encode_as(&self, format: Format, accepted: Compression) -> Vec<u8> {
+        let format = if self.version >= 7 { format } else { Format::Bincode };
+        let message = match self.version {
+            1 => return self.message().as_bytes().to_vec(),
+            2 => format.serialize(&ResponseV2 {
+                version: 2,
+                result: self.result.clone(),
+            }),
+            3..=9 => format.serialize(&ResponseV9 {
+                version: self.version,
+                request_id: self.request_id,
+                result: self.result.clone(),
+            }),
+            _ => format.serialize(self),
+        };
+        let message = message.expect("Failed to serialize response.");
+        if self.version >= 6 && accepted == Compression::Zstd && message.len() >= COMPRESSION_THRESHOLD {
+            let compressed = zstd::bulk::compress(&message, COMPRESSION_LEVEL).expect("Failed to compress response.");
+            if compressed.len() < message.len() {
+                return with_version(self.version, Compression::Zstd, format, &compressed);
+            }
+        }
+        with_version(self.version, Compression::None, format, &message)
+    }
+
+    /// Deserializes a response of any protocol version, decompressing it if it was compressed. Responses of version 1
+    /// nodes are plain messages, which don't say whether the request failed, so they are always treated as successful.
+    pub fn decode(bytes: &[u8]) -> Result<Response, String> {
+        let invalid = |e: &dyn std::fmt::Display| format!("❌ Invalid response from the server: {}", e);
+        let (header, message) = match split_header(bytes) {
+            None => {
+                let message = std::str::from_utf8(bytes).map_err(|_| "❌ Invalid response from the server.".to_string())?;
+                return Ok(Response::new(1, None, Ok(message.to_string())));
+            }
+            Some((header, message)) => (header, message),
+        };
+        let format = header.format.ok_or_else(|| invalid(&"unknown format"))?;
+        // Decompressed responses are limited in size, so a response can't exhaust the memory
+        let decompressed;
+        let message = match header.compression {
+            Compression::None => message,
+            Compression::Zstd => {
+                decompressed = zstd::bulk::decompress(message, MAX_DECOMPRESSED_SIZE).map_err(|e| invalid(&e))?;
+                &decompressed
+            }
+        };
+        match header.version {
+            2 => format
+                .deserialize::<ResponseV2>(message)
+                .map(|response| Response::new(2, None, response.result))
+                .map_err(|e| invalid(&e)),
+            3..=9 => format
+                .deserialize::<ResponseV9>(message)
+                .map(|response| Response::new(response.version, response.request_id, response.result))
+                .map_err(|e| invalid(&e)),
+            _ => format.deserialize(message).map_err(|e| invalid(&e)),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match &self.result {
+            Ok(message) | Err(message) => message,
         }
     }
 }
@@ -0,0 +1,263 @@
+//! Formats requests and responses are serialized in. Messages of protocol version 7 and later carry the format as a
+//! byte in their header, so clients in other languages can send JSON or CBOR instead of bincode, and get the response
+//! in the format of their request.
+//!
+//! JSON and CBOR messages have the same structure: enums are objects with the variant as their only key, e.g.
+//! `{"GetFunds": {"account_id": "Alice"}}`, and amounts are decimal strings. CBOR messages are converted from and to
+//! the JSON structure as described by RFC 8949, section 6.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+/// Deepest nesting of arrays and maps a CBOR message can have, so a message can't overflow the stack
+const MAX_CBOR_DEPTH: usize = 64;
+
+/// The format of a message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Bincode,
+    Json,
+    Cbor,
+}
+
+impl Format {
+    pub fn tag(self) -> u8 {
+        match self {
+            Format::Bincode => 0,
+            Format::Json => 1,
+            Format::Cbor => 2,
+        }
+    }
+
+    /// Returns: None for unknown tags, e.g. of formats added by later versions
+    pub fn from_tag(tag: u8) -> Option<Format> {
+        match tag {
+            0 => Some(Format::Bincode),
+            1 => Some(Format::Json),
+            2 => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    pub fn serialize<T: Serialize>(self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Bincode => bincode::serialize(value).map_err(|e| e.to_string()),
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+                let mut bytes = Vec::new();
+                write_cbor(&value, &mut bytes);
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Bincode => bincode::deserialize(bytes).map_err(|e| e.to_string()),
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut reader = CborReader { bytes, offset: 0 };
+                let value = reader.read(0)?;
+                if reader.offset != bytes.len() {
+                    return Err(format!("{} trailing bytes after the CBOR message", bytes.len() - reader.offset));
+                }
+                serde_json::from_value(value).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Format, String> {
+        match text {
+            "bincode" => Ok(Format::Bincode),
+            "json" => Ok(Format::Json),
+            "cbor" => Ok(Format::Cbor),
+            _ => Err(format!("Unknown format {}, expected bincode, json or cbor.", text)),
+        }
+    }
+}
+
+/// Writes the head of a CBOR data item: its major type and its argument, e.g. the length of a string
+fn write_head(major: u8, argument: u64, bytes: &mut Vec<u8>) {
+    let major = major << 5;
+    match argument {
+        0..=23 => bytes.push(major | argument as u8),
+        24..=0xff => bytes.extend_from_slice(&[major | 24, argument as u8]),
+        0x100..=0xffff => {
+            bytes.push(major | 25);
+            bytes.extend_from_slice(&(argument as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            bytes.push(major | 26);
+            bytes.extend_from_slice(&(argument as u32).to_be_bytes());
+        }
+        _ => {
+            bytes.push(major | 27);
+            bytes.extend_from_slice(&argument.to_be_bytes());
+        }
+    }
+}
+
+fn write_cbor(value: &Value, bytes: &mut Vec<u8>) {
+    match value {
+        Value::Null => bytes.push(0xf6),
+        Value::Bool(false) => bytes.push(0xf4),
+        Value::Bool(true) => bytes.push(0xf5),
+        Value::Number(number) => match (number.as_u64(), number.as_i64()) {
+            (Some(unsigned), _) => write_head(0, unsigned, bytes),
+            (None, Some(negative)) => write_head(1, !negative as u64, bytes),
+            (None, None) => {
+                bytes.push(0xfb);
+                bytes.extend_from_slice(&number.as_f64().unwrap_or_default().to_be_bytes());
+            }
+        },
+        Value::String(text) => {
+            write_head(3, text.len() as u64, bytes);
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        Value::Array(items) => {
+            write_head(4, items.len() as u64, bytes);
+            items.iter().for_each(|item| write_cbor(item, bytes));
+        }
+        Value::Object(entries) => {
+            write_head(5, entries.len() as u64, bytes);
+            for (key, value) in entries {
+                write_cbor(&Value::String(key.clone()), bytes);
+                write_cbor(value, bytes);
+            }
+        }
+    }
+}
+
+/// Reads CBOR data items into their JSON structure. Byte strings are read as arrays of numbers, like bincode's byte
+/// arrays are serialized to JSON, and tags are skipped. Items of indefinite length aren't supported.
+struct CborReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> CborReader<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], String> {
+        let end = self.offset.checked_add(length).filter(|&end| end <= self.bytes.len()).ok_or("unexpected end of the CBOR message")?;
+        let taken = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(taken)
+    }
+
+    /// Reads the head of a data item
+    ///
+    /// Returns: the major type of the item and the argument of its head
+    fn head(&mut self) -> Result<(u8, u64), String> {
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into().unwrap()) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into().unwrap()) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into().unwrap()),
+            31 => return Err("CBOR items of indefinite length aren't supported".to_string()),
+            _ => return Err(format!("invalid CBOR head {:#04x}", initial)),
+        };
+        Ok((major, argument))
+    }
+
+    /// Returns the length argument of a string, array or map, which can't be longer than the rest of the message
+    fn length(&self, argument: u64) -> Result<usize, String> {
+        usize::try_from(argument)
+            .ok()
+            .filter(|&length| length <= self.bytes.len() - self.offset)
+            .ok_or_else(|| "CBOR length beyond the end of the message".to_string())
+    }
+
+    fn read(&mut self, depth: usize) -> Result<Value, String> {
+        if depth > MAX_CBOR_DEPTH {
+            return Err(format!("CBOR message nested deeper than {} levels", MAX_CBOR_DEPTH));
+        }
+        let initial = *self.bytes.get(self.offset).ok_or("unexpected end of the CBOR message")?;
+        let (major, argument) = match initial {
+            // Floats are read before the head, whose argument would be their bits
+            0xf9 => {
+                self.offset += 1;
+                return Ok(float(half_to_f64(u16::from_be_bytes(self.take(2)?.try_into().unwrap()))));
+            }
+            0xfa => {
+                self.offset += 1;
+                return Ok(float(f32::from_be_bytes(self.take(4)?.try_into().unwrap()) as f64));
+            }
+            0xfb => {
+                self.offset += 1;
+                return Ok(float(f64::from_be_bytes(self.take(8)?.try_into().unwrap())));
+            }
+            _ => self.head()?,
+        };
+        match major {
+            0 => Ok(Value::Number(argument.into())),
+            1 => {
+                let negative = i64::try_from(argument).map(|argument| -1 - argument).map_err(|_| "CBOR integer out of range")?;
+                Ok(Value::Number(negative.into()))
+            }
+            2 => {
+                let length = self.length(argument)?;
+                Ok(Value::Array(self.take(length)?.iter().map(|&byte| Value::Number(byte.into())).collect()))
+            }
+            3 => {
+                let length = self.length(argument)?;
+                let text = std::str::from_utf8(self.take(length)?).map_err(|e| e.to_string())?;
+                Ok(Value::String(text.to_string()))
+            }
+            4 => {
+                let length = self.length(argument)?;
+                (0..length).map(|_| self.read(depth + 1)).collect::<Result<_, _>>().map(Value::Array)
+            }
+            5 => {
+                let length = self.length(argument)?;
+                let mut entries = Map::new();
+                for _ in 0..length {
+                    let key = match self.read(depth + 1)? {
+                        Value::String(key) => key,
+                        // Integer keys are read as their decimal string, like JSON writes them
+                        Value::Number(key) => key.to_string(),
+                        _ => return Err("CBOR map keys must be strings or integers".to_string()),
+                    };
+                    entries.insert(key, self.read(depth + 1)?);
+                }
+                Ok(Value::Object(entries))
+            }
+            6 => self.read(depth + 1),
+            _ => match argument {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 | 23 => Ok(Value::Null),
+                _ => Err(format!("unsupported CBOR simple value {}", argument)),
+            },
+        }
+    }
+}
+
+/// Non-finite floats have no JSON structure and are read as null
+fn float(float: f64) -> Value {
+    Number::from_f64(float).map_or(Value::Null, Value::Number)
+}
+
+/// Converts a half-precision float to a double, see RFC 8949, appendix D
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = (half >> 10) & 0x1f;
+    let mantissa = (half & 0x3ff) as f64;
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent as i32 - 25),
+    };
+    if half & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
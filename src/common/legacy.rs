@@ -0,0 +1,120 @@
+//! Formats of requests and responses of earlier protocol versions, which the server still accepts and converts to the
+//! current version
+
+use serde::{Deserialize, Serialize};
+use super::{AccountCreationOp, Amount, BatchTransferOp, FundTransferOp, GetFundsOp, GetNonceOp, GetTransactionOp, HistoryOp, Operation};
+
+/// A request of protocol version 1, without a version
+#[derive(Deserialize)]
+pub struct RequestV1 {
+    pub from_node: String,
+    pub operation: OperationV3,
+}
+
+/// A request of protocol version 2, without a request ID
+#[derive(Deserialize)]
+pub struct RequestV2 {
+    pub version: u16,
+    pub from_node: String,
+    pub operation: OperationV3,
+}
+
+/// A request of protocol version 8, without asking for structured results
+#[derive(Deserialize)]
+pub struct RequestV8 {
+    pub version: u16,
+    pub request_id: Option<u64>,
+    pub from_node: String,
+    pub chain: Option<String>,
+    pub operation: Operation,
+}
+
+/// A request of protocol versions 4 to 7, without the chain it is for
+#[derive(Deserialize)]
+pub struct RequestV7 {
+    pub version: u16,
+    pub request_id: Option<u64>,
+    pub from_node: String,
+    pub operation: Operation,
+}
+
+/// A request of protocol version 3, without transfer memos
+#[derive(Deserialize)]
+pub struct RequestV3 {
+    pub version: u16,
+    pub request_id: Option<u64>,
+    pub from_node: String,
+    pub operation: OperationV3,
+}
+
+/// The operations of protocol versions 1 to 3, before transfers had a memo
+#[derive(Deserialize)]
+pub enum OperationV3 {
+    CreateAccount(AccountCreationOp),
+    TransferFunds(FundTransferOpV3),
+    GetFunds(GetFundsOp),
+    GetTransaction(GetTransactionOp),
+    GetNonce(GetNonceOp),
+    History(HistoryOp),
+    Shutdown,
+    BatchTransfer(BatchTransferOpV3),
+}
+
+#[derive(Deserialize)]
+pub struct FundTransferOpV3 {
+    pub from_account_id: String,
+    pub to_account_id: String,
+    pub amount: Amount,
+    pub fee: Amount,
+    pub nonce: u64,
+}
+
+#[derive(Deserialize)]
+pub struct BatchTransferOpV3 {
+    pub transfers: Vec<FundTransferOpV3>,
+}
+
+impl From<FundTransferOpV3> for FundTransferOp {
+    fn from(transfer: FundTransferOpV3) -> FundTransferOp {
+        FundTransferOp {
+            from_account_id: transfer.from_account_id,
+            to_account_id: transfer.to_account_id,
+            amount: transfer.amount,
+            fee: transfer.fee,
+            nonce: transfer.nonce,
+            memo: None,
+        }
+    }
+}
+
+impl From<OperationV3> for Operation {
+    fn from(operation: OperationV3) -> Operation {
+        match operation {
+            OperationV3::CreateAccount(account_info) => Operation::CreateAccount(account_info),
+            OperationV3::TransferFunds(transfer_info) => Operation::TransferFunds(transfer_info.into()),
+            OperationV3::GetFunds(get_info) => Operation::GetFunds(get_info),
+            OperationV3::GetTransaction(get_info) => Operation::GetTransaction(get_info),
+            OperationV3::GetNonce(get_info) => Operation::GetNonce(get_info),
+            OperationV3::History(history_info) => Operation::History(history_info),
+            OperationV3::Shutdown => Operation::Shutdown,
+            OperationV3::BatchTransfer(batch_info) => Operation::BatchTransfer(BatchTransferOp {
+                transfers: batch_info.transfers.into_iter().map(FundTransferOp::from).collect(),
+            }),
+        }
+    }
+}
+
+/// A response of protocol version 2, without a request ID
+#[derive(Serialize, Deserialize)]
+pub struct ResponseV2 {
+    pub version: u16,
+    pub result: Result<String, String>,
+}
+
+/// A response of protocol versions 3 to 9, without an error code
+#[derive(Serialize, Deserialize)]
+pub struct ResponseV9 {
+    pub version: u16,
+    pub request_id: Option<u64>,
+    pub result: Result<String, String>,
+}
@@ -0,0 +1,317 @@
+use serde::Deserialize;
+use crate::common::Amount;
+use crate::logging::LogFormat;
+use crate::vault::Secret;
+
+/// Configuration read from an optional TOML file (`--config toychain.toml`).
+/// Every value is optional, command line flags take precedence over the file and the file takes precedence over the
+/// built-in defaults.
+///
+/// Example:
+///
+/// ```toml
+/// [node]
+/// bind = "0.0.0.0"
+/// port = 1337
+/// mint_interval = 10
+/// mint_threshold = 100
+/// consensus = "pos"
+/// max_clock_skew = 30
+/// max_block_txs = 500
+/// max_block_bytes = 65536
+/// checkpoint_interval = 100
+/// snapshot_interval = 1000
+/// prune = false
+/// compact_after = 1000
+/// light = false
+/// peer = "192.168.1.10:1337"
+/// bootstrap = ["192.168.1.10:1337"]
+/// mdns = false
+/// simulate = false
+/// allow = ["f6a071a9ced762951d62a02ed6c9c6678b88783cff8155eb9e6049c83cd1214c"]
+/// rpc_port = 8545
+/// rest_port = 8081
+/// graphql_port = 8082
+/// grpc_port = 50051
+/// explorer_port = 8080
+/// ws_port = 8546
+/// metrics_port = 9100
+/// mempool_size = 10000
+/// mempool_sender_limit = 100
+/// min_fee = "0.01"
+/// reward_account = "Miner"
+/// admin_key = "<hex encoded public key, see `keygen`>"
+/// validator_key = "<hex encoded private key, see `keygen`>"
+/// enable_faucet = false
+/// faucet_amount = "100"
+/// faucet_cooldown = 3600
+/// rate_limit = 50
+/// rate_limit_burst = 100
+/// workers = 8
+/// genesis = "genesis.json"
+/// data_dir = "data"
+/// chains = ["test", "staging=genesis-staging.json"]
+/// shard_map = "shards.json"
+/// shard = 0
+/// key_file = "data.key"
+/// audit_log = "audit.log"
+/// log_level = "info"
+/// log_format = "text"
+///
+/// [client]
+/// server = "192.168.1.10:1337"
+/// shard_map = "shards.json"
+/// ```
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub node: NodeConfigFile,
+    pub client: ClientConfigFile,
+}
+
+/// The `[client]` section of the config file
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfigFile {
+    // The address of the node the client sends its requests to, the node port on localhost if not set
+    pub server: Option<String>,
+    // The shard map of the sharded cluster the client sends its requests to, see [crate::shard]
+    pub shard_map: Option<String>,
+}
+
+/// The `[node]` section of the config file
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct NodeConfigFile {
+    pub bind: Option<String>,
+    pub port: Option<u16>,
+    pub mint_interval: Option<u64>,
+    pub mint_threshold: Option<usize>,
+    pub consensus: Option<Consensus>,
+    pub max_clock_skew: Option<u64>,
+    pub max_block_txs: Option<usize>,
+    pub max_block_bytes: Option<usize>,
+    pub checkpoint_interval: Option<usize>,
+    pub snapshot_interval: Option<usize>,
+    pub prune: Option<bool>,
+    pub compact_after: Option<usize>,
+    pub light: Option<bool>,
+    pub peer: Option<String>,
+    pub bootstrap: Option<Vec<String>>,
+    pub mdns: Option<bool>,
+    pub simulate: Option<bool>,
+    pub allow: Option<Vec<String>>,
+    pub rpc_port: Option<u16>,
+    pub rest_port: Option<u16>,
+    pub graphql_port: Option<u16>,
+    pub grpc_port: Option<u16>,
+    pub explorer_port: Option<u16>,
+    pub ws_port: Option<u16>,
+    pub metrics_port: Option<u16>,
+    pub mempool_size: Option<usize>,
+    pub mempool_sender_limit: Option<usize>,
+    pub min_fee: Option<Amount>,
+    pub reward_account: Option<String>,
+    pub admin_key: Option<String>,
+    pub validator_key: Option<String>,
+    pub enable_faucet: Option<bool>,
+    pub faucet_amount: Option<Amount>,
+    pub faucet_cooldown: Option<u64>,
+    pub rate_limit: Option<u32>,
+    pub rate_limit_burst: Option<u32>,
+    pub workers: Option<usize>,
+    pub genesis: Option<String>,
+    pub data_dir: Option<String>,
+    pub chains: Option<Vec<String>>,
+    pub shard_map: Option<String>,
+    pub shard: Option<usize>,
+    pub key_file: Option<String>,
+    pub audit_log: Option<String>,
+    pub log_level: Option<String>,
+    pub log_format: Option<LogFormat>,
+}
+
+impl Config {
+    /// Reads and parses the config file at the given path
+    pub fn load(path: &str) -> Result<Config, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+        toml::from_str(&contents).map_err(|e| format!("Failed to parse config file {}: {}", path, e))
+    }
+}
+
+/// How the node decides when it mints a block
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Consensus {
+    // A block every mint interval
+    Timer,
+    // Proof of stake: every mint interval belongs to a validator picked by stake, only that validator mints a block
+    Pos,
+}
+
+impl std::str::FromStr for Consensus {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Consensus, String> {
+        match text {
+            "timer" => Ok(Consensus::Timer),
+            "pos" => Ok(Consensus::Pos),
+            _ => Err(format!("Invalid consensus {}, expected timer or pos.", text)),
+        }
+    }
+}
+
+/// Settings the server node is started with, after merging the command line flags, config file and defaults
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+    // The address the node binds its socket to
+    pub bind: String,
+    // The port the node listens on for requests
+    pub port: u16,
+    // The interval in seconds at which the node mints blocks
+    pub mint_interval: u64,
+    // The number of pending transactions at which the node mints a block without waiting for the mint interval, only
+    // the interval counts if not set
+    pub mint_threshold: Option<usize>,
+    // How the node decides when it mints a block
+    pub consensus: Consensus,
+    // Seconds the timestamp of a block of another node can be ahead of the node's clock
+    pub max_clock_skew: u64,
+    // The maximum number of transactions in the blocks the node mints, below the chain's limit, only the chain's if not
+    // set
+    pub max_block_txs: Option<usize>,
+    // The maximum number of bytes the transactions of the blocks the node mints take up, unlimited if not set
+    pub max_block_bytes: Option<usize>,
+    // The number of blocks between checkpoints finalizing the chain, no checkpoints are recorded if not set
+    pub checkpoint_interval: Option<usize>,
+    // The number of blocks between snapshots of the accounts, no snapshots are taken if not set
+    pub snapshot_interval: Option<usize>,
+    // Whether the transactions of the blocks before the latest snapshot are discarded
+    pub prune: bool,
+    // Blocks more than this many blocks below the tip are compressed into the archive file, none are if not set
+    pub compact_after: Option<usize>,
+    // Whether the node only follows the block headers of its peer, see `peer`
+    pub light: bool,
+    // The address of the full node a light node follows
+    pub peer: Option<String>,
+    // The addresses of the nodes a node asks for their peers when it starts, see `peers`
+    pub bootstrap: Vec<String>,
+    // Whether the node announces itself and discovers peers on the local network with mDNS
+    pub mdns: bool,
+    // Whether the node runs on a simulated clock and only mints when it is advanced, see `advance-time`
+    pub simulate: bool,
+    // Node IDs of the peers and public client keys of the clients the node accepts requests from, every request is
+    // accepted if empty
+    pub allow: Vec<String>,
+    // The port of the optional JSON-RPC HTTP server, disabled if not set
+    pub rpc_port: Option<u16>,
+    // The port of the optional REST API HTTP server, disabled if not set
+    pub rest_port: Option<u16>,
+    // The port of the optional GraphQL HTTP server, disabled if not set
+    pub graphql_port: Option<u16>,
+    // The port of the optional gRPC-Web server, disabled if not set
+    pub grpc_port: Option<u16>,
+    // The port of the optional block explorer HTTP server, disabled if not set
+    pub explorer_port: Option<u16>,
+    // The port of the optional WebSocket subscription server, disabled if not set
+    pub ws_port: Option<u16>,
+    // The port of the optional Prometheus metrics HTTP endpoint, disabled if not set
+    pub metrics_port: Option<u16>,
+    // The maximum number of transactions waiting to be minted
+    pub mempool_size: usize,
+    // The maximum number of transactions waiting to be minted per sender
+    pub mempool_sender_limit: usize,
+    // The lowest fee a transfer has to pay to be accepted
+    pub min_fee: Amount,
+    // The account the fees of minted blocks are paid to, the node ID is used if not set
+    pub reward_account: Option<String>,
+    // The public key admin operations, e.g. freezing accounts, have to be signed with, they are rejected if not set
+    pub admin_key: Option<String>,
+    // The private key the blocks minted by the node are signed with, a key of the reward account as a multisig account,
+    // blocks aren't signed if not set
+    pub validator_key: Option<String>,
+    // Whether the node pays funds from its faucet to the accounts that ask, for test networks
+    pub enable_faucet: bool,
+    // The most the faucet pays at once
+    pub faucet_amount: Amount,
+    // Seconds an account has to wait between two payments of the faucet
+    pub faucet_cooldown: u64,
+    // The requests per second every client can make over UDP and JSON-RPC, 0 disables rate limiting
+    pub rate_limit: u32,
+    // The number of requests a client can make at once before being limited to the rate
+    pub rate_limit_burst: u32,
+    // The number of requests processed at once, the number of CPUs if not set
+    pub workers: Option<usize>,
+    // Path to the genesis configuration of the chain, an empty default genesis is used if not set
+    pub genesis: Option<String>,
+    // Directory the ledger is persisted to, the ledger is only kept in memory if not set
+    pub data_dir: Option<String>,
+    // The named chains the node hosts besides its default chain, as NAME or NAME=GENESIS. Their ledgers are persisted
+    // to chains/NAME in the data directory.
+    pub chains: Vec<String>,
+    // The shard map of the sharded cluster the node is part of, the node isn't sharded if not set
+    pub shard_map: Option<String>,
+    // The shard of the shard map the node holds, set together with the shard map
+    pub shard: Option<usize>,
+    // Passphrase or key file the data directory is encrypted with, it isn't encrypted if not set
+    pub secret: Option<Secret>,
+    // Hash-chained file every request the node processes is recorded in, no audit log is kept if not set
+    pub audit_log: Option<String>,
+    // Log level filter (e.g. `debug` or `toychain=debug`), RUST_LOG or info is used if not set
+    pub log_level: Option<String>,
+    // Format of the log lines
+    pub log_format: LogFormat,
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        NodeConfig {
+            bind: "0.0.0.0".to_string(),
+            port: 1337,
+            mint_interval: 10,
+            mint_threshold: None,
+            consensus: Consensus::Timer,
+            max_clock_skew: 30,
+            max_block_txs: None,
+            max_block_bytes: None,
+            checkpoint_interval: None,
+            snapshot_interval: None,
+            prune: false,
+            compact_after: None,
+            light: false,
+            peer: None,
+            bootstrap: Vec::new(),
+            mdns: false,
+            simulate: false,
+            allow: Vec::new(),
+            rpc_port: None,
+            rest_port: None,
+            graphql_port: None,
+            grpc_port: None,
+            explorer_port: None,
+            ws_port: None,
+            metrics_port: None,
+            mempool_size: 10000,
+            mempool_sender_limit: 100,
+            min_fee: Amount::ZERO,
+            reward_account: None,
+            admin_key: None,
+            validator_key: None,
+            enable_faucet: false,
+            faucet_amount: Amount::from_minor_units(10_000),
+            faucet_cooldown: 3600,
+            rate_limit: 50,
+            rate_limit_burst: 100,
+            workers: None,
+            genesis: None,
+            data_dir: None,
+            chains: Vec::new(),
+            shard_map: None,
+            shard: None,
+            secret: None,
+            audit_log: None,
+            log_level: None,
+            log_format: LogFormat::Text,
+        }
+    }
+}
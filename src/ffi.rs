@@ -0,0 +1,165 @@
+//! C-compatible bindings of the client, so it can be embedded from C, Python (ctypes) or any other language with a C
+//! FFI. The functions and structs are declared in `include/toychain.h` and only built with the `cdylib` feature, e.g.
+//! `cargo rustc --lib --release --features cdylib --crate-type cdylib`.
+//!
+//! A client is created with [toychain_client_new] and freed with [toychain_client_free]. Every request returns a
+//! [ToychainResponse] holding the message of the node, which the caller frees with [toychain_response_free]. Strings
+//! are passed in and out as NUL-terminated UTF-8, and amounts as decimal strings like on the command line. Requests are
+//! sent in plaintext, and a client must not be used by several threads at the same time.
+
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+use std::time::Duration;
+use crate::client::Client;
+use crate::common::{validate_memo, Amount, Request};
+
+/// A client sending requests to a server node, opaque to C
+pub struct ToychainClient {
+    client: Client,
+    // The node ID the requests are sent from
+    node_id: String,
+}
+
+/// The response of a server node to a request
+#[repr(C)]
+pub struct ToychainResponse {
+    /// Whether the node processed the request, false if it rejected it or the request couldn't be sent
+    pub ok: bool,
+    /// The user-facing message of the node, or why the request failed, owned by the response
+    pub message: *mut c_char,
+}
+
+/// Creates a client sending requests to the server node at the given address
+///
+/// Returns: the client, or null if an argument is null or not UTF-8 or the client's socket can't be bound
+///
+/// # Safety
+///
+/// `server_addr` and `node_id` must be null or NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn toychain_client_new(server_addr: *const c_char, node_id: *const c_char, timeout_ms: u64, retries: u32) -> *mut ToychainClient {
+    let (Ok(server_addr), Ok(node_id)) = (string_argument(server_addr, "The server address"), string_argument(node_id, "The node ID")) else {
+        return ptr::null_mut();
+    };
+    // Panics must not unwind into the caller, the client panics if it can't bind its socket
+    match std::panic::catch_unwind(|| Client::new(server_addr, Duration::from_millis(timeout_ms), retries).quiet()) {
+        Ok(client) => Box::into_raw(Box::new(ToychainClient { client, node_id })),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Frees a client
+///
+/// # Safety
+///
+/// `client` must be null or a client returned by [toychain_client_new] that isn't freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn toychain_client_free(client: *mut ToychainClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Creates an account with a starting balance, given as a decimal string
+///
+/// # Safety
+///
+/// `client` must be null or a live client, and `account_id` and `starting_balance` null or NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn toychain_client_create_account(client: *mut ToychainClient, account_id: *const c_char, starting_balance: *const c_char) -> ToychainResponse {
+    send(client, |client| {
+        let account_id = string_argument(account_id, "The account ID")?;
+        let starting_balance = amount_argument(starting_balance, "The starting balance")?;
+        Ok(Request::new_create_account_request(client.node_id.clone(), account_id, starting_balance))
+    })
+}
+
+/// Transfers an amount between accounts with the next nonce of the from account, which is asked from the node first.
+/// The fee and memo are optional and may be null.
+///
+/// # Safety
+///
+/// `client` must be null or a live client, and the strings null or NUL-terminated.
+#[no_mangle]
+pub unsafe extern "C" fn toychain_client_transfer(
+    client: *mut ToychainClient,
+    from_account_id: *const c_char,
+    to_account_id: *const c_char,
+    amount: *const c_char,
+    fee: *const c_char,
+    memo: *const c_char,
+) -> ToychainResponse {
+    send(client, |client| {
+        let from_account_id = string_argument(from_account_id, "The from account ID")?;
+        let to_account_id = string_argument(to_account_id, "The to account ID")?;
+        let amount = amount_argument(amount, "The amount")?;
+        let fee = if fee.is_null() { Amount::ZERO } else { amount_argument(fee, "The fee")? };
+        let memo = if memo.is_null() { None } else { Some(string_argument(memo, "The memo")?) };
+        if let Some(memo) = &memo {
+            validate_memo(memo)?;
+        }
+        let nonce_request = Request::new_get_nonce_request(client.node_id.clone(), from_account_id.clone());
+        let nonce = client.client.send(&nonce_request)?.result?;
+        let nonce = nonce.parse::<u64>().map_err(|_| format!("❌ The node answered with an invalid nonce: {}", nonce))?;
+        Ok(Request::new_transfer_funds_request(client.node_id.clone(), from_account_id, to_account_id, amount, fee, nonce, memo))
+    })
+}
+
+/// Gets the balance of an account, by its ID or alias
+///
+/// # Safety
+///
+/// `client` must be null or a live client, and `account_id` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn toychain_client_get_balance(client: *mut ToychainClient, account_id: *const c_char) -> ToychainResponse {
+    send(client, |client| {
+        let account_id = string_argument(account_id, "The account ID")?;
+        Ok(Request::new_get_funds_request(client.node_id.clone(), account_id))
+    })
+}
+
+/// Frees the message of a response
+///
+/// # Safety
+///
+/// `response` must be returned by one of the request functions and not be freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn toychain_response_free(response: ToychainResponse) {
+    if !response.message.is_null() {
+        drop(CString::from_raw(response.message));
+    }
+}
+
+/// Builds a request with the client and sends it
+///
+/// Returns: the response of the node, or why the request couldn't be built or sent
+unsafe fn send(client: *mut ToychainClient, request: impl FnOnce(&mut ToychainClient) -> Result<Request, String>) -> ToychainResponse {
+    let Some(client) = client.as_mut() else {
+        return response(Err("❌ The client is null.".to_string()));
+    };
+    response(request(client).and_then(|request| client.client.send(&request)?.result))
+}
+
+fn response(result: Result<String, String>) -> ToychainResponse {
+    let (ok, message) = match result {
+        Ok(message) => (true, message),
+        Err(message) => (false, message),
+    };
+    // A NUL would end the string early, so it is left out
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    ToychainResponse { ok, message: message.into_raw() }
+}
+
+/// Reads a string argument
+///
+/// Returns: the string, or a user-facing error naming the argument if it is null or not UTF-8
+unsafe fn string_argument(pointer: *const c_char, name: &str) -> Result<String, String> {
+    if pointer.is_null() {
+        return Err(format!("❌ {} is missing.", name));
+    }
+    CStr::from_ptr(pointer).to_str().map(str::to_string).map_err(|_| format!("❌ {} isn't valid UTF-8.", name))
+}
+
+unsafe fn amount_argument(pointer: *const c_char, name: &str) -> Result<Amount, String> {
+    string_argument(pointer, name)?.parse::<Amount>()
+}
@@ -0,0 +1,229 @@
+//! Fragmentation of messages that don't fit into a single datagram. A packet (a plaintext message or an encrypted
+//! transport packet, see [crate::noise]) larger than `MAX_DATAGRAM_SIZE` is split into fragments that are sent as
+//! datagrams of their own:
+//!
+//! `TCF` | message ID (8 bytes) | index (2 bytes) | fragment count (2 bytes) | payload
+//!
+//! All integers are little endian. The receiver collects the fragments of a message by sender and message ID, and
+//! handles the packet once all of them arrived, in any order. Fragments of messages that aren't complete within
+//! `REASSEMBLY_TIMEOUT` are dropped, so a lost fragment is recovered by retrying the whole message. The incomplete
+//! messages are limited in number, per sender and in the memory they take, so a sender spraying fragments of messages
+//! it never completes can't exhaust the memory of the receiver.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use crate::common::MAX_MESSAGE_SIZE;
+use crate::noise::MAX_OVERHEAD;
+
+/// Maximum size of a datagram, small enough to fit into the MTU of most networks. Larger packets are fragmented.
+pub const MAX_DATAGRAM_SIZE: usize = 1200;
+
+/// Prefix of every fragment, distinct from the prefixes of versioned messages and encrypted packets
+const MAGIC: &[u8; 3] = b"TCF";
+
+/// Length of the header of a fragment
+const HEADER_LENGTH: usize = MAGIC.len() + 8 + 2 + 2;
+
+/// Maximum payload of a single fragment
+const MAX_PAYLOAD: usize = MAX_DATAGRAM_SIZE - HEADER_LENGTH;
+
+/// Maximum size of a reassembled packet: the largest message with the encryption overhead
+const MAX_PACKET_SIZE: usize = MAX_MESSAGE_SIZE + MAX_OVERHEAD;
+
+/// Time within which all fragments of a message have to arrive
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Number of bytes of incomplete messages kept at most, counting their payloads and the slots of their fragments,
+/// fragments beyond that are dropped
+const MAX_BUFFERED_BYTES: usize = 16 * 1024 * 1024;
+
+/// Number of bytes a message is charged for every fragment it has, whether it arrived yet or not
+const SLOT_SIZE: usize = std::mem::size_of::<Option<Vec<u8>>>();
+
+/// Number of incomplete messages kept at most from all senders
+const MAX_MESSAGES: usize = 4096;
+
+/// Number of incomplete messages kept at most from the same IP address
+const MAX_MESSAGES_PER_SENDER: usize = 16;
+
+/// Splits a packet into the datagrams to send it in: the packet itself if it fits, otherwise its fragments
+pub fn split(packet: &[u8], message_id: u64) -> Vec<Vec<u8>> {
+    if packet.len() <= MAX_DATAGRAM_SIZE {
+        return vec![packet.to_vec()];
+    }
+
+    let count = packet.len().div_ceil(MAX_PAYLOAD) as u16;
+    packet
+        .chunks(MAX_PAYLOAD)
+        .enumerate()
+        .map(|(index, payload)| {
+            let mut fragment = Vec::with_capacity(HEADER_LENGTH + payload.len());
+            fragment.extend_from_slice(MAGIC);
+            fragment.extend_from_slice(&message_id.to_le_bytes());
+            fragment.extend_from_slice(&(index as u16).to_le_bytes());
+            fragment.extend_from_slice(&count.to_le_bytes());
+            fragment.extend_from_slice(payload);
+            fragment
+        })
+        .collect()
+}
+
+/// Checks whether a datagram is a fragment rather than a whole packet
+pub fn is_fragment(datagram: &[u8]) -> bool {
+    datagram.starts_with(MAGIC)
+}
+
+/// The fragments of a message received so far
+struct Partial {
+    fragments: Vec<Option<Vec<u8>>>,
+    // Number of fragments received, the message is complete once all arrived
+    received: usize,
+    // Number of bytes the message is charged for, the slots of its fragments and the payload bytes received
+    size: usize,
+    started: Instant,
+}
+
+/// Collects the fragments of messages until they are complete
+#[derive(Default)]
+pub struct Reassembler {
+    // Incomplete messages by sender and message ID
+    messages: HashMap<(SocketAddr, u64), Partial>,
+    // Number of incomplete messages by the IP address of their sender
+    senders: HashMap<IpAddr, usize>,
+    // Number of bytes all incomplete messages are charged for
+    buffered: usize,
+}
+
+impl Reassembler {
+    /// Checks whether a fragment starts a message no other fragment of which is kept yet, so the sender can be rate
+    /// limited once per message before its fragments are buffered
+    pub fn starts_message(&self, src: SocketAddr, fragment: &[u8]) -> bool {
+        let message_id = fragment.get(3..11).map_or(0, |bytes| u64::from_le_bytes(bytes.try_into().unwrap()));
+        !self.messages.contains_key(&(src, message_id))
+    }
+
+    /// Adds a fragment received from the given sender
+    ///
+    /// Returns: the reassembled packet once all fragments of its message arrived, or why the fragment was dropped
+    pub fn add(&mut self, src: SocketAddr, fragment: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        if fragment.len() < HEADER_LENGTH || !is_fragment(fragment) {
+            return Err("invalid fragment".to_string());
+        }
+        let message_id = u64::from_le_bytes(fragment[3..11].try_into().unwrap());
+        let index = u16::from_le_bytes(fragment[11..13].try_into().unwrap()) as usize;
+        let count = u16::from_le_bytes(fragment[13..15].try_into().unwrap()) as usize;
+        let payload = &fragment[HEADER_LENGTH..];
+        if count < 2 || count > MAX_PACKET_SIZE.div_ceil(MAX_PAYLOAD) || index >= count || payload.is_empty() {
+            return Err(format!("invalid fragment {} of {} with {} bytes", index, count, payload.len()));
+        }
+        self.forget_expired();
+
+        // A new message is charged for the slots of all its fragments up front
+        let started = !self.messages.contains_key(&(src, message_id));
+        let charged = payload.len() + if started { count * SLOT_SIZE } else { 0 };
+        if self.buffered + charged > MAX_BUFFERED_BYTES {
+            return Err("too many incomplete messages".to_string());
+        }
+        if started {
+            let sent = self.senders.get(&src.ip()).copied().unwrap_or_default();
+            if self.messages.len() >= MAX_MESSAGES || sent >= MAX_MESSAGES_PER_SENDER {
+                return Err(format!("too many incomplete messages, {} of them from {}", self.messages.len(), src.ip()));
+            }
+            self.senders.insert(src.ip(), sent + 1);
+            self.buffered += count * SLOT_SIZE;
+        }
+
+        let partial = self.messages.entry((src, message_id)).or_insert_with(|| Partial {
+            fragments: vec![None; count],
+            received: 0,
+            size: count * SLOT_SIZE,
+            started: Instant::now(),
+        });
+        if partial.fragments.len() != count {
+            return Err(format!("fragment of message {} with a different fragment count", message_id));
+        }
+        if partial.fragments[index].is_some() {
+            return Ok(None);
+        }
+        partial.fragments[index] = Some(payload.to_vec());
+        partial.received += 1;
+        partial.size += payload.len();
+        self.buffered += payload.len();
+
+        if partial.received < count {
+            return Ok(None);
+        }
+        let partial = self.messages.remove(&(src, message_id)).unwrap();
+        self.forget_message(src.ip(), partial.size);
+        Ok(Some(partial.fragments.into_iter().flatten().flatten().collect()))
+    }
+
+    /// Drops the fragments of messages that didn't complete in time
+    fn forget_expired(&mut self) {
+        let now = Instant::now();
+        let mut expired = Vec::new();
+        self.messages.retain(|(src, _), partial| {
+            let keep = now.duration_since(partial.started) < REASSEMBLY_TIMEOUT;
+            if !keep {
+                expired.push((src.ip(), partial.size));
+            }
+            keep
+        });
+        for (sender, size) in expired {
+            self.forget_message(sender, size);
+        }
+    }
+
+    /// Releases what an incomplete message of the sender was charged for once it completed or expired
+    fn forget_message(&mut self, sender: IpAddr, size: usize) {
+        self.buffered -= size;
+        if let Some(sent) = self.senders.get_mut(&sender) {
+            *sent -= 1;
+            if *sent == 0 {
+                self.senders.remove(&sender);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment(message_id: u64, index: u16, count: u16, payload: &[u8]) -> Vec<u8> {
+        [MAGIC.as_slice(), &message_id.to_le_bytes(), &index.to_le_bytes(), &count.to_le_bytes(), payload].concat()
+    }
+
+    #[test]
+    fn reassembles_fragments_in_any_order() {
+        let packet: Vec<u8> = (0..3 * MAX_PAYLOAD).map(|byte| byte as u8).collect();
+        let mut fragments = split(&packet, 7);
+        fragments.reverse();
+        let src = SocketAddr::from(([127, 0, 0, 1], 1));
+        let mut reassembler = Reassembler::default();
+        assert_eq!(reassembler.add(src, &fragments[0]), Ok(None));
+        assert_eq!(reassembler.add(src, &fragments[1]), Ok(None));
+        assert_eq!(reassembler.add(src, &fragments[2]), Ok(Some(packet)));
+        assert!(reassembler.messages.is_empty() && reassembler.senders.is_empty());
+        assert_eq!(reassembler.buffered, 0);
+    }
+
+    #[test]
+    fn limits_incomplete_messages() {
+        let mut reassembler = Reassembler::default();
+        let src = SocketAddr::from(([127, 0, 0, 1], 1));
+        assert!(reassembler.add(src, &fragment(1, 0, 2, &[])).is_err());
+        assert!(reassembler.messages.is_empty());
+
+        // Spraying message IDs only keeps as many messages as a sender may have, each charged for its slots
+        for message_id in 0..MAX_MESSAGES_PER_SENDER as u64 * 2 {
+            let _ = reassembler.add(src, &fragment(message_id, 0, 2, &[0]));
+        }
+        assert_eq!(reassembler.messages.len(), MAX_MESSAGES_PER_SENDER);
+        assert_eq!(reassembler.buffered, MAX_MESSAGES_PER_SENDER * (2 * SLOT_SIZE + 1));
+        // Even from other ports, but other senders are still accepted
+        assert!(reassembler.add(SocketAddr::from(([127, 0, 0, 1], 2)), &fragment(0, 0, 2, &[0])).is_err());
+        assert_eq!(reassembler.add(SocketAddr::from(([127, 0, 0, 2], 1)), &fragment(0, 0, 2, &[0])), Ok(None));
+    }
+}
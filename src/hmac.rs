@@ -0,0 +1,178 @@
+//! HMAC (RFC 2104) and PBKDF2 (RFC 8018) over the SHA-2 hashes, shared by the key derivation of [crate::vault] from a
+//! passphrase and the SLIP-0010 key derivation of the wallet.
+
+use sha2::{Digest, Sha256, Sha512};
+
+/// A hash function HMAC is computed with
+pub trait HashFunction {
+    /// Number of bytes of the blocks the hash function processes, which the HMAC key is padded to
+    const BLOCK_SIZE: usize;
+
+    /// Returns the hash of the parts, one after the other
+    fn hash(parts: &[&[u8]]) -> Vec<u8>;
+}
+
+impl HashFunction for Sha256 {
+    const BLOCK_SIZE: usize = 64;
+
+    fn hash(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().to_vec()
+    }
+}
+
+impl HashFunction for Sha512 {
+    const BLOCK_SIZE: usize = 128;
+
+    fn hash(parts: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = Sha512::new();
+        parts.iter().for_each(|part| hasher.update(part));
+        hasher.finalize().to_vec()
+    }
+}
+
+/// Returns the HMAC of the data with the given key
+pub fn hmac<H: HashFunction>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut padded_key = vec![0u8; H::BLOCK_SIZE];
+    if key.len() > H::BLOCK_SIZE {
+        let hashed_key = H::hash(&[key]);
+        padded_key[..hashed_key.len()].copy_from_slice(&hashed_key);
+    } else {
+        padded_key[..key.len()].copy_from_slice(key);
+    }
+
+    let inner_key: Vec<u8> = padded_key.iter().map(|b| b ^ 0x36).collect();
+    let outer_key: Vec<u8> = padded_key.iter().map(|b| b ^ 0x5c).collect();
+    H::hash(&[&outer_key, &H::hash(&[&inner_key, data])])
+}
+
+/// Returns the HMAC-SHA256 of the data with the given key
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; 32] {
+    hmac::<Sha256>(key, data).try_into().unwrap()
+}
+
+/// Returns the HMAC-SHA512 of the data with the given key
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    hmac::<Sha512>(key, data).try_into().unwrap()
+}
+
+/// Derives a key from a password with PBKDF2 and HMAC over the given hash function, filling the output
+pub fn pbkdf2<H: HashFunction>(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    // The output is made of blocks numbered from 1, the last one cut to the length of the output
+    for (index, chunk) in (1u32..).zip(output.chunks_mut(H::hash(&[]).len())) {
+        let mut block = hmac::<H>(password, &[salt, &index.to_be_bytes()].concat());
+        let mut key = block.clone();
+        for _ in 1..iterations {
+            block = hmac::<H>(password, &block);
+            for (byte, next) in key.iter_mut().zip(&block) {
+                *byte ^= next;
+            }
+        }
+        chunk.copy_from_slice(&key[..chunk.len()]);
+    }
+}
+
+/// Derives a 32 byte key from a password with PBKDF2-HMAC-SHA256
+pub fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::<Sha256>(password, salt, iterations, &mut key);
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::to_hex;
+
+    // SHA-1 is only needed for the test vectors of RFC 6070
+    impl HashFunction for sha1::Sha1 {
+        const BLOCK_SIZE: usize = 64;
+
+        fn hash(parts: &[&[u8]]) -> Vec<u8> {
+            let mut hasher = <sha1::Sha1 as sha1::Digest>::new();
+            parts.iter().for_each(|part| sha1::Digest::update(&mut hasher, part));
+            sha1::Digest::finalize(hasher).to_vec()
+        }
+    }
+
+    fn pbkdf2_hex<H: HashFunction>(password: &[u8], salt: &[u8], iterations: u32, length: usize) -> String {
+        let mut key = vec![0u8; length];
+        pbkdf2::<H>(password, salt, iterations, &mut key);
+        to_hex(&key)
+    }
+
+    /// The test cases of RFC 4231, section 4, except test case 5, whose output is truncated
+    #[test]
+    fn computes_rfc_4231_test_vectors() {
+        let cases: [(&[u8], &[u8], &str, &str); 6] = [
+            (
+                &[0x0b; 20],
+                b"Hi There",
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+                "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854",
+            ),
+            (
+                b"Jefe",
+                b"what do ya want for nothing?",
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+                "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737",
+            ),
+            (
+                &[0xaa; 20],
+                &[0xdd; 50],
+                "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe",
+                "fa73b0089d56a284efb0f0756c890be9b1b5dbdd8ee81a3655f83e33b2279d39bf3e848279a722c806b485a47e67c807b946a337bee8942674278859e13292fb",
+            ),
+            (
+                &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25],
+                &[0xcd; 50],
+                "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b",
+                "b0ba465637458c6990e5a8c5f61d4af7e576d97ff94b872de76f8050361ee3dba91ca5c11aa25eb4d679275cc5788063a5f19741120c4f2de2adebeb10a298dd",
+            ),
+            (
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First",
+                "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54",
+                "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598",
+            ),
+            (
+                &[0xaa; 131],
+                b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.",
+                "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2",
+                "e37b6a775dc87dbaa4dfa9f96e5e3ffddebd71f8867289865df5a32d20cdc944b6022cac3c4982b10d5eeb55c3e4de15134676fb6de0446065c97440fa8c6a58",
+            ),
+        ];
+        for (key, data, sha256, sha512) in cases {
+            assert_eq!(to_hex(&hmac_sha256(key, data)), sha256);
+            assert_eq!(to_hex(&hmac_sha512(key, data)), sha512);
+        }
+    }
+
+    /// The test vectors of RFC 6070 for PBKDF2-HMAC-SHA1, except the one with 16777216 iterations
+    #[test]
+    fn derives_rfc_6070_test_vectors() {
+        assert_eq!(pbkdf2_hex::<sha1::Sha1>(b"password", b"salt", 1, 20), "0c60c80f961f0e71f3a9b524af6012062fe037a6");
+        assert_eq!(pbkdf2_hex::<sha1::Sha1>(b"password", b"salt", 2, 20), "ea6c014dc72d6f8ccd1ed92ace1d41f0d8de8957");
+        assert_eq!(pbkdf2_hex::<sha1::Sha1>(b"password", b"salt", 4096, 20), "4b007901b765489abead49d926f721d065a429c1");
+        assert_eq!(
+            pbkdf2_hex::<sha1::Sha1>(b"passwordPASSWORDpassword", b"saltSALTsaltSALTsaltSALTsaltSALTsalt", 4096, 25),
+            "3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038"
+        );
+        assert_eq!(pbkdf2_hex::<sha1::Sha1>(b"pass\0word", b"sa\0lt", 4096, 16), "56fa6aa75548099dcc37d7f03425e0c3");
+    }
+
+    /// The PBKDF2-HMAC-SHA256 test vectors of RFC 7914, section 11, for the SHA-256 the vault derives its keys with
+    #[test]
+    fn derives_rfc_7914_test_vectors() {
+        assert_eq!(
+            pbkdf2_hex::<Sha256>(b"passwd", b"salt", 1, 64),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"
+        );
+        assert_eq!(
+            pbkdf2_hex::<Sha256>(b"Password", b"NaCl", 80000, 64),
+            "4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56a1d425a1225833549adb841b51c9b3176a272bdebba1d078478f62b397f33c8d"
+        );
+        assert_eq!(to_hex(&pbkdf2_sha256(b"passwd", b"salt", 1)), "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc");
+    }
+}
@@ -0,0 +1,185 @@
+//! Ed25519 keys that approve and cancel transfers from multisig accounts, sign minted blocks, authenticate admin
+//! operations and identify nodes. Keys are passed around hex encoded: the private key as its 32 byte seed and the
+//! public key as its 32 byte compressed point.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use crate::noise::{from_hex, to_hex};
+
+/// Generates a new private key from the operating system's random number generator
+pub fn generate() -> SigningKey {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).expect("Failed to generate a random key.");
+    SigningKey::from_bytes(&seed)
+}
+
+/// Parses a hex encoded private key
+pub fn parse_private_key(text: &str) -> Result<SigningKey, String> {
+    let seed: [u8; 32] = from_hex(text)?
+        .try_into()
+        .map_err(|_| "❌ Invalid private key, expected 32 hex encoded bytes.".to_string())?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Parses a hex encoded public key
+pub fn parse_public_key(text: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = from_hex(text)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("❌ Invalid public key {}, expected 32 hex encoded bytes.", text))?;
+    VerifyingKey::from_bytes(&bytes).map_err(|_| format!("❌ Invalid public key {}, it isn't a point on the curve.", text))
+}
+
+/// Returns the hex encoded public key of a private key
+pub fn public_key_hex(key: &SigningKey) -> String {
+    to_hex(key.verifying_key().as_bytes())
+}
+
+/// Returns the hex encoded private key
+pub fn private_key_hex(key: &SigningKey) -> String {
+    to_hex(&key.to_bytes())
+}
+
+/// Returns the message an approval of a transaction signs. Every kind of message has its own prefix, so a signature
+/// is never valid for anything else signed with the same key.
+fn approval_message(transaction_id: &str) -> Vec<u8> {
+    format!("toychain approve {}", transaction_id).into_bytes()
+}
+
+/// Returns the message the cancellation of a pending transaction signs
+fn cancel_message(transaction_id: &str) -> Vec<u8> {
+    format!("toychain cancel {}", transaction_id).into_bytes()
+}
+
+/// Returns the message an admin operation on an account signs, e.g. `freeze`, with the unix time it was signed at
+fn admin_message(operation: &str, account_id: &str, signed_at: u64) -> Vec<u8> {
+    format!("toychain admin {} {} at {}", operation, account_id, signed_at).into_bytes()
+}
+
+/// Returns the message an admin command to the node itself signs, e.g. `mint-now`, with the unix time it was signed at
+fn admin_command_message(command: &str, signed_at: u64) -> Vec<u8> {
+    format!("toychain admin command {} at {}", command, signed_at).into_bytes()
+}
+
+/// Returns the message the signature of a block by the validator that minted it signs
+fn block_message(block_hash: &str, validator: &str) -> Vec<u8> {
+    format!("toychain block {} by {}", block_hash, validator).into_bytes()
+}
+
+/// Returns the message the signature of a block by the identity key of the node that minted it signs
+fn node_block_message(block_hash: &str) -> Vec<u8> {
+    format!("toychain node block {}", block_hash).into_bytes()
+}
+
+/// Returns the message a request from one node to another signs: the operation, the request ID and the fields of the
+/// operation
+fn node_request_message(operation: &str, request_id: u64, fields: &[u8]) -> Vec<u8> {
+    [format!("toychain node request {} {} ", operation, request_id).as_bytes(), fields].concat()
+}
+
+/// Signs the approval of a transaction
+///
+/// Returns: the hex encoded signature
+pub fn sign_approval(key: &SigningKey, transaction_id: &str) -> String {
+    to_hex(&key.sign(&approval_message(transaction_id)).to_bytes())
+}
+
+/// Verifies the signature of an approval of a transaction by the given public key
+///
+/// Returns: a user-facing error if the signature is invalid
+pub fn verify_approval(public_key: &str, transaction_id: &str, signature: &str) -> Result<(), String> {
+    verify(public_key, &approval_message(transaction_id), signature)
+        .map_err(|()| format!("❌ Invalid signature of the approval of transaction {}.", transaction_id))
+}
+
+/// Signs the cancellation of a pending transaction
+///
+/// Returns: the hex encoded signature
+pub fn sign_cancel(key: &SigningKey, transaction_id: &str) -> String {
+    to_hex(&key.sign(&cancel_message(transaction_id)).to_bytes())
+}
+
+/// Verifies the signature of the cancellation of a pending transaction by the given public key
+///
+/// Returns: a user-facing error if the signature is invalid
+pub fn verify_cancel(public_key: &str, transaction_id: &str, signature: &str) -> Result<(), String> {
+    verify(public_key, &cancel_message(transaction_id), signature)
+        .map_err(|()| format!("❌ Invalid signature of the cancellation of transaction {}.", transaction_id))
+}
+
+/// Signs an admin operation on an account
+///
+/// Returns: the hex encoded signature
+pub fn sign_admin(key: &SigningKey, operation: &str, account_id: &str, signed_at: u64) -> String {
+    to_hex(&key.sign(&admin_message(operation, account_id, signed_at)).to_bytes())
+}
+
+/// Verifies the signature of an admin operation on an account by the admin key
+///
+/// Returns: a user-facing error if the signature is invalid
+pub fn verify_admin(admin_key: &str, operation: &str, account_id: &str, signed_at: u64, signature: &str) -> Result<(), String> {
+    verify(admin_key, &admin_message(operation, account_id, signed_at), signature)
+        .map_err(|()| format!("❌ Invalid admin signature, the {} of account {} has to be signed with the node's admin key.", operation, account_id))
+}
+
+/// Signs an admin command to the node itself
+///
+/// Returns: the hex encoded signature
+pub fn sign_admin_command(key: &SigningKey, command: &str, signed_at: u64) -> String {
+    to_hex(&key.sign(&admin_command_message(command, signed_at)).to_bytes())
+}
+
+/// Verifies the signature of an admin command to the node by the admin key
+///
+/// Returns: a user-facing error if the signature is invalid
+pub fn verify_admin_command(admin_key: &str, command: &str, signed_at: u64, signature: &str) -> Result<(), String> {
+    verify(admin_key, &admin_command_message(command, signed_at), signature).map_err(|()| format!("❌ Invalid admin signature, {} has to be signed with the node's admin key.", command))
+}
+
+/// Signs a block minted by the given validator
+///
+/// Returns: the hex encoded signature
+pub fn sign_block(key: &SigningKey, block_hash: &str, validator: &str) -> String {
+    to_hex(&key.sign(&block_message(block_hash, validator)).to_bytes())
+}
+
+/// Verifies the signature of a block minted by the given validator by the given public key
+///
+/// Returns: an error if the signature is invalid
+pub fn verify_block(public_key: &str, block_hash: &str, validator: &str, signature: &str) -> Result<(), String> {
+    verify(public_key, &block_message(block_hash, validator), signature).map_err(|()| format!("invalid signature of block {} by validator {}", block_hash, validator))
+}
+
+/// Signs a block minted by the node with the given identity key
+///
+/// Returns: the hex encoded signature
+pub fn sign_node_block(key: &SigningKey, block_hash: &str) -> String {
+    to_hex(&key.sign(&node_block_message(block_hash)).to_bytes())
+}
+
+/// Verifies the signature of a block by the node with the given node ID, its hex encoded public key
+///
+/// Returns: an error if the signature is invalid
+pub fn verify_node_block(node_id: &str, block_hash: &str, signature: &str) -> Result<(), String> {
+    verify(node_id, &node_block_message(block_hash), signature).map_err(|()| format!("invalid signature of block {} by node {}", block_hash, node_id))
+}
+
+/// Signs a request to another node with the identity key of the sending node
+///
+/// Returns: the hex encoded signature
+pub fn sign_node_request(key: &SigningKey, operation: &str, request_id: u64, fields: &[u8]) -> String {
+    to_hex(&key.sign(&node_request_message(operation, request_id, fields)).to_bytes())
+}
+
+/// Verifies the signature of a request by the node with the given node ID, its hex encoded public key
+///
+/// Returns: a user-facing error if the signature is invalid
+pub fn verify_node_request(node_id: &str, operation: &str, request_id: u64, fields: &[u8], signature: &str) -> Result<(), String> {
+    verify(node_id, &node_request_message(operation, request_id, fields), signature)
+        .map_err(|()| format!("❌ Invalid signature of the {} request, it has to be signed with the identity key of node {}.", operation, node_id))
+}
+
+fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<(), ()> {
+    let public_key = parse_public_key(public_key).map_err(|_| ())?;
+    let signature: [u8; 64] = from_hex(signature).ok().and_then(|bytes| bytes.try_into().ok()).ok_or(())?;
+    public_key.verify(message, &Signature::from_bytes(&signature)).map_err(|_| ())
+}
@@ -0,0 +1,19 @@
+//! ToyChain as a library: the protocol between clients and server nodes, the client sending requests over it and the
+//! server node, which downstream crates can run in process with [server::Node]. The `toychain` binary builds the CLI on
+//! top of it. With the `cdylib` feature, [ffi] exposes the client to C and other languages.
+
+pub mod client;
+pub mod common;
+pub mod config;
+pub mod fragment;
+pub mod hmac;
+pub mod keys;
+pub mod logging;
+pub mod merkle;
+pub mod noise;
+pub mod server;
+pub mod shard;
+pub mod vault;
+
+#[cfg(feature = "cdylib")]
+pub mod ffi;
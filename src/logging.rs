@@ -0,0 +1,40 @@
+use serde::Deserialize;
+use tracing_subscriber::EnvFilter;
+
+/// Format log lines are written in
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // Human-readable lines
+    Text,
+    // One JSON object per line, for log aggregation
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<LogFormat, String> {
+        match text {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Invalid log format {}, expected text or json.", text)),
+        }
+    }
+}
+
+/// Sets up logging to stdout. The level is a filter like `info` or `toychain=debug`, if none is given the `RUST_LOG`
+/// environment variable is used, and the default level otherwise.
+pub fn init(level: Option<&str>, default_level: &str, format: LogFormat) -> Result<(), String> {
+    let filter = match level {
+        Some(level) => EnvFilter::try_new(level).map_err(|e| format!("Invalid log level {}: {}", level, e))?,
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level)),
+    };
+
+    let subscriber = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
+    }
+    Ok(())
+}
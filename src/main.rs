@@ -1,142 +1,3381 @@
-mod common;
-mod server;
+mod batch;
+mod bench;
+mod monitor;
+mod output;
+mod shell;
+mod wallet;
 
-use crate::common::Request;
-use clap::{Arg, Command};
+use toychain::{client, common, config, hmac, keys, logging, noise, server, shard, vault};
+
+use crate::client::Client;
+use crate::common::{validate_memo, validate_nft_metadata, validate_token, Amount, Asset, Direction, Format, FundTransferOp, ParameterChange, QueryTransactionsOp, Request, Timelock};
+use crate::config::{Config, NodeConfig};
+use crate::logging::LogFormat;
+use crate::shard::ShardMap;
+use crate::vault::Secret;
+use crate::wallet::Wallet;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use serde_json::{json, Value};
+use std::net::ToSocketAddrs;
+use std::process::ExitCode;
 
 fn cli() -> Command {
     Command::new("Toychain")
         .about("ToyChain")
-        .subcommand(Command::new("start-node").about("Start a ToyChain server node"))
+        .arg(
+            Arg::new("output")
+                .help("Print the results as text or as one JSON object per result, for scripts")
+                .long("output")
+                .global(true)
+                .value_parser(["text", "json"])
+                .default_value("text")
+                .value_name("FORMAT"),
+        )
+        .arg(
+            Arg::new("server")
+                .help("The address of the server node to send requests to [env: TOYCHAIN_SERVER] [default: 127.0.0.1 and the port of the config file]")
+                .long("server")
+                .global(true)
+                .value_name("HOST:PORT"),
+        )
+        .arg(
+            Arg::new("chain")
+                .help("The named chain on the server node to send requests to, see `start-node --host-chain` [default: the default chain of the node]")
+                .long("chain")
+                .global(true)
+                .value_name("NAME"),
+        )
+        .arg(
+            Arg::new("shard-map")
+                .help("The shard map of a sharded cluster: clients send the requests about an account to the node of its shard, nodes hold the shard given with `start-node --shard`")
+                .long("shard-map")
+                .global(true)
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("config")
+                .help("Path to a TOML config file to read defaults from")
+                .long("config")
+                .global(true)
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("encrypt")
+                .help("Send requests to the server over an encrypted session")
+                .long("encrypt")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("server-key")
+                .help("Only send encrypted requests to a server with this key (hex), implies --encrypt")
+                .long("server-key")
+                .global(true)
+                .value_name("KEY"),
+        )
+        .arg(
+            Arg::new("client-key")
+                .help("Authenticate encrypted requests with this private client key (hex), e.g. to a node in permissioned mode, see `keygen --client`. Implies --encrypt")
+                .long("client-key")
+                .global(true)
+                .value_name("PRIVATE_KEY"),
+        )
+        .arg(
+            Arg::new("wire-format")
+                .help("The format requests are sent in, the server responds in the same format")
+                .long("wire-format")
+                .global(true)
+                .default_value("bincode")
+                .value_parser(["bincode", "json", "cbor"])
+                .value_name("FORMAT"),
+        )
+        .arg(
+            Arg::new("no-compression")
+                .help("Ask the server for uncompressed responses, large responses are compressed with zstd by default")
+                .long("no-compression")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("timeout")
+                .help("Milliseconds to wait for a response before retrying a request, doubled with every retry")
+                .long("timeout")
+                .global(true)
+                .default_value("1000")
+                .value_parser(value_parser!(u64))
+                .value_name("MS"),
+        )
+        .arg(
+            Arg::new("retries")
+                .help("Number of times a request is retried if no response arrives")
+                .long("retries")
+                .global(true)
+                .default_value("3")
+                .value_parser(value_parser!(u32))
+                .value_name("COUNT"),
+        )
+        .arg(
+            Arg::new("wallet")
+                .help("The wallet file that signs for the accounts created with it, used if it exists")
+                .long("wallet")
+                .global(true)
+                .default_value(wallet::DEFAULT_WALLET_FILE)
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("key-file")
+                .help("Encrypt the data directory and wallet with a key derived from this file instead of the passphrase in TOYCHAIN_PASSPHRASE, e.g. 32 random bytes")
+                .long("key-file")
+                .global(true)
+                .value_name("FILE"),
+        )
+        .arg(
+            Arg::new("idempotency-key")
+                .help("Key making the request idempotent, a command repeated with the same key gets the first response")
+                .long("idempotency-key")
+                .global(true)
+                .value_name("KEY"),
+        )
+        .subcommand(
+            Command::new("start-node")
+                .about("Start a ToyChain server node")
+                .arg(
+                    Arg::new("port")
+                        .help("The port to listen on for requests [default: 1337]")
+                        .long("port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("bind")
+                        .help("The address to bind the node to [default: 0.0.0.0]")
+                        .long("bind")
+                        .value_name("ADDRESS"),
+                )
+                .arg(
+                    Arg::new("mint-interval")
+                        .help("The interval in seconds at which blocks are minted [default: 10]")
+                        .long("mint-interval")
+                        .value_parser(value_parser!(u64))
+                        .value_name("SECONDS"),
+                )
+                .arg(
+                    Arg::new("mint-threshold")
+                        .help("Also mint a block as soon as this many transactions are pending, without waiting for the mint interval")
+                        .long("mint-threshold")
+                        .value_parser(value_parser!(usize))
+                        .value_name("COUNT"),
+                )
+                .arg(
+                    Arg::new("consensus")
+                        .help("How blocks are minted: every mint interval, or in turns by the validators weighted by their stake [default: timer]")
+                        .long("consensus")
+                        .value_parser(["timer", "pos"])
+                        .value_name("CONSENSUS"),
+                )
+                .arg(
+                    Arg::new("max-clock-skew")
+                        .help("The seconds the timestamp of a block of another node can be ahead of this node's clock [default: 30]")
+                        .long("max-clock-skew")
+                        .value_parser(value_parser!(u64))
+                        .value_name("SECONDS"),
+                )
+                .arg(
+                    Arg::new("max-block-txs")
+                        .help("Mint at most this many transactions per block, if fewer than the chain allows, the others stay pending for the next blocks")
+                        .long("max-block-txs")
+                        .value_parser(value_parser!(usize))
+                        .value_name("COUNT"),
+                )
+                .arg(
+                    Arg::new("max-block-bytes")
+                        .help("Mint at most this many bytes of transactions per block, the others stay pending for the next blocks")
+                        .long("max-block-bytes")
+                        .value_parser(value_parser!(usize))
+                        .value_name("BYTES"),
+                )
+                .arg(
+                    Arg::new("checkpoint-interval")
+                        .help("Finalize the chain with a checkpoint every this many blocks, the node never switches to a fork replacing them")
+                        .long("checkpoint-interval")
+                        .value_parser(value_parser!(usize))
+                        .value_name("BLOCKS"),
+                )
+                .arg(
+                    Arg::new("snapshot-interval")
+                        .help("Take a snapshot of the accounts every this many blocks, the state is restored from the latest one when the blocks before it are pruned")
+                        .long("snapshot-interval")
+                        .value_parser(value_parser!(usize))
+                        .value_name("BLOCKS"),
+                )
+                .arg(
+                    Arg::new("prune")
+                        .help("Discard the transactions of the blocks before the latest snapshot, keeping only their headers")
+                        .long("prune")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("compact-after")
+                        .help("Compress the blocks more than this many blocks below the tip into an archive file in the data directory, in the background")
+                        .long("compact-after")
+                        .value_parser(value_parser!(usize))
+                        .value_name("BLOCKS"),
+                )
+                .arg(
+                    Arg::new("light")
+                        .help("Run a light node, which only syncs and verifies the block headers of its peer and verifies balances with Merkle proofs")
+                        .long("light")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("peer")
+                        .help("The address of the full node a light node follows, e.g. 192.168.1.10:1337")
+                        .long("peer")
+                        .value_name("ADDRESS"),
+                )
+                .arg(
+                    Arg::new("bootstrap")
+                        .help("The address of a node to ask for its peers on start, e.g. 192.168.1.10:1337, can be given several times")
+                        .long("bootstrap")
+                        .value_name("ADDRESS")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("mdns")
+                        .help("Announce the node and discover peers on the local network with mDNS")
+                        .long("mdns")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("simulate")
+                        .help("Run on a simulated clock that only moves with `advance-time`, minting the blocks due in the time advanced instead of on a timer")
+                        .long("simulate")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("allow")
+                        .help("Only accept requests from this peer (its node ID) or client (its public client key, see `keygen --client`), can be given several times")
+                        .long("allow")
+                        .value_name("PUBLIC_KEY")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("rpc-port")
+                        .help("Serve a JSON-RPC HTTP endpoint on this port")
+                        .long("rpc-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("rest-port")
+                        .help("Serve a REST API over HTTP on this port, described by its /openapi.json")
+                        .long("rest-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("graphql-port")
+                        .help("Serve a GraphQL endpoint for queries of blocks, transactions and accounts on this port")
+                        .long("graphql-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("grpc-port")
+                        .help("Serve the gRPC service of proto/toychain.proto as gRPC-Web (HTTP/1.1, not native gRPC) on this port")
+                        .long("grpc-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("ws-port")
+                        .help("Serve a WebSocket endpoint for subscribing to chain events on this port")
+                        .long("ws-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("metrics-port")
+                        .help("Serve Prometheus metrics at /metrics on this port")
+                        .long("metrics-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("explorer-port")
+                        .help("Serve a block explorer for browsers on this port")
+                        .long("explorer-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                )
+                .arg(
+                    Arg::new("mempool-size")
+                        .help("The maximum number of transactions waiting to be minted [default: 10000]")
+                        .long("mempool-size")
+                        .value_parser(value_parser!(usize))
+                        .value_name("COUNT"),
+                )
+                .arg(
+                    Arg::new("mempool-sender-limit")
+                        .help("The maximum number of transactions waiting to be minted per sender [default: 100]")
+                        .long("mempool-sender-limit")
+                        .value_parser(value_parser!(usize))
+                        .value_name("COUNT"),
+                )
+                .arg(
+                    Arg::new("min-fee")
+                        .help("The lowest fee a transfer has to pay to be accepted [default: 0]")
+                        .long("min-fee")
+                        .value_parser(value_parser!(Amount))
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("reward-account")
+                        .help("The account the fees of the blocks this node mints are paid to [default: the node ID]")
+                        .long("reward-account")
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("admin-key")
+                        .help("The public key admin operations such as freezing accounts have to be signed with, see `keygen`")
+                        .long("admin-key")
+                        .value_name("PUBLIC_KEY"),
+                )
+                .arg(
+                    Arg::new("validator-key")
+                        .help("The private key minted blocks are signed with, a key of the reward account as a multisig account, so its conflicting blocks can be slashed")
+                        .long("validator-key")
+                        .value_name("PRIVATE_KEY"),
+                )
+                .arg(
+                    Arg::new("enable-faucet")
+                        .help("Pay funds from a faucet to the accounts that ask with `faucet`, for test networks")
+                        .long("enable-faucet")
+                        .action(ArgAction::SetTrue),
+                )
+                .arg(
+                    Arg::new("faucet-amount")
+                        .help("The most the faucet pays at once [default: 100]")
+                        .long("faucet-amount")
+                        .value_parser(value_parser!(Amount))
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("faucet-cooldown")
+                        .help("The seconds an account has to wait between two payments of the faucet [default: 3600]")
+                        .long("faucet-cooldown")
+                        .value_parser(value_parser!(u64))
+                        .value_name("SECONDS"),
+                )
+                .arg(
+                    Arg::new("rate-limit")
+                        .help("The requests per second every client can make, 0 disables rate limiting [default: 50]")
+                        .long("rate-limit")
+                        .value_parser(value_parser!(u32))
+                        .value_name("REQUESTS"),
+                )
+                .arg(
+                    Arg::new("rate-limit-burst")
+                        .help("The number of requests a client can make at once [default: 100]")
+                        .long("rate-limit-burst")
+                        .value_parser(value_parser!(u32))
+                        .value_name("REQUESTS"),
+                )
+                .arg(
+                    Arg::new("workers")
+                        .help("The number of requests processed at once, requests changing the same account are processed in the order they arrive in [default: the number of CPUs]")
+                        .long("workers")
+                        .value_parser(value_parser!(u16).range(1..))
+                        .value_name("COUNT"),
+                )
+                .arg(
+                    Arg::new("genesis")
+                        .help("Path to the genesis configuration (genesis.json) of the chain")
+                        .long("genesis")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::new("data-dir")
+                        .help("Directory to persist the ledger to, the ledger is only kept in memory if not set")
+                        .long("data-dir")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::new("host-chain")
+                        .help("Host a named chain besides the default chain, with its own ledger, mempool and genesis, e.g. test or staging=genesis-staging.json. Clients send requests to it with --chain, can be given several times")
+                        .long("host-chain")
+                        .value_name("NAME[=GENESIS]")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("shard")
+                        .help("Hold this shard of the shard map given with --shard-map, only accepting requests about the accounts of the shard")
+                        .long("shard")
+                        .value_name("INDEX")
+                        .value_parser(value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("audit-log")
+                        .help("Record every request processed in this hash-chained audit log file, see `audit`")
+                        .long("audit-log")
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::new("log-level")
+                        .help("The log level filter, e.g. debug or toychain=debug [default: RUST_LOG or info]")
+                        .long("log-level")
+                        .value_name("LEVEL"),
+                )
+                .arg(
+                    Arg::new("log-format")
+                        .help("The format of the log lines [default: text]")
+                        .long("log-format")
+                        .value_parser(["text", "json"])
+                        .value_name("FORMAT"),
+                ),
+        )
+        .subcommand(
+            Command::new("create-account")
+                .about("Create an account on Toychain")
+                .arg(
+                    Arg::new("id-of-account")
+                        .help("The ID of the account to create")
+                        .index(1)
+                        .required(true)
+                        .value_name("ID"),
+                )
+                .arg(
+                    Arg::new("starting-balance")
+                        .help("The starting balance of the account")
+                        .index(2)
+                        .required(true)
+                        .value_name("BALANCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("faucet")
+                .about("Get funds for an account from the faucet of a test network node started with --enable-faucet")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to fund, or its alias")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount to get, at most the faucet amount of the node [default: the faucet amount]")
+                        .long("amount")
+                        .value_parser(value_parser!(Amount))
+                        .value_name("AMOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("create-multisig-account")
+                .about("Create an account on Toychain whose transfers have to be approved by a number of its keys")
+                .arg(
+                    Arg::new("id-of-account")
+                        .help("The ID of the account to create")
+                        .index(1)
+                        .required(true)
+                        .value_name("ID"),
+                )
+                .arg(
+                    Arg::new("starting-balance")
+                        .help("The starting balance of the account")
+                        .index(2)
+                        .required(true)
+                        .value_name("BALANCE"),
+                )
+                .arg(
+                    Arg::new("key")
+                        .help("A public key that can approve transfers from the account, see `keygen`")
+                        .long("key")
+                        .required(true)
+                        .action(ArgAction::Append)
+                        .value_name("PUBLIC_KEY"),
+                )
+                .arg(
+                    Arg::new("threshold")
+                        .help("The number of keys that have to approve a transfer")
+                        .long("threshold")
+                        .required(true)
+                        .value_parser(value_parser!(usize))
+                        .value_name("M"),
+                ),
+        )
+        .subcommand(
+            Command::new("transfer")
+                .about("Transfer funds between accounts on Toychain")
+                .arg(
+                    Arg::new("from-account")
+                        .help("The account to transfer funds from")
+                        .index(1)
+                        .required(true)
+                        .value_name("FROM"),
+                )
+                .arg(
+                    Arg::new("to-account")
+                        .help("The account to transfer funds to, or its alias")
+                        .index(2)
+                        .required(true)
+                        .value_name("TO"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount of funds to transfer")
+                        .index(3)
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the transfer, transfers with higher fees are minted first")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the transfer, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                )
+                .arg(
+                    Arg::new("memo")
+                        .help("Text to reference the transfer by, e.g. an invoice number")
+                        .long("memo")
+                        .value_name("TEXT"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .help("The token to transfer instead of the native coin, the fee is still paid in the native coin")
+                        .long("token")
+                        .conflicts_with("dry-run")
+                        .value_name("TOKEN"),
+                )
+                .arg(
+                    Arg::new("valid-until-height")
+                        .help("The last block height the transfer can be minted at, it expires afterwards")
+                        .long("valid-until-height")
+                        .conflicts_with_all(["valid-until-time", "token", "dry-run"])
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("valid-until-time")
+                        .help("The unix time in seconds until which the transfer can be minted, it expires afterwards")
+                        .long("valid-until-time")
+                        .conflicts_with_all(["token", "dry-run"])
+                        .value_parser(value_parser!(u64))
+                        .value_name("TIME"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .help("Only check whether the server would accept the transfer, without making it")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("close-account")
+                .about("Close an account on Toychain, transferring its remaining balance to a beneficiary")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to close")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("beneficiary")
+                        .help("The account the remaining balance is transferred to")
+                        .index(2)
+                        .required(true)
+                        .value_name("BENEFICIARY"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay out of the remaining balance")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the closing, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("stake")
+                .about("Stake funds of an account on Toychain, which makes it a validator minting blocks in proof-of-stake mode")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to stake funds of")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount to add to the stake of the account, it can't be spent anymore")
+                        .index(2)
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay on top of the stake")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the staking, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("unstake")
+                .about("Unstake funds of an account on Toychain, which can be spent again once the unstaking is minted")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to unstake funds of")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount to take off the stake of the account")
+                        .index(2)
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the unstaking")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the unstaking, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(Command::new("validators").about("Get the validators of Toychain, their stakes and the staking reward"))
+        .subcommand(Command::new("peers").about("Get the peers of the node and whether they are connected"))
+        .subcommand(
+            Command::new("register-alias")
+                .about("Register a name for an account on Toychain, which transfers and balance queries accept instead of the account ID")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to register the alias for")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("alias")
+                        .help("The alias, 3 to 32 lowercase letters, digits, -, _ and ., starting with a letter. The first account to register it keeps it")
+                        .index(2)
+                        .required(true)
+                        .value_name("ALIAS"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the registration")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the registration, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("create-token")
+                .about("Create a token on Toychain besides the native coin, whose whole supply goes to the creating account")
+                .arg(
+                    Arg::new("account")
+                        .help("The account creating the token, which receives its supply")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .help("The symbol of the token, 2 to 8 uppercase letters and digits, starting with a letter. The first account to create it keeps it")
+                        .index(2)
+                        .required(true)
+                        .value_name("TOKEN"),
+                )
+                .arg(
+                    Arg::new("supply")
+                        .help("The supply of the token")
+                        .index(3)
+                        .required(true)
+                        .value_name("SUPPLY"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the creation, in the native coin")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the creation, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("mint-nft")
+                .about("Mint an NFT on Toychain with a unique ID, owned by the minting account")
+                .arg(
+                    Arg::new("account")
+                        .help("The account minting the NFT, which owns it")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("metadata")
+                        .help("Text describing the NFT, e.g. the URL of an image, at most 256 bytes")
+                        .index(2)
+                        .required(true)
+                        .value_name("METADATA"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the minting")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the minting, fetched from the server if not given. The ID of the NFT is derived from it")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("transfer-nft")
+                .about("Transfer an NFT to another account on Toychain")
+                .arg(
+                    Arg::new("from-account")
+                        .help("The account owning the NFT")
+                        .index(1)
+                        .required(true)
+                        .value_name("FROM"),
+                )
+                .arg(
+                    Arg::new("to-account")
+                        .help("The account to transfer the NFT to, or its alias")
+                        .index(2)
+                        .required(true)
+                        .value_name("TO"),
+                )
+                .arg(
+                    Arg::new("nft")
+                        .help("The ID of the NFT")
+                        .index(3)
+                        .required(true)
+                        .value_name("NFT_ID"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the transfer")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the transfer, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("transfer-batch")
+                .about("Transfer funds in several transfers that are made all together or not at all")
+                .arg(
+                    Arg::new("file")
+                        .help("CSV file with the columns from,to,amount[,fee[,nonce[,memo]]], or a JSON file ending in .json")
+                        .index(1)
+                        .required(true)
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("approve")
+                .about("Approve a transfer from a multisig account with one of its keys")
+                .arg(
+                    Arg::new("transaction-id")
+                        .help("The ID of the transfer, as returned when it was proposed")
+                        .index(1)
+                        .required(true)
+                        .value_name("ID"),
+                )
+                .arg(
+                    Arg::new("private-key")
+                        .help("The private key to approve the transfer with")
+                        .long("private-key")
+                        .required(true)
+                        .value_name("PRIVATE_KEY"),
+                ),
+        )
+        .subcommand(
+            Command::new("cancel")
+                .about("Cancel a transaction from a multisig or wallet account that wasn't minted yet")
+                .arg(
+                    Arg::new("transaction-id")
+                        .help("The ID of the transaction")
+                        .index(1)
+                        .required(true)
+                        .value_name("ID"),
+                )
+                .arg(
+                    Arg::new("private-key")
+                        .help("A private key of the from account to cancel the transaction with")
+                        .long("private-key")
+                        .value_name("PRIVATE_KEY"),
+                )
+                .arg(
+                    Arg::new("account")
+                        .help("The from account, to cancel the transaction with its key in the wallet")
+                        .long("account")
+                        .conflicts_with("private-key")
+                        .value_name("ACCOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("freeze")
+                .about("Freeze an account so no transfers can be made from it, signed with the node's admin key")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to freeze")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("private-key")
+                        .help("The private key of the node's admin key")
+                        .long("private-key")
+                        .required(true)
+                        .value_name("PRIVATE_KEY"),
+                )
+                .arg(
+                    Arg::new("signed-at")
+                        .help("Unix time to sign at instead of now, the simulated time of a node started with --simulate")
+                        .long("signed-at")
+                        .value_parser(value_parser!(u64))
+                        .value_name("UNIX_TIME"),
+                ),
+        )
+        .subcommand(
+            Command::new("unfreeze")
+                .about("Unfreeze a frozen account, signed with the node's admin key")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to unfreeze")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("private-key")
+                        .help("The private key of the node's admin key")
+                        .long("private-key")
+                        .required(true)
+                        .value_name("PRIVATE_KEY"),
+                )
+                .arg(
+                    Arg::new("signed-at")
+                        .help("Unix time to sign at instead of now, the simulated time of a node started with --simulate")
+                        .long("signed-at")
+                        .value_parser(value_parser!(u64))
+                        .value_name("UNIX_TIME"),
+                ),
+        )
+        .subcommand(
+            Command::new("wallet")
+                .about("Manage the local wallet, whose keys sign for the accounts created with it")
+                .subcommand_required(true)
+                .subcommand(Command::new("new").about("Create a new wallet with a random mnemonic"))
+                .subcommand(
+                    Command::new("restore")
+                        .about("Restore a wallet from its mnemonic")
+                        .arg(
+                            Arg::new("mnemonic")
+                                .help("The words of the mnemonic, in quotes")
+                                .index(1)
+                                .required(true)
+                                .value_name("MNEMONIC"),
+                        )
+                        .arg(
+                            Arg::new("account")
+                                .help("An account created with the wallet, in the order they were created")
+                                .long("account")
+                                .action(ArgAction::Append)
+                                .value_name("ACCOUNT"),
+                        ),
+                )
+                .subcommand(Command::new("list").about("List the keys of the wallet and the accounts they sign for")),
+        )
+        .subcommand(
+            Command::new("keygen").about("Generate a key pair for approving transfers from multisig accounts").arg(
+                Arg::new("client")
+                    .help("Generate a client key for encrypted requests instead, whose public key can be allowed by nodes in permissioned mode")
+                    .long("client")
+                    .action(ArgAction::SetTrue),
+            ),
+        )
+        .subcommand(
+            Command::new("balance")
+                .about("Get the balance of an account on Toychain")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to get the balance of, or its alias")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .help("The token to get the balance in instead of the native coin")
+                        .long("token")
+                        .value_name("TOKEN"),
+                ),
+        )
+        .subcommand(
+            Command::new("escrow")
+                .about("Transfer funds into escrow on Toychain, claimable by the recipient until they expire")
+                .arg(
+                    Arg::new("from-account")
+                        .help("The account to transfer funds from")
+                        .index(1)
+                        .required(true)
+                        .value_name("FROM"),
+                )
+                .arg(
+                    Arg::new("to-account")
+                        .help("The account that can claim the funds, or its alias")
+                        .index(2)
+                        .required(true)
+                        .value_name("TO"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount of funds to escrow")
+                        .index(3)
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("release-height")
+                        .help("The block height from which the recipient can claim the funds")
+                        .long("release-height")
+                        .required_unless_present("release-time")
+                        .conflicts_with("release-time")
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("release-time")
+                        .help("The unix time in seconds from which the recipient can claim the funds")
+                        .long("release-time")
+                        .value_parser(value_parser!(u64))
+                        .value_name("TIME"),
+                )
+                .arg(
+                    Arg::new("expiry-height")
+                        .help("The block height from which the recipient can't claim the funds anymore and the sender can get them refunded")
+                        .long("expiry-height")
+                        .required_unless_present("expiry-time")
+                        .conflicts_with("expiry-time")
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("expiry-time")
+                        .help("The unix time in seconds from which the recipient can't claim the funds anymore and the sender can get them refunded")
+                        .long("expiry-time")
+                        .value_parser(value_parser!(u64))
+                        .value_name("TIME"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the transfer")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the transfer, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                )
+                .arg(
+                    Arg::new("memo")
+                        .help("Text to reference the transfer by, e.g. an invoice number")
+                        .long("memo")
+                        .value_name("TEXT"),
+                ),
+        )
+        .subcommand(
+            Command::new("claim-escrow")
+                .about("Claim the funds of an escrow on Toychain for its recipient")
+                .arg(
+                    Arg::new("escrow")
+                        .help("The ID of the escrow, the ID of the transaction that funded it")
+                        .index(1)
+                        .required(true)
+                        .value_name("ESCROW_ID"),
+                ),
+        )
+        .subcommand(
+            Command::new("refund-escrow")
+                .about("Refund the funds of an expired escrow on Toychain to its sender")
+                .arg(
+                    Arg::new("escrow")
+                        .help("The ID of the escrow, the ID of the transaction that funded it")
+                        .index(1)
+                        .required(true)
+                        .value_name("ESCROW_ID"),
+                ),
+        )
+        .subcommand(
+            Command::new("schedule-payment")
+                .about("Schedule recurring payments between accounts on Toychain, made when the blocks they are due in are minted")
+                .arg(
+                    Arg::new("from-account")
+                        .help("The account to pay from")
+                        .index(1)
+                        .required(true)
+                        .value_name("FROM"),
+                )
+                .arg(
+                    Arg::new("to-account")
+                        .help("The account to pay to, or its alias")
+                        .index(2)
+                        .required(true)
+                        .value_name("TO"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount of every payment")
+                        .index(3)
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("start-height")
+                        .help("The block height the first payment is due at")
+                        .long("start-height")
+                        .required(true)
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("interval")
+                        .help("The number of blocks between payments")
+                        .long("interval")
+                        .required(true)
+                        .value_parser(value_parser!(u64))
+                        .value_name("BLOCKS"),
+                )
+                .arg(
+                    Arg::new("count")
+                        .help("The number of payments")
+                        .long("count")
+                        .required(true)
+                        .value_parser(value_parser!(u64))
+                        .value_name("COUNT"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the schedule, the payments pay no fee")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the schedule, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                )
+                .arg(
+                    Arg::new("memo")
+                        .help("Text to reference the schedule by, e.g. a contract number")
+                        .long("memo")
+                        .value_name("TEXT"),
+                ),
+        )
+        .subcommand(
+            Command::new("cancel-schedule")
+                .about("Cancel the remaining payments of a payment schedule on Toychain")
+                .arg(
+                    Arg::new("account")
+                        .help("The account paying the schedule")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("schedule")
+                        .help("The ID of the schedule, the ID of the transaction that created it")
+                        .index(2)
+                        .required(true)
+                        .value_name("SCHEDULE_ID"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the cancellation")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the cancellation, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("offer-swap")
+                .about("Offer another account on Toychain to swap assets, swapped at once when it accepts the offer")
+                .arg(
+                    Arg::new("from-account")
+                        .help("The account making the offer")
+                        .index(1)
+                        .required(true)
+                        .value_name("FROM"),
+                )
+                .arg(
+                    Arg::new("to-account")
+                        .help("The account that can accept the offer, or its alias")
+                        .index(2)
+                        .required(true)
+                        .value_name("TO"),
+                )
+                .arg(
+                    Arg::new("amount")
+                        .help("The amount to give")
+                        .index(3)
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("token")
+                        .help("The token to give instead of the native coin")
+                        .long("token")
+                        .value_name("TOKEN"),
+                )
+                .arg(
+                    Arg::new("for")
+                        .help("The amount to take in exchange")
+                        .long("for")
+                        .required(true)
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("for-token")
+                        .help("The token to take in exchange instead of the native coin")
+                        .long("for-token")
+                        .value_name("TOKEN"),
+                )
+                .arg(
+                    Arg::new("expiry-height")
+                        .help("The block height from which the offer can't be accepted anymore")
+                        .long("expiry-height")
+                        .required(true)
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the offer, the account accepting it pays its own fee")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the offer, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                )
+                .arg(
+                    Arg::new("memo")
+                        .help("Text to reference the offer by, e.g. an order number")
+                        .long("memo")
+                        .value_name("TEXT"),
+                ),
+        )
+        .subcommand(
+            Command::new("accept-swap")
+                .about("Accept a swap offer made to an account on Toychain, swapping both assets at once")
+                .arg(
+                    Arg::new("account")
+                        .help("The account the offer is made to")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("offer")
+                        .help("The ID of the offer, the ID of the transaction that made it")
+                        .index(2)
+                        .required(true)
+                        .value_name("OFFER_ID"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the acceptance")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the acceptance, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("deploy-contract")
+                .about("Deploy a WebAssembly contract on Toychain, owned by the deploying account")
+                .arg(
+                    Arg::new("account")
+                        .help("The account deploying the contract")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("file")
+                        .help("The WebAssembly module (.wasm) of the contract")
+                        .index(2)
+                        .required(true)
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the deployment")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the deployment, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("call-contract")
+                .about("Call a function of a contract on Toychain, executed when the call is minted")
+                .arg(
+                    Arg::new("account")
+                        .help("The account making the call")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("contract")
+                        .help("The ID of the contract")
+                        .index(2)
+                        .required(true)
+                        .value_name("CONTRACT_ID"),
+                )
+                .arg(
+                    Arg::new("function")
+                        .help("The function of the contract to call")
+                        .index(3)
+                        .required(true)
+                        .value_name("FUNCTION"),
+                )
+                .arg(
+                    Arg::new("input")
+                        .help("Text the contract can read as the input of the call")
+                        .long("input")
+                        .default_value("")
+                        .value_name("TEXT"),
+                )
+                .arg(
+                    Arg::new("gas-limit")
+                        .help("The most gas the call can consume, it fails if it needs more")
+                        .long("gas-limit")
+                        .default_value("1000000")
+                        .value_parser(value_parser!(u64))
+                        .value_name("GAS"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the call")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the call, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                )
+                .arg(
+                    Arg::new("memo")
+                        .help("Text to reference the call by, e.g. an order number")
+                        .long("memo")
+                        .value_name("TEXT"),
+                ),
+        )
+        .subcommand(
+            Command::new("contract")
+                .about("Get the owner and storage of a contract on Toychain")
+                .arg(
+                    Arg::new("contract")
+                        .help("The ID of the contract")
+                        .index(1)
+                        .required(true)
+                        .value_name("CONTRACT_ID"),
+                ),
+        )
+        .subcommand(
+            Command::new("propose")
+                .about("Propose a change of a chain parameter on Toychain, decided by the weighted votes of the accounts")
+                .arg(
+                    Arg::new("account")
+                        .help("The account making the proposal")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("change")
+                        .help("The change, min_fee=FEE, max_block_transactions=COUNT, mint_interval=SECONDS, staking_reward=AMOUNT or epoch_length=BLOCKS")
+                        .index(2)
+                        .required(true)
+                        .value_name("PARAMETER=VALUE"),
+                )
+                .arg(
+                    Arg::new("activation-height")
+                        .help("The block height from which the change applies if the proposal passes, votes are counted right before it")
+                        .long("activation-height")
+                        .required(true)
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the proposal")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the proposal, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(
+            Command::new("vote")
+                .about("Vote for or against a proposal on Toychain, weighted by the account's balance and stake")
+                .arg(
+                    Arg::new("account")
+                        .help("The account voting")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("proposal")
+                        .help("The ID of the proposal, the ID of the transaction that made it")
+                        .index(2)
+                        .required(true)
+                        .value_name("PROPOSAL_ID"),
+                )
+                .arg(
+                    Arg::new("vote")
+                        .help("Whether the account votes for or against the proposal")
+                        .index(3)
+                        .required(true)
+                        .value_parser(["yes", "no"])
+                        .value_name("yes|no"),
+                )
+                .arg(
+                    Arg::new("fee")
+                        .help("The fee to pay for the vote")
+                        .long("fee")
+                        .default_value("0")
+                        .value_name("FEE"),
+                )
+                .arg(
+                    Arg::new("nonce")
+                        .help("The nonce of the vote, fetched from the server if not given")
+                        .long("nonce")
+                        .value_parser(value_parser!(u64))
+                        .value_name("NONCE"),
+                ),
+        )
+        .subcommand(Command::new("proposals").about("Get the chain parameters decided by governance and the open proposals on Toychain"))
+        .subcommand(Command::new("supply").about("Get the total and circulating supply of Toychain and its block reward"))
+        .subcommand(
+            Command::new("nft-owner")
+                .about("Get the owner and metadata of an NFT on Toychain")
+                .arg(
+                    Arg::new("nft")
+                        .help("The ID of the NFT")
+                        .index(1)
+                        .required(true)
+                        .value_name("NFT_ID"),
+                ),
+        )
+        .subcommand(
+            Command::new("nfts")
+                .about("List the NFTs owned by an account on Toychain")
+                .arg(
+                    Arg::new("account")
+                        .help("The account, or its alias")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("schedules")
+                .about("List the payment schedules an account on Toychain pays or is paid by")
+                .arg(
+                    Arg::new("account")
+                        .help("The account, or its alias")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("nonce")
+                .about("Get the next transfer nonce of an account on Toychain")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to get the next nonce of")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Get the transactions of an account on Toychain, most recent first")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to get the transactions of")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("page")
+                        .help("The page of transactions to get, starting at 1")
+                        .long("page")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize))
+                        .value_name("N"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .help("The number of transactions per page")
+                        .long("limit")
+                        .default_value("5")
+                        .value_parser(value_parser!(usize))
+                        .value_name("M"),
+                ),
+        )
+        .subcommand(
+            Command::new("query")
+                .about("Get the transactions of an account on Toychain that match filters, most recent first")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to get the transactions of")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("direction")
+                        .help("Only the transactions the account sent or only those it received")
+                        .long("direction")
+                        .value_parser(["sent", "received"])
+                        .value_name("DIRECTION"),
+                )
+                .arg(
+                    Arg::new("min-amount")
+                        .help("Only transactions of at least this amount")
+                        .long("min-amount")
+                        .value_parser(value_parser!(Amount))
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("max-amount")
+                        .help("Only transactions of at most this amount")
+                        .long("max-amount")
+                        .value_parser(value_parser!(Amount))
+                        .value_name("AMOUNT"),
+                )
+                .arg(
+                    Arg::new("from-height")
+                        .help("Only transactions minted at this height or later")
+                        .long("from-height")
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("to-height")
+                        .help("Only transactions minted at this height or earlier")
+                        .long("to-height")
+                        .value_parser(value_parser!(u64))
+                        .value_name("HEIGHT"),
+                )
+                .arg(
+                    Arg::new("since")
+                        .help("Only transactions minted in blocks at this unix time or later")
+                        .long("since")
+                        .value_parser(value_parser!(u64))
+                        .value_name("UNIX_TIME"),
+                )
+                .arg(
+                    Arg::new("until")
+                        .help("Only transactions minted in blocks at this unix time or earlier")
+                        .long("until")
+                        .value_parser(value_parser!(u64))
+                        .value_name("UNIX_TIME"),
+                )
+                .arg(
+                    Arg::new("page")
+                        .help("The page of transactions to get, starting at 1")
+                        .long("page")
+                        .default_value("1")
+                        .value_parser(value_parser!(usize))
+                        .value_name("N"),
+                )
+                .arg(
+                    Arg::new("limit")
+                        .help("The number of transactions per page")
+                        .long("limit")
+                        .default_value("5")
+                        .value_parser(value_parser!(usize))
+                        .value_name("M"),
+                ),
+        )
+        .subcommand(
+            Command::new("block")
+                .about("Get a block of Toychain with its transactions")
+                .arg(
+                    Arg::new("block")
+                        .help("The height or the hash of the block")
+                        .index(1)
+                        .required(true)
+                        .value_name("HEIGHT|HASH"),
+                ),
+        )
+        .subcommand(
+            Command::new("pending")
+                .about("Get the transactions waiting to be minted on Toychain")
+                .arg(
+                    Arg::new("account")
+                        .help("Only get the pending transactions of this account")
+                        .index(1)
+                        .value_name("ACCOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("tx")
+                .about("Get a transaction and its inclusion status on Toychain")
+                .arg(
+                    Arg::new("transaction-id")
+                        .help("The ID of the transaction, as returned when it was committed")
+                        .index(1)
+                        .required(true)
+                        .value_name("ID"),
+                ),
+        )
+        .subcommand(
+            Command::new("receipt")
+                .about("Get the receipt of a transaction: the block, height and index it was minted at and the fee it paid, or why it is pending or was rejected")
+                .arg(
+                    Arg::new("transaction-id")
+                        .help("The ID of the transaction, as returned when it was committed")
+                        .index(1)
+                        .required(true)
+                        .value_name("ID"),
+                ),
+        )
+        .subcommand(
+            Command::new("add-webhook")
+                .about("Register a URL the server node posts to whenever a block with transactions from or to an account is minted, only accepted from localhost")
+                .arg(
+                    Arg::new("account-id")
+                        .help("The account to watch")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .help("The http:// URL to post the activity of the account to")
+                        .index(2)
+                        .required(true)
+                        .value_name("URL"),
+                ),
+        )
+        .subcommand(
+            Command::new("remove-webhook")
+                .about("Remove a URL registered for the activity of an account, only accepted from localhost")
+                .arg(
+                    Arg::new("account-id")
+                        .help("The account the URL is registered for")
+                        .index(1)
+                        .required(true)
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("url")
+                        .help("The registered URL")
+                        .index(2)
+                        .required(true)
+                        .value_name("URL"),
+                ),
+        )
+        .subcommand(
+            Command::new("webhooks")
+                .about("List the webhooks registered on the server node, only accepted from localhost")
+                .arg(
+                    Arg::new("account-id")
+                        .help("Only list the webhooks of this account")
+                        .index(1)
+                        .value_name("ACCOUNT"),
+                ),
+        )
+        .subcommand(
+            Command::new("prove-balance")
+                .about("Get a proof file of the balance of an account or of a minted transaction, with the block headers to verify it offline")
+                .arg(
+                    Arg::new("account")
+                        .help("The account to prove the balance of")
+                        .index(1)
+                        .required_unless_present("transaction")
+                        .conflicts_with("transaction")
+                        .value_name("ACCOUNT"),
+                )
+                .arg(
+                    Arg::new("transaction")
+                        .help("Prove that the transaction with this ID was minted instead")
+                        .long("transaction")
+                        .value_name("ID"),
+                )
+                .arg(
+                    Arg::new("file")
+                        .help("The file to write the proof to")
+                        .long("file")
+                        .default_value("proof.json")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-proof")
+                .about("Verify a proof file made with prove-balance offline")
+                .arg(
+                    Arg::new("file")
+                        .help("The proof file")
+                        .index(1)
+                        .required(true)
+                        .value_name("FILE"),
+                )
+                .arg(
+                    Arg::new("genesis")
+                        .help("Path to the genesis configuration (genesis.json) the proven chain has to start from")
+                        .long("genesis")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("verify-chain")
+                .about("Verify the integrity of the chain in a node's data directory")
+                .arg(
+                    Arg::new("data-dir")
+                        .help("The data directory of the node")
+                        .long("data-dir")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::new("genesis")
+                        .help("Path to the genesis configuration (genesis.json) the chain was started from")
+                        .long("genesis")
+                        .value_name("FILE"),
+                ),
+        )
+        .subcommand(
+            Command::new("migrate")
+                .about("Migrate the ledger in a node's data directory to the current format version")
+                .arg(
+                    Arg::new("data-dir")
+                        .help("The data directory of the node, which must not be running")
+                        .long("data-dir")
+                        .value_name("DIR"),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .help("Only report the migrations that would be applied, without changing the data directory")
+                        .long("dry-run")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
         .subcommand(
-            Command::new("create-account")
-                .about("Create an account on Toychain")
+            Command::new("export-chain")
+                .about("Export the chain in a node's data directory to a file")
                 .arg(
-                    Arg::new("id-of-account")
-                        .help("The ID of the account to create")
+                    Arg::new("file")
+                        .help("The file to export the chain to")
                         .index(1)
                         .required(true)
-                        .value_name("ID"),
+                        .value_name("FILE"),
                 )
+                .args(chain_file_args()),
+        )
+        .subcommand(
+            Command::new("import-chain")
+                .about("Import a chain from a file into an empty data directory, after verifying it")
                 .arg(
-                    Arg::new("starting-balance")
-                        .help("The starting balance of the account")
-                        .index(2)
+                    Arg::new("file")
+                        .help("The file to import the chain from")
+                        .index(1)
                         .required(true)
-                        .value_name("BALANCE"),
+                        .value_name("FILE"),
+                )
+                .args(chain_file_args()),
+        )
+        .subcommand(
+            Command::new("snapshot")
+                .about("Write the accounts at a height to a snapshot file, or start a new data directory from one")
+                .subcommand_required(true)
+                .subcommand(
+                    Command::new("create")
+                        .about("Write the accounts at a height and the block headers up to it to a snapshot file")
+                        .arg(
+                            Arg::new("file")
+                                .help("The snapshot file to write")
+                                .index(1)
+                                .required(true)
+                                .value_name("FILE"),
+                        )
+                        .arg(
+                            Arg::new("height")
+                                .help("The height to take the snapshot at, the tip of the chain by default")
+                                .long("height")
+                                .value_parser(value_parser!(usize))
+                                .value_name("HEIGHT"),
+                        )
+                        .args(data_dir_args()),
+                )
+                .subcommand(
+                    Command::new("restore")
+                        .about("Start an empty data directory from a snapshot file, a node started on it only syncs the blocks after the snapshot")
+                        .arg(
+                            Arg::new("file")
+                                .help("The snapshot file to restore")
+                                .index(1)
+                                .required(true)
+                                .value_name("FILE"),
+                        )
+                        .args(data_dir_args()),
                 ),
         )
         .subcommand(
-            Command::new("transfer")
-                .about("Transfer funds between accounts on Toychain")
+            Command::new("submit-chain")
+                .about("Send the blocks of a chain file to the server node, which switches to them if they make a longer chain")
                 .arg(
-                    Arg::new("from-account")
-                        .help("The account to transfer funds from")
+                    Arg::new("file")
+                        .help("The file to send the blocks of, exported from another node")
                         .index(1)
                         .required(true)
-                        .value_name("FROM"),
+                        .value_name("FILE"),
                 )
+                .arg(format_arg()),
+        )
+        .subcommand(Command::new("node-status").about("Show the height, pending transactions and uptime of the server node"))
+        .subcommand(Command::new("health").about("Check whether the server node accepts requests and its minting progresses, exits with status 1 if it isn't ready"))
+        .subcommand(
+            Command::new("audit")
+                .about("Verify the audit log of a server node started with --audit-log and list its latest entries, only accepted from localhost")
                 .arg(
-                    Arg::new("to-account")
-                        .help("The account to transfer funds to")
-                        .index(2)
-                        .required(true)
-                        .value_name("TO"),
+                    Arg::new("count")
+                        .help("The number of entries to list")
+                        .long("count")
+                        .value_parser(value_parser!(usize))
+                        .default_value("20")
+                        .value_name("COUNT"),
                 )
                 .arg(
-                    Arg::new("amount")
-                        .help("The amount of funds to transfer")
-                        .index(3)
-                        .required(true)
-                        .value_name("AMOUNT"),
+                    Arg::new("operation")
+                        .help("Only list the requests of this operation, e.g. transfer_funds")
+                        .long("operation")
+                        .value_name("OPERATION"),
+                )
+                .arg(
+                    Arg::new("source")
+                        .help("Only list the requests from source addresses starting with this, e.g. an IP address")
+                        .long("source")
+                        .value_name("ADDRESS"),
                 ),
         )
         .subcommand(
-            Command::new("balance")
-                .about("Get the balance of an account on Toychain")
+            Command::new("advance-time")
+                .about("Advance the simulated clock of a server node started with --simulate, minting the blocks due in that time")
                 .arg(
-                    Arg::new("account")
-                        .help("The account to get the balance of")
+                    Arg::new("seconds")
+                        .help("The seconds to advance the clock by, a block is minted at every mint interval passed")
                         .index(1)
                         .required(true)
-                        .value_name("ACCOUNT"),
+                        .value_parser(value_parser!(u64))
+                        .value_name("SECONDS"),
+                ),
+        )
+        .subcommand(
+            Command::new("mint-now")
+                .about("Mint the pending transactions into a block right away instead of waiting for the mint interval, signed with the node's admin key")
+                .arg(
+                    Arg::new("private-key")
+                        .help("The private key of the node's admin key")
+                        .long("private-key")
+                        .required(true)
+                        .value_name("PRIVATE_KEY"),
+                )
+                .arg(
+                    Arg::new("signed-at")
+                        .help("Unix time to sign at instead of now, the simulated time of a node started with --simulate")
+                        .long("signed-at")
+                        .value_parser(value_parser!(u64))
+                        .value_name("UNIX_TIME"),
+                ),
+        )
+        .subcommand(Command::new("shutdown").about("Shut down the server node cleanly, minting its pending transactions"))
+        .subcommand(
+            Command::new("monitor")
+                .about("Show a live dashboard of the server node's height, recent blocks, mempool, peers and request throughput")
+                .arg(
+                    Arg::new("interval")
+                        .help("Milliseconds between two refreshes of the dashboard")
+                        .long("interval")
+                        .value_parser(value_parser!(u64).range(100..))
+                        .default_value("1000")
+                        .value_name("MS"),
+                )
+                .arg(
+                    Arg::new("ws-port")
+                        .help("The WebSocket port of the node (see --ws-port of start-node), to refresh as soon as blocks and transactions arrive")
+                        .long("ws-port")
+                        .value_parser(value_parser!(u16))
+                        .value_name("PORT"),
+                ),
+        )
+        .subcommand(
+            Command::new("bench")
+                .about("Generate load against the server node with concurrent synthetic clients and report its throughput, latencies and errors")
+                .arg(
+                    Arg::new("clients")
+                        .help("The number of concurrent clients")
+                        .long("clients")
+                        .value_parser(value_parser!(u16).range(1..=1000))
+                        .default_value("4")
+                        .value_name("N"),
+                )
+                .arg(
+                    Arg::new("tps")
+                        .help("The requests per second all clients send together, 0 to send them as fast as the node answers")
+                        .long("tps")
+                        .value_parser(value_parser!(u64))
+                        .default_value("100")
+                        .value_name("T"),
+                )
+                .arg(
+                    Arg::new("duration")
+                        .help("Seconds to send requests for, after the accounts of the clients are minted")
+                        .long("duration")
+                        .value_parser(value_parser!(u64).range(1..))
+                        .default_value("10")
+                        .value_name("D"),
                 ),
         )
+        .subcommand(Command::new("shell").about("Open a shell to run commands against the server node without starting the client for each one"))
+}
+
+/// Arguments of the commands that work on the chain in a data directory and a chain file
+fn chain_file_args() -> [Arg; 3] {
+    let [data_dir, genesis] = data_dir_args();
+    [data_dir, genesis, format_arg()]
+}
+
+fn data_dir_args() -> [Arg; 2] {
+    [
+        Arg::new("data-dir")
+            .help("The data directory of the node")
+            .long("data-dir")
+            .value_name("DIR"),
+        Arg::new("genesis")
+            .help("Path to the genesis configuration (genesis.json) the chain was started from")
+            .long("genesis")
+            .value_name("FILE"),
+    ]
+}
+
+fn format_arg() -> Arg {
+    Arg::new("format")
+        .help("The format of the chain file")
+        .long("format")
+        .value_parser(["json", "binary"])
+        .default_value("json")
+        .value_name("FORMAT")
+}
+
+/// Gets the format of the chain file given with `--format`
+fn chain_file_format(args: &ArgMatches) -> server::ExportFormat {
+    match args.get_one::<String>("format").unwrap().as_str() {
+        "binary" => server::ExportFormat::Binary,
+        _ => server::ExportFormat::Json,
+    }
+}
+
+/// Builds the node config from the `start-node` flags, falling back to the config file and then the defaults
+fn node_config(args: &ArgMatches, config: &Config, secret: Option<&Secret>) -> NodeConfig {
+    let defaults = NodeConfig::default();
+    NodeConfig {
+        bind: args
+            .get_one::<String>("bind")
+            .cloned()
+            .or_else(|| config.node.bind.clone())
+            .unwrap_or(defaults.bind),
+        port: args.get_one::<u16>("port").copied().or(config.node.port).unwrap_or(defaults.port),
+        mint_interval: args
+            .get_one::<u64>("mint-interval")
+            .copied()
+            .or(config.node.mint_interval)
+            .unwrap_or(defaults.mint_interval),
+        mint_threshold: args.get_one::<usize>("mint-threshold").copied().or(config.node.mint_threshold),
+        consensus: args
+            .get_one::<String>("consensus")
+            .map(|consensus| consensus.parse().unwrap())
+            .or(config.node.consensus)
+            .unwrap_or(defaults.consensus),
+        max_clock_skew: args
+            .get_one::<u64>("max-clock-skew")
+            .copied()
+            .or(config.node.max_clock_skew)
+            .unwrap_or(defaults.max_clock_skew),
+        max_block_txs: args.get_one::<usize>("max-block-txs").copied().or(config.node.max_block_txs),
+        max_block_bytes: args.get_one::<usize>("max-block-bytes").copied().or(config.node.max_block_bytes),
+        checkpoint_interval: args
+            .get_one::<usize>("checkpoint-interval")
+            .copied()
+            .or(config.node.checkpoint_interval),
+        snapshot_interval: args
+            .get_one::<usize>("snapshot-interval")
+            .copied()
+            .or(config.node.snapshot_interval),
+        prune: args.get_flag("prune") || config.node.prune.unwrap_or(defaults.prune),
+        compact_after: args.get_one::<usize>("compact-after").copied().or(config.node.compact_after),
+        light: args.get_flag("light") || config.node.light.unwrap_or(defaults.light),
+        peer: args.get_one::<String>("peer").cloned().or_else(|| config.node.peer.clone()),
+        bootstrap: match args.get_many::<String>("bootstrap") {
+            Some(addresses) => addresses.cloned().collect(),
+            None => config.node.bootstrap.clone().unwrap_or(defaults.bootstrap),
+        },
+        mdns: args.get_flag("mdns") || config.node.mdns.unwrap_or(defaults.mdns),
+        simulate: args.get_flag("simulate") || config.node.simulate.unwrap_or(defaults.simulate),
+        allow: match args.get_many::<String>("allow") {
+            Some(keys) => keys.cloned().collect(),
+            None => config.node.allow.clone().unwrap_or(defaults.allow),
+        },
+        rpc_port: args.get_one::<u16>("rpc-port").copied().or(config.node.rpc_port),
+        rest_port: args.get_one::<u16>("rest-port").copied().or(config.node.rest_port),
+        graphql_port: args.get_one::<u16>("graphql-port").copied().or(config.node.graphql_port),
+        grpc_port: args.get_one::<u16>("grpc-port").copied().or(config.node.grpc_port),
+        explorer_port: args.get_one::<u16>("explorer-port").copied().or(config.node.explorer_port),
+        ws_port: args.get_one::<u16>("ws-port").copied().or(config.node.ws_port),
+        metrics_port: args.get_one::<u16>("metrics-port").copied().or(config.node.metrics_port),
+        mempool_size: args
+            .get_one::<usize>("mempool-size")
+            .copied()
+            .or(config.node.mempool_size)
+            .unwrap_or(defaults.mempool_size),
+        mempool_sender_limit: args
+            .get_one::<usize>("mempool-sender-limit")
+            .copied()
+            .or(config.node.mempool_sender_limit)
+            .unwrap_or(defaults.mempool_sender_limit),
+        min_fee: args.get_one::<Amount>("min-fee").copied().or(config.node.min_fee).unwrap_or(defaults.min_fee),
+        reward_account: args
+            .get_one::<String>("reward-account")
+            .cloned()
+            .or_else(|| config.node.reward_account.clone()),
+        admin_key: args.get_one::<String>("admin-key").cloned().or_else(|| config.node.admin_key.clone()),
+        validator_key: args.get_one::<String>("validator-key").cloned().or_else(|| config.node.validator_key.clone()),
+        enable_faucet: args.get_flag("enable-faucet") || config.node.enable_faucet.unwrap_or(defaults.enable_faucet),
+        faucet_amount: args.get_one::<Amount>("faucet-amount").copied().or(config.node.faucet_amount).unwrap_or(defaults.faucet_amount),
+        faucet_cooldown: args.get_one::<u64>("faucet-cooldown").copied().or(config.node.faucet_cooldown).unwrap_or(defaults.faucet_cooldown),
+        rate_limit: args.get_one::<u32>("rate-limit").copied().or(config.node.rate_limit).unwrap_or(defaults.rate_limit),
+        rate_limit_burst: args
+            .get_one::<u32>("rate-limit-burst")
+            .copied()
+            .or(config.node.rate_limit_burst)
+            .unwrap_or(defaults.rate_limit_burst),
+        workers: args.get_one::<u16>("workers").map(|workers| *workers as usize).or(config.node.workers),
+        genesis: args.get_one::<String>("genesis").cloned().or_else(|| config.node.genesis.clone()),
+        data_dir: args.get_one::<String>("data-dir").cloned().or_else(|| config.node.data_dir.clone()),
+        chains: match args.get_many::<String>("host-chain") {
+            Some(chains) => chains.cloned().collect(),
+            None => config.node.chains.clone().unwrap_or(defaults.chains),
+        },
+        shard_map: args.get_one::<String>("shard-map").cloned().or_else(|| config.node.shard_map.clone()),
+        shard: args.get_one::<usize>("shard").copied().or(config.node.shard),
+        secret: secret.cloned(),
+        audit_log: args.get_one::<String>("audit-log").cloned().or_else(|| config.node.audit_log.clone()),
+        log_level: args.get_one::<String>("log-level").cloned().or_else(|| config.node.log_level.clone()),
+        log_format: args
+            .get_one::<String>("log-format")
+            .map(|format| format.parse().unwrap())
+            .or(config.node.log_format)
+            .unwrap_or(defaults.log_format),
+    }
 }
 
-fn main() {
+fn main() -> ExitCode {
     // Parse the command line arguments
     let matches = cli().get_matches();
+    output::init(matches.get_one::<String>("output").unwrap().parse().unwrap());
+
+    // Read the config file if one was given
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => match Config::load(path) {
+            Ok(config) => config,
+            Err(e) => {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Config::default(),
+    };
 
-    // Use the hostname on Unix-like systems and the computer name on Windows as the NODE_ID
+    // Requests of the client are from the hostname on Unix-like systems and the computer name on Windows. Nodes are
+    // identified by the public key of their identity key instead, which can't be spoofed.
     let node_id = if cfg!(windows) {
         std::env::var("COMPUTERNAME").unwrap_or("localhost".to_string())
     } else {
         std::env::var("HOSTNAME").unwrap_or("localhost".to_string())
     };
 
-    println!("Node ID: {}", node_id);
+    if matches.subcommand_name() != Some("start-node") {
+        output::progress(format!("Node ID: {}", node_id));
+    }
+
+    let server_addr = server_address(&matches, &config);
+    if let (false, Err(e)) = (matches.subcommand_name() == Some("start-node"), server_addr.to_socket_addrs()) {
+        output::error(format!("❌ Invalid server address {}: {}", server_addr, e));
+        return ExitCode::FAILURE;
+    }
+    let chain = matches.get_one::<String>("chain").cloned();
+    if let Some(Err(e)) = chain.as_deref().map(common::validate_chain_name) {
+        output::error(e);
+        return ExitCode::FAILURE;
+    }
+    let timeout = std::time::Duration::from_millis(*matches.get_one::<u64>("timeout").unwrap());
+    let retries = *matches.get_one::<u32>("retries").unwrap();
+    let client_key = match matches.get_one::<String>("client-key").map(|key| noise::from_hex(key)) {
+        Some(Ok(key)) if key.len() == 32 => Some(key),
+        Some(Ok(_)) => {
+            output::error("❌ Invalid client key, expected 32 hex encoded bytes.");
+            return ExitCode::FAILURE;
+        }
+        Some(Err(e)) => {
+            output::error(e);
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+    let server_key = match matches.get_one::<String>("server-key").map(|key| noise::from_hex(key)) {
+        Some(Ok(key)) => Some(key),
+        Some(Err(e)) => {
+            output::error(e);
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+    // The passphrase is only taken from the environment, so it doesn't end up in the shell history
+    let passphrase = std::env::var("TOYCHAIN_PASSPHRASE").ok().filter(|passphrase| !passphrase.is_empty());
+    let key_file = matches.get_one::<String>("key-file").or(config.node.key_file.as_ref());
+    let secret = match Secret::load(passphrase.as_deref(), key_file.map(String::as_str)) {
+        Ok(secret) => secret,
+        Err(e) => {
+            output::error(e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let encrypt = server_key.is_some() || client_key.is_some() || matches.get_flag("encrypt");
+    let shard_map = match matches.get_one::<String>("shard-map").or(config.client.shard_map.as_ref()) {
+        Some(_) if matches.subcommand_name() == Some("start-node") => None,
+        Some(_) if encrypt => {
+            output::error("❌ Encrypted sessions are with a single node, --shard-map can't be combined with --encrypt.");
+            return ExitCode::FAILURE;
+        }
+        Some(path) => match ShardMap::load(path) {
+            Ok(shard_map) => Some(shard_map),
+            Err(e) => {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let compress = !matches.get_flag("no-compression");
+    let wire_format: Format = matches.get_one::<String>("wire-format").unwrap().parse().unwrap();
+    // Every client has its own socket and session, e.g. for the concurrent clients of `bench`
+    let new_client = || {
+        let client = Client::new(server_addr.clone(), timeout, retries);
+        let client = if output::is_json() { client.quiet() } else { client };
+        let client = if compress { client } else { client.uncompressed() };
+        let client = client.with_format(wire_format).on_chain(chain.clone());
+        let client = match &shard_map {
+            Some(shard_map) => client.sharded(shard_map.clone()),
+            None => client,
+        };
+        match encrypt {
+            true => client.encrypted(server_key.clone(), client_key.clone()),
+            false => client,
+        }
+    };
+    let mut client = new_client();
+
+    let wallet_path = matches.get_one::<String>("wallet").unwrap();
+    match matches.subcommand() {
+        Some(("shell", _)) => {
+            shell::run(&config, &node_id, &mut client, wallet_path, secret.as_ref());
+            return ExitCode::SUCCESS;
+        }
+        Some(("monitor", args)) => {
+            let interval = std::time::Duration::from_millis(*args.get_one::<u64>("interval").unwrap());
+            let ws_port = args.get_one::<u16>("ws-port").copied().or(config.node.ws_port);
+            let host = client.server_addr().rsplit_once(':').map_or("127.0.0.1", |(host, _)| host);
+            let ws_url = ws_port.map(|port| format!("ws://{}:{}", host, port));
+            monitor::run(node_id, client, interval, ws_url);
+            return ExitCode::SUCCESS;
+        }
+        Some(("bench", args)) => {
+            // The clients of the run don't print their progress, e.g. about retries, between the lines of the report
+            let clients = (0..*args.get_one::<u16>("clients").unwrap()).map(|_| new_client().quiet()).collect();
+            let tps = *args.get_one::<u64>("tps").unwrap();
+            let duration = std::time::Duration::from_secs(*args.get_one::<u64>("duration").unwrap());
+            if let Err(e) = bench::run(&node_id, clients, tps, duration) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        _ => {}
+    }
+
+    // Offline commands only log warnings, e.g. about an incomplete ledger file
+    if matches!(matches.subcommand_name(), Some("verify-chain" | "verify-proof" | "migrate" | "export-chain" | "import-chain" | "snapshot")) {
+        logging::init(None, "warn", LogFormat::Text).expect("Failed to set up logging.");
+    }
+
+    run_command(&matches, &config, node_id, &mut client, wallet_path, secret.as_ref())
+}
+
+/// Returns the address of the server node the client sends its requests to: `--server`, the `TOYCHAIN_SERVER`
+/// environment variable, `server` in the `[client]` section of the config file, or the node port on localhost
+fn server_address(matches: &ArgMatches, config: &Config) -> String {
+    matches
+        .get_one::<String>("server")
+        .cloned()
+        .or_else(|| std::env::var("TOYCHAIN_SERVER").ok().filter(|server| !server.is_empty()))
+        .or_else(|| config.client.server.clone())
+        .unwrap_or_else(|| format!("127.0.0.1:{}", config.node.port.unwrap_or(NodeConfig::default().port)))
+}
+
+/// Runs a command other than `shell`, the one of the command line or a line of the shell
+///
+/// # Arguments
+///
+/// * `matches`: the parsed command line
+/// * `config`: the config file
+/// * `node_id`: the node ID of the client
+/// * `client`: the client to send the requests with
+/// * `wallet_path`: the path of the wallet file
+/// * `secret`: the passphrase or key file the data directory and wallet are encrypted with, if any
+///
+/// Returns: the exit code of the command, a failure if it couldn't be sent or was rejected
+fn run_command(matches: &ArgMatches, config: &Config, node_id: String, client: &mut Client, wallet_path: &str, secret: Option<&Secret>) -> ExitCode {
+    if let Some(("wallet", args)) = matches.subcommand() {
+        if let Err(e) = manage_wallet(wallet_path, args, secret) {
+            output::error(e);
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+    let mut wallet = match Wallet::load(wallet_path, secret) {
+        Ok(wallet) => wallet,
+        Err(e) => {
+            output::error(e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Account the request spends from, whose key of the wallet approves the transfer once it was proposed
+    let mut spends_from = None;
+
+    if matches!(matches.subcommand(), Some(("keygen", args)) if args.get_flag("client")) {
+        let keypair = noise::builder().generate_keypair().expect("Failed to generate the client key.");
+        let (private_key, public_key) = (noise::to_hex(&keypair.private), noise::to_hex(&keypair.public));
+        output::fields(
+            format!("Private client key: {}\nPublic client key: {}\nPass the private key with --client-key, nodes in permissioned mode accept it if the public key is allowed with --allow.", private_key, public_key),
+            json!({ "private_key": private_key, "public_key": public_key }),
+        );
+        return ExitCode::SUCCESS;
+    }
+    if matches.subcommand_name() == Some("keygen") {
+        let key = keys::generate();
+        let (private_key, public_key) = (keys::private_key_hex(&key), keys::public_key_hex(&key));
+        output::fields(
+            format!("Private key: {}\nPublic key: {}\nKeep the private key secret, anyone who has it can approve transfers in its name.", private_key, public_key),
+            json!({ "private_key": private_key, "public_key": public_key }),
+        );
+        return ExitCode::SUCCESS;
+    }
 
     // Handle the subcommands
     let request = match matches.subcommand() {
         // Server command - Starts the server
-        Some(("start-node", _)) => {
-            server::init_server(1337, 10);
-            return; // Exit the program after starting the server
+        Some(("start-node", args)) => {
+            let node_config = node_config(args, config, secret);
+            if let Err(e) = logging::init(node_config.log_level.as_deref(), "info", node_config.log_format) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+            // Exit the program once the server stopped, with a failure if it couldn't be started
+            if let Err(e) = server::init_server(node_config) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+            return ExitCode::SUCCESS;
+        }
+        // Offline commands - Work on the data directory without a running server
+        Some(("verify-chain", args)) => {
+            let Some(data_dir) = args.get_one::<String>("data-dir").or(config.node.data_dir.as_ref()) else {
+                output::error("No data directory given, use --data-dir or set data_dir in the config file.");
+                return ExitCode::FAILURE;
+            };
+            let genesis = args.get_one::<String>("genesis").or(config.node.genesis.as_ref());
+
+            match server::verify_stored_chain(data_dir, genesis.map(String::as_str), secret) {
+                Ok(message) => output::message(message),
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
+        }
+        Some(("migrate", args)) => {
+            let Some(data_dir) = args.get_one::<String>("data-dir").or(config.node.data_dir.as_ref()) else {
+                output::error("No data directory given, use --data-dir or set data_dir in the config file.");
+                return ExitCode::FAILURE;
+            };
+
+            match server::migrate_data_dir(data_dir, args.get_flag("dry-run"), secret) {
+                Ok(message) => output::message(message),
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
+        }
+        Some((command @ ("export-chain" | "import-chain"), args)) => {
+            let Some(data_dir) = args.get_one::<String>("data-dir").or(config.node.data_dir.as_ref()) else {
+                output::error("No data directory given, use --data-dir or set data_dir in the config file.");
+                return ExitCode::FAILURE;
+            };
+            let genesis = args.get_one::<String>("genesis").or(config.node.genesis.as_ref()).map(String::as_str);
+            let file = args.get_one::<String>("file").unwrap();
+            let format = chain_file_format(args);
+
+            let result = if command == "export-chain" {
+                server::export_chain(data_dir, file, format, genesis, secret)
+            } else {
+                server::import_chain(file, data_dir, format, genesis, secret)
+            };
+            match result {
+                Ok(message) => output::message(message),
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
+        }
+        Some(("snapshot", args)) => {
+            let (command, args) = args.subcommand().unwrap();
+            let Some(data_dir) = args.get_one::<String>("data-dir").or(config.node.data_dir.as_ref()) else {
+                output::error("No data directory given, use --data-dir or set data_dir in the config file.");
+                return ExitCode::FAILURE;
+            };
+            let genesis = args.get_one::<String>("genesis").or(config.node.genesis.as_ref()).map(String::as_str);
+            let file = args.get_one::<String>("file").unwrap();
+
+            let result = if command == "create" {
+                server::create_snapshot(data_dir, file, args.get_one::<usize>("height").copied(), genesis, secret)
+            } else {
+                server::restore_snapshot(file, data_dir, genesis, secret)
+            };
+            match result {
+                Ok(message) => output::message(message),
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
+        }
+        Some(("verify-proof", args)) => {
+            let genesis = args.get_one::<String>("genesis").or(config.node.genesis.as_ref());
+            match server::verify_proof_file(args.get_one::<String>("file").unwrap(), genesis.map(String::as_str)) {
+                Ok(message) => output::message(message),
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
         }
         // Client commands
+        Some(("prove-balance", args)) => {
+            let target = match args.get_one::<String>("transaction") {
+                Some(transaction_id) => server::ProofTarget::Transaction(transaction_id.clone()),
+                None => server::ProofTarget::Account(args.get_one::<String>("account").unwrap().clone()),
+            };
+            match server::fetch_proof(client, &node_id, target, args.get_one::<String>("file").unwrap()) {
+                Ok(message) => output::message(message),
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
+        }
+        Some(("submit-chain", args)) => {
+            let blocks = match server::read_chain_blocks(args.get_one::<String>("file").unwrap(), chain_file_format(args)) {
+                Ok(blocks) => blocks,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            // Blocks are sent oldest first, so every block builds on a block the node has by the time it arrives
+            for block in blocks {
+                let response = match client.send(&Request::new_submit_block_request(node_id.clone(), block)) {
+                    Ok(response) => response,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                };
+                output::response(&response);
+                if response.result.is_err() {
+                    return ExitCode::FAILURE;
+                }
+            }
+            return ExitCode::SUCCESS;
+        }
         Some(("create-account", args)) => {
             let id = args.get_one::<String>("id-of-account").unwrap();
 
             let balance = args.get_one::<String>("starting-balance").unwrap();
-            let balance = balance.parse::<f64>().expect("Failed to parse balance.");
+            let balance = match balance.parse::<Amount>() {
+                Ok(balance) => balance,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            // Accounts created with a wallet get its next key, which then signs for the account
+            match &mut wallet {
+                Some(wallet) => {
+                    let public_key = keys::public_key_hex(&wallet.assign_key(id));
+                    output::progress(format!("Creating account {} with key {} of the wallet.", id, public_key));
+                    Request::new_create_multisig_account_request(node_id, id.to_string(), balance, vec![public_key], 1)
+                }
+                None => Request::new_create_account_request(node_id, id.to_string(), balance),
+            }
+        }
+        Some(("faucet", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            Request::new_faucet_request(node_id, account.to_string(), args.get_one::<Amount>("amount").copied())
+        }
+        Some(("create-multisig-account", args)) => {
+            let id = args.get_one::<String>("id-of-account").unwrap();
+
+            let balance = args.get_one::<String>("starting-balance").unwrap();
+            let balance = match balance.parse::<Amount>() {
+                Ok(balance) => balance,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
 
-            Request::new_create_account_request(node_id, id.to_string(), balance)
+            let public_keys = args.get_many::<String>("key").unwrap().cloned().collect();
+            let threshold = *args.get_one::<usize>("threshold").unwrap();
+            Request::new_create_multisig_account_request(node_id, id.to_string(), balance, public_keys, threshold)
         }
         Some(("transfer", args)) => {
             let from = args.get_one::<String>("from-account").unwrap();
             let to = args.get_one::<String>("to-account").unwrap();
 
             let amount = args.get_one::<String>("amount").unwrap();
-            let amount = amount.parse::<f64>().expect("Failed to parse amount.");
+            let amount = match amount.parse::<Amount>() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let memo = args.get_one::<String>("memo").cloned();
+            if let Some(Err(e)) = memo.as_deref().map(validate_memo) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            // Ask the server for the next nonce of the account if it wasn't given
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, from) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            let valid_until = match (args.get_one::<u64>("valid-until-height"), args.get_one::<u64>("valid-until-time")) {
+                (Some(height), _) => Some(Timelock::Height(*height)),
+                (None, Some(time)) => Some(Timelock::Time(*time)),
+                (None, None) => None,
+            };
+
+            let request = Request::new_transfer_funds_request(node_id, from.to_string(), to.to_string(), amount, fee, nonce, memo);
+            if args.get_flag("dry-run") {
+                request.simulated()
+            } else {
+                spends_from = Some(from);
+                match (args.get_one::<String>("token"), valid_until) {
+                    (Some(token), _) => request.of_token(token.to_string()),
+                    (None, Some(valid_until)) => request.expiring(valid_until),
+                    (None, None) => request,
+                }
+            }
+        }
+        Some(("close-account", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let beneficiary = args.get_one::<String>("beneficiary").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_close_account_request(node_id, account.to_string(), beneficiary.to_string(), fee, nonce)
+        }
+        Some(("stake", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+
+            let amount = args.get_one::<String>("amount").unwrap();
+            let amount = match amount.parse::<Amount>() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_stake_request(node_id, account.to_string(), amount, fee, nonce)
+        }
+        Some(("unstake", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+
+            let amount = args.get_one::<String>("amount").unwrap();
+            let amount = match amount.parse::<Amount>() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_unstake_request(node_id, account.to_string(), amount, fee, nonce)
+        }
+        Some(("validators", _)) => Request::new_get_validators_request(node_id),
+        Some(("peers", _)) => Request::new_get_peers_request(node_id),
+        Some(("register-alias", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let alias = args.get_one::<String>("alias").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_register_alias_request(node_id, account.to_string(), alias.to_string(), fee, nonce)
+        }
+        Some(("create-token", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let token = args.get_one::<String>("token").unwrap();
+            if let Err(e) = validate_token(token) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            let supply = args.get_one::<String>("supply").unwrap();
+            let supply = match supply.parse::<Amount>() {
+                Ok(supply) => supply,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_create_token_request(node_id, account.to_string(), token.to_string(), supply, fee, nonce)
+        }
+        Some(("mint-nft", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let metadata = args.get_one::<String>("metadata").unwrap();
+            if let Err(e) = validate_nft_metadata(metadata) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_mint_nft_request(node_id, account.to_string(), metadata.to_string(), fee, nonce)
+        }
+        Some(("transfer-nft", args)) => {
+            let from = args.get_one::<String>("from-account").unwrap();
+            let to = args.get_one::<String>("to-account").unwrap();
+            let nft = args.get_one::<String>("nft").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, from) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(from);
+            Request::new_transfer_nft_request(node_id, from.to_string(), to.to_string(), nft.to_string(), fee, nonce)
+        }
+        Some(("escrow", args)) => {
+            let from = args.get_one::<String>("from-account").unwrap();
+            let to = args.get_one::<String>("to-account").unwrap();
+
+            let amount = args.get_one::<String>("amount").unwrap();
+            let amount = match amount.parse::<Amount>() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let memo = args.get_one::<String>("memo").cloned();
+            if let Some(Err(e)) = memo.as_deref().map(validate_memo) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            let timelock = |name: &str| match args.get_one::<u64>(&format!("{}-height", name)) {
+                Some(height) => Timelock::Height(*height),
+                None => Timelock::Time(*args.get_one::<u64>(&format!("{}-time", name)).unwrap()),
+            };
+            let (release, expiry) = (timelock("release"), timelock("expiry"));
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, from) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(from);
+            Request::new_transfer_funds_request(node_id, from.to_string(), to.to_string(), amount, fee, nonce, memo).into_escrow(release, expiry)
+        }
+        Some(("claim-escrow", args)) => Request::new_settle_escrow_request(node_id, args.get_one::<String>("escrow").unwrap().to_string(), false),
+        Some(("refund-escrow", args)) => Request::new_settle_escrow_request(node_id, args.get_one::<String>("escrow").unwrap().to_string(), true),
+        Some(("schedule-payment", args)) => {
+            let from = args.get_one::<String>("from-account").unwrap();
+            let to = args.get_one::<String>("to-account").unwrap();
+
+            let amount = args.get_one::<String>("amount").unwrap();
+            let amount = match amount.parse::<Amount>() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let memo = args.get_one::<String>("memo").cloned();
+            if let Some(Err(e)) = memo.as_deref().map(validate_memo) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, from) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            let start_height = *args.get_one::<u64>("start-height").unwrap();
+            let interval = *args.get_one::<u64>("interval").unwrap();
+            let count = *args.get_one::<u64>("count").unwrap();
+            spends_from = Some(from);
+            Request::new_transfer_funds_request(node_id, from.to_string(), to.to_string(), amount, fee, nonce, memo).into_schedule(start_height, interval, count)
+        }
+        Some(("cancel-schedule", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let schedule = args.get_one::<String>("schedule").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_cancel_schedule_request(node_id, account.to_string(), schedule.to_string(), fee, nonce)
+        }
+        Some(("offer-swap", args)) => {
+            let from = args.get_one::<String>("from-account").unwrap();
+            let to = args.get_one::<String>("to-account").unwrap();
+
+            let amount = args.get_one::<String>("amount").unwrap();
+            let amount = match amount.parse::<Amount>() {
+                Ok(amount) => amount,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
 
-            Request::new_transfer_funds_request(node_id, from.to_string(), to.to_string(), amount)
+            let take = args.get_one::<String>("for").unwrap();
+            let take = match take.parse::<Amount>() {
+                Ok(amount) => Asset { token: args.get_one::<String>("for-token").cloned(), amount },
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let memo = args.get_one::<String>("memo").cloned();
+            if let Some(Err(e)) = memo.as_deref().map(validate_memo) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, from) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            let give_token = args.get_one::<String>("token").cloned();
+            let expiry_height = *args.get_one::<u64>("expiry-height").unwrap();
+            spends_from = Some(from);
+            Request::new_transfer_funds_request(node_id, from.to_string(), to.to_string(), amount, fee, nonce, memo).into_swap(give_token, take, expiry_height)
+        }
+        Some(("accept-swap", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let offer = args.get_one::<String>("offer").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_accept_swap_request(node_id, account.to_string(), offer.to_string(), fee, nonce)
+        }
+        Some(("deploy-contract", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let file = args.get_one::<String>("file").unwrap();
+            let code = match std::fs::read(file) {
+                Ok(code) => code,
+                Err(e) => {
+                    output::error(format!("❌ Failed to read contract file {}: {}", file, e));
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_deploy_contract_request(node_id, account.to_string(), code, fee, nonce)
+        }
+        Some(("call-contract", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let contract = args.get_one::<String>("contract").unwrap();
+            let function = args.get_one::<String>("function").unwrap();
+            let input = args.get_one::<String>("input").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let memo = args.get_one::<String>("memo").cloned();
+            if let Some(Err(e)) = memo.as_deref().map(validate_memo) {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            let gas_limit = *args.get_one::<u64>("gas-limit").unwrap();
+            spends_from = Some(account);
+            Request::new_transfer_funds_request(node_id, account.to_string(), contract.to_string(), Amount::ZERO, fee, nonce, memo)
+                .into_contract_call(function.to_string(), input.as_bytes().to_vec(), gas_limit)
+        }
+        Some(("propose", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let change = match args.get_one::<String>("change").unwrap().parse::<ParameterChange>() {
+                Ok(change) => change,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let activation_height = *args.get_one::<u64>("activation-height").unwrap();
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_propose_request(node_id, account.to_string(), change, activation_height, fee, nonce)
+        }
+        Some(("vote", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let proposal = args.get_one::<String>("proposal").unwrap();
+            let approve = args.get_one::<String>("vote").unwrap() == "yes";
+
+            let fee = args.get_one::<String>("fee").unwrap();
+            let fee = match fee.parse::<Amount>() {
+                Ok(fee) => fee,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            let nonce = match args.get_one::<u64>("nonce") {
+                Some(nonce) => *nonce,
+                None => match fetch_nonce(client, &node_id, account) {
+                    Ok(nonce) => nonce,
+                    Err(e) => {
+                        output::error(e);
+                        return ExitCode::FAILURE;
+                    }
+                },
+            };
+
+            spends_from = Some(account);
+            Request::new_vote_request(node_id, account.to_string(), proposal.to_string(), approve, fee, nonce)
+        }
+        Some(("proposals", _)) => Request::new_get_proposals_request(node_id),
+        Some(("supply", _)) => Request::new_get_supply_request(node_id),
+        Some(("transfer-batch", args)) => {
+            let entries = match batch::load(args.get_one::<String>("file").unwrap()) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            // Transfers without a nonce use the next nonce of their account, which is fetched from the server for the
+            // first transfer of every account and counted up for the following ones
+            let mut next_nonces = std::collections::HashMap::new();
+            let mut transfers = Vec::new();
+            for entry in entries {
+                let nonce = match entry.nonce.or_else(|| next_nonces.get(&entry.from).copied()) {
+                    Some(nonce) => nonce,
+                    None => match fetch_nonce(client, &node_id, &entry.from) {
+                        Ok(nonce) => nonce,
+                        Err(e) => {
+                            output::error(e);
+                            return ExitCode::FAILURE;
+                        }
+                    },
+                };
+                next_nonces.insert(entry.from.clone(), nonce + 1);
+                transfers.push(FundTransferOp {
+                    from_account_id: entry.from,
+                    to_account_id: entry.to,
+                    amount: entry.amount,
+                    fee: entry.fee,
+                    nonce,
+                    memo: entry.memo,
+                });
+            }
+
+            Request::new_batch_transfer_request(node_id, transfers)
+        }
+        Some(("approve", args)) => {
+            let transaction_id = args.get_one::<String>("transaction-id").unwrap();
+            let key = match keys::parse_private_key(args.get_one::<String>("private-key").unwrap()) {
+                Ok(key) => key,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let signature = keys::sign_approval(&key, transaction_id);
+            Request::new_approve_transfer_request(node_id, transaction_id.to_string(), keys::public_key_hex(&key), signature)
+        }
+        Some(("cancel", args)) => {
+            let transaction_id = args.get_one::<String>("transaction-id").unwrap();
+            let key = match (args.get_one::<String>("private-key"), args.get_one::<String>("account")) {
+                (Some(private_key), _) => keys::parse_private_key(private_key),
+                (None, Some(account)) => wallet
+                    .as_ref()
+                    .and_then(|wallet| wallet.key_of(account))
+                    .ok_or_else(|| format!("❌ The wallet {} has no key of account {}.", wallet_path, account)),
+                (None, None) => Err("❌ Give the key to cancel the transaction with, --private-key or the --account of the wallet.".to_string()),
+            };
+            let key = match key {
+                Ok(key) => key,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let signature = keys::sign_cancel(&key, transaction_id);
+            Request::new_cancel_transaction_request(node_id, transaction_id.to_string(), keys::public_key_hex(&key), signature)
+        }
+        Some((command @ ("freeze" | "unfreeze"), args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let key = match keys::parse_private_key(args.get_one::<String>("private-key").unwrap()) {
+                Ok(key) => key,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let signed_at = args.get_one::<u64>("signed-at").copied().unwrap_or_else(|| std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs());
+            let signature = keys::sign_admin(&key, command, account, signed_at);
+            Request::new_freeze_account_request(node_id, account.to_string(), signed_at, signature, command == "freeze")
         }
         Some(("balance", args)) => {
             let account = args.get_one::<String>("account").unwrap();
-            Request::new_get_funds_request(node_id, account.to_string())
+            match args.get_one::<String>("token") {
+                Some(token) => Request::new_get_token_balance_request(node_id, account.to_string(), token.to_string()),
+                None => Request::new_get_funds_request(node_id, account.to_string()),
+            }
+        }
+        Some(("contract", args)) => Request::new_get_contract_request(node_id, args.get_one::<String>("contract").unwrap().to_string()),
+        Some(("nft-owner", args)) => Request::new_owner_of_request(node_id, args.get_one::<String>("nft").unwrap().to_string()),
+        Some(("nfts", args)) => Request::new_tokens_of_request(node_id, args.get_one::<String>("account").unwrap().to_string()),
+        Some(("schedules", args)) => Request::new_get_schedules_request(node_id, args.get_one::<String>("account").unwrap().to_string()),
+        Some(("nonce", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            Request::new_get_nonce_request(node_id, account.to_string())
+        }
+        Some(("history", args)) => {
+            let account = args.get_one::<String>("account").unwrap();
+            let page = *args.get_one::<usize>("page").unwrap();
+            let limit = *args.get_one::<usize>("limit").unwrap();
+            Request::new_history_request(node_id, account.to_string(), page, limit)
+        }
+        Some(("query", args)) => {
+            let query = QueryTransactionsOp {
+                account_id: args.get_one::<String>("account").unwrap().to_string(),
+                direction: args.get_one::<String>("direction").map(|direction| match direction.as_str() {
+                    "sent" => Direction::Sent,
+                    _ => Direction::Received,
+                }),
+                min_amount: args.get_one::<Amount>("min-amount").copied(),
+                max_amount: args.get_one::<Amount>("max-amount").copied(),
+                from_height: args.get_one::<u64>("from-height").copied(),
+                to_height: args.get_one::<u64>("to-height").copied(),
+                since: args.get_one::<u64>("since").copied(),
+                until: args.get_one::<u64>("until").copied(),
+                page: *args.get_one::<usize>("page").unwrap(),
+                limit: *args.get_one::<usize>("limit").unwrap(),
+            };
+            Request::new_query_transactions_request(node_id, query)
+        }
+        Some(("block", args)) => {
+            // Block hashes are 64 hex digits, so a shorter number is a height
+            let block = args.get_one::<String>("block").unwrap();
+            match block.parse::<usize>() {
+                Ok(height) if block.len() < 64 => Request::new_get_block_request(node_id, Some(height), None),
+                _ => Request::new_get_block_request(node_id, None, Some(block.to_ascii_lowercase())),
+            }
+        }
+        Some(("pending", args)) => {
+            let account = args.get_one::<String>("account").cloned();
+            Request::new_get_pending_request(node_id, account)
+        }
+        Some(("tx", args)) => {
+            let transaction_id = args.get_one::<String>("transaction-id").unwrap();
+            Request::new_get_transaction_request(node_id, transaction_id.to_string())
+        }
+        Some(("add-webhook", args)) => {
+            let account_id = args.get_one::<String>("account-id").unwrap();
+            let url = args.get_one::<String>("url").unwrap();
+            Request::new_add_webhook_request(node_id, account_id.to_string(), url.to_string())
+        }
+        Some(("remove-webhook", args)) => {
+            let account_id = args.get_one::<String>("account-id").unwrap();
+            let url = args.get_one::<String>("url").unwrap();
+            Request::new_remove_webhook_request(node_id, account_id.to_string(), url.to_string())
+        }
+        Some(("webhooks", args)) => Request::new_get_webhooks_request(node_id, args.get_one::<String>("account-id").cloned()),
+        Some(("receipt", args)) => {
+            let transaction_id = args.get_one::<String>("transaction-id").unwrap();
+            Request::new_get_receipt_request(node_id, transaction_id.to_string())
         }
+        Some(("node-status", _)) => Request::new_status_request(node_id),
+        Some(("health", _)) => Request::new_health_request(node_id),
+        Some(("audit", args)) => Request::new_get_audit_log_request(
+            node_id,
+            *args.get_one::<usize>("count").unwrap(),
+            args.get_one::<String>("operation").cloned(),
+            args.get_one::<String>("source").cloned(),
+        ),
+        Some(("advance-time", args)) => Request::new_advance_time_request(node_id, *args.get_one::<u64>("seconds").unwrap()),
+        Some(("mint-now", args)) => {
+            let key = match keys::parse_private_key(args.get_one::<String>("private-key").unwrap()) {
+                Ok(key) => key,
+                Err(e) => {
+                    output::error(e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let signed_at = args.get_one::<u64>("signed-at").copied().unwrap_or_else(|| std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs());
+            Request::new_mint_now_request(node_id, signed_at, keys::sign_admin_command(&key, "mint-now", signed_at))
+        }
+        Some(("shutdown", _)) => Request::new_shutdown_request(node_id),
         _ => {
-            eprintln!("Invalid command. Use `b --help` for usage information.");
-            return;
+            output::error("Invalid command. Use `b --help` for usage information.");
+            return ExitCode::FAILURE;
         }
     };
+    let request = match matches.get_one::<String>("idempotency-key") {
+        Some(key) => request.with_idempotency_key(key),
+        None => request,
+    };
+    // JSON output prints the fields of the results of queries
+    let request = match output::is_json() && request.operation.has_structured_result() {
+        true => request.structured(),
+        false => request,
+    };
 
-    // UDP socket to send the request to the server. Port 0 = any available port
-    let socket = std::net::UdpSocket::bind("0.0.0.0:0").expect("Failed to bind to address.");
-    let server_addr = "127.0.0.1:1337";
-
-    // Serialize the request and send it to the server
-    let request_bytes = bincode::serialize(&request).expect("Failed to serialize request.");
-
-    // Send the request bytes to the server
-    socket
-        .send_to(&request_bytes, server_addr)
-        .expect("Failed to send message.");
-    println!("Request sent to server.");
+    let approve_with = spends_from.and_then(|account| wallet.as_ref()?.key_of(account));
 
-    // Receive the response from the server. Responses from the server are just user-facing strings
-    let mut buf = [0; 1024];
-    let (amt, _) = match socket.recv_from(&mut buf) {
-        Ok(res) => res,
+    let creates_account = matches!(request.operation, common::Operation::CreateMultisigAccount(_));
+    let mut response = match client.send(&request) {
+        Ok(response) => response,
         Err(e) => {
-            eprintln!("Failed to receive response. Make sure the server is running (use `start-node`).\nError: {}", e);
-            return;
+            output::error(e);
+            return ExitCode::FAILURE;
         }
     };
+    match request.structured {
+        true => output::structured_response(&response),
+        false => output::response(&response),
+    }
+
+    if let (Some(wallet), true, Ok(_)) = (&wallet, creates_account, &response.result) {
+        if let Err(e) = wallet.save(wallet_path, secret) {
+            output::error(e);
+            return ExitCode::FAILURE;
+        }
+    }
+    // Transfers from accounts of the wallet are approved right away, which commits them
+    if let (Some(key), Some(transaction_id)) = (approve_with, pending_transaction_id(&response)) {
+        let signature = keys::sign_approval(&key, &transaction_id);
+        let approval = Request::new_approve_transfer_request(request.from_node.clone(), transaction_id, keys::public_key_hex(&key), signature);
+        response = match client.send(&approval) {
+            Ok(response) => response,
+            Err(e) => {
+                output::error(e);
+                return ExitCode::FAILURE;
+            }
+        };
+        output::response(&response);
+    }
+
+    // Let scripts tell rejected requests apart
+    match response.result {
+        Ok(_) => ExitCode::SUCCESS,
+        Err(_) => ExitCode::FAILURE,
+    }
+}
+
+/// Gets the ID of the transfer a response says is waiting for approvals
+fn pending_transaction_id(response: &common::Response) -> Option<String> {
+    let message = response.result.as_ref().ok()?.strip_prefix("⏳ Transaction ")?;
+    message.split_whitespace().next().map(str::to_string)
+}
 
-    // Convert the response bytes to a string
-    let response = std::str::from_utf8(&buf[..amt]).expect("Failed to convert message to string.");
+/// Handles the `wallet` subcommands, which only work on the wallet file
+fn manage_wallet(path: &str, args: &ArgMatches, secret: Option<&Secret>) -> Result<(), String> {
+    match args.subcommand() {
+        Some(("new", _)) | Some(("restore", _)) if std::path::Path::new(path).exists() => {
+            Err(format!("❌ Wallet file {} already exists, use --wallet to create the wallet in another file.", path))
+        }
+        Some(("new", _)) => {
+            let wallet = Wallet::generate();
+            wallet.save(path, secret)?;
+            output::fields(
+                format!("✅ Wallet created in {}.\nMnemonic: {}\nWrite the mnemonic down and keep it secret, it restores every key of the wallet.", path, wallet.mnemonic()),
+                json!({ "path": path, "mnemonic": wallet.mnemonic() }),
+            );
+            Ok(())
+        }
+        Some(("restore", args)) => {
+            let accounts: Vec<String> = args.get_many::<String>("account").unwrap_or_default().cloned().collect();
+            let wallet = Wallet::restore(args.get_one::<String>("mnemonic").unwrap(), &accounts)?;
+            wallet.save(path, secret)?;
+            output::fields(format!("✅ Wallet restored in {} with {} accounts.", path, accounts.len()), json!({ "path": path, "accounts": accounts }));
+            Ok(())
+        }
+        Some(("list", _)) => {
+            let wallet = Wallet::load(path, secret)?.ok_or_else(|| format!("❌ Wallet file {} doesn't exist, create it with `wallet new`.", path))?;
+            let lines: Vec<String> = wallet
+                .keys()
+                .iter()
+                .map(|key| format!("Key {}: {} ({})", key.index, key.public_key, key.account_id.as_deref().unwrap_or("unused")))
+                .collect();
+            let keys: Vec<Value> = wallet
+                .keys()
+                .iter()
+                .map(|key| json!({ "index": key.index, "public_key": key.public_key, "account_id": key.account_id }))
+                .collect();
+            output::fields(lines.join("\n"), json!({ "keys": keys }));
+            Ok(())
+        }
+        _ => unreachable!("Unknown wallet command."),
+    }
+}
 
-    println!("Response from server: {}", response);
+/// Asks the server for the nonce the next transfer from an account has to be made with
+fn fetch_nonce(client: &mut Client, node_id: &str, account_id: &str) -> Result<u64, String> {
+    let nonce_request = Request::new_get_nonce_request(node_id.to_string(), account_id.to_string());
+    let nonce = client.send(&nonce_request)?.result?;
+    Ok(nonce.parse::<u64>().expect("Failed to parse nonce."))
 }
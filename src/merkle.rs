@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
+
+/// Returns the hex encoded SHA-256 hash of the given bytes
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(bytes);
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect::<String>()
+}
+
+/// Hashes two child nodes of the tree into their parent
+fn hash_pair(left: &str, right: &str) -> String {
+    sha256_hex(format!("{}{}", left, right).as_bytes())
+}
+
+/// One step of a Merkle proof: the sibling hash to combine with at that level of the tree
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProofStep {
+    pub sibling: String,
+    // Whether the sibling is the left node of the pair
+    pub sibling_is_left: bool,
+}
+
+/// Calculates the next level of the tree. If a level has an odd number of nodes, the last node is paired with itself,
+/// so leaves ending in a repeated leaf have the same root as without it, e.g. `[a, b, c]` and `[a, b, c, c]`. Blocks
+/// must not repeat a transaction for their root to only match their own transactions, which verifying a chain checks.
+fn next_level(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+/// Calculates the Merkle root of the given leaves (transaction IDs). The root of no leaves is the hash of nothing.
+pub fn merkle_root(leaves: &[String]) -> String {
+    if leaves.is_empty() {
+        return sha256_hex(&[]);
+    }
+
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+/// Builds the proof that the leaf at the given index is part of the tree, from the leaf level up to the root
+///
+/// Returns: None if the index is out of bounds
+pub fn merkle_proof(leaves: &[String], index: usize) -> Option<Vec<ProofStep>> {
+    if index >= leaves.len() {
+        return None;
+    }
+
+    let mut proof = Vec::new();
+    let mut level = leaves.to_vec();
+    let mut index = index;
+    while level.len() > 1 {
+        // The sibling of an even index is the next node and the sibling of an odd index the previous one
+        let sibling_index = index ^ 1;
+        proof.push(ProofStep {
+            // The last node of an odd level is its own sibling
+            sibling: level.get(sibling_index).unwrap_or(&level[index]).clone(),
+            sibling_is_left: index & 1 == 1,
+        });
+
+        level = next_level(&level);
+        index /= 2;
+    }
+    Some(proof)
+}
+
+/// Verifies that a leaf is part of the tree with the given root, by hashing it up the tree with the proof's siblings
+pub fn verify_proof(leaf: &str, proof: &[ProofStep], root: &str) -> bool {
+    let hash = proof.iter().fold(leaf.to_string(), |hash, step| {
+        if step.sibling_is_left {
+            hash_pair(&step.sibling, &hash)
+        } else {
+            hash_pair(&hash, &step.sibling)
+        }
+    });
+    hash == root
+}
@@ -0,0 +1,328 @@
+//! The terminal dashboard of `toychain monitor`. The dashboard polls the server node for its status, peers and new
+//! blocks every refresh interval and shows its height, the recent blocks, the mempool, the peers and the number of
+//! requests it processes per second. Given the WebSocket port of the node, the dashboard also subscribes to its new
+//! blocks and transactions and refreshes as soon as one arrives.
+
+use std::collections::VecDeque;
+use std::io::{IsTerminal, Read, Write};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde_json::{json, Value};
+use tungstenite::Message;
+use crate::client::Client;
+use crate::common::Request;
+use crate::shell::RawMode;
+
+/// Number of recent blocks shown
+const RECENT_BLOCKS: usize = 10;
+
+/// Number of peers shown at most, the rest are counted
+const SHOWN_PEERS: usize = 10;
+
+/// Time between two refreshes at least, so a burst of events doesn't flood the node with requests
+const MIN_REFRESH: Duration = Duration::from_millis(250);
+
+/// What the dashboard is woken up for, besides the refresh interval
+enum Update {
+    // A block was minted, pushed by the WebSocket subscription
+    Block { height: u64, hash: String, transactions: u64 },
+    // A transaction was accepted into the mempool, pushed by the WebSocket subscription
+    Transaction,
+    // The WebSocket subscription failed or was closed
+    Disconnected(String),
+    // The operator pressed q or Ctrl-C
+    Quit,
+}
+
+/// A block shown in the list of recent blocks
+struct BlockSummary {
+    height: u64,
+    hash: String,
+    // Unix time in seconds the block was minted at, None if it is only known from its event
+    timestamp: Option<u64>,
+    transactions: String,
+}
+
+/// What the dashboard shows
+#[derive(Default)]
+struct Dashboard {
+    // First line of the status response, naming the node, its version and chain
+    node: String,
+    // The `- Key: value` lines of the status response
+    status: Vec<(String, String)>,
+    peers: Vec<String>,
+    // Most recent first
+    blocks: VecDeque<BlockSummary>,
+    // Requests the node processed per second since the last refresh, without the requests of the dashboard
+    throughput: Option<f64>,
+    // Transactions accepted since the dashboard started, if it is subscribed to them
+    live_transactions: Option<u64>,
+    // How the dashboard gets its updates
+    source: String,
+    error: Option<String>,
+}
+
+impl Dashboard {
+    fn status(&self, key: &str) -> Option<&str> {
+        self.status.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Renders the dashboard, styled and clearing the rest of every line if it is shown on a terminal
+    fn render(&self, terminal: bool) -> String {
+        let mut output = String::new();
+        let mut line = |text: String| {
+            output.push_str(&text);
+            output.push_str(if terminal { "\x1b[K\n" } else { "\n" });
+        };
+        let bold = |text: &str| if terminal { format!("\x1b[1m{}\x1b[0m", text) } else { text.to_string() };
+        line(format!("{}  {}", bold("ToyChain monitor"), self.source));
+        line(self.node.trim_end_matches(':').to_string());
+        line(String::new());
+        let field = |key| self.status(key).unwrap_or("?").to_string();
+        line(format!("Height:       {}", field("Height")));
+        line(format!("Tip:          {}", field("Tip")));
+        line(format!("Mempool:      {} pending transactions", field("Pending transactions")));
+        if let Some(transactions) = self.live_transactions {
+            line(format!("Accepted:     {} transactions since the monitor started", transactions));
+        }
+        let throughput = self.throughput.map_or("?".to_string(), |throughput| format!("{:.1}", throughput));
+        line(format!("Throughput:   {} requests/s", throughput));
+        line(format!("Consensus:    {}", field("Consensus")));
+        line(format!("Sync:         {}", field("Sync")));
+        line(format!("Uptime:       {}", field("Uptime")));
+        line(String::new());
+
+        line(bold("Recent blocks"));
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        for block in &self.blocks {
+            let age = block.timestamp.map_or("just now".to_string(), |timestamp| format_age(now.saturating_sub(timestamp)));
+            line(format!("  {:>8}  {}  {:>4} transactions  {}", block.height, block.hash, block.transactions, age));
+        }
+        line(String::new());
+
+        line(format!("{}  {}", bold("Peers"), field("Peers")));
+        for peer in self.peers.iter().take(SHOWN_PEERS) {
+            line(format!("  {}", peer.trim_start_matches("- ")));
+        }
+        if self.peers.len() > SHOWN_PEERS {
+            line(format!("  … and {} more", self.peers.len() - SHOWN_PEERS));
+        }
+        line(String::new());
+        if let Some(error) = &self.error {
+            line(error.clone());
+        }
+        line("Press q to quit.".to_string());
+        output
+    }
+}
+
+/// Formats the age of a block in its largest unit, e.g. `5m ago`
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s ago", seconds),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+/// Parses the block response of a block request
+fn parse_block(response: &str) -> Option<BlockSummary> {
+    let mut lines = response.lines();
+    let (hash, height) = lines.next()?.strip_prefix("Block ")?.strip_suffix(':')?.split_once(" at height ")?;
+    let mut block = BlockSummary { height: height.parse().ok()?, hash: hash.to_string(), timestamp: None, transactions: "?".to_string() };
+    for line in lines {
+        if let Some(timestamp) = line.strip_prefix("- Minted at: ") {
+            block.timestamp = timestamp.split_whitespace().next().and_then(|timestamp| timestamp.parse().ok());
+        } else if let Some(transactions) = line.strip_prefix("- Transactions: ") {
+            // The count of pruned blocks is unknown
+            block.transactions = transactions.parse::<u64>().map_or("?".to_string(), |count| count.to_string());
+        }
+    }
+    Some(block)
+}
+
+/// Polls the node and updates the dashboard with its responses
+struct Poller {
+    node_id: String,
+    client: Client,
+    // Number of requests the dashboard sent, which aren't counted in the throughput
+    sent: u64,
+    // Number of requests the node processed, the time and the number of requests the dashboard sent before the last
+    // refresh
+    last: Option<(u64, Instant, u64)>,
+}
+
+impl Poller {
+    fn send(&mut self, request: Request) -> Result<String, String> {
+        self.sent += 1;
+        self.client.send(&request)?.result
+    }
+
+    fn refresh(&mut self, dashboard: &mut Dashboard) -> Result<(), String> {
+        let status = self.send(Request::new_status_request(self.node_id.clone()))?;
+        let mut lines = status.lines();
+        dashboard.node = lines.next().unwrap_or_default().to_string();
+        dashboard.status = lines
+            .filter_map(|line| line.strip_prefix("- ")?.split_once(": "))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+
+        // The node counts a request once it responded to it, so the count covers the requests sent before this one
+        if let Some(requests) = dashboard.status("Requests").and_then(|requests| requests.split_whitespace().next()?.parse::<u64>().ok()) {
+            if let Some((last_requests, last_time, last_sent)) = self.last {
+                let elapsed = last_time.elapsed().as_secs_f64();
+                let others = requests.saturating_sub(last_requests).saturating_sub(self.sent - 1 - last_sent);
+                dashboard.throughput = Some(others as f64 / elapsed.max(0.001));
+            }
+            self.last = Some((requests, Instant::now(), self.sent - 1));
+        }
+
+        let peers = self.send(Request::new_get_peers_request(self.node_id.clone()))?;
+        dashboard.peers = peers.lines().skip(1).map(str::to_string).collect();
+
+        // Blocks minted since the last refresh, only the ones that are shown
+        let height: u64 = dashboard.status("Height").and_then(|height| height.parse().ok()).unwrap_or_default();
+        // Blocks only known from their event are fetched again, with their timestamp
+        let newest = dashboard.blocks.iter().filter(|block| block.timestamp.is_some()).map(|block| block.height).max();
+        let from = match newest {
+            Some(newest) if newest <= height => newest + 1,
+            // The node switched to a shorter fork, or nothing was fetched yet
+            _ => {
+                dashboard.blocks.clear();
+                0
+            }
+        };
+        dashboard.blocks.retain(|block| block.timestamp.is_some());
+        for block_height in from.max((height + 1).saturating_sub(RECENT_BLOCKS as u64))..=height {
+            let response = self.send(Request::new_get_block_request(self.node_id.clone(), Some(block_height as usize), None))?;
+            if let Some(block) = parse_block(&response) {
+                dashboard.blocks.push_front(block);
+            }
+        }
+        dashboard.blocks.truncate(RECENT_BLOCKS);
+        Ok(())
+    }
+}
+
+/// Subscribes to the new blocks and transactions of the node over WebSocket and sends them to the dashboard, until
+/// the connection fails
+fn subscribe(url: &str, updates: &Sender<Update>) -> Result<(), String> {
+    let (mut socket, _) = tungstenite::connect(url).map_err(|e| format!("{}", e))?;
+    for topic in ["new_block", "new_transaction"] {
+        let message = json!({ "action": "subscribe", "topic": topic });
+        socket.send(Message::Text(message.to_string())).map_err(|e| format!("{}", e))?;
+    }
+    loop {
+        let text = match socket.read().map_err(|e| format!("{}", e))? {
+            Message::Text(text) => text,
+            Message::Close(_) => return Err("the node closed the connection".to_string()),
+            _ => continue,
+        };
+        let event: Value = serde_json::from_str(&text).map_err(|e| format!("invalid event: {}", e))?;
+        if let Some(error) = event.get("error").and_then(Value::as_str) {
+            return Err(error.to_string());
+        }
+        let update = match event["topic"].as_str() {
+            Some("new_block") => Update::Block {
+                height: event["height"].as_u64().unwrap_or_default(),
+                hash: event["hash"].as_str().unwrap_or_default().to_string(),
+                transactions: event["transactions"].as_u64().unwrap_or_default(),
+            },
+            Some("new_transaction") => Update::Transaction,
+            _ => continue,
+        };
+        if updates.send(update).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs the dashboard until the operator quits it
+///
+/// # Arguments
+///
+/// * `node_id`: the node ID of the client
+/// * `client`: the client to poll the node with
+/// * `interval`: how often the dashboard polls the node
+/// * `ws_url`: the URL of the WebSocket endpoint of the node, to refresh as blocks and transactions arrive
+pub fn run(node_id: String, client: Client, interval: Duration, ws_url: Option<String>) {
+    let terminal = std::io::stdout().is_terminal();
+    let (updates, receiver) = channel();
+    let mut dashboard = Dashboard {
+        source: match &ws_url {
+            Some(url) => format!("live from {}, polling every {:.1}s", url, interval.as_secs_f64()),
+            None => format!("polling {} every {:.1}s", client.server_addr(), interval.as_secs_f64()),
+        },
+        ..Dashboard::default()
+    };
+
+    // Keys are read on their own thread, so the dashboard refreshes while waiting for them
+    let raw_mode = RawMode::enable();
+    if raw_mode.is_some() {
+        let updates = updates.clone();
+        std::thread::spawn(move || {
+            let mut stdin = std::io::stdin().lock();
+            let mut key = [0u8; 1];
+            // q, Ctrl-C and Ctrl-D quit
+            while stdin.read_exact(&mut key).is_ok() && !matches!(key[0], b'q' | b'Q' | 3 | 4) {}
+            let _ = updates.send(Update::Quit);
+        });
+    }
+    if let Some(url) = ws_url {
+        dashboard.live_transactions = Some(0);
+        let updates = updates.clone();
+        std::thread::spawn(move || {
+            let error = subscribe(&url, &updates).err().unwrap_or_else(|| "closed".to_string());
+            let _ = updates.send(Update::Disconnected(error));
+        });
+    }
+    drop(updates);
+
+    if terminal {
+        // Alternate screen, hidden cursor
+        print!("\x1b[?1049h\x1b[?25l");
+    }
+    let mut poller = Poller { node_id, client: client.quiet(), sent: 0, last: None };
+    let mut next_poll = Instant::now();
+    let mut refreshed = Instant::now();
+    loop {
+        if Instant::now() >= next_poll {
+            dashboard.error = poller.refresh(&mut dashboard).err();
+            refreshed = Instant::now();
+            next_poll = refreshed + interval;
+            let frame = dashboard.render(terminal);
+            if terminal {
+                print!("\x1b[H{}\x1b[J", frame);
+            } else {
+                println!("{}", frame);
+            }
+            let _ = std::io::stdout().flush();
+        }
+        match receiver.recv_timeout(next_poll.saturating_duration_since(Instant::now())) {
+            Ok(Update::Block { height, hash, transactions }) => {
+                // Shown right away and completed with the block's timestamp by the next poll
+                dashboard.blocks.push_front(BlockSummary { height, hash, timestamp: None, transactions: transactions.to_string() });
+                dashboard.blocks.truncate(RECENT_BLOCKS);
+                next_poll = refreshed + MIN_REFRESH;
+            }
+            Ok(Update::Transaction) => {
+                *dashboard.live_transactions.get_or_insert(0) += 1;
+                next_poll = refreshed + MIN_REFRESH;
+            }
+            Ok(Update::Disconnected(error)) => {
+                dashboard.source = format!("WebSocket subscription failed ({}), polling every {:.1}s", error, interval.as_secs_f64());
+                dashboard.live_transactions = None;
+                next_poll = refreshed + MIN_REFRESH;
+            }
+            Ok(Update::Quit) => break,
+            Err(RecvTimeoutError::Timeout) => {}
+            // No keys are read and there is no subscription
+            Err(RecvTimeoutError::Disconnected) => std::thread::sleep(next_poll.saturating_duration_since(Instant::now())),
+        }
+    }
+    if terminal {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = std::io::stdout().flush();
+    }
+}
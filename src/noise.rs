@@ -0,0 +1,98 @@
+//! Packet format of the optional encrypted transport between client and server. Encrypted sessions use the Noise XX
+//! handshake, in which both sides exchange static keys, so clients can verify the server's key:
+//!
+//! 1. client -> server `Handshake1`: the client's ephemeral key
+//! 2. server -> client `Handshake2`: the server's ephemeral and static key
+//! 3. client -> server `Handshake3`: the client's static key, with the first request as payload
+//!
+//! Every request after that, and every response, is a `Transport` packet encrypted with the session keys. Packets
+//! start with a magic prefix that a plaintext bincode request can never start with, so the server tells encrypted and
+//! plaintext requests apart without any negotiation.
+
+/// Noise protocol used for encrypted sessions
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Prefix of every encrypted transport packet, distinct from the prefix of versioned plaintext requests and from the
+/// start of version 1 requests (see [crate::common::PROTOCOL_VERSION])
+const MAGIC: &[u8; 3] = b"TNZ";
+
+/// Maximum number of bytes the encryption adds to a message: the packet header and the largest handshake overhead
+pub const MAX_OVERHEAD: usize = 128;
+
+/// Length of the nonce at the start of the payload of transport packets
+const NONCE_LENGTH: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketKind {
+    Handshake1 = 1,
+    Handshake2 = 2,
+    Handshake3 = 3,
+    // A request or response encrypted with the session keys, prefixed with its nonce
+    Transport = 4,
+    // A plaintext error from the server, e.g. when the session expired
+    Error = 5,
+}
+
+/// Builds a packet of the given kind
+pub fn encode(kind: PacketKind, payload: &[u8]) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(MAGIC.len() + 1 + payload.len());
+    packet.extend_from_slice(MAGIC);
+    packet.push(kind as u8);
+    packet.extend_from_slice(payload);
+    packet
+}
+
+/// Builds a transport packet from the nonce and ciphertext of a message
+pub fn encode_transport(nonce: u64, ciphertext: &[u8]) -> Vec<u8> {
+    let mut payload = nonce.to_le_bytes().to_vec();
+    payload.extend_from_slice(ciphertext);
+    encode(PacketKind::Transport, &payload)
+}
+
+/// Checks whether a datagram is an encrypted transport packet rather than a plaintext request
+pub fn is_noise_packet(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Splits a packet into its kind and payload
+///
+/// Returns: None if the datagram isn't a valid packet
+pub fn decode(bytes: &[u8]) -> Option<(PacketKind, &[u8])> {
+    let rest = bytes.strip_prefix(MAGIC)?;
+    let (kind, payload) = rest.split_first()?;
+    let kind = match kind {
+        1 => PacketKind::Handshake1,
+        2 => PacketKind::Handshake2,
+        3 => PacketKind::Handshake3,
+        4 => PacketKind::Transport,
+        5 => PacketKind::Error,
+        _ => return None,
+    };
+    Some((kind, payload))
+}
+
+/// Splits the payload of a transport packet into its nonce and ciphertext
+pub fn decode_transport(payload: &[u8]) -> Option<(u64, &[u8])> {
+    if payload.len() < NONCE_LENGTH {
+        return None;
+    }
+    let (nonce, ciphertext) = payload.split_at(NONCE_LENGTH);
+    Some((u64::from_le_bytes(nonce.try_into().unwrap()), ciphertext))
+}
+
+pub fn builder() -> snow::Builder<'static> {
+    snow::Builder::new(NOISE_PARAMS.parse().unwrap())
+}
+
+/// Formats a key as hex, e.g. to print the server's public key
+pub fn to_hex(key: &[u8]) -> String {
+    key.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Parses a hex encoded key
+pub fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(format!("Invalid key {}, expected a hex encoded key.", text));
+    }
+    Ok((0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect())
+}
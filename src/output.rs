@@ -0,0 +1,126 @@
+//! Output of the client. Text output is meant to be read, with the messages of the node as they are. JSON output prints
+//! one JSON object per result instead, for scripts and tools like `jq`: `{"ok": true, ...}` with the fields of the
+//! result, e.g. the `message` of the node or the fields the node answers a query with, or `{"ok": false, "error":
+//! {"kind": ..., "message": ...}}`. Errors of kind `rejected` were rejected by the node, with the `code` of the error
+//! if it has one, errors of kind `failed` happened on the client, e.g. a file couldn't be read or the node didn't
+//! respond. Progress messages, e.g. about retries, are left out of JSON output.
+
+use std::sync::OnceLock;
+use serde_json::{json, Map, Value};
+use crate::common::Response;
+
+/// Format the client prints its output in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    // Human-readable lines
+    Text,
+    // One JSON object per result
+    Json,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<OutputFormat, String> {
+        match text {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(format!("Invalid output format {}, expected text or json.", text)),
+        }
+    }
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Sets the output format, before anything is printed
+pub fn init(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// Removes the status emoji the messages of the node start with, e.g. `✅ `, as JSON output has the `ok` field
+fn strip_status(message: &str) -> &str {
+    ["✅ ", "❌ ", "⚠️ ", "⏳ ", "🛑 "].iter().find_map(|status| message.strip_prefix(status)).unwrap_or(message)
+}
+
+fn print_json(value: Value) {
+    println!("{}", value);
+}
+
+/// Prints a progress message, which is left out of JSON output
+pub fn progress(message: impl std::fmt::Display) {
+    if !is_json() {
+        println!("{}", message);
+    }
+}
+
+/// Prints the message a command succeeded with
+pub fn message(message: impl std::fmt::Display) {
+    let message = message.to_string();
+    match is_json() {
+        true => print_json(json!({ "ok": true, "message": strip_status(&message) })),
+        false => println!("{}", message),
+    }
+}
+
+/// Prints the result of a command with structured fields, as `text` in text output and with the fields in JSON output
+pub fn fields(text: impl std::fmt::Display, fields: Value) {
+    match (is_json(), fields) {
+        (true, Value::Object(fields)) => {
+            let mut object = Map::new();
+            object.insert("ok".to_string(), Value::Bool(true));
+            object.extend(fields);
+            print_json(Value::Object(object));
+        }
+        (true, fields) => print_json(json!({ "ok": true, "result": fields })),
+        (false, _) => println!("{}", text),
+    }
+}
+
+/// Prints the response of the node to a request
+pub fn response(response: &Response) {
+    if !is_json() {
+        println!("Response from server: {}", response.message());
+        return;
+    }
+    match &response.result {
+        Ok(message) => print_json(json!({ "ok": true, "request_id": response.request_id, "message": strip_status(message) })),
+        Err(message) => {
+            let mut error = json!({ "kind": "rejected", "message": strip_status(message) });
+            if let Some(code) = &response.error_code {
+                error["code"] = json!(code);
+            }
+            print_json(json!({ "ok": false, "request_id": response.request_id, "error": error }));
+        }
+    }
+}
+
+/// Prints the response of the node to a request for a structured result, with the fields of the result in JSON output
+pub fn structured_response(response: &Response) {
+    match &response.result {
+        Ok(message) => match serde_json::from_str(message) {
+            Ok(Value::Object(fields)) => {
+                let mut object = Map::new();
+                object.insert("ok".to_string(), Value::Bool(true));
+                object.insert("request_id".to_string(), json!(response.request_id));
+                object.extend(fields);
+                print_json(Value::Object(object));
+            }
+            _ => self::response(response),
+        },
+        Err(_) => self::response(response),
+    }
+}
+
+/// Prints an error that happened on the client, to stderr in text output and to stdout in JSON output, so scripts
+/// read every result from the same stream
+pub fn error(message: impl std::fmt::Display) {
+    let message = message.to_string();
+    match is_json() {
+        true => print_json(json!({ "ok": false, "error": { "kind": "failed", "message": strip_status(&message) } })),
+        false => eprintln!("{}", message),
+    }
+}
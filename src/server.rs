@@ -1,9 +1,136 @@
-use std::net::UdpSocket;
-use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use ed25519_dalek::SigningKey;
 use serde::{Deserialize, Serialize};
-use sha2::Digest;
+use serde_json::{json, Value};
 use crate::common;
-use crate::common::Operation;
+use crate::common::{validate_alias, validate_memo, validate_nft_metadata, validate_token, AccountCreationOp, AcceptSwapOp, AdminAccountOp, AdminCommandOp, Amount, ApproveTransferOp, Asset, BatchTransferOp, CallContractOp, CancelTransactionOp, CloseAccountOp, CancelScheduleOp, CreateTokenOp, DeployContractOp, ErrorCode, EscrowTransferOp, ExpiringTransferOp, FaucetOp, FundTransferOp, GetBlockOp, GetContractOp, GetNftOp, GetNftsOfOp, GetPendingOp, GetTokenBalanceOp, MintNftOp, OfferSwapOp, MultisigAccountCreationOp, Operation, ParameterChange, ProposeOp, RegisterAliasOp, RequestError, Response, SchedulePaymentOp, SettleEscrowOp, StakeOp, Timelock, TokenTransferOp, TransferNftOp, VoteOp, PROTOCOL_VERSION, RESPONSE_OVERHEAD};
+use crate::fragment::{self, Reassembler, MAX_DATAGRAM_SIZE};
+use crate::config::{Consensus, NodeConfig};
+use crate::keys;
+use crate::merkle::{merkle_proof, merkle_root, sha256_hex, ProofStep};
+use crate::noise;
+use crate::shard::ShardMap;
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tokio::task::JoinSet;
+use tracing::{debug, error, info, info_span, warn};
+
+mod admin;
+mod allowlist;
+mod archive;
+mod audit;
+mod chains;
+mod checkpoint;
+mod clock;
+mod compaction;
+mod consistency;
+mod contract;
+mod cross_shard;
+mod embedded;
+mod encryption;
+mod escrow;
+mod events;
+mod explorer;
+mod faucet;
+mod fork;
+mod genesis;
+mod governance;
+mod gossip;
+mod graphql;
+mod grpc;
+mod health;
+mod http;
+mod identity;
+mod index;
+mod light;
+mod mdns;
+mod mempool;
+mod metrics;
+mod migration;
+mod monetary;
+mod multisig;
+mod nft;
+mod orphan;
+mod peers;
+mod proof;
+mod query;
+mod rate_limit;
+mod receipt;
+mod response_cache;
+mod rest;
+mod schedule;
+mod rpc;
+mod sharding;
+mod slashing;
+mod snapshot;
+mod stake;
+mod swap;
+mod storage;
+mod sync;
+mod token;
+mod validation;
+mod verify;
+mod wal;
+mod webhook;
+mod websocket;
+mod workers;
+
+pub use archive::{export_chain, import_chain, read_chain_blocks, ExportFormat};
+pub use embedded::{Node, NodeHandle};
+pub use proof::{fetch_proof, verify_proof_file, ProofTarget};
+pub use snapshot::{create_snapshot, restore_snapshot};
+use admin::FrozenAccounts;
+use allowlist::Allowlist;
+use audit::{AuditLog, Audited};
+use chains::Chains;
+use checkpoint::Checkpoints;
+use clock::{Clock, Simulation, SystemClock};
+use contract::Contracts;
+use cross_shard::{CrossShardTransfers, Lock, CROSS_SHARD_ACCOUNT, CROSS_SHARD_TIMEOUT};
+use encryption::Encryption;
+use escrow::{Escrow, Escrows, ESCROW_ACCOUNT};
+use events::{Event, EventHub};
+use faucet::Faucet;
+use fork::Forks;
+use genesis::Genesis;
+use gossip::Gossip;
+use governance::{Governance, Proposal};
+use health::MintRounds;
+use identity::NodeSignature;
+use index::TransactionIndex;
+use mempool::Mempool;
+use metrics::Metrics;
+use multisig::{MultisigPolicy, PendingApprovals};
+use nft::Nfts;
+use orphan::Orphans;
+use peers::PeerManager;
+use query::TransactionFilter;
+use rate_limit::RateLimiter;
+use receipt::Rejections;
+use response_cache::{Lookup, ResponseCache};
+use schedule::{Schedule, Schedules};
+use sharding::Shard;
+use slashing::{BlockSignature, Evidence, SLASHED_ACCOUNT};
+use snapshot::Snapshots;
+use stake::STAKE_ACCOUNT;
+use swap::{SwapOffer, Swaps};
+use storage::Storage;
+use crate::vault::{Secret, Vault};
+use wal::Wal;
+use sync::SyncStatus;
+use token::Tokens;
+use webhook::Webhooks;
+use workers::{Sequencer, Ticket, Workers};
+
+/// Number of minted blocks after which the balance index is checked against the ledger
+const BALANCE_CHECK_INTERVAL: usize = 100;
+
+/// Interval at which the ledger file is checked for blocks to compact into the archive, see [compaction]
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(10);
 
 /// A blockchain transaction that will be part of a block
 #[derive(Serialize, Deserialize, Clone)]
@@ -16,211 +143,4835 @@ struct Transaction {
     // The account that the funds are being transferred to
     to_account_id: String,
     // The amount of funds being transferred
-    amount: f64,
+    amount: Amount,
+    // Fee paid by the from account on top of the amount, transactions with higher fees are minted first
+    fee: Amount,
+    // Nonce of the from account the transfer was made with. None for transactions without a from account
+    nonce: Option<u64>,
     // Timestamp of the transaction
     datetime: std::time::SystemTime,
+    // Free text the sender referenced the transfer by. None for transactions without a memo
+    #[serde(default)]
+    memo: Option<String>,
+    // What the transaction does
+    kind: TransactionKind,
+}
+
+/// What a transaction does besides moving its amount to the to account
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+enum TransactionKind {
+    // A transfer between accounts, or the creation of an account if there is no from account
+    Transfer,
+    // The block reward, which pays the fees of the block's transfers to the account of the node that minted the block
+    Reward,
+    // The creation of a multisig account, whose transfers have to be approved by enough of its keys
+    CreateMultisig(MultisigPolicy),
+    // A transfer of the whole balance of the from account, which closes the account
+    Close,
+    // Freezing of the to account by the admin, with the unix time the admin signed it at
+    Freeze { signed_at: u64 },
+    // Unfreezing of the to account by the admin, with the unix time the admin signed it at
+    Unfreeze { signed_at: u64 },
+    // A transfer to the staking account, which adds the amount to the stake of the from account as a validator
+    Stake,
+    // Unstaking of the amount of the stake of the from account, which pays the fee and is also the to account, moving
+    // the amount from the staking account back to it
+    Unstake,
+    // The share of the to account in the staking reward minted at the start of the epoch, without a from account
+    StakingReward { epoch: u64 },
+    // Registration of the alias for the from account, which pays the fee and is also the to account
+    RegisterAlias(String),
+    // Creation of a token by the from account, which pays the fee, is also the to account and receives the supply
+    CreateToken { token: String, supply: Amount },
+    // A transfer of the amount in the token instead of the native coin, the fee is still paid in the native coin
+    TokenTransfer(String),
+    // Minting of an NFT by the from account, which pays the fee, is also the to account and owns the NFT
+    MintNft { nft_id: String, metadata: String },
+    // A transfer of the NFT with the ID from its owner to the to account, which moves no funds
+    TransferNft(String),
+    // A transfer to the escrow account, which locks the amount for the recipient until the escrow is settled
+    EscrowTransfer { recipient: String, release: Timelock, expiry: Timelock },
+    // Payment of the escrow funded by the transaction with the ID from the escrow account to its recipient
+    ClaimEscrow(String),
+    // Payment of the expired escrow funded by the transaction with the ID from the escrow account back to its sender
+    RefundEscrow(String),
+    // A schedule of payments of the amount from the from account to the to account, which pays the fee and moves no
+    // funds itself
+    SchedulePayment { amount: Amount, start_height: u64, interval: u64, count: u64 },
+    // A payment due by the schedule created by the transaction with the ID, added by the minter without a fee or nonce
+    ScheduledPayment(String),
+    // Cancellation of the schedule created by the transaction with the ID by its from account, which is also the to
+    // account
+    CancelSchedule(String),
+    // An offer of the from account to give the to account an asset in exchange for another asset until the expiry
+    // height, which pays the fee and moves no funds itself
+    SwapOffer { give: Asset, take: Asset, expiry_height: u64 },
+    // Acceptance of the swap offer made by the transaction with the ID by the from account, which pays the fee and
+    // swaps the asset the offer takes, given to the to account, for the asset the offer gives
+    SwapAccept { offer_id: String, give: Asset, take: Asset },
+    // Deployment of a contract with the bytecode by the from account, which pays the fee, is also the to account and
+    // owns the contract
+    DeployContract { contract_id: String, code: Vec<u8> },
+    // A call of the function of the contract by the from account with the input, which pays the fee, is also the to
+    // account and is executed with at most the gas limit when the call is minted
+    CallContract { contract_id: String, function: String, input: Vec<u8>, gas_limit: u64 },
+    // A proposal of the from account to change a chain parameter from the activation height on, which pays the fee and
+    // is also the to account
+    Propose { change: ParameterChange, activation_height: u64 },
+    // A vote of the from account for or against the proposal made by the transaction with the ID, which pays the fee
+    // and is also the to account
+    Vote { proposal_id: String, approve: bool },
+    // Slashing of the stake of the to account for the evidence that it signed conflicting blocks, which moves the
+    // amount from the staking account to the slashed account, without a from account
+    Slash(Box<Evidence>),
+    // A payment of the amount by the faucet of a test network to the existing to account, without a from account
+    Faucet,
+    // A transfer like a plain one that can only be minted in blocks until the timelock, after which it expires
+    ExpiringTransfer { valid_until: Timelock },
+    // A transfer to the cross-shard account, which locks the amount for the recipient on the shard until the deadline
+    CrossShardLock { recipient: String, shard: usize, deadline: u64 },
+    // Commit of the cross-shard transfer locked by the transaction with the ID once its shard credited it, from the
+    // cross-shard account to itself as the funds moved to the other shard
+    CrossShardCommit(String),
+    // Refund of the cross-shard transfer locked by the transaction with the ID from the cross-shard account back to its
+    // sender, once its deadline passed without a credit
+    CrossShardRefund(String),
+    // A payment of the amount of the cross-shard transfer locked by the transaction with the ID on the shard of the
+    // sender to the to account, without a from account, which can only be minted until the deadline
+    CrossShardCredit { transfer_id: String, sender: String, shard: usize, deadline: u64 },
 }
 
 impl Transaction {
-    /// Returns a new transaction with the given parameters
-    fn new(node_id: String, from_account_id: Option<String>, to_account_id: String, amount: f64) -> Transaction {
+    /// Returns a new transaction with the given parameters, created at the given time of the node's clock
+    #[allow(clippy::too_many_arguments)]
+    fn new(node_id: String, from_account_id: Option<String>, to_account_id: String, amount: Amount, fee: Amount, nonce: Option<u64>, datetime: SystemTime, memo: Option<String>) -> Transaction {
         Transaction {
             node_id,
             from_account_id,
             to_account_id,
             amount,
-            datetime: std::time::SystemTime::now(),
+            fee,
+            nonce,
+            datetime,
+            memo,
+            kind: TransactionKind::Transfer,
+        }
+    }
+
+    /// Returns the block reward paying the fees of the given transactions and the subsidy of the block to the reward
+    /// account, see [monetary::block_subsidy]
+    fn new_reward(node_id: String, reward_account: String, transactions: &[Transaction], subsidy: Amount, datetime: SystemTime) -> Transaction {
+        Transaction {
+            kind: TransactionKind::Reward,
+            ..Transaction::new(node_id, None, reward_account, Block::fees(transactions).and_then(|fees| fees.checked_add(subsidy)).expect("Fees of a minted block overflowed"), Amount::ZERO, None, datetime, None)
+        }
+    }
+
+    /// Returns the ID of the transaction, the hash of its contents. The ID is deterministic, so any node can derive it
+    /// from the transaction itself. The memo and kind are only hashed if set, so plain transfers keep the IDs they had
+    /// before transactions could have them.
+    fn id(&self) -> String {
+        let fields = (&self.node_id, &self.from_account_id, &self.to_account_id, &self.amount, &self.fee, &self.nonce, &self.datetime);
+        let mut bytes = bincode::serialize(&fields).unwrap();
+        if let Some(memo) = &self.memo {
+            bytes.extend_from_slice(&bincode::serialize(memo).unwrap());
+        }
+        match &self.kind {
+            TransactionKind::Transfer => {}
+            // Block rewards were a flag before there were other kinds, and keep the IDs they had
+            TransactionKind::Reward => bytes.extend_from_slice(&bincode::serialize(&true).unwrap()),
+            kind => bytes.extend_from_slice(&bincode::serialize(kind).unwrap()),
+        }
+        sha256_hex(&bytes)
+    }
+
+    fn is_reward(&self) -> bool {
+        self.kind == TransactionKind::Reward
+    }
+
+    fn is_staking_reward(&self) -> bool {
+        matches!(self.kind, TransactionKind::StakingReward { .. })
+    }
+
+    /// Returns the funds the transaction newly issues, counted against the maximum supply: the starting balance of a
+    /// created account, a faucet payment or a staking reward. The block reward is counted separately, see
+    /// [monetary::block_subsidy], and cross-shard credits move funds issued on another shard.
+    fn issued(&self) -> Amount {
+        match self.kind {
+            TransactionKind::Transfer | TransactionKind::CreateMultisig(_) | TransactionKind::Faucet | TransactionKind::StakingReward { .. } if self.from_account_id.is_none() => self.amount,
+            _ => Amount::ZERO,
+        }
+    }
+
+    /// Returns the evidence the transaction slashes its to account for, None if it isn't a slash
+    fn slashes(&self) -> Option<&Evidence> {
+        match &self.kind {
+            TransactionKind::Slash(evidence) => Some(evidence),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the transaction freezes (true) or unfreezes (false) its to account, and when the admin signed it
+    fn freezes(&self) -> Option<(bool, u64)> {
+        match self.kind {
+            TransactionKind::Freeze { signed_at } => Some((true, signed_at)),
+            TransactionKind::Unfreeze { signed_at } => Some((false, signed_at)),
+            _ => None,
+        }
+    }
+
+    /// Returns the token the transaction transfers, None if it moves the native coin
+    fn token(&self) -> Option<&str> {
+        match &self.kind {
+            TransactionKind::TokenTransfer(token) => Some(token),
+            _ => None,
+        }
+    }
+
+    /// Returns the amount of the native coin the transaction moves to the to account, none for token transfers,
+    /// unstaking, whose amount is the stake it releases, and slashes, whose amount is the stake they slash
+    fn native_amount(&self) -> Amount {
+        if self.token().is_some() || self.kind == TransactionKind::Unstake || self.slashes().is_some() { Amount::ZERO } else { self.amount }
+    }
+
+    /// Returns the escrow the transaction settles and whether it is refunded, None if it doesn't settle one
+    fn settles_escrow(&self) -> Option<(&str, bool)> {
+        match &self.kind {
+            TransactionKind::ClaimEscrow(escrow_id) => Some((escrow_id, false)),
+            TransactionKind::RefundEscrow(escrow_id) => Some((escrow_id, true)),
+            _ => None,
+        }
+    }
+
+    /// Returns the cross-shard transfer the transaction settles and whether it is refunded, None if it doesn't settle
+    /// one
+    fn settles_cross_shard(&self) -> Option<(&str, bool)> {
+        match &self.kind {
+            TransactionKind::CrossShardCommit(transfer_id) => Some((transfer_id, false)),
+            TransactionKind::CrossShardRefund(transfer_id) => Some((transfer_id, true)),
+            _ => None,
+        }
+    }
+
+    /// Returns the cross-shard transfer the transaction credits, None if it isn't a cross-shard credit
+    fn credits_cross_shard(&self) -> Option<&String> {
+        match &self.kind {
+            TransactionKind::CrossShardCredit { transfer_id, .. } => Some(transfer_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the schedule the transaction is a payment of, None if it isn't a scheduled payment
+    fn pays_schedule(&self) -> Option<&str> {
+        match &self.kind {
+            TransactionKind::ScheduledPayment(schedule_id) => Some(schedule_id),
+            _ => None,
+        }
+    }
+
+    /// Returns the legs of the swap the transaction accepts, each the account giving an asset, the account receiving it
+    /// and the asset, None if it doesn't accept a swap
+    fn swap_legs(&self) -> Option<[(&str, &str, &Asset); 2]> {
+        match (&self.kind, &self.from_account_id) {
+            (TransactionKind::SwapAccept { give, take, .. }, Some(counterparty)) => Some([(&self.to_account_id, counterparty, give), (counterparty, &self.to_account_id, take)]),
+            _ => None,
+        }
+    }
+
+    /// Returns the moves of the native coin the transaction makes besides moving its native amount, the legs of the
+    /// swap it accepts in the native coin, like [Transaction::swap_legs], the release of the stake it unstakes or the
+    /// stake it slashes
+    fn native_legs(&self) -> impl Iterator<Item = (&str, &str, Amount)> {
+        let unstaked = match (&self.kind, &self.from_account_id) {
+            (TransactionKind::Unstake, Some(from_account_id)) => Some((STAKE_ACCOUNT, from_account_id.as_str(), self.amount)),
+            (TransactionKind::Slash(_), None) => Some((STAKE_ACCOUNT, SLASHED_ACCOUNT, self.amount)),
+            _ => None,
+        };
+        self.swap_legs()
+            .into_iter()
+            .flatten()
+            .filter(|(_, _, asset)| asset.token.is_none())
+            .map(|(giver, receiver, asset)| (giver, receiver, asset.amount))
+            .chain(unstaked)
+    }
+
+    /// Returns the accounts the transaction credits in the native coin and the amounts it credits them, the native
+    /// amount to its to account and the receivers of its native legs, see [Transaction::native_legs]
+    fn native_credits(&self) -> impl Iterator<Item = (&str, Amount)> {
+        std::iter::once((self.to_account_id.as_str(), self.native_amount())).chain(self.native_legs().map(|(_, receiver, amount)| (receiver, amount)))
+    }
+
+    /// Returns the timelock until which the transaction can be minted, None if it doesn't expire
+    fn valid_until(&self) -> Option<Timelock> {
+        match self.kind {
+            TransactionKind::ExpiringTransfer { valid_until } => Some(valid_until),
+            TransactionKind::CrossShardCredit { deadline, .. } => Some(Timelock::Time(deadline)),
+            _ => None,
+        }
+    }
+
+    /// Whether the transaction expired before a block at the given height with the given timestamp
+    fn has_expired(&self, height: u64, timestamp: u64) -> bool {
+        self.valid_until().is_some_and(|valid_until| valid_until.has_passed(height, timestamp))
+    }
+
+    /// Returns the number of bytes the transaction takes up in a block
+    fn encoded_size(&self) -> usize {
+        bincode::serialized_size(self).unwrap() as usize
+    }
+
+    /// Returns the unix time in seconds at which the transaction was created
+    fn timestamp(&self) -> u64 {
+        self.datetime.duration_since(std::time::UNIX_EPOCH).unwrap_or_default().as_secs()
+    }
+
+    /// Describes the transaction from the point of view of the given account
+    fn describe_for(&self, account_id: &str) -> String {
+        let description = match &self.from_account_id {
+            Some(from_account_id) if from_account_id == account_id && self.kind == TransactionKind::Close => {
+                format!("closed the account, sweeping {} (fee {}) to {}", self.amount, self.fee, self.to_account_id)
+            }
+            Some(from_account_id) if self.kind == TransactionKind::Close => format!("received {} from closing account {}", self.amount, from_account_id),
+            Some(from_account_id) if from_account_id == account_id && self.kind == TransactionKind::Stake => format!("staked {} (fee {})", self.amount, self.fee),
+            Some(_) if self.kind == TransactionKind::Unstake => format!("unstaked {} (fee {})", self.amount, self.fee),
+            Some(_) if let TransactionKind::RegisterAlias(alias) = &self.kind => format!("registered alias {} (fee {})", alias, self.fee),
+            Some(_) if let TransactionKind::CreateToken { token, supply } = &self.kind => format!("created token {} with a supply of {} (fee {})", token, supply, self.fee),
+            Some(from_account_id) if let Some(token) = self.token() => match from_account_id == account_id {
+                true => format!("sent {} {} (fee {}) to {}", self.amount, token, self.fee, self.to_account_id),
+                false => format!("received {} {} from {}", self.amount, token, from_account_id),
+            },
+            Some(_) if let TransactionKind::MintNft { nft_id, metadata } = &self.kind => format!("minted NFT {} with metadata \"{}\" (fee {})", nft_id, metadata, self.fee),
+            Some(_) if let TransactionKind::Propose { change, activation_height } = &self.kind => {
+                format!("proposed changing {} from height {} (fee {})", change, activation_height, self.fee)
+            }
+            Some(_) if let TransactionKind::Vote { proposal_id, approve } = &self.kind => {
+                format!("voted {} proposal {} (fee {})", if *approve { "for" } else { "against" }, proposal_id, self.fee)
+            }
+            Some(_) if let TransactionKind::DeployContract { contract_id, code } = &self.kind => format!("deployed contract {} of {} bytes (fee {})", contract_id, code.len(), self.fee),
+            Some(_) if let TransactionKind::CallContract { contract_id, function, input, gas_limit } = &self.kind => {
+                format!("called function {} of contract {} with {} bytes of input (fee {}, gas limit {})", function, contract_id, input.len(), self.fee, gas_limit)
+            }
+            Some(_) if let TransactionKind::EscrowTransfer { recipient, release, expiry } = &self.kind => {
+                format!("escrowed {} (fee {}) for {}, claimable from {} until {}", self.amount, self.fee, recipient, release, expiry)
+            }
+            Some(_) if let Some((escrow_id, refund)) = self.settles_escrow() => match refund {
+                true => format!("refunded {} from expired escrow {}", self.amount, escrow_id),
+                false => format!("claimed {} from escrow {}", self.amount, escrow_id),
+            },
+            Some(_) if let TransactionKind::CrossShardLock { recipient, shard, deadline } = &self.kind => {
+                format!("sent {} (fee {}) to {} on shard {}, locked until it is credited or refunded after {}", self.amount, self.fee, recipient, shard, deadline)
+            }
+            Some(_) if let Some((transfer_id, refund)) = self.settles_cross_shard() => match refund {
+                true => format!("refunded {} from cross-shard transfer {}", self.amount, transfer_id),
+                false => format!("committed {} of cross-shard transfer {}", self.amount, transfer_id),
+            },
+            Some(from_account_id) if let TransactionKind::SchedulePayment { amount, start_height, interval, count } = &self.kind => {
+                let payments = format!("{} payments of {} every {} blocks from height {}", count, amount, interval, start_height);
+                match from_account_id == account_id {
+                    true => format!("scheduled {} (fee {}) to {}", payments, self.fee, self.to_account_id),
+                    false => format!("scheduled to receive {} from {}", payments, from_account_id),
+                }
+            }
+            Some(from_account_id) if let Some(schedule_id) = self.pays_schedule() => match from_account_id == account_id {
+                true => format!("sent scheduled payment {} of schedule {} to {}", self.amount, schedule_id, self.to_account_id),
+                false => format!("received scheduled payment {} of schedule {} from {}", self.amount, schedule_id, from_account_id),
+            },
+            Some(_) if let TransactionKind::CancelSchedule(schedule_id) = &self.kind => format!("cancelled schedule {} (fee {})", schedule_id, self.fee),
+            Some(from_account_id) if let TransactionKind::SwapOffer { give, take, expiry_height } = &self.kind => match from_account_id == account_id {
+                true => format!("offered {} (fee {}) to {} for {} until height {}", give, self.fee, self.to_account_id, take, expiry_height),
+                false => format!("was offered {} by {} for {} until height {}", give, from_account_id, take, expiry_height),
+            },
+            Some(from_account_id) if let TransactionKind::SwapAccept { offer_id, give, take } = &self.kind => match from_account_id == account_id {
+                true => format!("swapped {} (fee {}) for {} from {} by accepting offer {}", take, self.fee, give, self.to_account_id, offer_id),
+                false => format!("swapped {} for {} from {}, who accepted offer {}", give, take, from_account_id, offer_id),
+            },
+            Some(from_account_id) if let TransactionKind::TransferNft(nft_id) = &self.kind => match from_account_id == account_id {
+                true => format!("sent NFT {} (fee {}) to {}", nft_id, self.fee, self.to_account_id),
+                false => format!("received NFT {} from {}", nft_id, from_account_id),
+            },
+            Some(from_account_id) if from_account_id == account_id => format!("sent {} (fee {}) to {}", self.amount, self.fee, self.to_account_id),
+            Some(from_account_id) => format!("received {} from {}", self.amount, from_account_id),
+            None if self.is_reward() => format!("received block reward {}", self.amount),
+            None if let TransactionKind::StakingReward { epoch } = self.kind => format!("received staking reward {} for epoch {}", self.amount, epoch),
+            None if let Some(evidence) = self.slashes() => format!("slashed {} of stake for signing conflicting blocks at height {}", self.amount, evidence.height()),
+            None if self.freezes().is_some_and(|(freeze, _)| freeze) => "account frozen by the admin".to_string(),
+            None if self.freezes().is_some() => "account unfrozen by the admin".to_string(),
+            None if self.kind == TransactionKind::Faucet => format!("received {} from the faucet", self.amount),
+            None if let TransactionKind::CrossShardCredit { sender, shard, .. } = &self.kind => format!("received {} from {} on shard {}", self.amount, sender, shard),
+            None => match &self.kind {
+                TransactionKind::CreateMultisig(policy) => format!("multisig account created ({} of {} keys) with balance {}", policy.threshold, policy.public_keys.len(), self.amount),
+                _ => format!("account created with balance {}", self.amount),
+            },
+        };
+        match &self.memo {
+            Some(memo) => format!("{} with memo \"{}\"", description, memo),
+            None => description,
+        }
+    }
+}
+
+impl std::fmt::Display for Transaction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self.from_account_id {
+            Some(from_account_id) if self.kind == TransactionKind::Close => write!(f, "closure of account {} sweeping {} (fee {}) to {}", from_account_id, self.amount, self.fee, self.to_account_id)?,
+            Some(from_account_id) if self.kind == TransactionKind::Stake => write!(f, "staking of {} (fee {}) by {}", self.amount, self.fee, from_account_id)?,
+            Some(from_account_id) if self.kind == TransactionKind::Unstake => write!(f, "unstaking of {} (fee {}) by {}", self.amount, self.fee, from_account_id)?,
+            Some(from_account_id) if let TransactionKind::RegisterAlias(alias) = &self.kind => write!(f, "registration of alias {} (fee {}) by {}", alias, self.fee, from_account_id)?,
+            Some(from_account_id) if let TransactionKind::CreateToken { token, supply } = &self.kind => {
+                write!(f, "creation of token {} with a supply of {} (fee {}) by {}", token, supply, self.fee, from_account_id)?
+            }
+            Some(from_account_id) if let Some(token) = self.token() => write!(f, "transfer of {} {} (fee {}) from {} to {}", self.amount, token, self.fee, from_account_id, self.to_account_id)?,
+            Some(from_account_id) if let TransactionKind::MintNft { nft_id, metadata } = &self.kind => {
+                write!(f, "minting of NFT {} with metadata \"{}\" (fee {}) by {}", nft_id, metadata, self.fee, from_account_id)?
+            }
+            Some(from_account_id) if let TransactionKind::TransferNft(nft_id) = &self.kind => write!(f, "transfer of NFT {} (fee {}) from {} to {}", nft_id, self.fee, from_account_id, self.to_account_id)?,
+            Some(from_account_id) if let TransactionKind::Propose { change, activation_height } = &self.kind => {
+                write!(f, "proposal to change {} from height {} (fee {}) by {}", change, activation_height, self.fee, from_account_id)?
+            }
+            Some(from_account_id) if let TransactionKind::Vote { proposal_id, approve } = &self.kind => {
+                write!(f, "vote {} proposal {} (fee {}) by {}", if *approve { "for" } else { "against" }, proposal_id, self.fee, from_account_id)?
+            }
+            Some(from_account_id) if let TransactionKind::DeployContract { contract_id, code } = &self.kind => {
+                write!(f, "deployment of contract {} of {} bytes (fee {}) by {}", contract_id, code.len(), self.fee, from_account_id)?
+            }
+            Some(from_account_id) if let TransactionKind::CallContract { contract_id, function, input, gas_limit } = &self.kind => write!(
+                f,
+                "call of function {} of contract {} with {} bytes of input (fee {}, gas limit {}) by {}",
+                function, contract_id, input.len(), self.fee, gas_limit, from_account_id
+            )?,
+            Some(from_account_id) if let TransactionKind::EscrowTransfer { recipient, release, expiry } = &self.kind => {
+                write!(f, "escrow of {} (fee {}) from {} for {}, claimable from {} until {}", self.amount, self.fee, from_account_id, recipient, release, expiry)?
+            }
+            Some(from_account_id) if let TransactionKind::SchedulePayment { amount, start_height, interval, count } = &self.kind => write!(
+                f,
+                "schedule of {} payments of {} (fee {}) from {} to {} every {} blocks from height {}",
+                count, amount, self.fee, from_account_id, self.to_account_id, interval, start_height
+            )?,
+            Some(from_account_id) if let Some(schedule_id) = self.pays_schedule() => {
+                write!(f, "payment of schedule {} of {} from {} to {}", schedule_id, self.amount, from_account_id, self.to_account_id)?
+            }
+            Some(from_account_id) if let TransactionKind::CancelSchedule(schedule_id) = &self.kind => write!(f, "cancellation of schedule {} (fee {}) by {}", schedule_id, self.fee, from_account_id)?,
+            Some(from_account_id) if let TransactionKind::SwapOffer { give, take, expiry_height } = &self.kind => {
+                write!(f, "swap offer of {} (fee {}) from {} to {} for {} until height {}", give, self.fee, from_account_id, self.to_account_id, take, expiry_height)?
+            }
+            Some(from_account_id) if let TransactionKind::SwapAccept { offer_id, give, take } = &self.kind => {
+                write!(f, "acceptance of swap offer {} (fee {}) by {}, swapping {} from {} for {} from {}", offer_id, self.fee, from_account_id, give, self.to_account_id, take, from_account_id)?
+            }
+            Some(_) if let Some((escrow_id, refund)) = self.settles_escrow() => match refund {
+                true => write!(f, "refund of expired escrow {} paying {} to {}", escrow_id, self.amount, self.to_account_id)?,
+                false => write!(f, "claim of escrow {} paying {} to {}", escrow_id, self.amount, self.to_account_id)?,
+            },
+            Some(from_account_id) if let TransactionKind::CrossShardLock { recipient, shard, deadline } = &self.kind => {
+                write!(f, "cross-shard transfer of {} (fee {}) from {} to {} on shard {}, creditable until {}", self.amount, self.fee, from_account_id, recipient, shard, deadline)?
+            }
+            Some(_) if let Some((transfer_id, refund)) = self.settles_cross_shard() => match refund {
+                true => write!(f, "refund of cross-shard transfer {} paying {} to {}", transfer_id, self.amount, self.to_account_id)?,
+                false => write!(f, "commit of cross-shard transfer {} of {}", transfer_id, self.amount)?,
+            },
+            Some(from_account_id) if let Some(valid_until) = self.valid_until() => {
+                write!(f, "transfer of {} (fee {}) from {} to {} valid until {}", self.amount, self.fee, from_account_id, self.to_account_id, valid_until)?
+            }
+            Some(from_account_id) => write!(f, "transfer of {} (fee {}) from {} to {}", self.amount, self.fee, from_account_id, self.to_account_id)?,
+            None if self.is_reward() => write!(f, "block reward of {} to {}", self.amount, self.to_account_id)?,
+            None if let TransactionKind::StakingReward { epoch } = self.kind => write!(f, "staking reward of {} to {} for epoch {}", self.amount, self.to_account_id, epoch)?,
+            None if let Some(evidence) = self.slashes() => {
+                write!(f, "slashing of {} of the stake of {} for signing conflicting blocks at height {}", self.amount, self.to_account_id, evidence.height())?
+            }
+            None if self.freezes().is_some_and(|(freeze, _)| freeze) => write!(f, "freezing of account {}", self.to_account_id)?,
+            None if self.freezes().is_some() => write!(f, "unfreezing of account {}", self.to_account_id)?,
+            None if self.kind == TransactionKind::Faucet => write!(f, "faucet payment of {} to {}", self.amount, self.to_account_id)?,
+            None if let TransactionKind::CrossShardCredit { transfer_id, sender, shard, deadline } = &self.kind => write!(
+                f,
+                "credit of cross-shard transfer {} of {} from {} on shard {} to {}, valid until {}",
+                transfer_id, self.amount, sender, shard, self.to_account_id, deadline
+            )?,
+            None => match &self.kind {
+                TransactionKind::CreateMultisig(policy) => write!(f, "creation of multisig account {} ({} of {} keys) with balance {}", self.to_account_id, policy.threshold, policy.public_keys.len(), self.amount)?,
+                _ => write!(f, "creation of account {} with balance {}", self.to_account_id, self.amount)?,
+            },
+        }
+        if let Some(memo) = &self.memo {
+            write!(f, " with memo \"{}\"", memo)?;
         }
+        write!(f, " by node {} at {} (unix time)", self.node_id, self.timestamp())
+    }
+}
+
+/// Header of a block. The hash of a block is the hash of its header, which commits to the transactions through the
+/// Merkle root, so a block can be verified from its header alone.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+struct BlockHeader {
+    // Hash of the previous block
+    previous_hash: String,
+    // Merkle root of the IDs of the transactions in the block
+    merkle_root: String,
+    // Height of the block in the chain, 0 for the genesis block
+    height: u64,
+    // Unix time in seconds at which the block was minted
+    timestamp: u64,
+}
+
+impl BlockHeader {
+    fn hash(&self) -> String {
+        sha256_hex(&bincode::serialize(self).unwrap())
+    }
+
+    /// Returns the hash of the header without its height and timestamp. Blocks minted before headers had them keep
+    /// this hash, and so does the genesis block, so that the genesis hash of a chain never changes.
+    fn legacy_hash(&self) -> String {
+        sha256_hex(&bincode::serialize(&(&self.previous_hash, &self.merkle_root)).unwrap())
     }
 }
 
 /// Blockchain block that contains transactions
 #[derive(Serialize, Deserialize, Clone)]
 struct Block {
+    header: BlockHeader,
     // All transactions in the block
     transactions: Vec<Transaction>,
-    // Hash of the previous block
-    previous_hash: String,
-    // Hash of the block
+    // Hash of the block header
     hash: String,
+    // Signature of the validator that minted the block, None for blocks minted without a validator key
+    #[serde(default)]
+    signature: Option<BlockSignature>,
+    // Signature of the node that minted the block with its identity key, None for blocks minted before nodes had one
+    #[serde(default)]
+    node_signature: Option<NodeSignature>,
 }
 
 impl Block {
-    /// Calculate and set the hash of the block if not already set
-    fn calc_and_set_hash(&mut self) {
-        if self.hash.is_empty() {
-            let mut hasher = sha2::Sha256::new();
-            hasher.update(bincode::serialize(&self).unwrap());
-            let calculated_hash = hasher.finalize();
-            self.hash = calculated_hash.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    /// Returns a new block with the given transactions at the given height on top of the block with the given hash,
+    /// with its Merkle root and hash calculated
+    fn new(previous_hash: String, height: usize, timestamp: u64, transactions: Vec<Transaction>) -> Block {
+        let header = BlockHeader {
+            previous_hash,
+            merkle_root: merkle_root(&Block::transaction_ids(&transactions)),
+            height: height as u64,
+            timestamp,
+        };
+        let hash = header.hash();
+        Block { header, transactions, hash, signature: None, node_signature: None }
+    }
+
+    /// Returns the timestamp of a block whose hash doesn't cover its timestamp: the time of its latest transaction,
+    /// which for minted blocks is the block reward created when the block was minted
+    fn legacy_timestamp(transactions: &[Transaction]) -> u64 {
+        transactions.iter().map(Transaction::timestamp).max().unwrap_or_default()
+    }
+
+    /// Checks the timestamp of a block of another node: it can't be before the timestamp of the block before it, so the
+    /// time of the chain never goes back, and at most the given number of seconds ahead of the given time
+    fn check_timestamp(&self, previous: &Block, now: u64, max_clock_skew: u64) -> Result<(), String> {
+        if self.header.timestamp < previous.header.timestamp {
+            return Err(format!("its timestamp {} is before the timestamp {} of the block before it", self.header.timestamp, previous.header.timestamp));
         }
+        if self.header.timestamp > now.saturating_add(max_clock_skew) {
+            return Err(format!("its timestamp {} is more than {}s ahead of the time {} of this node", self.header.timestamp, max_clock_skew, now));
+        }
+        Ok(())
     }
-}
 
-/// State of the blockchain server
-struct State {
-    ledger: Mutex<Vec<Block>>,
-    next_block_to_mint: Mutex<Block>,
-}
+    /// Returns the amount of the block reward, the last transaction of the block, 0 for blocks without one
+    fn reward(&self) -> Amount {
+        self.transactions.last().filter(|transaction| transaction.is_reward()).map_or(Amount::ZERO, |transaction| transaction.amount)
+    }
 
-impl State {
-    /// Checks if an account exists in the ledger by checking all previous transactions ever made
-    fn account_exists(&self, account_id: &String) -> bool {
-        let ledger = self.ledger.lock().unwrap();
-        ledger.iter().any(|block| block.transactions.iter().any(|transaction| {
-            transaction.to_account_id == *account_id || transaction.from_account_id.as_ref().map_or(false, |from_id| from_id == account_id)
-        }))
+    /// Returns the total fee paid by the given transactions, None if it is more than an amount can hold
+    fn fees(transactions: &[Transaction]) -> Option<Amount> {
+        Amount::checked_sum(transactions.iter().map(|transaction| transaction.fee))
     }
 
-    /// Gets the balance of an account by checking all previous transactions ever made
-    fn get_balance(&self, account_id: &String) -> f64 {
-        let ledger = self.ledger.lock().unwrap();
-        let mut balance = 0.0;
+    /// Returns the IDs of the given transactions, the leaves of the Merkle tree of a block
+    fn transaction_ids(transactions: &[Transaction]) -> Vec<String> {
+        transactions.iter().map(|transaction| transaction.id()).collect()
+    }
 
-        for block in ledger.iter() {
-            for transaction in &block.transactions {
-                // If account is the sender, subtract the amount
-                if let Some(from_account_id) = &transaction.from_account_id {
-                    if from_account_id == account_id {
-                        balance -= transaction.amount;
-                    }
+    /// Returns every account involved in a transaction of the block, in the order they first appear
+    fn accounts(&self) -> Vec<&String> {
+        let mut accounts = Vec::new();
+        for transaction in &self.transactions {
+            for account_id in transaction.from_account_id.iter().chain(std::iter::once(&transaction.to_account_id)) {
+                if !accounts.contains(&account_id) {
+                    accounts.push(account_id);
                 }
+            }
+        }
+        accounts
+    }
+
+    /// Returns the multisig accounts created in the block with their policies
+    fn multisig_accounts(&self) -> impl Iterator<Item = (&String, &MultisigPolicy)> {
+        self.transactions.iter().filter_map(|transaction| match &transaction.kind {
+            TransactionKind::CreateMultisig(policy) => Some((&transaction.to_account_id, policy)),
+            _ => None,
+        })
+    }
 
-                // If account is the receiver, add the amount
-                if &transaction.to_account_id == account_id {
-                    balance += transaction.amount;
+    /// Applies the stakes, unstakes and slashes of the block to the given stakes of the validators, an account that
+    /// unstakes its whole stake or is slashed isn't a validator anymore
+    fn apply_to_stakes(&self, stakes: &mut HashMap<String, Amount>) {
+        for transaction in &self.transactions {
+            if transaction.slashes().is_some() {
+                stakes.remove(&transaction.to_account_id);
+            }
+            let Some(account_id) = &transaction.from_account_id else { continue };
+            match transaction.kind {
+                TransactionKind::Stake => *stakes.entry(account_id.clone()).or_default() += transaction.amount,
+                TransactionKind::Unstake => {
+                    let stake = stakes.entry(account_id.clone()).or_default();
+                    *stake -= transaction.amount;
+                    if *stake <= Amount::ZERO {
+                        stakes.remove(account_id);
+                    }
                 }
+                _ => {}
             }
         }
-        balance
     }
-}
 
-/// Mint blocks every specified interval
-fn mint_blocks(state: Arc<State>, mint_interval_in_seconds: u64) {
-    println!("Minting blocks every {} seconds.", mint_interval_in_seconds);
-    loop {
-        println!("Waiting {} seconds to mint the next block.", mint_interval_in_seconds);
-        std::thread::sleep(std::time::Duration::from_secs(mint_interval_in_seconds));
-
-        let mut next_block_to_mint = state.next_block_to_mint.lock().unwrap();
-        if next_block_to_mint.transactions.is_empty() {
-            println!("Skipping block minting as there are no transactions.");
-            continue;
+    /// Records the slashes of the block in the given heights of the offences the validators were slashed for last
+    fn apply_to_slashed(&self, slashed: &mut HashMap<String, u64>) {
+        for transaction in &self.transactions {
+            if let Some(evidence) = transaction.slashes() {
+                slashed.insert(transaction.to_account_id.clone(), evidence.height());
+            }
         }
+    }
 
-        // Set the hash of the block
-        next_block_to_mint.calc_and_set_hash();
+    /// Returns the aliases registered in the block with the accounts they belong to
+    fn aliases(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.transactions.iter().filter_map(|transaction| match &transaction.kind {
+            TransactionKind::RegisterAlias(alias) => Some((alias, &transaction.to_account_id)),
+            _ => None,
+        })
+    }
 
-        // Add the block to the ledger
-        state.ledger.lock().unwrap().push(next_block_to_mint.clone());
+    /// Builds the proof that the transaction at the given index is included in the Merkle root of the block
+    fn transaction_proof(&self, index: usize) -> Option<Vec<ProofStep>> {
+        merkle_proof(&Block::transaction_ids(&self.transactions), index)
+    }
 
-        println!("Block {} minted with {} transactions.", &next_block_to_mint.hash, next_block_to_mint.transactions.len());
+    /// Applies the transactions of the block to the given balance index of the native coin. Every account touched by
+    /// a transaction gets an entry, even if its balance stays 0. Nothing is applied if a balance would overflow.
+    ///
+    /// Returns: why the block can't be applied
+    fn apply_to_balances(&self, balances: &mut HashMap<String, Amount>) -> Result<(), String> {
+        let mut updated: HashMap<&str, Amount> = HashMap::new();
+        for transaction in &self.transactions {
+            // The sender pays the amount and the fee, the receiver gets the amount
+            let sent = match &transaction.from_account_id {
+                Some(from_account_id) => {
+                    let total = transaction.native_amount().checked_add(transaction.fee).ok_or_else(|| format!("transaction {} has an amount and fee that overflow", transaction.id()))?;
+                    Some((from_account_id.as_str(), total))
+                }
+                None => None,
+            };
+            let debits = sent.into_iter().chain(transaction.native_legs().map(|(giver, _, amount)| (giver, amount)));
+            let changes = debits.map(|(account_id, amount)| (account_id, amount, false)).chain(transaction.native_credits().map(|(account_id, amount)| (account_id, amount, true)));
+            for (account_id, amount, credit) in changes {
+                let balance = updated.get(account_id).or_else(|| balances.get(account_id)).copied().unwrap_or_default();
+                let changed = if credit { balance.checked_add(amount) } else { balance.checked_sub(amount) };
+                let changed = changed.ok_or_else(|| format!("transaction {} overflows the balance {} of account {}", transaction.id(), balance, account_id))?;
+                updated.insert(account_id, changed);
+            }
+        }
+        balances.extend(updated.into_iter().map(|(account_id, balance)| (account_id.to_string(), balance)));
+        Ok(())
+    }
 
-        // Reset the next block to mint to a new block
-        next_block_to_mint.transactions.clear();
-        next_block_to_mint.previous_hash = next_block_to_mint.hash.clone();
-        next_block_to_mint.hash = "".to_string();
+    /// Applies the token creations, token transfers and swaps in tokens of the block to the given token balances
+    fn apply_to_tokens(&self, tokens: &mut Tokens) {
+        for transaction in &self.transactions {
+            match (&transaction.kind, &transaction.from_account_id) {
+                (TransactionKind::CreateToken { token, supply }, _) => tokens.create(token, &transaction.to_account_id, *supply),
+                (TransactionKind::TokenTransfer(token), Some(from_account_id)) => tokens.transfer(token, from_account_id, &transaction.to_account_id, transaction.amount),
+                _ => {}
+            }
+            for (giver, receiver, asset) in transaction.swap_legs().into_iter().flatten() {
+                if let Some(token) = &asset.token {
+                    tokens.transfer(token, giver, receiver, asset.amount);
+                }
+            }
+        }
     }
-}
 
-/// Initializes the blockchain server on a given port. The server listens for requests from clients and processes them.
-/// The server also mints blocks every specified interval and adds them to the ledger.
-///
-/// # Arguments
-///
-/// * `port`: the port on which the server will listen for requests
-/// * `mint_interval_in_seconds`: the interval in seconds at which the server will mint blocks
-///
-/// Returns: This function should be called only once and will run indefinitely (until manually stopped).
-pub fn init_server(port: u16, mint_interval_in_seconds: u64) {
-    let addr = format!("0.0.0.0:{}", port);
-    let socket = UdpSocket::bind(addr).expect("Failed to bind to address. Make sure PORT {} is not in use.");
-    println!("Server started on port {}.", port);
+    /// Applies the escrow transfers and settlements of the block to the given open escrows
+    fn apply_to_escrows(&self, escrows: &mut Escrows) {
+        for transaction in &self.transactions {
+            match (&transaction.kind, &transaction.from_account_id) {
+                (TransactionKind::EscrowTransfer { recipient, release, expiry }, Some(sender)) => {
+                    let escrow = Escrow { sender: sender.clone(), recipient: recipient.clone(), amount: transaction.amount, release: *release, expiry: *expiry };
+                    escrows.open(transaction.id(), escrow);
+                }
+                _ => {
+                    if let Some((escrow_id, _)) = transaction.settles_escrow() {
+                        escrows.settle(escrow_id);
+                    }
+                }
+            }
+        }
+    }
 
-    let state = Arc::new(State {
-        ledger: Mutex::new(Vec::new()),
-        next_block_to_mint: Mutex::new(Block {
-            transactions: Vec::new(),
-            previous_hash: "".to_string(),
-            hash: "".to_string(),
-        }),
-    });
+    /// Applies the cross-shard transfers, their settlements and the credits of transfers from other shards of the block
+    /// to the given cross-shard transfers
+    fn apply_to_cross_shard(&self, cross_shard: &mut CrossShardTransfers) {
+        for transaction in &self.transactions {
+            match (&transaction.kind, &transaction.from_account_id) {
+                (TransactionKind::CrossShardLock { recipient, shard, deadline }, Some(sender)) => {
+                    let lock = Lock { sender: sender.clone(), recipient: recipient.clone(), shard: *shard, amount: transaction.amount, deadline: *deadline };
+                    cross_shard.lock(transaction.id(), lock);
+                }
+                (TransactionKind::CrossShardCommit(transfer_id) | TransactionKind::CrossShardRefund(transfer_id), _) => cross_shard.settle(transfer_id),
+                (TransactionKind::CrossShardCredit { transfer_id, .. }, _) => cross_shard.credit(transfer_id.clone()),
+                _ => {}
+            }
+        }
+    }
 
-    let shared_state = state.clone();
-    std::thread::spawn(move || mint_blocks(shared_state, mint_interval_in_seconds));
+    /// Applies the payment schedules, scheduled payments and cancellations of the block to the given schedules, and
+    /// removes the schedules with no payment left after it
+    fn apply_to_schedules(&self, schedules: &mut Schedules) {
+        for transaction in &self.transactions {
+            match (&transaction.kind, &transaction.from_account_id) {
+                (TransactionKind::SchedulePayment { amount, start_height, interval, count }, Some(sender)) => {
+                    let schedule = Schedule {
+                        sender: sender.clone(),
+                        recipient: transaction.to_account_id.clone(),
+                        amount: *amount,
+                        start_height: *start_height,
+                        interval: *interval,
+                        count: *count,
+                        paid_at: None,
+                    };
+                    schedules.add(transaction.id(), schedule);
+                }
+                (TransactionKind::ScheduledPayment(schedule_id), _) => schedules.pay(schedule_id, self.header.height),
+                (TransactionKind::CancelSchedule(schedule_id), _) => schedules.cancel(schedule_id),
+                _ => {}
+            }
+        }
+        schedules.finish(self.header.height);
+    }
 
-    let mut buf = [0u8; 1024];
-    loop {
-        let (amt, src) = match socket.recv_from(&mut buf) {
-            Ok((amt, src)) => (amt, src),
-            Err(e) => {
-                eprintln!("Failed to receive request: {}", e);
-                continue;
+    /// Applies the swap offers and acceptances of the block to the given offers, and removes the offers that expire
+    /// after it
+    fn apply_to_swaps(&self, swaps: &mut Swaps) {
+        for transaction in &self.transactions {
+            match (&transaction.kind, &transaction.from_account_id) {
+                (TransactionKind::SwapOffer { give, take, expiry_height }, Some(maker)) => {
+                    let offer = SwapOffer {
+                        maker: maker.clone(),
+                        counterparty: transaction.to_account_id.clone(),
+                        give: give.clone(),
+                        take: take.clone(),
+                        expiry_height: *expiry_height,
+                    };
+                    swaps.offer(transaction.id(), offer);
+                }
+                (TransactionKind::SwapAccept { offer_id, .. }, _) => swaps.accept(offer_id),
+                _ => {}
             }
-        };
+        }
+        swaps.expire(self.header.height);
+    }
 
-        let response = match bincode::deserialize(&buf[..amt]) {
-            Ok(request) => process_request(state.clone(), request),
-            Err(e) => {
-                eprintln!("Failed to deserialize request: {} - from: {}", e, src);
-                continue;
+    /// Applies the proposals and votes of the block to the given governance, and decides the proposals that take effect
+    /// after it by the balances and stakes after the block
+    fn apply_to_governance(&self, governance: &mut Governance, balances: &HashMap<String, Amount>, stakes: &HashMap<String, Amount>) {
+        for transaction in &self.transactions {
+            match (&transaction.kind, &transaction.from_account_id) {
+                (TransactionKind::Propose { change, activation_height }, Some(proposer)) => {
+                    let proposal = Proposal { proposer: proposer.clone(), change: *change, activation_height: *activation_height, votes: Default::default() };
+                    governance.propose(transaction.id(), proposal);
+                }
+                (TransactionKind::Vote { proposal_id, approve }, Some(voter)) => governance.vote(proposal_id, voter, *approve),
+                _ => {}
             }
-        };
+        }
+        for (proposal_id, proposal, passed) in governance.decide(self.header.height, |account_id| governance::weight(balances, stakes, account_id)) {
+            match passed {
+                true => info!(proposal = %proposal_id, "Proposal to change {} passed, it takes effect at height {}.", proposal.change, proposal.activation_height),
+                false => info!(proposal = %proposal_id, "Proposal to change {} was rejected.", proposal.change),
+            }
+        }
+    }
 
-        if let Err(e) = socket.send_to(response.as_bytes(), src) {
-            eprintln!("Failed to send response: {}", e);
+    /// Applies the contract deployments of the block to the given contracts and executes its contract calls
+    fn apply_to_contracts(&self, contracts: &mut Contracts) {
+        for transaction in &self.transactions {
+            match &transaction.kind {
+                TransactionKind::DeployContract { contract_id, code } => contracts.deploy(contract_id, &transaction.to_account_id, code),
+                TransactionKind::CallContract { contract_id, function, input, gas_limit } => {
+                    let outcome = contracts.call(contract_id, &transaction.to_account_id, function, input, *gas_limit, self.header.height);
+                    match outcome.error {
+                        None => debug!(transaction = %transaction.id(), "Called function {} of contract {}, using {} gas.", function, contract_id, outcome.gas_used),
+                        Some(e) => debug!(transaction = %transaction.id(), "Call of function {} of contract {} failed after {} gas: {}.", function, contract_id, outcome.gas_used, e),
+                    }
+                }
+                _ => {}
+            }
         }
+    }
 
-        println!("Response sent to client: {}", response);
+    /// Applies the NFT mints and NFT transfers of the block to the given NFTs
+    fn apply_to_nfts(&self, nfts: &mut Nfts) {
+        for transaction in &self.transactions {
+            match &transaction.kind {
+                TransactionKind::MintNft { nft_id, metadata } => nfts.mint(nft_id, &transaction.to_account_id, metadata),
+                TransactionKind::TransferNft(nft_id) => nfts.transfer(nft_id, &transaction.to_account_id),
+                _ => {}
+            }
+        }
     }
 }
 
-/// Processes a request received from a client. The client can request to create an account, transfer funds, or get funds.
-///
-/// # Arguments
-///
-/// * `state`: the current state of blockchain server
-/// * `request`: the request from the client
-///
-/// Returns: String which can be sent back to the client as a response
-fn process_request(state: Arc<State>, request: common::Request) -> String {
-    println!("Received request from {}: {:?}", request.from_node, request.operation);
+/// State of the blockchain server
+struct State {
+    // The genesis configuration the chain was started from
+    genesis: Genesis,
+    // The blocks of the chain, starting with the genesis block
+    ledger: RwLock<Vec<Block>>,
+    // Locations of the minted transactions on the main chain by their IDs and accounts, locked after the ledger and
+    // the snapshots
+    index: Mutex<TransactionIndex>,
+    // Transactions waiting to be minted into the next block
+    mempool: Mutex<Mempool>,
+    // Balance of every account that appears in the ledger, updated whenever a block is added so that lookups don't
+    // need to scan all previous transactions
+    balances: Mutex<HashMap<String, Amount>>,
+    // Next expected transfer nonce of every account that has made a transfer, including pending transfers
+    nonces: Mutex<HashMap<String, u64>>,
+    // Subscribers notified of new blocks, transactions and account changes
+    events: EventHub,
+    // Storage the ledger is persisted to, if the node was started with a data directory
+    storage: Option<Mutex<Storage>>,
+    // Log of the transactions in the mempool, replayed into it after a restart, if the node was started with a data
+    // directory
+    wal: Option<Mutex<Wal>>,
+    // Set once the node starts shutting down, after which no new transactions are accepted
+    shutting_down: AtomicBool,
+    // Wakes up the request loop once the node starts shutting down
+    shutdown_requested: Notify,
+    // Request and minting metrics served by the metrics endpoint
+    metrics: Metrics,
+    // Limits the requests per client over UDP and JSON-RPC
+    rate_limiter: RateLimiter,
+    // Sessions of clients that send their requests encrypted
+    encryption: Encryption,
+    // Responses to recent requests, to answer retried requests without processing them again
+    responses: ResponseCache,
+    // ID of this node, the hex encoded public key of its identity key, which the block rewards it mints are attributed
+    // to
+    node_id: String,
+    // Key the node signs the blocks it mints and its requests to other nodes with, see [identity]
+    identity: SigningKey,
+    // Keys of the clients and peers the node accepts requests from in permissioned mode, see [allowlist]
+    allowlist: Allowlist,
+    // Account the fees of the blocks minted by this node are paid to
+    reward_account: String,
+    // Lowest fee a transfer has to pay to be accepted by this node, unless governance decided on a higher one
+    min_fee: Amount,
+    // When the node was started, for its uptime
+    started: std::time::Instant,
+    // Fragments of requests that don't fit into a single datagram, until all fragments arrived
+    fragments: Mutex<Reassembler>,
+    // Keys and threshold of every multisig account in the ledger
+    multisig: Mutex<HashMap<String, MultisigPolicy>>,
+    // Transfers from multisig accounts waiting for the approvals of the account's keys
+    approvals: Mutex<PendingApprovals>,
+    // Accounts that were closed, including closures that are still pending
+    closed: Mutex<HashSet<String>>,
+    // Hex encoded public key admin operations have to be signed with, admin operations are rejected if not set
+    admin_key: Option<String>,
+    // Accounts frozen by the admin, including freezes that are still pending
+    frozen: Mutex<FrozenAccounts>,
+    // Blocks received from other nodes that aren't on the main chain
+    forks: Mutex<Forks>,
+    // Blocks received from other nodes before the blocks they build on
+    orphans: Mutex<Orphans>,
+    // How the node decides when it mints a block
+    consensus: Consensus,
+    // Seconds between the blocks the node mints, the length of a slot in proof-of-stake mode, unless governance decided
+    // on another interval
+    mint_interval: u64,
+    // Seconds the timestamp of a block of another node can be ahead of the node's clock
+    max_clock_skew: u64,
+    // Maximum number of transactions in the blocks this node mints, below the chain's limit
+    max_block_txs: Option<usize>,
+    // Maximum number of bytes the transactions of the blocks this node mints take up
+    max_block_bytes: Option<usize>,
+    // Number of pending transactions at which a block is minted before the mint interval elapsed, only at the interval
+    // if not set
+    mint_threshold: Option<usize>,
+    // Wakes the mint loop up once the mint threshold is reached
+    mint_wakeup: Notify,
+    // Unix time the last mint-now admin command was signed at, so a signature isn't accepted twice
+    mint_now_signed_at: Mutex<u64>,
+    // Stake of every validator in the ledger
+    stakes: Mutex<HashMap<String, Amount>>,
+    // Key the blocks minted by this node are signed with for its reward account, they aren't signed if not set
+    validator_key: Option<SigningKey>,
+    // Height of the offence every slashed validator in the ledger was slashed for last
+    slashed: Mutex<HashMap<String, u64>>,
+    // Evidence of conflicting blocks received from other nodes, slashed in the next block this node mints
+    evidence: Mutex<Vec<Evidence>>,
+    // Blocks finalized by checkpoints, the node never switches to a fork replacing them
+    checkpoints: Mutex<Checkpoints>,
+    // The latest snapshot of the accounts, the blocks before it have no transactions if the node prunes
+    snapshots: Mutex<Snapshots>,
+    // The state of the accounts after the block with the hash, the tip of the main chain when it was last verified or
+    // minted, so blocks extending the tip are verified without replaying the chain. Locked after the ledger.
+    verified: Mutex<Option<(String, verify::Accounts)>>,
+    // Account every alias belongs to, including registrations that are still pending
+    aliases: Mutex<HashMap<String, String>>,
+    // Tokens in the ledger and the balances of the accounts in them
+    tokens: Mutex<Tokens>,
+    // NFTs in the ledger and their owners
+    nfts: Mutex<Nfts>,
+    // Escrows in the ledger that weren't settled yet
+    escrows: Mutex<Escrows>,
+    // Cross-shard transfers in the ledger that weren't settled yet and the transfers from other shards credited
+    cross_shard: Mutex<CrossShardTransfers>,
+    // Payment schedules in the ledger with payments left
+    schedules: Mutex<Schedules>,
+    // Swap offers in the ledger that weren't accepted and didn't expire
+    swaps: Mutex<Swaps>,
+    // Contracts deployed in the ledger and their storage
+    contracts: Mutex<Contracts>,
+    // Proposals in the ledger that weren't decided yet and the chain parameters decided so far
+    governance: Mutex<Governance>,
+    // Nodes this node exchanges peers with, see [peers]
+    peers: Mutex<PeerManager>,
+    // Whether the node is catching up with the chain of a peer, see [sync]
+    sync: Mutex<SyncStatus>,
+    // Transactions seen and waiting to be relayed to the peers, see [gossip]
+    gossip: Mutex<Gossip>,
+    // When the mint loop last ran and made progress, see [health]
+    mint_rounds: Mutex<MintRounds>,
+    // Why accepted transactions were dropped before they were minted, for their receipts, see [receipt]
+    rejected: Mutex<Rejections>,
+    // The workers processing requests, see [workers]
+    workers: Workers,
+    // The order requests arrive in, so requests changing the same account are processed in it
+    sequencer: Arc<Sequencer>,
+    // The audit log of a node started with `--audit-log`, see [audit]
+    audit: Option<Mutex<AuditLog>>,
+    // URLs notified of the activity of accounts, see [webhook]
+    webhooks: Mutex<Webhooks>,
+    // The faucet of a node started with `--enable-faucet`, faucet requests are rejected if not set
+    faucet: Option<Mutex<Faucet>>,
+    // The clock the node reads the time from, the simulated one if the node simulates, see [clock]
+    clock: Arc<dyn Clock>,
+    // The simulated time of a node started with `--simulate`, which mints when it is advanced, see [clock]
+    simulation: Option<Simulation>,
+    // The shard of a node started with `--shard`, requests about accounts of other shards are rejected if set
+    shard: Option<Shard>,
+}
 
-    match request.operation {
-        Operation::CreateAccount(account_info) => {
-            if state.account_exists(&account_info.account_id) {
-                return format!("⚠️ Account {} already exists.", &account_info.account_id);
-            };
+impl State {
+    /// Checks if an account exists in the ledger, i.e. if any transaction ever made involves it
+    fn account_exists(&self, account_id: &str) -> bool {
+        self.balances.lock().unwrap().contains_key(account_id)
+    }
+
+    /// Gets the balance of an account from the balance index
+    fn get_balance(&self, account_id: &str) -> Amount {
+        self.balances.lock().unwrap().get(account_id).copied().unwrap_or_default()
+    }
+
+    /// Gets the balance of an account from the balance index and the funds spent by its transactions in the mempool.
+    /// Funds the account receives with pending transactions don't count until they are minted, as they may be minted
+    /// after the account's own transactions.
+    fn get_pending_balance(&self, account_id: &str) -> (Amount, Amount) {
+        // Locked in the same order as the minter, so no transaction is counted in neither or both
+        let mempool = self.mempool.lock().unwrap();
+        (self.get_balance(account_id), mempool.spent_by(account_id))
+    }
+
+    /// Gets the balance of an account and the funds the transactions in the mempool credit it, None if they overflow
+    fn get_pending_credits(&self, account_id: &str) -> (Amount, Option<Amount>) {
+        let mempool = self.mempool.lock().unwrap();
+        (self.get_balance(account_id), mempool.received_by(account_id))
+    }
+
+    /// Gets the balance of an account in a token and the amount of the token spent by its transfers in the mempool,
+    /// like [State::get_pending_balance] for the native coin
+    fn get_pending_token_balance(&self, account_id: &str, token: &str) -> (Amount, Amount) {
+        let mempool = self.mempool.lock().unwrap();
+        (self.tokens.lock().unwrap().balance(token, account_id), mempool.token_spent_by(account_id, token))
+    }
+
+    /// Gets the maximum number of transactions in the blocks this node mints, decided by governance or given by the
+    /// genesis configuration, and lowered by the node's own limit if it has one
+    fn max_block_transactions(&self) -> usize {
+        let chain_limit = self.governance.lock().unwrap().parameters().max_block_transactions.unwrap_or(self.genesis.parameters.max_block_transactions);
+        self.max_block_txs.map_or(chain_limit, |node_limit| node_limit.min(chain_limit))
+    }
+
+    /// Gets the lowest fee a transfer has to pay, the higher of the node's minimum fee and the one decided by
+    /// governance
+    fn min_fee(&self) -> Amount {
+        self.governance.lock().unwrap().parameters().min_fee.map_or(self.min_fee, |min_fee| min_fee.max(self.min_fee))
+    }
+
+    /// Gets the seconds between blocks, decided by governance or given by the node's configuration
+    fn mint_interval(&self) -> u64 {
+        self.governance.lock().unwrap().parameters().mint_interval.unwrap_or(self.mint_interval)
+    }
+
+    /// Gets the keys and threshold of an account, None if it isn't a multisig account
+    fn multisig_policy(&self, account_id: &str) -> Option<MultisigPolicy> {
+        self.multisig.lock().unwrap().get(account_id).cloned()
+    }
+
+    /// Gets the nonce the next transfer from an account has to be made with
+    fn get_next_nonce(&self, account_id: &str) -> u64 {
+        self.nonces.lock().unwrap().get(account_id).copied().unwrap_or(0)
+    }
+
+    /// Adds a block minted by this node to the end of the ledger and applies its transactions to the balance index, and
+    /// to the verified state of the accounts if it is of the block before it
+    ///
+    /// Returns: why the block can't be added, if a balance would overflow, in which case nothing changed
+    fn add_block(&self, block: Block) -> Result<(), String> {
+        let mut ledger = self.ledger.write().unwrap();
+        self.append_block(&mut ledger, block)?;
+        let block = ledger.last().unwrap();
+        let mut verified = self.verified.lock().unwrap();
+        let followed = verified
+            .as_mut()
+            .filter(|(hash, _)| *hash == block.header.previous_hash)
+            .map(|(hash, accounts)| accounts.apply_block(ledger.len() - 1, block, self.genesis.parameters.max_supply).map(|_| *hash = block.hash.clone()));
+        if !matches!(followed, Some(Ok(()))) {
+            *verified = None;
+        }
+        Ok(())
+    }
 
-            let transaction = Transaction::new(request.from_node, None, account_info.account_id.clone(), account_info.starting_balance);
+    /// Appends a block to the locked ledger and applies its transactions to the balance index and the other indexes of
+    /// the minted blocks, publishing the accounts it changed
+    ///
+    /// Returns: why the block can't be added, if a balance would overflow, in which case nothing changed
+    fn append_block(&self, ledger: &mut Vec<Block>, block: Block) -> Result<(), String> {
+        let mut balances = self.balances.lock().unwrap();
+        block.apply_to_balances(&mut balances)?;
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.lock().unwrap().append(&block) {
+                error!("{}", e);
+            }
+        }
+        {
+            let mut multisig = self.multisig.lock().unwrap();
+            for (account_id, policy) in block.multisig_accounts() {
+                multisig.insert(account_id.clone(), policy.clone());
+            }
+            let mut stakes = self.stakes.lock().unwrap();
+            block.apply_to_stakes(&mut stakes);
+            block.apply_to_slashed(&mut self.slashed.lock().unwrap());
+            block.apply_to_tokens(&mut self.tokens.lock().unwrap());
+            block.apply_to_nfts(&mut self.nfts.lock().unwrap());
+            block.apply_to_escrows(&mut self.escrows.lock().unwrap());
+            block.apply_to_cross_shard(&mut self.cross_shard.lock().unwrap());
+            block.apply_to_schedules(&mut self.schedules.lock().unwrap());
+            block.apply_to_swaps(&mut self.swaps.lock().unwrap());
+            block.apply_to_contracts(&mut self.contracts.lock().unwrap());
+            block.apply_to_governance(&mut self.governance.lock().unwrap(), &balances, &stakes);
 
-            state.next_block_to_mint.lock().unwrap().transactions.push(transaction);
+            let height = ledger.len();
+            self.events.publish(Event::NewBlock {
+                height,
+                hash: block.hash.clone(),
+                transactions: block.transactions.len(),
+            });
+            for account_id in block.accounts() {
+                self.events.publish(Event::AccountChanged {
+                    account_id: account_id.clone(),
+                    balance: balances[account_id],
+                    height,
+                });
+            }
+        }
+        drop(balances);
+        self.index.lock().unwrap().add_block(&block);
+        ledger.push(block);
+        let height = ledger.len() - 1;
+        if let Err(e) = self.checkpoints.lock().unwrap().record(ledger, height) {
+            error!("{}", e);
+        }
+        match self.snapshots.lock().unwrap().record(ledger, height, self.genesis.parameters.max_supply) {
+            Ok(true) => {
+                self.rewrite_storage(ledger);
+                *self.index.lock().unwrap() = TransactionIndex::build(ledger);
+            }
+            Ok(false) => {}
+            Err(e) => error!("{}", e),
+        }
+        consistency::assert_consistent(self, ledger);
 
-            return format!("✅ Transaction to create account {} with balance {} committed.", &account_info.account_id, &account_info.starting_balance);
+        if ledger.len().is_multiple_of(BALANCE_CHECK_INTERVAL) {
+            self.check_balances(ledger);
         }
+        Ok(())
+    }
 
-        Operation::TransferFunds(transfer_info) => {
-            // Validate that the from and to accounts are different
-            if transfer_info.from_account_id == transfer_info.to_account_id {
-                return "❌ Cannot transfer funds to the same account.".to_string();
+    /// Restores the ledger from blocks loaded from storage, rebuilding the balance index, the transfer nonces, the
+    /// multisig accounts, the closed accounts, the frozen accounts, the stakes, the slashed validators, the aliases,
+    /// the tokens, the NFTs, the escrows, the cross-shard transfers, the payment schedules, the swap offers, the
+    /// contracts, the governance and the transaction index. If the blocks before the latest snapshot are pruned, they
+    /// are rebuilt from the snapshot and the blocks after it.
+    fn restore_ledger(&self, blocks: Vec<Block>) {
+        let mut ledger = self.ledger.write().unwrap();
+        let mut balances = self.balances.lock().unwrap();
+        let mut nonces = self.nonces.lock().unwrap();
+        let mut multisig = self.multisig.lock().unwrap();
+        let mut closed = self.closed.lock().unwrap();
+        let mut frozen = self.frozen.lock().unwrap();
+        let mut stakes = self.stakes.lock().unwrap();
+        let mut slashed = self.slashed.lock().unwrap();
+        let mut aliases = self.aliases.lock().unwrap();
+        let mut tokens = self.tokens.lock().unwrap();
+        let mut nfts = self.nfts.lock().unwrap();
+        let mut escrows = self.escrows.lock().unwrap();
+        let mut cross_shard = self.cross_shard.lock().unwrap();
+        let mut schedules = self.schedules.lock().unwrap();
+        let mut swaps = self.swaps.lock().unwrap();
+        let mut contracts = self.contracts.lock().unwrap();
+        let mut governance = self.governance.lock().unwrap();
+        let mut snapshots = self.snapshots.lock().unwrap();
+        let mut start = 0;
+        if let Some(snapshot) = snapshots.pruned() {
+            let accounts = snapshot.accounts.clone();
+            (*balances, *nonces, *multisig, *closed, *frozen, *stakes, *slashed, *aliases, *tokens, *nfts, *escrows, *cross_shard, *schedules, *swaps, *contracts, *governance) = (
+                accounts.balances,
+                accounts.nonces,
+                accounts.multisig,
+                accounts.closed,
+                accounts.frozen,
+                accounts.stakes,
+                accounts.slashed,
+                accounts.aliases,
+                accounts.tokens,
+                accounts.nfts,
+                accounts.escrows,
+                accounts.cross_shard,
+                accounts.schedules,
+                accounts.swaps,
+                accounts.contracts,
+                accounts.governance,
+            );
+            start = snapshot.height + 1;
+        }
+        for (height, block) in blocks.into_iter().enumerate() {
+            if height < start {
+                ledger.push(block);
+                continue;
+            }
+            block.apply_to_balances(&mut balances).expect("Balances of a verified chain overflowed");
+            for (account_id, policy) in block.multisig_accounts() {
+                multisig.insert(account_id.clone(), policy.clone());
+            }
+            block.apply_to_stakes(&mut stakes);
+            block.apply_to_slashed(&mut slashed);
+            for (alias, account_id) in block.aliases() {
+                aliases.insert(alias.clone(), account_id.clone());
+            }
+            block.apply_to_tokens(&mut tokens);
+            block.apply_to_nfts(&mut nfts);
+            block.apply_to_escrows(&mut escrows);
+            block.apply_to_cross_shard(&mut cross_shard);
+            block.apply_to_schedules(&mut schedules);
+            block.apply_to_swaps(&mut swaps);
+            block.apply_to_contracts(&mut contracts);
+            block.apply_to_governance(&mut governance, &balances, &stakes);
+            for transaction in &block.transactions {
+                if let (Some(from_account_id), Some(nonce)) = (&transaction.from_account_id, transaction.nonce) {
+                    nonces.insert(from_account_id.clone(), nonce + 1);
+                }
+                if let (Some(from_account_id), TransactionKind::Close) = (&transaction.from_account_id, &transaction.kind) {
+                    closed.insert(from_account_id.clone());
+                }
+                if let Some((freeze, signed_at)) = transaction.freezes() {
+                    frozen.apply(&transaction.to_account_id, freeze, signed_at);
+                }
             }
+            ledger.push(block);
+        }
+        if let Err(e) = self.checkpoints.lock().unwrap().record(&ledger, 0) {
+            error!("{}", e);
+        }
+        match snapshots.record(&mut ledger, 0, self.genesis.parameters.max_supply) {
+            Ok(true) => self.rewrite_storage(&ledger),
+            Ok(false) => {}
+            Err(e) => error!("{}", e),
+        }
+        *self.index.lock().unwrap() = TransactionIndex::build(&ledger);
+    }
 
-            // Validate that the from account has sufficient funds
-            let balance = state.get_balance(&transfer_info.from_account_id);
-            if balance < transfer_info.amount {
-                return format!("❌ Insufficient funds in account {} to transfer {}.", transfer_info.from_account_id, transfer_info.amount);
+    /// Recalculates all balances by checking all previous transactions ever made and compares them to the balance
+    /// index. If they differ the index is rebuilt from the ledger, as the ledger is the source of truth.
+    fn check_balances(&self, ledger: &[Block]) {
+        let calculated_balances = minted_indexes(ledger, self.snapshots.lock().unwrap().pruned()).balances;
+
+        let mut balances = self.balances.lock().unwrap();
+        if *balances != calculated_balances {
+            warn!("Balance index is inconsistent with the ledger, rebuilding it.");
+            *balances = calculated_balances;
+        } else {
+            info!("Balance index checked against {} blocks.", ledger.len());
+        }
+    }
+
+    /// Logs transactions accepted into the mempool to the write-ahead log. Called while holding the mempool, so the log
+    /// keeps the order of the mempool.
+    fn log_pending(&self, transactions: &[Transaction]) {
+        if let Some(wal) = &self.wal {
+            if let Err(e) = wal.lock().unwrap().append(transactions) {
+                error!("{}", e);
             }
+        }
+    }
 
-            let transaction = Transaction::new(request.from_node, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), transfer_info.amount);
+    /// Wakes the mint loop up to mint a block right away if the mempool reached the mint threshold. A wakeup while the
+    /// loop is minting is kept, so the loop mints again once it is done.
+    fn wake_minter(&self, mempool: &Mempool) {
+        if self.mint_threshold.is_some_and(|threshold| mempool.len() >= threshold) {
+            self.mint_wakeup.notify_one();
+        }
+    }
 
-            state.next_block_to_mint.lock().unwrap().transactions.push(transaction);
-            return format!("✅ Transaction to transfer {} from {} to {} committed.", transfer_info.amount, &transfer_info.from_account_id, &transfer_info.to_account_id);
+    /// Truncates the write-ahead log to the transactions in the mempool, after the blocks minting the others are
+    /// flushed to the ledger file. If they can't be flushed the log is kept, as the blocks may be lost in a crash.
+    fn truncate_wal(&self, mempool: &Mempool) {
+        let Some(wal) = &self.wal else {
+            return;
+        };
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.lock().unwrap().sync() {
+                error!("{}", e);
+                return;
+            }
         }
+        if let Err(e) = wal.lock().unwrap().truncate(mempool.transactions()) {
+            error!("{}", e);
+        }
+    }
 
-        Operation::GetFunds(get_info) => {
-            let balance = state.get_balance(&get_info.account_id);
-            return format!("Account {} has a balance of {}.", get_info.account_id, balance);
+    /// Replays the transactions of the write-ahead log into the mempool after a restart. They are validated against the
+    /// restored chain like relayed transactions, so those that were minted before the node stopped are dropped.
+    fn replay_pending(&self, transactions: Vec<Transaction>) {
+        if transactions.is_empty() {
+            return;
+        }
+        // Locked in the same order as transfers and relayed transactions
+        let mut nonces = self.nonces.lock().unwrap();
+        let mut mempool = self.mempool.lock().unwrap();
+        let ledger = self.ledger.read().unwrap();
+        let snapshots = self.snapshots.lock().unwrap();
+        let mut accounts = match verify::verify_chain_with(&ledger, &self.genesis, snapshots.pruned(), |_, _, _| Ok(())) {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                error!("Can't replay the write-ahead log, the chain of this node isn't valid. {}", e);
+                return;
+            }
+        };
+        let height = ledger.len() as u64;
+        let now = self.clock.unix_time();
+        let logged = transactions.len();
+        let kept: Vec<Transaction> = transactions
+            .into_iter()
+            .filter(|transaction| match accounts.apply_pending(transaction, height, now) {
+                Ok(()) => true,
+                Err(reason) => {
+                    debug!(transaction = %transaction.id(), "Dropping logged transaction, it {}.", reason);
+                    false
+                }
+            })
+            .collect();
+        // The nonces, closed and frozen accounts and aliases include the pending transactions
+        *nonces = accounts.nonces;
+        *self.closed.lock().unwrap() = accounts.closed;
+        *self.frozen.lock().unwrap() = accounts.frozen;
+        *self.aliases.lock().unwrap() = accounts.aliases;
+        info!("Replayed {} pending transactions from the write-ahead log, {} were minted already or are no longer valid.", kept.len(), logged - kept.len());
+        mempool.restore(kept);
+        drop((snapshots, ledger));
+        self.truncate_wal(&mempool);
+    }
+
+    /// Replaces the blocks in the ledger file, e.g. after switching to a fork or pruning blocks
+    fn rewrite_storage(&self, ledger: &[Block]) {
+        if let Some(storage) = &self.storage {
+            if let Err(e) = storage.lock().unwrap().rewrite(ledger) {
+                error!("{}", e);
+            }
+        }
+    }
+
+    /// Adds a block minted by another node. A block that builds on a known block is kept as part of a fork, and the
+    /// longest chain is the main chain, the node staying on its own chain if a fork is only as long. Once a fork is
+    /// longer, the node switches to it: the blocks after the fork point are rolled back and kept as a fork in turn, the
+    /// fork's blocks are applied, and the transactions of the rolled back blocks go back into the mempool if they are
+    /// still valid on the new chain, like the transactions already in the mempool.
+    ///
+    /// Returns: a user-facing message, either saying where the block was added or explaining why it was rejected
+    fn receive_block(&self, block: Block) -> Result<String, String> {
+        self.receive_blocks(vec![block])
+    }
+
+    /// Adds a run of blocks minted by other nodes, oldest first, each building on the one before, like
+    /// [State::receive_block] adds a single block but checking the run only once, e.g. a batch when syncing. Blocks
+    /// that build on an unknown block are kept as orphans, and the orphans that build on the added blocks are added
+    /// after them.
+    ///
+    /// Returns: a user-facing message, either saying where the last block was added or explaining why it was rejected
+    fn receive_blocks(&self, blocks: Vec<Block>) -> Result<String, String> {
+        let result = self.add_blocks(blocks);
+        if result.is_ok() {
+            self.connect_orphans();
+        }
+        result
+    }
+
+    /// Adds the orphans whose parents are known now, see [orphan]
+    fn connect_orphans(&self) {
+        loop {
+            let run = {
+                let ledger = self.ledger.read().unwrap();
+                let forks = self.forks.lock().unwrap();
+                self.orphans.lock().unwrap().take_connectable(|hash| forks.contains(hash) || ledger.iter().any(|known| known.hash == hash))
+            };
+            let Some(hash) = run.last().map(|block| block.hash.clone()) else {
+                break;
+            };
+            match self.add_blocks(run) {
+                Ok(message) => info!(%hash, "Connected orphan blocks. {}", message.trim_start_matches("✅ ")),
+                Err(e) => warn!(%hash, "Dropping orphan blocks. {}", e.trim_start_matches("❌ ")),
+            }
+        }
+    }
+
+    /// Adds a run of blocks like [State::receive_blocks], without connecting the orphans building on them
+    fn add_blocks(&self, blocks: Vec<Block>) -> Result<String, String> {
+        for block in &blocks {
+            let hash = block.header.hash();
+            if block.hash != hash {
+                return Err(format!("❌ Block {} doesn't match its header, which hashes to {}.", block.hash, hash));
+            }
+        }
+        let Some(hash) = blocks.last().map(|block| block.hash.clone()) else {
+            return Err("❌ No blocks to add.".to_string());
+        };
+
+        // Locked in the same order as transfers and the minter, so no transaction is accepted or minted while the
+        // chain changes
+        let mut nonces = self.nonces.lock().unwrap();
+        let mut mempool = self.mempool.lock().unwrap();
+        let mut ledger = self.ledger.write().unwrap();
+        let mut forks = self.forks.lock().unwrap();
+        let mut snapshots = self.snapshots.lock().unwrap();
+        if forks.contains(&hash) || ledger.iter().any(|known| known.hash == hash) {
+            return Ok(format!("⚠️ Block {} is known already.", hash));
+        }
+        // The blocks added to the forks, which are removed again if the chain they end is rejected
+        let mut added = Vec::new();
+        for block in blocks {
+            if forks.contains(&block.hash) || ledger.iter().any(|known| known.hash == block.hash) {
+                continue;
+            }
+            // A block signed for a validator that signed another block at the same height proves that it minted
+            // competing chains, whether or not the block turns out to be valid otherwise
+            if let Some(evidence) = ledger.get(block.header.height as usize).into_iter().chain(forks.at_height(block.header.height)).find_map(|known| Evidence::of(&block, known)) {
+                self.report_evidence(evidence);
+            }
+            added.push(block.hash.clone());
+            forks.add(block);
+        }
+        let discard = |forks: &mut Forks| {
+            for hash in &added {
+                forks.remove(hash);
+            }
+        };
+
+        let (branch, fork_point) = forks.branch(&hash);
+        let Some(fork_height) = ledger.iter().position(|known| known.hash == fork_point).map(|height| height + 1) else {
+            // Kept until the missing block arrives, which the sync asks the peers for
+            let mut orphans = self.orphans.lock().unwrap();
+            for hash in &added {
+                if let Some(block) = forks.remove(hash) {
+                    orphans.add(block);
+                }
+            }
+            info!(%hash, parent = %fork_point, orphans = orphans.len(), "Kept block of another node as an orphan, its parent is unknown.");
+            return Ok(format!("⚠️ Block {} is kept as an orphan, it builds on block {} which this node doesn't know yet. It is added once that block arrives.", hash, fork_point));
+        };
+        if let Some(checkpoint) = self.checkpoints.lock().unwrap().conflicting(fork_height) {
+            discard(&mut forks);
+            return Err(format!(
+                "❌ Block {} is rejected, it builds on block {} at height {}, but block {} at height {} is finalized by a checkpoint.",
+                hash,
+                fork_point,
+                fork_height - 1,
+                checkpoint.hash,
+                checkpoint.height
+            ));
+        }
+        // The transactions a fork would roll back to before the snapshot are pruned
+        if let Some(snapshot) = snapshots.pruned().filter(|snapshot| fork_height <= snapshot.height) {
+            discard(&mut forks);
+            return Err(format!(
+                "❌ Block {} is rejected, it builds on block {} at height {}, but the blocks up to the snapshot at height {} are pruned.",
+                hash,
+                fork_point,
+                fork_height - 1,
+                snapshot.height
+            ));
+        }
+        let length = fork_height + branch.len();
+        if length <= ledger.len() {
+            return Ok(format!("✅ Block {} added to a fork at height {}, the main chain with {} blocks is at least as long.", hash, length - 1, ledger.len()));
+        }
+        // Blocks extending the tip are verified on top of it, only a switch to a fork replays the chain
+        if fork_height == ledger.len() {
+            let blocks: Vec<Block> = branch.into_iter().cloned().collect();
+            for block in &blocks {
+                forks.remove(&block.hash);
+            }
+            drop(forks);
+            drop(snapshots);
+            return self.extend_tip(&mut nonces, &mut mempool, &mut ledger, blocks);
+        }
+
+        let chain: Vec<Block> = ledger[..fork_height].iter().cloned().chain(branch.into_iter().cloned()).collect();
+        let now = self.clock.unix_time();
+        let check_block = |height: usize, block: &Block, accounts: &verify::Accounts| match height < fork_height {
+            true => Ok(()),
+            false => self.check_received(block, &chain[height - 1], accounts, now),
+        };
+        let accounts = match verify::verify_chain_with(&chain, &self.genesis, snapshots.pruned(), check_block) {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                discard(&mut forks);
+                return Err(format!("❌ Block {} is rejected, the chain it ends isn't valid. {}", hash, e));
+            }
+        };
+        *self.verified.lock().unwrap() = Some((hash.clone(), accounts.clone()));
+
+        let rolled_back: Vec<Block> = ledger.drain(fork_height..).collect();
+        let mut index = self.index.lock().unwrap();
+        index.roll_back(fork_height, &rolled_back);
+        // Block rewards, staking rewards, scheduled payments and slashes are made by the minter of a block, so they
+        // don't go back, the evidence of the slashes goes back to the evidence to slash instead
+        for evidence in rolled_back.iter().flat_map(|block| &block.transactions).filter_map(Transaction::slashes) {
+            self.report_evidence(evidence.clone());
+        }
+        let pending: Vec<Transaction> = rolled_back
+            .iter()
+            .flat_map(|block| {
+                block.transactions.iter().filter(|transaction| !transaction.is_reward() && !transaction.is_staking_reward() && transaction.pays_schedule().is_none() && transaction.slashes().is_none()).cloned()
+            })
+            .chain(mempool.take_top(usize::MAX, None))
+            .collect();
+        let (restored, dropped) = self.restore_pending(accounts, pending, &chain, fork_height, &mut nonces, &mut mempool, now);
+
+        for block in &chain[fork_height..] {
+            forks.remove(&block.hash);
+        }
+        // The accounts of the replaced blocks changed too, as their transactions were rolled back
+        let mut changed: Vec<String> = Vec::new();
+        for account_id in rolled_back.iter().chain(&chain[fork_height..]).flat_map(Block::accounts) {
+            if !changed.contains(account_id) {
+                changed.push(account_id.clone());
+            }
+        }
+        let replaced = rolled_back.len();
+        for block in rolled_back {
+            forks.add(block);
+        }
+        *ledger = chain;
+        for block in &ledger[fork_height..] {
+            index.add_block(block);
+        }
+        match snapshots.record(&mut ledger, fork_height, self.genesis.parameters.max_supply) {
+            Ok(true) => *index = TransactionIndex::build(&ledger),
+            Ok(false) => {}
+            Err(e) => error!("{}", e),
+        }
+        drop(index);
+        self.rewrite_storage(&ledger);
+        self.truncate_wal(&mempool);
+        if let Err(e) = self.checkpoints.lock().unwrap().record(&ledger, fork_height) {
+            error!("{}", e);
+        }
+
+        // The balance index, multisig accounts, stakes, tokens, NFTs, escrows, cross-shard transfers, schedules, swap
+        // offers, contracts and governance only cover minted blocks, the nonces, closed and frozen accounts and aliases
+        // include the pending transactions, see State::restore_pending
+        let indexes = minted_indexes(&ledger, snapshots.pruned());
+        *self.balances.lock().unwrap() = indexes.balances;
+        *self.multisig.lock().unwrap() = indexes.multisig;
+        *self.stakes.lock().unwrap() = indexes.stakes;
+        *self.slashed.lock().unwrap() = indexes.slashed;
+        *self.tokens.lock().unwrap() = indexes.tokens;
+        *self.nfts.lock().unwrap() = indexes.nfts;
+        *self.escrows.lock().unwrap() = indexes.escrows;
+        *self.cross_shard.lock().unwrap() = indexes.cross_shard;
+        *self.schedules.lock().unwrap() = indexes.schedules;
+        *self.swaps.lock().unwrap() = indexes.swaps;
+        *self.contracts.lock().unwrap() = indexes.contracts;
+        *self.governance.lock().unwrap() = indexes.governance;
+
+        for (height, block) in ledger.iter().enumerate().skip(fork_height) {
+            self.events.publish(Event::NewBlock {
+                height,
+                hash: block.hash.clone(),
+                transactions: block.transactions.len(),
+            });
+        }
+        let balances = self.balances.lock().unwrap();
+        for account_id in changed {
+            let balance = balances.get(&account_id).copied().unwrap_or_default();
+            self.events.publish(Event::AccountChanged { account_id, balance, height: length - 1 });
+        }
+        drop(balances);
+        warn!(%hash, fork_height, replaced, added = length - fork_height, restored, dropped, "Switched to a longer fork.");
+        Ok(format!(
+            "✅ Block {} added at height {} and switched to its fork: the {} blocks after height {} were replaced by {}, {} transactions of the old chain and the mempool are pending and {} were dropped.",
+            hash,
+            length - 1,
+            replaced,
+            fork_height - 1,
+            length - fork_height,
+            restored,
+            dropped
+        ))
+    }
+
+    /// Adds blocks of another node extending the tip of the locked ledger, verifying them on top of the accounts of the
+    /// tip and applying them one by one, without replaying the chain
+    ///
+    /// Returns: why the blocks are rejected, if one of them isn't valid, in which case none of them is added
+    fn extend_tip(&self, nonces: &mut HashMap<String, u64>, mempool: &mut Mempool, ledger: &mut Vec<Block>, blocks: Vec<Block>) -> Result<String, String> {
+        let tip = ledger.last().unwrap().hash.clone();
+        let verified = match self.verified.lock().unwrap().take().filter(|(hash, _)| *hash == tip) {
+            Some((_, accounts)) => accounts,
+            None => verify::verify_chain_with(ledger, &self.genesis, self.snapshots.lock().unwrap().pruned(), |_, _, _| Ok(()))?,
+        };
+        let hash = blocks.last().unwrap().hash.clone();
+        let now = self.clock.unix_time();
+        let mut accounts = verified.clone();
+        let mut previous = ledger.last().unwrap();
+        for block in &blocks {
+            let check = |_: usize, block: &Block, accounts: &verify::Accounts| self.check_received(block, previous, accounts, now);
+            if let Err(e) = verify::verify_next_block(block, previous, &self.genesis, &mut accounts, check) {
+                *self.verified.lock().unwrap() = Some((tip, verified));
+                return Err(format!("❌ Block {} is rejected, the chain it ends isn't valid. {}", hash, e));
+            }
+            previous = block;
+        }
+
+        let start = ledger.len();
+        for block in blocks {
+            self.append_block(ledger, block)?;
+        }
+        *self.verified.lock().unwrap() = Some((hash.clone(), accounts.clone()));
+
+        let pending = mempool.take_top(usize::MAX, None);
+        let (restored, dropped) = self.restore_pending(accounts, pending, ledger, start, nonces, mempool, now);
+        self.truncate_wal(mempool);
+        let height = ledger.len() - 1;
+        info!(%hash, height, restored, dropped, "Block of another node added.");
+        Ok(format!("✅ Block {} added to the main chain at height {}.", hash, height))
+    }
+
+    /// Checks a block of another node against the block before it: its timestamp has to go forward and can't be too far
+    /// ahead of this node's clock, and in proof-of-stake mode it has to be minted by the validator of its slot, and
+    /// signed by it if that is a multisig account
+    fn check_received(&self, block: &Block, previous: &Block, accounts: &verify::Accounts, now: u64) -> Result<(), String> {
+        block.check_timestamp(previous, now, self.max_clock_skew)?;
+        if self.consensus == Consensus::Pos {
+            stake::check_validator(block, &accounts.stakes, accounts.governance.parameters().mint_interval.unwrap_or(self.mint_interval))?;
+            slashing::check_signed(block, &accounts.multisig)?;
+        }
+        Ok(())
+    }
+
+    /// Puts the pending transactions back into the locked mempool on top of the accounts of the new chain, skipping the
+    /// ones minted in its blocks from the given height and dropping the ones that don't apply anymore, and takes over
+    /// the nonces, closed and frozen accounts and aliases of the accounts with the pending transactions
+    ///
+    /// Returns: how many transactions are pending again and how many were dropped
+    #[allow(clippy::too_many_arguments)]
+    fn restore_pending(&self, mut accounts: verify::Accounts, pending: Vec<Transaction>, chain: &[Block], from: usize, nonces: &mut HashMap<String, u64>, mempool: &mut Mempool, now: u64) -> (usize, usize) {
+        let minted: HashSet<String> = chain[from..].iter().flat_map(|block| Block::transaction_ids(&block.transactions)).collect();
+        let height = chain.len() as u64;
+        let (mut kept, mut dropped) = (Vec::new(), 0);
+        for transaction in pending.into_iter().filter(|transaction| !minted.contains(&transaction.id())) {
+            match accounts.apply_pending(&transaction, height, now) {
+                Ok(()) => kept.push(transaction),
+                Err(reason) => {
+                    debug!("Dropping transaction {} after the chain changed, it {}.", transaction.id(), reason);
+                    match transaction.valid_until().filter(|valid_until| valid_until.has_passed(height, now)) {
+                        Some(valid_until) => self.rejected.lock().unwrap().expire(transaction.id(), format!("valid until {} but not minted before block {}", valid_until, height)),
+                        None => self.rejected.lock().unwrap().record(transaction.id(), format!("dropped after the chain changed as it {}", reason)),
+                    }
+                    dropped += 1;
+                }
+            }
+        }
+        let restored = kept.len();
+        mempool.restore(kept);
+        *nonces = accounts.nonces;
+        *self.closed.lock().unwrap() = accounts.closed;
+        *self.frozen.lock().unwrap() = accounts.frozen;
+        *self.aliases.lock().unwrap() = accounts.aliases;
+        (restored, dropped)
+    }
+
+    /// Returns the validator key if it is one of the keys of the reward account, which the blocks minted by this node
+    /// are signed with
+    fn signing_key(&self) -> Option<&SigningKey> {
+        let key = self.validator_key.as_ref()?;
+        let multisig = self.multisig.lock().unwrap();
+        multisig.get(&self.reward_account).filter(|policy| policy.public_keys.contains(&keys::public_key_hex(key))).map(|_| key)
+    }
+
+    /// Keeps evidence of conflicting blocks to slash in the next block this node mints, unless it doesn't prove an
+    /// offence or the offence is known already
+    fn report_evidence(&self, evidence: Evidence) {
+        if let Err(e) = evidence.check(&self.multisig.lock().unwrap()) {
+            debug!("Ignoring evidence against validator {}, {}.", evidence.validator(), e);
+            return;
+        }
+        let mut pool = self.evidence.lock().unwrap();
+        if pool.iter().any(|known| known.validator() == evidence.validator() && known.height() == evidence.height()) {
+            return;
+        }
+        warn!(validator = evidence.validator(), height = evidence.height(), "Validator signed conflicting blocks.");
+        pool.push(evidence);
+    }
+
+    /// Finds a transaction by its ID, first in the ledger and then in the mempool
+    ///
+    /// Returns: the transaction and the height and hash of the block it was minted in, if it was minted already
+    fn find_transaction(&self, transaction_id: &str) -> Option<(Transaction, Option<(usize, String)>)> {
+        // Lock in the same order as the minter, so a transaction can't move to the ledger while it is searched for
+        let mempool = self.mempool.lock().unwrap();
+        let ledger = self.ledger.read().unwrap();
+
+        if let Some((height, position)) = self.locate_transaction(transaction_id) {
+            let block = &ledger[height];
+            return Some((block.transactions[position].clone(), Some((height, block.hash.clone()))));
+        }
+
+        let pending = mempool.transactions().find(|transaction| transaction.id() == transaction_id).cloned();
+        pending.map(|transaction| (transaction, None))
+    }
+
+    /// Finds a minted transaction on the main chain by its ID in the transaction index, called while holding the
+    /// ledger so the location stays valid
+    ///
+    /// Returns: the height of its block and its index in the block
+    fn locate_transaction(&self, transaction_id: &str) -> Option<index::Location> {
+        self.index.lock().unwrap().locate(transaction_id)
+    }
+
+    /// Finds a block by its height, or by its hash if no height is given
+    ///
+    /// Returns: the height and the block
+    fn find_block(&self, height: Option<usize>, hash: Option<&str>) -> Option<(usize, Block)> {
+        let ledger = self.ledger.read().unwrap();
+        match (height, hash) {
+            (Some(height), _) => ledger.get(height).map(|block| (height, block.clone())),
+            (None, Some(hash)) => ledger.iter().enumerate().find(|(_, block)| block.hash == hash).map(|(height, block)| (height, block.clone())),
+            (None, None) => None,
+        }
+    }
+
+    /// Gets a page of the minted transactions involving an account that match a filter, most recent first
+    ///
+    /// Returns: the transactions on the page with the height and hash of their block, and the total number of
+    /// matching transactions
+    fn get_history(&self, filter: &TransactionFilter, page: usize, limit: usize) -> (Vec<(usize, String, Transaction)>, usize) {
+        let ledger = self.ledger.read().unwrap();
+        let skip = (page - 1) * limit;
+        let mut transactions = Vec::new();
+        let mut total = 0;
+
+        let heights = filter.heights(ledger.len() - 1);
+        let index = self.index.lock().unwrap();
+        for &(height, position) in index.of_account(&filter.account_id).iter().rev() {
+            let (block, transaction) = (&ledger[height], &ledger[height].transactions[position]);
+            if !heights.contains(&height) || !filter.matches(block.header.timestamp, transaction) {
+                continue;
+            }
+            if total >= skip && transactions.len() < limit {
+                transactions.push((height, block.hash.clone(), transaction.clone()));
+            }
+            total += 1;
+        }
+        (transactions, total)
+    }
+
+    /// Adds a validated transaction to the mempool and notifies subscribers of it
+    ///
+    /// Returns: the ID of the transaction, or a user-facing error if the mempool rejected it
+    fn submit_transaction(&self, transaction: Transaction) -> Result<String, String> {
+        Ok(self.submit_transactions(vec![transaction])?.remove(0))
+    }
+
+    /// Adds validated transactions to the mempool, all of them or none, notifies subscribers of them and queues them to
+    /// be relayed to the peers
+    ///
+    /// Returns: the IDs of the transactions, or a user-facing error if the mempool rejected them
+    fn submit_transactions(&self, transactions: Vec<Transaction>) -> Result<Vec<String>, String> {
+        let transaction_ids: Vec<String> = transactions.iter().map(Transaction::id).collect();
+        {
+            // Checked while holding the mempool, so no transaction is added after the final block was minted
+            let mut mempool = self.mempool.lock().unwrap();
+            if self.shutting_down.load(Ordering::SeqCst) {
+                return Err("❌ The node is shutting down and doesn't accept new transactions.".to_string());
+            }
+            self.check_issuance(&mempool, &transactions)?;
+            mempool.add_all(transactions.clone())?;
+            self.log_pending(&transactions);
+            self.wake_minter(&mempool);
+        }
+        self.gossip.lock().unwrap().relay(&transactions);
+        for (transaction, id) in transactions.into_iter().zip(&transaction_ids) {
+            self.events.publish(Event::NewTransaction { id: id.clone(), transaction: Box::new(transaction) });
+        }
+        Ok(transaction_ids)
+    }
+
+    /// Checks that an account can be created: it isn't one of the accounts reserved for the funds the chain holds, an
+    /// alias or an account that exists already
+    ///
+    /// Returns: a user-facing error if it can't
+    fn check_new_account(&self, account_id: &str) -> Result<(), String> {
+        let reserved = [
+            (STAKE_ACCOUNT, "the staked funds"),
+            (ESCROW_ACCOUNT, "the escrowed funds"),
+            (SLASHED_ACCOUNT, "the slashed stakes"),
+            (CROSS_SHARD_ACCOUNT, "the funds moving between shards"),
+        ];
+        if let Some((_, funds)) = reserved.iter().find(|(reserved, _)| *reserved == account_id) {
+            return Err(format!("❌ Account {} is reserved for {}.", account_id, funds));
+        }
+        self.check_not_alias(account_id)?;
+        if self.account_exists(account_id) {
+            return Err(format!("⚠️ Account {} already exists.", account_id));
+        }
+        Ok(())
+    }
+
+    /// Validates an account creation and adds its transaction to the mempool
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn create_account(&self, node_id: String, account_info: &AccountCreationOp) -> Result<String, String> {
+        self.check_new_account(&account_info.account_id)?;
+
+        let transaction = Transaction::new(node_id, None, account_info.account_id.clone(), account_info.starting_balance, Amount::ZERO, None, self.clock.now(), None);
+        let transaction_id = self.submit_transaction(transaction)?;
+
+        Ok(format!("✅ Transaction {} to create account {} with balance {} committed.", transaction_id, &account_info.account_id, &account_info.starting_balance))
+    }
+
+    /// Validates the creation of a multisig account and adds its transaction to the mempool
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn create_multisig_account(&self, node_id: String, account_info: &MultisigAccountCreationOp) -> Result<String, String> {
+        self.check_new_account(&account_info.account_id)?;
+        let policy = MultisigPolicy::new(&account_info.public_keys, account_info.threshold).map_err(|e| format!("❌ Invalid multisig account: {}.", e))?;
+
+        let (threshold, keys) = (policy.threshold, policy.public_keys.len());
+        let transaction = Transaction {
+            kind: TransactionKind::CreateMultisig(policy),
+            ..Transaction::new(node_id, None, account_info.account_id.clone(), account_info.starting_balance, Amount::ZERO, None, self.clock.now(), None)
+        };
+        let transaction_id = self.submit_transaction(transaction)?;
+
+        Ok(format!("✅ Transaction {} to create multisig account {} ({} of {} keys) with balance {} committed.", transaction_id, &account_info.account_id, threshold, keys, &account_info.starting_balance))
+    }
+
+    /// Validates a transfer of funds and adds its transaction to the mempool. Transfers from multisig accounts wait for
+    /// the approvals of the account's keys instead.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn transfer_funds(&self, node_id: String, transfer_info: &FundTransferOp) -> Result<String, String> {
+        let (transfer_info, recipient) = &self.resolve_recipient(transfer_info);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        if let Some(shard) = self.shard.as_ref().and_then(|shard| shard.foreign_shard(&transfer_info.to_account_id)) {
+            return self.lock_cross_shard(node_id, transfer_info, shard);
+        }
+        // The nonces are locked until the transfer is added so that no other transfer can use the same nonce in between
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), transfer_info.amount, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone());
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!("✅ Transaction {} to transfer {} from {} to {} committed.", transaction_id, transfer_info.amount, &transfer_info.from_account_id, recipient))
+    }
+
+    /// Validates a transfer of funds to an account of another shard and adds the transaction locking them in the
+    /// cross-shard account to the mempool, or waits for the approvals of the account's keys if it is a multisig
+    /// account. The node asks the recipient's shard to credit the transfer once it is minted, and refunds it if it
+    /// isn't credited until the deadline, see [cross_shard].
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn lock_cross_shard(&self, node_id: String, transfer_info: &FundTransferOp, shard: usize) -> Result<String, String> {
+        if transfer_info.amount <= Amount::ZERO {
+            return Err(format!("❌ Invalid amount {}, a transfer to another shard has to move more than 0.", transfer_info.amount));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let deadline = self.clock.unix_time() + CROSS_SHARD_TIMEOUT;
+        let transaction = Transaction {
+            kind: TransactionKind::CrossShardLock { recipient: transfer_info.to_account_id.clone(), shard, deadline },
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), CROSS_SHARD_ACCOUNT.to_string(), transfer_info.amount, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone())
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to transfer {} from {} to {} on shard {} committed, it is credited on shard {} once it is minted, or refunded if it isn't credited until {}.",
+            transaction_id, transfer_info.amount, &transfer_info.from_account_id, transfer_info.to_account_id, shard, shard, deadline
+        ))
+    }
+
+    /// Validates a transfer of funds that expires if it isn't minted until its timelock and adds its transaction to the
+    /// mempool, or waits for the approvals of the account's keys if it is a multisig account. An expired transfer is
+    /// dropped from the mempool by the minter, see [drop_expired].
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn expiring_transfer(&self, node_id: String, expiring_info: &ExpiringTransferOp) -> Result<String, String> {
+        let (transfer_info, recipient) = &self.resolve_recipient(&expiring_info.transfer);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        if expiring_info.valid_until.has_passed(next_height, self.clock.unix_time()) {
+            return Err(format!("❌ The transfer would expire at {} before it is minted, it is minted at height {} at the earliest.", expiring_info.valid_until, next_height));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::ExpiringTransfer { valid_until: expiring_info.valid_until },
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), transfer_info.amount, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone())
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to transfer {} from {} to {} committed, it expires if it isn't minted until {}.",
+            transaction_id, transfer_info.amount, &transfer_info.from_account_id, recipient, expiring_info.valid_until
+        ))
+    }
+
+    /// Validates a transfer of funds like `transfer_funds`, without making it
+    ///
+    /// Returns: a user-facing message, either saying that the transfer would be accepted or explaining why it would be
+    /// rejected
+    fn simulate_transfer(&self, transfer_info: &FundTransferOp) -> Result<String, String> {
+        let (transfer_info, recipient) = &self.resolve_recipient(transfer_info);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        let nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let outcome = match self.multisig_policy(&transfer_info.from_account_id) {
+            Some(policy) => format!("wait for the approval of {} of the {} keys of the account", policy.threshold, policy.public_keys.len()),
+            None => "be committed".to_string(),
+        };
+        Ok(format!("✅ The transfer of {} from {} to {} is valid and would {}, it wasn't made (dry run).", transfer_info.amount, &transfer_info.from_account_id, recipient, outcome))
+    }
+
+    /// Validates the staking of funds of an account and adds its transaction to the mempool, or waits for the approvals
+    /// of the account's keys if it is a multisig account. The funds move to the staking account like a transfer, and
+    /// once the transaction is minted the account is a validator with the amount added to its stake.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn stake(&self, node_id: String, stake_info: &StakeOp) -> Result<String, String> {
+        if stake_info.amount <= Amount::ZERO {
+            return Err(format!("❌ Invalid stake {}, the stake has to be more than 0.", stake_info.amount));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&stake_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", stake_info.account_id));
+        }
+        let transfer_info = FundTransferOp {
+            from_account_id: stake_info.account_id.clone(),
+            to_account_id: STAKE_ACCOUNT.to_string(),
+            amount: stake_info.amount,
+            fee: stake_info.fee,
+            nonce: stake_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&stake_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::Stake,
+            ..Transaction::new(node_id, Some(stake_info.account_id.clone()), STAKE_ACCOUNT.to_string(), stake_info.amount, stake_info.fee, Some(stake_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&stake_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(stake_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!("✅ Transaction {} to stake {} of account {} committed, it validates blocks once the transaction is minted.", transaction_id, stake_info.amount, stake_info.account_id))
+    }
+
+    /// Validates the unstaking of funds staked by an account and adds its transaction to the mempool, or waits for the
+    /// approvals of the account's keys if it is a multisig account. Once the transaction is minted, the amount moves
+    /// from the staking account back to the account and is taken off its stake.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn unstake(&self, node_id: String, unstake_info: &StakeOp) -> Result<String, String> {
+        if unstake_info.amount <= Amount::ZERO {
+            return Err(format!("❌ Invalid amount {}, the amount to unstake has to be more than 0.", unstake_info.amount));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&unstake_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", unstake_info.account_id));
+        }
+        // Validated like a transfer of nothing to the staking account, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: unstake_info.account_id.clone(),
+            to_account_id: STAKE_ACCOUNT.to_string(),
+            amount: Amount::ZERO,
+            fee: unstake_info.fee,
+            nonce: unstake_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&unstake_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+        self.check_unstake(&unstake_info.account_id, unstake_info.amount)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::Unstake,
+            ..Transaction::new(node_id, Some(unstake_info.account_id.clone()), unstake_info.account_id.clone(), unstake_info.amount, unstake_info.fee, Some(unstake_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&unstake_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(unstake_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to unstake {} of account {} committed, the funds can be spent once the transaction is minted.",
+            transaction_id, unstake_info.amount, unstake_info.account_id
+        ))
+    }
+
+    /// Checks that an account has the given amount staked, on top of what its pending transactions unstake
+    ///
+    /// Returns: a user-facing error if it doesn't
+    fn check_unstake(&self, account_id: &str, amount: Amount) -> Result<(), String> {
+        let stake = self.stakes.lock().unwrap().get(account_id).copied().unwrap_or_default();
+        let pending: Amount = self
+            .mempool
+            .lock()
+            .unwrap()
+            .transactions()
+            .filter(|transaction| transaction.kind == TransactionKind::Unstake && transaction.from_account_id.as_deref() == Some(account_id))
+            .map(|transaction| transaction.amount)
+            .sum();
+        if amount.checked_add(pending).is_none_or(|total| stake < total) {
+            return Err(format!("❌ Account {} can't unstake {}, it has a stake of {} of which its pending transactions unstake {}.", account_id, amount, stake, pending));
+        }
+        Ok(())
+    }
+
+    /// Checks that a transfer doesn't move funds to the staking or escrow account, which only staking and escrow
+    /// transfers do
+    ///
+    /// Returns: a user-facing error if it does
+    fn check_not_reserved(to_account_id: &str) -> Result<(), String> {
+        if to_account_id == STAKE_ACCOUNT {
+            return Err(format!("❌ Funds only move to the staking account {} by staking them with `stake`.", STAKE_ACCOUNT));
+        }
+        if to_account_id == ESCROW_ACCOUNT {
+            return Err(format!("❌ Funds only move to the escrow account {} with `escrow`.", ESCROW_ACCOUNT));
+        }
+        if to_account_id == SLASHED_ACCOUNT {
+            return Err(format!("❌ Funds only move to the slashed account {} by slashing validators.", SLASHED_ACCOUNT));
+        }
+        if to_account_id == CROSS_SHARD_ACCOUNT {
+            return Err(format!("❌ Funds only move to the cross-shard account {} by transfers to other shards.", CROSS_SHARD_ACCOUNT));
+        }
+        Ok(())
+    }
+
+    /// Validates the registration of an alias for an account and adds its transaction to the mempool, or waits for the
+    /// approvals of the account's keys if it is a multisig account. Aliases are first come, first served, an alias that
+    /// is registered already, even by a pending transaction, can't be registered again.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn register_alias(&self, node_id: String, alias_info: &RegisterAliasOp) -> Result<String, String> {
+        validate_alias(&alias_info.alias)?;
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&alias_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", alias_info.account_id));
+        }
+        self.check_alias_available(&alias_info.alias)?;
+        // Validated like a transfer of nothing to the alias, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: alias_info.account_id.clone(),
+            to_account_id: alias_info.alias.clone(),
+            amount: Amount::ZERO,
+            fee: alias_info.fee,
+            nonce: alias_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&alias_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::RegisterAlias(alias_info.alias.clone()),
+            ..Transaction::new(node_id, Some(alias_info.account_id.clone()), alias_info.account_id.clone(), Amount::ZERO, alias_info.fee, Some(alias_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&alias_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(alias_info.account_id.clone(), expected_nonce + 1);
+        self.aliases.lock().unwrap().insert(alias_info.alias.clone(), alias_info.account_id.clone());
+        Ok(format!("✅ Transaction {} to register alias {} for account {} committed.", transaction_id, alias_info.alias, alias_info.account_id))
+    }
+
+    /// Checks that an alias isn't registered yet and isn't the ID of an account, including pending ones
+    ///
+    /// Returns: a user-facing error if it is taken
+    fn check_alias_available(&self, alias: &str) -> Result<(), String> {
+        if let Some(owner) = self.aliases.lock().unwrap().get(alias) {
+            return Err(format!("❌ Alias {} is already registered for account {}.", alias, owner));
+        }
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| transaction.from_account_id.is_none() && transaction.to_account_id == alias);
+        if pending || self.account_exists(alias) {
+            return Err(format!("❌ Alias {} is the ID of an account.", alias));
+        }
+        Ok(())
+    }
+
+    /// Checks that transactions issue no funds beyond the maximum supply, counting the funds the pending transactions
+    /// issue, see [Transaction::issued]
+    ///
+    /// Returns: a user-facing error if they do
+    fn check_issuance(&self, mempool: &Mempool, transactions: &[Transaction]) -> Result<(), String> {
+        let Some(max_supply) = self.genesis.parameters.max_supply else {
+            return Ok(());
+        };
+        let issued = Amount::checked_sum(transactions.iter().map(Transaction::issued)).unwrap_or(Amount::MAX);
+        if issued == Amount::ZERO {
+            return Ok(());
+        }
+        let issuable = monetary::issuable(Some(max_supply), &self.balances.lock().unwrap());
+        let left = monetary::issuable_after(issuable, mempool.transactions()).unwrap_or_default();
+        if issued > left {
+            return Err(format!("❌ The transaction would issue {}, but only {} is left to issue below the maximum supply of {} with the pending transactions.", issued, left, max_supply));
+        }
+        Ok(())
+    }
+
+    /// Checks that an account ID isn't registered as an alias, so creating the account doesn't make the alias ambiguous
+    ///
+    /// Returns: a user-facing error if it is
+    fn check_not_alias(&self, account_id: &str) -> Result<(), String> {
+        match self.aliases.lock().unwrap().get(account_id) {
+            Some(owner) => Err(format!("❌ {} is registered as the alias of account {}, choose another account ID.", account_id, owner)),
+            None => Ok(()),
+        }
+    }
+
+    /// Resolves an account ID or alias to an account ID. Account IDs take precedence, so an alias never shadows an
+    /// account.
+    ///
+    /// Returns: the account ID and the alias it was resolved from, if any
+    fn resolve_account(&self, account_id_or_alias: &str) -> (String, Option<String>) {
+        if self.account_exists(account_id_or_alias) {
+            return (account_id_or_alias.to_string(), None);
+        }
+        match self.aliases.lock().unwrap().get(account_id_or_alias) {
+            Some(account_id) => (account_id.clone(), Some(account_id_or_alias.to_string())),
+            None => (account_id_or_alias.to_string(), None),
+        }
+    }
+
+    /// Resolves the to account of a transfer, which may be given by its alias
+    ///
+    /// Returns: the transfer to the account ID, and the to account with the alias it was given by for messages
+    fn resolve_recipient(&self, transfer_info: &FundTransferOp) -> (FundTransferOp, String) {
+        let (to_account_id, alias) = self.resolve_account(&transfer_info.to_account_id);
+        let recipient = match alias {
+            Some(alias) => format!("{} (alias {})", to_account_id, alias),
+            None => to_account_id.clone(),
+        };
+        (FundTransferOp { to_account_id, ..transfer_info.clone() }, recipient)
+    }
+
+    /// Validates the creation of a token and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. Like aliases, token symbols are first come, first served. The
+    /// account receives the whole supply once the transaction is minted.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn create_token(&self, node_id: String, token_info: &CreateTokenOp) -> Result<String, String> {
+        validate_token(&token_info.token)?;
+        if token_info.supply <= Amount::ZERO {
+            return Err(format!("❌ Invalid supply {}, the supply of a token has to be more than 0.", token_info.supply));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&token_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", token_info.account_id));
+        }
+        self.check_token_available(&token_info.token)?;
+        // Validated like a transfer of nothing to the token, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: token_info.account_id.clone(),
+            to_account_id: token_info.token.clone(),
+            amount: Amount::ZERO,
+            fee: token_info.fee,
+            nonce: token_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&token_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::CreateToken { token: token_info.token.clone(), supply: token_info.supply },
+            ..Transaction::new(node_id, Some(token_info.account_id.clone()), token_info.account_id.clone(), Amount::ZERO, token_info.fee, Some(token_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&token_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(token_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to create token {} with a supply of {} for account {} committed.",
+            transaction_id, token_info.token, token_info.supply, token_info.account_id
+        ))
+    }
+
+    /// Checks that no token with a symbol exists or is being created by a pending transaction
+    ///
+    /// Returns: a user-facing error if the symbol is taken
+    fn check_token_available(&self, token: &str) -> Result<(), String> {
+        if let Some(existing) = self.tokens.lock().unwrap().get(token) {
+            return Err(format!("❌ Token {} was already created by account {}.", token, existing.issuer));
+        }
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| matches!(&transaction.kind, TransactionKind::CreateToken { token: pending, .. } if pending == token));
+        if pending {
+            return Err(format!("❌ Token {} is already being created by a pending transaction.", token));
+        }
+        Ok(())
+    }
+
+    /// Validates a transfer of a token and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn transfer_token(&self, node_id: String, token_info: &TokenTransferOp) -> Result<String, String> {
+        let (transfer_info, recipient) = &self.resolve_recipient(&token_info.transfer);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_token_transfer(transfer_info, &token_info.token, expected_nonce)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::TokenTransfer(token_info.token.clone()),
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), transfer_info.amount, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone())
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to transfer {} {} from {} to {} committed.",
+            transaction_id, transfer_info.amount, token_info.token, &transfer_info.from_account_id, recipient
+        ))
+    }
+
+    /// Validates a transfer of a token against the state of the chain: the fee like a transfer of the native coin, and
+    /// the amount against the balance of the from account in the token, on top of the amount spent by its pending
+    /// transfers of the token
+    ///
+    /// Returns: a user-facing error explaining why the transfer is invalid
+    fn validate_token_transfer(&self, transfer_info: &FundTransferOp, token: &str, expected_nonce: u64) -> Result<(), String> {
+        if self.tokens.lock().unwrap().get(token).is_none() {
+            return Err(format!("❌ Token {} doesn't exist.", token));
+        }
+        self.validate_transfer(&FundTransferOp { amount: Amount::ZERO, ..transfer_info.clone() }, expected_nonce, Amount::ZERO)?;
+
+        let (balance, pending) = self.get_pending_token_balance(&transfer_info.from_account_id, token);
+        if transfer_info.amount.checked_add(pending).is_none_or(|total| balance < total) {
+            let spent = if pending == Amount::ZERO { String::new() } else { format!(" ({} of its balance of {} is spent by pending transactions)", pending, balance) };
+            return Err(format!("❌ Insufficient {} in account {} to transfer {} {}{}.", token, transfer_info.from_account_id, transfer_info.amount, token, spent));
+        }
+        Ok(())
+    }
+
+    /// Validates the minting of an NFT and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. The ID of the NFT is derived from the account and nonce.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn mint_nft(&self, node_id: String, nft_info: &MintNftOp) -> Result<String, String> {
+        validate_nft_metadata(&nft_info.metadata)?;
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&nft_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", nft_info.account_id));
+        }
+        let nft_id = nft::nft_id(&nft_info.account_id, nft_info.nonce);
+        // Validated like a transfer of nothing to the NFT, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: nft_info.account_id.clone(),
+            to_account_id: nft_id.clone(),
+            amount: Amount::ZERO,
+            fee: nft_info.fee,
+            nonce: nft_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&nft_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::MintNft { nft_id: nft_id.clone(), metadata: nft_info.metadata.clone() },
+            ..Transaction::new(node_id, Some(nft_info.account_id.clone()), nft_info.account_id.clone(), Amount::ZERO, nft_info.fee, Some(nft_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&nft_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(nft_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!("✅ Transaction {} to mint NFT {} for account {} committed.", transaction_id, nft_id, nft_info.account_id))
+    }
+
+    /// Validates the deployment of a contract and adds its transaction to the mempool, or waits for the approvals of
+    /// the account's keys if it is a multisig account. The ID of the contract is derived from the account and nonce.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn deploy_contract(&self, node_id: String, deploy_info: &DeployContractOp) -> Result<String, String> {
+        contract::validate_code(&deploy_info.code).map_err(|e| format!("❌ The contract {}.", e))?;
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&deploy_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", deploy_info.account_id));
+        }
+        let contract_id = contract::contract_id(&deploy_info.account_id, deploy_info.nonce);
+        // Validated like a transfer of nothing to the contract, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: deploy_info.account_id.clone(),
+            to_account_id: contract_id.clone(),
+            amount: Amount::ZERO,
+            fee: deploy_info.fee,
+            nonce: deploy_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&deploy_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::DeployContract { contract_id: contract_id.clone(), code: deploy_info.code.clone() },
+            ..Transaction::new(node_id, Some(deploy_info.account_id.clone()), deploy_info.account_id.clone(), Amount::ZERO, deploy_info.fee, Some(deploy_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&deploy_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(deploy_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!("✅ Transaction {} to deploy contract {} of {} bytes for account {} committed.", transaction_id, contract_id, deploy_info.code.len(), deploy_info.account_id))
+    }
+
+    /// Validates a call of a contract and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. The call is executed once it is minted, so whether it succeeds is
+    /// only known then.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn call_contract(&self, node_id: String, call_info: &CallContractOp) -> Result<String, String> {
+        let transfer_info = &call_info.transfer;
+        let contract_id = &transfer_info.to_account_id;
+        if transfer_info.amount != Amount::ZERO {
+            return Err(format!("❌ Invalid amount {}, contracts hold no funds, so calls transfer nothing.", transfer_info.amount));
+        }
+        if call_info.gas_limit == 0 || call_info.gas_limit > contract::MAX_GAS_LIMIT {
+            return Err(format!("❌ Invalid gas limit {}, it has to be 1 to {}.", call_info.gas_limit, contract::MAX_GAS_LIMIT));
+        }
+        if call_info.input.len() > contract::MAX_INPUT_LENGTH {
+            return Err(format!("❌ The input is {} bytes long, the input of a call can be at most {} bytes.", call_info.input.len(), contract::MAX_INPUT_LENGTH));
+        }
+        match self.contracts.lock().unwrap().get(contract_id) {
+            Some(contract) => contract::check_function(&contract.code, &call_info.function).map_err(|e| format!("❌ Contract {} {}.", contract_id, e))?,
+            None => return Err(format!("❌ Contract {} doesn't exist.", contract_id)),
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&transfer_info.from_account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", transfer_info.from_account_id));
+        }
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let kind = TransactionKind::CallContract {
+            contract_id: contract_id.clone(),
+            function: call_info.function.clone(),
+            input: call_info.input.clone(),
+            gas_limit: call_info.gas_limit,
+        };
+        let transaction = Transaction {
+            kind,
+            ..Transaction::new(
+                node_id,
+                Some(transfer_info.from_account_id.clone()),
+                transfer_info.from_account_id.clone(),
+                Amount::ZERO,
+                transfer_info.fee,
+                Some(transfer_info.nonce),
+                self.clock.now(),
+                transfer_info.memo.clone(),
+            )
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to call function {} of contract {} from {} committed, it is executed once it is minted.",
+            transaction_id, call_info.function, contract_id, transfer_info.from_account_id
+        ))
+    }
+
+    /// Validates a proposal to change a chain parameter and adds its transaction to the mempool, or waits for the
+    /// approvals of the account's keys if it is a multisig account. The ID of the transaction identifies the proposal
+    /// for voting on it.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn propose(&self, node_id: String, propose_info: &ProposeOp) -> Result<String, String> {
+        propose_info.change.validate()?;
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        if propose_info.activation_height <= next_height + 1 {
+            return Err(format!(
+                "❌ The proposal would take effect at height {} before it could be voted on, it is minted at height {} at the earliest.",
+                propose_info.activation_height, next_height
+            ));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&propose_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", propose_info.account_id));
+        }
+        let transaction = Transaction {
+            kind: TransactionKind::Propose { change: propose_info.change, activation_height: propose_info.activation_height },
+            ..Transaction::new(node_id, Some(propose_info.account_id.clone()), propose_info.account_id.clone(), Amount::ZERO, propose_info.fee, Some(propose_info.nonce), self.clock.now(), None)
+        };
+        // Validated like a transfer of nothing to the proposal, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: propose_info.account_id.clone(),
+            to_account_id: transaction.id(),
+            amount: Amount::ZERO,
+            fee: propose_info.fee,
+            nonce: propose_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&propose_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        if let Some(policy) = self.multisig_policy(&propose_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(propose_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to propose changing {} from height {} committed, vote on it with its ID.",
+            transaction_id, propose_info.change, propose_info.activation_height
+        ))
+    }
+
+    /// Validates a vote on a proposal and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. Every account votes once per proposal, and the weight of its vote is
+    /// only known when the proposal is decided.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn vote(&self, node_id: String, vote_info: &VoteOp) -> Result<String, String> {
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&vote_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", vote_info.account_id));
+        }
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        self.governance
+            .lock()
+            .unwrap()
+            .check_vote(&vote_info.proposal_id, &vote_info.account_id, next_height)
+            .map_err(|e| format!("❌ Proposal {} {}.", vote_info.proposal_id, e))?;
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| {
+            transaction.from_account_id.as_ref() == Some(&vote_info.account_id)
+                && matches!(&transaction.kind, TransactionKind::Vote { proposal_id, .. } if proposal_id == &vote_info.proposal_id)
+        });
+        if pending {
+            return Err(format!("❌ Account {} already has a pending vote on proposal {}.", vote_info.account_id, vote_info.proposal_id));
+        }
+        // Validated like a transfer of nothing to the proposal, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: vote_info.account_id.clone(),
+            to_account_id: vote_info.proposal_id.clone(),
+            amount: Amount::ZERO,
+            fee: vote_info.fee,
+            nonce: vote_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&vote_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::Vote { proposal_id: vote_info.proposal_id.clone(), approve: vote_info.approve },
+            ..Transaction::new(node_id, Some(vote_info.account_id.clone()), vote_info.account_id.clone(), Amount::ZERO, vote_info.fee, Some(vote_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&vote_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(vote_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to vote {} proposal {} committed.",
+            transaction_id,
+            if vote_info.approve { "for" } else { "against" },
+            vote_info.proposal_id
+        ))
+    }
+
+    /// Validates a transfer of an NFT and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn transfer_nft(&self, node_id: String, nft_info: &TransferNftOp) -> Result<String, String> {
+        let transfer_info = FundTransferOp {
+            from_account_id: nft_info.from_account_id.clone(),
+            to_account_id: nft_info.to_account_id.clone(),
+            amount: Amount::ZERO,
+            fee: nft_info.fee,
+            nonce: nft_info.nonce,
+            memo: None,
+        };
+        let (transfer_info, recipient) = &self.resolve_recipient(&transfer_info);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        self.validate_nft_transfer(transfer_info, &nft_info.nft_id, expected_nonce)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::TransferNft(nft_info.nft_id.clone()),
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), Amount::ZERO, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!("✅ Transaction {} to transfer NFT {} from {} to {} committed.", transaction_id, nft_info.nft_id, &transfer_info.from_account_id, recipient))
+    }
+
+    /// Validates a transfer of an NFT against the state of the chain: the fee like a transfer of the native coin, and
+    /// that the from account owns the NFT and no pending transaction transfers it already
+    ///
+    /// Returns: a user-facing error explaining why the transfer is invalid
+    fn validate_nft_transfer(&self, transfer_info: &FundTransferOp, nft_id: &str, expected_nonce: u64) -> Result<(), String> {
+        match self.nfts.lock().unwrap().get(nft_id) {
+            None => return Err(format!("❌ NFT {} doesn't exist.", nft_id)),
+            Some(nft) if nft.owner != transfer_info.from_account_id => return Err(format!("❌ NFT {} is owned by account {}, not {}.", nft_id, nft.owner, transfer_info.from_account_id)),
+            Some(_) => {}
+        }
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| matches!(&transaction.kind, TransactionKind::TransferNft(pending) if pending == nft_id));
+        if pending {
+            return Err(format!("❌ NFT {} is already being transferred by a pending transaction.", nft_id));
+        }
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)
+    }
+
+    /// Validates a transfer into escrow and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. The funds move to the escrow account like a transfer, and the ID of
+    /// the transaction identifies the escrow for settling it.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn escrow_transfer(&self, node_id: String, escrow_info: &EscrowTransferOp) -> Result<String, String> {
+        let (transfer_info, recipient) = &self.resolve_recipient(&escrow_info.transfer);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        if transfer_info.amount <= Amount::ZERO {
+            return Err(format!("❌ Invalid amount {}, an escrow has to hold more than 0.", transfer_info.amount));
+        }
+        if escrow_info.release.is_before(&escrow_info.expiry) == Some(false) {
+            return Err(format!("❌ The escrow is released at {}, which isn't before it expires at {}.", escrow_info.release, escrow_info.expiry));
+        }
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        let now = self.clock.unix_time();
+        if escrow_info.expiry.is_open(next_height, now) {
+            return Err(format!("❌ The escrow would expire at {} before it is minted.", escrow_info.expiry));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        // Validated like a transfer to the recipient, so it can't be closed or the sender itself
+        self.validate_transfer(transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::EscrowTransfer { recipient: transfer_info.to_account_id.clone(), release: escrow_info.release, expiry: escrow_info.expiry },
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), ESCROW_ACCOUNT.to_string(), transfer_info.amount, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone())
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to escrow {} from {} for {} committed, it can be claimed from {} until {} and refunded afterwards.",
+            transaction_id, transfer_info.amount, &transfer_info.from_account_id, recipient, escrow_info.release, escrow_info.expiry
+        ))
+    }
+
+    /// Validates the claim or refund of an escrow against the next block and adds its transaction to the mempool. The
+    /// funds can only go to the recipient or back to the sender, so anyone may settle an escrow, and the settlement is
+    /// paid from the escrow account without a fee or nonce.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn settle_escrow(&self, node_id: String, settle_info: &SettleEscrowOp, refund: bool) -> Result<String, String> {
+        let escrow_id = &settle_info.escrow_id;
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        let now = self.clock.unix_time();
+        let escrow = self.escrows.lock().unwrap().check_settlement(escrow_id, refund, next_height, now).cloned().map_err(|e| format!("❌ Escrow {} {}.", escrow_id, e))?;
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| transaction.settles_escrow().is_some_and(|(pending, _)| pending == escrow_id));
+        if pending {
+            return Err(format!("❌ Escrow {} is already being settled by a pending transaction.", escrow_id));
+        }
+
+        let to_account_id = if refund { &escrow.sender } else { &escrow.recipient };
+        let kind = if refund { TransactionKind::RefundEscrow(escrow_id.clone()) } else { TransactionKind::ClaimEscrow(escrow_id.clone()) };
+        let transaction = Transaction {
+            kind,
+            ..Transaction::new(node_id, Some(ESCROW_ACCOUNT.to_string()), to_account_id.clone(), escrow.amount, Amount::ZERO, None, self.clock.now(), None)
+        };
+        let transaction_id = self.submit_transaction(transaction)?;
+        let settlement = if refund { "refund" } else { "claim" };
+        Ok(format!("✅ Transaction {} to {} escrow {} paying {} to {} committed.", transaction_id, settlement, escrow_id, escrow.amount, to_account_id))
+    }
+
+    /// Validates a payment schedule and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. The transaction only pays the fee, the payments are made by the
+    /// minter once they are due, and its ID identifies the schedule for cancelling it.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn schedule_payment(&self, node_id: String, schedule_info: &SchedulePaymentOp) -> Result<String, String> {
+        let (transfer_info, recipient) = &self.resolve_recipient(&schedule_info.transfer);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        if transfer_info.amount <= Amount::ZERO {
+            return Err(format!("❌ Invalid amount {}, scheduled payments have to pay more than 0.", transfer_info.amount));
+        }
+        let SchedulePaymentOp { start_height, interval, count, .. } = *schedule_info;
+        if interval == 0 || count == 0 {
+            return Err("❌ A schedule needs an interval and a number of payments of at least 1.".to_string());
+        }
+        if Schedule::last_height(start_height, interval, count).is_none() {
+            return Err(format!("❌ The last of the {} payments every {} blocks from height {} would be due after the highest possible height.", count, interval, start_height));
+        }
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        if start_height <= next_height {
+            return Err(format!("❌ The schedule has to start after height {}, the height of the next block, which only records the schedule.", next_height));
+        }
+        if !self.account_exists(&transfer_info.to_account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", transfer_info.to_account_id));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        // Validated like a transfer of nothing to the recipient, which pays the fee with the account's next nonce
+        let fee_info = FundTransferOp { amount: Amount::ZERO, ..transfer_info.clone() };
+        self.validate_transfer(&fee_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::SchedulePayment { amount: transfer_info.amount, start_height, interval, count },
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), Amount::ZERO, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone())
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(&fee_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to schedule {} payments of {} from {} to {} every {} blocks from height {} committed.",
+            transaction_id, count, transfer_info.amount, &transfer_info.from_account_id, recipient, interval, start_height
+        ))
+    }
+
+    /// Validates the cancellation of a payment schedule by the account paying it and adds its transaction to the
+    /// mempool, or waits for the approvals of the account's keys if it is a multisig account
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn cancel_schedule(&self, node_id: String, cancel_info: &CancelScheduleOp) -> Result<String, String> {
+        let schedule_id = &cancel_info.schedule_id;
+        let mut nonces = self.nonces.lock().unwrap();
+        match self.schedules.lock().unwrap().get(schedule_id) {
+            None => return Err(format!("❌ Schedule {} doesn't exist or has no payments left.", schedule_id)),
+            Some(schedule) if schedule.sender != cancel_info.account_id => {
+                return Err(format!("❌ Schedule {} is paid by account {}, only it can cancel the schedule.", schedule_id, schedule.sender))
+            }
+            Some(_) => {}
+        }
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| matches!(&transaction.kind, TransactionKind::CancelSchedule(pending) if pending == schedule_id));
+        if pending {
+            return Err(format!("❌ Schedule {} is already being cancelled by a pending transaction.", schedule_id));
+        }
+        // Validated like a transfer of nothing to the schedule, which pays the fee with the account's next nonce
+        let transfer_info = FundTransferOp {
+            from_account_id: cancel_info.account_id.clone(),
+            to_account_id: schedule_id.clone(),
+            amount: Amount::ZERO,
+            fee: cancel_info.fee,
+            nonce: cancel_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&cancel_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::CancelSchedule(schedule_id.clone()),
+            ..Transaction::new(node_id, Some(cancel_info.account_id.clone()), cancel_info.account_id.clone(), Amount::ZERO, cancel_info.fee, Some(cancel_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&cancel_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(cancel_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!("✅ Transaction {} to cancel schedule {} committed, its remaining payments won't be made.", transaction_id, schedule_id))
+    }
+
+    /// Validates an offer to swap assets with another account and adds its transaction to the mempool, or waits for the
+    /// approvals of the account's keys if it is a multisig account. The transaction only pays the fee, the assets
+    /// are swapped once the counterparty accepts the offer, and its ID identifies the offer for accepting it.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn offer_swap(&self, node_id: String, swap_info: &OfferSwapOp) -> Result<String, String> {
+        let (transfer_info, counterparty) = &self.resolve_recipient(&swap_info.transfer);
+        State::check_not_reserved(&transfer_info.to_account_id)?;
+        let give = Asset { token: swap_info.give_token.clone(), amount: transfer_info.amount };
+        for asset in [&give, &swap_info.take] {
+            if asset.amount <= Amount::ZERO {
+                return Err(format!("❌ Invalid amount {}, both sides of a swap have to be more than 0.", asset.amount));
+            }
+            if let Some(token) = asset.token.as_deref().filter(|token| self.tokens.lock().unwrap().get(token).is_none()) {
+                return Err(format!("❌ Token {} doesn't exist.", token));
+            }
+        }
+        if give.token == swap_info.take.token {
+            return Err(format!("❌ A swap exchanges two different assets, but both sides are in {}.", give.token.as_deref().unwrap_or("the native coin")));
+        }
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        if swap_info.expiry_height <= next_height + 1 {
+            return Err(format!("❌ The offer would expire at height {} before it could be accepted, it is minted at height {} at the earliest.", swap_info.expiry_height, next_height));
+        }
+        if !self.account_exists(&transfer_info.to_account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", transfer_info.to_account_id));
+        }
+        let mut nonces = self.nonces.lock().unwrap();
+        let expected_nonce = nonces.get(&transfer_info.from_account_id).copied().unwrap_or(0);
+        // Validated like a transfer of nothing to the counterparty, which pays the fee with the account's next nonce
+        let fee_info = FundTransferOp { amount: Amount::ZERO, ..transfer_info.clone() };
+        self.validate_transfer(&fee_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::SwapOffer { give: give.clone(), take: swap_info.take.clone(), expiry_height: swap_info.expiry_height },
+            ..Transaction::new(node_id, Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), Amount::ZERO, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone())
+        };
+        if let Some(policy) = self.multisig_policy(&transfer_info.from_account_id) {
+            return self.propose_transfer(&fee_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(transfer_info.from_account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to offer {} from {} to {} for {} until height {} committed.",
+            transaction_id, give, &transfer_info.from_account_id, counterparty, swap_info.take, swap_info.expiry_height
+        ))
+    }
+
+    /// Validates the acceptance of a swap offer and adds its transaction to the mempool, or waits for the approvals of
+    /// the account's keys if it is a multisig account
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn accept_swap(&self, node_id: String, accept_info: &AcceptSwapOp) -> Result<String, String> {
+        let mut nonces = self.nonces.lock().unwrap();
+        let maker = self.swaps.lock().unwrap().get(&accept_info.offer_id).map(|offer| offer.maker.clone()).unwrap_or_default();
+        let transfer_info = FundTransferOp {
+            from_account_id: accept_info.account_id.clone(),
+            to_account_id: maker,
+            amount: Amount::ZERO,
+            fee: accept_info.fee,
+            nonce: accept_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&accept_info.account_id).copied().unwrap_or(0);
+        let offer = self.validate_swap_acceptance(&transfer_info, &accept_info.offer_id, expected_nonce)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::SwapAccept { offer_id: accept_info.offer_id.clone(), give: offer.give.clone(), take: offer.take.clone() },
+            ..Transaction::new(node_id, Some(accept_info.account_id.clone()), offer.maker.clone(), Amount::ZERO, accept_info.fee, Some(accept_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&accept_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(accept_info.account_id.clone(), expected_nonce + 1);
+        Ok(format!(
+            "✅ Transaction {} to accept swap offer {} committed, {} swaps {} for {} from {}.",
+            transaction_id, accept_info.offer_id, accept_info.account_id, offer.take, offer.give, offer.maker
+        ))
+    }
+
+    /// Validates the acceptance of a swap offer against the next block, given as a transfer of nothing from the
+    /// counterparty to the maker that pays the fee. Both accounts have to afford what they give on top of what their
+    /// pending transactions spend, and no other acceptance of the offer may be pending.
+    ///
+    /// Returns: the offer, or a user-facing error if it can't be accepted
+    fn validate_swap_acceptance(&self, transfer_info: &FundTransferOp, offer_id: &str, expected_nonce: u64) -> Result<SwapOffer, String> {
+        let next_height = self.ledger.read().unwrap().len() as u64;
+        let offer = self.swaps.lock().unwrap().check_acceptance(offer_id, &transfer_info.from_account_id, next_height).cloned().map_err(|e| format!("❌ Swap offer {} {}.", offer_id, e))?;
+        let pending = self.mempool.lock().unwrap().transactions().any(|transaction| matches!(&transaction.kind, TransactionKind::SwapAccept { offer_id: pending, .. } if pending == offer_id));
+        if pending {
+            return Err(format!("❌ Swap offer {} is already being accepted by a pending transaction.", offer_id));
+        }
+        if self.frozen.lock().unwrap().is_frozen(&offer.maker) {
+            return Err(format!("❌ Account {} is frozen, it can't give {} in the swap.", offer.maker, offer.give));
+        }
+        // The counterparty's side is validated like a transfer to the maker, in the native coin or the token
+        match &offer.take.token {
+            Some(token) => self.validate_token_transfer(&FundTransferOp { amount: offer.take.amount, ..transfer_info.clone() }, token, expected_nonce)?,
+            None => self.validate_transfer(&FundTransferOp { amount: offer.take.amount, ..transfer_info.clone() }, expected_nonce, Amount::ZERO)?,
+        }
+        let (balance, pending) = match &offer.give.token {
+            Some(token) => self.get_pending_token_balance(&offer.maker, token),
+            None => self.get_pending_balance(&offer.maker),
+        };
+        if offer.give.amount.checked_add(pending).is_none_or(|total| balance < total) {
+            return Err(format!("❌ Insufficient funds in account {} to give {} in the swap, it has {} left after its pending transactions.", offer.maker, offer.give, balance - pending));
+        }
+        Ok(offer)
+    }
+
+    /// Gets the validators and their stakes, highest stake first
+    fn get_validators(&self) -> Vec<(String, Amount)> {
+        let mut validators: Vec<(String, Amount)> = self.stakes.lock().unwrap().iter().map(|(account_id, stake)| (account_id.clone(), *stake)).collect();
+        validators.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        validators
+    }
+
+    /// Validates the closing of an account and adds its transaction to the mempool, or waits for the approvals of the
+    /// account's keys if it is a multisig account. The whole balance goes to the beneficiary, so the account can't have
+    /// pending transactions that would change its balance.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn close_account(&self, node_id: String, close_info: &CloseAccountOp) -> Result<String, String> {
+        State::check_not_reserved(&close_info.beneficiary_id)?;
+        let mut nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(&close_info.account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", close_info.account_id));
+        }
+        self.check_no_pending_transactions(&close_info.account_id)?;
+        self.check_holds_no_tokens(&close_info.account_id)?;
+
+        let balance = self.get_balance(&close_info.account_id);
+        if balance < close_info.fee {
+            return Err(format!("❌ Insufficient funds in account {} to pay the fee of {} for closing it.", close_info.account_id, close_info.fee));
+        }
+        let transfer_info = FundTransferOp {
+            from_account_id: close_info.account_id.clone(),
+            to_account_id: close_info.beneficiary_id.clone(),
+            amount: balance - close_info.fee,
+            fee: close_info.fee,
+            nonce: close_info.nonce,
+            memo: None,
+        };
+        let expected_nonce = nonces.get(&close_info.account_id).copied().unwrap_or(0);
+        self.validate_transfer(&transfer_info, expected_nonce, Amount::ZERO)?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::Close,
+            ..Transaction::new(node_id, Some(close_info.account_id.clone()), close_info.beneficiary_id.clone(), transfer_info.amount, close_info.fee, Some(close_info.nonce), self.clock.now(), None)
+        };
+        if let Some(policy) = self.multisig_policy(&close_info.account_id) {
+            return self.propose_transfer(&transfer_info, transaction, &policy);
+        }
+        let transaction_id = self.submit_transaction(transaction)?;
+        nonces.insert(close_info.account_id.clone(), expected_nonce + 1);
+        self.closed.lock().unwrap().insert(close_info.account_id.clone());
+        Ok(format!("✅ Transaction {} to close account {} and sweep {} to {} committed.", transaction_id, close_info.account_id, transfer_info.amount, close_info.beneficiary_id))
+    }
+
+    /// Checks that an account has no balance in any token and owns no NFT, which closing it would lose as only the
+    /// native coin is swept, is involved in no open escrow, which couldn't be settled anymore, and pays no schedule
+    ///
+    /// Returns: a user-facing error if it has
+    fn check_holds_no_tokens(&self, account_id: &str) -> Result<(), String> {
+        let tokens = self.tokens.lock().unwrap();
+        let mut held: Vec<String> = tokens.held_by(account_id).into_iter().map(|(token, balance)| format!("{} {}", balance, token)).collect();
+        let nfts = self.nfts.lock().unwrap().owned_by(account_id).len();
+        if nfts > 0 {
+            held.push(format!("{} NFTs", nfts));
+        }
+
+        if !held.is_empty() {
+            return Err(format!("❌ Account {} still holds {}, transfer its tokens before closing it.", account_id, held.join(", ")));
+        }
+        let escrows = self.escrows.lock().unwrap().involving(account_id);
+        if escrows > 0 {
+            return Err(format!("❌ Account {} is involved in {} open escrows, close it once they are settled.", account_id, escrows));
+        }
+        let transfers = self.cross_shard.lock().unwrap().involving(account_id);
+        if transfers > 0 {
+            return Err(format!("❌ Account {} sent {} transfers to other shards that weren't settled, close it once they are.", account_id, transfers));
+        }
+        let schedules = self.schedules.lock().unwrap().involving(account_id).iter().filter(|(_, schedule)| schedule.sender == account_id).count();
+        if schedules > 0 {
+            return Err(format!("❌ Account {} pays {} payment schedules, cancel them before closing it.", account_id, schedules));
+        }
+        Ok(())
+    }
+
+    /// Checks that no transaction from or to an account is waiting in the mempool
+    ///
+    /// Returns: a user-facing error if there is one
+    fn check_no_pending_transactions(&self, account_id: &str) -> Result<(), String> {
+        let mempool = self.mempool.lock().unwrap();
+        let pending = mempool.transactions().any(|transaction| transaction.to_account_id == account_id || transaction.from_account_id.as_deref() == Some(account_id));
+        if pending {
+            return Err(format!("❌ Account {} has pending transactions, close it once they are minted.", account_id));
+        }
+        Ok(())
+    }
+
+    /// Holds a transfer from a multisig account until enough of the account's keys approved it. The nonce is only used
+    /// once the transfer is approved, so other transfers can still be proposed with it.
+    ///
+    /// Returns: a user-facing message asking for the approvals
+    fn propose_transfer(&self, transfer_info: &FundTransferOp, transaction: Transaction, policy: &MultisigPolicy) -> Result<String, String> {
+        let transaction_id = transaction.id();
+        self.approvals.lock().unwrap().add(transaction_id.clone(), transfer_info.clone(), transaction)?;
+        Ok(format!(
+            "⏳ Transaction {} to transfer {} from {} to {} is waiting for the approval of {} of the {} keys of the account, approve it with `approve {}`.",
+            transaction_id,
+            transfer_info.amount,
+            &transfer_info.from_account_id,
+            &transfer_info.to_account_id,
+            policy.threshold,
+            policy.public_keys.len(),
+            transaction_id
+        ))
+    }
+
+    /// Records the approval of a transfer from a multisig account by one of its keys. Once enough keys approved it, the
+    /// transfer is validated again, as other transfers may have been made in the meantime, and added to the mempool.
+    ///
+    /// Returns: a user-facing message, either confirming the approval or explaining why it was rejected
+    fn approve_transfer(&self, approval_info: &ApproveTransferOp) -> Result<String, String> {
+        let mut nonces = self.nonces.lock().unwrap();
+        let mut approvals = self.approvals.lock().unwrap();
+        let Some(pending) = approvals.get_mut(&approval_info.transaction_id) else {
+            return Err(format!("❌ Transaction {} isn't a transfer waiting for approvals.", approval_info.transaction_id));
+        };
+        let from_account_id = pending.transfer.from_account_id.clone();
+        let policy = self.multisig_policy(&from_account_id).expect("Pending transfer from an account that isn't a multisig account.");
+
+        let public_key = approval_info.public_key.to_ascii_lowercase();
+        if !policy.public_keys.contains(&public_key) {
+            return Err(format!("❌ Key {} isn't one of the keys of multisig account {}.", public_key, from_account_id));
+        }
+        if pending.approved_by.contains(&public_key) {
+            return Err(format!("⚠️ Key {} already approved transaction {}.", public_key, approval_info.transaction_id));
+        }
+        keys::verify_approval(&public_key, &approval_info.transaction_id, &approval_info.signature)?;
+
+        pending.approved_by.push(public_key);
+        if pending.approved_by.len() < policy.threshold {
+            return Ok(format!("✅ Approval {} of {} for transaction {} recorded.", pending.approved_by.len(), policy.threshold, approval_info.transaction_id));
+        }
+
+        let pending = approvals.remove(&approval_info.transaction_id).unwrap();
+        let expected_nonce = nonces.get(&from_account_id).copied().unwrap_or(0);
+        let closes = pending.transaction.kind == TransactionKind::Close;
+        let alias = match &pending.transaction.kind {
+            TransactionKind::RegisterAlias(alias) => Some(alias.clone()),
+            _ => None,
+        };
+        let validated = match &pending.transaction.kind {
+            TransactionKind::TokenTransfer(token) => self.validate_token_transfer(&pending.transfer, token, expected_nonce),
+            TransactionKind::TransferNft(nft_id) => self.validate_nft_transfer(&pending.transfer, nft_id, expected_nonce),
+            TransactionKind::CreateToken { token, .. } => self.validate_transfer(&pending.transfer, expected_nonce, Amount::ZERO).and_then(|()| self.check_token_available(token)),
+            TransactionKind::SwapAccept { offer_id, .. } => self.validate_swap_acceptance(&pending.transfer, offer_id, expected_nonce).map(|_| ()),
+            TransactionKind::Unstake => {
+                self.validate_transfer(&pending.transfer, expected_nonce, Amount::ZERO).and_then(|()| self.check_unstake(&from_account_id, pending.transaction.amount))
+            }
+            _ => self.validate_transfer(&pending.transfer, expected_nonce, Amount::ZERO),
+        };
+        validated
+            .and_then(|()| if closes { self.check_closes_balance(&pending.transfer) } else { Ok(()) })
+            .and_then(|()| alias.as_deref().map_or(Ok(()), |alias| self.check_alias_available(alias)))
+            .map_err(|e| format!("{} The transfer was approved but can't be made anymore, propose it again.", e))?;
+        let transaction_id = self.submit_transaction(pending.transaction)?;
+        nonces.insert(from_account_id.clone(), expected_nonce + 1);
+        if closes {
+            self.closed.lock().unwrap().insert(from_account_id.clone());
+        }
+        if let Some(alias) = alias {
+            self.aliases.lock().unwrap().insert(alias, from_account_id.clone());
+        }
+        Ok(format!(
+            "✅ Transaction {} to transfer {} from {} to {} approved by {} of {} keys and committed.",
+            transaction_id,
+            pending.transfer.amount,
+            from_account_id,
+            pending.transfer.to_account_id,
+            policy.threshold,
+            policy.public_keys.len()
+        ))
+    }
+
+    /// Cancels a transaction that wasn't minted yet, either waiting in the mempool or for approvals. Only the last
+    /// pending transaction of an account can be cancelled, so the account's next nonce goes back to the nonce of the
+    /// cancelled transaction without leaving a gap before the later ones.
+    ///
+    /// Returns: a user-facing message, either confirming the cancellation or explaining why it was rejected
+    fn cancel_transaction(&self, cancel_info: &CancelTransactionOp) -> Result<String, String> {
+        let transaction_id = &cancel_info.transaction_id;
+        let mut nonces = self.nonces.lock().unwrap();
+        {
+            let mut approvals = self.approvals.lock().unwrap();
+            if let Some(pending) = approvals.get_mut(transaction_id) {
+                self.check_cancel_signature(&pending.transfer.from_account_id, cancel_info)?;
+                approvals.remove(transaction_id);
+                self.rejected.lock().unwrap().record(transaction_id.clone(), "cancelled while waiting for approvals".to_string());
+                return Ok(format!("✅ Transaction {} waiting for approvals cancelled.", transaction_id));
+            }
+        }
+
+        let mut mempool = self.mempool.lock().unwrap();
+        let Some(transaction) = mempool.transactions().find(|transaction| &transaction.id() == transaction_id).cloned() else {
+            drop(mempool);
+            return match self.find_transaction(transaction_id) {
+                Some((_, Some((height, block_hash)))) => Err(format!("❌ Transaction {} was minted already in block {} at height {}, it can't be cancelled.", transaction_id, block_hash, height)),
+                _ => Err(format!("❌ Transaction {} isn't pending.", transaction_id)),
+            };
+        };
+        let (Some(from_account_id), Some(nonce)) = (&transaction.from_account_id, transaction.nonce) else {
+            return Err(format!("❌ Transaction {} has no from account that could cancel it.", transaction_id));
+        };
+        self.check_cancel_signature(from_account_id, cancel_info)?;
+        let later = mempool.transactions().find(|later| later.from_account_id.as_ref() == Some(from_account_id) && later.nonce > Some(nonce));
+        if let Some(later) = later {
+            return Err(format!("❌ Transaction {} of account {} was made after transaction {}, cancel it first.", later.id(), from_account_id, transaction_id));
+        }
+
+        mempool.remove(transaction_id);
+        self.truncate_wal(&mempool);
+        drop(mempool);
+        self.rejected.lock().unwrap().record(transaction_id.clone(), "cancelled before it was minted".to_string());
+        nonces.insert(from_account_id.clone(), nonce);
+        if transaction.kind == TransactionKind::Close {
+            self.closed.lock().unwrap().remove(from_account_id);
+        }
+        if let TransactionKind::RegisterAlias(alias) = &transaction.kind {
+            self.aliases.lock().unwrap().remove(alias);
+        }
+        Ok(format!("✅ Transaction {} cancelled: {}.", transaction_id, transaction))
+    }
+
+    /// Checks that the cancellation of a transaction is signed by a key of its from account. Accounts without keys
+    /// can't prove who is cancelling, so they can't cancel their transactions.
+    ///
+    /// Returns: a user-facing error if it isn't
+    fn check_cancel_signature(&self, account_id: &str, cancel_info: &CancelTransactionOp) -> Result<(), String> {
+        let Some(policy) = self.multisig_policy(account_id) else {
+            return Err(format!("❌ Account {} has no keys, only transactions from multisig and wallet accounts can be cancelled.", account_id));
+        };
+        let public_key = cancel_info.public_key.to_ascii_lowercase();
+        if !policy.public_keys.contains(&public_key) {
+            return Err(format!("❌ Key {} isn't one of the keys of account {}.", public_key, account_id));
+        }
+        keys::verify_cancel(&public_key, &cancel_info.transaction_id, &cancel_info.signature)
+    }
+
+    /// Validates an admin operation freezing or unfreezing an account and adds its transaction to the mempool. The
+    /// nonces are locked like for a transfer, so the freeze is ordered after the transfers from the account accepted
+    /// before it.
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn set_frozen(&self, node_id: String, admin_info: &AdminAccountOp, freeze: bool) -> Result<String, String> {
+        let Some(admin_key) = &self.admin_key else {
+            return Err("❌ This node has no admin key, start it with --admin-key to accept admin operations.".to_string());
+        };
+        let (operation, account_id) = (if freeze { "freeze" } else { "unfreeze" }, &admin_info.account_id);
+        let now = self.clock.unix_time();
+        if admin_info.signed_at.abs_diff(now) > admin::SIGNATURE_VALIDITY.as_secs() {
+            return Err(format!("❌ The admin signature was made at {} but it is {} now (unix time), sign the {} again.", admin_info.signed_at, now, operation));
+        }
+        keys::verify_admin(admin_key, operation, account_id, admin_info.signed_at, &admin_info.signature)?;
+
+        let _nonces = self.nonces.lock().unwrap();
+        if !self.account_exists(account_id) {
+            return Err(format!("❌ Account {} doesn't exist.", account_id));
+        }
+        if self.closed.lock().unwrap().contains(account_id) {
+            return Err(format!("❌ Account {} is closed.", account_id));
+        }
+        let mut frozen = self.frozen.lock().unwrap();
+        frozen.check(account_id, freeze, admin_info.signed_at).map_err(|e| format!("❌ Can't {} account {}: {}.", operation, account_id, e))?;
+
+        let kind = if freeze {
+            TransactionKind::Freeze { signed_at: admin_info.signed_at }
+        } else {
+            TransactionKind::Unfreeze { signed_at: admin_info.signed_at }
+        };
+        let transaction = Transaction {
+            kind,
+            ..Transaction::new(node_id, None, account_id.clone(), Amount::ZERO, Amount::ZERO, None, self.clock.now(), None)
+        };
+        let transaction_id = self.submit_transaction(transaction)?;
+        frozen.apply(account_id, freeze, admin_info.signed_at);
+        Ok(format!("✅ Transaction {} to {} account {} committed.", transaction_id, operation, account_id))
+    }
+
+    /// Mints the pending transactions into a block right away, for an admin that doesn't want to wait out the mint
+    /// interval, e.g. in demos and tests. The mint loop keeps its schedule.
+    ///
+    /// Returns: a user-facing message with the hash of the minted block, or why no block was minted
+    fn mint_now(&self, admin_info: &AdminCommandOp) -> Result<String, String> {
+        let Some(admin_key) = &self.admin_key else {
+            return Err("❌ This node has no admin key, start it with --admin-key to accept admin operations.".to_string());
+        };
+        let now = self.clock.unix_time();
+        if admin_info.signed_at.abs_diff(now) > admin::SIGNATURE_VALIDITY.as_secs() {
+            return Err(format!("❌ The admin signature was made at {} but it is {} now (unix time), sign mint-now again.", admin_info.signed_at, now));
+        }
+        keys::verify_admin_command(admin_key, "mint-now", admin_info.signed_at, &admin_info.signature)?;
+        {
+            let mut last_signed_at = self.mint_now_signed_at.lock().unwrap();
+            if admin_info.signed_at <= *last_signed_at {
+                return Err(format!("❌ The admin signature was made at {}, but the last mint-now was signed at {}, sign mint-now again.", admin_info.signed_at, last_signed_at));
+            }
+            *last_signed_at = admin_info.signed_at;
+        }
+
+        if self.mempool.lock().unwrap().is_empty() {
+            return Err("⚠️ There are no pending transactions, no block was minted.".to_string());
+        }
+        let hash = mint_block(self);
+        health::record_mint_round(self, hash.is_some());
+        match hash {
+            Some(hash) => Ok(format!("✅ Block {} minted at height {}.", hash, self.ledger.read().unwrap().len() - 1)),
+            None if self.sync.lock().unwrap().is_syncing() => Err("❌ No block was minted, the node is syncing with a peer.".to_string()),
+            None => Err("❌ No block was minted, the current slot belongs to another validator.".to_string()),
+        }
+    }
+
+    /// Validates a payment of the faucet to an existing account and adds its transaction to the mempool
+    ///
+    /// Returns: a user-facing message, either confirming the transaction or explaining why it was rejected
+    fn pay_from_faucet(&self, node_id: String, faucet_info: &FaucetOp) -> Result<String, String> {
+        let Some(faucet) = &self.faucet else {
+            return Err("❌ This node has no faucet, start it with --enable-faucet on a test network.".to_string());
+        };
+        let _nonces = self.nonces.lock().unwrap();
+        let (account_id, _) = self.resolve_account(&faucet_info.account_id);
+        if !self.account_exists(&account_id) {
+            return Err(format!("❌ Account {} doesn't exist, the faucet only pays minted accounts.", account_id));
+        }
+        if self.closed.lock().unwrap().contains(&account_id) {
+            return Err(format!("❌ Account {} is closed.", account_id));
+        }
+        let mut faucet = faucet.lock().unwrap();
+        let now = self.clock.now();
+        let amount = faucet.check(&account_id, faucet_info.amount, now).map_err(|e| format!("❌ The faucet {}.", e))?;
+
+        let transaction = Transaction {
+            kind: TransactionKind::Faucet,
+            ..Transaction::new(node_id, None, account_id.clone(), amount, Amount::ZERO, None, self.clock.now(), None)
+        };
+        let transaction_id = self.submit_transaction(transaction)?;
+        faucet.record(&account_id, now);
+        Ok(format!("✅ Transaction {} to pay {} from the faucet to account {} committed.", transaction_id, amount, account_id))
+    }
+
+    /// Validates a batch of transfers and adds their transactions to the mempool, either all of them or none if any
+    /// transfer is invalid. Every transfer is validated as if the transfers before it were made already, so an account
+    /// can make several transfers with consecutive nonces.
+    ///
+    /// Returns: a user-facing message of at most `max_length` bytes, either confirming the transactions or explaining
+    /// why the batch was rejected
+    fn transfer_batch(&self, node_id: String, batch_info: &BatchTransferOp, max_length: usize) -> Result<String, String> {
+        if batch_info.transfers.is_empty() {
+            return Err("❌ The batch doesn't contain any transfers.".to_string());
+        }
+
+        let mut nonces = self.nonces.lock().unwrap();
+        // Next nonces of the accounts and the funds they spent within the batch
+        let mut batch_nonces: HashMap<&str, u64> = HashMap::new();
+        let mut spent: HashMap<&str, Amount> = HashMap::new();
+        let mut transactions = Vec::new();
+        for (index, transfer_info) in batch_info.transfers.iter().enumerate() {
+            let from_account_id = transfer_info.from_account_id.as_str();
+            let expected_nonce = batch_nonces.get(from_account_id).copied().unwrap_or_else(|| nonces.get(from_account_id).copied().unwrap_or(0));
+            let already_spent = spent.get(from_account_id).copied().unwrap_or_default();
+            if self.multisig_policy(from_account_id).is_some() {
+                return Err(format!("❌ Account {} is a multisig account, its transfers can't be batched. Transfer {} of the batch was rejected, so none of its transfers were made.", from_account_id, index + 1));
+            }
+            State::check_not_reserved(&transfer_info.to_account_id)
+                .and_then(|()| self.validate_transfer(transfer_info, expected_nonce, already_spent))
+                .map_err(|e| format!("{} Transfer {} of the batch was rejected, so none of its transfers were made.", e, index + 1))?;
+
+            batch_nonces.insert(from_account_id, expected_nonce + 1);
+            spent.insert(from_account_id, already_spent + transfer_info.amount + transfer_info.fee);
+            transactions.push(Transaction::new(node_id.clone(), Some(transfer_info.from_account_id.clone()), transfer_info.to_account_id.clone(), transfer_info.amount, transfer_info.fee, Some(transfer_info.nonce), self.clock.now(), transfer_info.memo.clone()));
+        }
+
+        let transaction_ids = self.submit_transactions(transactions)?;
+        for (account_id, nonce) in batch_nonces {
+            nonces.insert(account_id.to_string(), nonce);
+        }
+
+        // List as many transaction IDs as fit into the response
+        let mut response = format!("✅ Batch of {} transfers committed:", transaction_ids.len());
+        for (index, transaction_id) in transaction_ids.iter().enumerate() {
+            let more = format!("\n… and {} more", transaction_ids.len() - index);
+            if response.len() + transaction_id.len() + 3 + more.len() > max_length && index + 1 < transaction_ids.len() {
+                response.push_str(&more);
+                break;
+            }
+            response.push_str("\n- ");
+            response.push_str(transaction_id);
+        }
+        Ok(response)
+    }
+
+    /// Checks that the closing of an account still sweeps its whole balance, which may have changed while the closing
+    /// waited for approvals
+    ///
+    /// Returns: a user-facing error if it doesn't
+    fn check_closes_balance(&self, transfer_info: &FundTransferOp) -> Result<(), String> {
+        self.check_no_pending_transactions(&transfer_info.from_account_id)?;
+        self.check_holds_no_tokens(&transfer_info.from_account_id)?;
+        if transfer_info.amount.checked_add(transfer_info.fee) != Some(self.get_balance(&transfer_info.from_account_id)) {
+            return Err(format!("❌ The balance of account {} changed since its closing was proposed.", transfer_info.from_account_id));
+        }
+        Ok(())
+    }
+
+    /// Validates a transfer of funds against the state of the chain
+    ///
+    /// # Arguments
+    ///
+    /// * `transfer_info`: the transfer
+    /// * `expected_nonce`: the nonce the transfer has to be made with
+    /// * `already_spent`: funds of the from account spent by earlier transfers that aren't in the balance index yet
+    ///
+    /// Returns: a user-facing error explaining why the transfer is invalid
+    fn validate_transfer(&self, transfer_info: &FundTransferOp, expected_nonce: u64, already_spent: Amount) -> Result<(), String> {
+        // Validate that the from and to accounts are different
+        if transfer_info.from_account_id == transfer_info.to_account_id {
+            return Err("❌ Cannot transfer funds to the same account.".to_string());
+        }
+
+        if transfer_info.from_account_id == STAKE_ACCOUNT {
+            return Err(format!("❌ The staked funds in account {} can't be transferred.", STAKE_ACCOUNT));
+        }
+        if transfer_info.from_account_id == ESCROW_ACCOUNT {
+            return Err(format!("❌ The escrowed funds in account {} can only be claimed or refunded.", ESCROW_ACCOUNT));
+        }
+        if transfer_info.from_account_id == SLASHED_ACCOUNT {
+            return Err(format!("❌ The slashed stakes in account {} can't be transferred.", SLASHED_ACCOUNT));
+        }
+        if transfer_info.from_account_id == CROSS_SHARD_ACCOUNT {
+            return Err(format!("❌ The funds in account {} moved to other shards or are refunded by the node.", CROSS_SHARD_ACCOUNT));
+        }
+
+        if transfer_info.fee.is_negative() {
+            return Err(format!("❌ Invalid fee {}, the fee can't be negative.", transfer_info.fee));
+        }
+        let min_fee = self.min_fee();
+        if transfer_info.fee < min_fee {
+            return Err(format!("❌ The fee {} is too low, this node only accepts transfers with a fee of at least {}.", transfer_info.fee, min_fee));
+        }
+
+        if let Some(memo) = &transfer_info.memo {
+            validate_memo(memo)?;
+        }
+
+        // Validate that neither account was closed
+        let closed = self.closed.lock().unwrap();
+        if let Some(account_id) = [&transfer_info.from_account_id, &transfer_info.to_account_id].into_iter().find(|account_id| closed.contains(*account_id)) {
+            return Err(format!("❌ Account {} is closed.", account_id));
+        }
+        drop(closed);
+        if self.frozen.lock().unwrap().is_frozen(&transfer_info.from_account_id) {
+            return Err(format!("❌ Account {} is frozen, no transfers can be made from it.", transfer_info.from_account_id));
+        }
+
+        // Validate that the transfer is made with the next nonce of the account, which rejects replayed transfers
+        if transfer_info.nonce != expected_nonce {
+            return Err(format!("❌ Invalid nonce {} for account {}, expected {}.", transfer_info.nonce, transfer_info.from_account_id, expected_nonce));
+        }
+
+        // Validate that the from account has sufficient funds for the amount and the fee, on top of the funds spent by
+        // its pending transactions, so that transfers that pass one by one can't overdraw the account together
+        let (balance, pending) = self.get_pending_balance(&transfer_info.from_account_id);
+        let total = transfer_info.amount.checked_add(transfer_info.fee).and_then(|total| total.checked_add(already_spent)).and_then(|total| total.checked_add(pending));
+        if total.is_none_or(|total| balance < total) {
+            let spent = if pending == Amount::ZERO { String::new() } else { format!(" ({} of its balance of {} is spent by pending transactions)", pending, balance) };
+            return Err(format!("❌ Insufficient funds in account {} to transfer {} with a fee of {}{}.", transfer_info.from_account_id, transfer_info.amount, transfer_info.fee, spent));
+        }
+
+        // Validate that the to account can hold the amount, on top of the funds its pending transactions credit it
+        let (to_balance, received) = self.get_pending_credits(&transfer_info.to_account_id);
+        if received.and_then(|received| to_balance.checked_add(received)).and_then(|total| total.checked_add(transfer_info.amount)).is_none() {
+            return Err(format!("❌ Transferring {} to account {} would overflow its balance of {}.", transfer_info.amount, transfer_info.to_account_id, to_balance));
+        }
+        Ok(())
+    }
+}
+
+/// Mint blocks every specified interval, and as soon as the mint threshold is reached if the node has one
+async fn mint_blocks(state: Arc<State>, mint_interval_in_seconds: u64) {
+    match state.mint_threshold {
+        Some(threshold) => info!("Minting blocks every {} seconds, or as soon as {} transactions are pending.", mint_interval_in_seconds, threshold),
+        None => info!("Minting blocks every {} seconds.", mint_interval_in_seconds),
+    }
+    loop {
+        debug!("Waiting {} seconds to mint the next block.", mint_interval_in_seconds);
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(mint_interval_in_seconds)) => {}
+            _ = state.mint_wakeup.notified() => debug!("Minting early, the mint threshold is reached."),
+        }
+
+        let shared_state = state.clone();
+        let minted = tokio::task::spawn_blocking(move || mint_block(&shared_state).is_some()).await.unwrap();
+        health::record_mint_round(&state, minted);
+        // Transactions left behind by a full block may still reach the threshold. Not after a round that minted
+        // nothing, e.g. while syncing, as the loop would spin until the sync is done.
+        if minted {
+            state.wake_minter(&state.mempool.lock().unwrap());
+        }
+    }
+}
+
+/// Mints a block from the highest priority transactions of the mempool, the payments due by the schedules and the
+/// slashes of the evidence received and adds it to the ledger, signed if the node has a validator key. In
+/// proof-of-stake mode the node only mints in the slots of its validator, the reward account.
+///
+/// Returns: the hash of the minted block, None if there was nothing to mint or the slot belongs to another validator
+fn mint_block(state: &State) -> Option<String> {
+    let started = std::time::Instant::now();
+    // Locked before the mempool like for transfers, as the scheduled payments depend on the closed and frozen accounts
+    let mut nonces = state.nonces.lock().unwrap();
+    let mut mempool = state.mempool.lock().unwrap();
+
+    // Build the block on top of the last block
+    let (height, previous_hash, previous_timestamp) = {
+        let ledger = state.ledger.read().unwrap();
+        let last = ledger.last().unwrap();
+        (ledger.len(), last.hash.clone(), last.header.timestamp)
+    };
+    let (scheduled, due) = {
+        let schedules = state.schedules.lock().unwrap();
+        (!schedules.is_empty(), schedules.due(height as u64).len())
+    };
+    if mempool.is_empty() && !scheduled && state.evidence.lock().unwrap().is_empty() {
+        debug!("Skipping block minting as there are no transactions, payment schedules or evidence to slash.");
+        return None;
+    }
+    if state.sync.lock().unwrap().is_syncing() {
+        debug!("Skipping block minting while syncing with a peer, the block would build on a stale tip.");
+        return None;
+    }
+    // Never before the block before it, even if the clock went back, as other nodes reject such blocks
+    let timestamp = state.clock.unix_time().max(previous_timestamp);
+    if state.consensus == Consensus::Pos {
+        let stakes = state.stakes.lock().unwrap();
+        let slot = timestamp / state.mint_interval().max(1);
+        match stake::validator_for(&stakes, &previous_hash, slot) {
+            Some(validator) if validator != state.reward_account => {
+                debug!(slot, validator, "Skipping block minting as the slot belongs to another validator.");
+                return None;
+            }
+            _ => {}
+        }
+    }
+    let _span = info_span!("mint", height).entered();
+    drop_expired(state, &mut nonces, &mut mempool, height as u64, timestamp);
+    let max_transactions = state.max_block_transactions();
+    let mut transactions = mempool.take_top(max_transactions.saturating_sub(due), state.max_block_bytes);
+    // Escrow timelocks are checked against the block, so a claim that was still pending when its escrow expired, or a
+    // settlement that wasn't valid yet, is dropped
+    {
+        let escrows = state.escrows.lock().unwrap();
+        transactions.retain(|transaction| match transaction.settles_escrow() {
+            Some((escrow_id, refund)) => match escrows.check_settlement(escrow_id, refund, height as u64, timestamp) {
+                Ok(_) => true,
+                Err(e) => {
+                    debug!(transaction = %transaction.id(), "Dropping the settlement of escrow {}, it {}.", escrow_id, e);
+                    state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as escrow {} {}", height, escrow_id, e));
+                    false
+                }
+            },
+            None => true,
+        });
+    }
+    // So is the acceptance of a swap offer that expired
+    {
+        let swaps = state.swaps.lock().unwrap();
+        transactions.retain(|transaction| match &transaction.kind {
+            TransactionKind::SwapAccept { offer_id, .. } => match swaps.check_acceptance(offer_id, transaction.from_account_id.as_deref().unwrap_or_default(), height as u64) {
+                Ok(_) => true,
+                Err(e) => {
+                    debug!(transaction = %transaction.id(), "Dropping the acceptance of swap offer {}, it {}.", offer_id, e);
+                    state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as swap offer {} {}", height, offer_id, e));
+                    false
+                }
+            },
+            _ => true,
+        });
+    }
+    // And a vote on a proposal that was decided in the meantime
+    {
+        let governance = state.governance.lock().unwrap();
+        transactions.retain(|transaction| match &transaction.kind {
+            TransactionKind::Vote { proposal_id, .. } => match governance.check_vote(proposal_id, transaction.from_account_id.as_deref().unwrap_or_default(), height as u64) {
+                Ok(()) => true,
+                Err(e) => {
+                    debug!(transaction = %transaction.id(), "Dropping the vote on proposal {}, it {}.", proposal_id, e);
+                    state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as proposal {} {}", height, proposal_id, e));
+                    false
+                }
+            },
+            _ => true,
+        });
+    }
+    // And an account creation or faucet payment that would issue funds beyond the maximum supply, when the ones before
+    // it issued what was left
+    let issuable = monetary::issuable(state.genesis.parameters.max_supply, &state.balances.lock().unwrap());
+    if let Some(mut left) = issuable {
+        transactions.retain(|transaction| match left.checked_sub(transaction.issued()).filter(|after| !after.is_negative()) {
+            Some(after) => {
+                left = after;
+                true
+            }
+            None => {
+                debug!(transaction = %transaction.id(), "Dropping transaction, it issues more than the {} left below the maximum supply.", left);
+                state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as it issues more than the {} left below the maximum supply", height, left));
+                false
+            }
+        });
+    }
+    // And a transaction that would overflow the balance of an account it credits, counting every credit of the block
+    // before it and none of the debits, or whose fee would overflow the block reward, whose subsidy is at most the one
+    // of the schedule
+    {
+        let balances = state.balances.lock().unwrap();
+        let mut credited: HashMap<String, Amount> = HashMap::new();
+        let mut reward = monetary::scheduled_reward(&state.genesis.parameters, height as u64);
+        transactions.retain(|transaction| {
+            let Some(rewarded) = reward.checked_add(transaction.fee) else {
+                debug!(transaction = %transaction.id(), "Dropping transaction, its fee overflows the block reward.");
+                state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as its fee overflows the block reward", height));
+                return false;
+            };
+            let mut updated = Vec::new();
+            for (account_id, amount) in transaction.native_credits() {
+                let balance = updated.iter().rev().find(|(updated_id, _)| *updated_id == account_id).map(|(_, balance)| *balance);
+                let balance = balance.or_else(|| credited.get(account_id).copied()).unwrap_or_else(|| balances.get(account_id).copied().unwrap_or_default());
+                match balance.checked_add(amount) {
+                    Some(balance) => updated.push((account_id, balance)),
+                    None => {
+                        debug!(transaction = %transaction.id(), "Dropping transaction, it overflows the balance of account {}.", account_id);
+                        state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as it overflows the balance of account {}", height, account_id));
+                        return false;
+                    }
+                }
+            }
+            credited.extend(updated.into_iter().map(|(account_id, balance)| (account_id.to_string(), balance)));
+            reward = rewarded;
+            true
+        });
+    }
+    // The stakes slashed by the block are slashed first, so an unstake of a slashed validator can't be minted anymore
+    let slashed = slashes(state);
+    transactions.retain(|transaction| {
+        let unstakes_slashed = transaction.kind == TransactionKind::Unstake && slashed.iter().any(|slash| transaction.from_account_id.as_ref() == Some(&slash.to_account_id));
+        if unstakes_slashed {
+            debug!(transaction = %transaction.id(), "Dropping the unstake of a validator slashed by the block.");
+            state.rejected.lock().unwrap().record(transaction.id(), format!("dropped when minting block {} as the validator was slashed by it", height));
+        }
+        !unstakes_slashed
+    });
+    let mut payments = scheduled_payments(state, &mempool, &transactions, height as u64);
+    payments.truncate(max_transactions.saturating_sub(transactions.len()));
+    if let Some(max_bytes) = state.max_block_bytes {
+        let mut bytes_left = max_bytes.saturating_sub(transactions.iter().map(Transaction::encoded_size).sum());
+        payments.retain(|payment| {
+            let fits = payment.encoded_size() <= bytes_left;
+            if fits {
+                bytes_left -= payment.encoded_size();
+            }
+            fits
+        });
+    }
+    transactions.extend(payments);
+    // A block starting an epoch distributes the staking reward by the stakes before it, on top of the block's limit
+    // like the block reward, as every node expects all of them in the block
+    let issuable = monetary::issuable_after(issuable, &transactions);
+    transactions.extend(staking_rewards(state, height as u64, issuable));
+
+    // The fees of the block and the newly issued subsidy are paid to the minting node with a reward transaction at the
+    // end of the block. In proof-of-stake mode blocks always have one, as it names the validator that minted the block.
+    let subsidy = monetary::block_subsidy(&state.genesis.parameters, height as u64, monetary::issuable_after(issuable, transactions.iter().filter(|transaction| transaction.is_staking_reward())));
+    let reward = Transaction::new_reward(state.node_id.clone(), state.reward_account.clone(), &transactions, subsidy, state.clock.now());
+    if reward.amount > Amount::ZERO || state.consensus == Consensus::Pos {
+        transactions.push(reward);
+    }
+    // On top of the block's limit too, as the evidence is slashed in the first block this node mints
+    let transactions = slashed.into_iter().chain(transactions).collect();
+    let mut block = Block::new(previous_hash, height, timestamp, transactions);
+    // Blocks are signed for the validator their reward pays with one of its keys, one without a reward can't be
+    // attributed to it
+    if let Some(key) = state.signing_key() {
+        if block.transactions.last().is_some_and(Transaction::is_reward) {
+            block.signature = Some(BlockSignature::new(&block, &state.reward_account, key));
+        }
+    }
+    block.node_signature = Some(NodeSignature::new(&block, &state.identity));
+
+    info!(hash = %block.hash, transactions = block.transactions.len(), pending = mempool.len(), reward = %block.reward(), "Block minted.");
+
+    // Add the block to the ledger. The mempool stays locked until then, so transactions are never missing from both
+    let hash = block.hash.clone();
+    let transaction_ids = Block::transaction_ids(&block.transactions);
+    if let Err(e) = state.add_block(block) {
+        error!(hash = %hash, "The minted block can't be added, {}.", e);
+        let mut rejected = state.rejected.lock().unwrap();
+        for transaction_id in transaction_ids {
+            rejected.record(transaction_id, format!("dropped when minting block {} as {}", height, e));
+        }
+        return None;
+    }
+    state.truncate_wal(&mempool);
+    state.metrics.observe_minting(started.elapsed());
+    Some(hash)
+}
+
+/// Drops the pending transactions that expired before the block at the given height with the given timestamp. The
+/// transactions after them are validated again without them, dropping those that can't be minted anymore, e.g. the
+/// later transfers of an account whose nonces follow the expired transfer, and the nonces, closed and frozen accounts
+/// and aliases are rebuilt from the transactions left, like when replaying the write-ahead log.
+fn drop_expired(state: &State, nonces: &mut HashMap<String, u64>, mempool: &mut Mempool, height: u64, timestamp: u64) {
+    if !mempool.transactions().any(|transaction| transaction.has_expired(height, timestamp)) {
+        return;
+    }
+    let ledger = state.ledger.read().unwrap();
+    let snapshots = state.snapshots.lock().unwrap();
+    let mut accounts = match verify::verify_chain_with(&ledger, &state.genesis, snapshots.pruned(), |_, _, _| Ok(())) {
+        Ok(accounts) => accounts,
+        Err(e) => {
+            error!("Can't drop the expired transactions, the chain of this node isn't valid. {}", e);
+            return;
+        }
+    };
+    let (mut kept, mut expired, mut dropped) = (Vec::new(), 0, 0);
+    let mut rejected = state.rejected.lock().unwrap();
+    for transaction in mempool.drain() {
+        if let Some(valid_until) = transaction.valid_until().filter(|valid_until| valid_until.has_passed(height, timestamp)) {
+            debug!(transaction = %transaction.id(), "Dropping transaction, it expired at {}.", valid_until);
+            rejected.expire(transaction.id(), format!("valid until {} but not minted before block {}", valid_until, height));
+            expired += 1;
+            continue;
+        }
+        match accounts.apply_pending(&transaction, height, timestamp) {
+            Ok(()) => kept.push(transaction),
+            Err(reason) => {
+                debug!(transaction = %transaction.id(), "Dropping transaction after an expired one, it {}.", reason);
+                rejected.record(transaction.id(), format!("dropped when minting block {} after an earlier transaction expired, as it {}", height, reason));
+                dropped += 1;
+            }
+        }
+    }
+    *nonces = accounts.nonces;
+    *state.closed.lock().unwrap() = accounts.closed;
+    *state.frozen.lock().unwrap() = accounts.frozen;
+    *state.aliases.lock().unwrap() = accounts.aliases;
+    info!(expired, dropped, "Dropped expired transactions from the mempool.");
+    mempool.restore(kept);
+}
+
+/// Returns the payments due by the schedules at the given height that can be made after the given transactions of the
+/// block. A payment is missed if the paying account can't afford it on top of what its pending transactions spend, if
+/// it is frozen or being frozen or unfrozen, if the recipient is closed or being closed, or if the schedule is being
+/// cancelled, so the payment is valid whichever of the pending transactions are minted before it.
+fn scheduled_payments(state: &State, mempool: &Mempool, transactions: &[Transaction], height: u64) -> Vec<Transaction> {
+    let balances = state.balances.lock().unwrap();
+    let closed = state.closed.lock().unwrap();
+    let frozen = state.frozen.lock().unwrap();
+    let schedules = state.schedules.lock().unwrap();
+    let pending: Vec<&Transaction> = transactions.iter().chain(mempool.transactions()).collect();
+    let mut spent: HashMap<&str, Amount> = HashMap::new();
+    let mut payments = Vec::new();
+    for (schedule_id, schedule) in schedules.due(height) {
+        let sender = schedule.sender.as_str();
+        let spent_by = spent.entry(sender).or_insert_with(|| {
+            pending.iter().filter(|transaction| transaction.from_account_id.as_deref() == Some(sender)).map(|transaction| transaction.native_amount() + transaction.fee).sum()
+        });
+        let balance = balances.get(sender).copied().unwrap_or_default();
+        let affordable = spent_by.checked_add(schedule.amount).is_some_and(|total| total <= balance);
+        let blocked = frozen.is_frozen(sender)
+            || closed.contains(sender)
+            || closed.contains(&schedule.recipient)
+            || pending.iter().any(|transaction| {
+                (transaction.freezes().is_some() && transaction.to_account_id == sender) || matches!(&transaction.kind, TransactionKind::CancelSchedule(pending) if pending == schedule_id)
+            });
+        if !affordable || blocked {
+            debug!(schedule = %schedule_id, affordable, "Missing the scheduled payment.");
+            continue;
+        }
+        *spent_by += schedule.amount;
+        payments.push(Transaction {
+            kind: TransactionKind::ScheduledPayment(schedule_id.clone()),
+            ..Transaction::new(state.node_id.clone(), Some(schedule.sender.clone()), schedule.recipient.clone(), schedule.amount, Amount::ZERO, None, state.clock.now(), None)
+        });
+    }
+    payments
+}
+
+/// Returns the staking reward transactions of the block at the given height, none if it doesn't start an epoch, cut to
+/// what is left to issue below the maximum supply
+fn staking_rewards(state: &State, height: u64, issuable: Option<Amount>) -> Vec<Transaction> {
+    let stakes = state.stakes.lock().unwrap();
+    let governance = state.governance.lock().unwrap();
+    let epoch = stake::epoch_of(governance.parameters(), height);
+    stake::epoch_rewards(&stakes, governance.parameters(), height, issuable)
+        .into_iter()
+        .map(|(account_id, share)| Transaction {
+            kind: TransactionKind::StakingReward { epoch },
+            ..Transaction::new(state.node_id.clone(), None, account_id, share, Amount::ZERO, None, state.clock.now(), None)
+        })
+        .collect()
+}
+
+/// Returns the slashes of the stakes of the validators this node has evidence against, for the block after the ledger.
+/// Evidence that doesn't prove an offence anymore, or against a validator without a stake, is dropped.
+fn slashes(state: &State) -> Vec<Transaction> {
+    let stakes = state.stakes.lock().unwrap();
+    let multisig = state.multisig.lock().unwrap();
+    let slashed = state.slashed.lock().unwrap();
+    let mut pool = state.evidence.lock().unwrap();
+    let mut slashes: Vec<Transaction> = Vec::new();
+    for evidence in pool.drain(..) {
+        let validator = evidence.validator().to_string();
+        let stake = stakes.get(&validator).copied().unwrap_or_default();
+        let slashable = evidence.check(&multisig).is_ok()
+            && slashed.get(&validator).is_none_or(|height| evidence.height() > *height)
+            && stake > Amount::ZERO
+            && !slashes.iter().any(|slash| slash.to_account_id == validator);
+        if !slashable {
+            debug!(validator, height = evidence.height(), "Dropping evidence that can't be slashed.");
+            continue;
+        }
+        info!(validator, height = evidence.height(), %stake, "Slashing validator for signing conflicting blocks.");
+        slashes.push(Transaction {
+            kind: TransactionKind::Slash(Box::new(evidence)),
+            ..Transaction::new(state.node_id.clone(), None, validator, stake, Amount::ZERO, None, state.clock.now(), None)
+        });
+    }
+    slashes
+}
+
+/// Compacts the blocks of the ledger file that are more than the given number of blocks below the tip into the archive
+/// every compaction interval, until the node shuts down
+fn compact_storage(state: Arc<State>, keep: usize) {
+    let Some(storage) = &state.storage else {
+        return;
+    };
+    loop {
+        std::thread::sleep(COMPACTION_INTERVAL);
+        if state.shutting_down.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut storage = storage.lock().unwrap();
+        match storage.compact(keep) {
+            Ok(0) => {}
+            Ok(compacted) => info!(compacted, archived = storage.archived(), "Compacted old blocks into the archive."),
+            Err(e) => error!("{}", e),
+        }
+    }
+}
+
+/// Shuts the node down cleanly: the transactions left in the mempool are minted, so no accepted transaction is lost,
+/// and the storage is flushed to disk
+fn shutdown(state: &State) {
+    info!("Shutting down, minting the {} transactions left in the mempool.", state.mempool.lock().unwrap().len());
+    // Stops once the mempool is empty, as blocks with scheduled payments could be minted on and on
+    while !state.mempool.lock().unwrap().is_empty() {
+        if mint_block(state).is_none() {
+            break;
+        }
+    }
+    let left = state.mempool.lock().unwrap().len();
+    if left > 0 {
+        warn!("{} pending transactions weren't minted, the slot belongs to another validator.", left);
+    }
+
+    match &state.storage {
+        Some(storage) => match storage.lock().unwrap().sync() {
+            Ok(()) => info!("Ledger with {} blocks flushed to storage.", state.ledger.read().unwrap().len()),
+            Err(e) => error!("{}", e),
+        },
+        None => warn!("No data directory configured, the ledger is not persisted."),
+    }
+    info!("Server stopped.");
+}
+
+/// Starts shutting the node down. The request loop notices it and stops accepting requests. A second shutdown request
+/// (e.g. pressing Ctrl-C twice) exits immediately.
+fn request_shutdown(state: &State) {
+    if state.shutting_down.swap(true, Ordering::SeqCst) {
+        warn!("Shutdown requested again, exiting immediately.");
+        std::process::exit(1);
+    }
+    state.shutdown_requested.notify_one();
+}
+
+/// Requests a shutdown whenever the node receives SIGINT (Ctrl-C) or SIGTERM
+async fn watch_shutdown_signals(state: Arc<State>) {
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("Failed to set the shutdown signal handler.");
+    loop {
+        #[cfg(unix)]
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result.expect("Failed to set the shutdown signal handler."),
+            _ = terminate.recv() => {}
+        }
+        #[cfg(not(unix))]
+        tokio::signal::ctrl_c().await.expect("Failed to set the shutdown signal handler.");
+
+        request_shutdown(&state);
+    }
+}
+
+/// Initializes the blockchain server on the configured address and port. The server listens for requests from clients
+/// and processes them. The server also mints blocks every configured interval and adds them to the ledger.
+///
+/// # Arguments
+///
+/// * `config`: the node config holding the bind address, port, mint interval, genesis file and data directory of the
+///   server
+///
+/// Requests are received and answered asynchronously on a tokio runtime, and each request is processed as its own task.
+/// The state is only locked on the blocking thread pool (never across an `.await`), so a slow request, e.g. a long
+/// history query, doesn't hold up other clients or the minter.
+///
+/// A light node only follows the headers of its peer instead, see [light].
+///
+/// Returns: This function should be called only once and runs until the node is shut down (SIGINT, SIGTERM or a
+/// shutdown request), or why the node couldn't be started, e.g. an invalid genesis file, a port in use or a stored
+/// chain that doesn't verify.
+pub fn init_server(config: NodeConfig) -> Result<(), String> {
+    let runtime = tokio::runtime::Runtime::new().map_err(|e| format!("Failed to start the async runtime: {}", e))?;
+    if config.light {
+        runtime.block_on(light::run_light_node(config))
+    } else {
+        runtime.block_on(run_server(config))
+    }
+}
+
+/// Validates the config and builds the state of a chain of the node from it, restoring the chain and pending
+/// transactions from the data directory if there is one. Nothing is bound or started yet.
+///
+/// Returns: the state, or why the node can't be started
+fn open_state(config: &NodeConfig, genesis: Genesis) -> Result<Arc<State>, String> {
+    if config.min_fee.is_negative() {
+        return Err(format!("Invalid minimum fee {}, the fee can't be negative.", config.min_fee));
+    }
+    if config.enable_faucet && config.faucet_amount <= Amount::ZERO {
+        return Err(format!("Invalid faucet amount {}, the faucet has to pay more than 0.", config.faucet_amount));
+    }
+    if config.checkpoint_interval == Some(0) {
+        return Err("Invalid checkpoint interval 0, there has to be at least 1 block between checkpoints.".to_string());
+    }
+    if config.snapshot_interval == Some(0) {
+        return Err("Invalid snapshot interval 0, there has to be at least 1 block between snapshots.".to_string());
+    }
+    if config.mint_threshold == Some(0) {
+        return Err("Invalid mint threshold 0, a block needs at least 1 pending transaction.".to_string());
+    }
+    if config.mint_threshold.is_some() && (config.consensus == Consensus::Pos || config.simulate) {
+        return Err("A mint threshold only works with the timer consensus and the system clock, proof-of-stake nodes mint once per slot and simulating nodes when their clock is advanced.".to_string());
+    }
+    if config.max_block_txs == Some(0) || config.max_block_bytes == Some(0) {
+        return Err("Invalid block limit 0, the blocks the node mints have to fit at least 1 transaction.".to_string());
+    }
+    if config.compact_after == Some(0) {
+        return Err("Invalid compaction depth 0, the latest block is never compacted.".to_string());
+    }
+    if config.compact_after.is_some() && config.data_dir.is_none() {
+        return Err("Compaction needs a data directory to compact the ledger file of. Set one with --data-dir.".to_string());
+    }
+    if config.prune && config.snapshot_interval.is_none() {
+        return Err("Pruning needs snapshots, the blocks are pruned up to the latest one. Set a snapshot interval with --snapshot-interval.".to_string());
+    }
+
+    let vault = match &config.data_dir {
+        Some(data_dir) => Vault::unlock(data_dir, config.secret.as_ref(), true)?,
+        None => None,
+    };
+    let (storage, stored_blocks) = match &config.data_dir {
+        Some(data_dir) => {
+            let (storage, blocks) = Storage::open(data_dir, vault.as_ref())?;
+            (Some(Mutex::new(storage)), blocks)
+        }
+        None => (None, Vec::new()),
+    };
+    let (wal, logged) = match &config.data_dir {
+        Some(data_dir) => {
+            let (wal, transactions) = Wal::open(data_dir, vault.as_ref())?;
+            (Some(Mutex::new(wal)), transactions)
+        }
+        None => (None, Vec::new()),
+    };
+
+    let identity = identity::load(config.data_dir.as_deref(), vault.as_ref())?;
+    let node_id = keys::public_key_hex(&identity);
+
+    let encryption = Encryption::new(config.data_dir.as_deref(), vault.as_ref())?;
+
+    let checkpoints = Checkpoints::load(config.data_dir.as_deref(), config.checkpoint_interval)?;
+    checkpoints.check(&stored_blocks).map_err(|e| format!("The chain in the data directory conflicts with its checkpoints. {}", e))?;
+
+    let workers = config.workers.unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |parallelism| parallelism.get()));
+    info!("Processing up to {} requests at once.", workers);
+    let audit = config.audit_log.as_deref().map(AuditLog::open).transpose()?;
+    if let Some(path) = &config.audit_log {
+        info!("Recording the requests processed in audit log {}.", path);
+    }
+    let webhooks = Webhooks::load(config.data_dir.as_deref())?;
+    let snapshots = Snapshots::load(config.data_dir.as_deref(), config.snapshot_interval, config.prune, vault.as_ref())?;
+    if snapshots.is_pruning() {
+        info!("Pruning the transactions of the blocks before the latest snapshot.");
+    }
+
+    let admin_key = match config.admin_key.as_deref().map(keys::parse_public_key) {
+        Some(Ok(key)) => Some(noise::to_hex(key.as_bytes())),
+        Some(Err(e)) => return Err(format!("Invalid admin key: {}", e)),
+        None => None,
+    };
+    let validator_key = match config.validator_key.as_deref().map(keys::parse_private_key) {
+        Some(Ok(key)) => Some(key),
+        Some(Err(e)) => return Err(format!("Invalid validator key: {}", e)),
+        None => None,
+    };
+    let allowlist = Allowlist::new(&config.allow)?;
+    if allowlist.is_permissioned() {
+        info!("Permissioned mode, only accepting requests from the {} allowed clients and peers.", allowlist.len());
+    }
+    if let Some(address) = config.bootstrap.iter().find(|address| address.parse::<SocketAddr>().is_err()) {
+        return Err(format!("Invalid bootstrap peer {}, expected an address like 192.168.1.10:1337.", address));
+    }
+
+    let shard = match (&config.shard_map, config.shard) {
+        (Some(path), Some(index)) => Some(Shard::new(ShardMap::load(path)?, index)?),
+        (None, None) => None,
+        (Some(_), None) => return Err("A sharded node needs the shard it holds. Set it with --shard.".to_string()),
+        (None, Some(_)) => return Err("A sharded node needs the shard map of its cluster. Set it with --shard-map.".to_string()),
+    };
+    if let Some(shard) = &shard {
+        shard.check_genesis(&genesis)?;
+        info!("Holding shard {} of {}, only accepting requests about the accounts of the shard.", shard.index(), shard.count());
+    }
+
+    let simulation = config.simulate.then(Simulation::new);
+    let clock = simulation.as_ref().map_or_else(|| Arc::new(SystemClock) as Arc<dyn Clock>, Simulation::clock);
+
+    let state = Arc::new(State {
+        genesis,
+        ledger: RwLock::new(Vec::new()),
+        index: Mutex::new(TransactionIndex::default()),
+        mempool: Mutex::new(Mempool::new(config.mempool_size, config.mempool_sender_limit)),
+        balances: Mutex::new(HashMap::new()),
+        nonces: Mutex::new(HashMap::new()),
+        events: EventHub::default(),
+        storage,
+        wal,
+        shutting_down: AtomicBool::new(false),
+        shutdown_requested: Notify::new(),
+        metrics: Metrics::default(),
+        rate_limiter: RateLimiter::new(config.rate_limit, config.rate_limit_burst),
+        encryption,
+        responses: ResponseCache::default(),
+        reward_account: config.reward_account.clone().unwrap_or_else(|| node_id.clone()),
+        node_id,
+        identity,
+        allowlist,
+        min_fee: config.min_fee,
+        started: std::time::Instant::now(),
+        fragments: Mutex::new(Reassembler::default()),
+        multisig: Mutex::new(HashMap::new()),
+        approvals: Mutex::new(PendingApprovals::default()),
+        closed: Mutex::new(HashSet::new()),
+        admin_key,
+        frozen: Mutex::new(FrozenAccounts::default()),
+        forks: Mutex::new(Forks::default()),
+        orphans: Mutex::new(Orphans::default()),
+        consensus: config.consensus,
+        mint_interval: config.mint_interval,
+        max_clock_skew: config.max_clock_skew,
+        max_block_txs: config.max_block_txs,
+        max_block_bytes: config.max_block_bytes,
+        mint_threshold: config.mint_threshold,
+        mint_wakeup: Notify::new(),
+        mint_now_signed_at: Mutex::new(0),
+        stakes: Mutex::new(HashMap::new()),
+        validator_key,
+        slashed: Mutex::new(HashMap::new()),
+        evidence: Mutex::new(Vec::new()),
+        checkpoints: Mutex::new(checkpoints),
+        snapshots: Mutex::new(snapshots),
+        verified: Mutex::new(None),
+        aliases: Mutex::new(HashMap::new()),
+        tokens: Mutex::new(Tokens::default()),
+        nfts: Mutex::new(Nfts::default()),
+        escrows: Mutex::new(Escrows::default()),
+        cross_shard: Mutex::new(CrossShardTransfers::default()),
+        schedules: Mutex::new(Schedules::default()),
+        swaps: Mutex::new(Swaps::default()),
+        contracts: Mutex::new(Contracts::default()),
+        governance: Mutex::new(Governance::default()),
+        peers: Mutex::new(PeerManager::new(&config.bootstrap)),
+        sync: Mutex::new(SyncStatus::default()),
+        gossip: Mutex::new(Gossip::default()),
+        mint_rounds: Mutex::new(MintRounds::new()),
+        rejected: Mutex::new(Rejections::default()),
+        workers: Workers::new(workers),
+        sequencer: Arc::new(Sequencer::default()),
+        audit: audit.map(Mutex::new),
+        webhooks: Mutex::new(webhooks),
+        faucet: config.enable_faucet.then(|| Mutex::new(Faucet::new(config.faucet_amount, Duration::from_secs(config.faucet_cooldown)))),
+        clock,
+        simulation,
+        shard,
+    });
+    info!("Paying block rewards to account {}, accepting transfers with a fee of at least {}.", state.reward_account, state.min_fee);
+    if state.faucet.is_some() {
+        info!("Paying up to {} from the faucet to every account every {} seconds.", config.faucet_amount, config.faucet_cooldown);
+    }
+
+    let genesis_block = state.genesis.to_block();
+    let pruned = state.snapshots.lock().unwrap().pruned().cloned();
+    if stored_blocks.is_empty() {
+        info!("Chain {} started from genesis block {} with {} accounts.", state.genesis.chain_id, genesis_block.hash, genesis_block.transactions.len());
+        state.add_block(genesis_block).map_err(|e| format!("The genesis block can't be added, {}.", e))?;
+    } else if let Err(e) = verify::verify_chain_with(&stored_blocks, &state.genesis, pruned.as_ref(), |_, _, _| Ok(())) {
+        return Err(format!("The chain in the data directory can't be restored. {}", e));
+    } else {
+        info!("Chain {} restored from storage with {} blocks.", state.genesis.chain_id, stored_blocks.len());
+        state.restore_ledger(stored_blocks);
+    }
+    state.replay_pending(logged);
+    if let Some(key) = &state.validator_key {
+        let public_key = keys::public_key_hex(key);
+        match state.multisig.lock().unwrap().get(&state.reward_account) {
+            Some(policy) if policy.public_keys.contains(&public_key) => info!("Signing minted blocks with validator key {}.", public_key),
+            _ => warn!("Validator key {} isn't a key of the multisig account {}, blocks aren't signed until the account is created with it.", public_key, state.reward_account),
+        }
+    }
+    Ok(state)
+}
+
+/// Starts minting blocks every mint interval, or when the simulated clock is advanced past the next block time
+fn start_minting(state: &Arc<State>, mint_interval_in_seconds: u64) {
+    match &state.simulation {
+        Some(simulation) => simulation.start(state.ledger.read().unwrap().last().unwrap().header.timestamp, state.mint_interval()),
+        None => {
+            tokio::spawn(mint_blocks(state.clone(), mint_interval_in_seconds));
+        }
+    }
+}
+
+async fn run_server(config: NodeConfig) -> Result<(), String> {
+    let chains = Arc::new(Chains::open(&config)?);
+    let state = chains.default_chain().clone();
+
+    let addr = format!("{}:{}", config.bind, config.port);
+    let socket = UdpSocket::bind(&addr)
+        .await
+        .map_err(|e| format!("Failed to bind to address {}. Make sure PORT {} is not in use. Error: {}", addr, config.port, e))?;
+    let socket = Arc::new(socket);
+    info!("Server started on {}.", addr);
+
+    tokio::spawn(watch_shutdown_signals(state.clone()));
+
+    if let Some(rpc_port) = config.rpc_port {
+        let rpc_addr = format!("{}:{}", config.bind, rpc_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || rpc::serve(shared_state, &rpc_addr));
+    }
+
+    if let Some(rest_port) = config.rest_port {
+        let rest_addr = format!("{}:{}", config.bind, rest_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || rest::serve(shared_state, &rest_addr));
+    }
+
+    if let Some(graphql_port) = config.graphql_port {
+        let graphql_addr = format!("{}:{}", config.bind, graphql_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || graphql::serve(shared_state, &graphql_addr));
+    }
+
+    if let Some(grpc_port) = config.grpc_port {
+        let grpc_addr = format!("{}:{}", config.bind, grpc_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || grpc::serve(shared_state, &grpc_addr));
+    }
+
+    if let Some(ws_port) = config.ws_port {
+        let ws_addr = format!("{}:{}", config.bind, ws_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || websocket::serve(shared_state, &ws_addr));
+    }
+
+    if let Some(metrics_port) = config.metrics_port {
+        let metrics_addr = format!("{}:{}", config.bind, metrics_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || metrics::serve(shared_state, &metrics_addr));
+    }
+
+    if let Some(explorer_port) = config.explorer_port {
+        let explorer_addr = format!("{}:{}", config.bind, explorer_port);
+        let shared_state = state.clone();
+        std::thread::spawn(move || explorer::serve(shared_state, &explorer_addr));
+    }
+
+    if let Some(keep) = config.compact_after {
+        info!("Compacting the blocks more than {} blocks below the tip into the archive.", keep);
+    }
+    // Every chain notifies its webhooks, compacts its storage and mints on its own
+    chains.for_each(|chain| {
+        let shared_state = chain.clone();
+        std::thread::spawn(move || webhook::notify(shared_state));
+        if let Some(keep) = config.compact_after {
+            let shared_state = chain.clone();
+            std::thread::spawn(move || compact_storage(shared_state, keep));
+        }
+        start_minting(chain, config.mint_interval);
+    });
+    if !chains.names().is_empty() {
+        info!("Hosting the named chains {} besides the default chain.", chains.names().join(", "));
+    }
+    chains.forward_shutdown_requests();
+    if !config.bootstrap.is_empty() {
+        info!("Bootstrapping from peers {}.", config.bootstrap.join(", "));
+    }
+    tokio::spawn(peers::exchange_peers(state.clone(), config.port));
+    tokio::spawn(sync::sync_blocks(state.clone()));
+    tokio::spawn(gossip::gossip_transactions(state.clone()));
+    if let Some(shard) = state.shard.as_ref().filter(|shard| shard.transfers_between_shards()) {
+        match shard.node_id(shard.index()) == Some(state.node_id.as_str()) {
+            true => {
+                info!("Settling the transfers to other shards every few seconds.");
+                tokio::spawn(cross_shard::settle_transfers(state.clone()));
+            }
+            false => warn!("The shard map lists another node ID for shard {}, the other shards don't credit the transfers of this node.", shard.index()),
+        }
+    }
+    if config.mdns {
+        let interface = config.bind.parse().unwrap_or(std::net::Ipv4Addr::UNSPECIFIED);
+        let shared_state = state.clone();
+        std::thread::spawn(move || mdns::serve(shared_state, interface, config.port));
+    }
+
+    // Receive requests until the node is shut down, every request is handled by its own task
+    let mut requests = JoinSet::new();
+    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        tokio::select! {
+            _ = state.shutdown_requested.notified() => break,
+            received = socket.recv_from(&mut buf) => match received {
+                Ok((amt, src)) => {
+                    let ticket = state.sequencer.admit();
+                    requests.spawn(handle_datagram(chains.clone(), socket.clone(), buf[..amt].to_vec(), src, ticket));
+                }
+                Err(e) => warn!("Failed to receive request: {}", e),
+            },
+            // Reap finished requests so the set doesn't grow
+            Some(_) = requests.join_next(), if !requests.is_empty() => {}
+        }
+    }
+
+    // Let requests that were already received finish before minting the last blocks
+    while requests.join_next().await.is_some() {}
+    tokio::task::spawn_blocking(move || {
+        chains.for_each(|chain| {
+            chain.shutting_down.store(true, Ordering::SeqCst);
+            shutdown(chain);
+        })
+    })
+    .await
+    .unwrap();
+    Ok(())
+}
+
+/// Handles a request datagram received from a client and sends the response back to it. Fragments are collected until
+/// the request is complete. The ticket holds the place of the datagram in the order datagrams arrived in.
+async fn handle_datagram(chains: Arc<Chains>, socket: Arc<UdpSocket>, bytes: Vec<u8>, src: SocketAddr, ticket: Ticket) {
+    // Requests are received by the default chain, which holds the sessions and rate limits of the node
+    let state = chains.default_chain().clone();
+    // A request in fragments takes its token with its first fragment, so the fragments of a rate limited client aren't
+    // even buffered. They are dropped without a response, as there is no request yet to answer in its version.
+    let (bytes, limited) = if fragment::is_fragment(&bytes) {
+        let mut fragments = state.fragments.lock().unwrap();
+        if fragments.starts_message(src, &bytes) && state.rate_limiter.check(src.ip()).is_err() {
+            debug!("Dropped fragment of a rate limited request from {}.", src);
+            state.metrics.observe_rate_limited("udp");
+            return;
+        }
+        match fragments.add(src, &bytes) {
+            Ok(Some(packet)) => (packet, Ok(())),
+            Ok(None) => return,
+            Err(e) => {
+                debug!("Dropped fragment from {}: {}", src, e);
+                return;
+            }
+        }
+    } else {
+        let limited = state.rate_limiter.check(src.ip());
+        (bytes, limited)
+    };
+
+    // Rate limited requests are rejected before they are even deserialized
+    if let Err(retry_after) = limited {
+        debug!("Rate limited request from {}.", src);
+        state.metrics.observe_rate_limited("udp");
+        let retry_after_ms = retry_after.as_millis().max(1) as u64;
+        let response = format!("⏳ Rate limit exceeded, retry in {} ms.", retry_after_ms);
+        // Encrypted clients expect every response in a packet, the error is sent in plaintext either way
+        let response = if noise::is_noise_packet(&bytes) {
+            noise::encode(noise::PacketKind::Error, response.as_bytes())
+        } else {
+            let version = common::protocol_version(&bytes).min(PROTOCOL_VERSION);
+            let (format, accepted) = common::response_encoding(&bytes);
+            Response::new(version, None, Err(response)).with_error_code(ErrorCode::RateLimited { retry_after_ms }).encode_as(format, accepted)
+        };
+        send_response(&socket, &response, src).await;
+        return;
+    }
+
+    // Processing locks the state, so it runs on the blocking thread pool instead of holding up the runtime
+    let response = tokio::task::spawn_blocking(move || {
+        if noise::is_noise_packet(&bytes) {
+            state.encryption.handle(&bytes, src, |request, client_key| respond(&chains, request, src, Some(client_key), &ticket))
+        } else {
+            respond(&chains, &bytes, src, None, &ticket)
+        }
+    })
+    .await
+    .unwrap();
+
+    if let Some(response) = response {
+        send_response(&socket, &response, src).await;
+    }
+}
+
+/// Sends a response to a client, in fragments if it doesn't fit into a single datagram
+async fn send_response(socket: &UdpSocket, response: &[u8], src: SocketAddr) {
+    for datagram in fragment::split(response, common::random_id()) {
+        if let Err(e) = socket.send_to(&datagram, src).await {
+            warn!("Failed to send response: {}", e);
+            return;
+        }
+    }
+}
+
+/// Decodes and processes a request, either received in plaintext or decrypted from an encrypted session with the
+/// given static key of the client
+///
+/// Returns: the encoded response to send back to the client, or None if the request couldn't be decoded
+fn respond(chains: &Chains, bytes: &[u8], src: SocketAddr, client_key: Option<&[u8]>, ticket: &Ticket) -> Option<Vec<u8>> {
+    let state = chains.default_chain();
+    let request = match common::Request::decode(bytes) {
+        Ok(request) => request,
+        Err(RequestError::Malformed(e)) => {
+            warn!("Failed to deserialize request: {} - from: {}", e, src);
+            return None;
+        }
+        Err(e @ RequestError::UnsupportedVersion(_)) => {
+            warn!("Rejected request from {}: {}", src, e);
+            return Some(Response::new(PROTOCOL_VERSION, None, Err(e.to_string())).encode());
+        }
+    };
+
+    let operation = request.operation.name();
+    let (version, request_id) = (request.version, request.request_id);
+    // The response is sent in the format of the request, compressed if it is large and the client accepts it
+    let (format, accepted) = common::response_encoding(bytes);
+    let encrypted = client_key.is_some();
+    let _span = info_span!("request", operation, from = %request.from_node, %src, encrypted, version).entered();
+
+    // In permissioned mode only the requests of allowed clients and peers are processed
+    if let Err(e) = state.allowlist.check(&request, client_key) {
+        debug!("Rejected unauthorized request: {}", e);
+        state.metrics.observe_unauthorized("udp");
+        let result = Err(e.to_string());
+        audit::record(state, Audited { transport: "udp", source: src, from_node: &request.from_node, operation, result: &result });
+        let error_code = ErrorCode::Unauthorized { reason: e.code().to_string() };
+        return Some(Response::new(version, request_id, result).with_error_code(error_code).encode_as(format, accepted));
+    }
+
+    // Requests carrying invalid fields are rejected before any state is locked, see [validation]
+    if let Err(e) = validation::validate(&request, bytes.len()) {
+        debug!("Rejected invalid request: {:?}", e);
+        let result = Err(e.to_string());
+        audit::record(state, Audited { transport: "udp", source: src, from_node: &request.from_node, operation, result: &result });
+        return Some(Response::new(version, request_id, result).with_error_code(e.error_code()).encode_as(format, accepted));
+    }
+
+    // Requests are processed by the chain they are for, see [chains]
+    let chain = match chains.get(request.chain.as_deref()) {
+        Ok(chain) => chain,
+        Err(e) => {
+            debug!("Rejected request for a chain the node doesn't host: {}", e);
+            let result = Err(e);
+            audit::record(state, Audited { transport: "udp", source: src, from_node: &request.from_node, operation, result: &result });
+            return Some(Response::new(version, request_id, result).encode_as(format, accepted));
+        }
+    };
+
+    // Retries of a request are answered from the cache, so e.g. a transfer whose response was lost isn't made twice
+    if let Some(request_id) = request_id {
+        match state.responses.begin(src.ip(), request_id) {
+            Lookup::New => {}
+            Lookup::InProgress => {
+                debug!("Dropping retry of request {} that is still being processed.", request_id);
+                return None;
+            }
+            Lookup::Done(response) => {
+                debug!("Answering retry of request {} with the cached response.", request_id);
+                return Some(response);
+            }
+        }
+    }
+
+    // Requests changing an account wait for the requests changing it that arrived before them
+    ticket.wait_for_turn(&request.operation.changed_accounts());
+    let _worker = state.workers.acquire();
+    let started = std::time::Instant::now();
+    let from_node = request.from_node.clone();
+    let result = process_request(chain.clone(), request, src);
+    state.metrics.observe_request("udp", operation, started.elapsed());
+    audit::record(state, Audited { transport: "udp", source: src, from_node: &from_node, operation, result: &result });
+    let response = Response::new(version, request_id, result);
+    debug!("Responding to client: {}", response.message());
+
+    let mut response = response.encode_as(format, accepted);
+    // Clients of versions before 5 can't reassemble fragments, so responses to them have to fit into a datagram
+    if response.len() > common::max_message_size(version) {
+        let message = format!("❌ The response is too large ({} bytes, at most {} for protocol version {}).", response.len(), common::max_message_size(version), version);
+        response = Response::new(version, request_id, Err(message)).encode_as(format, accepted);
+    }
+    if let Some(request_id) = request_id {
+        state.responses.finish(src.ip(), request_id, response.clone());
+    }
+    Some(response)
+}
+
+/// Loads the genesis configuration from the given path, or the empty default genesis if no path is given
+fn load_genesis(path: Option<&str>) -> Result<Genesis, String> {
+    match path {
+        Some(path) => Genesis::load(path),
+        None => Ok(Genesis::default()),
+    }
+}
+
+/// Verifies the integrity of the chain stored in a data directory, see [verify::verify_chain]
+///
+/// # Arguments
+///
+/// * `data_dir`: the data directory of the node
+/// * `genesis`: path to the genesis configuration the chain was started from, the default genesis if not given
+/// * `secret`: the passphrase or key file the data directory is encrypted with, if it is
+///
+/// Returns: a user-facing message, either confirming the chain is valid or describing the first inconsistency found
+pub fn verify_stored_chain(data_dir: &str, genesis: Option<&str>, secret: Option<&Secret>) -> Result<String, String> {
+    let genesis = load_genesis(genesis)?;
+    let vault = Vault::unlock(data_dir, secret, false).map_err(|e| format!("❌ {}", e))?;
+    let blocks = Storage::read(data_dir, vault.as_ref())?;
+    let snapshots = Snapshots::load(Some(data_dir), None, false, vault.as_ref()).map_err(|e| format!("❌ {}", e))?;
+    verify::verify_chain_with(&blocks, &genesis, snapshots.pruned(), |_, _, _| Ok(())).map_err(|e| format!("❌ {}", e))?;
+
+    let transactions: usize = blocks.iter().map(|block| block.transactions.len()).sum();
+    let pruned = match snapshots.pruned() {
+        Some(snapshot) => format!(", verified from the snapshot at height {} as the blocks before it are pruned", snapshot.height),
+        None => String::new(),
+    };
+    Ok(format!("✅ Chain {} is valid: {} blocks with {} transactions, tip {}{}.", genesis.chain_id, blocks.len(), transactions, blocks.last().unwrap().hash, pruned))
+}
+
+/// Migrates the ledger and archive files in a data directory to the current format version, see [migration::migrate]
+///
+/// Returns: a user-facing message listing the migrations, or only the migrations that would be applied for a dry run
+pub fn migrate_data_dir(data_dir: &str, dry_run: bool, secret: Option<&Secret>) -> Result<String, String> {
+    let vault = Vault::unlock(data_dir, secret, false).map_err(|e| format!("❌ {}", e))?;
+    migration::migrate(data_dir, dry_run, vault.as_ref())
+}
+
+/// Processes a request received from a client. The client can request to create an account, transfer funds, or get funds.
+///
+/// # Arguments
+///
+/// * `state`: the current state of blockchain server
+/// * `request`: the request from the client
+/// * `src`: the address the request was sent from
+///
+/// Returns: a user-facing message, either the result of the request or explaining why it failed
+fn process_request(state: Arc<State>, request: common::Request, src: SocketAddr) -> Result<String, String> {
+    info!("Received request: {:?}", request.operation);
+    // Longest message that fits into a response of the protocol version of the request
+    let max_length = common::max_message_size(request.version) - RESPONSE_OVERHEAD;
+    // Requests between nodes are only processed if they are signed by the node they are from
+    identity::check_request(&request)?;
+    if let Some(shard) = &state.shard {
+        shard.check(&request.operation)?;
+    }
+    let structured = request.structured;
+
+    match request.operation {
+        Operation::CreateAccount(account_info) => state.create_account(request.from_node, &account_info),
+        Operation::CreateMultisigAccount(account_info) => state.create_multisig_account(request.from_node, &account_info),
+        Operation::TransferFunds(transfer_info) => state.transfer_funds(request.from_node, &transfer_info),
+        Operation::ApproveTransfer(approval_info) => state.approve_transfer(&approval_info),
+        Operation::CloseAccount(close_info) => state.close_account(request.from_node, &close_info),
+        Operation::Faucet(faucet_info) => state.pay_from_faucet(request.from_node, &faucet_info),
+        Operation::FreezeAccount(admin_info) => state.set_frozen(request.from_node, &admin_info, true),
+        Operation::UnfreezeAccount(admin_info) => state.set_frozen(request.from_node, &admin_info, false),
+        Operation::BatchTransfer(batch_info) => state.transfer_batch(request.from_node, &batch_info, max_length),
+        Operation::GetFunds(get_info) => {
+            let (account_id, alias) = state.resolve_account(&get_info.account_id);
+            let balance = state.get_balance(&account_id);
+            Ok(match (structured, alias) {
+                (true, alias) => json!({ "account_id": account_id, "alias": alias, "balance": balance }).to_string(),
+                (false, Some(alias)) => format!("Account {} (alias {}) has a balance of {}.", account_id, alias, balance),
+                (false, None) => format!("Account {} has a balance of {}.", account_id, balance),
+            })
+        }
+        // The next nonce is returned on its own, so clients can use it for their next transfer
+        Operation::GetNonce(get_info) => Ok(state.get_next_nonce(&get_info.account_id).to_string()),
+        Operation::History(history_info) => {
+            get_history_response(&state, &TransactionFilter::of_account(&history_info.account_id), history_info.page, history_info.limit, max_length, structured)
+        }
+        Operation::QueryTransactions(query) => {
+            TransactionFilter::of_query(&query).and_then(|filter| get_history_response(&state, &filter, query.page, query.limit, max_length, structured))
+        }
+        Operation::Simulate(transfer_info) => state.simulate_transfer(&transfer_info),
+        Operation::CancelTransaction(cancel_info) => state.cancel_transaction(&cancel_info),
+        Operation::GetBlock(block_info) => get_block_response(&state, &block_info, max_length, structured),
+        Operation::Stake(stake_info) => state.stake(request.from_node, &stake_info),
+        Operation::Unstake(unstake_info) => state.unstake(request.from_node, &unstake_info),
+        Operation::RegisterAlias(alias_info) => state.register_alias(request.from_node, &alias_info),
+        Operation::CreateToken(token_info) => state.create_token(request.from_node, &token_info),
+        Operation::TransferToken(token_info) => state.transfer_token(request.from_node, &token_info),
+        Operation::GetTokenBalance(get_info) => get_token_balance_response(&state, &get_info),
+        Operation::MintNft(nft_info) => state.mint_nft(request.from_node, &nft_info),
+        Operation::TransferNft(nft_info) => state.transfer_nft(request.from_node, &nft_info),
+        Operation::OwnerOf(nft_info) => get_nft_response(&state, &nft_info),
+        Operation::TokensOf(nfts_info) => Ok(get_nfts_of_response(&state, &nfts_info, max_length)),
+        Operation::EscrowTransfer(escrow_info) => state.escrow_transfer(request.from_node, &escrow_info),
+        Operation::ExpiringTransfer(expiring_info) => state.expiring_transfer(request.from_node, &expiring_info),
+        Operation::ClaimEscrow(settle_info) => state.settle_escrow(request.from_node, &settle_info, false),
+        Operation::RefundEscrow(settle_info) => state.settle_escrow(request.from_node, &settle_info, true),
+        Operation::SchedulePayment(schedule_info) => state.schedule_payment(request.from_node, &schedule_info),
+        Operation::GetSchedules(schedules_info) => Ok(get_schedules_response(&state, &schedules_info.account_id, max_length)),
+        Operation::CancelSchedule(cancel_info) => state.cancel_schedule(request.from_node, &cancel_info),
+        Operation::OfferSwap(swap_info) => state.offer_swap(request.from_node, &swap_info),
+        Operation::AcceptSwap(accept_info) => state.accept_swap(request.from_node, &accept_info),
+        Operation::DeployContract(deploy_info) => state.deploy_contract(request.from_node, &deploy_info),
+        Operation::CallContract(call_info) => state.call_contract(request.from_node, &call_info),
+        Operation::GetContract(contract_info) => get_contract_response(&state, &contract_info, max_length),
+        Operation::Propose(propose_info) => state.propose(request.from_node, &propose_info),
+        Operation::Vote(vote_info) => state.vote(request.from_node, &vote_info),
+        Operation::GetProposals => Ok(get_proposals_response(&state, max_length)),
+        Operation::GetSupply => Ok(get_supply_response(&state)),
+        Operation::GetValidators => Ok(get_validators_response(&state, max_length)),
+        Operation::ExchangePeers(exchange_info) => Ok(peers::exchange_peers_response(&state, src, &exchange_info)),
+        Operation::GetPeers => Ok(peers::get_peers_response(&state, max_length)),
+        Operation::Health => health::Health::check(&state).describe(&state.node_id),
+        Operation::AdvanceTime(_) if !src.ip().is_loopback() => Err("❌ Requests to advance the time are only accepted from localhost.".to_string()),
+        Operation::AdvanceTime(advance_info) => clock::advance_time(&state, &advance_info),
+        Operation::MintNow(admin_info) => state.mint_now(&admin_info),
+        Operation::GetBlocks(blocks_info) => sync::get_blocks_response(&state, &blocks_info, max_length),
+        Operation::RelayTransactions(relay_info) => gossip::receive_transactions(&state, &relay_info.transactions),
+        Operation::CreditCrossShard(credit_info) => cross_shard::credit_response(&state, request.from_node, &credit_info),
+        Operation::GetHeaders(headers_info) => Ok(light::get_headers_response(&state, &headers_info, max_length)),
+        Operation::GetAccountProof(proof_info) => light::get_account_proof_response(&state, &proof_info.account_id),
+        Operation::GetTransactionProof(proof_info) => light::get_transaction_proof_response(&state, &proof_info.transaction_id),
+        Operation::GetReceipt(receipt_info) => receipt::get_receipt_response(&state, &receipt_info.transaction_id),
+        Operation::AddWebhook(_) | Operation::RemoveWebhook(_) | Operation::GetWebhooks(_) if !src.ip().is_loopback() => {
+            Err("❌ Webhooks are only managed from localhost.".to_string())
+        }
+        Operation::AddWebhook(webhook_info) => state.webhooks.lock().unwrap().add(&webhook_info.account_id, &webhook_info.url),
+        Operation::RemoveWebhook(webhook_info) => state.webhooks.lock().unwrap().remove(&webhook_info.account_id, &webhook_info.url),
+        Operation::GetAuditLog(_) if !src.ip().is_loopback() => Err("❌ The audit log is only read from localhost.".to_string()),
+        Operation::GetAuditLog(audit_info) => audit::get_audit_log_response(&state, &audit_info, max_length),
+        Operation::GetWebhooks(webhooks_info) => Ok(state.webhooks.lock().unwrap().describe(webhooks_info.account_id.as_deref())),
+        Operation::SubmitBlock(block_info) => match bincode::deserialize::<Block>(&block_info.block) {
+            Ok(block) => state.receive_block(block),
+            Err(e) => Err(format!("❌ Invalid block: {}.", e)),
+        },
+        Operation::GetPending(pending_info) => Ok(get_pending_response(&state, &pending_info, max_length)),
+        Operation::Status => Ok(get_status_response(&state, structured)),
+        Operation::GetTransaction(get_info) => match state.find_transaction(&get_info.transaction_id) {
+            Some((transaction, Some((height, block_hash)))) => {
+                Ok(format!("Transaction {}: {}. Included in block {} at height {}.", get_info.transaction_id, transaction, block_hash, height))
+            }
+            Some((transaction, None)) => {
+                Ok(format!("Transaction {}: {}. Pending, not minted yet.", get_info.transaction_id, transaction))
+            }
+            None => Err(format!("❌ Transaction {} not found.", get_info.transaction_id)),
+        },
+        // Admin operations are only accepted from the machine the node runs on
+        Operation::Shutdown if !src.ip().is_loopback() => Err("❌ Shutdown requests are only accepted from localhost.".to_string()),
+        Operation::Shutdown => {
+            request_shutdown(&state);
+            Ok("🛑 Server is shutting down.".to_string())
+        }
+    }
+}
+
+/// Replays the minted blocks into a balance index, the multisig accounts, the stakes, the tokens, the NFTs, the
+/// escrows, the cross-shard transfers, the payment schedules, the swap offers, the contracts and the governance,
+/// starting from the snapshot if the blocks before it are pruned
+///
+/// Returns: the balances, multisig accounts, stakes, tokens, NFTs, escrows, cross-shard transfers, schedules, swap
+/// offers, contracts and governance, the other fields are left empty
+fn minted_indexes(ledger: &[Block], snapshot: Option<&snapshot::Snapshot>) -> verify::Accounts {
+    let (mut indexes, start) = match snapshot {
+        Some(snapshot) => {
+            let accounts = &snapshot.accounts;
+            let indexes = verify::Accounts {
+                balances: accounts.balances.clone(),
+                multisig: accounts.multisig.clone(),
+                stakes: accounts.stakes.clone(),
+                slashed: accounts.slashed.clone(),
+                tokens: accounts.tokens.clone(),
+                nfts: accounts.nfts.clone(),
+                escrows: accounts.escrows.clone(),
+                cross_shard: accounts.cross_shard.clone(),
+                schedules: accounts.schedules.clone(),
+                swaps: accounts.swaps.clone(),
+                contracts: accounts.contracts.clone(),
+                governance: accounts.governance.clone(),
+                ..Default::default()
+            };
+            (indexes, snapshot.height + 1)
+        }
+        None => (verify::Accounts::default(), 0),
+    };
+    for block in &ledger[start.min(ledger.len())..] {
+        block.apply_to_balances(&mut indexes.balances).expect("Balances of a verified chain overflowed");
+        indexes.multisig.extend(block.multisig_accounts().map(|(account_id, policy)| (account_id.clone(), policy.clone())));
+        block.apply_to_stakes(&mut indexes.stakes);
+        block.apply_to_slashed(&mut indexes.slashed);
+        block.apply_to_tokens(&mut indexes.tokens);
+        block.apply_to_nfts(&mut indexes.nfts);
+        block.apply_to_escrows(&mut indexes.escrows);
+        block.apply_to_cross_shard(&mut indexes.cross_shard);
+        block.apply_to_schedules(&mut indexes.schedules);
+        block.apply_to_swaps(&mut indexes.swaps);
+        block.apply_to_contracts(&mut indexes.contracts);
+        block.apply_to_governance(&mut indexes.governance, &indexes.balances, &indexes.stakes);
+    }
+    indexes
+}
+
+/// Gets the balance of an account, which may be given by its alias, in a token
+///
+/// Returns: a user-facing message with the balance, or an error if the token doesn't exist
+fn get_token_balance_response(state: &State, get_info: &GetTokenBalanceOp) -> Result<String, String> {
+    let (account_id, alias) = state.resolve_account(&get_info.account_id);
+    let tokens = state.tokens.lock().unwrap();
+    if tokens.get(&get_info.token).is_none() {
+        return Err(format!("❌ Token {} doesn't exist.", get_info.token));
+    }
+    let balance = tokens.balance(&get_info.token, &account_id);
+    match alias {
+        Some(alias) => Ok(format!("Account {} (alias {}) has a balance of {} {}.", account_id, alias, balance, get_info.token)),
+        None => Ok(format!("Account {} has a balance of {} {}.", account_id, balance, get_info.token)),
+    }
+}
+
+/// Describes an NFT with its owner as the response to an owner request
+///
+/// Returns: a user-facing message, or an error if the NFT doesn't exist
+fn get_nft_response(state: &State, nft_info: &GetNftOp) -> Result<String, String> {
+    match state.nfts.lock().unwrap().get(&nft_info.nft_id) {
+        Some(nft) => Ok(format!("NFT {} is owned by account {}, minted by account {} with metadata \"{}\".", nft_info.nft_id, nft.owner, nft.minter, nft.metadata)),
+        None => Err(format!("❌ NFT {} doesn't exist.", nft_info.nft_id)),
+    }
+}
+
+/// Describes a contract and lists the values in its storage, as many as fit into a message of `max_length` bytes.
+/// Keys and values are shown as text if they are valid UTF-8, otherwise in hex.
+fn get_contract_response(state: &State, contract_info: &GetContractOp, max_length: usize) -> Result<String, String> {
+    let contracts = state.contracts.lock().unwrap();
+    let Some(contract) = contracts.get(&contract_info.contract_id) else {
+        return Err(format!("❌ Contract {} doesn't exist.", contract_info.contract_id));
+    };
+    let printable = |bytes: &[u8]| match std::str::from_utf8(bytes) {
+        Ok(text) => format!("\"{}\"", text.escape_debug()),
+        Err(_) => format!("0x{}", bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()),
+    };
+    let mut response = format!(
+        "Contract {} is owned by account {}, with {} bytes of code and {} values in its storage:",
+        contract_info.contract_id,
+        contract.owner,
+        contract.code.len(),
+        contract.storage.len()
+    );
+    for (index, (key, value)) in contract.storage.iter().enumerate() {
+        let line = format!("\n- {}: {}", printable(key), printable(value));
+        let more = format!("\n… and {} more", contract.storage.len() - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < contract.storage.len() {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    Ok(response)
+}
+
+/// Lists the NFTs owned by an account, which may be given by its alias, as many as fit into a message of `max_length`
+/// bytes
+fn get_nfts_of_response(state: &State, nfts_info: &GetNftsOfOp, max_length: usize) -> String {
+    let (account_id, _) = state.resolve_account(&nfts_info.account_id);
+    let nfts = state.nfts.lock().unwrap();
+    let owned = nfts.owned_by(&account_id);
+    let mut response = format!("Account {} owns {} NFTs:", account_id, owned.len());
+    for (index, (nft_id, nft)) in owned.iter().enumerate() {
+        let line = format!("\n- {}: \"{}\"", nft_id, nft.metadata);
+        let more = format!("\n… and {} more", owned.len() - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < owned.len() {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    response
+}
+
+/// Lists the payment schedules an account pays or is paid by, which may be given by its alias, as many as fit into a
+/// message of `max_length` bytes
+fn get_schedules_response(state: &State, account_id: &str, max_length: usize) -> String {
+    let (account_id, _) = state.resolve_account(account_id);
+    let next_height = state.ledger.read().unwrap().len() as u64;
+    let schedules = state.schedules.lock().unwrap();
+    let involving = schedules.involving(&account_id);
+    let mut response = format!("Account {} is involved in {} payment schedules:", account_id, involving.len());
+    for (index, (schedule_id, schedule)) in involving.iter().enumerate() {
+        let next = match schedule.next_payment(next_height) {
+            Some((height, left)) => format!("{} payments left, the next at height {}", left, height),
+            None => "no payments left".to_string(),
+        };
+        let line = format!("\n- {}: {} from {} to {} every {} blocks, {}", schedule_id, schedule.amount, schedule.sender, schedule.recipient, schedule.interval, next);
+        let more = format!("\n… and {} more", involving.len() - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < involving.len() {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    response
+}
+
+/// Describes the health of the node as the response to a status request, as a JSON object if the result is structured
+fn get_status_response(state: &State, structured: bool) -> String {
+    let (height, tip_hash) = {
+        let ledger = state.ledger.read().unwrap();
+        (ledger.len() - 1, ledger.last().unwrap().hash.clone())
+    };
+    let (pending, backed_up) = {
+        let mempool = state.mempool.lock().unwrap();
+        (mempool.len(), mempool.is_backed_up())
+    };
+    let fork_blocks = state.forks.lock().unwrap().len();
+    let orphan_blocks = state.orphans.lock().unwrap().len();
+    let validators = (state.consensus == Consensus::Pos).then(|| state.stakes.lock().unwrap().len());
+    let checkpoint = state.checkpoints.lock().unwrap().last().cloned();
+    let snapshot = state.snapshots.lock().unwrap().latest().map(|snapshot| (snapshot.height, snapshot.hash.clone(), snapshot.pruned));
+    let uptime = state.started.elapsed().as_secs();
+    let (connected_peers, known_peers) = state.peers.lock().unwrap().counts();
+    let sync = state.sync.lock().unwrap().describe(height);
+    let requests = state.metrics.processed_requests();
+
+    if structured {
+        return json!({
+            "node_id": state.node_id,
+            "version": env!("CARGO_PKG_VERSION"),
+            "chain_id": state.genesis.chain_id,
+            "height": height,
+            "tip": tip_hash,
+            "pending_transactions": pending,
+            "backed_up": backed_up,
+            "fork_blocks": fork_blocks,
+            "orphan_blocks": orphan_blocks,
+            "last_checkpoint": checkpoint.map(|checkpoint| json!({ "height": checkpoint.height, "hash": checkpoint.hash })),
+            "snapshot": snapshot.map(|(height, hash, pruned)| json!({ "height": height, "hash": hash, "pruned": pruned })),
+            "consensus": match state.consensus {
+                Consensus::Timer => json!({ "kind": "timer" }),
+                Consensus::Pos => json!({ "kind": "pos", "validators": validators, "reward_account": state.reward_account }),
+            },
+            "uptime_secs": uptime,
+            "connected_peers": connected_peers,
+            "known_peers": known_peers,
+            "sync": sync,
+            "requests": requests,
+        })
+        .to_string();
+    }
+    let pending_transactions = match backed_up {
+        true => format!("{} (backed up, the last block was full)", pending),
+        false => pending.to_string(),
+    };
+    let consensus = match validators {
+        None => "timer".to_string(),
+        Some(validators) => format!("proof of stake with {} validators, minting for {}", validators, state.reward_account),
+    };
+    let last_checkpoint = match checkpoint {
+        Some(checkpoint) => format!("{} ({})", checkpoint.height, checkpoint.hash),
+        None => "none".to_string(),
+    };
+    let snapshot = match snapshot {
+        Some((height, hash, true)) => format!("{} ({}), blocks before it pruned", height, hash),
+        Some((height, hash, false)) => format!("{} ({})", height, hash),
+        None => "none".to_string(),
+    };
+
+    format!(
+        "Node {} running ToyChain {} on chain {}:\n- Height: {}\n- Tip: {}\n- Pending transactions: {}\n- Fork blocks: {}\n- Orphan blocks: {}\n- Last checkpoint: {}\n- Snapshot: {}\n- Consensus: {}\n- Uptime: {}h {}m {}s\n- Peers: {} connected of {} known\n- Sync: {}\n- Requests: {} processed",
+        state.node_id,
+        env!("CARGO_PKG_VERSION"),
+        state.genesis.chain_id,
+        height,
+        tip_hash,
+        pending_transactions,
+        fork_blocks,
+        orphan_blocks,
+        last_checkpoint,
+        snapshot,
+        consensus,
+        uptime / 3600,
+        uptime / 60 % 60,
+        uptime % 60,
+        connected_peers,
+        known_peers,
+        sync,
+        requests
+    )
+}
+
+/// Lists the validators with their stakes and the staking reward as the response to a validators request, the
+/// validators that don't fit into the response are counted at the end
+fn get_validators_response(state: &State, max_length: usize) -> String {
+    let validators = state.get_validators();
+    let total: Amount = validators.iter().map(|(_, stake)| *stake).sum();
+    let (reward, epoch_length) = {
+        let governance = state.governance.lock().unwrap();
+        let parameters = governance.parameters();
+        (parameters.staking_reward.unwrap_or_default(), parameters.epoch_length.unwrap_or(stake::DEFAULT_EPOCH_LENGTH))
+    };
+    let slashed = state.slashed.lock().unwrap().clone();
+    let mut response = format!("{} validators with a total stake of {}, sharing a staking reward of {} every {} blocks:", validators.len(), total, reward, epoch_length);
+    for (index, (account_id, stake)) in validators.iter().enumerate() {
+        let line = match slashed.get(account_id) {
+            Some(height) => format!("\n- {}: stake {}, slashed for signing conflicting blocks at height {}", account_id, stake, height),
+            None => format!("\n- {}: stake {}", account_id, stake),
+        };
+        let more = format!("\n… and {} more", validators.len() - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < validators.len() {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    response
+}
+
+/// Reports the supply of the chain and the block reward of its monetary policy at the next height as the response to a
+/// supply request
+fn get_supply_response(state: &State) -> String {
+    let parameters = &state.genesis.parameters;
+    let height = state.ledger.read().unwrap().len() as u64;
+    let balances = state.balances.lock().unwrap();
+    let supply = monetary::Supply::of(&balances);
+    let max_supply = match parameters.max_supply {
+        Some(max_supply) => format!("{}, {} left to issue", max_supply, (max_supply - supply.total).max(Amount::ZERO)),
+        None => "unlimited".to_string(),
+    };
+    let halving = match parameters.halving_interval {
+        Some(interval) => format!("halving every {} blocks, next at height {}", interval, (height / interval + 1) * interval),
+        None => "never halving".to_string(),
+    };
+    format!(
+        "Supply of chain {} at height {}:\n- Total: {}\n- Circulating: {}\n- Staked: {}\n- In escrow: {}\n- Slashed: {}\n- Between shards: {}\n- Max supply: {}\n- Block reward: {} plus the fees ({} by the schedule), {}",
+        state.genesis.chain_id,
+        height.saturating_sub(1),
+        supply.total,
+        supply.circulating(),
+        supply.staked,
+        supply.escrowed,
+        supply.slashed,
+        supply.cross_shard,
+        max_supply,
+        monetary::block_subsidy(parameters, height, monetary::issuable(parameters.max_supply, &balances)),
+        monetary::scheduled_reward(parameters, height),
+        halving
+    )
+}
+
+/// Lists the chain parameters decided by governance and the open proposals with the weight of their votes so far as the
+/// response to a proposals request, the proposals that don't fit into the response are counted at the end
+fn get_proposals_response(state: &State, max_length: usize) -> String {
+    let balances = state.balances.lock().unwrap();
+    let stakes = state.stakes.lock().unwrap();
+    let governance = state.governance.lock().unwrap();
+    let parameters = governance.parameters();
+    let decided = |value: Option<String>| value.unwrap_or_else(|| "not changed".to_string());
+    let mut response = format!(
+        "Min fee: {}, max block transactions: {}, mint interval: {}.",
+        decided(parameters.min_fee.map(|fee| fee.to_string())),
+        decided(parameters.max_block_transactions.map(|count| count.to_string())),
+        decided(parameters.mint_interval.map(|seconds| format!("{}s", seconds)))
+    );
+    let proposals: Vec<_> = governance.open().collect();
+    response.push_str(&format!("\n{} open proposals:", proposals.len()));
+    for (index, (proposal_id, proposal)) in proposals.iter().enumerate() {
+        let (approve, reject) = Governance::tally(proposal, |account_id| governance::weight(&balances, &stakes, account_id));
+        let line = format!(
+            "\n- {}: change {} from height {} by {}, {} votes weighing {} for and {} against",
+            proposal_id,
+            proposal.change,
+            proposal.activation_height,
+            proposal.proposer,
+            proposal.votes.len(),
+            approve,
+            reject
+        );
+        let more = format!("\n… and {} more", proposals.len() - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < proposals.len() {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    response
+}
+
+/// Describes a block with its header and transactions as the response to a block request, as a JSON object if the
+/// result is structured. Transactions that don't fit into a message of `max_length` bytes are left out.
+fn get_block_response(state: &State, block_info: &GetBlockOp, max_length: usize, structured: bool) -> Result<String, String> {
+    if block_info.height.is_none() && block_info.hash.is_none() {
+        return Err("❌ Either the height or the hash of the block must be given.".to_string());
+    }
+    let Some((height, block)) = state.find_block(block_info.height, block_info.hash.as_deref()) else {
+        return Err(match (block_info.height, &block_info.hash) {
+            (Some(height), _) => format!("❌ There is no block at height {}.", height),
+            (None, hash) => format!("❌ Block {} not found.", hash.as_deref().unwrap_or_default()),
+        });
+    };
+
+    let pruned = state.snapshots.lock().unwrap().pruned().map(|snapshot| snapshot.height).filter(|snapshot| height < *snapshot);
+    if structured {
+        let result = json!({
+            "hash": block.hash,
+            "height": height,
+            "previous_hash": block.header.previous_hash,
+            "merkle_root": block.header.merkle_root,
+            "timestamp": block.header.timestamp,
+            "pruned": pruned.is_some(),
+            "minted_by": block.node_signature.as_ref().map(|signature| &signature.node_id),
+            "signed_by": block.signature.as_ref().map(|signature| json!({ "validator": signature.validator, "public_key": signature.public_key })),
+            "transactions": block.transactions.iter().map(|transaction| transaction_fields(transaction, None)).collect::<Vec<_>>(),
+        });
+        return Ok(fit_list(result, "transactions", max_length));
+    }
+    let transactions = match pruned {
+        Some(snapshot) => format!("pruned, the node only keeps the transactions after the snapshot at height {}", snapshot),
+        None => block.transactions.len().to_string(),
+    };
+    let mut response = format!(
+        "Block {} at height {}:\n- Previous block: {}\n- Merkle root: {}\n- Minted at: {} (unix time)\n- Transactions: {}",
+        block.hash,
+        height,
+        block.header.previous_hash,
+        block.header.merkle_root,
+        block.header.timestamp,
+        transactions
+    );
+    if let Some(signature) = &block.node_signature {
+        response.push_str(&format!("\n- Minted by: node {}", signature.node_id));
+    }
+    if let Some(signature) = &block.signature {
+        response.push_str(&format!("\n- Signed by: validator {} with key {}", signature.validator, signature.public_key));
+    }
+    for (index, transaction) in block.transactions.iter().enumerate() {
+        let line = format!("\n  - {}: {}", transaction.id(), transaction);
+        let more = format!("\n  … and {} more", block.transactions.len() - index);
+        if response.len() + line.len() + more.len() > max_length {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    Ok(response)
+}
+
+/// Lists the transactions waiting in the mempool in the order they were accepted, only those involving the account if
+/// one is given
+///
+/// Returns: the list of at most `max_length` bytes
+fn get_pending_response(state: &State, pending_info: &GetPendingOp, max_length: usize) -> String {
+    let mempool = state.mempool.lock().unwrap();
+    let account_id = pending_info.account_id.as_deref();
+    let transactions: Vec<&Transaction> = mempool
+        .transactions()
+        .filter(|transaction| account_id.is_none_or(|account_id| transaction.to_account_id == account_id || transaction.from_account_id.as_deref() == Some(account_id)))
+        .collect();
+
+    let mut response = match account_id {
+        Some(account_id) => format!("{} pending transactions of {}:", transactions.len(), account_id),
+        None => format!("{} pending transactions:", transactions.len()),
+    };
+    for (index, transaction) in transactions.iter().enumerate() {
+        let line = match account_id {
+            Some(account_id) => format!("\n- {} at {} (unix time): {}", transaction.id(), transaction.timestamp(), transaction.describe_for(account_id)),
+            None => format!("\n- {}: {}", transaction.id(), transaction),
+        };
+        let more = format!("\n… and {} more", transactions.len() - index);
+        if response.len() + line.len() + more.len() > max_length {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    response
+}
+
+/// Formats a page of the history of an account as the response to a history request, or of the transactions matching
+/// a filter as the response to a query, as a JSON object if the result is structured. Lines that don't fit into a
+/// message of `max_length` bytes are left out, asking the client to use a smaller page size instead.
+fn get_history_response(state: &State, filter: &TransactionFilter, page: usize, limit: usize, max_length: usize, structured: bool) -> Result<String, String> {
+    const TRUNCATED: &str = "\n… (the page doesn't fit into a single response, use a smaller limit)";
+
+    if page == 0 || limit == 0 {
+        return Err("❌ Page and limit must be at least 1.".to_string());
+    }
+
+    let (transactions, total) = state.get_history(filter, page, limit);
+    let pruned = state.snapshots.lock().unwrap().pruned().map(|snapshot| snapshot.height);
+    if structured {
+        let result = json!({
+            "account_id": filter.account_id,
+            "page": page,
+            "pages": total.div_ceil(limit).max(1),
+            "total": total,
+            "pruned_before": pruned,
+            "transactions": transactions
+                .iter()
+                .map(|(height, block_hash, transaction)| {
+                    let mut fields = transaction_fields(transaction, Some(&filter.account_id));
+                    fields["height"] = json!(height);
+                    fields["block_hash"] = json!(block_hash);
+                    fields
+                })
+                .collect::<Vec<_>>(),
+        });
+        return Ok(fit_list(result, "transactions", max_length));
+    }
+    let pruned = match pruned {
+        Some(snapshot) => format!(" after the snapshot at height {}, the transactions before it are pruned", snapshot),
+        None => String::new(),
+    };
+    let filters = match filter.describe() {
+        description if description.is_empty() => String::new(),
+        description => format!(" ({})", description),
+    };
+    let mut response = format!(
+        "History of {}{}, page {} of {} ({} transactions{}):",
+        filter.account_id,
+        filters,
+        page,
+        total.div_ceil(limit).max(1),
+        total,
+        pruned
+    );
+
+    for (height, block_hash, transaction) in transactions {
+        let line = format!(
+            "\n- {} in block {} ({}) at {} (unix time): {}",
+            transaction.id(),
+            height,
+            &block_hash[..16],
+            transaction.timestamp(),
+            transaction.describe_for(&filter.account_id)
+        );
+        if response.len() + line.len() + TRUNCATED.len() > max_length {
+            response.push_str(TRUNCATED);
+            break;
+        }
+        response.push_str(&line);
+    }
+
+    Ok(response)
+}
+
+/// Returns the fields of a transaction in a structured result, described from the point of view of the account if one
+/// is given
+fn transaction_fields(transaction: &Transaction, account_id: Option<&str>) -> Value {
+    json!({
+        "id": transaction.id(),
+        "from": transaction.from_account_id,
+        "to": transaction.to_account_id,
+        "amount": transaction.amount,
+        "fee": transaction.fee,
+        "nonce": transaction.nonce,
+        "timestamp": transaction.timestamp(),
+        "memo": transaction.memo,
+        "description": match account_id {
+            Some(account_id) => transaction.describe_for(account_id),
+            None => transaction.to_string(),
+        },
+    })
+}
+
+/// Serializes a structured result with a list, leaving out the entries at the end of the list that don't fit into a
+/// message of `max_length` bytes and counting them in its `more` field
+fn fit_list(mut result: Value, list: &str, max_length: usize) -> String {
+    let Value::Array(entries) = result[list].take() else {
+        return result.to_string();
+    };
+    result["more"] = json!(entries.len());
+    let mut length = result.to_string().len();
+    let mut kept = Vec::new();
+    for entry in entries.iter() {
+        // The entry and the comma separating it from the one before
+        let entry_length = entry.to_string().len() + 1;
+        if length + entry_length > max_length {
+            break;
         }
+        length += entry_length;
+        kept.push(entry.clone());
     }
+    result["more"] = json!(entries.len() - kept.len());
+    result[list] = Value::Array(kept);
+    result.to_string()
 }
@@ -0,0 +1,49 @@
+//! Admin operations on accounts, signed with the node's admin key. A frozen account can still receive funds, but no
+//! transfers can be made from it until it is unfrozen. Freezes are recorded on-chain with the time their signature was
+//! made, so every node restores the same frozen accounts and an old signature can't be replayed.
+
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// How far the time a signature was made at may be from the node's clock, so a signature can't be kept for later
+pub const SIGNATURE_VALIDITY: Duration = Duration::from_secs(5 * 60);
+
+/// The frozen accounts and the times the last admin operations on accounts were signed at
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct FrozenAccounts {
+    frozen: HashSet<String>,
+    last_signed_at: HashMap<String, u64>,
+}
+
+impl FrozenAccounts {
+    pub fn is_frozen(&self, account_id: &str) -> bool {
+        self.frozen.contains(account_id)
+    }
+
+    /// Checks that an account can be frozen or unfrozen with a signature made at the given time, which has to be later
+    /// than the signature of the last admin operation on the account
+    ///
+    /// Returns: why the operation is invalid
+    pub fn check(&self, account_id: &str, freeze: bool, signed_at: u64) -> Result<(), String> {
+        match (freeze, self.is_frozen(account_id)) {
+            (true, true) => return Err(format!("account {} is frozen already", account_id)),
+            (false, false) => return Err(format!("account {} isn't frozen", account_id)),
+            _ => {}
+        }
+        match self.last_signed_at.get(account_id) {
+            Some(last) if signed_at <= *last => Err(format!("it was signed at {}, but the last admin operation on account {} was signed at {}", signed_at, account_id, last)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Freezes or unfreezes an account
+    pub fn apply(&mut self, account_id: &str, freeze: bool, signed_at: u64) {
+        if freeze {
+            self.frozen.insert(account_id.to_string());
+        } else {
+            self.frozen.remove(account_id);
+        }
+        self.last_signed_at.insert(account_id.to_string(), signed_at);
+    }
+}
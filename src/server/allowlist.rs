@@ -0,0 +1,94 @@
+//! Permissioned mode. A node started with `--allow KEY` only accepts requests from the clients and peers whose public
+//! keys are allowed: peers by their node ID, the public key of the identity key their requests are signed with (see
+//! [super::identity]), and clients by the static key they authenticate their encrypted session with (`--client-key`).
+//! Every other request is rejected with an authorization error before it is processed. The JSON-RPC, REST, GraphQL,
+//! gRPC-Web, WebSocket and block explorer endpoints can't authenticate their clients by a key, so they reject every
+//! request in permissioned mode.
+
+use std::collections::HashSet;
+use crate::common::Request;
+use crate::noise;
+
+/// Why a request isn't authorized by the allowlist
+#[derive(Debug)]
+pub enum Unauthorized {
+    // The request is neither signed by a node nor sent over an encrypted session, so it has no key to check
+    Unauthenticated,
+    // The hex encoded key the request is from isn't allowed
+    NotAllowed(String),
+}
+
+impl Unauthorized {
+    /// Returns the machine-readable reason, sent as the error code of the response and e.g. in the `data` of JSON-RPC
+    /// errors
+    pub fn code(&self) -> &'static str {
+        match self {
+            Unauthorized::Unauthenticated => "unauthenticated",
+            Unauthorized::NotAllowed(_) => "not_allowed",
+        }
+    }
+}
+
+impl std::fmt::Display for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Unauthorized::Unauthenticated => write!(
+                f,
+                "❌ Unauthorized (unauthenticated): the node is in permissioned mode, send requests over an encrypted session with an allowed client key, see --client-key."
+            ),
+            Unauthorized::NotAllowed(key) => write!(f, "❌ Unauthorized (not_allowed): key {} isn't in the allowlist of the node.", key),
+        }
+    }
+}
+
+/// The public keys of the clients and peers a node accepts requests from, every request is accepted if it is empty
+pub struct Allowlist {
+    keys: HashSet<String>,
+}
+
+impl Allowlist {
+    /// Returns the allowlist of the given hex encoded public keys
+    ///
+    /// Returns: an error if a key isn't 32 hex encoded bytes
+    pub fn new(keys: &[String]) -> Result<Allowlist, String> {
+        let mut allowed = HashSet::new();
+        for key in keys {
+            match noise::from_hex(key) {
+                Ok(bytes) if bytes.len() == 32 => allowed.insert(noise::to_hex(&bytes)),
+                _ => return Err(format!("Invalid allowed key {}, expected a public key of 32 hex encoded bytes.", key)),
+            };
+        }
+        Ok(Allowlist { keys: allowed })
+    }
+
+    /// Whether the node is in permissioned mode
+    pub fn is_permissioned(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Checks that a request is from an allowed peer or client. Requests between nodes are checked by the node they
+    /// are from, whose signature is checked when the request is processed, other requests by the client's static key.
+    ///
+    /// # Arguments
+    ///
+    /// * `request`: the request
+    /// * `client_key`: the static key of the client's encrypted session, None for plaintext requests
+    pub fn check(&self, request: &Request, client_key: Option<&[u8]>) -> Result<(), Unauthorized> {
+        if !self.is_permissioned() {
+            return Ok(());
+        }
+        let key = match (request.operation.node_signature(), client_key) {
+            (Some(_), _) => request.from_node.to_lowercase(),
+            (None, Some(client_key)) => noise::to_hex(client_key),
+            (None, None) => return Err(Unauthorized::Unauthenticated),
+        };
+        match self.keys.contains(&key) {
+            true => Ok(()),
+            false => Err(Unauthorized::NotAllowed(key)),
+        }
+    }
+}
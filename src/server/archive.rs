@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+use super::snapshot::Snapshots;
+use super::storage::{upgrade_blocks, AnyBlock, BlockV1, BlockV17, BlockV18, BlockV2, BlockV3, BlockV6, Storage};
+use super::{load_genesis, verify, Block};
+use crate::vault::{Secret, Vault};
+
+/// File format chains are exported to and imported from
+#[derive(Clone, Copy, Debug)]
+pub enum ExportFormat {
+    // Human-readable JSON, amounts are decimal strings
+    Json,
+    // Compact bincode encoding
+    Binary,
+}
+
+/// A portable copy of a chain, holding every block starting with the genesis block. Binary exports of earlier nodes
+/// hold blocks of an earlier format.
+#[derive(Serialize, Deserialize)]
+struct ChainExport<B = Block> {
+    chain_id: String,
+    genesis_hash: String,
+    blocks: Vec<B>,
+}
+
+/// Exports the chain stored in a data directory to a file, after verifying it
+///
+/// # Arguments
+///
+/// * `data_dir`: the data directory of the node
+/// * `path`: the file to export to
+/// * `format`: the format of the file
+/// * `genesis`: path to the genesis configuration the chain was started from, the default genesis if not given
+/// * `secret`: the passphrase or key file the data directory is encrypted with, if it is
+///
+/// Returns: a user-facing message, either confirming the export or explaining why it failed
+pub fn export_chain(data_dir: &str, path: &str, format: ExportFormat, genesis: Option<&str>, secret: Option<&Secret>) -> Result<String, String> {
+    let genesis = load_genesis(genesis)?;
+    let genesis_block = genesis.to_block();
+    let vault = Vault::unlock(data_dir, secret, false).map_err(|e| format!("❌ {}", e))?;
+    let blocks = Storage::read(data_dir, vault.as_ref())?;
+    if let Some(snapshot) = Snapshots::load(Some(data_dir), None, false, vault.as_ref()).map_err(|e| format!("❌ {}", e))?.pruned() {
+        return Err(format!("❌ The chain can't be exported, the transactions of its blocks before the snapshot at height {} are pruned.", snapshot.height));
+    }
+    verify::verify_chain(&blocks, &genesis).map_err(|e| format!("❌ {}", e))?;
+
+    let export = ChainExport {
+        chain_id: genesis.chain_id,
+        genesis_hash: genesis_block.hash,
+        blocks,
+    };
+    let bytes = match format {
+        ExportFormat::Json => serde_json::to_vec_pretty(&export).unwrap(),
+        ExportFormat::Binary => bincode::serialize(&export).unwrap(),
+    };
+    std::fs::write(path, bytes).map_err(|e| format!("❌ Failed to write {}: {}", path, e))?;
+
+    Ok(format!("✅ Exported {} blocks of chain {} to {}.", export.blocks.len(), export.chain_id, path))
+}
+
+/// Imports a chain from a file into an empty data directory. The chain is only imported if it is valid and starts
+/// with the genesis block of the given genesis configuration.
+///
+/// # Arguments
+///
+/// * `path`: the file to import from
+/// * `data_dir`: the data directory to import into
+/// * `format`: the format of the file
+/// * `genesis`: path to the genesis configuration of the chain, the default genesis if not given
+/// * `secret`: the passphrase or key file to encrypt a new data directory with, or the existing one is encrypted with
+///
+/// Returns: a user-facing message, either confirming the import or explaining why it was rejected
+pub fn import_chain(path: &str, data_dir: &str, format: ExportFormat, genesis: Option<&str>, secret: Option<&Secret>) -> Result<String, String> {
+    let genesis = load_genesis(genesis)?;
+    let export = read_export(path, format)?;
+
+    if export.chain_id != genesis.chain_id {
+        return Err(format!("❌ {} holds chain {}, but the genesis configuration is for chain {}.", path, export.chain_id, genesis.chain_id));
+    }
+    let genesis_block = genesis.to_block();
+    if export.genesis_hash != genesis_block.hash {
+        return Err(format!("❌ {} starts with genesis block {}, but the genesis configuration gives {}.", path, export.genesis_hash, genesis_block.hash));
+    }
+    verify::verify_chain(&export.blocks, &genesis).map_err(|e| format!("❌ {}", e))?;
+
+    let vault = Vault::unlock(data_dir, secret, true).map_err(|e| format!("❌ {}", e))?;
+    let (mut storage, stored_blocks) = Storage::open(data_dir, vault.as_ref())?;
+    if !stored_blocks.is_empty() {
+        return Err(format!("❌ The data directory {} already holds a chain with {} blocks.", data_dir, stored_blocks.len()));
+    }
+    for block in &export.blocks {
+        storage.append(block)?;
+    }
+    storage.sync()?;
+
+    Ok(format!("✅ Imported {} blocks of chain {} into {}.", export.blocks.len(), export.chain_id, data_dir))
+}
+
+/// Reads the blocks of a chain file after the genesis block, each bincode encoded like in a ledger file, e.g. to hand
+/// them to a running node with `SubmitBlock` requests
+pub fn read_chain_blocks(path: &str, format: ExportFormat) -> Result<Vec<Vec<u8>>, String> {
+    let export = read_export(path, format)?;
+    Ok(export.blocks.iter().skip(1).map(|block| bincode::serialize(block).unwrap()).collect())
+}
+
+fn read_export(path: &str, format: ExportFormat) -> Result<ChainExport, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("❌ Failed to read {}: {}", path, e))?;
+    match format {
+        ExportFormat::Json => decode_json(&bytes).map_err(|e| format!("❌ Failed to parse {}: {}", path, e)),
+        ExportFormat::Binary => decode_binary(&bytes).map_err(|e| format!("❌ Failed to parse {}: {}", path, e)),
+    }
+}
+
+/// Decodes a JSON chain export, falling back to the formats of earlier nodes, whose blocks have no height and timestamp
+/// and whose transactions may have no kind
+fn decode_json(bytes: &[u8]) -> Result<ChainExport, serde_json::Error> {
+    serde_json::from_slice(bytes)
+        .or_else(|e| serde_json::from_slice::<ChainExport<BlockV6>>(bytes).map(ChainExport::upgrade).map_err(|_| e))
+        .or_else(|e| serde_json::from_slice::<ChainExport<BlockV3>>(bytes).map(ChainExport::upgrade).map_err(|_| e))
+}
+
+/// Decodes a binary chain export, falling back to the formats of earlier nodes. Trailing bytes are rejected, so a
+/// file isn't mistaken for another format.
+fn decode_binary(bytes: &[u8]) -> Result<ChainExport, bincode::Error> {
+    decode_binary_as::<Block>(bytes)
+        .or_else(|e| decode_binary_as::<BlockV18>(bytes).map_err(|_| e))
+        .or_else(|e| decode_binary_as::<BlockV17>(bytes).map_err(|_| e))
+        .or_else(|e| decode_binary_as::<BlockV6>(bytes).map_err(|_| e))
+        .or_else(|e| decode_binary_as::<BlockV3>(bytes).map_err(|_| e))
+        .or_else(|e| decode_binary_as::<BlockV2>(bytes).map_err(|_| e))
+        .or_else(|e| decode_binary_as::<BlockV1>(bytes).map_err(|_| e))
+}
+
+fn decode_binary_as<B: DeserializeOwned + AnyBlock>(bytes: &[u8]) -> Result<ChainExport, bincode::Error> {
+    let export: ChainExport<B> = bincode::options().with_fixint_encoding().reject_trailing_bytes().deserialize(bytes)?;
+    Ok(export.upgrade())
+}
+
+impl<B: AnyBlock> ChainExport<B> {
+    /// Converts an export of an earlier format to the current one
+    fn upgrade(self) -> ChainExport {
+        ChainExport {
+            chain_id: self.chain_id,
+            genesis_hash: self.genesis_hash,
+            blocks: upgrade_blocks(self.blocks),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+//! The audit log of a node started with `--audit-log FILE`. Every request the node processes, over UDP or JSON-RPC,
+//! is appended to the file as a JSON line with its source, operation, outcome and the ID of the transaction it made.
+//! Every entry holds the hash of the entry before it and its own hash over both, so an entry that is changed or
+//! removed breaks the chain of hashes after it, see `toychain audit`. When the file grows beyond `MAX_FILE_SIZE` it
+//! is rotated to `FILE.1`, `FILE.1` to `FILE.2` and so on, the oldest of the `MAX_ROTATED_FILES` is deleted, and the
+//! chain of hashes continues into the new file.
+
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::common::GetAuditLogOp;
+use crate::merkle::sha256_hex;
+use super::State;
+
+/// Size in bytes after which the audit log is rotated
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+/// Number of rotated files kept besides the current one
+const MAX_ROTATED_FILES: usize = 5;
+/// Longest reason of a rejected request recorded, longer ones are cut
+const MAX_REASON_LENGTH: usize = 200;
+/// Hash the first entry of an audit log links to
+const FIRST_PREVIOUS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// What the audit log records of a request, the hash of an entry is made over it
+#[derive(Serialize, Deserialize)]
+struct Record {
+    // Number of the entry, counted from the first entry of the log
+    sequence: u64,
+    // When the request was processed, in seconds since the Unix epoch
+    time: u64,
+    // How the request arrived, `udp` or `rpc`
+    transport: String,
+    // The address the request was sent from
+    source: String,
+    // The node the request claims to be from, the caller's address for JSON-RPC requests
+    from_node: String,
+    operation: String,
+    accepted: bool,
+    // Why the request was rejected, None if it was accepted
+    reason: Option<String>,
+    // The transaction the request committed, if any
+    transaction_id: Option<String>,
+    // Hash of the entry before this one
+    previous_hash: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    #[serde(flatten)]
+    record: Record,
+    hash: String,
+}
+
+impl Record {
+    fn hash(&self) -> String {
+        sha256_hex(&serde_json::to_vec(self).unwrap())
+    }
+}
+
+/// A request to record in the audit log
+pub struct Audited<'a> {
+    pub transport: &'static str,
+    pub source: SocketAddr,
+    pub from_node: &'a str,
+    pub operation: &'a str,
+    pub result: &'a Result<String, String>,
+}
+
+/// The audit log file and the last entry appended to it
+pub struct AuditLog {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    next_sequence: u64,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Opens the audit log at the given path, continuing the chain of hashes of the entries it holds already
+    pub fn open(path: &str) -> Result<AuditLog, String> {
+        let path = PathBuf::from(path);
+        let mut entries = read_entries(&path)?;
+        // The current file is empty right after a rotation, the chain then continues from the last rotated file
+        if entries.is_empty() {
+            entries = read_entries(&rotated_path(&path, 1))?;
+        }
+        let (next_sequence, last_hash) = match entries.last() {
+            Some(entry) => (entry.record.sequence + 1, entry.hash.clone()),
+            None => (0, FIRST_PREVIOUS_HASH.to_string()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(&path).map_err(|e| format!("Failed to open audit log {}: {}", path.display(), e))?;
+        let size = file.metadata().map(|metadata| metadata.len()).unwrap_or_default();
+        Ok(AuditLog { path, file, size, next_sequence, last_hash })
+    }
+
+    /// Appends a processed request to the audit log at the given unix time, rotating it first if it is full
+    pub fn append(&mut self, audited: &Audited, time: u64) -> Result<(), String> {
+        let (reason, transaction_id) = match audited.result {
+            Ok(message) => (None, committed_transaction(message)),
+            Err(reason) => (Some(reason.chars().take(MAX_REASON_LENGTH).collect()), None),
+        };
+        let record = Record {
+            sequence: self.next_sequence,
+            time,
+            transport: audited.transport.to_string(),
+            source: audited.source.to_string(),
+            from_node: audited.from_node.to_string(),
+            operation: audited.operation.to_string(),
+            accepted: audited.result.is_ok(),
+            reason,
+            transaction_id,
+            previous_hash: self.last_hash.clone(),
+        };
+        let hash = record.hash();
+        let mut line = serde_json::to_string(&Entry { record, hash: hash.clone() }).unwrap();
+        line.push('\n');
+
+        if self.size > 0 && self.size + line.len() as u64 > MAX_FILE_SIZE {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes()).map_err(|e| format!("Failed to write to audit log {}: {}", self.path.display(), e))?;
+        self.size += line.len() as u64;
+        self.next_sequence += 1;
+        self.last_hash = hash;
+        Ok(())
+    }
+
+    /// Moves every file of the audit log one number up, deleting the oldest, and starts a new current file
+    fn rotate(&mut self) -> Result<(), String> {
+        let rotate = || -> std::io::Result<File> {
+            for number in (1..MAX_ROTATED_FILES).rev() {
+                let rotated = rotated_path(&self.path, number);
+                if rotated.exists() {
+                    std::fs::rename(&rotated, rotated_path(&self.path, number + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, rotated_path(&self.path, 1))?;
+            OpenOptions::new().create(true).append(true).open(&self.path)
+        };
+        self.file = rotate().map_err(|e| format!("Failed to rotate audit log {}: {}", self.path.display(), e))?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// Returns the path of a rotated file of the audit log, e.g. `audit.log.1`
+fn rotated_path(path: &std::path::Path, number: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", number));
+    PathBuf::from(rotated)
+}
+
+/// Reads the entries of a file of the audit log, none if it doesn't exist
+fn read_entries(path: &std::path::Path) -> Result<Vec<Entry>, String> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read audit log {}: {}", path.display(), e)),
+    };
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| serde_json::from_str(line).map_err(|e| format!("Invalid entry on line {} of audit log {}: {}", index + 1, path.display(), e)))
+        .collect()
+}
+
+/// Returns the ID of the transaction a response commits, from e.g. "✅ Transaction 0b7d...e21c to transfer ...
+/// committed."
+fn committed_transaction(message: &str) -> Option<String> {
+    let rest = message.strip_prefix("✅ Transaction ")?;
+    let transaction_id = rest.split(' ').next()?;
+    message.contains(" committed").then(|| transaction_id.to_string())
+}
+
+/// Records a processed request in the audit log of the node, if it keeps one
+pub fn record(state: &State, audited: Audited) {
+    if let Some(audit) = &state.audit {
+        if let Err(e) = audit.lock().unwrap().append(&audited, state.clock.unix_time()) {
+            tracing::error!("{}", e);
+        }
+    }
+}
+
+/// Verifies the chain of hashes of the audit log, from the oldest rotated file to the current one, and describes the
+/// latest entries
+///
+/// Returns: the latest entries matching the query, or an error describing where the chain is broken
+pub fn get_audit_log_response(state: &State, audit_info: &GetAuditLogOp, max_length: usize) -> Result<String, String> {
+    let Some(audit) = &state.audit else {
+        return Err(format!("❌ Node {} doesn't keep an audit log, start it with --audit-log FILE.", state.node_id));
+    };
+    // Held while the files are read, so no entry is appended or rotated away meanwhile
+    let audit = audit.lock().unwrap();
+    let mut files: Vec<PathBuf> = (1..=MAX_ROTATED_FILES).rev().map(|number| rotated_path(&audit.path, number)).filter(|path| path.exists()).collect();
+    files.push(audit.path.clone());
+
+    let mut entries = Vec::new();
+    let mut previous: Option<(String, u64)> = None;
+    for path in &files {
+        for entry in read_entries(path).map_err(|e| format!("❌ {}", e))? {
+            if entry.record.hash() != entry.hash {
+                return Err(format!("❌ The audit log is tampered with: entry {} in {} doesn't match its hash.", entry.record.sequence, path.display()));
+            }
+            // The oldest entry kept links to an entry in a file that was rotated away, so its link can't be checked
+            if let Some((previous_hash, previous_sequence)) = &previous {
+                if entry.record.previous_hash != *previous_hash || entry.record.sequence != previous_sequence + 1 {
+                    return Err(format!(
+                        "❌ The audit log is tampered with: entry {} in {} doesn't follow entry {}, entries were removed or changed.",
+                        entry.record.sequence,
+                        path.display(),
+                        previous_sequence
+                    ));
+                }
+            }
+            previous = Some((entry.hash.clone(), entry.record.sequence));
+            entries.push(entry);
+        }
+    }
+    // Entries removed from the end don't break the chain, but the log knows which entry it wrote last
+    let last_sequence = previous.map(|(_, sequence)| sequence);
+    if last_sequence != audit.next_sequence.checked_sub(1) {
+        let last = last_sequence.map_or("no entries".to_string(), |sequence| format!("entry {}", sequence));
+        return Err(format!("❌ The audit log is tampered with: it ends with {}, but entry {} was the last one written.", last, audit.next_sequence - 1));
+    }
+
+    let matching: Vec<&Entry> = entries
+        .iter()
+        .rev()
+        .filter(|entry| audit_info.operation.as_ref().is_none_or(|operation| *operation == entry.record.operation))
+        .filter(|entry| audit_info.source.as_ref().is_none_or(|source| entry.record.source.starts_with(source.as_str())))
+        .take(audit_info.count)
+        .collect();
+    let first = entries.first().map_or(0, |entry| entry.record.sequence);
+    let mut response = format!("✅ The hash chain of the audit log is intact over {} entries, from entry {}. The latest {} matching:", entries.len(), first, matching.len());
+    for (index, entry) in matching.iter().enumerate() {
+        let record = &entry.record;
+        let outcome = match (&record.reason, &record.transaction_id) {
+            (Some(reason), _) => format!("rejected, {}", reason),
+            (None, Some(transaction_id)) => format!("accepted, transaction {}", transaction_id),
+            (None, None) => "accepted".to_string(),
+        };
+        let line = format!("\n- #{} at {}: {} from {} ({}) over {}: {}", record.sequence, record.time, record.operation, record.source, record.from_node, record.transport, outcome);
+        let more = format!("\n… and {} more", matching.len() - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < matching.len() {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    Ok(response)
+}
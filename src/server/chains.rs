@@ -0,0 +1,108 @@
+//! The chains a node hosts. Every node has a default chain, the chain of its genesis configuration and data directory,
+//! and can host named chains besides it with `--host-chain`, e.g. a `test` chain next to the default one. Every chain
+//! has its own genesis, ledger, mempool, clock and storage in `chains/<NAME>` of the data directory, and mints its own
+//! blocks. Requests name the chain they are for with `--chain` and are routed to it, requests without a chain go to the
+//! default chain. Named chains are local to the node: they aren't synced or gossiped with peers, and the HTTP endpoints
+//! only serve the default chain.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::info_span;
+use crate::common::validate_chain_name;
+use crate::config::NodeConfig;
+use super::genesis::Genesis;
+use super::{load_genesis, open_state, request_shutdown, State};
+
+/// The default chain of a node and its named chains
+pub struct Chains {
+    default: Arc<State>,
+    named: BTreeMap<String, Arc<State>>,
+}
+
+impl Chains {
+    /// Opens the default chain of the config and its named chains. A named chain without a genesis configuration
+    /// starts from the empty default genesis, with its name as the chain ID.
+    ///
+    /// Returns: the chains, or why one of them can't be opened
+    pub fn open(config: &NodeConfig) -> Result<Chains, String> {
+        let mut chains = BTreeMap::new();
+        for chain in &config.chains {
+            let (name, genesis) = match chain.split_once('=') {
+                Some((name, genesis)) => (name, Some(genesis)),
+                None => (chain.as_str(), None),
+            };
+            validate_chain_name(name).map_err(|e| e.trim_start_matches("❌ ").to_string())?;
+            if chains.insert(name.to_string(), genesis).is_some() {
+                return Err(format!("Chain {} is hosted twice, every named chain needs its own name.", name));
+            }
+        }
+        if !chains.is_empty() && config.shard_map.is_some() {
+            return Err("A sharded node holds its shard on the default chain only, as the shards settle their transfers on it. Host the named chains on another node.".to_string());
+        }
+
+        let default = open_state(config, load_genesis(config.genesis.as_deref())?)?;
+        let mut named = BTreeMap::new();
+        for (name, genesis) in chains {
+            let _span = info_span!("chain", name).entered();
+            let genesis = match genesis {
+                Some(path) => Genesis::load(path)?,
+                None => Genesis { chain_id: name.clone(), ..Genesis::default() },
+            };
+            // The audit log records the requests to every chain, it is kept by the default chain
+            let chain_config = NodeConfig {
+                data_dir: config.data_dir.as_ref().map(|data_dir| Path::new(data_dir).join("chains").join(&name).to_string_lossy().into_owned()),
+                audit_log: None,
+                ..config.clone()
+            };
+            let state = open_state(&chain_config, genesis).map_err(|e| format!("Chain {} can't be opened. {}", name, e))?;
+            named.insert(name, state);
+        }
+        Ok(Chains { default, named })
+    }
+
+    /// The default chain, which also receives the requests and holds the state of the node as a whole, e.g. its peers
+    /// and sessions
+    pub fn default_chain(&self) -> &Arc<State> {
+        &self.default
+    }
+
+    /// Finds the chain a request is for
+    ///
+    /// Returns: the chain, the default chain if no name is given, or a user-facing error if the node doesn't host it
+    pub fn get(&self, name: Option<&str>) -> Result<&Arc<State>, String> {
+        let Some(name) = name else {
+            return Ok(&self.default);
+        };
+        self.named.get(name).ok_or_else(|| match self.named.is_empty() {
+            true => format!("❌ Chain {} isn't hosted by this node, it only hosts its default chain.", name),
+            false => format!("❌ Chain {} isn't hosted by this node, its named chains are {}.", name, self.names().join(", ")),
+        })
+    }
+
+    /// The names of the named chains, in alphabetical order
+    pub fn names(&self) -> Vec<&str> {
+        self.named.keys().map(String::as_str).collect()
+    }
+
+    /// Calls a function for every chain, the default chain first. The logs of the named chains name their chain.
+    pub fn for_each(&self, mut f: impl FnMut(&Arc<State>)) {
+        f(&self.default);
+        for (name, state) in &self.named {
+            let _span = info_span!("chain", name).entered();
+            f(state);
+        }
+    }
+
+    /// Shuts the node down when one of its named chains is asked to, e.g. by a shutdown request sent to it. The node
+    /// only waits for the shutdown requests of its default chain. Must be called on the runtime of the node.
+    pub fn forward_shutdown_requests(&self) {
+        for state in self.named.values() {
+            let (state, default) = (state.clone(), self.default.clone());
+            tokio::spawn(async move {
+                state.shutdown_requested.notified().await;
+                request_shutdown(&default);
+            });
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Checkpoints finalizing the chain every configured number of blocks. Once a block at a checkpoint height is on the
+//! main chain its hash is recorded, and the node never switches to a fork that doesn't contain it, so blocks up to the
+//! last checkpoint are final. Checkpoints are kept next to the ledger, so they survive a ledger that is replaced, e.g.
+//! by importing another chain into the data directory.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use super::Block;
+
+/// Name of the file in the data directory the checkpoints are stored in
+const CHECKPOINT_FILE: &str = "checkpoints.json";
+
+/// A finalized block
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Checkpoint {
+    pub height: usize,
+    pub hash: String,
+}
+
+/// The checkpoints of the chain, oldest first
+pub struct Checkpoints {
+    // Number of blocks between checkpoints, no new checkpoints are recorded if not set
+    interval: Option<usize>,
+    // File the checkpoints are stored in, they are only kept in memory if not set
+    path: Option<PathBuf>,
+    finalized: Vec<Checkpoint>,
+}
+
+impl Checkpoints {
+    /// Loads the checkpoints stored in the given data directory, none if it has no checkpoint file yet
+    pub fn load(data_dir: Option<&str>, interval: Option<usize>) -> Result<Checkpoints, String> {
+        let path = data_dir.map(|data_dir| Path::new(data_dir).join(CHECKPOINT_FILE));
+        let finalized = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str(&text).map_err(|e| format!("Invalid checkpoint file {}: {}", path.display(), e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+                Err(e) => return Err(format!("Failed to read checkpoint file {}: {}", path.display(), e)),
+            },
+            None => Vec::new(),
+        };
+        Ok(Checkpoints { interval, path, finalized })
+    }
+
+    pub fn last(&self) -> Option<&Checkpoint> {
+        self.finalized.last()
+    }
+
+    /// Checks that a chain contains every checkpoint
+    ///
+    /// Returns: why the chain conflicts with a checkpoint
+    pub fn check(&self, blocks: &[Block]) -> Result<(), String> {
+        for checkpoint in &self.finalized {
+            match blocks.get(checkpoint.height) {
+                Some(block) if block.hash == checkpoint.hash => {}
+                Some(block) => {
+                    return Err(format!("Block {} is {}, but block {} at that height is finalized by a checkpoint.", checkpoint.height, block.hash, checkpoint.hash));
+                }
+                None => {
+                    return Err(format!("The chain ends before block {} at height {}, which is finalized by a checkpoint.", checkpoint.hash, checkpoint.height));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the last checkpoint that replacing the blocks from the given height onwards would conflict with
+    pub fn conflicting(&self, fork_height: usize) -> Option<&Checkpoint> {
+        self.finalized.iter().rev().find(|checkpoint| checkpoint.height >= fork_height)
+    }
+
+    /// Records the checkpoints among the blocks of the main chain from the given height onwards that aren't recorded
+    /// yet
+    pub fn record(&mut self, ledger: &[Block], from_height: usize) -> Result<(), String> {
+        let Some(interval) = self.interval else {
+            return Ok(());
+        };
+        let mut recorded = false;
+        for (height, block) in ledger.iter().enumerate().skip(from_height.max(1)) {
+            if height.is_multiple_of(interval) && self.last().is_none_or(|last| last.height < height) {
+                self.finalized.push(Checkpoint { height, hash: block.hash.clone() });
+                tracing::info!(height, hash = %block.hash, "Block finalized by a checkpoint.");
+                recorded = true;
+            }
+        }
+        if recorded {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Saves the checkpoints, written next to the old file and then moved over it so a failed write keeps the old file
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let temporary = path.with_extension("json.tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&temporary)?;
+            file.write_all(serde_json::to_string_pretty(&self.finalized).unwrap().as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, path)
+        };
+        write().map_err(|e| format!("Failed to save checkpoint file {}: {}", path.display(), e))
+    }
+}
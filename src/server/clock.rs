@@ -0,0 +1,106 @@
+//! The clock of the node. Transactions, blocks and the timelocks of escrows read the time from the [Clock] of the
+//! node's state, the system clock unless the node simulates. A node started with `--simulate` doesn't mint blocks on a
+//! timer: its simulated clock starts at the timestamp of its last block and only moves when a client advances it (see
+//! `toychain advance-time`), which mints a block at every mint interval the clock passes. So the same requests and
+//! advances always make the same chain, e.g. for integration tests of scenarios spanning many blocks.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::info;
+use crate::common::AdvanceTimeOp;
+use super::{health, mint_block, State};
+
+/// Source of the current time
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// Returns the current time in seconds since the Unix epoch
+    fn unix_time(&self) -> u64 {
+        self.now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+    }
+}
+
+/// The time of the system
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A time that only moves when it is set
+pub struct SimulatedClock {
+    now: Mutex<SystemTime>,
+}
+
+impl SimulatedClock {
+    fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// The simulated time of a node started with `--simulate`
+pub struct Simulation {
+    clock: Arc<SimulatedClock>,
+    // Time of the next tick of the mint loop, held while the clock is advanced so advances don't interleave
+    next_tick: Mutex<SystemTime>,
+}
+
+impl Simulation {
+    /// Returns a simulated time standing at the Unix epoch until it is started
+    pub fn new() -> Simulation {
+        let clock = Arc::new(SimulatedClock { now: Mutex::new(UNIX_EPOCH) });
+        Simulation { clock, next_tick: Mutex::new(UNIX_EPOCH) }
+    }
+
+    /// Returns the simulated clock, for the state of the node to read the time from
+    pub fn clock(&self) -> Arc<dyn Clock> {
+        self.clock.clone()
+    }
+
+    /// Starts the simulated time at the timestamp of the last block, the first block is minted a mint interval later
+    pub fn start(&self, timestamp: u64, mint_interval: u64) {
+        let start = UNIX_EPOCH + Duration::from_secs(timestamp);
+        self.clock.set(start);
+        *self.next_tick.lock().unwrap() = start + Duration::from_secs(mint_interval.max(1));
+        info!("Simulating, the clock starts at {} (unix time) and only moves when it is advanced.", timestamp);
+    }
+}
+
+/// Advances the simulated clock of the node, minting a block at every tick of the mint loop it passes, like the mint
+/// loop would have in that time
+///
+/// Returns: a user-facing message with the new time and the blocks minted, an error if the node doesn't simulate
+pub fn advance_time(state: &State, advance_info: &AdvanceTimeOp) -> Result<String, String> {
+    let Some(simulation) = &state.simulation else {
+        return Err(format!("❌ Node {} runs on the system clock, start it with --simulate to advance its time.", state.node_id));
+    };
+    let mut next_tick = simulation.next_tick.lock().unwrap();
+    let target = simulation.clock.now() + Duration::from_secs(advance_info.seconds);
+    let mut minted = 0;
+    while *next_tick <= target {
+        simulation.clock.set(*next_tick);
+        let minted_block = mint_block(state).is_some();
+        health::record_mint_round(state, minted_block);
+        minted += minted_block as usize;
+        // The mint interval can change with the blocks minted, as governance decides on it
+        *next_tick += Duration::from_secs(state.mint_interval().max(1));
+    }
+    simulation.clock.set(target);
+
+    let height = state.ledger.read().unwrap().len() - 1;
+    Ok(format!(
+        "✅ Advanced the clock by {}s to {} (unix time), minted {} blocks, the chain is at height {}.",
+        advance_info.seconds,
+        simulation.clock.unix_time(),
+        minted,
+        height
+    ))
+}
@@ -0,0 +1,233 @@
+//! Compaction of the ledger of long-running nodes. A node started with `--compact-after N` moves the blocks that are
+//! more than N blocks below the tip out of the ledger file into an archive file on a background thread, compressed
+//! with zstd in segments of at least N blocks. The archive is read before the ledger file whenever a data directory is
+//! read, so the compacted blocks are decompressed transparently, e.g. when the node restores its chain or the chain is
+//! verified or exported.
+
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use super::storage::{upgrade_blocks, BlockV17, BlockV18, FORMAT_VERSION};
+use super::Block;
+use crate::vault::Vault;
+
+/// Name of the file in the data directory the compacted blocks are stored in
+const ARCHIVE_FILE: &str = "archive.zst";
+
+/// Prefix of archive files, followed by the format version of the blocks in it (2 bytes, little endian)
+const MAGIC: &[u8; 3] = b"TCZ";
+
+/// Length of the header of archive files
+const HEADER_LENGTH: u64 = MAGIC.len() as u64 + 2;
+
+/// The zstd level segments are compressed with. Compaction runs in the background, so it favors size over speed.
+const COMPRESSION_LEVEL: i32 = 19;
+
+/// A compressed run of blocks in the archive file
+struct Segment {
+    // Number of blocks in the segment
+    blocks: usize,
+    // Hash of the last block and number of transactions in the segment, to tell whether its blocks changed
+    last_hash: String,
+    transactions: usize,
+    // Offset in the archive file right after the segment
+    end: u64,
+}
+
+/// The archive file of the oldest blocks of the chain. After the header, every segment is stored as its compressed
+/// length (4 bytes, little endian) followed by its bincode encoded blocks, compressed with zstd and then sealed if the
+/// data directory is encrypted.
+pub struct Archive {
+    path: PathBuf,
+    segments: Vec<Segment>,
+    // Format version of the blocks in the archive file
+    version: u16,
+    vault: Option<Vault>,
+}
+
+impl Archive {
+    /// Reads the archive file in the given data directory, an empty archive if there is none yet. A segment that was
+    /// only partially written is ignored, its blocks are still in the ledger file.
+    ///
+    /// Returns: the archive and its blocks, oldest first
+    pub fn read(data_dir: &Path, vault: Option<&Vault>) -> Result<(Archive, Vec<Block>), String> {
+        let path = data_dir.join(ARCHIVE_FILE);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read archive file {}: {}", path.display(), e)),
+        };
+        let mut archive = Archive { path, segments: Vec::new(), version: FORMAT_VERSION, vault: vault.cloned() };
+        if contents.is_empty() {
+            return Ok((archive, Vec::new()));
+        }
+
+        let version = contents.strip_prefix(MAGIC).and_then(|rest| rest.get(..2)).map(|version| u16::from_le_bytes(version.try_into().unwrap()));
+        match version {
+            // Versions 9 to 17 only added transaction kinds, so the blocks of version 8 to 17 archives have the same
+            // format. Archives of earlier versions are upgraded, see [Archive::upgrade].
+            Some(version @ 8..=FORMAT_VERSION) => archive.version = version,
+            Some(version) => {
+                return Err(format!(
+                    "Archive file {} has format version {}, this node supports version {}.",
+                    archive.path.display(),
+                    version,
+                    FORMAT_VERSION
+                ))
+            }
+            None => return Err(format!("Invalid header of archive file {}.", archive.path.display())),
+        }
+
+        let mut blocks = Vec::new();
+        let mut offset = HEADER_LENGTH as usize;
+        while let Some(length) = contents.get(offset..offset + 4) {
+            let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+            let Some(compressed) = contents.get(offset + 4..offset + 4 + length) else {
+                tracing::warn!("Ignoring incomplete segment at the end of archive file {}.", archive.path.display());
+                break;
+            };
+            let segment: Vec<Block> = Vault::open_with(vault, compressed)
+                .and_then(|compressed| zstd::decode_all(compressed.as_slice()).map_err(|e| e.to_string()))
+                .and_then(|bytes| match archive.version {
+                    ..=17 => bincode::deserialize::<Vec<BlockV17>>(&bytes).map(upgrade_blocks).map_err(|e| e.to_string()),
+                    18 => bincode::deserialize::<Vec<BlockV18>>(&bytes).map(upgrade_blocks).map_err(|e| e.to_string()),
+                    _ => bincode::deserialize(&bytes).map_err(|e| e.to_string()),
+                })
+                .map_err(|e| format!("Failed to read segment {} of archive file {}: {}", archive.segments.len(), archive.path.display(), e))?;
+            offset += 4 + length;
+            archive.segments.push(Segment::of(&segment, offset as u64));
+            blocks.extend(segment);
+        }
+        Ok((archive, blocks))
+    }
+
+    /// Returns the number of blocks in the archive
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(|segment| segment.blocks).sum()
+    }
+
+    /// Returns the format version of the blocks in the archive file, None if it has no blocks
+    pub fn version(&self) -> Option<u16> {
+        (!self.segments.is_empty()).then_some(self.version)
+    }
+
+    /// Returns the offset in the archive file after the last complete segment
+    fn end(&self) -> u64 {
+        self.segments.last().map_or(HEADER_LENGTH, |segment| segment.end)
+    }
+
+    /// Compresses the given blocks, which follow the archived ones, into a new segment at the end of the archive file
+    pub fn append(&mut self, blocks: &[Block]) -> Result<(), String> {
+        let record = compress(blocks, self.vault.as_ref());
+        let end = self.end();
+        let write = || -> std::io::Result<()> {
+            let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(&self.path)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&header())?;
+            file.seek(SeekFrom::Start(end))?;
+            file.write_all(&record)?;
+            // Drops what is left of a segment that was only partially written
+            file.set_len(end + record.len() as u64)?;
+            file.sync_all()
+        };
+        write().map_err(|e| format!("Failed to write archive file {}: {}", self.path.display(), e))?;
+        self.segments.push(Segment::of(blocks, end + record.len() as u64));
+        Ok(())
+    }
+
+    /// Replaces the archived blocks by the first blocks of the given chain, e.g. after blocks were pruned or the node
+    /// switched to a fork replacing archived blocks. Segments whose blocks didn't change are kept as they are, the
+    /// following ones are compressed again with the same number of blocks as long as the chain has enough blocks.
+    ///
+    /// Returns: the number of blocks of the chain that are archived
+    pub fn rewrite(&mut self, blocks: &[Block]) -> Result<usize, String> {
+        let mut kept = 0;
+        let mut archived = 0;
+        for segment in &self.segments {
+            match blocks.get(archived..archived + segment.blocks) {
+                Some(segment_blocks) if segment.matches(segment_blocks) => {
+                    kept += 1;
+                    archived += segment.blocks;
+                }
+                _ => break,
+            }
+        }
+        if kept == self.segments.len() {
+            return Ok(archived);
+        }
+        self.rewrite_from(kept, archived, blocks)
+    }
+
+    /// Rewrites the archive file in the current format version if it is of an earlier one, as segments can't be
+    /// appended to it. The segments are compressed again with the same number of blocks.
+    pub fn upgrade(&mut self, blocks: &[Block]) -> Result<(), String> {
+        if self.version == FORMAT_VERSION {
+            return Ok(());
+        }
+        self.rewrite_from(0, 0, blocks)?;
+        tracing::info!("Upgraded archive file {} from format version {} to {}.", self.path.display(), self.version, FORMAT_VERSION);
+        self.version = FORMAT_VERSION;
+        Ok(())
+    }
+
+    /// Keeps the given number of segments, which hold the given number of archived blocks, and compresses the following
+    /// ones again from the blocks of the chain after them
+    ///
+    /// Returns: the number of blocks of the chain that are archived
+    fn rewrite_from(&mut self, kept: usize, mut archived: usize, blocks: &[Block]) -> Result<usize, String> {
+        let contents = std::fs::read(&self.path).map_err(|e| format!("Failed to read archive file {}: {}", self.path.display(), e))?;
+        let replaced = self.segments.split_off(kept);
+        let mut end = self.end();
+        let mut contents = [header().as_slice(), &contents[HEADER_LENGTH as usize..end as usize]].concat();
+        for segment in replaced {
+            let Some(segment_blocks) = blocks.get(archived..archived + segment.blocks) else { break };
+            let record = compress(segment_blocks, self.vault.as_ref());
+            end += record.len() as u64;
+            contents.extend_from_slice(&record);
+            self.segments.push(Segment::of(segment_blocks, end));
+            archived += segment.blocks;
+        }
+
+        // Written next to the old file and then moved over it, so the old file stays intact if the write fails
+        let temporary = self.path.with_extension("zst.tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&temporary)?;
+            file.write_all(&contents)?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, &self.path)
+        };
+        write().map_err(|e| format!("Failed to rewrite archive file {}: {}", self.path.display(), e))?;
+        Ok(archived)
+    }
+}
+
+impl Segment {
+    fn of(blocks: &[Block], end: u64) -> Segment {
+        Segment {
+            blocks: blocks.len(),
+            last_hash: blocks.last().map(|block| block.hash.clone()).unwrap_or_default(),
+            transactions: blocks.iter().map(|block| block.transactions.len()).sum(),
+            end,
+        }
+    }
+
+    /// Whether the segment holds the given blocks. Blocks are identified by their hash, which covers what they link
+    /// to, and a block whose transactions were pruned lost them.
+    fn matches(&self, blocks: &[Block]) -> bool {
+        let segment = Segment::of(blocks, self.end);
+        segment.blocks == self.blocks && segment.last_hash == self.last_hash && segment.transactions == self.transactions
+    }
+}
+
+fn header() -> Vec<u8> {
+    [MAGIC.as_slice(), &FORMAT_VERSION.to_le_bytes()].concat()
+}
+
+/// Encodes blocks as a segment of the archive file, sealed if the data directory is encrypted
+fn compress(blocks: &[Block], vault: Option<&Vault>) -> Vec<u8> {
+    let compressed = Vault::seal_with(vault, zstd::encode_all(bincode::serialize(blocks).unwrap().as_slice(), COMPRESSION_LEVEL).unwrap());
+    let mut record = Vec::with_capacity(4 + compressed.len());
+    record.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+    record.extend_from_slice(&compressed);
+    record
+}
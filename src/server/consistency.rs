@@ -0,0 +1,120 @@
+//! Invariants of the chain, asserted after every block added to the ledger in debug builds, so a change that breaks
+//! one panics at the block that broke it instead of leaving a corrupt chain behind:
+//!
+//! * the block is linked into the chain: its header gives its height, its hash matches its header and its previous
+//!   hash is the hash of the block before it
+//! * no balance is negative, neither in the native coin nor in a token, and no stake is
+//! * the native coin is conserved: the balances, including the staked, escrowed and slashed funds, add up to the funds
+//!   the chain issued, the starting balances of the accounts created, the staking rewards, the faucet payments, the
+//!   credits of transfers from other shards and the subsidies of the block rewards, as fees are paid to the block
+//!   rewards
+//! * every token is conserved: the balances in it add up to its supply
+//!
+//! Release builds skip the checks, as the funds issued are added up over the whole chain for every block.
+
+use std::collections::HashMap;
+use crate::common::Amount;
+use super::token::Tokens;
+use super::{Block, State};
+
+/// Returns the funds the transactions of the blocks issued, or None if they add up to more than an amount can hold
+fn issued(blocks: &[Block]) -> Option<Amount> {
+    let transactions = blocks.iter().flat_map(|block| &block.transactions);
+    // Transactions without a from account create accounts, pay rewards, from the faucet or from other shards, slash or
+    // freeze, and only rewards pay what other transactions paid in fees, on top of the subsidies they issue
+    let issuing = transactions.filter(|transaction| transaction.from_account_id.is_none() && !transaction.is_reward());
+    let subsidies = blocks.iter().map(|block| Block::fees(&block.transactions).and_then(|fees| block.reward().checked_sub(fees))).collect::<Option<Vec<Amount>>>()?;
+    Amount::checked_sum(issuing.map(|transaction| transaction.native_amount()).chain(subsidies))
+}
+
+/// Returns the total of the balances, or None if they add up to more than an amount can hold
+fn total(balances: &HashMap<String, Amount>) -> Option<Amount> {
+    Amount::checked_sum(balances.values().copied())
+}
+
+/// Describes a total that may have overflowed
+fn describe(total: Option<Amount>) -> String {
+    total.map_or("more than an amount can hold".to_string(), |total| total.to_string())
+}
+
+/// Checks the invariants of the chain against the indexes of the node built from it
+///
+/// # Arguments
+///
+/// * `ledger`: the blocks of the chain, the block to check last
+/// * `pruned`: the height of the latest snapshot and the total of its balances (None if they overflowed), if the
+///   blocks up to it are pruned
+/// * `balances`, `stakes`, `tokens`: the indexes of the node at the tip of the chain
+///
+/// Returns: a description of every invariant that doesn't hold
+fn violations(ledger: &[Block], pruned: Option<(usize, Option<Amount>)>, balances: &HashMap<String, Amount>, stakes: &HashMap<String, Amount>, tokens: &Tokens) -> Vec<String> {
+    let mut violations = Vec::new();
+    let height = ledger.len() - 1;
+    let block = &ledger[height];
+    if block.header.height != height as u64 {
+        violations.push(format!("block {} at height {} has height {} in its header", block.hash, height, block.header.height));
+    }
+    if block.hash != block.header.hash() && block.hash != block.header.legacy_hash() {
+        violations.push(format!("block {} at height {} has a header hashing to {}", block.hash, height, block.header.hash()));
+    }
+    if height > 0 && block.header.previous_hash != ledger[height - 1].hash {
+        violations.push(format!("block {} at height {} links to {} instead of block {}", block.hash, height, block.header.previous_hash, ledger[height - 1].hash));
+    }
+
+    let negative = |(account_id, balance): (&String, &Amount)| balance.is_negative().then(|| format!("{} of account {}", balance, account_id));
+    violations.extend(balances.iter().filter_map(negative).map(|balance| format!("negative balance {}", balance)));
+    violations.extend(stakes.iter().filter_map(negative).map(|stake| format!("negative stake {}", stake)));
+
+    let (base, start) = match pruned {
+        Some((snapshot_height, snapshot_total)) => (snapshot_total, snapshot_height + 1),
+        None => (Some(Amount::ZERO), 0),
+    };
+    let expected = base.zip(issued(&ledger[start.min(ledger.len())..])).and_then(|(base, issued)| base.checked_add(issued));
+    let actual = total(balances);
+    // Totals that overflowed are violations even if both did, as no chain can issue that much
+    if expected.is_none() || expected != actual {
+        violations.push(format!("the balances add up to {}, but the chain issued {}", describe(actual), describe(expected)));
+    }
+
+    for (token, supply, token_balances) in tokens.supplies() {
+        violations.extend(token_balances.iter().filter_map(negative).map(|balance| format!("negative balance {} in token {}", balance, token)));
+        let actual = total(token_balances);
+        if actual != Some(supply) {
+            violations.push(format!("the balances in token {} add up to {}, but its supply is {}", token, describe(actual), supply));
+        }
+    }
+    violations
+}
+
+/// Asserts the invariants of the chain after a block was added to the ledger, in debug builds
+///
+/// Panics: with every invariant that doesn't hold
+pub fn assert_consistent(state: &State, ledger: &[Block]) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+    let pruned = state.snapshots.lock().unwrap().pruned().map(|snapshot| (snapshot.height, total(&snapshot.accounts.balances)));
+    let violations = violations(ledger, pruned, &state.balances.lock().unwrap(), &state.stakes.lock().unwrap(), &state.tokens.lock().unwrap());
+    assert!(violations.is_empty(), "The chain is inconsistent after block {}: {}.", ledger.len() - 1, violations.join(", "));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::genesis::Genesis;
+
+    #[test]
+    fn reports_balances_overflowing_as_violation() {
+        let ledger = vec![Genesis::default().to_block()];
+        let balances = HashMap::from([("Alice".to_string(), Amount::MAX), ("Bob".to_string(), Amount::from_minor_units(1))]);
+        let violations = violations(&ledger, None, &balances, &HashMap::new(), &Tokens::default());
+        assert_eq!(violations, ["the balances add up to more than an amount can hold, but the chain issued 0"]);
+    }
+
+    #[test]
+    fn reports_pruned_total_overflowing_as_violation() {
+        let ledger = vec![Genesis::default().to_block()];
+        let violations = violations(&ledger, Some((0, None)), &HashMap::new(), &HashMap::new(), &Tokens::default());
+        assert_eq!(violations, ["the balances add up to 0, but the chain issued more than an amount can hold"]);
+    }
+}
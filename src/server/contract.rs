@@ -0,0 +1,254 @@
+//! Smart contracts. `deploy-contract` stores the bytecode of a WebAssembly module on the chain, and `call-contract`
+//! calls a function it exports. Calls are executed when the block they are in is minted, and again by every node
+//! adding or verifying the block, in a sandboxed wasmtime runtime: a contract only sees what the host functions below
+//! give it, its memory is limited, and every instruction consumes gas, of which a call has at most its gas limit. A
+//! call that traps or runs out of gas is still minted and pays its fee, but leaves the storage of the contract as it
+//! was. Floating-point results are canonicalized, so every node gets the same outcome.
+//!
+//! A contract exports its memory as `memory` and the functions to call, which take no parameters and return nothing.
+//! It can import these host functions from the `env` module, with pointers and lengths into its memory:
+//!
+//! * `input(ptr, cap) -> len`: copies up to `cap` bytes of the input of the call to `ptr`, returns its full length
+//! * `caller(ptr, cap) -> len`: the same for the ID of the account making the call
+//! * `height() -> i64`: the height of the block the call is executed in
+//! * `storage_read(key_ptr, key_len, value_ptr, value_cap) -> len`: copies up to `value_cap` bytes of the value stored
+//!   under the key to `value_ptr`, returns its full length, or -1 if nothing is stored under the key
+//! * `storage_write(key_ptr, key_len, value_ptr, value_len)`: stores the value under the key
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Mutex, OnceLock};
+use serde::{Deserialize, Serialize};
+use wasmtime::{Caller, Config, Engine, ExternType, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+use crate::merkle::sha256_hex;
+
+/// Length of the ID of a contract in hex characters
+const CONTRACT_ID_LENGTH: usize = 16;
+
+/// Maximum size in bytes of the bytecode of a contract, so its deployment fits into a request
+pub const MAX_CODE_SIZE: usize = 16 * 1024;
+
+/// Maximum length in bytes of the input of a call
+pub const MAX_INPUT_LENGTH: usize = 1024;
+
+/// Maximum gas limit of a call
+pub const MAX_GAS_LIMIT: u64 = 10_000_000;
+
+/// Maximum size in bytes of the memory of a contract while it executes
+const MAX_MEMORY_SIZE: usize = 1024 * 1024;
+
+/// Maximum length in bytes of a storage key and of a storage value
+const MAX_KEY_LENGTH: usize = 64;
+const MAX_VALUE_LENGTH: usize = 1024;
+
+/// Gas a call of a host function consumes on top of the instructions, and per byte a storage write stores
+const HOST_CALL_GAS: u64 = 100;
+const STORAGE_BYTE_GAS: u64 = 10;
+
+/// A contract deployed on the chain
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Contract {
+    // Account that deployed the contract
+    pub owner: String,
+    pub code: Vec<u8>,
+    // The values the contract stored, by key
+    pub storage: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+/// The contracts deployed on the chain, by ID
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Contracts {
+    contracts: HashMap<String, Contract>,
+}
+
+/// What a call of a contract did
+pub struct Outcome {
+    pub gas_used: u64,
+    // Why the call failed, None if it succeeded
+    pub error: Option<String>,
+}
+
+/// What the host functions of a call can access
+struct Host {
+    caller: String,
+    input: Vec<u8>,
+    height: u64,
+    // The storage of the contract with the writes of the call, kept only if the call succeeds
+    storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    limits: StoreLimits,
+}
+
+/// Returns the ID of the contract deployed by an account with a nonce, derived like the ID of an NFT
+pub fn contract_id(account_id: &str, nonce: u64) -> String {
+    let hash = sha256_hex(&bincode::serialize(&("contract", account_id, nonce)).unwrap());
+    hash[..CONTRACT_ID_LENGTH].to_string()
+}
+
+/// Returns the engine all contracts are compiled with, configured so that execution is deterministic and metered
+fn engine() -> &'static Engine {
+    static ENGINE: OnceLock<Engine> = OnceLock::new();
+    ENGINE.get_or_init(|| {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        config.cranelift_nan_canonicalization(true);
+        config.relaxed_simd_deterministic(true);
+        Engine::new(&config).expect("the contract engine configuration is valid")
+    })
+}
+
+/// Compiles the bytecode of a contract, cached by its hash as every node compiles the same contracts again and again
+fn compile(code: &[u8]) -> Result<Module, String> {
+    static MODULES: OnceLock<Mutex<HashMap<String, Module>>> = OnceLock::new();
+    let hash = sha256_hex(code);
+    let mut modules = MODULES.get_or_init(Default::default).lock().unwrap();
+    if let Some(module) = modules.get(&hash) {
+        return Ok(module.clone());
+    }
+    let module = Module::new(engine(), code).map_err(|e| format!("isn't a valid WebAssembly module: {}", e))?;
+    modules.insert(hash, module.clone());
+    Ok(module)
+}
+
+/// Validates the bytecode of a contract: at most `MAX_CODE_SIZE` bytes of a WebAssembly module that exports its
+/// memory and only imports the host functions
+///
+/// Returns: why the bytecode is invalid, to follow "The contract"
+pub fn validate_code(code: &[u8]) -> Result<(), String> {
+    if code.len() > MAX_CODE_SIZE {
+        return Err(format!("is {} bytes long, a contract can be at most {} bytes", code.len(), MAX_CODE_SIZE));
+    }
+    let module = compile(code)?;
+    if !module.exports().any(|export| export.name() == "memory" && matches!(export.ty(), ExternType::Memory(_))) {
+        return Err("doesn't export its memory as \"memory\"".to_string());
+    }
+    if let Some(import) = module.imports().find(|import| import.module() != "env") {
+        return Err(format!("imports {} from module {}, contracts can only import the host functions of module env", import.name(), import.module()));
+    }
+    Ok(())
+}
+
+/// Checks that the bytecode of a contract exports a function that can be called
+///
+/// Returns: why the function can't be called, to follow the contract ID
+pub fn check_function(code: &[u8], function: &str) -> Result<(), String> {
+    let module = compile(code)?;
+    match module.get_export(function) {
+        Some(ExternType::Func(ty)) if ty.params().len() == 0 && ty.results().len() == 0 => Ok(()),
+        Some(ExternType::Func(_)) => Err(format!("exports function {} with parameters or results, only functions without them can be called", function)),
+        _ => Err(format!("doesn't export function {}", function)),
+    }
+}
+
+impl Contracts {
+    pub fn get(&self, contract_id: &str) -> Option<&Contract> {
+        self.contracts.get(contract_id)
+    }
+
+    pub fn deploy(&mut self, contract_id: &str, owner: &str, code: &[u8]) {
+        let contract = Contract { owner: owner.to_string(), code: code.to_vec(), storage: BTreeMap::new() };
+        self.contracts.insert(contract_id.to_string(), contract);
+    }
+
+    /// Executes a call of a contract's function by an account in the block at the given height, and keeps the writes
+    /// to the storage of the contract if the call succeeds
+    pub fn call(&mut self, contract_id: &str, caller: &str, function: &str, input: &[u8], gas_limit: u64, height: u64) -> Outcome {
+        let Some(contract) = self.contracts.get_mut(contract_id) else {
+            return Outcome { gas_used: 0, error: Some(format!("contract {} doesn't exist", contract_id)) };
+        };
+        let host = Host {
+            caller: caller.to_string(),
+            input: input.to_vec(),
+            height,
+            storage: contract.storage.clone(),
+            limits: StoreLimitsBuilder::new().memory_size(MAX_MEMORY_SIZE).instances(1).build(),
+        };
+        let mut store = Store::new(engine(), host);
+        store.limiter(|host| &mut host.limits);
+        store.set_fuel(gas_limit).expect("fuel is enabled");
+        let result = execute(&mut store, &contract.code, function);
+        let gas_used = gas_limit - store.get_fuel().unwrap_or(0);
+        match result {
+            Ok(()) => {
+                contract.storage = store.into_data().storage;
+                Outcome { gas_used, error: None }
+            }
+            Err(e) => Outcome { gas_used, error: Some(e) },
+        }
+    }
+}
+
+/// Instantiates the bytecode of a contract with the host functions and calls the function
+fn execute(store: &mut Store<Host>, code: &[u8], function: &str) -> Result<(), String> {
+    let module = compile(code)?;
+    let mut linker = Linker::new(engine());
+    link_host_functions(&mut linker).map_err(|e| e.to_string())?;
+    let instance = linker.instantiate(&mut *store, &module).map_err(|e| format!("failed to instantiate: {}", e))?;
+    let function = instance.get_typed_func::<(), ()>(&mut *store, function).map_err(|e| e.to_string())?;
+    function.call(&mut *store, ()).map_err(|e| match store.get_fuel() {
+        Ok(0) => "ran out of gas".to_string(),
+        _ => format!("trapped: {}", e.root_cause()),
+    })
+}
+
+/// Reads bytes from the memory of the contract, trapping if they are out of bounds
+fn read(caller: &mut Caller<'_, Host>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let memory = caller.get_export("memory").and_then(|export| export.into_memory()).ok_or_else(|| wasmtime::format_err!("the contract exports no memory"))?;
+    let mut bytes = vec![0; usize::try_from(len)?];
+    memory.read(&caller, usize::try_from(ptr)?, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Writes up to `cap` bytes to the memory of the contract, trapping if they are out of bounds
+///
+/// Returns: the full length of the bytes
+fn write(caller: &mut Caller<'_, Host>, ptr: i32, cap: i32, bytes: &[u8]) -> wasmtime::Result<i32> {
+    let memory = caller.get_export("memory").and_then(|export| export.into_memory()).ok_or_else(|| wasmtime::format_err!("the contract exports no memory"))?;
+    let length = bytes.len().min(usize::try_from(cap)?);
+    memory.write(caller, usize::try_from(ptr)?, &bytes[..length])?;
+    Ok(bytes.len() as i32)
+}
+
+/// Consumes gas for a call of a host function, trapping if the call runs out of gas
+fn consume(caller: &mut Caller<'_, Host>, gas: u64) -> wasmtime::Result<()> {
+    let fuel = caller.get_fuel()?;
+    if fuel < gas {
+        caller.set_fuel(0)?;
+        wasmtime::bail!("out of gas");
+    }
+    caller.set_fuel(fuel - gas)
+}
+
+fn link_host_functions(linker: &mut Linker<Host>) -> wasmtime::Result<()> {
+    linker.func_wrap("env", "input", |mut caller: Caller<'_, Host>, ptr: i32, cap: i32| {
+        consume(&mut caller, HOST_CALL_GAS)?;
+        let input = caller.data().input.clone();
+        write(&mut caller, ptr, cap, &input)
+    })?;
+    linker.func_wrap("env", "caller", |mut caller: Caller<'_, Host>, ptr: i32, cap: i32| {
+        consume(&mut caller, HOST_CALL_GAS)?;
+        let account_id = caller.data().caller.clone();
+        write(&mut caller, ptr, cap, account_id.as_bytes())
+    })?;
+    linker.func_wrap("env", "height", |mut caller: Caller<'_, Host>| {
+        consume(&mut caller, HOST_CALL_GAS)?;
+        Ok(caller.data().height as i64)
+    })?;
+    linker.func_wrap("env", "storage_read", |mut caller: Caller<'_, Host>, key_ptr: i32, key_len: i32, value_ptr: i32, value_cap: i32| {
+        consume(&mut caller, HOST_CALL_GAS)?;
+        let key = read(&mut caller, key_ptr, key_len)?;
+        match caller.data().storage.get(&key).cloned() {
+            Some(value) => write(&mut caller, value_ptr, value_cap, &value),
+            None => Ok(-1),
+        }
+    })?;
+    linker.func_wrap("env", "storage_write", |mut caller: Caller<'_, Host>, key_ptr: i32, key_len: i32, value_ptr: i32, value_len: i32| {
+        if usize::try_from(key_len).is_ok_and(|len| len > MAX_KEY_LENGTH) || usize::try_from(value_len).is_ok_and(|len| len > MAX_VALUE_LENGTH) {
+            wasmtime::bail!("a storage key can be at most {} bytes and a value at most {} bytes", MAX_KEY_LENGTH, MAX_VALUE_LENGTH);
+        }
+        consume(&mut caller, HOST_CALL_GAS + (key_len.max(0) + value_len.max(0)) as u64 * STORAGE_BYTE_GAS)?;
+        let key = read(&mut caller, key_ptr, key_len)?;
+        let value = read(&mut caller, value_ptr, value_len)?;
+        caller.data_mut().storage.insert(key, value);
+        Ok(())
+    })?;
+    Ok(())
+}
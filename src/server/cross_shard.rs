@@ -0,0 +1,215 @@
+//! Transfers between the shards of a sharded cluster, see [crate::shard]. A transfer to an account of another shard is
+//! made in two phases. First the source shard locks the funds: the transfer moves them from the sender to the
+//! cross-shard account, until a deadline. Then the node of the source shard asks the node of the destination shard to
+//! credit them to the recipient before the deadline, and settles the lock by what it answers: a credit that was minted
+//! commits the transfer, which keeps the funds in the cross-shard account of the source shard as they moved to the
+//! other shard, and a transfer the destination shard can't credit anymore once the deadline passed is refunded to the
+//! sender. The destination shard decides the outcome, as it only credits a transfer in a block before its deadline and
+//! only once, so the funds are either credited or refunded, never both.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+use crate::client::Client;
+use crate::common::{Amount, CreditCrossShardOp, Request};
+use super::{State, Transaction, TransactionKind};
+
+/// Account holding the funds locked for transfers to other shards and the funds that moved to them. Funds only get
+/// into it by cross-shard transfers and only leave it by refunds.
+pub const CROSS_SHARD_ACCOUNT: &str = "@cross-shard";
+
+/// Seconds a cross-shard transfer can be credited on the destination shard for, after which it is refunded
+pub const CROSS_SHARD_TIMEOUT: u64 = 60;
+
+/// Interval at which the node asks the destination shards to credit its locked transfers
+const SETTLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Funds locked on the source shard for a transfer to another shard
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Lock {
+    pub sender: String,
+    pub recipient: String,
+    // Shard of the recipient
+    pub shard: usize,
+    pub amount: Amount,
+    // Unix time in seconds until which the recipient's shard can credit the transfer
+    pub deadline: u64,
+}
+
+/// The cross-shard transfers of a shard: the ones it sent that weren't settled yet, by the ID of the transaction that
+/// locked them, and the IDs of the ones it credited
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct CrossShardTransfers {
+    locked: HashMap<String, Lock>,
+    credited: HashSet<String>,
+}
+
+impl CrossShardTransfers {
+    pub fn lock(&mut self, transfer_id: String, lock: Lock) {
+        self.locked.insert(transfer_id, lock);
+    }
+
+    pub fn settle(&mut self, transfer_id: &str) {
+        self.locked.remove(transfer_id);
+    }
+
+    pub fn credit(&mut self, transfer_id: String) {
+        self.credited.insert(transfer_id);
+    }
+
+    pub fn is_credited(&self, transfer_id: &str) -> bool {
+        self.credited.contains(transfer_id)
+    }
+
+    /// Returns the locked transfers that weren't settled yet, by their IDs
+    pub fn locked(&self) -> impl Iterator<Item = (&String, &Lock)> {
+        self.locked.iter()
+    }
+
+    /// Returns the number of locked transfers an account sent that weren't settled yet
+    pub fn involving(&self, account_id: &str) -> usize {
+        self.locked.values().filter(|lock| lock.sender == account_id).count()
+    }
+
+    /// Checks that a locked transfer can be committed, or refunded if `refund` is set, in a block with the given
+    /// timestamp
+    ///
+    /// Returns: the lock, or why it can't be settled, to follow the transfer ID
+    pub fn check_settlement(&self, transfer_id: &str, refund: bool, timestamp: u64) -> Result<&Lock, String> {
+        let lock = self.locked.get(transfer_id).ok_or_else(|| "doesn't exist or was settled already".to_string())?;
+        if refund && timestamp <= lock.deadline {
+            return Err(format!("can be credited until {}, it can't be refunded before", lock.deadline));
+        }
+        Ok(lock)
+    }
+}
+
+/// What the destination shard answers when asked to credit a transfer
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum CreditStatus {
+    // The credit was minted, the source shard commits the transfer
+    Credited,
+    // The credit is waiting to be minted, or can't be made yet
+    Pending,
+    // The deadline passed without a credit, the source shard refunds the transfer
+    Aborted,
+}
+
+/// Answers the node of a source shard asking to credit a transfer: credits it if it wasn't yet and its deadline didn't
+/// pass, and tells whether it is credited
+///
+/// Returns: the status of the credit in JSON, or a user-facing error if the node can't credit the transfer
+pub fn credit_response(state: &State, from_node: String, credit_info: &CreditCrossShardOp) -> Result<String, String> {
+    let Some(shard) = &state.shard else {
+        return Err("❌ This node doesn't hold a shard, it has no transfers from other shards to credit.".to_string());
+    };
+    shard.check_credit(&from_node, credit_info)?;
+    let transfer_id = &credit_info.transfer_id;
+
+    // Locked before the mempool like for transfers, so the credit is only added once
+    let _nonces = state.nonces.lock().unwrap();
+    let status = if state.cross_shard.lock().unwrap().is_credited(transfer_id) {
+        CreditStatus::Credited
+    } else if state.mempool.lock().unwrap().transactions().any(|transaction| transaction.credits_cross_shard() == Some(transfer_id)) {
+        CreditStatus::Pending
+    } else if state.clock.unix_time() > credit_info.deadline {
+        CreditStatus::Aborted
+    } else {
+        State::check_not_reserved(&credit_info.recipient)?;
+        if !state.account_exists(&credit_info.recipient) {
+            return Err(format!("❌ Account {} doesn't exist, transfer {} is refunded unless it is created until {}.", credit_info.recipient, transfer_id, credit_info.deadline));
+        }
+        if state.closed.lock().unwrap().contains(&credit_info.recipient) {
+            return Err(format!("❌ Account {} is closed.", credit_info.recipient));
+        }
+        let transaction = Transaction {
+            kind: TransactionKind::CrossShardCredit {
+                transfer_id: transfer_id.clone(),
+                sender: credit_info.sender.clone(),
+                shard: credit_info.shard,
+                deadline: credit_info.deadline,
+            },
+            ..Transaction::new(from_node, None, credit_info.recipient.clone(), credit_info.amount, Amount::ZERO, None, state.clock.now(), None)
+        };
+        state.submit_transaction(transaction)?;
+        info!(transfer = %transfer_id, "Crediting {} from account {} on shard {} to account {}.", credit_info.amount, credit_info.sender, credit_info.shard, credit_info.recipient);
+        CreditStatus::Pending
+    };
+    Ok(serde_json::to_string(&status).unwrap())
+}
+
+/// Asks the destination shards to credit the locked transfers of the node, and commits or refunds the ones they
+/// credited or aborted
+fn settle_round(state: &State) {
+    let Some(shard) = &state.shard else { return };
+    let locked: Vec<(String, Lock)> = state.cross_shard.lock().unwrap().locked().map(|(transfer_id, lock)| (transfer_id.clone(), lock.clone())).collect();
+    for (transfer_id, lock) in locked {
+        let settling = state.mempool.lock().unwrap().transactions().any(|transaction| transaction.settles_cross_shard().is_some_and(|(pending, _)| pending == transfer_id));
+        if settling {
+            continue;
+        }
+        let credit = CreditCrossShardOp {
+            transfer_id: transfer_id.clone(),
+            shard: shard.index(),
+            sender: lock.sender.clone(),
+            recipient: lock.recipient.clone(),
+            amount: lock.amount,
+            deadline: lock.deadline,
+            signature: String::new(),
+        };
+        let request = Request::new_credit_cross_shard_request(&state.identity, credit);
+        let node = shard.node(lock.shard);
+        let mut client = Client::new(node.to_string(), Duration::from_secs(1), 1).quiet();
+        let status = client
+            .send(&request)
+            .and_then(|response| response.result)
+            .and_then(|message| serde_json::from_str::<CreditStatus>(&message).map_err(|_| format!("❌ Invalid credit status: {}", message)));
+        let refund = match status {
+            Ok(CreditStatus::Credited) => false,
+            // The clock of the destination shard may be ahead, the refund has to be minted after the deadline
+            Ok(CreditStatus::Aborted) if state.clock.unix_time() > lock.deadline => true,
+            Ok(_) => continue,
+            Err(e) => {
+                debug!(transfer = %transfer_id, node, "Crediting the transfer on shard {} failed: {}", lock.shard, e);
+                continue;
+            }
+        };
+        match settle(state, &transfer_id, &lock, refund) {
+            Ok(message) => info!(transfer = %transfer_id, "{}", message),
+            Err(e) => warn!(transfer = %transfer_id, "Settling the transfer failed: {}", e),
+        }
+    }
+}
+
+/// Commits a locked transfer, or refunds it to its sender if `refund` is set. The settlement is paid from the
+/// cross-shard account without a fee or nonce, a commit to the account itself as the funds stay in it.
+///
+/// Returns: a message confirming the transaction, or a user-facing error if the mempool rejected it
+fn settle(state: &State, transfer_id: &str, lock: &Lock, refund: bool) -> Result<String, String> {
+    let (to_account_id, kind) = match refund {
+        true => (lock.sender.clone(), TransactionKind::CrossShardRefund(transfer_id.to_string())),
+        false => (CROSS_SHARD_ACCOUNT.to_string(), TransactionKind::CrossShardCommit(transfer_id.to_string())),
+    };
+    let transaction = Transaction {
+        kind,
+        ..Transaction::new(state.node_id.clone(), Some(CROSS_SHARD_ACCOUNT.to_string()), to_account_id, lock.amount, Amount::ZERO, None, state.clock.now(), None)
+    };
+    let transaction_id = state.submit_transaction(transaction)?;
+    Ok(match refund {
+        true => format!("Transaction {} refunds {} to account {}, shard {} didn't credit it until {}.", transaction_id, lock.amount, lock.sender, lock.shard, lock.deadline),
+        false => format!("Transaction {} commits the transfer of {} to account {} credited on shard {}.", transaction_id, lock.amount, lock.recipient, lock.shard),
+    })
+}
+
+/// Settles the locked transfers of the node with the destination shards every settle interval, until the node shuts
+/// down
+pub async fn settle_transfers(state: Arc<State>) {
+    loop {
+        tokio::time::sleep(SETTLE_INTERVAL).await;
+        let shared_state = state.clone();
+        tokio::task::spawn_blocking(move || settle_round(&shared_state)).await.unwrap();
+    }
+}
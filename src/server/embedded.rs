@@ -0,0 +1,174 @@
+//! A node running in the process of its caller, so downstream crates can write integration tests against a real node
+//! without binding sockets or waiting on the network. Requests are handed to the node directly and processed like the
+//! requests it receives over UDP, and the ledger, balances and mempool can be read back right away.
+//!
+//! ```no_run
+//! use toychain::common::{Amount, Request};
+//! use toychain::config::NodeConfig;
+//! use toychain::server::Node;
+//!
+//! let node = Node::spawn_in_process(NodeConfig::default()).unwrap();
+//! let balance: Amount = "100".parse().unwrap();
+//! node.submit(Request::new_create_account_request("test".to_string(), "alice".to_string(), balance)).unwrap();
+//! node.mint();
+//! assert_eq!(node.balance("alice"), balance);
+//! node.shutdown();
+//! ```
+
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+use serde_json::Value;
+use tokio::runtime::Runtime;
+use crate::common::{Amount, Compression, Format, Request};
+use crate::config::NodeConfig;
+use super::audit::{self, Audited};
+use super::chains::Chains;
+use super::{mint_block, shutdown, start_minting, validation, State};
+
+/// How long shutting down waits for a block the mint loop is minting
+const MINT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Starts nodes in the process of the caller, see [Node::spawn_in_process]
+pub struct Node;
+
+/// A node running in process. The node is shut down when the handle is, or when it is dropped.
+pub struct NodeHandle {
+    chains: Chains,
+    // Runs the mint loops, taken when the node is shut down
+    runtime: Option<Runtime>,
+}
+
+impl Node {
+    /// Starts a node in the process, from the same config as `start-node`. The node doesn't bind its port or the ports
+    /// of its endpoints, and doesn't talk to peers, but mints blocks every mint interval (or when its simulated clock
+    /// is advanced) and persists its chains to the data directory if the config has one. Every node has its own clock,
+    /// so several nodes in the process can simulate and advance their clocks independently.
+    ///
+    /// Returns: the handle of the node, or why it can't be started
+    pub fn spawn_in_process(config: NodeConfig) -> Result<NodeHandle, String> {
+        if config.light {
+            return Err("A light node follows the headers of its peer and can't run in process.".to_string());
+        }
+        let runtime = Runtime::new().map_err(|e| format!("Failed to start the async runtime: {}", e))?;
+        let chains = Chains::open(&config)?;
+        {
+            let _runtime = runtime.enter();
+            chains.for_each(|chain| start_minting(chain, config.mint_interval));
+        }
+        Ok(NodeHandle { chains, runtime: Some(runtime) })
+    }
+}
+
+impl NodeHandle {
+    /// Processes a request like the node processes the requests it receives over UDP: the request is validated and
+    /// waits for the requests changing the same accounts that were submitted before it, and is processed by the chain
+    /// it names. Requests submitted in process aren't checked against the allowlist or rate limited.
+    ///
+    /// Returns: the user-facing message of the node, either the result of the request or why it failed
+    pub fn submit(&self, request: Request) -> Result<String, String> {
+        let _runtime = self.runtime.as_ref().map(Runtime::enter);
+        let source = SocketAddr::from(([127, 0, 0, 1], 0));
+        let operation = request.operation.name();
+        let from_node = request.from_node.clone();
+
+        let state = self.state();
+        let ticket = state.sequencer.admit();
+        let size = request.encode(Format::Bincode, Compression::None).len();
+        let chain = validation::validate(&request, size).map_err(|e| e.to_string()).and_then(|()| self.chains.get(request.chain.as_deref()));
+        let chain = match chain {
+            Ok(chain) => chain,
+            Err(e) => {
+                let result = Err(e);
+                audit::record(state, Audited { transport: "in-process", source, from_node: &from_node, operation, result: &result });
+                return result;
+            }
+        };
+
+        ticket.wait_for_turn(&request.operation.changed_accounts());
+        let worker = state.workers.acquire();
+        let started = std::time::Instant::now();
+        let result = super::process_request(chain.clone(), request, source);
+        drop(worker);
+        state.metrics.observe_request("in-process", operation, started.elapsed());
+        audit::record(state, Audited { transport: "in-process", source, from_node: &from_node, operation, result: &result });
+        result
+    }
+
+    /// Mints a block from the pending transactions of the default chain right away, instead of waiting for the mint
+    /// interval
+    ///
+    /// Returns: the hash of the minted block, None if there was nothing to mint or the slot belongs to another
+    /// validator
+    pub fn mint(&self) -> Option<String> {
+        mint_block(self.state())
+    }
+
+    /// The public key of the node in hex, the node ID of its signed requests
+    pub fn node_id(&self) -> &str {
+        &self.state().node_id
+    }
+
+    /// The height of the last block of the default chain
+    pub fn height(&self) -> usize {
+        self.state().ledger.read().unwrap().len() - 1
+    }
+
+    /// The minted balance of an account on the default chain, by its ID or alias
+    pub fn balance(&self, account_id: &str) -> Amount {
+        let (account_id, _) = self.state().resolve_account(account_id);
+        self.state().get_balance(&account_id)
+    }
+
+    /// The nonce the next transfer from an account has to be made with
+    pub fn nonce(&self, account_id: &str) -> u64 {
+        self.state().get_next_nonce(account_id)
+    }
+
+    /// The number of transactions waiting in the mempool of the default chain
+    pub fn pending(&self) -> usize {
+        self.state().mempool.lock().unwrap().len()
+    }
+
+    /// A block of the default chain and its transactions, as served by `get-block`
+    pub fn block(&self, height: usize) -> Option<Value> {
+        self.state().find_block(Some(height), None).map(|(_, block)| serde_json::to_value(block).unwrap())
+    }
+
+    /// A block of the default chain encoded like the blocks of a chain file, which another node adds when it is
+    /// submitted with [Request::new_submit_block_request]
+    pub fn encoded_block(&self, height: usize) -> Option<Vec<u8>> {
+        self.state().find_block(Some(height), None).map(|(_, block)| bincode::serialize(&block).unwrap())
+    }
+
+    /// Shuts the node down like SIGTERM does: the mint loops are stopped, the transactions left in the mempools are
+    /// minted and the storage is flushed, so the chains are final once this returns
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let Some(runtime) = self.runtime.take() else {
+            return;
+        };
+        self.chains.for_each(|chain| chain.shutting_down.store(true, Ordering::SeqCst));
+        // Waiting for a block being minted blocks, which isn't allowed on a thread of another runtime, e.g. in async
+        // tests
+        if tokio::runtime::Handle::try_current().is_ok() {
+            runtime.shutdown_background();
+        } else {
+            runtime.shutdown_timeout(MINT_TIMEOUT);
+        }
+        self.chains.for_each(|chain| shutdown(chain));
+    }
+
+    fn state(&self) -> &State {
+        self.chains.default_chain()
+    }
+}
+
+impl Drop for NodeHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
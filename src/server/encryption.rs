@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use snow::{HandshakeState, Keypair, StatelessTransportState};
+use tracing::{debug, info, warn};
+use crate::common::MAX_MESSAGE_SIZE;
+use crate::noise::{self, PacketKind, MAX_OVERHEAD};
+use crate::vault::Vault;
+
+/// Name of the file in the data directory the static key of the node is kept in
+const KEY_FILE: &str = "noise.key";
+
+/// Time after which an idle session is forgotten and the client has to start a new handshake
+const SESSION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of sessions above which expired sessions are forgotten
+const MAX_SESSIONS: usize = 10000;
+
+/// A session of a client, keyed by the address the client sends from
+enum Session {
+    // The server answered the first handshake message and waits for the last one
+    Handshaking(Box<HandshakeState>),
+    Established {
+        transport: Arc<StatelessTransportState>,
+        // Nonce of the last request, every request has to use a higher nonce so that requests can't be replayed
+        last_nonce: u64,
+        // Static key the client authenticated with in the handshake
+        client_key: Vec<u8>,
+    },
+}
+
+/// Server side of the encrypted transport, see [crate::noise]
+pub struct Encryption {
+    // Static key pair of the node, the public key identifies the node to clients
+    keypair: Keypair,
+    // Sessions of clients and when they were last used
+    sessions: Mutex<HashMap<SocketAddr, (Session, Instant)>>,
+}
+
+impl Encryption {
+    /// Sets up the encrypted transport with the static key of the node. The key is kept in the data directory so it
+    /// stays the same across restarts, sealed if the data directory is encrypted, without a data directory a new key is
+    /// generated on every start.
+    pub fn new(data_dir: Option<&str>, vault: Option<&Vault>) -> Result<Encryption, String> {
+        let keypair = match data_dir {
+            Some(data_dir) => load_or_generate_keypair(&Path::new(data_dir).join(KEY_FILE), vault)?,
+            None => generate_keypair(),
+        };
+        info!("Encrypted transport available with server key {}.", noise::to_hex(&keypair.public));
+
+        Ok(Encryption {
+            keypair,
+            sessions: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Handles an encrypted packet received from a client
+    ///
+    /// # Arguments
+    ///
+    /// * `packet`: the packet received
+    /// * `src`: the address the packet was sent from, which identifies the session
+    /// * `respond`: processes a decrypted request from the client with the given static key and returns the response,
+    ///   if there is one
+    ///
+    /// Returns: the packet to send back to the client, if any
+    pub fn handle(&self, packet: &[u8], src: SocketAddr, respond: impl FnOnce(&[u8], &[u8]) -> Option<Vec<u8>>) -> Option<Vec<u8>> {
+        let Some((kind, payload)) = noise::decode(packet) else {
+            warn!("Invalid encrypted packet from {}.", src);
+            return None;
+        };
+
+        let result = match kind {
+            PacketKind::Handshake1 => self.start_handshake(payload, src),
+            PacketKind::Handshake3 => self.finish_handshake(payload, src, respond),
+            PacketKind::Transport => self.handle_transport(payload, src, respond),
+            PacketKind::Handshake2 | PacketKind::Error => Err("❌ Unexpected packet, servers only answer handshakes and requests.".to_string()),
+        };
+
+        match result {
+            Ok(response) => response,
+            Err(e) => {
+                debug!("Rejected encrypted packet from {}: {}", src, e);
+                Some(noise::encode(PacketKind::Error, e.as_bytes()))
+            }
+        }
+    }
+
+    /// Answers the first handshake message of a client, replacing any session the client had before
+    fn start_handshake(&self, payload: &[u8], src: SocketAddr) -> Result<Option<Vec<u8>>, String> {
+        let mut handshake = noise::builder()
+            .local_private_key(&self.keypair.private)
+            .and_then(|builder| builder.build_responder())
+            .map_err(|e| format!("❌ Failed to start the handshake: {}", e))?;
+
+        let mut buf = [0u8; MAX_OVERHEAD];
+        handshake.read_message(payload, &mut buf).map_err(|e| format!("❌ Invalid handshake: {}", e))?;
+        let len = handshake.write_message(&[], &mut buf).map_err(|e| format!("❌ Invalid handshake: {}", e))?;
+
+        let mut sessions = self.sessions.lock().unwrap();
+        if sessions.len() >= MAX_SESSIONS {
+            let now = Instant::now();
+            sessions.retain(|_, (_, used)| now.duration_since(*used) < SESSION_TIMEOUT);
+        }
+        sessions.insert(src, (Session::Handshaking(Box::new(handshake)), Instant::now()));
+        Ok(Some(noise::encode(PacketKind::Handshake2, &buf[..len])))
+    }
+
+    /// Completes the handshake of a client with its last message, which carries the first request of the session. The
+    /// response to it is sent with nonce 0.
+    fn finish_handshake(&self, payload: &[u8], src: SocketAddr, respond: impl FnOnce(&[u8], &[u8]) -> Option<Vec<u8>>) -> Result<Option<Vec<u8>>, String> {
+        let Some((Session::Handshaking(mut handshake), _)) = self.take_session(src) else {
+            return Err("❌ No handshake in progress, start a new handshake.".to_string());
+        };
+
+        let mut request = vec![0u8; payload.len()];
+        let len = handshake.read_message(payload, &mut request).map_err(|e| format!("❌ Invalid handshake: {}", e))?;
+        let client_key = handshake.get_remote_static().ok_or("❌ Invalid handshake without a client key.")?.to_vec();
+        let transport = handshake.into_stateless_transport_mode().map_err(|e| format!("❌ Invalid handshake: {}", e))?;
+        let transport = Arc::new(transport);
+        debug!("Encrypted session established with {}.", src);
+
+        let session = Session::Established { transport: transport.clone(), last_nonce: 0, client_key: client_key.clone() };
+        self.sessions.lock().unwrap().insert(src, (session, Instant::now()));
+        respond(&request[..len], &client_key).map(|response| encrypt(&transport, 0, &response)).transpose()
+    }
+
+    /// Decrypts a request of an established session and encrypts the response with the nonce of the request
+    fn handle_transport(&self, payload: &[u8], src: SocketAddr, respond: impl FnOnce(&[u8], &[u8]) -> Option<Vec<u8>>) -> Result<Option<Vec<u8>>, String> {
+        let (nonce, ciphertext) = noise::decode_transport(payload).ok_or("❌ Invalid encrypted request.")?;
+
+        let mut request = vec![0u8; ciphertext.len()];
+        let (transport, len, client_key) = {
+            let mut sessions = self.sessions.lock().unwrap();
+            let Some((Session::Established { transport, last_nonce, client_key }, used)) = sessions.get_mut(&src) else {
+                return Err("❌ Unknown or expired encrypted session, start a new handshake.".to_string());
+            };
+            if used.elapsed() >= SESSION_TIMEOUT {
+                sessions.remove(&src);
+                return Err("❌ Unknown or expired encrypted session, start a new handshake.".to_string());
+            }
+
+            // The nonce is checked and updated while the sessions are locked, so a request can't be replayed even
+            // while it is still being processed
+            if nonce <= *last_nonce {
+                return Err("❌ Replayed encrypted request.".to_string());
+            }
+            let len = transport.read_message(nonce, ciphertext, &mut request).map_err(|_| "❌ Invalid encrypted request.")?;
+            *last_nonce = nonce;
+            *used = Instant::now();
+            (transport.clone(), len, client_key.clone())
+        };
+
+        respond(&request[..len], &client_key).map(|response| encrypt(&transport, nonce, &response)).transpose()
+    }
+
+    fn take_session(&self, src: SocketAddr) -> Option<(Session, Instant)> {
+        self.sessions.lock().unwrap().remove(&src)
+    }
+}
+
+/// Encrypts a response as a transport packet
+fn encrypt(transport: &StatelessTransportState, nonce: u64, response: &[u8]) -> Result<Vec<u8>, String> {
+    let mut ciphertext = vec![0u8; MAX_MESSAGE_SIZE + MAX_OVERHEAD];
+    let len = transport
+        .write_message(nonce, response, &mut ciphertext)
+        .map_err(|e| format!("❌ Failed to encrypt the response: {}", e))?;
+    Ok(noise::encode_transport(nonce, &ciphertext[..len]))
+}
+
+fn generate_keypair() -> Keypair {
+    noise::builder().generate_keypair().expect("Failed to generate the server key.")
+}
+
+/// Loads the static key pair of the node from the given file, generating and saving a new one if the file doesn't
+/// exist. The file holds the private key followed by the public key.
+fn load_or_generate_keypair(path: &Path, vault: Option<&Vault>) -> Result<Keypair, String> {
+    match std::fs::read(path).map(|bytes| Vault::open_with(vault, &bytes)) {
+        Ok(Ok(bytes)) if bytes.len() == 64 => Ok(Keypair {
+            private: bytes[..32].to_vec(),
+            public: bytes[32..].to_vec(),
+        }),
+        Ok(_) => Err(format!("Invalid server key file {}, delete it to generate a new key.", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let keypair = generate_keypair();
+            write_key_file(path, &Vault::seal_with(vault, [keypair.private.as_slice(), keypair.public.as_slice()].concat()))
+                .map_err(|e| format!("Failed to save the server key to {}: {}", path.display(), e))?;
+            Ok(keypair)
+        }
+        Err(e) => Err(format!("Failed to read the server key from {}: {}", path.display(), e)),
+    }
+}
+
+/// Writes a key file, readable only by the owner on Unix
+pub(super) fn write_key_file(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(bytes)
+}
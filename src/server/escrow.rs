@@ -0,0 +1,67 @@
+//! Escrow transfers. `escrow` moves funds from the sender to the escrow account, where they stay locked until they are
+//! settled: the recipient can claim them with `claim-escrow` once the release timelock is open and until the expiry
+//! timelock opens, and from then on the sender can get them back with `refund-escrow`. The timelocks are checked
+//! against the block a settlement is minted in, so a claim that is still pending when the escrow expires is dropped.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::common::{Amount, Timelock};
+
+/// Account holding the escrowed funds. Funds only get into it by escrow transfers and only leave it by settlements.
+pub const ESCROW_ACCOUNT: &str = "@escrow";
+
+/// Funds locked in the escrow account
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Escrow {
+    pub sender: String,
+    pub recipient: String,
+    pub amount: Amount,
+    // When the recipient can claim the funds
+    pub release: Timelock,
+    // When the recipient can't claim the funds anymore and the sender can get them refunded
+    pub expiry: Timelock,
+}
+
+/// The escrows that weren't settled yet, by the ID of the transaction that funded them
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Escrows {
+    open: HashMap<String, Escrow>,
+}
+
+impl Escrows {
+    pub fn get(&self, escrow_id: &str) -> Option<&Escrow> {
+        self.open.get(escrow_id)
+    }
+
+    pub fn open(&mut self, escrow_id: String, escrow: Escrow) {
+        self.open.insert(escrow_id, escrow);
+    }
+
+    pub fn settle(&mut self, escrow_id: &str) {
+        self.open.remove(escrow_id);
+    }
+
+    /// Returns the number of open escrows an account sent or is the recipient of
+    pub fn involving(&self, account_id: &str) -> usize {
+        self.open.values().filter(|escrow| escrow.sender == account_id || escrow.recipient == account_id).count()
+    }
+
+    /// Checks that an escrow can be claimed, or refunded if `refund` is set, in a block at the given height with the
+    /// given timestamp
+    ///
+    /// Returns: the escrow, or why it can't be settled, to follow the escrow ID
+    pub fn check_settlement(&self, escrow_id: &str, refund: bool, height: u64, timestamp: u64) -> Result<&Escrow, String> {
+        let escrow = self.get(escrow_id).ok_or_else(|| "doesn't exist or was settled already".to_string())?;
+        let expired = escrow.expiry.is_open(height, timestamp);
+        if refund && !expired {
+            return Err(format!("expires at {}, it can't be refunded before", escrow.expiry));
+        }
+        if !refund && expired {
+            return Err(format!("expired at {}, it can only be refunded", escrow.expiry));
+        }
+        if !refund && !escrow.release.is_open(height, timestamp) {
+            return Err(format!("is released at {}, it can't be claimed before", escrow.release));
+        }
+        Ok(escrow)
+    }
+}
@@ -0,0 +1,48 @@
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Mutex;
+use serde::Serialize;
+use crate::common::Amount;
+use super::Transaction;
+
+/// An event on the chain that subscribers are notified of
+#[derive(Serialize, Clone)]
+#[serde(tag = "topic", rename_all = "snake_case")]
+pub enum Event {
+    // A block was minted and added to the ledger
+    NewBlock {
+        height: usize,
+        hash: String,
+        transactions: usize,
+    },
+    // A transaction was accepted into the mempool
+    NewTransaction {
+        id: String,
+        transaction: Box<Transaction>,
+    },
+    // A minted block changed the balance of an account
+    AccountChanged {
+        account_id: String,
+        balance: Amount,
+        height: usize,
+    },
+}
+
+/// Distributes events to all subscribers. Every subscriber gets its own channel, subscribers that went away are
+/// dropped the next time an event is published.
+#[derive(Default)]
+pub struct EventHub {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl EventHub {
+    /// Returns a receiver for all events published from now on
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn publish(&self, event: Event) {
+        self.subscribers.lock().unwrap().retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
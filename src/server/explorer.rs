@@ -0,0 +1,282 @@
+use std::sync::Arc;
+use serde_json::{json, Value};
+use super::allowlist::Unauthorized;
+use super::http::{self, HttpRequest, HttpResponse};
+use super::query::TransactionFilter;
+use super::{Block, State, Transaction};
+
+/// Number of blocks listed on the explorer's front page
+const RECENT_BLOCKS: usize = 20;
+/// Number of transactions listed on an account page
+const ACCOUNT_TRANSACTIONS: usize = 50;
+
+/// Starts the block explorer on the given address. Pages are served as HTML, and the same data is served as JSON when
+/// the path is prefixed with `/api`:
+///
+/// * `/`: chain info and the most recent blocks
+/// * `/blocks/<HEIGHT|HASH>`: a block and its transactions
+/// * `/tx/<ID>`: a transaction and its inclusion status
+/// * `/accounts/<ID>`: the balance and most recent transactions of an account
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    tracing::info!("Block explorer started on http://{}.", addr);
+    http::serve(addr, move |request| handle(&state, request));
+}
+
+/// A page of the explorer, rendered either as JSON or HTML
+struct Page {
+    title: String,
+    json: Value,
+    html: String,
+}
+
+fn handle(state: &State, request: HttpRequest) -> HttpResponse {
+    if request.method != "GET" {
+        return HttpResponse::text(405, "The explorer only supports GET requests.");
+    }
+
+    let (as_json, path) = match request.path.strip_prefix("/api") {
+        Some(path) => (true, path),
+        None => (false, request.path.as_str()),
+    };
+
+    // Browsers can't authenticate with a key, so no page is served in permissioned mode
+    if state.allowlist.is_permissioned() {
+        state.metrics.observe_unauthorized("explorer");
+        let error = "Unauthorized, the node is in permissioned mode and only accepts requests of allowed keys.";
+        return match as_json {
+            true => HttpResponse::json(403, &json!({ "error": error, "reason": Unauthorized::Unauthenticated.code() })),
+            false => html_response(403, "Unauthorized", &format!("<p>{}</p>", error)),
+        };
+    }
+
+    let segments: Vec<String> = path.split('/').filter(|segment| !segment.is_empty()).map(percent_decode).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+    let page = match segments.as_slice() {
+        [] => Some(index_page(state)),
+        ["blocks", id] => block_page(state, id),
+        ["tx", id] => transaction_page(state, id),
+        ["accounts", id] => Some(account_page(state, id)),
+        _ => None,
+    };
+
+    match (page, as_json) {
+        (Some(page), true) => HttpResponse::json(200, &page.json),
+        (Some(page), false) => html_response(200, &page.title, &page.html),
+        (None, true) => HttpResponse::json(404, &json!({ "error": "Not found." })),
+        (None, false) => html_response(404, "Not found", "<p>Nothing was found at this address.</p>"),
+    }
+}
+
+fn index_page(state: &State) -> Page {
+    let ledger = state.ledger.read().unwrap();
+    let pending_transactions = state.mempool.lock().unwrap().len();
+    let recent_blocks: Vec<(usize, &Block)> = ledger.iter().enumerate().rev().take(RECENT_BLOCKS).collect();
+
+    let mut html = format!(
+        "<p>Chain <b>{}</b> with {} blocks and {} pending transactions.</p>\n",
+        escape_html(&state.genesis.chain_id),
+        ledger.len(),
+        pending_transactions
+    );
+    html.push_str("<h2>Recent blocks</h2>\n<table>\n<tr><th>Height</th><th>Hash</th><th>Transactions</th></tr>\n");
+    for (height, block) in &recent_blocks {
+        html.push_str(&format!(
+            "<tr><td>{}</td><td><a href=\"/blocks/{}\">{}</a></td><td>{}</td></tr>\n",
+            height,
+            block.hash,
+            block.hash,
+            block.transactions.len()
+        ));
+    }
+    html.push_str("</table>\n");
+
+    Page {
+        title: "ToyChain explorer".to_string(),
+        json: json!({
+            "chain_id": state.genesis.chain_id,
+            "blocks": ledger.len(),
+            "pending_transactions": pending_transactions,
+            "recent_blocks": recent_blocks
+                .iter()
+                .map(|(height, block)| json!({ "height": height, "hash": block.hash, "transactions": block.transactions.len() }))
+                .collect::<Vec<Value>>(),
+        }),
+        html,
+    }
+}
+
+fn block_page(state: &State, id: &str) -> Option<Page> {
+    let ledger = state.ledger.read().unwrap();
+    let (height, block) = match id.parse::<usize>() {
+        Ok(height) => (height, ledger.get(height)?),
+        Err(_) => ledger.iter().enumerate().find(|(_, block)| block.hash == id)?,
+    };
+
+    let mut html = format!(
+        "<table>\n<tr><th>Height</th><td>{}</td></tr>\n<tr><th>Hash</th><td>{}</td></tr>\n<tr><th>Previous hash</th><td>{}</td></tr>\n<tr><th>Merkle root</th><td>{}</td></tr>\n<tr><th>Minted at</th><td>{} (unix time)</td></tr>\n</table>\n",
+        height,
+        block.hash,
+        previous_block_link(height, &block.header.previous_hash),
+        block.header.merkle_root,
+        block.header.timestamp
+    );
+    html.push_str("<h2>Transactions</h2>\n");
+    html.push_str(&transactions_table(block.transactions.iter()));
+
+    let mut json = serde_json::to_value(block).unwrap();
+    json["height"] = json!(height);
+
+    Some(Page {
+        title: format!("Block {}", height),
+        json,
+        html,
+    })
+}
+
+fn transaction_page(state: &State, id: &str) -> Option<Page> {
+    let (transaction, block) = state.find_transaction(id)?;
+
+    let status_html = match &block {
+        Some((height, hash)) => format!("Minted in block <a href=\"/blocks/{}\">{}</a>", hash, height),
+        None => "Pending, not minted yet".to_string(),
+    };
+    let html = format!(
+        "<table>\n<tr><th>ID</th><td>{}</td></tr>\n<tr><th>Status</th><td>{}</td></tr>\n<tr><th>Description</th><td>{}</td></tr>\n</table>\n",
+        escape_html(id),
+        status_html,
+        escape_html(&transaction.to_string())
+    );
+
+    let mut json = json!({ "id": id, "transaction": transaction, "status": "pending" });
+    if let Some((height, hash)) = block {
+        json["status"] = json!("minted");
+        json["block_height"] = json!(height);
+        json["block_hash"] = json!(hash);
+    }
+
+    Some(Page {
+        title: "Transaction".to_string(),
+        json,
+        html,
+    })
+}
+
+fn account_page(state: &State, id: &str) -> Page {
+    let balance = state.get_balance(id);
+    let (history, total) = state.get_history(&TransactionFilter::of_account(id), 1, ACCOUNT_TRANSACTIONS);
+
+    let mut html = format!("<p>Balance: <b>{}</b></p>\n<h2>Most recent transactions ({} in total)</h2>\n", balance, total);
+    html.push_str(&transactions_table(history.iter().map(|(_, _, transaction)| transaction)));
+
+    Page {
+        title: format!("Account {}", escape_html(id)),
+        json: json!({
+            "account_id": id,
+            "balance": balance,
+            "transaction_count": total,
+            "recent_transactions": history
+                .iter()
+                .map(|(height, hash, transaction)| json!({
+                    "id": transaction.id(),
+                    "block_height": height,
+                    "block_hash": hash,
+                    "transaction": transaction,
+                }))
+                .collect::<Vec<Value>>(),
+        }),
+        html,
+    }
+}
+
+/// Links to the previous block, the genesis block has no previous block
+fn previous_block_link(height: usize, previous_hash: &str) -> String {
+    if height == 0 {
+        return previous_hash.to_string();
+    }
+    format!("<a href=\"/blocks/{}\">{}</a>", previous_hash, previous_hash)
+}
+
+fn transactions_table<'a>(transactions: impl Iterator<Item = &'a Transaction>) -> String {
+    let mut html = "<table>\n<tr><th>ID</th><th>From</th><th>To</th><th>Amount</th><th>Fee</th></tr>\n".to_string();
+    for transaction in transactions {
+        let from = match &transaction.from_account_id {
+            Some(from_account_id) => account_link(from_account_id),
+            None if transaction.is_reward() => "block reward".to_string(),
+            None if transaction.is_staking_reward() => "staking reward".to_string(),
+            None if transaction.slashes().is_some() => "slashing".to_string(),
+            None if transaction.freezes().is_some() => "admin".to_string(),
+            None => "-".to_string(),
+        };
+        let id = transaction.id();
+        html.push_str(&format!(
+            "<tr><td><a href=\"/tx/{}\">{}</a></td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            id,
+            &id[..16],
+            from,
+            account_link(&transaction.to_account_id),
+            transaction.amount,
+            transaction.fee
+        ));
+    }
+    html.push_str("</table>\n");
+    html
+}
+
+fn account_link(account_id: &str) -> String {
+    format!("<a href=\"/accounts/{}\">{}</a>", percent_encode(account_id), escape_html(account_id))
+}
+
+fn html_response(status: u16, title: &str, body: &str) -> HttpResponse {
+    let html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n<style>body {{ font-family: sans-serif; margin: 2em; }} table {{ border-collapse: collapse; }} td, th {{ border: 1px solid #ccc; padding: 4px 8px; text-align: left; font-family: monospace; }}</style>\n</head>\n<body>\n<p><a href=\"/\">ToyChain explorer</a></p>\n<h1>{}</h1>\n{}</body>\n</html>\n",
+        title, title, body
+    );
+    HttpResponse {
+        status,
+        content_type: "text/html; charset=utf-8",
+        body: html.into_bytes(),
+        stream: None,
+    }
+}
+
+/// Escapes characters with a special meaning in HTML, account IDs and descriptions come from clients
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Encodes an account ID for use as a path segment
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Decodes a percent-encoded path segment, invalid escapes are kept as they are
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,46 @@
+//! The faucet of test networks. A node started with `--enable-faucet` pays up to the faucet amount to any existing
+//! account that asks with `toychain faucet`, at most once per cooldown for every account, so developers can fund their
+//! test accounts. Faucet payments issue new funds, like the starting balances of accounts, so every node of the chain
+//! accepts them in blocks and relayed mempools, whether it runs a faucet itself or not.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use crate::common::Amount;
+
+/// The faucet of a node and when it last paid every account
+pub struct Faucet {
+    // The most the faucet pays at once
+    amount: Amount,
+    // Time an account has to wait between two payments
+    cooldown: Duration,
+    // When the faucet last paid every account, by the clock of the node
+    paid: HashMap<String, SystemTime>,
+}
+
+impl Faucet {
+    pub fn new(amount: Amount, cooldown: Duration) -> Faucet {
+        Faucet { amount, cooldown, paid: HashMap::new() }
+    }
+
+    /// Checks that the faucet can pay an account at the given time
+    ///
+    /// Returns: the amount to pay, the faucet amount if none was asked for, or why the faucet doesn't pay it
+    pub fn check(&self, account_id: &str, amount: Option<Amount>, now: SystemTime) -> Result<Amount, String> {
+        let amount = amount.unwrap_or(self.amount);
+        if amount <= Amount::ZERO || amount > self.amount {
+            return Err(format!("pays between 0 and {}, not {}", self.amount, amount));
+        }
+        let waited = self.paid.get(account_id).map(|paid| now.duration_since(*paid).unwrap_or_default());
+        match waited {
+            Some(waited) if waited < self.cooldown => Err(format!("paid account {} {}s ago, ask again in {}s", account_id, waited.as_secs(), (self.cooldown - waited).as_secs().max(1))),
+            _ => Ok(amount),
+        }
+    }
+
+    /// Records a payment to an account, starting its cooldown, and forgets the accounts whose cooldown is over
+    pub fn record(&mut self, account_id: &str, now: SystemTime) {
+        let cooldown = self.cooldown;
+        self.paid.retain(|_, paid| now.duration_since(*paid).unwrap_or_default() < cooldown);
+        self.paid.insert(account_id.to_string(), now);
+    }
+}
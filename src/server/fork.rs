@@ -0,0 +1,69 @@
+//! Blocks received from other nodes that aren't on the main chain. Two nodes can mint competing blocks at the same
+//! height, so a received block may build on a block before the tip. It is kept here as part of a fork, and the node
+//! switches to the fork once it is longer than the main chain, see `State::receive_block`.
+
+use std::collections::{HashMap, VecDeque};
+use super::Block;
+
+/// Number of fork blocks kept at most, the oldest are forgotten first
+const MAX_FORK_BLOCKS: usize = 1000;
+
+/// Blocks of forks by hash
+#[derive(Default)]
+pub struct Forks {
+    blocks: HashMap<String, Block>,
+    // Hashes of the blocks in the order they were added, oldest first
+    order: VecDeque<String>,
+}
+
+impl Forks {
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// Adds a block, forgetting the oldest blocks while there are too many
+    pub fn add(&mut self, block: Block) {
+        if self.contains(&block.hash) {
+            return;
+        }
+        while self.blocks.len() >= MAX_FORK_BLOCKS {
+            let Some(oldest) = self.order.pop_front() else { break };
+            self.blocks.remove(&oldest);
+        }
+        self.order.push_back(block.hash.clone());
+        self.blocks.insert(block.hash.clone(), block);
+    }
+
+    /// Removes a block, e.g. once it is on the main chain
+    ///
+    /// Returns: the block, None if it isn't a fork block
+    pub fn remove(&mut self, hash: &str) -> Option<Block> {
+        let block = self.blocks.remove(hash)?;
+        self.order.retain(|known| known != hash);
+        Some(block)
+    }
+
+    /// Walks back from the block with the given hash through its ancestors among the fork blocks
+    ///
+    /// Returns: the blocks of the branch ending in the block, oldest first, and the hash of the block the branch builds
+    /// on, which isn't a fork block
+    pub fn branch(&self, hash: &str) -> (Vec<&Block>, String) {
+        let mut branch = Vec::new();
+        let mut hash = hash;
+        while let Some(block) = self.blocks.get(hash) {
+            branch.push(block);
+            hash = &block.header.previous_hash;
+        }
+        branch.reverse();
+        (branch, hash.to_string())
+    }
+
+    /// Returns the fork blocks at the given height
+    pub fn at_height(&self, height: u64) -> impl Iterator<Item = &Block> {
+        self.blocks.values().filter(move |block| block.header.height == height)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use crate::common::Amount;
+use crate::merkle::sha256_hex;
+use super::{Block, Transaction, TransactionKind};
+
+/// Node ID the transactions of the genesis block are attributed to
+const GENESIS_NODE_ID: &str = "genesis";
+
+/// An account that exists from the start of the chain
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GenesisAccount {
+    pub account_id: String,
+    pub balance: Amount,
+}
+
+/// Parameters all nodes of a chain have to agree on. The monetary policy is only hashed into the genesis block if it is
+/// set, so chains started before it existed keep their genesis block hash.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainParameters {
+    // Maximum number of transactions in a single block
+    pub max_block_transactions: usize,
+    // Coins newly issued to the minter of every block on top of its fees, see monetary::block_subsidy
+    #[serde(skip_serializing_if = "is_zero")]
+    pub block_reward: Amount,
+    // Number of blocks after which the block reward halves, it never does if not set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub halving_interval: Option<u64>,
+    // Most funds that can exist on the chain, block rewards stop once the supply reaches it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_supply: Option<Amount>,
+}
+
+impl Default for ChainParameters {
+    fn default() -> Self {
+        ChainParameters {
+            max_block_transactions: 1000,
+            block_reward: Amount::ZERO,
+            halving_interval: None,
+            max_supply: None,
+        }
+    }
+}
+
+fn is_zero(amount: &Amount) -> bool {
+    *amount == Amount::ZERO
+}
+
+/// Genesis configuration (`genesis.json`) that is deterministically converted into block 0. Nodes started from the
+/// same genesis configuration have the same genesis block hash.
+///
+/// Example:
+///
+/// ```json
+/// {
+///   "chain_id": "toychain-dev",
+///   "timestamp": 1700000000,
+///   "accounts": [{ "account_id": "Alice", "balance": 100 }],
+///   "parameters": {
+///     "max_block_transactions": 1000,
+///     "block_reward": 50,
+///     "halving_interval": 100000,
+///     "max_supply": 10000000
+///   }
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    pub chain_id: String,
+    // Unix time of the genesis block, used as the timestamp of its transactions
+    #[serde(default)]
+    pub timestamp: u64,
+    #[serde(default)]
+    pub accounts: Vec<GenesisAccount>,
+    #[serde(default)]
+    pub parameters: ChainParameters,
+}
+
+impl Default for Genesis {
+    fn default() -> Self {
+        Genesis {
+            chain_id: "toychain".to_string(),
+            timestamp: 0,
+            accounts: Vec::new(),
+            parameters: ChainParameters::default(),
+        }
+    }
+}
+
+impl Genesis {
+    /// Reads, parses and validates the genesis configuration at the given path
+    pub fn load(path: &str) -> Result<Genesis, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read genesis file {}: {}", path, e))?;
+        let genesis: Genesis = serde_json::from_str(&contents).map_err(|e| format!("Failed to parse genesis file {}: {}", path, e))?;
+        genesis.validate().map_err(|e| format!("Invalid genesis file {}: {}", path, e))?;
+        Ok(genesis)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.chain_id.is_empty() {
+            return Err("chain_id can't be empty".to_string());
+        }
+        if self.parameters.max_block_transactions == 0 {
+            return Err("max_block_transactions must be at least 1".to_string());
+        }
+        if self.parameters.halving_interval == Some(0) {
+            return Err("halving_interval must be at least 1".to_string());
+        }
+
+        let mut account_ids = HashSet::new();
+        for account in &self.accounts {
+            if !account_ids.insert(&account.account_id) {
+                return Err(format!("account {} is listed more than once", account.account_id));
+            }
+            if account.balance.is_negative() {
+                return Err(format!("account {} has an invalid balance {}", account.account_id, account.balance));
+            }
+        }
+        let supply = Amount::checked_sum(self.accounts.iter().map(|account| account.balance)).ok_or("the balances of the accounts add up to more than an amount can hold")?;
+        if let Some(max_supply) = self.parameters.max_supply.filter(|max_supply| supply > *max_supply) {
+            return Err(format!("the accounts hold {}, more than the max_supply of {}", supply, max_supply));
+        }
+        Ok(())
+    }
+
+    /// Converts the genesis configuration into block 0, which creates the genesis accounts in the listed order. The
+    /// block only depends on the configuration, so every node derives the same block. As there is no previous block,
+    /// the hash of the configuration is used as the previous hash, which makes the genesis block hash differ for
+    /// anything in the configuration that differs (e.g. the chain ID or parameters). The genesis block keeps the hash
+    /// it had before block headers had a height and timestamp.
+    pub fn to_block(&self) -> Block {
+        let datetime = std::time::UNIX_EPOCH + std::time::Duration::from_secs(self.timestamp);
+        let transactions: Vec<Transaction> = self
+            .accounts
+            .iter()
+            .map(|account| Transaction {
+                node_id: GENESIS_NODE_ID.to_string(),
+                from_account_id: None,
+                to_account_id: account.account_id.clone(),
+                amount: account.balance,
+                fee: Amount::ZERO,
+                nonce: None,
+                datetime,
+                memo: None,
+                kind: TransactionKind::Transfer,
+            })
+            .collect();
+
+        let timestamp = Block::legacy_timestamp(&transactions);
+        let block = Block::new(sha256_hex(&bincode::serialize(self).unwrap()), 0, timestamp, transactions);
+        Block {
+            hash: block.header.legacy_hash(),
+            ..block
+        }
+    }
+}
@@ -0,0 +1,184 @@
+//! Gossip of the mempool. Every gossip interval a node relays the transactions it accepted into its mempool since the
+//! last round to its connected peers, which validate them against their chain and mempool like the transactions of
+//! rolled back blocks, and relay the ones they accept in turn, so all nodes mint from the same transactions whichever
+//! node they were sent to. Every node remembers the IDs of the transactions it has seen, so a transaction is relayed
+//! at most once by every node and relays don't loop.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::client::Client;
+use crate::common::Request;
+use tracing::debug;
+use super::events::Event;
+use super::{verify, State, Transaction, TransactionKind};
+
+/// Interval at which the node relays the transactions it accepted
+pub const GOSSIP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Number of transaction IDs remembered at most, the oldest are forgotten first
+const MAX_SEEN: usize = 10000;
+
+/// Size of the encoded transactions in a relay at most, so the request fits into a message
+const MAX_RELAY_SIZE: usize = 48 * 1024;
+
+/// The transactions seen and the ones waiting to be relayed
+#[derive(Default)]
+pub struct Gossip {
+    seen: HashSet<String>,
+    // IDs of the seen transactions in the order they were seen, oldest first
+    order: VecDeque<String>,
+    outbox: Vec<Transaction>,
+}
+
+impl Gossip {
+    /// Remembers a transaction as seen, forgetting the oldest while too many are remembered
+    ///
+    /// Returns: whether the transaction wasn't seen before
+    fn see(&mut self, transaction_id: String) -> bool {
+        if self.seen.contains(&transaction_id) {
+            return false;
+        }
+        while self.order.len() >= MAX_SEEN {
+            let Some(oldest) = self.order.pop_front() else { break };
+            self.seen.remove(&oldest);
+        }
+        self.order.push_back(transaction_id.clone());
+        self.seen.insert(transaction_id);
+        true
+    }
+
+    /// Queues transactions accepted into the mempool to be relayed to the peers in the next round
+    pub fn relay(&mut self, transactions: &[Transaction]) {
+        for transaction in transactions {
+            self.see(transaction.id());
+            self.outbox.push(transaction.clone());
+        }
+    }
+}
+
+/// Splits transactions into relays that fit into a message
+fn relays(transactions: &[Transaction]) -> Vec<Vec<u8>> {
+    let mut relays = Vec::new();
+    let mut relay: Vec<&Transaction> = Vec::new();
+    let mut size = 0;
+    for transaction in transactions {
+        let length = bincode::serialized_size(transaction).unwrap() as usize;
+        if !relay.is_empty() && size + length > MAX_RELAY_SIZE {
+            relays.push(bincode::serialize(&relay).unwrap());
+            (relay, size) = (Vec::new(), 0);
+        }
+        relay.push(transaction);
+        size += length;
+    }
+    if !relay.is_empty() {
+        relays.push(bincode::serialize(&relay).unwrap());
+    }
+    relays
+}
+
+/// Relays the queued transactions to every connected peer
+fn relay_round(state: &State) {
+    let transactions = std::mem::take(&mut state.gossip.lock().unwrap().outbox);
+    let peers = state.peers.lock().unwrap().shared("");
+    if transactions.is_empty() || peers.is_empty() {
+        return;
+    }
+    for relay in relays(&transactions) {
+        let request = Request::new_relay_transactions_request(&state.identity, relay);
+        for peer in &peers {
+            let mut client = Client::new(peer.clone(), Duration::from_secs(1), 1).quiet();
+            match client.send(&request).and_then(|response| response.result) {
+                Ok(message) => debug!(peer, "Relayed transactions: {}", message),
+                Err(e) => debug!(peer, "Relaying transactions failed: {}", e),
+            }
+        }
+    }
+}
+
+/// Relays the transactions the node accepts to its peers every gossip interval, until the node shuts down
+pub async fn gossip_transactions(state: Arc<State>) {
+    loop {
+        tokio::time::sleep(GOSSIP_INTERVAL).await;
+        let shared_state = state.clone();
+        tokio::task::spawn_blocking(move || relay_round(&shared_state)).await.unwrap();
+    }
+}
+
+/// Adds the transactions relayed by a peer that weren't seen before to the mempool, if they are valid on top of the
+/// chain and the mempool and fit into the mempool, and queues them to be relayed further
+///
+/// Returns: a user-facing message saying how many of the transactions were accepted
+pub fn receive_transactions(state: &State, encoded: &[u8]) -> Result<String, String> {
+    let transactions: Vec<Transaction> = bincode::deserialize(encoded).map_err(|e| format!("❌ Invalid transactions: {}.", e))?;
+    let total = transactions.len();
+    let unseen: Vec<Transaction> = {
+        let mut gossip = state.gossip.lock().unwrap();
+        transactions.into_iter().filter(|transaction| gossip.see(transaction.id())).collect()
+    };
+    if unseen.is_empty() {
+        return Ok(format!("⚠️ All {} transactions were seen already.", total));
+    }
+    let min_fee = state.min_fee();
+
+    // Locked in the same order as transfers and received blocks
+    let mut nonces = state.nonces.lock().unwrap();
+    let mut mempool = state.mempool.lock().unwrap();
+    if state.shutting_down.load(Ordering::SeqCst) {
+        return Err("❌ The node is shutting down and doesn't accept new transactions.".to_string());
+    }
+    let ledger = state.ledger.read().unwrap();
+    let snapshots = state.snapshots.lock().unwrap();
+    let mut accounts = verify::verify_chain_with(&ledger, &state.genesis, snapshots.pruned(), |_, _, _| Ok(())).map_err(|e| format!("❌ The chain of this node isn't valid. {}", e))?;
+    let height = ledger.len() as u64;
+    let now = state.clock.unix_time();
+    for pending in mempool.transactions() {
+        if let Err(reason) = accounts.apply_pending(pending, height, now) {
+            debug!(transaction = %pending.id(), "Pending transaction doesn't apply, it {}.", reason);
+        }
+    }
+
+    let mut accepted = Vec::new();
+    for transaction in unseen {
+        let id = transaction.id();
+        // Block rewards, staking rewards, scheduled payments and slashes are only made by the minter of a block
+        let minted_only = transaction.is_reward() || transaction.is_staking_reward() || transaction.pays_schedule().is_some() || transaction.slashes().is_some();
+        // And the settlements and credits of cross-shard transfers by the node of a shard, which asked the other shard
+        let cross_shard = transaction.settles_cross_shard().is_some() || transaction.credits_cross_shard().is_some();
+        let low_fee = matches!(transaction.kind, TransactionKind::Transfer | TransactionKind::ExpiringTransfer { .. } | TransactionKind::CrossShardLock { .. })
+            && transaction.from_account_id.is_some()
+            && transaction.fee < min_fee;
+        let result = match (minted_only || cross_shard, low_fee) {
+            (true, _) if cross_shard => Err("is only made by the node of its shard".to_string()),
+            (true, _) => Err("is only made by minters".to_string()),
+            (_, true) => Err(format!("pays a fee below the minimum fee {} of this node", min_fee)),
+            // Added first, as the accounts can't be rolled back if the mempool rejects it
+            _ => mempool.add_all(vec![transaction.clone()]).map_err(|e| e.trim_start_matches("❌ ").to_string()).and_then(|()| {
+                accounts.apply_pending(&transaction, height, now).inspect_err(|_| {
+                    mempool.remove(&id);
+                })
+            }),
+        };
+        match result {
+            Ok(()) => accepted.push(transaction),
+            Err(reason) => debug!(transaction = %id, "Dropping relayed transaction, it {}.", reason),
+        }
+    }
+    if !accepted.is_empty() {
+        // The nonces, closed and frozen accounts and aliases include the pending transactions
+        *nonces = accounts.nonces;
+        *state.closed.lock().unwrap() = accounts.closed;
+        *state.frozen.lock().unwrap() = accounts.frozen;
+        *state.aliases.lock().unwrap() = accounts.aliases;
+        state.log_pending(&accepted);
+        state.wake_minter(&mempool);
+    }
+    drop((snapshots, ledger, mempool, nonces));
+
+    state.gossip.lock().unwrap().relay(&accepted);
+    for transaction in &accepted {
+        state.events.publish(Event::NewTransaction { id: transaction.id(), transaction: Box::new(transaction.clone()) });
+    }
+    Ok(format!("✅ {} of {} relayed transactions accepted.", accepted.len(), total))
+}
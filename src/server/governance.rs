@@ -0,0 +1,125 @@
+//! On-chain governance of the chain parameters. `propose` records a proposal to change the minimum fee, the maximum
+//! number of transactions in a block, the mint interval or the staking rewards from an activation height on, and
+//! accounts vote for or against it with `vote` until then. Right before the block at the activation height, i.e. after
+//! the block before it, the votes are weighted by the balance and stake each voting account has at that point, and the
+//! proposal passes if the weight for it is larger than the weight against it. Every node counts the same votes at the
+//! same height, so a change that passes takes effect at the activation height on all nodes.
+
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use crate::common::{Amount, ParameterChange};
+
+/// A proposal that wasn't decided yet
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Proposal {
+    pub proposer: String,
+    pub change: ParameterChange,
+    pub activation_height: u64,
+    // Whether each account that voted approves the proposal
+    pub votes: BTreeMap<String, bool>,
+}
+
+/// The chain parameters changed by governance, the ones not set have the value of the genesis configuration or the
+/// node's configuration
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Parameters {
+    pub min_fee: Option<Amount>,
+    pub max_block_transactions: Option<usize>,
+    pub mint_interval: Option<u64>,
+    pub staking_reward: Option<Amount>,
+    pub epoch_length: Option<u64>,
+}
+
+/// The open proposals, by the ID of the transaction that made them, and the parameters decided so far
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Governance {
+    proposals: BTreeMap<String, Proposal>,
+    parameters: Parameters,
+}
+
+impl Parameters {
+    fn apply(&mut self, change: ParameterChange) {
+        match change {
+            ParameterChange::MinFee(fee) => self.min_fee = Some(fee),
+            ParameterChange::MaxBlockTransactions(count) => self.max_block_transactions = Some(count),
+            ParameterChange::MintInterval(seconds) => self.mint_interval = Some(seconds),
+            ParameterChange::StakingReward(reward) => self.staking_reward = Some(reward),
+            ParameterChange::EpochLength(blocks) => self.epoch_length = Some(blocks),
+        }
+    }
+}
+
+/// Returns the weight of an account's votes, its balance plus its stake
+pub fn weight(balances: &HashMap<String, Amount>, stakes: &HashMap<String, Amount>, account_id: &str) -> Amount {
+    balances.get(account_id).copied().unwrap_or_default() + stakes.get(account_id).copied().unwrap_or_default()
+}
+
+impl Governance {
+    pub fn get(&self, proposal_id: &str) -> Option<&Proposal> {
+        self.proposals.get(proposal_id)
+    }
+
+    /// Returns the open proposals, sorted by ID
+    pub fn open(&self) -> impl Iterator<Item = (&String, &Proposal)> {
+        self.proposals.iter()
+    }
+
+    pub fn parameters(&self) -> &Parameters {
+        &self.parameters
+    }
+
+    pub fn propose(&mut self, proposal_id: String, proposal: Proposal) {
+        self.proposals.insert(proposal_id, proposal);
+    }
+
+    pub fn vote(&mut self, proposal_id: &str, account_id: &str, approve: bool) {
+        if let Some(proposal) = self.proposals.get_mut(proposal_id) {
+            proposal.votes.insert(account_id.to_string(), approve);
+        }
+    }
+
+    /// Checks that an account can vote on a proposal in a block at the given height
+    ///
+    /// Returns: why it can't vote, to follow the proposal ID
+    pub fn check_vote(&self, proposal_id: &str, account_id: &str, height: u64) -> Result<(), String> {
+        let proposal = self.get(proposal_id).ok_or_else(|| "doesn't exist or was decided already".to_string())?;
+        if height >= proposal.activation_height {
+            return Err(format!("takes effect at height {}, votes can only be minted before", proposal.activation_height));
+        }
+        if proposal.votes.contains_key(account_id) {
+            return Err(format!("was voted on by account {} already", account_id));
+        }
+        Ok(())
+    }
+
+    /// Returns the weight of the votes for and against a proposal
+    pub fn tally(proposal: &Proposal, weight: impl Fn(&str) -> Amount) -> (Amount, Amount) {
+        let (mut approve, mut reject) = (Amount::ZERO, Amount::ZERO);
+        for (account_id, approves) in &proposal.votes {
+            match approves {
+                true => approve += weight(account_id),
+                false => reject += weight(account_id),
+            }
+        }
+        (approve, reject)
+    }
+
+    /// Decides the proposals that take effect right after the block at the given height, in the order of their IDs,
+    /// and applies the changes that pass
+    ///
+    /// Returns: the decided proposals and whether they passed
+    pub fn decide(&mut self, height: u64, weight: impl Fn(&str) -> Amount) -> Vec<(String, Proposal, bool)> {
+        let due: Vec<String> = self.proposals.iter().filter(|(_, proposal)| proposal.activation_height <= height + 1).map(|(id, _)| id.clone()).collect();
+        let mut decided = Vec::new();
+        for proposal_id in due {
+            let proposal = self.proposals.remove(&proposal_id).unwrap();
+            let (approve, reject) = Governance::tally(&proposal, &weight);
+            let passed = approve > reject;
+            if passed {
+                self.parameters.apply(proposal.change);
+            }
+            decided.push((proposal_id, proposal, passed));
+        }
+        decided
+    }
+}
@@ -0,0 +1,670 @@
+//! The GraphQL endpoint of the node, so explorers can fetch nested data, e.g. a block with its transactions and the
+//! balances of their accounts, in a single query instead of a request per object. Queries are POSTed to `/graphql` as
+//! `{"query": ..., "variables": ..., "operationName": ...}` and answered with `{"data": ...}`, or with `{"data": null,
+//! "errors": [...]}` if the query can't be executed.
+//!
+//! The schema is a graph of the chain, with the fields listed in the README:
+//!
+//! * `Query`: `chain`, `block(height, hash)`, `blocks(from, to)`, `transaction(id)` and `account(id)`
+//! * `Block`: its header fields, its `transactions` and its `parent` block
+//! * `Transaction`: its fields, its `from` and `to` accounts and its `block`
+//! * `Account`: its balance and nonce and its `transactions`
+//!
+//! Only queries are parsed and executed here, with arguments given as literals or variables. Mutations, fragments and
+//! directives aren't supported, changes are made over the other APIs.
+
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::Arc;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use super::allowlist::Unauthorized;
+use super::http::{self, HttpRequest, HttpResponse};
+use super::query::TransactionFilter;
+use super::{Block, State, Transaction};
+
+/// Number of levels selections can be nested at most, so a query can't walk the graph without bound
+const MAX_DEPTH: usize = 10;
+/// Number of fields a query can resolve at most, counting every field of every object in the result
+const MAX_FIELDS: usize = 10_000;
+/// Number of blocks or transactions a list field returns at most
+const MAX_LIST: u64 = 100;
+/// Number of blocks or transactions a list field returns if no range or limit is given
+const DEFAULT_LIST: u64 = 10;
+
+/// A GraphQL request, as POSTed by clients
+#[derive(Deserialize)]
+struct GraphQlRequest {
+    query: String,
+    #[serde(default)]
+    variables: Option<Map<String, Value>>,
+    #[serde(default, rename = "operationName")]
+    operation_name: Option<String>,
+}
+
+/// Starts the GraphQL endpoint on the given address
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    tracing::info!("GraphQL endpoint started on http://{}/graphql.", addr);
+    http::serve(addr, move |request| handle(&state, request));
+}
+
+/// Handles a single HTTP request carrying a GraphQL query
+fn handle(state: &State, request: HttpRequest) -> HttpResponse {
+    if request.path != "/graphql" {
+        return HttpResponse::text(404, "Not found.");
+    }
+    if request.method != "POST" {
+        return HttpResponse::text(405, "GraphQL queries must be sent with POST.");
+    }
+
+    if let Err(retry_after) = state.rate_limiter.check(request.remote_addr.ip()) {
+        state.metrics.observe_rate_limited("graphql");
+        let extensions = json!({ "retry_after_ms": retry_after.as_millis().max(1) as u64 });
+        return HttpResponse::json(429, &json!({ "errors": [{ "message": "Rate limit exceeded.", "extensions": extensions }] }));
+    }
+
+    // GraphQL clients can't authenticate with a key, so none is allowed in permissioned mode
+    if state.allowlist.is_permissioned() {
+        state.metrics.observe_unauthorized("graphql");
+        let message = "Unauthorized, the node is in permissioned mode and only accepts requests of allowed keys.";
+        let extensions = json!({ "reason": Unauthorized::Unauthenticated.code() });
+        return HttpResponse::json(403, &json!({ "errors": [{ "message": message, "extensions": extensions }] }));
+    }
+
+    let graphql_request: GraphQlRequest = match serde_json::from_slice(&request.body) {
+        Ok(graphql_request) => graphql_request,
+        Err(e) => return HttpResponse::json(400, &json!({ "errors": [{ "message": format!("Invalid request: {}", e) }] })),
+    };
+
+    let _span = tracing::info_span!("graphql_request", from = %request.remote_addr.ip()).entered();
+    tracing::info!("Received GraphQL query.");
+
+    let started = std::time::Instant::now();
+    let worker = state.workers.acquire();
+    let result = execute(state, graphql_request);
+    drop(worker);
+    state.metrics.observe_request("graphql", "query", started.elapsed());
+
+    match result {
+        Ok(data) => HttpResponse::json(200, &json!({ "data": data })),
+        Err(message) => HttpResponse::json(200, &json!({ "data": null, "errors": [{ "message": message }] })),
+    }
+}
+
+/// Parses the query of a request and executes its operation
+///
+/// Returns: the data the query selected, or why it can't be executed
+fn execute(state: &State, request: GraphQlRequest) -> Result<Value, String> {
+    let operations = Parser { tokens: tokenize(&request.query)?, position: 0 }.document()?;
+    let operation = match &request.operation_name {
+        Some(name) => operations.iter().find(|operation| operation.name.as_ref() == Some(name)).ok_or(format!("Operation {} not found.", name))?,
+        None if operations.len() == 1 => &operations[0],
+        None => return Err("The query has several operations, give the one to execute as operationName.".to_string()),
+    };
+    if operation.kind != "query" {
+        return Err(format!("Only queries are supported, {} operations aren't. Make changes over the other APIs.", operation.kind));
+    }
+
+    // Every variable the operation defines is known, with null if neither a value nor a default is given
+    let mut given = request.variables.unwrap_or_default();
+    let mut variables = HashMap::new();
+    for definition in &operation.variables {
+        let value = given.remove(&definition.name).filter(|value| !value.is_null()).or_else(|| definition.default.clone()).unwrap_or(Value::Null);
+        if definition.required && value.is_null() {
+            return Err(format!("Variable ${} is required but not given.", definition.name));
+        }
+        variables.insert(definition.name.clone(), value);
+    }
+
+    let mut executor = Executor { state, variables, fields: 0 };
+    executor.object("Query", &operation.selections, |executor, field| executor.query(field))
+}
+
+/// A token of a GraphQL document
+#[derive(Debug, PartialEq)]
+enum Token {
+    Punctuator(char),
+    // The `...` of fragment spreads
+    Spread,
+    Name(String),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+/// Splits a GraphQL document into tokens, skipping whitespace, commas and comments
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() || c == ',' || c == '\u{feff}' => {
+                chars.next();
+            }
+            '#' => while chars.next_if(|&c| c != '\n' && c != '\r').is_some() {},
+            '{' | '}' | '(' | ')' | ':' | '!' | '$' | '[' | ']' | '=' | '@' | '|' | '&' => {
+                chars.next();
+                tokens.push(Token::Punctuator(c));
+            }
+            '.' => {
+                if !(0..3).all(|_| chars.next() == Some('.')) {
+                    return Err("Unexpected character '.'.".to_string());
+                }
+                tokens.push(Token::Spread);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::String(read_string(&mut chars)?));
+            }
+            c if c == '_' || c.is_ascii_alphabetic() => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|&c| c == '_' || c.is_ascii_alphanumeric()) {
+                    name.push(c);
+                }
+                tokens.push(Token::Name(name));
+            }
+            c if c == '-' || c.is_ascii_digit() => {
+                let mut number = String::new();
+                while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.')) {
+                    number.push(c);
+                }
+                let token = match number.parse::<i64>() {
+                    Ok(int) => Token::Int(int),
+                    Err(_) => Token::Float(number.parse::<f64>().map_err(|_| format!("Invalid number {}.", number))?),
+                };
+                tokens.push(token);
+            }
+            c => return Err(format!("Unexpected character {:?}.", c)),
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads a string after its opening quote up to and including its closing quote, decoding its escapes
+fn read_string(chars: &mut Peekable<Chars>) -> Result<String, String> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            None | Some('\n') | Some('\r') => return Err("Unterminated string.".to_string()),
+            Some('"') => return Ok(string),
+            Some('\\') => {
+                let escaped = match chars.next() {
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('/') => '/',
+                    Some('b') => '\u{8}',
+                    Some('f') => '\u{c}',
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                        u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32).ok_or(format!("Invalid unicode escape \\u{}.", hex))?
+                    }
+                    escape => return Err(format!("Invalid escape in string: \\{}.", escape.map(String::from).unwrap_or_default())),
+                };
+                string.push(escaped);
+            }
+            Some(c) => string.push(c),
+        }
+    }
+}
+
+/// An operation of a GraphQL document
+struct Operation {
+    // `query`, `mutation` or `subscription`
+    kind: String,
+    name: Option<String>,
+    variables: Vec<VariableDefinition>,
+    selections: Vec<Field>,
+}
+
+/// A variable an operation defines
+struct VariableDefinition {
+    name: String,
+    // Whether the type of the variable is non-null
+    required: bool,
+    default: Option<Value>,
+}
+
+/// A field selected by a query
+struct Field {
+    // The key of the field in the result if it isn't its name
+    alias: Option<String>,
+    name: String,
+    arguments: Vec<(String, Argument)>,
+    // The fields selected of the object the field resolves to, empty for a scalar field
+    selections: Vec<Field>,
+}
+
+impl Field {
+    /// The key of the field in the result
+    fn key(&self) -> &str {
+        self.alias.as_ref().unwrap_or(&self.name)
+    }
+}
+
+/// The value of an argument, given as a literal or a variable of the operation
+enum Argument {
+    Literal(Value),
+    Variable(String),
+}
+
+/// Parses the tokens of a GraphQL document
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<&Token, String> {
+        let token = self.tokens.get(self.position).ok_or("Unexpected end of the query.")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    /// Skips the punctuator if it is next
+    ///
+    /// Returns: whether it was next
+    fn eat(&mut self, punctuator: char) -> bool {
+        let next = self.peek() == Some(&Token::Punctuator(punctuator));
+        if next {
+            self.position += 1;
+        }
+        next
+    }
+
+    fn expect(&mut self, punctuator: char) -> Result<(), String> {
+        match self.next()? {
+            Token::Punctuator(c) if *c == punctuator => Ok(()),
+            token => Err(format!("Expected '{}' but found {:?}.", punctuator, token)),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Name(name) => Ok(name.clone()),
+            token => Err(format!("Expected a name but found {:?}.", token)),
+        }
+    }
+
+    /// Parses a document, which has to have at least one operation
+    fn document(&mut self) -> Result<Vec<Operation>, String> {
+        let mut operations = Vec::new();
+        while self.peek().is_some() {
+            operations.push(self.operation()?);
+        }
+        if operations.is_empty() {
+            return Err("The query has no operation.".to_string());
+        }
+        Ok(operations)
+    }
+
+    fn operation(&mut self) -> Result<Operation, String> {
+        // A selection set on its own is a query without a name or variables
+        if self.peek() == Some(&Token::Punctuator('{')) {
+            return Ok(Operation { kind: "query".to_string(), name: None, variables: Vec::new(), selections: self.selection_set(1)? });
+        }
+        let kind = self.name()?;
+        match kind.as_str() {
+            "query" | "mutation" | "subscription" => {}
+            "fragment" => return Err("Fragments aren't supported.".to_string()),
+            _ => return Err(format!("Expected an operation but found {}.", kind)),
+        }
+        let name = match self.peek() {
+            Some(Token::Name(_)) => Some(self.name()?),
+            _ => None,
+        };
+        let variables = if self.eat('(') { self.variable_definitions()? } else { Vec::new() };
+        Ok(Operation { kind, name, variables, selections: self.selection_set(1)? })
+    }
+
+    /// Parses the variable definitions of an operation after their opening parenthesis
+    fn variable_definitions(&mut self) -> Result<Vec<VariableDefinition>, String> {
+        let mut definitions = Vec::new();
+        while !self.eat(')') {
+            self.expect('$')?;
+            let name = self.name()?;
+            self.expect(':')?;
+            let required = self.type_reference()?;
+            let default = match self.eat('=') {
+                true => match self.value()? {
+                    Argument::Literal(value) => Some(value),
+                    Argument::Variable(_) => return Err(format!("The default of variable ${} can't be a variable.", name)),
+                },
+                false => None,
+            };
+            definitions.push(VariableDefinition { name, required, default });
+        }
+        Ok(definitions)
+    }
+
+    /// Parses the type of a variable, whose name isn't checked since the arguments are checked by the fields
+    ///
+    /// Returns: whether the type is non-null
+    fn type_reference(&mut self) -> Result<bool, String> {
+        if self.eat('[') {
+            self.type_reference()?;
+            self.expect(']')?;
+        } else {
+            self.name()?;
+        }
+        Ok(self.eat('!'))
+    }
+
+    /// Parses a selection set including its braces, nested at the given depth
+    fn selection_set(&mut self, depth: usize) -> Result<Vec<Field>, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("The query is nested deeper than {} levels.", MAX_DEPTH));
+        }
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        while !self.eat('}') {
+            if self.peek() == Some(&Token::Spread) {
+                return Err("Fragments aren't supported.".to_string());
+            }
+            fields.push(self.field(depth)?);
+        }
+        if fields.is_empty() {
+            return Err("A selection set has to select at least one field.".to_string());
+        }
+        Ok(fields)
+    }
+
+    fn field(&mut self, depth: usize) -> Result<Field, String> {
+        let mut name = self.name()?;
+        let mut alias = None;
+        if self.eat(':') {
+            alias = Some(name);
+            name = self.name()?;
+        }
+        let mut arguments = Vec::new();
+        if self.eat('(') {
+            while !self.eat(')') {
+                let argument = self.name()?;
+                self.expect(':')?;
+                arguments.push((argument, self.value()?));
+            }
+        }
+        if self.peek() == Some(&Token::Punctuator('@')) {
+            return Err("Directives aren't supported.".to_string());
+        }
+        let selections = if self.peek() == Some(&Token::Punctuator('{')) { self.selection_set(depth + 1)? } else { Vec::new() };
+        Ok(Field { alias, name, arguments, selections })
+    }
+
+    /// Parses the value of an argument. No argument takes a list or an object, so they aren't parsed.
+    fn value(&mut self) -> Result<Argument, String> {
+        let value = match self.next()? {
+            Token::Punctuator('$') => return Ok(Argument::Variable(self.name()?)),
+            Token::Int(int) => json!(int),
+            Token::Float(float) => json!(float),
+            Token::String(string) => json!(string),
+            Token::Name(name) => match name.as_str() {
+                "true" => json!(true),
+                "false" => json!(false),
+                "null" => Value::Null,
+                // Enum values
+                _ => json!(name),
+            },
+            Token::Punctuator('[') | Token::Punctuator('{') => return Err("List and object values aren't supported.".to_string()),
+            token => return Err(format!("Expected a value but found {:?}.", token)),
+        };
+        Ok(Argument::Literal(value))
+    }
+}
+
+/// Executes the selections of a query against the node's state
+struct Executor<'a> {
+    state: &'a State,
+    // The values of the variables the operation defines
+    variables: HashMap<String, Value>,
+    // Number of fields resolved so far
+    fields: usize,
+}
+
+impl Executor<'_> {
+    /// Resolves the selected fields of an object of the given type with the resolver of its fields
+    fn object(&mut self, type_name: &str, selections: &[Field], mut resolve: impl FnMut(&mut Self, &Field) -> Result<Value, String>) -> Result<Value, String> {
+        let mut object = Map::new();
+        for field in selections {
+            self.fields += 1;
+            if self.fields > MAX_FIELDS {
+                return Err(format!("The query resolves more than {} fields.", MAX_FIELDS));
+            }
+            let value = match field.name.as_str() {
+                "__typename" => leaf(field, json!(type_name))?,
+                _ => resolve(self, field)?,
+            };
+            object.insert(field.key().to_string(), value);
+        }
+        Ok(Value::Object(object))
+    }
+
+    /// Returns the value of an argument of a field, None if it isn't given or is null
+    fn argument(&self, field: &Field, name: &str) -> Result<Option<Value>, String> {
+        let value = match field.arguments.iter().find(|(argument, _)| argument == name) {
+            None => return Ok(None),
+            Some((_, Argument::Literal(value))) => value.clone(),
+            Some((_, Argument::Variable(variable))) => self.variables.get(variable).cloned().ok_or(format!("Variable ${} isn't defined by the operation.", variable))?,
+        };
+        Ok(Some(value).filter(|value| !value.is_null()))
+    }
+
+    fn int_argument(&self, field: &Field, name: &str) -> Result<Option<u64>, String> {
+        match self.argument(field, name)? {
+            None => Ok(None),
+            Some(value) => value.as_u64().map(Some).ok_or(format!("Argument {} of field {} has to be a non-negative integer, not {}.", name, field.name, value)),
+        }
+    }
+
+    fn string_argument(&self, field: &Field, name: &str) -> Result<Option<String>, String> {
+        match self.argument(field, name)? {
+            None => Ok(None),
+            Some(Value::String(string)) => Ok(Some(string)),
+            Some(value) => Err(format!("Argument {} of field {} has to be a string, not {}.", name, field.name, value)),
+        }
+    }
+
+    /// Resolves a field of the query root
+    fn query(&mut self, field: &Field) -> Result<Value, String> {
+        let state = self.state;
+        match field.name.as_str() {
+            "chain" => {
+                check_arguments(field, &[])?;
+                self.chain(subfields(field)?)
+            }
+            "block" => {
+                check_arguments(field, &["height", "hash"])?;
+                let height = self.int_argument(field, "height")?.map(|height| usize::try_from(height).unwrap_or(usize::MAX));
+                let hash = self.string_argument(field, "hash")?;
+                if height.is_none() && hash.is_none() {
+                    return Err("Field block needs a height or hash argument.".to_string());
+                }
+                match state.find_block(height, hash.as_deref()) {
+                    Some((height, block)) => self.block(height, &block, subfields(field)?),
+                    None => Ok(Value::Null),
+                }
+            }
+            "blocks" => {
+                check_arguments(field, &["from", "to"])?;
+                let tip = state.ledger.read().unwrap().len() as u64 - 1;
+                let to = self.int_argument(field, "to")?.unwrap_or(tip).min(tip);
+                let from = self.int_argument(field, "from")?.unwrap_or(to.saturating_sub(DEFAULT_LIST - 1));
+                if from <= to && to - from >= MAX_LIST {
+                    return Err(format!("Field blocks returns at most {} blocks, narrow its range.", MAX_LIST));
+                }
+                let selections = subfields(field)?;
+                let blocks: Vec<Block> = state.ledger.read().unwrap().iter().take(to as usize + 1).skip(from as usize).cloned().collect();
+                let blocks = blocks.iter().enumerate().map(|(offset, block)| self.block(from as usize + offset, block, selections));
+                blocks.collect::<Result<Vec<Value>, String>>().map(Value::Array)
+            }
+            "transaction" => {
+                check_arguments(field, &["id"])?;
+                let id = self.string_argument(field, "id")?.ok_or("Field transaction needs an id argument.")?;
+                match state.find_transaction(&id) {
+                    Some((transaction, block)) => self.transaction(&transaction, block.map(|(height, _)| height), subfields(field)?),
+                    None => Ok(Value::Null),
+                }
+            }
+            "account" => {
+                check_arguments(field, &["id"])?;
+                let id = self.string_argument(field, "id")?.ok_or("Field account needs an id argument.")?;
+                let (account_id, alias) = state.resolve_account(&id);
+                self.account(&account_id, alias, subfields(field)?)
+            }
+            _ => Err(unknown_field("Query", field)),
+        }
+    }
+
+    fn chain(&mut self, selections: &[Field]) -> Result<Value, String> {
+        let state = self.state;
+        let (height, tip_hash, genesis_hash) = {
+            let ledger = state.ledger.read().unwrap();
+            (ledger.len() - 1, ledger[ledger.len() - 1].hash.clone(), ledger[0].hash.clone())
+        };
+        let pending_transactions = state.mempool.lock().unwrap().len();
+        self.object("Chain", selections, |_, field| match field.name.as_str() {
+            "id" => leaf(field, json!(state.genesis.chain_id)),
+            "height" => leaf(field, json!(height)),
+            "tipHash" => leaf(field, json!(tip_hash)),
+            "genesisHash" => leaf(field, json!(genesis_hash)),
+            "pendingTransactions" => leaf(field, json!(pending_transactions)),
+            _ => Err(unknown_field("Chain", field)),
+        })
+    }
+
+    fn block(&mut self, height: usize, block: &Block, selections: &[Field]) -> Result<Value, String> {
+        let state = self.state;
+        self.object("Block", selections, |executor, field| match field.name.as_str() {
+            "height" => leaf(field, json!(height)),
+            "hash" => leaf(field, json!(block.hash)),
+            "previousHash" => leaf(field, json!(block.header.previous_hash)),
+            "merkleRoot" => leaf(field, json!(block.header.merkle_root)),
+            "timestamp" => leaf(field, json!(block.header.timestamp)),
+            "transactionCount" => leaf(field, json!(block.transactions.len())),
+            "transactions" => {
+                check_arguments(field, &[])?;
+                let selections = subfields(field)?;
+                let transactions = block.transactions.iter().map(|transaction| executor.transaction(transaction, Some(height), selections));
+                transactions.collect::<Result<Vec<Value>, String>>().map(Value::Array)
+            }
+            "parent" => {
+                check_arguments(field, &[])?;
+                let selections = subfields(field)?;
+                match height.checked_sub(1).and_then(|parent| state.find_block(Some(parent), None)) {
+                    Some((parent, block)) => executor.block(parent, &block, selections),
+                    None => Ok(Value::Null),
+                }
+            }
+            _ => Err(unknown_field("Block", field)),
+        })
+    }
+
+    /// Resolves a transaction, minted in the block at the given height or pending if there is none
+    fn transaction(&mut self, transaction: &Transaction, height: Option<usize>, selections: &[Field]) -> Result<Value, String> {
+        let state = self.state;
+        self.object("Transaction", selections, |executor, field| match field.name.as_str() {
+            "id" => leaf(field, json!(transaction.id())),
+            "kind" => leaf(field, json!(kind_name(transaction))),
+            "description" => leaf(field, json!(transaction.to_string())),
+            "amount" => leaf(field, json!(transaction.amount)),
+            "fee" => leaf(field, json!(transaction.fee)),
+            "nonce" => leaf(field, json!(transaction.nonce)),
+            "memo" => leaf(field, json!(transaction.memo)),
+            "timestamp" => leaf(field, json!(transaction.timestamp())),
+            "status" => leaf(field, json!(if height.is_some() { "minted" } else { "pending" })),
+            "from" => {
+                check_arguments(field, &[])?;
+                match &transaction.from_account_id {
+                    Some(from_account_id) => executor.account(from_account_id, None, subfields(field)?),
+                    None => Ok(Value::Null),
+                }
+            }
+            "to" => {
+                check_arguments(field, &[])?;
+                executor.account(&transaction.to_account_id, None, subfields(field)?)
+            }
+            "block" => {
+                check_arguments(field, &[])?;
+                let selections = subfields(field)?;
+                match height.and_then(|height| state.find_block(Some(height), None)) {
+                    Some((height, block)) => executor.block(height, &block, selections),
+                    None => Ok(Value::Null),
+                }
+            }
+            _ => Err(unknown_field("Transaction", field)),
+        })
+    }
+
+    /// Resolves an account, with the alias it was looked up by if it was
+    fn account(&mut self, account_id: &str, alias: Option<String>, selections: &[Field]) -> Result<Value, String> {
+        let state = self.state;
+        self.object("Account", selections, |executor, field| match field.name.as_str() {
+            "id" => leaf(field, json!(account_id)),
+            "alias" => leaf(field, json!(alias)),
+            "balance" => leaf(field, json!(state.get_balance(account_id))),
+            "nonce" => leaf(field, json!(state.get_next_nonce(account_id))),
+            "transactionCount" => leaf(field, json!(state.get_history(&TransactionFilter::of_account(account_id), 1, 0).1)),
+            "transactions" => {
+                check_arguments(field, &["page", "limit"])?;
+                let page = executor.int_argument(field, "page")?.unwrap_or(1).clamp(1, u32::MAX as u64);
+                let limit = executor.int_argument(field, "limit")?.unwrap_or(DEFAULT_LIST);
+                if limit > MAX_LIST {
+                    return Err(format!("Field transactions returns at most {} transactions, use a smaller limit.", MAX_LIST));
+                }
+                let selections = subfields(field)?;
+                let (history, _) = state.get_history(&TransactionFilter::of_account(account_id), page as usize, limit as usize);
+                let transactions = history.iter().map(|(height, _, transaction)| executor.transaction(transaction, Some(*height), selections));
+                transactions.collect::<Result<Vec<Value>, String>>().map(Value::Array)
+            }
+            _ => Err(unknown_field("Account", field)),
+        })
+    }
+}
+
+/// Returns the value of a scalar field, which takes no arguments and has no subfields
+fn leaf(field: &Field, value: Value) -> Result<Value, String> {
+    check_arguments(field, &[])?;
+    if !field.selections.is_empty() {
+        return Err(format!("Field {} is a scalar, it has no subfields to select.", field.name));
+    }
+    Ok(value)
+}
+
+/// Returns the selected subfields of an object field, which has to select some
+fn subfields(field: &Field) -> Result<&[Field], String> {
+    if field.selections.is_empty() {
+        return Err(format!("Field {} is an object, select its subfields.", field.name));
+    }
+    Ok(&field.selections)
+}
+
+/// Checks that a field only has the given arguments
+fn check_arguments(field: &Field, allowed: &[&str]) -> Result<(), String> {
+    match field.arguments.iter().find(|(argument, _)| !allowed.contains(&argument.as_str())) {
+        Some((argument, _)) => Err(format!("Field {} has no argument {}.", field.name, argument)),
+        None => Ok(()),
+    }
+}
+
+fn unknown_field(type_name: &str, field: &Field) -> String {
+    format!("Type {} has no field {}.", type_name, field.name)
+}
+
+/// Returns the name of what a transaction does, e.g. `Transfer` or `Stake`
+fn kind_name(transaction: &Transaction) -> String {
+    // The kinds are serialized as their name, or as an object with their name as the only key if they have fields
+    match serde_json::to_value(&transaction.kind) {
+        Ok(Value::String(name)) => name,
+        Ok(Value::Object(kind)) => kind.keys().next().cloned().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
@@ -0,0 +1,431 @@
+//! The gRPC service of the node, defined in `proto/toychain.proto`, so services in other languages can generate a
+//! client instead of reverse-engineering the bincode layout of the UDP protocol. The service is served as gRPC-Web over
+//! the node's HTTP/1.1 server: calls are POSTed to `/toychain.ToyChain/<Method>` with a body of length-prefixed frames,
+//! and answered with the frame of the reply followed by a frame of trailers carrying the gRPC status. Subscriptions are
+//! streamed as one frame per event until the client closes the connection.
+//!
+//! This isn't native gRPC, which needs HTTP/2: native gRPC clients can only call the service through a proxy
+//! translating their calls into gRPC-Web, gRPC-Web clients call it directly.
+//!
+//! The messages of the service only have varint and length-delimited fields, which are encoded and decoded here.
+
+use std::io::Write;
+use std::sync::Arc;
+use crate::common::{AccountCreationOp, Amount, FundTransferOp};
+use super::allowlist::Unauthorized;
+use super::audit::{self, Audited};
+use super::events::Event;
+use super::http::{self, HttpRequest, HttpResponse};
+use super::validation;
+use super::{State, Transaction};
+
+/// The path prefix of the methods of the service
+const SERVICE_PATH: &str = "/toychain.ToyChain/";
+
+const CONTENT_TYPE: &str = "application/grpc-web+proto";
+
+/// Methods the node serves, see `call`
+const METHODS: [&str; 6] = ["CreateAccount", "Transfer", "GetBalance", "GetNonce", "GetBlock", "Subscribe"];
+
+// Status codes defined by gRPC
+const OK: u32 = 0;
+const INVALID_ARGUMENT: u32 = 3;
+const NOT_FOUND: u32 = 5;
+const RESOURCE_EXHAUSTED: u32 = 8;
+// Operations rejected by the node, e.g. for insufficient funds
+const FAILED_PRECONDITION: u32 = 9;
+const UNIMPLEMENTED: u32 = 12;
+const UNAUTHENTICATED: u32 = 16;
+
+// Flags of the frames of a gRPC-Web body
+const DATA_FRAME: u8 = 0x00;
+const COMPRESSED_FRAME: u8 = 0x01;
+const TRAILERS_FRAME: u8 = 0x80;
+
+// Wire types of protobuf fields
+const VARINT: u64 = 0;
+const LENGTH_DELIMITED: u64 = 2;
+
+/// A gRPC error: its status code and message
+type Status = (u32, String);
+
+/// Starts the gRPC-Web server on the given address
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    tracing::info!("gRPC-Web server started on {}, native gRPC clients need a proxy translating to gRPC-Web.", addr);
+    http::serve(addr, move |request| handle(&state, request));
+}
+
+/// Handles a single HTTP request carrying a gRPC call
+fn handle(state: &Arc<State>, request: HttpRequest) -> HttpResponse {
+    let Some(method) = request.path.strip_prefix(SERVICE_PATH) else {
+        return HttpResponse::text(404, "Not found.");
+    };
+    if request.method != "POST" {
+        return HttpResponse::text(405, "gRPC calls must be sent with POST.");
+    }
+
+    if let Err(retry_after) = state.rate_limiter.check(request.remote_addr.ip()) {
+        state.metrics.observe_rate_limited("grpc");
+        return reply(Err((RESOURCE_EXHAUSTED, format!("Rate limit exceeded, retry in {} ms.", retry_after.as_millis().max(1)))));
+    }
+
+    // gRPC clients can't authenticate with a key, so none is allowed in permissioned mode
+    if state.allowlist.is_permissioned() {
+        state.metrics.observe_unauthorized("grpc");
+        let message = format!("Unauthorized ({}), the node is in permissioned mode and only accepts requests of allowed keys.", Unauthorized::Unauthenticated.code());
+        return reply(Err((UNAUTHENTICATED, message)));
+    }
+
+    // Numbered before the call is parsed, like requests over UDP are, see [super::workers]
+    let ticket = state.sequencer.admit();
+    let message = match read_frame(&request.body) {
+        Ok(message) => message,
+        Err(status) => return reply(Err(status)),
+    };
+    let fields = match Fields::decode(message) {
+        Ok(fields) => fields,
+        Err(e) => return reply(Err((INVALID_ARGUMENT, format!("Invalid message: {}", e)))),
+    };
+
+    // Transactions created through gRPC are attributed to the address of the caller
+    let node_id = format!("grpc:{}", request.remote_addr.ip());
+    let _span = tracing::info_span!("grpc_request", from = %node_id, method).entered();
+    tracing::info!("Received gRPC call.");
+
+    if method == "Subscribe" {
+        return subscribe(state, &fields);
+    }
+
+    let started = std::time::Instant::now();
+    // Calls changing an account wait for the requests changing it that arrived before them
+    let changed_account = match method {
+        "CreateAccount" | "Transfer" => fields.string(1).ok().flatten(),
+        _ => None,
+    };
+    ticket.wait_for_turn(changed_account.as_deref().as_slice());
+    let worker = state.workers.acquire();
+    let result = call(state, node_id.clone(), method, &fields);
+    drop(worker);
+    // Unknown methods share a label, so clients can't create any number of metric series
+    let method = METHODS.iter().copied().find(|&known| known == method).unwrap_or("unknown");
+    state.metrics.observe_request("grpc", method, started.elapsed());
+    let outcome = result.as_ref().map(|_| "OK".to_string()).map_err(|(_, message)| message.clone());
+    audit::record(state, Audited { transport: "grpc", source: request.remote_addr, from_node: &node_id, operation: method, result: &outcome });
+
+    reply(result)
+}
+
+/// Dispatches a unary call to the node state
+///
+/// Returns: the encoded reply message, or the status of the error
+fn call(state: &State, node_id: String, method: &str, fields: &Fields) -> Result<Vec<u8>, Status> {
+    match method {
+        "CreateAccount" => {
+            let account_info = AccountCreationOp {
+                account_id: fields.string(1)?.unwrap_or_default(),
+                starting_balance: fields.amount(2)?,
+            };
+            validation::validate_account_creation(&account_info.account_id, account_info.starting_balance).map_err(|e| (INVALID_ARGUMENT, e.to_string()))?;
+            let message = state.create_account(node_id, &account_info).map_err(|message| (FAILED_PRECONDITION, message))?;
+            Ok(Encoder::default().string(1, &message).finish())
+        }
+        "Transfer" => {
+            let transfer_info = FundTransferOp {
+                from_account_id: fields.string(1)?.unwrap_or_default(),
+                to_account_id: fields.string(2)?.unwrap_or_default(),
+                amount: fields.amount(3)?,
+                fee: fields.amount(4)?,
+                nonce: fields.uint64(5).unwrap_or_default(),
+                memo: fields.string(6)?,
+            };
+            validation::validate_transfer(&transfer_info).map_err(|e| (INVALID_ARGUMENT, e.to_string()))?;
+            let message = state.transfer_funds(node_id, &transfer_info).map_err(|message| (FAILED_PRECONDITION, message))?;
+            Ok(Encoder::default().string(1, &message).finish())
+        }
+        "GetBalance" => {
+            let account_id = fields.string(1)?.unwrap_or_default();
+            let balance = state.get_balance(&account_id);
+            Ok(Encoder::default().string(1, &account_id).string(2, &balance.to_string()).finish())
+        }
+        "GetNonce" => {
+            let account_id = fields.string(1)?.unwrap_or_default();
+            let nonce = state.get_next_nonce(&account_id);
+            Ok(Encoder::default().string(1, &account_id).uint64(2, nonce).finish())
+        }
+        "GetBlock" => {
+            let (height, hash) = (fields.uint64(1), fields.string(2)?);
+            if height.is_none() && hash.is_none() {
+                return Err((INVALID_ARGUMENT, "Either height or hash must be given.".to_string()));
+            }
+            let (height, block) = state.find_block(height.map(|height| height as usize), hash.as_deref()).ok_or((NOT_FOUND, "Block not found.".to_string()))?;
+            let mut reply = Encoder::default()
+                .uint64(1, height as u64)
+                .string(2, &block.hash)
+                .string(3, &block.header.previous_hash)
+                .string(4, &block.header.merkle_root)
+                .uint64(5, block.header.timestamp);
+            for transaction in &block.transactions {
+                reply = reply.message(6, encode_transaction(&transaction.id(), transaction));
+            }
+            Ok(reply.finish())
+        }
+        _ => Err((UNIMPLEMENTED, format!("Method {} not found.", method))),
+    }
+}
+
+/// A topic a subscription streams the events of
+enum Topic {
+    NewBlock,
+    NewTransaction,
+    Account(String),
+}
+
+impl Topic {
+    fn matches(&self, event: &Event) -> bool {
+        match (self, event) {
+            (Topic::NewBlock, Event::NewBlock { .. }) | (Topic::NewTransaction, Event::NewTransaction { .. }) => true,
+            (Topic::Account(watched), Event::AccountChanged { account_id, .. }) => watched == account_id,
+            _ => false,
+        }
+    }
+}
+
+/// Answers a `Subscribe` call with a stream of the events of the topic, which ends when the client goes away
+fn subscribe(state: &Arc<State>, fields: &Fields) -> HttpResponse {
+    let topic = match (fields.uint64(1).unwrap_or_default(), fields.string(2)) {
+        (0, _) => Topic::NewBlock,
+        (1, _) => Topic::NewTransaction,
+        (2, Ok(Some(account_id))) if !account_id.is_empty() => Topic::Account(account_id),
+        (2, _) => return reply(Err((INVALID_ARGUMENT, "The ACCOUNT_CHANGED topic requires an account_id.".to_string()))),
+        (topic, _) => return reply(Err((INVALID_ARGUMENT, format!("Unknown topic {}.", topic)))),
+    };
+
+    let events = state.events.subscribe();
+    let mut response = HttpResponse {
+        status: 200,
+        content_type: CONTENT_TYPE,
+        body: Vec::new(),
+        stream: None,
+    };
+    response.stream = Some(Box::new(move |stream| {
+        // Every event is flushed on its own, a failed write means the client closed the stream
+        for event in events.iter().filter(|event| topic.matches(event)) {
+            stream.write_all(&frame(DATA_FRAME, &encode_event(&event)))?;
+            stream.flush()?;
+        }
+        Ok(())
+    }));
+    response
+}
+
+/// Returns the response to a unary call: the frame of the reply if the call succeeded, and the trailers with its status
+fn reply(result: Result<Vec<u8>, Status>) -> HttpResponse {
+    let (mut body, status, message) = match result {
+        Ok(reply) => (frame(DATA_FRAME, &reply), OK, String::new()),
+        Err((status, message)) => (Vec::new(), status, message),
+    };
+    let trailers = format!("grpc-status:{}\r\ngrpc-message:{}\r\n", status, percent_encode(&message));
+    body.extend_from_slice(&frame(TRAILERS_FRAME, trailers.as_bytes()));
+    HttpResponse {
+        status: 200,
+        content_type: CONTENT_TYPE,
+        body,
+        stream: None,
+    }
+}
+
+/// Reads the message of the single frame of a unary request body
+fn read_frame(body: &[u8]) -> Result<&[u8], Status> {
+    let invalid = || (INVALID_ARGUMENT, "The request body isn't a gRPC-Web frame.".to_string());
+    let (&flags, rest) = body.split_first().ok_or_else(invalid)?;
+    let length = rest.get(..4).ok_or_else(invalid)?;
+    let length = u32::from_be_bytes(length.try_into().unwrap()) as usize;
+    let message = rest.get(4..4 + length).ok_or_else(invalid)?;
+    match flags {
+        DATA_FRAME => Ok(message),
+        COMPRESSED_FRAME => Err((UNIMPLEMENTED, "Compressed messages aren't supported.".to_string())),
+        _ => Err(invalid()),
+    }
+}
+
+fn frame(flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(5 + payload.len());
+    frame.push(flags);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(payload);
+    frame
+}
+
+/// Percent-encodes a status message as gRPC requires, only printable ASCII other than `%` is sent as is
+fn percent_encode(message: &str) -> String {
+    message
+        .bytes()
+        .map(|byte| match byte {
+            b' '..=b'~' if byte != b'%' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn encode_transaction(id: &str, transaction: &Transaction) -> Encoder {
+    let encoder = Encoder::default()
+        .string(1, id)
+        .string(2, transaction.from_account_id.as_deref().unwrap_or_default())
+        .string(3, &transaction.to_account_id)
+        .string(4, &transaction.amount.to_string())
+        .string(5, &transaction.fee.to_string())
+        .uint64(6, transaction.timestamp());
+    match &transaction.memo {
+        Some(memo) => encoder.optional_string(7, memo),
+        None => encoder,
+    }
+}
+
+fn encode_event(event: &Event) -> Vec<u8> {
+    let event = match event {
+        Event::NewBlock { height, hash, transactions } => {
+            Encoder::default().message(1, Encoder::default().uint64(1, *height as u64).string(2, hash).uint64(3, *transactions as u64))
+        }
+        Event::NewTransaction { id, transaction } => Encoder::default().message(2, encode_transaction(id, transaction)),
+        Event::AccountChanged { account_id, balance, height } => {
+            Encoder::default().message(3, Encoder::default().string(1, account_id).string(2, &balance.to_string()).uint64(3, *height as u64))
+        }
+    };
+    event.finish()
+}
+
+/// Writes the fields of a protobuf message. Fields with their default value are left out, as proto3 does.
+#[derive(Default)]
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn key(&mut self, number: u64, wire_type: u64) {
+        self.varint(number << 3 | wire_type);
+    }
+
+    fn uint64(mut self, number: u64, value: u64) -> Encoder {
+        if value != 0 {
+            self.key(number, VARINT);
+            self.varint(value);
+        }
+        self
+    }
+
+    fn string(self, number: u64, text: &str) -> Encoder {
+        if text.is_empty() {
+            return self;
+        }
+        self.optional_string(number, text)
+    }
+
+    /// Writes a string even if it is empty, for fields with explicit presence
+    fn optional_string(mut self, number: u64, text: &str) -> Encoder {
+        self.key(number, LENGTH_DELIMITED);
+        self.varint(text.len() as u64);
+        self.0.extend_from_slice(text.as_bytes());
+        self
+    }
+
+    fn message(mut self, number: u64, message: Encoder) -> Encoder {
+        self.key(number, LENGTH_DELIMITED);
+        self.varint(message.0.len() as u64);
+        self.0.extend_from_slice(&message.0);
+        self
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// A field of a decoded protobuf message
+enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+}
+
+/// The fields of a decoded protobuf message by their number. Fixed-width fields, which no message of the service has,
+/// are skipped.
+struct Fields<'a>(Vec<(u64, Field<'a>)>);
+
+impl<'a> Fields<'a> {
+    fn decode(mut bytes: &'a [u8]) -> Result<Fields<'a>, String> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = read_varint(&mut bytes)?;
+            let (number, wire_type) = (key >> 3, key & 7);
+            let field = match wire_type {
+                VARINT => Field::Varint(read_varint(&mut bytes)?),
+                LENGTH_DELIMITED => {
+                    let length = usize::try_from(read_varint(&mut bytes)?).map_err(|_| "field too long")?;
+                    if length > bytes.len() {
+                        return Err("field longer than the message".to_string());
+                    }
+                    let (field, rest) = bytes.split_at(length);
+                    bytes = rest;
+                    Field::Bytes(field)
+                }
+                1 | 5 => {
+                    let width = if wire_type == 1 { 8 } else { 4 };
+                    bytes = bytes.get(width..).ok_or("fixed-width field longer than the message")?;
+                    continue;
+                }
+                _ => return Err(format!("unsupported wire type {}", wire_type)),
+            };
+            fields.push((number, field));
+        }
+        Ok(Fields(fields))
+    }
+
+    /// Returns the last value of the field, as a later value of a field overrides earlier ones
+    fn get(&self, number: u64) -> Option<&Field<'a>> {
+        self.0.iter().rev().find(|(field_number, _)| *field_number == number).map(|(_, field)| field)
+    }
+
+    fn uint64(&self, number: u64) -> Option<u64> {
+        match self.get(number) {
+            Some(Field::Varint(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(&self, number: u64) -> Result<Option<String>, Status> {
+        match self.get(number) {
+            Some(Field::Bytes(bytes)) => std::str::from_utf8(bytes)
+                .map(|text| Some(text.to_string()))
+                .map_err(|_| (INVALID_ARGUMENT, format!("Field {} isn't valid UTF-8.", number))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Returns the amount in a decimal string field, zero if the field isn't set
+    fn amount(&self, number: u64) -> Result<Amount, Status> {
+        match self.string(number)? {
+            Some(text) if !text.is_empty() => text.parse().map_err(|e| (INVALID_ARGUMENT, e)),
+            _ => Ok(Amount::ZERO),
+        }
+    }
+}
+
+fn read_varint(bytes: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = bytes.split_first().ok_or("varint longer than the message")?;
+        *bytes = rest;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint longer than 64 bits".to_string())
+}
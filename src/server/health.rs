@@ -0,0 +1,168 @@
+//! Health checks. A node is live while it accepts requests, i.e. until it starts shutting down, and ready while it is
+//! live, isn't syncing the chain of a peer and its minting progresses. Minting is stalled if the mint loop stopped
+//! running, or if transactions are waiting in the mempool but no block was added for a few mint intervals. The
+//! health is reported by the health operation and, on the metrics port, by `/healthz` for liveness and `/readyz` for
+//! readiness, for orchestrators and scripts.
+
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use serde_json::{json, Value};
+use super::http::HttpResponse;
+use super::State;
+
+/// Number of mint intervals without a block while transactions are waiting after which minting is stalled
+const STALL_INTERVALS: u64 = 3;
+
+/// Time the mint loop may take beyond its interval, e.g. for minting a large block, before it counts as stopped
+const MINT_LOOP_GRACE: Duration = Duration::from_secs(5);
+
+/// The rounds of the mint loop, see [record_mint_round]
+pub struct MintRounds {
+    // When the mint loop last ran
+    ran: Instant,
+    // When the mint loop last ran without transactions left waiting, or a block was added since the round before
+    progressed: Instant,
+    // Length of the ledger at the last round
+    length: usize,
+}
+
+impl MintRounds {
+    pub fn new() -> MintRounds {
+        MintRounds { ran: Instant::now(), progressed: Instant::now(), length: 0 }
+    }
+}
+
+/// How the minting of the node is doing
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Minting {
+    // Blocks are minted as transactions arrive
+    Progressing,
+    // There is nothing to mint
+    Idle,
+    // The node doesn't mint while catching up with the chain of a peer
+    Syncing,
+    // Transactions are waiting but no block was minted for several mint intervals, or the mint loop stopped
+    Stalled,
+}
+
+impl Minting {
+    fn name(&self) -> &'static str {
+        match self {
+            Minting::Progressing => "progressing",
+            Minting::Idle => "idle",
+            Minting::Syncing => "syncing",
+            Minting::Stalled => "stalled",
+        }
+    }
+}
+
+/// The health of the node at a point in time
+pub struct Health {
+    // Whether the node accepts requests, false once it started shutting down
+    live: bool,
+    minting: Minting,
+    height: usize,
+    // Seconds since the last block was minted, by any node
+    lag: u64,
+    pending_transactions: usize,
+    // Whether the last block minted was full and left transactions in the mempool
+    backed_up: bool,
+}
+
+impl Health {
+    /// Checks the health of the node
+    pub fn check(state: &State) -> Health {
+        let (height, timestamp) = {
+            let ledger = state.ledger.read().unwrap();
+            (ledger.len() - 1, ledger.last().unwrap().header.timestamp)
+        };
+        let lag = state.clock.unix_time().saturating_sub(timestamp);
+        let (pending_transactions, backed_up) = {
+            let mempool = state.mempool.lock().unwrap();
+            (mempool.len(), mempool.is_backed_up())
+        };
+        let mint_interval = state.mint_interval();
+        let (ran, progressed) = {
+            let rounds = state.mint_rounds.lock().unwrap();
+            (rounds.ran.elapsed(), rounds.progressed.elapsed())
+        };
+        // The mint loop sleeps the mint interval the node was started with. A simulating node only mints when its
+        // clock is advanced, so it doesn't stall however long it waits in real time.
+        let simulating = state.simulation.is_some();
+        let mint_loop_stopped = !simulating && ran > Duration::from_secs(state.mint_interval * 2) + MINT_LOOP_GRACE;
+        let waiting = !simulating && pending_transactions > 0 && progressed > Duration::from_secs(mint_interval * STALL_INTERVALS);
+
+        let minting = if state.sync.lock().unwrap().is_syncing() {
+            Minting::Syncing
+        } else if mint_loop_stopped || waiting {
+            Minting::Stalled
+        } else if pending_transactions > 0 {
+            Minting::Progressing
+        } else {
+            Minting::Idle
+        };
+        Health { live: !state.shutting_down.load(Ordering::SeqCst), minting, height, lag, pending_transactions, backed_up }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.live && matches!(self.minting, Minting::Progressing | Minting::Idle)
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "live": self.live,
+            "ready": self.is_ready(),
+            "minting": self.minting.name(),
+            "height": self.height,
+            "last_block_seconds_ago": self.lag,
+            "pending_transactions": self.pending_transactions,
+            "mempool_backed_up": self.backed_up,
+        })
+    }
+
+    /// Describes the health for the health response
+    ///
+    /// Returns: the description, as an error if the node isn't ready
+    pub fn describe(&self, node_id: &str) -> Result<String, String> {
+        let details = format!(
+            "{}, minting {}, last block minted {}s ago at height {}, {} pending transactions{}",
+            if self.live { "accepting requests" } else { "shutting down" },
+            self.minting.name(),
+            self.lag,
+            self.height,
+            self.pending_transactions,
+            if self.backed_up { " backed up behind full blocks" } else { "" }
+        );
+        match self.is_ready() {
+            true => Ok(format!("✅ Node {} is healthy: {}.", node_id, details)),
+            false => Err(format!("❌ Node {} isn't ready: {}.", node_id, details)),
+        }
+    }
+}
+
+/// Records a round of the mint loop, so a stopped mint loop and transactions that aren't minted are noticed. Minting
+/// progresses if the round minted a block, there was nothing to mint, or a peer added a block, e.g. in the slot of
+/// another validator.
+pub fn record_mint_round(state: &State, minted: bool) {
+    let idle = state.mempool.lock().unwrap().is_empty();
+    let length = state.ledger.read().unwrap().len();
+    let mut rounds = state.mint_rounds.lock().unwrap();
+    rounds.ran = Instant::now();
+    if minted || idle || length != rounds.length {
+        rounds.progressed = rounds.ran;
+    }
+    rounds.length = length;
+}
+
+/// Answers `/healthz` and `/readyz`: with status 200 if the node is live or ready, 503 otherwise, and the health as
+/// JSON
+///
+/// Returns: None for other paths
+pub fn http_response(state: &State, path: &str) -> Option<HttpResponse> {
+    if path != "/healthz" && path != "/readyz" {
+        return None;
+    }
+    let health = Health::check(state);
+    let healthy = if path == "/healthz" { health.live } else { health.is_ready() };
+    Some(HttpResponse::json(if healthy { 200 } else { 503 }, &health.to_json()))
+}
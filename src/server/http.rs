@@ -0,0 +1,247 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Maximum size of a request body the HTTP server will accept
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// Maximum size of the request line and headers together the HTTP server will accept
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// Maximum number of header lines the HTTP server will accept
+const MAX_HEADERS: usize = 100;
+
+/// Maximum number of connections the HTTP server handles at the same time, others are answered they have to wait
+const MAX_CONNECTIONS: usize = 256;
+
+/// How long a read from or write to a connection may block before it is dropped, so slow clients don't hold on to it
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed HTTP request. Only what the node's HTTP endpoints need is kept.
+pub struct HttpRequest {
+    pub method: String,
+    // The path without the query string
+    pub path: String,
+    pub body: Vec<u8>,
+    pub remote_addr: SocketAddr,
+}
+
+/// Writes the body of a streamed response to the connection until the stream ends
+pub type BodyStream = Box<dyn FnOnce(&mut TcpStream) -> std::io::Result<()> + Send>;
+
+/// A HTTP response to be written back to the client
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+    // Written after the body, e.g. the events of a subscription. The response then has no length, and ends when the
+    // connection is closed.
+    pub stream: Option<BodyStream>,
+}
+
+impl HttpResponse {
+    pub fn json(status: u16, body: &serde_json::Value) -> HttpResponse {
+        HttpResponse {
+            status,
+            content_type: "application/json",
+            body: body.to_string().into_bytes(),
+            stream: None,
+        }
+    }
+
+    pub fn text(status: u16, body: &str) -> HttpResponse {
+        HttpResponse {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.as_bytes().to_vec(),
+            stream: None,
+        }
+    }
+}
+
+/// Returns the reason phrase for the status codes used by the node
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Counts a connection being handled for as long as it lives
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    /// Takes a slot for a connection, unless all of them are taken
+    fn take(connections: &Arc<AtomicUsize>) -> Option<ConnectionSlot> {
+        connections
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| (count < MAX_CONNECTIONS).then_some(count + 1))
+            .ok()
+            .map(|_| ConnectionSlot(connections.clone()))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Listens on the given address and calls the handler for every HTTP request received. Each connection is handled on
+/// its own thread and closed after the response is sent. At most [MAX_CONNECTIONS] are handled at the same time, and
+/// reads and writes time out after [IO_TIMEOUT].
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve<F>(addr: &str, handler: F)
+where
+    F: Fn(HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind(addr)
+        .unwrap_or_else(|e| panic!("Failed to bind HTTP server to address {}. Error: {}", addr, e));
+    let handler = Arc::new(handler);
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::warn!("Failed to accept HTTP connection: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = stream.set_read_timeout(Some(IO_TIMEOUT)).and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT))) {
+            tracing::warn!("Failed to set HTTP connection timeouts: {}", e);
+            continue;
+        }
+
+        let Some(slot) = ConnectionSlot::take(&connections) else {
+            tracing::debug!("Rejected HTTP connection, {} connections are handled already.", MAX_CONNECTIONS);
+            let _ = write_response(stream, HttpResponse::text(503, "Too many connections, try again later"));
+            continue;
+        };
+        let handler = handler.clone();
+        std::thread::spawn(move || {
+            let _slot = slot;
+            if let Err(e) = handle_connection(stream, handler.as_ref()) {
+                tracing::debug!("Failed to handle HTTP connection: {}", e);
+            }
+        });
+    }
+}
+
+/// Reads a single request from the connection, passes it to the handler and writes the response
+fn handle_connection<F>(stream: TcpStream, handler: &F) -> std::io::Result<()>
+where
+    F: Fn(HttpRequest) -> HttpResponse,
+{
+    let remote_addr = stream.peer_addr()?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let response = match read_request(&mut reader, remote_addr)? {
+        Ok(request) => handler(request),
+        Err(response) => response,
+    };
+
+    write_response(stream, response)
+}
+
+/// Reads a line of the request head, of at most the remaining size of the head
+///
+/// Returns: the line, or None if it doesn't end within the remaining size
+fn read_head_line(reader: &mut BufReader<TcpStream>, remaining: &mut usize) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    let read = reader.by_ref().take(*remaining as u64).read_line(&mut line)?;
+    *remaining -= read;
+    Ok((read == 0 || line.ends_with('\n')).then_some(line))
+}
+
+/// Parses the request line, headers and body. Returns an error response if the request is malformed or its head is
+/// larger than [MAX_HEAD_SIZE] or has more than [MAX_HEADERS] lines.
+fn read_request(
+    reader: &mut BufReader<TcpStream>,
+    remote_addr: SocketAddr,
+) -> std::io::Result<Result<HttpRequest, HttpResponse>> {
+    let too_large = || HttpResponse::text(431, "Request head too large");
+    let mut remaining = MAX_HEAD_SIZE;
+    let Some(request_line) = read_head_line(reader, &mut remaining)? else {
+        return Ok(Err(too_large()));
+    };
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Ok(Err(HttpResponse::text(400, "Malformed request line"))),
+    };
+
+    let mut content_length = 0;
+    let mut headers = 0;
+    loop {
+        let Some(line) = read_head_line(reader, &mut remaining)? else {
+            return Ok(Err(too_large()));
+        };
+        if line.trim().is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADERS {
+            return Ok(Err(too_large()));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = match value.trim().parse::<usize>() {
+                    Ok(length) => length,
+                    Err(_) => return Ok(Err(HttpResponse::text(400, "Invalid Content-Length"))),
+                };
+            }
+        }
+    }
+
+    if content_length > MAX_BODY_SIZE {
+        return Ok(Err(HttpResponse::text(413, "Request body too large")));
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let path = match target.split_once('?') {
+        Some((path, _)) => path.to_string(),
+        None => target,
+    };
+
+    Ok(Ok(HttpRequest {
+        method,
+        path,
+        body,
+        remote_addr,
+    }))
+}
+
+fn write_response(mut stream: TcpStream, response: HttpResponse) -> std::io::Result<()> {
+    let content_length = match response.stream {
+        Some(_) => String::new(),
+        None => format!("Content-Length: {}\r\n", response.body.len()),
+    };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n{}Connection: close\r\n\r\n",
+        response.status,
+        reason_phrase(response.status),
+        response.content_type,
+        content_length
+    );
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()?;
+    match response.stream {
+        Some(body_stream) => body_stream(&mut stream),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,90 @@
+//! Node identities. Every node has an ed25519 identity key, generated on its first start and kept in the data
+//! directory, and its node ID is the hex encoded public key of it. The node signs the blocks it mints and the requests
+//! it sends to its peers with the key, so both can be attributed to it and another node can't claim its node ID, which
+//! a hostname didn't prevent.
+
+use std::path::Path;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use tracing::info;
+use crate::common::Request;
+use crate::keys;
+use crate::vault::Vault;
+use super::encryption::write_key_file;
+use super::Block;
+
+/// Name of the file in the data directory the identity key of the node is kept in
+const KEY_FILE: &str = "node.key";
+
+/// Signature of a block by the node that minted it. The signature isn't covered by the hash of the block.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct NodeSignature {
+    // ID of the node, its hex encoded public key
+    pub node_id: String,
+    pub signature: String,
+}
+
+impl NodeSignature {
+    /// Signs a block with the identity key of the node
+    pub fn new(block: &Block, key: &SigningKey) -> NodeSignature {
+        NodeSignature {
+            node_id: keys::public_key_hex(key),
+            signature: keys::sign_node_block(key, &block.hash),
+        }
+    }
+}
+
+/// Loads the identity key of the node from the data directory, generating and saving a new one on the first start.
+/// Without a data directory a new key is generated on every start, so the node gets a new node ID.
+pub fn load(data_dir: Option<&str>, vault: Option<&Vault>) -> Result<SigningKey, String> {
+    let key = match data_dir {
+        Some(data_dir) => load_or_generate_key(&Path::new(data_dir).join(KEY_FILE), vault)?,
+        None => keys::generate(),
+    };
+    info!("Node ID {}.", keys::public_key_hex(&key));
+    Ok(key)
+}
+
+/// Loads the identity key from the given file, generating and saving a new one if the file doesn't exist. The file
+/// holds the 32 byte private key, sealed if the data directory is encrypted.
+fn load_or_generate_key(path: &Path, vault: Option<&Vault>) -> Result<SigningKey, String> {
+    match std::fs::read(path) {
+        Ok(bytes) => match Vault::open_with(vault, &bytes).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes.as_slice()).ok()) {
+            Some(seed) => Ok(SigningKey::from_bytes(&seed)),
+            None => Err(format!("Invalid node identity key file {}, delete it to generate a new node ID.", path.display())),
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| format!("Failed to create data directory {}: {}", parent.display(), e))?;
+            }
+            let key = keys::generate();
+            write_key_file(path, &Vault::seal_with(vault, key.to_bytes().to_vec())).map_err(|e| format!("Failed to save the node identity key to {}: {}", path.display(), e))?;
+            info!("Generated a new node identity key in {}.", path.display());
+            Ok(key)
+        }
+        Err(e) => Err(format!("Failed to read the node identity key from {}: {}", path.display(), e)),
+    }
+}
+
+/// Checks the signature of a block by the node that minted it, if it has one
+///
+/// Returns: why the signature is invalid
+pub fn check_block(block: &Block) -> Result<(), String> {
+    match &block.node_signature {
+        Some(signature) => keys::verify_node_block(&signature.node_id, &block.hash, &signature.signature),
+        None => Ok(()),
+    }
+}
+
+/// Checks that a request from another node is signed with the identity key of the node it claims to be from
+///
+/// Returns: a user-facing error if the request isn't signed by the node
+pub fn check_request(request: &Request) -> Result<(), String> {
+    let Some((fields, signature)) = request.operation.node_signature() else {
+        return Ok(());
+    };
+    let Some(request_id) = request.request_id else {
+        return Err(format!("❌ The {} request has no request ID, which its signature covers.", request.operation.name()));
+    };
+    keys::verify_node_request(&request.from_node, request.operation.name(), request_id, &fields, signature)
+}
@@ -0,0 +1,82 @@
+//! Secondary indexes of the minted transactions, so looking up a transaction, its receipt or proof, or the history of
+//! an account doesn't scan the whole chain. Every transaction is located by its ID and by the accounts it is from and
+//! to, as the height of its block and its index in the block. The indexes are updated as blocks are added to the main
+//! chain and rolled back when the node switches to a fork, and rebuilt from the ledger when it is restored or pruned.
+
+use std::collections::HashMap;
+use super::Block;
+
+/// Where a minted transaction is: the height of its block and its index in the block
+pub type Location = (usize, usize);
+
+/// The locations of the minted transactions on the main chain
+#[derive(Default)]
+pub struct TransactionIndex {
+    // Location of every transaction by its ID
+    transactions: HashMap<String, Location>,
+    // Locations of the transactions every account is the from or to account of, in the order they were minted
+    accounts: HashMap<String, Vec<Location>>,
+    // Number of blocks indexed, the blocks up to the tip of the main chain
+    blocks: usize,
+}
+
+impl TransactionIndex {
+    /// Builds the indexes of the transactions of a chain
+    pub fn build(ledger: &[Block]) -> TransactionIndex {
+        let mut index = TransactionIndex::default();
+        for block in ledger {
+            index.add_block(block);
+        }
+        index
+    }
+
+    /// Indexes the transactions of the block added at the tip of the chain
+    pub fn add_block(&mut self, block: &Block) {
+        let height = self.blocks;
+        for (position, transaction) in block.transactions.iter().enumerate() {
+            let location = (height, position);
+            self.transactions.insert(transaction.id(), location);
+            let from = transaction.from_account_id.iter().filter(|from_account_id| **from_account_id != transaction.to_account_id);
+            for account_id in from.chain([&transaction.to_account_id]) {
+                self.accounts.entry(account_id.clone()).or_default().push(location);
+            }
+        }
+        self.blocks += 1;
+    }
+
+    /// Removes the transactions of the blocks rolled back from the tip of the chain, e.g. when the node switches to a
+    /// fork
+    ///
+    /// # Arguments
+    ///
+    /// * `height`: the height of the first block rolled back
+    /// * `rolled_back`: the blocks rolled back, oldest first
+    pub fn roll_back(&mut self, height: usize, rolled_back: &[Block]) {
+        for transaction in rolled_back.iter().flat_map(|block| &block.transactions) {
+            let id = transaction.id();
+            // A transaction minted again on the rolled back blocks keeps its earlier location
+            if self.transactions.get(&id).is_some_and(|(block_height, _)| *block_height >= height) {
+                self.transactions.remove(&id);
+            }
+            for account_id in transaction.from_account_id.iter().chain([&transaction.to_account_id]) {
+                if let Some(locations) = self.accounts.get_mut(account_id) {
+                    locations.retain(|(block_height, _)| *block_height < height);
+                    if locations.is_empty() {
+                        self.accounts.remove(account_id);
+                    }
+                }
+            }
+        }
+        self.blocks = height;
+    }
+
+    /// Returns the location of a minted transaction, None if it isn't on the main chain
+    pub fn locate(&self, transaction_id: &str) -> Option<Location> {
+        self.transactions.get(transaction_id).copied()
+    }
+
+    /// Returns the locations of the transactions an account is the from or to account of, oldest first
+    pub fn of_account(&self, account_id: &str) -> &[Location] {
+        self.accounts.get(account_id).map_or(&[], Vec::as_slice)
+    }
+}
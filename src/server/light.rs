@@ -0,0 +1,399 @@
+//! Light nodes, started with `start-node --light --peer ADDRESS`, for machines that can't keep the whole chain. A light
+//! node only downloads the block headers from its full peer and verifies that they hash correctly and link up to the
+//! genesis block. Balance queries are answered by getting the account's transactions with their Merkle proofs from the
+//! peer and checking every proof against the Merkle root of its block's header, so the peer can't make up
+//! transactions. It could still leave transactions out, which a light node can't tell from the headers alone.
+//!
+//! Full nodes serve light nodes with the `GetHeaders` and `GetAccountProof` operations, which are also used for
+//! proof files, see [super::proof].
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::client::Client;
+use crate::common::{self, Amount, GetHeadersOp, Operation, Request, RequestError, Response, PROTOCOL_VERSION};
+use crate::config::NodeConfig;
+use crate::fragment::{self, MAX_DATAGRAM_SIZE};
+use crate::keys;
+use crate::merkle::{verify_proof, ProofStep};
+use crate::vault::Vault;
+use tokio::net::UdpSocket;
+use tokio::sync::Notify;
+use tracing::{debug, info, info_span, warn};
+use super::{identity, load_genesis, BlockHeader, State, Transaction};
+
+/// Number of headers a light node requests from its peer at once
+pub const HEADER_BATCH: usize = 500;
+
+/// A block header with the hash of its block
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Header {
+    pub hash: String,
+    #[serde(flatten)]
+    header: BlockHeader,
+}
+
+impl Header {
+    pub fn height(&self) -> u64 {
+        self.header.height
+    }
+
+    /// Checks that the header hashes to its hash and follows the given header
+    ///
+    /// Returns: why the header is invalid
+    pub fn verify(&self, previous: &Header) -> Result<(), String> {
+        if self.header.height != previous.header.height + 1 {
+            return Err(format!("header {} has height {}, but follows height {}", self.hash, self.header.height, previous.header.height));
+        }
+        if self.header.previous_hash != previous.hash {
+            return Err(format!("header {} at height {} doesn't link to block {}", self.hash, self.header.height, previous.hash));
+        }
+        // Blocks hashed without their height and timestamp only come before all other blocks, see verify::verify_chain
+        let hash = self.header.hash();
+        let legacy = previous.hash == previous.header.legacy_hash() && self.hash == self.header.legacy_hash();
+        if self.hash != hash && !legacy {
+            return Err(format!("header {} at height {} hashes to {}", self.hash, self.header.height, hash));
+        }
+        Ok(())
+    }
+}
+
+/// The minted transactions of an account with the proofs that they are included in their blocks
+#[derive(Serialize, Deserialize)]
+pub struct AccountProof {
+    pub account_id: String,
+    pub transactions: Vec<ProvenTransaction>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ProvenTransaction {
+    // Height of the block the transaction was minted in
+    pub height: usize,
+    pub transaction: Transaction,
+    // Proof that the transaction ID is included in the Merkle root of the block
+    pub proof: Vec<ProofStep>,
+}
+
+impl ProvenTransaction {
+    /// Checks the proof of the transaction against the header of its block
+    ///
+    /// Returns: why the proof is invalid
+    pub fn verify(&self, header: &Header) -> Result<(), String> {
+        let transaction_id = self.transaction.id();
+        if !verify_proof(&transaction_id, &self.proof, &header.header.merkle_root) {
+            return Err(format!("transaction {} isn't included in block {} at height {}", transaction_id, header.hash, self.height));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the balance the given transactions add up to for an account
+pub fn balance_of<'a>(account_id: &str, transactions: impl Iterator<Item = &'a Transaction>) -> Amount {
+    let mut balance = Amount::ZERO;
+    for transaction in transactions {
+        if transaction.from_account_id.as_deref() == Some(account_id) {
+            balance -= transaction.native_amount() + transaction.fee;
+        }
+        if transaction.to_account_id == account_id {
+            balance += transaction.native_amount();
+        }
+        for (giver, receiver, amount) in transaction.native_legs() {
+            if giver == account_id {
+                balance -= amount;
+            }
+            if receiver == account_id {
+                balance += amount;
+            }
+        }
+    }
+    balance
+}
+
+/// Gets the headers of the blocks from a height onwards as JSON, as many as fit into the response
+pub fn get_headers_response(state: &State, headers_info: &GetHeadersOp, max_length: usize) -> String {
+    let ledger = state.ledger.read().unwrap();
+    let mut headers = Vec::new();
+    // Length of the JSON array so far, with its brackets and the commas between the headers
+    let mut length = 2;
+    for block in ledger.iter().skip(headers_info.from_height).take(headers_info.count) {
+        let header = serde_json::to_string(&Header { hash: block.hash.clone(), header: block.header.clone() }).unwrap();
+        if length + header.len() + 1 > max_length {
+            break;
+        }
+        length += header.len() + 1;
+        headers.push(header);
+    }
+    format!("[{}]", headers.join(","))
+}
+
+/// Gets the minted transactions of an account with their Merkle proofs as JSON
+///
+/// Returns: the proof of the account, or a user-facing error if the node pruned some of its transactions
+pub fn get_account_proof_response(state: &State, account_id: &str) -> Result<String, String> {
+    let ledger = state.ledger.read().unwrap();
+    if let Some(snapshot) = state.snapshots.lock().unwrap().pruned() {
+        return Err(format!("❌ Node {} pruned the transactions before the snapshot at height {}, so it can't prove the balance of an account.", state.node_id, snapshot.height));
+    }
+    let mut transactions = Vec::new();
+    for &(height, index) in state.index.lock().unwrap().of_account(account_id) {
+        let block = &ledger[height];
+        transactions.push(ProvenTransaction {
+            height,
+            transaction: block.transactions[index].clone(),
+            proof: block.transaction_proof(index).unwrap(),
+        });
+    }
+    Ok(serde_json::to_string(&AccountProof { account_id: account_id.to_string(), transactions }).unwrap())
+}
+
+/// Gets a minted transaction with its Merkle proof as JSON
+///
+/// Returns: the proven transaction, or a user-facing error if it wasn't minted
+pub fn get_transaction_proof_response(state: &State, transaction_id: &str) -> Result<String, String> {
+    let ledger = state.ledger.read().unwrap();
+    if let Some((height, index)) = state.locate_transaction(transaction_id) {
+        let block = &ledger[height];
+        let proven = ProvenTransaction {
+            height,
+            transaction: block.transactions[index].clone(),
+            proof: block.transaction_proof(index).unwrap(),
+        };
+        return Ok(serde_json::to_string(&proven).unwrap());
+    }
+    Err(format!("❌ Transaction {} isn't minted in any block.", transaction_id))
+}
+
+/// State of a light node
+struct LightState {
+    node_id: String,
+    chain_id: String,
+    // Address of the full node the headers and proofs are requested from
+    peer: String,
+    // Verified headers of the peer's chain, starting with the genesis block
+    headers: RwLock<Vec<Header>>,
+    // Set once the node starts shutting down
+    shutting_down: AtomicBool,
+    // Wakes up the request loop once the node starts shutting down
+    shutdown_requested: Notify,
+    started: std::time::Instant,
+}
+
+impl LightState {
+    /// Sends a request to the peer
+    ///
+    /// Returns: the message of the peer's response, or why the request failed
+    fn request_peer(&self, request: &Request) -> Result<String, String> {
+        let mut client = Client::new(self.peer.clone(), Duration::from_secs(1), 2).quiet();
+        client.send(request)?.result
+    }
+
+    /// Downloads and verifies the headers the peer has beyond the tip. If the tip isn't on the peer's chain anymore,
+    /// e.g. because the peer switched to a fork, the headers are rolled back until they are.
+    ///
+    /// Returns: the number of headers added, or why syncing failed
+    fn sync_headers(&self) -> Result<usize, String> {
+        let mut added = 0;
+        loop {
+            // The tip is requested again, to check that it is still on the peer's chain
+            let tip_height = self.headers.read().unwrap().len() - 1;
+            let request = Request::new_get_headers_request(self.node_id.clone(), tip_height, HEADER_BATCH);
+            let received: Vec<Header> = serde_json::from_str(&self.request_peer(&request)?).map_err(|e| format!("Invalid headers from peer {}: {}", self.peer, e))?;
+
+            let mut headers = self.headers.write().unwrap();
+            match received.first() {
+                // The peer's chain is shorter, it may catch up again
+                None => return Ok(added),
+                Some(first) if first.hash != headers[tip_height].hash => {
+                    if tip_height == 0 {
+                        return Err(format!("Peer {} has genesis block {}, but the genesis configuration gives {}.", self.peer, first.hash, headers[0].hash));
+                    }
+                    warn!(height = tip_height, hash = %headers[tip_height].hash, "Rolling back a header that isn't on the peer's chain.");
+                    headers.pop();
+                    added = added.saturating_sub(1);
+                    continue;
+                }
+                Some(_) => {}
+            }
+            for header in &received[1..] {
+                header.verify(headers.last().unwrap()).map_err(|e| format!("Invalid headers from peer {}: {}", self.peer, e))?;
+                headers.push(header.clone());
+                added += 1;
+            }
+            if received.len() < HEADER_BATCH {
+                return Ok(added);
+            }
+        }
+    }
+
+    /// Gets the balance of an account at the tip of the headers, from its transactions proven by the peer
+    ///
+    /// Returns: a user-facing message with the balance, or explaining why it couldn't be verified
+    fn get_balance(&self, account_id: &str) -> Result<String, String> {
+        let request = Request::new_get_account_proof_request(self.node_id.clone(), account_id.to_string());
+        let response = self.request_peer(&request).map_err(|e| format!("❌ Failed to get the proof of account {} from peer {}: {}", account_id, self.peer, e))?;
+        let proof: AccountProof = serde_json::from_str(&response).map_err(|e| format!("❌ Invalid proof of account {} from peer {}: {}", account_id, self.peer, e))?;
+
+        let headers = self.headers.read().unwrap();
+        // Transactions in blocks the light node has no header of yet are left for the next sync
+        let proven: Vec<&ProvenTransaction> = proof.transactions.iter().filter(|proven| proven.height < headers.len()).collect();
+        for proven_transaction in &proven {
+            proven_transaction
+                .verify(&headers[proven_transaction.height])
+                .map_err(|e| format!("❌ Invalid proof of account {} from peer {}: {}.", account_id, self.peer, e))?;
+        }
+        let balance = balance_of(account_id, proven.iter().map(|proven| &proven.transaction));
+        Ok(format!("Account {} has a balance of {} at height {}, proven by {} transactions from peer {}.", account_id, balance, headers.len() - 1, proven.len(), self.peer))
+    }
+
+    fn status(&self) -> String {
+        let (height, tip_hash) = {
+            let headers = self.headers.read().unwrap();
+            (headers.len() - 1, headers.last().unwrap().hash.clone())
+        };
+        let uptime = self.started.elapsed().as_secs();
+        format!(
+            "Light node {} running ToyChain {} on chain {}:\n- Height: {}\n- Tip: {}\n- Peer: {}\n- Uptime: {}h {}m {}s",
+            self.node_id,
+            env!("CARGO_PKG_VERSION"),
+            self.chain_id,
+            height,
+            tip_hash,
+            self.peer,
+            uptime / 3600,
+            uptime / 60 % 60,
+            uptime % 60
+        )
+    }
+
+    fn request_shutdown(&self) {
+        if self.shutting_down.swap(true, Ordering::SeqCst) {
+            warn!("Shutdown requested again, exiting immediately.");
+            std::process::exit(1);
+        }
+        self.shutdown_requested.notify_one();
+    }
+}
+
+/// Runs a light node until it is shut down, syncing the headers from its peer every mint interval and answering
+/// status and balance requests. Other requests have to be sent to a full node.
+///
+/// Returns: why the light node couldn't be started, e.g. without a peer or with its port in use
+pub async fn run_light_node(config: NodeConfig) -> Result<(), String> {
+    let peer = config.peer.clone().ok_or("A light node needs a full node to follow, use --peer.")?;
+    let genesis = load_genesis(config.genesis.as_deref())?;
+
+    let addr = format!("{}:{}", config.bind, config.port);
+    let socket = UdpSocket::bind(&addr)
+        .await
+        .map_err(|e| format!("Failed to bind to address {}. Make sure PORT {} is not in use. Error: {}", addr, config.port, e))?;
+    let socket = Arc::new(socket);
+    info!("Light node started on {}, following peer {}.", addr, peer);
+
+    // A light node only keeps its identity key in the data directory, encrypted like the files of a full node
+    let vault = match &config.data_dir {
+        Some(data_dir) => Vault::unlock(data_dir, config.secret.as_ref(), true)?,
+        None => None,
+    };
+    let identity = identity::load(config.data_dir.as_deref(), vault.as_ref())?;
+
+    let genesis_block = genesis.to_block();
+    let state = Arc::new(LightState {
+        node_id: keys::public_key_hex(&identity),
+        chain_id: genesis.chain_id.clone(),
+        peer,
+        headers: RwLock::new(vec![Header { hash: genesis_block.hash, header: genesis_block.header }]),
+        shutting_down: AtomicBool::new(false),
+        shutdown_requested: Notify::new(),
+        started: std::time::Instant::now(),
+    });
+
+    tokio::spawn(sync_headers(state.clone(), config.mint_interval));
+    tokio::spawn(watch_shutdown_signal(state.clone()));
+
+    let mut buf = [0u8; MAX_DATAGRAM_SIZE];
+    loop {
+        tokio::select! {
+            _ = state.shutdown_requested.notified() => break,
+            received = socket.recv_from(&mut buf) => match received {
+                Ok((amt, src)) => {
+                    tokio::spawn(handle_datagram(state.clone(), socket.clone(), buf[..amt].to_vec(), src));
+                }
+                Err(e) => warn!("Failed to receive request: {}", e),
+            },
+        }
+    }
+    info!("Light node stopped.");
+    Ok(())
+}
+
+/// Syncs the headers from the peer every interval
+async fn sync_headers(state: Arc<LightState>, interval: u64) {
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+    loop {
+        ticker.tick().await;
+        let shared_state = state.clone();
+        match tokio::task::spawn_blocking(move || shared_state.sync_headers()).await.unwrap() {
+            Ok(0) => {}
+            Ok(added) => info!(added, height = state.headers.read().unwrap().len() - 1, "Synced headers from peer."),
+            Err(e) => warn!("Failed to sync headers: {}", e),
+        }
+    }
+}
+
+/// Requests a shutdown whenever the node receives SIGINT (Ctrl-C)
+async fn watch_shutdown_signal(state: Arc<LightState>) {
+    loop {
+        tokio::signal::ctrl_c().await.expect("Failed to set the shutdown signal handler.");
+        state.request_shutdown();
+    }
+}
+
+/// Handles a plaintext request datagram and sends the response back to the client. Light nodes don't take encrypted or
+/// fragmented requests, which no request they answer needs.
+async fn handle_datagram(state: Arc<LightState>, socket: Arc<UdpSocket>, bytes: Vec<u8>, src: SocketAddr) {
+    if fragment::is_fragment(&bytes) || crate::noise::is_noise_packet(&bytes) {
+        debug!("Dropped a fragmented or encrypted request from {}, light nodes only take plaintext requests.", src);
+        return;
+    }
+    let request = match Request::decode(&bytes) {
+        Ok(request) => request,
+        Err(RequestError::Malformed(e)) => {
+            warn!("Failed to deserialize request: {} - from: {}", e, src);
+            return;
+        }
+        Err(e @ RequestError::UnsupportedVersion(_)) => {
+            let response = Response::new(PROTOCOL_VERSION, None, Err(e.to_string())).encode();
+            let _ = socket.send_to(&response, src).await;
+            return;
+        }
+    };
+
+    let (version, request_id) = (request.version, request.request_id);
+    let (format, accepted) = common::response_encoding(&bytes);
+    let result = tokio::task::spawn_blocking(move || {
+        let _span = info_span!("request", operation = request.operation.name(), from = %request.from_node, %src).entered();
+        info!("Received request: {:?}", request.operation);
+        match request.operation {
+            Operation::Status => Ok(state.status()),
+            Operation::GetFunds(get_info) => state.get_balance(&get_info.account_id),
+            Operation::Shutdown if !src.ip().is_loopback() => Err("❌ Shutdown requests are only accepted from localhost.".to_string()),
+            Operation::Shutdown => {
+                state.request_shutdown();
+                Ok("🛑 Server is shutting down.".to_string())
+            }
+            _ => Err(format!("❌ Node {} is a light node, it only answers status and balance requests. Send other requests to a full node, e.g. its peer {}.", state.node_id, state.peer)),
+        }
+    })
+    .await
+    .unwrap();
+
+    let response = Response::new(version, request_id, result).encode_as(format, accepted);
+    for datagram in fragment::split(&response, common::random_id()) {
+        if let Err(e) = socket.send_to(&datagram, src).await {
+            warn!("Failed to send response: {}", e);
+            return;
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Discovery of peers on the local network with multicast DNS. A node started with `--mdns` announces itself as an
+//! instance of the `_toychain._udp.local` service, with the port it listens on and the ID of its chain, and asks for
+//! the other instances when it starts. It answers the questions of other nodes and announces itself again every
+//! announce interval, so nodes on the same network find each other without bootstrap peers. The nodes it discovers are
+//! added to its peers, at the address the announcement came from with the announced port, if they run the same chain.
+//! Only what discovery needs of mDNS is implemented, the node doesn't announce host names or answer other questions.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use socket2::{Domain, Protocol, Socket, Type};
+use tracing::{debug, error, info, warn};
+use crate::common::random_id;
+use super::State;
+
+/// The multicast group and port of mDNS
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+const MDNS_PORT: u16 = 5353;
+
+/// Name of the service ToyChain nodes announce themselves as instances of
+const SERVICE: &str = "_toychain._udp.local";
+
+/// Interval at which the node announces itself again
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Shortest time between two announcements, so questions of many nodes at once don't flood the network
+const MIN_ANNOUNCE_GAP: Duration = Duration::from_secs(1);
+
+/// Seconds the announced records are valid for
+const RECORD_TTL: u32 = 120;
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+// Set in the class of records that only this node announces, so other hosts replace cached ones
+const CACHE_FLUSH: u16 = 0x8000;
+
+/// What a node announces about itself
+struct Announcement {
+    // Name of the node's instance of the service, unique per run so a node recognizes its own announcements
+    instance: String,
+    port: u16,
+    chain_id: String,
+}
+
+/// A record of an mDNS message, what discovery needs of it
+struct Record {
+    name: String,
+    kind: u16,
+    ttl: u32,
+    // The name a PTR record points to, the port of a SRV record or the strings of a TXT record
+    data: RecordData,
+}
+
+enum RecordData {
+    Pointer(String),
+    Service(u16),
+    Text(Vec<String>),
+    Other,
+}
+
+/// An mDNS message, with the names of the questions it asks and its records
+struct Message {
+    questions: Vec<(String, u16)>,
+    records: Vec<Record>,
+    response: bool,
+}
+
+/// Announces the node on the local network and adds the nodes it discovers to its peers, until the node shuts down
+///
+/// # Arguments
+///
+/// * `state`: the state of the node
+/// * `interface`: address of the network interface to discover peers on, any interface if unspecified
+/// * `port`: the port the node listens on, which other nodes reach it at
+pub fn serve(state: Arc<State>, interface: Ipv4Addr, port: u16) {
+    let socket = match bind(interface) {
+        Ok(socket) => socket,
+        Err(e) => {
+            error!("Failed to start mDNS discovery on port {}: {}", MDNS_PORT, e);
+            return;
+        }
+    };
+    let own = Announcement {
+        instance: instance_name(&state.node_id),
+        port,
+        chain_id: state.genesis.chain_id.clone(),
+    };
+    info!(instance = %own.instance, "Discovering peers on the local network with mDNS.");
+
+    let group = SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT));
+    let send = |message: &[u8]| {
+        if let Err(e) = socket.send_to(message, group) {
+            warn!("Failed to send mDNS message: {}", e);
+        }
+    };
+    send(&encode_query());
+    send(&encode_announcement(&own));
+    let mut announced = Instant::now();
+
+    let mut buf = [0u8; 9000];
+    while !state.shutting_down.load(std::sync::atomic::Ordering::SeqCst) {
+        if announced.elapsed() >= ANNOUNCE_INTERVAL {
+            send(&encode_announcement(&own));
+            announced = Instant::now();
+        }
+        let (length, src) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                warn!("Failed to receive mDNS message: {}", e);
+                continue;
+            }
+        };
+        let Some(message) = decode(&buf[..length]) else {
+            debug!(%src, "Ignoring invalid mDNS message.");
+            continue;
+        };
+        if message.response {
+            for address in discovered(&message, &own, src) {
+                if state.peers.lock().unwrap().learn(std::slice::from_ref(&address)) > 0 {
+                    info!(peer = %address, "Discovered peer on the local network.");
+                }
+            }
+        } else if message.questions.iter().any(|(name, kind)| *kind == TYPE_PTR && name.eq_ignore_ascii_case(SERVICE)) && announced.elapsed() >= MIN_ANNOUNCE_GAP {
+            send(&encode_announcement(&own));
+            announced = Instant::now();
+        }
+    }
+}
+
+/// Binds the mDNS port, shared with other mDNS responders on the machine, and joins the mDNS group
+fn bind(interface: Ipv4Addr) -> std::io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, MDNS_PORT)).into())?;
+    socket.join_multicast_v4(&MDNS_GROUP, &interface)?;
+    // Nodes on the same machine discover each other as well
+    socket.set_multicast_loop_v4(true)?;
+    // Wakes up regularly to announce the node and notice a shutdown
+    socket.set_read_timeout(Some(MIN_ANNOUNCE_GAP))?;
+    Ok(socket.into())
+}
+
+/// Returns the name of the node's instance of the service, its node ID with a random suffix as a single label
+fn instance_name(node_id: &str) -> String {
+    let mut label: String = node_id.chars().map(|c| if c == '.' { '-' } else { c }).collect();
+    while label.len() > 50 {
+        label.pop();
+    }
+    format!("{}-{:08x}.{}", label, random_id() as u32, SERVICE)
+}
+
+/// Returns the addresses of the nodes of the same chain announced in a response, except for this node
+fn discovered(message: &Message, own: &Announcement, src: SocketAddr) -> Vec<String> {
+    let mut ports = HashMap::new();
+    let mut chains = HashMap::new();
+    for record in message.records.iter().filter(|record| record.ttl > 0) {
+        match &record.data {
+            RecordData::Service(port) => {
+                ports.insert(record.name.to_ascii_lowercase(), *port);
+            }
+            RecordData::Text(strings) => {
+                if let Some(chain_id) = strings.iter().find_map(|string| string.strip_prefix("chain=")) {
+                    chains.insert(record.name.to_ascii_lowercase(), chain_id.to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    let instances = message.records.iter().filter_map(|record| match &record.data {
+        RecordData::Pointer(instance) if record.kind == TYPE_PTR && record.ttl > 0 && record.name.eq_ignore_ascii_case(SERVICE) => Some(instance.to_ascii_lowercase()),
+        _ => None,
+    });
+    instances
+        .filter(|instance| !instance.eq_ignore_ascii_case(&own.instance))
+        .filter(|instance| chains.get(instance) == Some(&own.chain_id))
+        .filter_map(|instance| ports.get(&instance).map(|port| SocketAddr::new(src.ip(), *port).to_string()))
+        .collect()
+}
+
+/// Encodes a question for the instances of the service
+fn encode_query() -> Vec<u8> {
+    let mut message = header(false, 1, 0);
+    write_name(&mut message, SERVICE);
+    message.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    message
+}
+
+/// Encodes the announcement of the node's instance: a PTR record from the service to the instance, a SRV record with
+/// the port and a TXT record with the chain ID
+fn encode_announcement(own: &Announcement) -> Vec<u8> {
+    let mut message = header(true, 0, 3);
+
+    let mut pointer = Vec::new();
+    write_name(&mut pointer, &own.instance);
+    write_record(&mut message, SERVICE, TYPE_PTR, CLASS_IN, &pointer);
+
+    let mut service = [0u16.to_be_bytes(), 0u16.to_be_bytes(), own.port.to_be_bytes()].concat();
+    let host = own.instance.split('.').next().unwrap_or_default();
+    write_name(&mut service, &format!("{}.local", host));
+    write_record(&mut message, &own.instance, TYPE_SRV, CLASS_IN | CACHE_FLUSH, &service);
+
+    let mut text = format!("chain={}", own.chain_id).into_bytes();
+    text.truncate(255);
+    let text = [&[text.len() as u8], text.as_slice()].concat();
+    write_record(&mut message, &own.instance, TYPE_TXT, CLASS_IN | CACHE_FLUSH, &text);
+    message
+}
+
+fn header(response: bool, questions: u16, answers: u16) -> Vec<u8> {
+    // Responses are authoritative, the ID is always 0 in mDNS
+    let flags: u16 = if response { 0x8400 } else { 0 };
+    [0u16, flags, questions, answers, 0, 0].iter().flat_map(|field| field.to_be_bytes()).collect()
+}
+
+fn write_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label);
+    }
+    buf.push(0);
+}
+
+fn write_record(buf: &mut Vec<u8>, name: &str, kind: u16, class: u16, data: &[u8]) {
+    write_name(buf, name);
+    buf.extend_from_slice(&kind.to_be_bytes());
+    buf.extend_from_slice(&class.to_be_bytes());
+    buf.extend_from_slice(&RECORD_TTL.to_be_bytes());
+    buf.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    buf.extend_from_slice(data);
+}
+
+/// Decodes an mDNS message, None if it is malformed
+fn decode(packet: &[u8]) -> Option<Message> {
+    let field = |offset: usize| packet.get(offset..offset + 2).map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]));
+    let response = field(2)? & 0x8000 != 0;
+    let question_count = field(4)?;
+    let record_count = field(6)? as usize + field(8)? as usize + field(10)? as usize;
+
+    let mut offset = 12;
+    let mut questions = Vec::new();
+    for _ in 0..question_count {
+        let (name, next) = read_name(packet, offset)?;
+        questions.push((name, field(next)?));
+        offset = next + 4;
+    }
+    let mut records = Vec::new();
+    for _ in 0..record_count {
+        let (name, next) = read_name(packet, offset)?;
+        let kind = field(next)?;
+        let ttl = u32::from_be_bytes(packet.get(next + 4..next + 8)?.try_into().ok()?);
+        let length = field(next + 8)? as usize;
+        let start = next + 10;
+        let rdata = packet.get(start..start + length)?;
+        let data = match kind {
+            TYPE_PTR => RecordData::Pointer(read_name(packet, start)?.0),
+            TYPE_SRV => RecordData::Service(field(start + 4)?),
+            TYPE_TXT => RecordData::Text(read_strings(rdata)),
+            _ => RecordData::Other,
+        };
+        records.push(Record { name, kind, ttl, data });
+        offset = start + length;
+    }
+    Some(Message { questions, records, response })
+}
+
+/// Reads a name at the given offset, following compression pointers
+///
+/// Returns: the name and the offset right after it
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds the pointers followed, so a message pointing in a loop is rejected
+    for _ in 0..64 {
+        let length = *packet.get(offset)? as usize;
+        match length {
+            0 => return Some((labels.join("."), end.unwrap_or(offset + 1))),
+            _ if length & 0xc0 == 0xc0 => {
+                let pointer = ((length & 0x3f) << 8) | *packet.get(offset + 1)? as usize;
+                end.get_or_insert(offset + 2);
+                offset = pointer;
+            }
+            _ if length > 63 => return None,
+            _ => {
+                let label = packet.get(offset + 1..offset + 1 + length)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                offset += 1 + length;
+            }
+        }
+    }
+    None
+}
+
+/// Reads the length prefixed strings of a TXT record
+fn read_strings(mut data: &[u8]) -> Vec<String> {
+    let mut strings = Vec::new();
+    while let Some((&length, rest)) = data.split_first() {
+        let Some(string) = rest.get(..length as usize) else { break };
+        strings.push(String::from_utf8_lossy(string).into_owned());
+        data = &rest[length as usize..];
+    }
+    strings
+}
@@ -0,0 +1,226 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use crate::common::Amount;
+use super::Transaction;
+
+/// A transaction waiting in the mempool to be minted
+struct PendingTransaction {
+    transaction: Transaction,
+    // The account (or node for account creations) that submitted the transaction, used for the per-sender limit
+    sender: String,
+    // Order in which transactions were added, lower is older
+    sequence: u64,
+}
+
+/// A transaction that can be taken next from the mempool, ordered by fee and then age
+struct Candidate {
+    fee: Amount,
+    sequence: u64,
+    // Index of the transaction in the mempool
+    index: usize,
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // Higher fees first, then lower (older) sequences first
+        self.fee.cmp(&other.fee).then(other.sequence.cmp(&self.sequence))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// Pool of transactions that were accepted but not minted yet. The minter takes the transactions with the highest fee
+/// first, and the oldest first among equal fees.
+pub struct Mempool {
+    pending: Vec<PendingTransaction>,
+    // Maximum number of transactions the mempool holds
+    max_size: usize,
+    // Maximum number of transactions a single sender can have in the mempool
+    max_per_sender: usize,
+    next_sequence: u64,
+    // Whether the last block taken from the mempool was full and left transactions behind
+    backed_up: bool,
+}
+
+impl Mempool {
+    pub fn new(max_size: usize, max_per_sender: usize) -> Mempool {
+        Mempool {
+            pending: Vec::new(),
+            max_size,
+            max_per_sender,
+            next_sequence: 0,
+            backed_up: false,
+        }
+    }
+
+    /// Returns who is considered the sender of a transaction: the from account, the account an escrow or cross-shard
+    /// settlement pays, so settlements don't share the limit of the escrow account, the frozen or unfrozen account, so
+    /// a freeze is minted after the transfers from the account accepted before it, or the node that created the account
+    fn sender_of(transaction: &Transaction) -> String {
+        match (&transaction.from_account_id, transaction.freezes()) {
+            (Some(_), _) if transaction.settles_escrow().is_some() || transaction.settles_cross_shard().is_some() => transaction.to_account_id.clone(),
+            (Some(from_account_id), _) => from_account_id.clone(),
+            (None, Some(_)) => transaction.to_account_id.clone(),
+            (None, None) => transaction.node_id.clone(),
+        }
+    }
+
+    /// Adds transactions to the mempool, either all of them or none if they don't fit into the mempool or the share of
+    /// one of their senders
+    ///
+    /// Returns: a user-facing error if the transactions were rejected
+    pub fn add_all(&mut self, transactions: Vec<Transaction>) -> Result<(), String> {
+        if self.pending.len() + transactions.len() > self.max_size {
+            return Err("❌ The mempool is full, try again after the next block is minted.".to_string());
+        }
+
+        let mut added: HashMap<String, usize> = HashMap::new();
+        for transaction in &transactions {
+            *added.entry(Mempool::sender_of(transaction)).or_default() += 1;
+        }
+        for (sender, count) in &added {
+            let pending = self.pending.iter().filter(|pending| &pending.sender == sender).count();
+            if pending + count > self.max_per_sender {
+                return Err(format!("❌ {} already has {} pending transactions, try again after the next block is minted.", sender, pending));
+            }
+        }
+
+        self.restore(transactions);
+        Ok(())
+    }
+
+    /// Puts transactions that were accepted before back into the mempool, e.g. those of blocks that were rolled back.
+    /// They were within the limits when they were accepted, so they are added even if the mempool is full now.
+    pub fn restore(&mut self, transactions: Vec<Transaction>) {
+        for transaction in transactions {
+            self.pending.push(PendingTransaction {
+                sender: Mempool::sender_of(&transaction),
+                transaction,
+                sequence: self.next_sequence,
+            });
+            self.next_sequence += 1;
+        }
+    }
+
+    /// Removes and returns up to `count` transactions for the next block, highest fee first and oldest first among
+    /// equal fees. Transactions of the same sender are always taken in the order they were added, so that a transfer
+    /// is never minted before an earlier transfer of the same account (which would break its nonce order). With a
+    /// maximum number of bytes, a transaction that doesn't fit into what is left is skipped together with the later
+    /// transactions of its sender, which stay in the mempool for the next blocks.
+    pub fn take_top(&mut self, count: usize, max_bytes: Option<usize>) -> Vec<Transaction> {
+        let order = {
+            // Queue the transactions of every sender in the order they were added, only the head of each queue can
+            // be taken next
+            let mut queues: HashMap<&str, VecDeque<usize>> = HashMap::new();
+            for (index, pending) in self.pending.iter().enumerate() {
+                queues.entry(pending.sender.as_str()).or_default().push_back(index);
+            }
+
+            let candidate = |index: usize| Candidate {
+                fee: self.pending[index].transaction.fee,
+                sequence: self.pending[index].sequence,
+                index,
+            };
+            let mut heads: BinaryHeap<Candidate> = queues.values_mut().filter_map(|queue| queue.pop_front()).map(candidate).collect();
+
+            let mut order = Vec::new();
+            let mut bytes_left = max_bytes.unwrap_or(usize::MAX);
+            while order.len() < count {
+                let Some(best) = heads.pop() else { break };
+                let size = self.pending[best.index].transaction.encoded_size();
+                if size > bytes_left {
+                    continue;
+                }
+                bytes_left -= size;
+                order.push(best.index);
+                if let Some(next) = queues.get_mut(self.pending[best.index].sender.as_str()).unwrap().pop_front() {
+                    heads.push(candidate(next));
+                }
+            }
+            order
+        };
+
+        let mut pending: Vec<Option<PendingTransaction>> = self.pending.drain(..).map(Some).collect();
+        let taken = order.iter().map(|&index| pending[index].take().unwrap().transaction).collect();
+        self.pending = pending.into_iter().flatten().collect();
+        self.backed_up = !self.pending.is_empty();
+        taken
+    }
+
+    /// Removes a transaction that wasn't minted yet
+    ///
+    /// Returns: the transaction, None if it isn't in the mempool
+    pub fn remove(&mut self, transaction_id: &str) -> Option<Transaction> {
+        let index = self.pending.iter().position(|pending| pending.transaction.id() == transaction_id)?;
+        Some(self.pending.remove(index).transaction)
+    }
+
+    /// Removes and returns all transactions in the order they were added
+    pub fn drain(&mut self) -> Vec<Transaction> {
+        self.pending.drain(..).map(|pending| pending.transaction).collect()
+    }
+
+    /// Returns the funds of the native coin the transactions from an account spend, their amounts and fees and what the
+    /// account gives in the swaps they accept
+    pub fn spent_by(&self, account_id: &str) -> Amount {
+        self.pending
+            .iter()
+            .filter(|pending| pending.transaction.from_account_id.as_deref() == Some(account_id))
+            .map(|pending| pending.transaction.native_amount() + pending.transaction.fee)
+            .chain(self.transactions().flat_map(Transaction::native_legs).filter(|(giver, _, _)| *giver == account_id).map(|(_, _, amount)| amount))
+            .sum()
+    }
+
+    /// Returns the funds of the native coin the transactions credit an account with once they are minted
+    ///
+    /// Returns: None if they add up to more than an amount can hold
+    pub fn received_by(&self, account_id: &str) -> Option<Amount> {
+        Amount::checked_sum(self.transactions().flat_map(Transaction::native_credits).filter(|(receiver, _)| *receiver == account_id).map(|(_, amount)| amount))
+    }
+
+    /// Returns the amount of a token the transfers from an account and the swaps it gives the token in spend
+    pub fn token_spent_by(&self, account_id: &str, token: &str) -> Amount {
+        self.pending
+            .iter()
+            .filter(|pending| pending.transaction.from_account_id.as_deref() == Some(account_id) && pending.transaction.token() == Some(token))
+            .map(|pending| pending.transaction.amount)
+            .chain(
+                self.transactions()
+                    .flat_map(|transaction| transaction.swap_legs().into_iter().flatten())
+                    .filter(|(giver, _, asset)| *giver == account_id && asset.token.as_deref() == Some(token))
+                    .map(|(_, _, asset)| asset.amount),
+            )
+            .sum()
+    }
+
+    /// Returns the pending transactions in the order they were added
+    pub fn transactions(&self) -> impl Iterator<Item = &Transaction> {
+        self.pending.iter().map(|pending| &pending.transaction)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns whether transactions are waiting for more than the next block: the last block taken from the mempool
+    /// was full and transactions are still pending
+    pub fn is_backed_up(&self) -> bool {
+        self.backed_up && !self.pending.is_empty()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
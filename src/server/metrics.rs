@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use super::health;
+use super::http::{self, HttpRequest, HttpResponse};
+use super::State;
+
+/// Upper bounds in seconds of the buckets of the duration histograms
+const DURATION_BUCKETS: [f64; 11] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0];
+
+/// A Prometheus histogram of durations with the buckets of `DURATION_BUCKETS`
+#[derive(Default)]
+struct Histogram {
+    // Number of observations per bucket, not cumulative. Observations above the last bucket are only counted in count.
+    buckets: [u64; DURATION_BUCKETS.len()],
+    // Sum of all observations in seconds
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|&bound| seconds <= bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    /// Writes the histogram in the Prometheus text format, with the given labels added to every sample
+    fn render(&self, output: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in DURATION_BUCKETS.iter().zip(self.buckets) {
+            cumulative += count;
+            writeln!(output, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, cumulative).unwrap();
+        }
+        writeln!(output, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count).unwrap();
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        writeln!(output, "{}_sum{} {}", name, labels, self.sum).unwrap();
+        writeln!(output, "{}_count{} {}", name, labels, self.count).unwrap();
+    }
+}
+
+/// Metrics the node collects about itself, served in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    // Duration of the requests processed, by transport (udp or rpc) and operation
+    requests: Mutex<BTreeMap<(&'static str, &'static str), Histogram>>,
+    // Duration of minting a block and adding it to the ledger
+    minting: Mutex<Histogram>,
+    // Number of requests rejected by the rate limiter, by transport
+    rate_limited: Mutex<BTreeMap<&'static str, u64>>,
+    // Number of requests rejected in permissioned mode, by transport
+    unauthorized: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+impl Metrics {
+    /// Records a processed request
+    ///
+    /// # Arguments
+    ///
+    /// * `transport`: how the request was received, `udp` or `rpc`
+    /// * `operation`: the operation of the request, from a fixed set so the number of series stays bounded
+    /// * `duration`: how long processing the request took
+    pub fn observe_request(&self, transport: &'static str, operation: &'static str, duration: Duration) {
+        self.requests.lock().unwrap().entry((transport, operation)).or_default().observe(duration);
+    }
+
+    /// Records a request rejected by the rate limiter
+    pub fn observe_rate_limited(&self, transport: &'static str) {
+        *self.rate_limited.lock().unwrap().entry(transport).or_default() += 1;
+    }
+
+    /// Records a request rejected in permissioned mode, see [super::allowlist]
+    pub fn observe_unauthorized(&self, transport: &'static str) {
+        *self.unauthorized.lock().unwrap().entry(transport).or_default() += 1;
+    }
+
+    /// Returns the number of requests processed so far, over all transports and operations
+    pub fn processed_requests(&self) -> u64 {
+        self.requests.lock().unwrap().values().map(|histogram| histogram.count).sum()
+    }
+
+    /// Records how long minting a block took
+    pub fn observe_minting(&self, duration: Duration) {
+        self.minting.lock().unwrap().observe(duration);
+    }
+}
+
+/// Starts the metrics endpoint on the given address, serving the metrics of the node at `/metrics` in the Prometheus
+/// text format and its health at `/healthz` and `/readyz`, see [super::health]
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    tracing::info!("Metrics endpoint started on http://{}/metrics.", addr);
+    http::serve(addr, move |request| handle(&state, request));
+}
+
+fn handle(state: &State, request: HttpRequest) -> HttpResponse {
+    if let Some(response) = health::http_response(state, &request.path) {
+        return response;
+    }
+    if request.path != "/metrics" {
+        return HttpResponse::text(404, "Not found.");
+    }
+    if request.method != "GET" {
+        return HttpResponse::text(405, "Metrics must be requested with GET.");
+    }
+
+    HttpResponse {
+        status: 200,
+        content_type: "text/plain; version=0.0.4; charset=utf-8",
+        body: render(state).into_bytes(),
+        stream: None,
+    }
+}
+
+/// Renders all metrics in the Prometheus text format
+fn render(state: &State) -> String {
+    let mut output = String::new();
+
+    let height = state.ledger.read().unwrap().len() - 1;
+    output.push_str("# HELP toychain_block_height Height of the last block of the ledger.\n# TYPE toychain_block_height gauge\n");
+    writeln!(output, "toychain_block_height {}", height).unwrap();
+
+    let pending_transactions = state.mempool.lock().unwrap().len();
+    output.push_str("# HELP toychain_pending_transactions Number of transactions waiting in the mempool.\n# TYPE toychain_pending_transactions gauge\n");
+    writeln!(output, "toychain_pending_transactions {}", pending_transactions).unwrap();
+
+    let requests = state.metrics.requests.lock().unwrap();
+    output.push_str("# HELP toychain_requests_total Number of requests processed.\n# TYPE toychain_requests_total counter\n");
+    for ((transport, operation), histogram) in requests.iter() {
+        writeln!(output, "toychain_requests_total{{transport=\"{}\",operation=\"{}\"}} {}", transport, operation, histogram.count).unwrap();
+    }
+    output.push_str("# HELP toychain_request_duration_seconds Time taken to process a request.\n# TYPE toychain_request_duration_seconds histogram\n");
+    for ((transport, operation), histogram) in requests.iter() {
+        let labels = format!("transport=\"{}\",operation=\"{}\"", transport, operation);
+        histogram.render(&mut output, "toychain_request_duration_seconds", &labels);
+    }
+    drop(requests);
+
+    output.push_str("# HELP toychain_rate_limited_total Number of requests rejected by the rate limiter.\n# TYPE toychain_rate_limited_total counter\n");
+    for (transport, count) in state.metrics.rate_limited.lock().unwrap().iter() {
+        writeln!(output, "toychain_rate_limited_total{{transport=\"{}\"}} {}", transport, count).unwrap();
+    }
+
+    output.push_str("# HELP toychain_unauthorized_total Number of requests rejected in permissioned mode.\n# TYPE toychain_unauthorized_total counter\n");
+    for (transport, count) in state.metrics.unauthorized.lock().unwrap().iter() {
+        writeln!(output, "toychain_unauthorized_total{{transport=\"{}\"}} {}", transport, count).unwrap();
+    }
+
+    output.push_str("# HELP toychain_minting_duration_seconds Time taken to mint a block and add it to the ledger.\n# TYPE toychain_minting_duration_seconds histogram\n");
+    state.metrics.minting.lock().unwrap().render(&mut output, "toychain_minting_duration_seconds", "");
+
+    output
+}
@@ -0,0 +1,95 @@
+//! Migrations of the data directory to the current format version. Every format version of the ledger after the first
+//! is a migration of the blocks of the version before it, which the blocks of ledger and archive files of earlier
+//! versions go through when they are read, see [super::storage]. A node migrates its data directory in place when it
+//! starts, and `migrate` migrates it without starting the node, or with `--dry-run` reports what it would migrate.
+
+use std::path::Path;
+use super::compaction::Archive;
+use super::storage::{Storage, FORMAT_VERSION};
+use crate::vault::Vault;
+
+/// A change of the format of the blocks, from the format version before it to its version
+pub struct Migration {
+    pub version: u16,
+    pub description: &'static str,
+}
+
+/// The migrations from format version 1 to the current format version, oldest first. A new format version has to add
+/// its migration here.
+pub const MIGRATIONS: [Migration; 20] = [
+    Migration { version: 2, description: "transfer memos" },
+    Migration { version: 3, description: "block rewards" },
+    Migration { version: 4, description: "transaction kinds, e.g. the creation of multisig accounts" },
+    Migration { version: 5, description: "account closures" },
+    Migration { version: 6, description: "account freezes" },
+    Migration { version: 7, description: "block heights and timestamps" },
+    Migration { version: 8, description: "stakes" },
+    Migration { version: 9, description: "account aliases" },
+    Migration { version: 10, description: "tokens" },
+    Migration { version: 11, description: "NFTs" },
+    Migration { version: 12, description: "escrows" },
+    Migration { version: 13, description: "payment schedules" },
+    Migration { version: 14, description: "swaps" },
+    Migration { version: 15, description: "contracts" },
+    Migration { version: 16, description: "governance" },
+    Migration { version: 17, description: "unstaking and staking rewards" },
+    Migration { version: 18, description: "block signatures and slashing" },
+    Migration { version: 19, description: "block signatures by the minting node" },
+    Migration { version: 20, description: "transaction expiry" },
+    Migration { version: 21, description: "cross-shard transfers" },
+];
+
+// The last migration is to the current format version, so a new format version can't be added without its migration
+const _: () = assert!(MIGRATIONS[MIGRATIONS.len() - 1].version == FORMAT_VERSION);
+
+/// Returns the migrations a file of the given format version goes through to the current format version
+pub fn pending(version: u16) -> impl Iterator<Item = &'static Migration> {
+    MIGRATIONS.iter().filter(move |migration| migration.version > version)
+}
+
+/// Migrates the ledger and archive files in a data directory to the current format version. Both files are parsed in
+/// full before anything is written, so a dry run fails on the same files the migration would fail on.
+///
+/// # Arguments
+///
+/// * `data_dir`: the data directory of the node, which must not be running
+/// * `dry_run`: only reports the migrations the files need, without changing them
+/// * `vault`: the vault the data directory is encrypted with, if it is
+///
+/// Returns: a user-facing message listing the migrations of every file, or why the data directory can't be migrated
+pub fn migrate(data_dir: &str, dry_run: bool, vault: Option<&Vault>) -> Result<String, String> {
+    if !Path::new(data_dir).is_dir() {
+        return Err(format!("❌ Data directory {} doesn't exist.", data_dir));
+    }
+    let ledger = Storage::format_version(data_dir, vault).map_err(|e| format!("❌ {}", e))?;
+    let (archive, archived) = Archive::read(Path::new(data_dir), vault).map_err(|e| format!("❌ {}", e))?;
+    let files = [("ledger.bin", ledger), ("archive.zst", archive.version().map(|version| (version, archived.len())))];
+
+    let mut report = Vec::new();
+    let mut outdated = false;
+    for (name, file) in files {
+        let Some((version, blocks)) = file else { continue };
+        if version == FORMAT_VERSION {
+            report.push(format!("{}: format version {} with {} blocks, up to date.", name, version, blocks));
+            continue;
+        }
+        outdated = true;
+        report.push(format!("{}: format version {} with {} blocks, migrating to {}:", name, version, blocks, FORMAT_VERSION));
+        report.extend(pending(version).map(|migration| format!("- {}: {}", migration.version, migration.description)));
+    }
+    if report.is_empty() {
+        return Ok(format!("Data directory {} has no ledger yet, nothing to migrate.", data_dir));
+    }
+
+    let summary = match (outdated, dry_run) {
+        (false, _) => format!("✅ Data directory {} is at format version {}, nothing to migrate.", data_dir, FORMAT_VERSION),
+        (true, true) => format!("Dry run, data directory {} was not changed. Run `migrate` without --dry-run, or start the node, to migrate it.", data_dir),
+        (true, false) => {
+            // Opening the storage rewrites the files of earlier versions in the current version
+            Storage::open(data_dir, vault).map_err(|e| format!("❌ {}", e))?;
+            format!("✅ Migrated data directory {} to format version {}.", data_dir, FORMAT_VERSION)
+        }
+    };
+    report.push(summary);
+    Ok(report.join("\n"))
+}
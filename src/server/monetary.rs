@@ -0,0 +1,114 @@
+//! Monetary policy of a chain, set by the parameters of its genesis configuration: the block reward newly issued to
+//! the minter of every block on top of its fees, halving every configured number of blocks, and the maximum supply
+//! nothing takes the supply beyond. Chains without a block reward only pay the fees to the minter.
+//!
+//! Besides the block rewards, funds are issued by creating accounts with a starting balance, by the faucet and by the
+//! staking rewards. A block issues them in that order up to what is left below the maximum supply: a block can't create
+//! accounts or pay from the faucet beyond it, its staking rewards are cut to what is left after those, and its block
+//! reward to what is left after the staking rewards. Credits of transfers from other shards aren't issued, they move
+//! funds issued on another shard.
+
+use std::collections::HashMap;
+use crate::common::Amount;
+use super::cross_shard::CROSS_SHARD_ACCOUNT;
+use super::escrow::ESCROW_ACCOUNT;
+use super::genesis::ChainParameters;
+use super::slashing::SLASHED_ACCOUNT;
+use super::stake::STAKE_ACCOUNT;
+use super::{Block, Transaction};
+
+/// Returns the coins issued by the block at the given height on top of its fees: the block reward halved once for
+/// every halving interval before the height, cut to what is left to issue
+///
+/// # Arguments
+///
+/// * `parameters`: the parameters of the chain
+/// * `height`: the height of the block
+/// * `issuable`: what is left to issue below the maximum supply after the other transactions of the block, None if the
+///   chain has no maximum supply
+pub fn block_subsidy(parameters: &ChainParameters, height: u64, issuable: Option<Amount>) -> Amount {
+    let reward = scheduled_reward(parameters, height);
+    issuable.map_or(reward, |issuable| reward.min(issuable))
+}
+
+/// Returns what is left to issue below the maximum supply after the given balances, None if there is no maximum supply
+pub fn issuable(max_supply: Option<Amount>, balances: &HashMap<String, Amount>) -> Option<Amount> {
+    max_supply.map(|max_supply| max_supply.checked_sub(total_supply(balances)).unwrap_or(Amount::ZERO).max(Amount::ZERO))
+}
+
+/// Returns what is left to issue after the given transactions, see [Transaction::issued], None if there is no maximum
+/// supply
+pub fn issuable_after<'a>(issuable: Option<Amount>, transactions: impl IntoIterator<Item = &'a Transaction>) -> Option<Amount> {
+    let issued = Amount::checked_sum(transactions.into_iter().map(Transaction::issued));
+    issuable.map(|issuable| issued.and_then(|issued| issuable.checked_sub(issued)).unwrap_or(Amount::ZERO).max(Amount::ZERO))
+}
+
+/// Checks that the transactions of a block issue at most what is left to issue before it
+///
+/// Returns: what is left for the block reward to issue, None if there is no maximum supply
+pub fn check_issuance(block: &Block, issuable: Option<Amount>) -> Result<Option<Amount>, String> {
+    let Some(issuable) = issuable else {
+        return Ok(None);
+    };
+    match Amount::checked_sum(block.transactions.iter().map(Transaction::issued)) {
+        Some(issued) if issued <= issuable => Ok(Some(issuable - issued)),
+        Some(issued) => Err(format!("it issues {} in its transactions, but only {} is left to issue below the maximum supply", issued, issuable)),
+        None => Err("it issues more in its transactions than an amount can hold".to_string()),
+    }
+}
+
+/// Returns the block reward of the schedule at the given height, before it is cut to the maximum supply
+pub fn scheduled_reward(parameters: &ChainParameters, height: u64) -> Amount {
+    if height == 0 {
+        return Amount::ZERO;
+    }
+    let halvings = parameters.halving_interval.map_or(0, |interval| height / interval);
+    Amount::from_minor_units(parameters.block_reward.minor_units().checked_shr(halvings.try_into().unwrap_or(u32::MAX)).unwrap_or(0))
+}
+
+/// Checks that the block reward of a block pays its fees plus the given subsidy. The minter may leave out the block
+/// reward, giving up the fees and the subsidy.
+pub fn check_reward(block: &Block, subsidy: Amount) -> Result<(), String> {
+    let Some((reward, transactions)) = block.transactions.split_last().filter(|(last, _)| last.is_reward()) else {
+        return Ok(());
+    };
+    let fees = Block::fees(transactions).ok_or("has fees that add up to more than an amount can hold")?;
+    if fees.checked_add(subsidy) != Some(reward.amount) {
+        return Err(format!("pays a block reward of {} but the fees of the block are {} and its subsidy is {}", reward.amount, fees, subsidy));
+    }
+    Ok(())
+}
+
+/// Returns all funds on the chain, including the staked, escrowed and slashed ones, or the largest amount if they add
+/// up to more than an amount can hold
+pub fn total_supply(balances: &HashMap<String, Amount>) -> Amount {
+    Amount::checked_sum(balances.values().copied()).unwrap_or(Amount::MAX)
+}
+
+/// How the supply of a chain is split up
+pub struct Supply {
+    pub total: Amount,
+    pub staked: Amount,
+    pub escrowed: Amount,
+    pub slashed: Amount,
+    // Locked for transfers to other shards or moved to them
+    pub cross_shard: Amount,
+}
+
+impl Supply {
+    pub fn of(balances: &HashMap<String, Amount>) -> Supply {
+        let balance = |account_id: &str| balances.get(account_id).copied().unwrap_or_default();
+        Supply {
+            total: total_supply(balances),
+            staked: balance(STAKE_ACCOUNT),
+            escrowed: balance(ESCROW_ACCOUNT),
+            slashed: balance(SLASHED_ACCOUNT),
+            cross_shard: balance(CROSS_SHARD_ACCOUNT),
+        }
+    }
+
+    /// Returns the funds the accounts can spend, the ones that aren't staked, escrowed, slashed or with other shards
+    pub fn circulating(&self) -> Amount {
+        self.total - self.staked - self.escrowed - self.slashed - self.cross_shard
+    }
+}
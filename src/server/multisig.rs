@@ -0,0 +1,109 @@
+//! Multisig accounts, whose transfers have to be approved by a number of the account's keys. A transfer from a multisig
+//! account is validated like any other transfer, but waits here instead of in the mempool until enough keys approved
+//! it with an `ApproveTransfer` request.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::common::FundTransferOp;
+use crate::keys;
+use crate::noise::to_hex;
+use super::Transaction;
+
+/// Maximum number of keys of a multisig account
+const MAX_KEYS: usize = 16;
+
+/// Time a transfer waits for approvals before it is dropped
+const APPROVAL_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Number of transfers waiting for approvals at most
+const MAX_PENDING_TRANSFERS: usize = 10000;
+
+/// The keys of a multisig account and how many of them have to approve a transfer. Recorded on-chain by the
+/// transaction creating the account, so every node restores the same accounts.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct MultisigPolicy {
+    // Hex encoded Ed25519 public keys, lowercase
+    pub public_keys: Vec<String>,
+    pub threshold: usize,
+}
+
+impl MultisigPolicy {
+    /// Returns the policy for the given keys, with the keys normalized to lowercase hex
+    ///
+    /// Returns: why the keys or threshold are invalid
+    pub fn new(public_keys: &[String], threshold: usize) -> Result<MultisigPolicy, String> {
+        let public_keys = public_keys
+            .iter()
+            .map(|public_key| match keys::parse_public_key(public_key) {
+                Ok(key) => Ok(to_hex(key.as_bytes())),
+                Err(_) => Err(format!("{} isn't a hex encoded Ed25519 public key", public_key)),
+            })
+            .collect::<Result<Vec<String>, String>>()?;
+        let policy = MultisigPolicy { public_keys, threshold };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    /// Checks that the policy has distinct keys and a threshold that can be met
+    ///
+    /// Returns: why the policy is invalid
+    pub fn validate(&self) -> Result<(), String> {
+        if self.public_keys.is_empty() || self.public_keys.len() > MAX_KEYS {
+            return Err(format!("a multisig account has between 1 and {} keys, not {}", MAX_KEYS, self.public_keys.len()));
+        }
+        if self.threshold == 0 || self.threshold > self.public_keys.len() {
+            return Err(format!("the threshold has to be between 1 and the number of keys {}, not {}", self.public_keys.len(), self.threshold));
+        }
+        if let Some((index, key)) = self.public_keys.iter().enumerate().find(|(index, key)| self.public_keys[..*index].contains(key)) {
+            return Err(format!("key {} is given more than once (again as key {})", key, index + 1));
+        }
+        Ok(())
+    }
+}
+
+/// A transfer from a multisig account waiting for approvals
+pub struct PendingTransfer {
+    pub transfer: FundTransferOp,
+    pub transaction: Transaction,
+    // Public keys that approved the transfer so far
+    pub approved_by: Vec<String>,
+    created: Instant,
+}
+
+/// Transfers from multisig accounts waiting for approvals, by transaction ID
+#[derive(Default)]
+pub struct PendingApprovals {
+    transfers: HashMap<String, PendingTransfer>,
+}
+
+impl PendingApprovals {
+    /// Adds a transfer waiting for approvals, dropping transfers that weren't approved in time
+    ///
+    /// Returns: a user-facing error if too many transfers are waiting for approvals
+    pub fn add(&mut self, transaction_id: String, transfer: FundTransferOp, transaction: Transaction) -> Result<(), String> {
+        let now = Instant::now();
+        self.transfers.retain(|_, pending| now.duration_since(pending.created) < APPROVAL_TIMEOUT);
+        if self.transfers.len() >= MAX_PENDING_TRANSFERS {
+            return Err("❌ Too many transfers are waiting for approvals, try again later.".to_string());
+        }
+
+        self.transfers.insert(transaction_id, PendingTransfer {
+            transfer,
+            transaction,
+            approved_by: Vec::new(),
+            created: now,
+        });
+        Ok(())
+    }
+
+    /// Gets a transfer waiting for approvals, unless it wasn't approved in time
+    pub fn get_mut(&mut self, transaction_id: &str) -> Option<&mut PendingTransfer> {
+        self.transfers.get_mut(transaction_id).filter(|pending| pending.created.elapsed() < APPROVAL_TIMEOUT)
+    }
+
+    /// Removes a transfer once it was approved
+    pub fn remove(&mut self, transaction_id: &str) -> Option<PendingTransfer> {
+        self.transfers.remove(transaction_id)
+    }
+}
@@ -0,0 +1,57 @@
+//! Non-fungible tokens (NFTs). An account mints an NFT with `mint-nft`, which gives it a unique ID and the metadata it
+//! was minted with, e.g. the URL of an image, and can transfer it to another account with `transfer-nft`. Every NFT has
+//! exactly one owner, and its metadata never changes.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::merkle::sha256_hex;
+
+/// Length of the ID of an NFT in hex characters
+const NFT_ID_LENGTH: usize = 16;
+
+/// An NFT minted on the chain
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Nft {
+    pub owner: String,
+    // Account that minted the NFT
+    pub minter: String,
+    pub metadata: String,
+}
+
+/// The NFTs minted on the chain, by ID
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Nfts {
+    nfts: HashMap<String, Nft>,
+}
+
+/// Returns the ID of the NFT minted by an account with a nonce. Nonces are only used once per account, so every NFT
+/// gets a different ID, which any node can derive from the minting transaction.
+pub fn nft_id(account_id: &str, nonce: u64) -> String {
+    let hash = sha256_hex(&bincode::serialize(&(account_id, nonce)).unwrap());
+    hash[..NFT_ID_LENGTH].to_string()
+}
+
+impl Nfts {
+    pub fn get(&self, nft_id: &str) -> Option<&Nft> {
+        self.nfts.get(nft_id)
+    }
+
+    /// Returns the NFTs owned by an account, sorted by ID
+    pub fn owned_by(&self, account_id: &str) -> Vec<(&String, &Nft)> {
+        let mut owned: Vec<(&String, &Nft)> = self.nfts.iter().filter(|(_, nft)| nft.owner == account_id).collect();
+        owned.sort_by(|a, b| a.0.cmp(b.0));
+        owned
+    }
+
+    pub fn mint(&mut self, nft_id: &str, minter: &str, metadata: &str) {
+        let nft = Nft { owner: minter.to_string(), minter: minter.to_string(), metadata: metadata.to_string() };
+        self.nfts.insert(nft_id.to_string(), nft);
+    }
+
+    /// Makes an account the owner of an NFT. The previous owner isn't checked, that is up to the caller.
+    pub fn transfer(&mut self, nft_id: &str, to_account_id: &str) {
+        if let Some(nft) = self.nfts.get_mut(nft_id) {
+            nft.owner = to_account_id.to_string();
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Blocks received before their parent. A block submitted by another node, or a run of blocks submitted out of order,
+//! may build on a block the node doesn't know yet. Instead of dropping it, the node keeps it here as an orphan, asks
+//! its peers for the missing parent every sync interval (see [super::sync]), and connects the orphan once its parent is
+//! on the main chain or a fork, see `State::receive_blocks`.
+
+use std::collections::{HashMap, VecDeque};
+use super::Block;
+
+/// Number of orphan blocks kept at most, the oldest are forgotten first
+const MAX_ORPHAN_BLOCKS: usize = 100;
+
+/// Orphan blocks by hash
+#[derive(Default)]
+pub struct Orphans {
+    blocks: HashMap<String, Block>,
+    // Hashes of the blocks in the order they were added, oldest first
+    order: VecDeque<String>,
+}
+
+impl Orphans {
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// Adds a block, forgetting the oldest blocks while there are too many
+    pub fn add(&mut self, block: Block) {
+        if self.contains(&block.hash) {
+            return;
+        }
+        while self.blocks.len() >= MAX_ORPHAN_BLOCKS {
+            let Some(oldest) = self.order.pop_front() else { break };
+            self.blocks.remove(&oldest);
+        }
+        self.order.push_back(block.hash.clone());
+        self.blocks.insert(block.hash.clone(), block);
+    }
+
+    fn remove(&mut self, hash: &str) -> Option<Block> {
+        let block = self.blocks.remove(hash)?;
+        self.order.retain(|known| known != hash);
+        Some(block)
+    }
+
+    /// Removes an orphan whose parent is known now, together with the run of orphans building on it
+    ///
+    /// Returns: the blocks of the run, oldest first, empty if no orphan has a known parent
+    pub fn take_connectable(&mut self, is_known: impl Fn(&str) -> bool) -> Vec<Block> {
+        let Some(hash) = self.order.iter().find(|hash| is_known(&self.blocks[*hash].header.previous_hash)).cloned() else {
+            return Vec::new();
+        };
+        let mut run = vec![self.remove(&hash).unwrap()];
+        while let Some(child) = self.order.iter().find(|child| self.blocks[*child].header.previous_hash == run.last().unwrap().hash).cloned() {
+            run.push(self.remove(&child).unwrap());
+        }
+        run
+    }
+
+    /// Returns the hash and height of the parents the orphans are missing, the parents that aren't orphans themselves
+    pub fn missing_parents(&self) -> Vec<(String, u64)> {
+        let mut missing: Vec<(String, u64)> = self
+            .blocks
+            .values()
+            .filter(|block| !self.contains(&block.header.previous_hash))
+            .map(|block| (block.header.previous_hash.clone(), block.header.height.saturating_sub(1)))
+            .collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+}
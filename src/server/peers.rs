@@ -0,0 +1,227 @@
+//! Peer discovery. A node started with `--bootstrap ADDRESS` asks the nodes at those addresses for the peers they know,
+//! and every peer exchange interval it exchanges its peers with every peer it knows, so nodes find each other through
+//! any node they share and the mesh heals itself as nodes come and go. A peer that answers an exchange, or sends one,
+//! is connected. One that doesn't answer several exchanges in a row is disconnected, but still tried, and forgotten
+//! once it didn't answer for long, unless it is a bootstrap peer.
+
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::client::Client;
+use crate::common::{ExchangePeersOp, Request};
+use tracing::{debug, info, warn};
+use super::State;
+
+/// Interval at which the node exchanges its peers with every peer it knows
+pub const PEER_EXCHANGE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of exchanges in a row a peer doesn't answer after which it is disconnected
+const DISCONNECT_AFTER: u32 = 3;
+
+/// Number of exchanges in a row a peer doesn't answer after which it is forgotten, unless it is a bootstrap peer
+const FORGET_AFTER: u32 = 30;
+
+/// Number of peers a node knows at most, peers learned beyond it are ignored
+const MAX_PEERS: usize = 100;
+
+/// The response to a peer exchange
+#[derive(Serialize, Deserialize)]
+pub struct PeerList {
+    // Address of the requesting node as the responding node sees it, so a node doesn't add itself as a peer
+    pub address: String,
+    // The connected peers of the responding node
+    pub peers: Vec<String>,
+}
+
+/// A node this node exchanges peers with
+pub struct Peer {
+    // Whether the peer was given with `--bootstrap`, bootstrap peers are never forgotten
+    pub bootstrap: bool,
+    pub connected: bool,
+    // Number of exchanges in a row the peer didn't answer
+    pub failures: u32,
+    // When the peer last answered an exchange or sent one, None if it never did
+    pub last_seen: Option<Instant>,
+}
+
+/// The peers of the node by address
+#[derive(Default)]
+pub struct PeerManager {
+    peers: BTreeMap<String, Peer>,
+    // Addresses other nodes see this node at
+    own_addresses: HashSet<String>,
+}
+
+impl PeerManager {
+    /// Returns the peer manager knowing the given bootstrap peers
+    pub fn new(bootstrap: &[String]) -> PeerManager {
+        let peers = bootstrap.iter().map(|address| (address.clone(), Peer { bootstrap: true, connected: false, failures: 0, last_seen: None })).collect();
+        PeerManager { peers, own_addresses: HashSet::new() }
+    }
+
+    /// Adds the peers learned from another node. Addresses of this node, addresses that aren't a socket address and
+    /// peers beyond the maximum number of peers are ignored.
+    ///
+    /// Returns: the number of peers added
+    pub fn learn(&mut self, addresses: &[String]) -> usize {
+        let mut added = 0;
+        for address in addresses {
+            if self.peers.len() >= MAX_PEERS || self.peers.contains_key(address) || self.own_addresses.contains(address) || address.parse::<SocketAddr>().is_err() {
+                continue;
+            }
+            self.peers.insert(address.clone(), Peer { bootstrap: false, connected: false, failures: 0, last_seen: None });
+            added += 1;
+        }
+        added
+    }
+
+    /// Records that the peer at the given address answered an exchange or sent one, adding it if it is unknown
+    ///
+    /// Returns: whether the peer wasn't connected before
+    pub fn connected(&mut self, address: &str) -> bool {
+        if !self.peers.contains_key(address) && self.learn(&[address.to_string()]) == 0 {
+            return false;
+        }
+        let Some(peer) = self.peers.get_mut(address) else { return false };
+        let newly = !peer.connected;
+        (peer.connected, peer.failures, peer.last_seen) = (true, 0, Some(Instant::now()));
+        newly
+    }
+
+    /// Records that the peer at the given address didn't answer an exchange
+    ///
+    /// Returns: whether the peer is disconnected by it
+    pub fn failed(&mut self, address: &str) -> bool {
+        let Some(peer) = self.peers.get_mut(address) else { return false };
+        peer.failures += 1;
+        if !peer.bootstrap && peer.failures >= FORGET_AFTER {
+            self.peers.remove(address);
+            return false;
+        }
+        let disconnected = peer.connected && peer.failures >= DISCONNECT_AFTER;
+        if disconnected {
+            peer.connected = false;
+        }
+        disconnected
+    }
+
+    /// Records an address other nodes see this node at, which is never a peer
+    pub fn add_own_address(&mut self, address: &str) {
+        if self.own_addresses.insert(address.to_string()) {
+            self.peers.remove(address);
+        }
+    }
+
+    /// Returns the addresses of all known peers
+    pub fn addresses(&self) -> Vec<String> {
+        self.peers.keys().cloned().collect()
+    }
+
+    /// Returns the addresses of the connected peers, which are shared with other nodes, except for the given one
+    pub fn shared(&self, except: &str) -> Vec<String> {
+        self.peers.iter().filter(|(address, peer)| peer.connected && *address != except).map(|(address, _)| address.clone()).collect()
+    }
+
+    /// Returns the known peers, sorted by address
+    pub fn peers(&self) -> impl Iterator<Item = (&String, &Peer)> {
+        self.peers.iter()
+    }
+
+    /// Returns the number of connected peers and of all known peers
+    pub fn counts(&self) -> (usize, usize) {
+        (self.peers.values().filter(|peer| peer.connected).count(), self.peers.len())
+    }
+}
+
+/// Exchanges peers with the peer at the given address
+///
+/// Returns: the peers the peer shared, or why the exchange failed
+fn exchange(state: &State, address: &str, port: u16) -> Result<PeerList, String> {
+    let peers = state.peers.lock().unwrap().shared(address);
+    let request = Request::new_exchange_peers_request(&state.identity, port, peers);
+    let mut client = Client::new(address.to_string(), Duration::from_secs(1), 1).quiet();
+    let response = client.send(&request)?.result?;
+    serde_json::from_str(&response).map_err(|e| format!("Invalid peer list from peer {}: {}", address, e))
+}
+
+/// Exchanges peers with every known peer, starting with the bootstrap peers right away and then every peer exchange
+/// interval, until the node shuts down
+///
+/// # Arguments
+///
+/// * `state`: the state of the node
+/// * `port`: the port the node listens on, which other nodes reach it at
+pub async fn exchange_peers(state: Arc<State>, port: u16) {
+    loop {
+        let shared_state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            let state = shared_state;
+            let addresses = state.peers.lock().unwrap().addresses();
+            for address in addresses {
+                let result = exchange(&state, &address, port);
+                let mut peers = state.peers.lock().unwrap();
+                match result {
+                    Ok(list) => {
+                        peers.add_own_address(&list.address);
+                        if peers.connected(&address) {
+                            info!(peer = %address, "Connected to peer.");
+                        }
+                        let added = peers.learn(&list.peers);
+                        if added > 0 {
+                            debug!(peer = %address, added, "Learned peers.");
+                        }
+                    }
+                    Err(e) => {
+                        debug!(peer = %address, "Peer exchange failed: {}", e);
+                        if peers.failed(&address) {
+                            warn!(peer = %address, "Disconnected from peer.");
+                        }
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap();
+        tokio::time::sleep(PEER_EXCHANGE_INTERVAL).await;
+    }
+}
+
+/// Answers a peer exchange: the requesting node is connected, its peers are learned and the connected peers of this
+/// node are shared with it
+///
+/// Returns: the JSON encoded peer list
+pub fn exchange_peers_response(state: &State, src: SocketAddr, exchange_info: &ExchangePeersOp) -> String {
+    let address = SocketAddr::new(src.ip(), exchange_info.port).to_string();
+    let mut peers = state.peers.lock().unwrap();
+    if peers.connected(&address) {
+        info!(peer = %address, "Connected to peer.");
+    }
+    peers.learn(&exchange_info.peers);
+    serde_json::to_string(&PeerList { peers: peers.shared(&address), address }).unwrap()
+}
+
+/// Lists the peers of the node and whether they are connected as the response to a peers request, the peers that don't
+/// fit into the response are counted at the end
+pub fn get_peers_response(state: &State, max_length: usize) -> String {
+    let peers = state.peers.lock().unwrap();
+    let (connected, known) = peers.counts();
+    let mut response = format!("{} peers, {} of them connected:", known, connected);
+    for (index, (address, peer)) in peers.peers().enumerate() {
+        let status = match (peer.connected, peer.last_seen) {
+            (true, _) => "connected".to_string(),
+            (false, Some(last_seen)) => format!("disconnected, last seen {}s ago", last_seen.elapsed().as_secs()),
+            (false, None) => "never connected".to_string(),
+        };
+        let bootstrap = if peer.bootstrap { ", bootstrap peer" } else { "" };
+        let line = format!("\n- {}: {}{}", address, status, bootstrap);
+        let more = format!("\n… and {} more", known - index);
+        if response.len() + line.len() + more.len() > max_length && index + 1 < known {
+            response.push_str(&more);
+            break;
+        }
+        response.push_str(&line);
+    }
+    response
+}
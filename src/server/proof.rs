@@ -0,0 +1,151 @@
+//! Proof files, made with `prove-balance` and checked offline with `verify-proof`. A proof file holds the transactions
+//! of an account, or a single transaction, with their Merkle proofs and the headers of the chain from the genesis block
+//! to the tip, so anyone can check that the transactions are included in the chain without asking a node. Like for
+//! light nodes, see [super::light], the proof of an account shows its balance from the transactions given, but can't
+//! show that none were left out.
+
+use serde::{Deserialize, Serialize};
+use crate::client::Client;
+use crate::common::Request;
+use super::light::{balance_of, AccountProof, Header, ProvenTransaction, HEADER_BATCH};
+use super::load_genesis;
+
+/// What a proof file proves
+pub enum ProofTarget {
+    // The balance of an account, by all its minted transactions
+    Account(String),
+    // That a transaction was minted
+    Transaction(String),
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProofFile {
+    // Account whose transactions are proven, None if the file proves a single transaction
+    account_id: Option<String>,
+    transactions: Vec<ProvenTransaction>,
+    // Headers of the chain from the genesis block to the tip
+    headers: Vec<Header>,
+}
+
+impl ProofFile {
+    /// Checks the header chain, starting at the genesis block with the given hash if one is given, and the proofs of
+    /// the transactions against it
+    ///
+    /// Returns: a user-facing message saying what the file proves, or why it is invalid
+    fn verify(&self, genesis_hash: Option<&str>) -> Result<String, String> {
+        let genesis = self.headers.first().ok_or("❌ Invalid proof, it has no headers.")?;
+        if genesis.height() != 0 {
+            return Err(format!("❌ Invalid proof, its headers start at height {} instead of the genesis block.", genesis.height()));
+        }
+        if let Some(genesis_hash) = genesis_hash.filter(|genesis_hash| *genesis_hash != genesis.hash) {
+            return Err(format!("❌ Invalid proof, its chain starts with block {}, but the genesis configuration gives genesis block {}.", genesis.hash, genesis_hash));
+        }
+        for (previous, header) in self.headers.iter().zip(&self.headers[1..]) {
+            header.verify(previous).map_err(|e| format!("❌ Invalid proof, {}.", e))?;
+        }
+        for proven in &self.transactions {
+            let header = self
+                .headers
+                .get(proven.height)
+                .ok_or_else(|| format!("❌ Invalid proof, transaction {} is in block {}, but the headers end before it.", proven.transaction.id(), proven.height))?;
+            proven.verify(header).map_err(|e| format!("❌ Invalid proof, {}.", e))?;
+        }
+
+        let tip_height = self.headers.len() - 1;
+        let tip = &self.headers[tip_height].hash;
+        let anchor = match genesis_hash {
+            Some(_) => "the genesis block of the genesis configuration".to_string(),
+            None => format!("genesis block {}", genesis.hash),
+        };
+        match &self.account_id {
+            Some(account_id) => {
+                if let Some(proven) = self.transactions.iter().find(|proven| proven.transaction.to_account_id != *account_id && proven.transaction.from_account_id.as_ref() != Some(account_id)) {
+                    return Err(format!("❌ Invalid proof, transaction {} doesn't involve account {}.", proven.transaction.id(), account_id));
+                }
+                let balance = balance_of(account_id, self.transactions.iter().map(|proven| &proven.transaction));
+                Ok(format!(
+                    "✅ Proof is valid: account {} has a balance of {} at height {} (tip {}) from {} transactions, on a chain starting with {}.",
+                    account_id,
+                    balance,
+                    tip_height,
+                    tip,
+                    self.transactions.len(),
+                    anchor
+                ))
+            }
+            None => {
+                let [proven] = self.transactions.as_slice() else {
+                    return Err(format!("❌ Invalid proof, it proves {} transactions but no account.", self.transactions.len()));
+                };
+                Ok(format!(
+                    "✅ Proof is valid: transaction {} is included in block {} ({}), {} blocks below tip {}, on a chain starting with {}.",
+                    proven.transaction.id(),
+                    proven.height,
+                    self.headers[proven.height].hash,
+                    tip_height - proven.height,
+                    tip,
+                    anchor
+                ))
+            }
+        }
+    }
+}
+
+/// Gets the proof of an account or transaction from the server node with the headers of its chain and writes it to a
+/// proof file, after checking it like `verify-proof` does
+///
+/// Returns: a user-facing message, either confirming the proof was written or explaining why it couldn't be made
+pub fn fetch_proof(client: &mut Client, node_id: &str, target: ProofTarget, path: &str) -> Result<String, String> {
+    let request = |client: &mut Client, request: Request| client.send(&request).and_then(|response| response.result);
+    let invalid = |e: serde_json::Error| format!("❌ Invalid response from the server: {}", e);
+
+    let (account_id, transactions) = match target {
+        ProofTarget::Account(account_id) => {
+            let response = request(client, Request::new_get_account_proof_request(node_id.to_string(), account_id.clone()))?;
+            let proof: AccountProof = serde_json::from_str(&response).map_err(invalid)?;
+            (Some(account_id), proof.transactions)
+        }
+        ProofTarget::Transaction(transaction_id) => {
+            let response = request(client, Request::new_get_transaction_proof_request(node_id.to_string(), transaction_id))?;
+            (None, vec![serde_json::from_str(&response).map_err(invalid)?])
+        }
+    };
+
+    // The headers are requested in batches until the server has no more
+    let mut headers: Vec<Header> = Vec::new();
+    loop {
+        let response = request(client, Request::new_get_headers_request(node_id.to_string(), headers.len(), HEADER_BATCH))?;
+        let batch: Vec<Header> = serde_json::from_str(&response).map_err(invalid)?;
+        if batch.is_empty() {
+            break;
+        }
+        headers.extend(batch);
+    }
+
+    let proof = ProofFile { account_id, transactions, headers };
+    proof
+        .verify(None)
+        .map_err(|e| format!("{} The chain of the server may have changed while the proof was made, try again.", e))?;
+    let json = serde_json::to_string_pretty(&proof).unwrap();
+    std::fs::write(path, json).map_err(|e| format!("❌ Failed to write proof file {}: {}", path, e))?;
+    Ok(format!("✅ Proof of {} transactions with {} headers written to {}.", proof.transactions.len(), proof.headers.len(), path))
+}
+
+/// Verifies a proof file offline, see [fetch_proof]
+///
+/// # Arguments
+///
+/// * `path`: the proof file
+/// * `genesis`: path to the genesis configuration the chain has to start with, any genesis block is accepted if not
+///   given
+///
+/// Returns: a user-facing message, either saying what the file proves or why it is invalid
+pub fn verify_proof_file(path: &str, genesis: Option<&str>) -> Result<String, String> {
+    let genesis_hash = match genesis {
+        Some(genesis) => Some(load_genesis(Some(genesis))?.to_block().hash),
+        None => None,
+    };
+    let json = std::fs::read_to_string(path).map_err(|e| format!("❌ Failed to read proof file {}: {}", path, e))?;
+    let proof: ProofFile = serde_json::from_str(&json).map_err(|e| format!("❌ Invalid proof file {}: {}", path, e))?;
+    proof.verify(genesis_hash.as_deref())
+}
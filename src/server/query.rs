@@ -0,0 +1,110 @@
+//! Filters of the minted transactions of an account, evaluated by the node for `query`, so a client looking for some
+//! transactions of an account doesn't have to page through its whole history. `history` is a query without filters.
+
+use std::ops::RangeInclusive;
+use crate::common::{Amount, Direction, QueryTransactionsOp};
+use super::Transaction;
+
+/// The transactions of an account a query returns
+pub struct TransactionFilter {
+    pub account_id: String,
+    direction: Option<Direction>,
+    min_amount: Option<Amount>,
+    max_amount: Option<Amount>,
+    from_height: Option<u64>,
+    to_height: Option<u64>,
+    since: Option<u64>,
+    until: Option<u64>,
+}
+
+impl TransactionFilter {
+    /// The filter of a history, all transactions of the account
+    pub fn of_account(account_id: &str) -> TransactionFilter {
+        TransactionFilter {
+            account_id: account_id.to_string(),
+            direction: None,
+            min_amount: None,
+            max_amount: None,
+            from_height: None,
+            to_height: None,
+            since: None,
+            until: None,
+        }
+    }
+
+    /// The filter of a query
+    ///
+    /// Returns: the filter, or a user-facing error if one of its ranges is empty
+    pub fn of_query(query: &QueryTransactionsOp) -> Result<TransactionFilter, String> {
+        let empty = |name: &str, from: String, to: String| format!("❌ The {} range from {} to {} is empty, its start has to be at most its end.", name, from, to);
+        if let (Some(min), Some(max)) = (query.min_amount, query.max_amount) {
+            if min > max {
+                return Err(empty("amount", min.to_string(), max.to_string()));
+            }
+        }
+        if let (Some(from), Some(to)) = (query.from_height, query.to_height) {
+            if from > to {
+                return Err(empty("height", from.to_string(), to.to_string()));
+            }
+        }
+        if let (Some(since), Some(until)) = (query.since, query.until) {
+            if since > until {
+                return Err(empty("time", since.to_string(), until.to_string()));
+            }
+        }
+        Ok(TransactionFilter {
+            account_id: query.account_id.clone(),
+            direction: query.direction,
+            min_amount: query.min_amount,
+            max_amount: query.max_amount,
+            from_height: query.from_height,
+            to_height: query.to_height,
+            since: query.since,
+            until: query.until,
+        })
+    }
+
+    /// Returns the heights of the blocks to search on a chain with the given tip, empty if the range is beyond it
+    pub fn heights(&self, tip: usize) -> RangeInclusive<usize> {
+        let to = self.to_height.map_or(tip, |to| usize::try_from(to).unwrap_or(usize::MAX).min(tip));
+        let from = self.from_height.map_or(0, |from| usize::try_from(from).unwrap_or(usize::MAX));
+        from..=to
+    }
+
+    /// Whether a transaction minted in a block with the given timestamp matches the filter. The height is matched by
+    /// [TransactionFilter::heights].
+    pub fn matches(&self, block_timestamp: u64, transaction: &Transaction) -> bool {
+        let sent = transaction.from_account_id.as_deref() == Some(self.account_id.as_str());
+        let received = transaction.to_account_id == self.account_id;
+        let direction = match self.direction {
+            None => sent || received,
+            Some(Direction::Sent) => sent,
+            Some(Direction::Received) => received,
+        };
+        direction
+            && self.min_amount.is_none_or(|min| transaction.amount >= min)
+            && self.max_amount.is_none_or(|max| transaction.amount <= max)
+            && self.since.is_none_or(|since| block_timestamp >= since)
+            && self.until.is_none_or(|until| block_timestamp <= until)
+    }
+
+    /// Describes the filters, empty for a history
+    pub fn describe(&self) -> String {
+        let range = |name: &str, from: Option<String>, to: Option<String>| match (from, to) {
+            (Some(from), Some(to)) => Some(format!("{} {} to {}", name, from, to)),
+            (Some(from), None) => Some(format!("{} from {}", name, from)),
+            (None, Some(to)) => Some(format!("{} up to {}", name, to)),
+            (None, None) => None,
+        };
+        let filters: Vec<String> = [
+            self.direction.map(|direction| if direction == Direction::Sent { "sent" } else { "received" }.to_string()),
+            range("amount", self.min_amount.map(|min| min.to_string()), self.max_amount.map(|max| max.to_string())),
+            range("height", self.from_height.map(|from| from.to_string()), self.to_height.map(|to| to.to_string())),
+            range("time", self.since.map(|since| since.to_string()), self.until.map(|until| until.to_string())),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        filters.join(", ")
+    }
+}
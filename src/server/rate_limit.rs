@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Number of tracked clients above which clients with a full bucket are forgotten
+const MAX_TRACKED_CLIENTS: usize = 10000;
+
+/// The tokens of a single client
+struct Bucket {
+    tokens: f64,
+    // When the tokens were last refilled
+    updated: Instant,
+}
+
+/// Token bucket rate limiter keyed by the IP address of the client. Every client can make `burst` requests at once,
+/// and gets `rate` more requests per second after that.
+pub struct RateLimiter {
+    // Requests per second, 0 disables rate limiting
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u32, burst: u32) -> RateLimiter {
+        RateLimiter {
+            rate: rate as f64,
+            burst: burst.max(1) as f64,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Takes a token for a request of the given client
+    ///
+    /// Returns: how long the client has to wait for the next token if it has none left
+    pub fn check(&self, client: IpAddr) -> Result<(), Duration> {
+        if self.rate == 0.0 {
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| self.refill(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let tokens = self.refill(bucket, now);
+        if tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - tokens) / self.rate));
+        }
+        bucket.tokens = tokens - 1.0;
+        Ok(())
+    }
+
+    /// Adds the tokens earned since the last refill to the bucket, up to the burst size
+    ///
+    /// Returns: the tokens in the bucket
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> f64 {
+        let earned = now.duration_since(bucket.updated).as_secs_f64() * self.rate;
+        bucket.tokens = (bucket.tokens + earned).min(self.burst);
+        bucket.updated = now;
+        bucket.tokens
+    }
+}
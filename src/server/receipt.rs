@@ -0,0 +1,82 @@
+//! Receipts of transactions. A receipt tells where a transaction landed: the block it was minted in, its height and
+//! the index of the transaction in the block, the fee it paid and how many blocks were minted on top of it. A
+//! transaction that was accepted but then dropped, e.g. a claim of an escrow that expired before it was minted or a
+//! transaction that doesn't apply after switching to a fork, is rejected, and so is a cancelled transaction. A transfer
+//! that wasn't minted before its expiry is expired. The node remembers why for the most recent ones.
+
+use std::collections::{HashMap, VecDeque};
+use super::State;
+
+/// Number of rejected transactions remembered at most, the oldest are forgotten first
+const MAX_REJECTED: usize = 10000;
+
+/// Why accepted transactions were dropped before they were minted
+#[derive(Default)]
+pub struct Rejections {
+    // The status of every dropped transaction, rejected or expired, and why
+    reasons: HashMap<String, (&'static str, String)>,
+    // IDs of the rejected transactions in the order they were rejected, oldest first
+    order: VecDeque<String>,
+}
+
+impl Rejections {
+    /// Remembers why a transaction was dropped, forgetting the oldest while too many are remembered
+    pub fn record(&mut self, transaction_id: String, reason: String) {
+        self.insert(transaction_id, "rejected", reason);
+    }
+
+    /// Remembers that a transaction expired before it was minted
+    pub fn expire(&mut self, transaction_id: String, reason: String) {
+        self.insert(transaction_id, "expired", reason);
+    }
+
+    fn insert(&mut self, transaction_id: String, status: &'static str, reason: String) {
+        if self.reasons.insert(transaction_id.clone(), (status, reason)).is_none() {
+            self.order.push_back(transaction_id);
+        }
+        while self.order.len() > MAX_REJECTED {
+            if let Some(oldest) = self.order.pop_front() {
+                self.reasons.remove(&oldest);
+            }
+        }
+    }
+
+    fn get(&self, transaction_id: &str) -> Option<&(&'static str, String)> {
+        self.reasons.get(transaction_id)
+    }
+}
+
+/// Builds the receipt of a transaction, minted, pending or rejected
+///
+/// Returns: the receipt, or an error if the node doesn't know the transaction
+pub fn get_receipt_response(state: &State, transaction_id: &str) -> Result<String, String> {
+    {
+        // Locked in the same order as the minter, so a transaction can't move to the ledger while it is searched for
+        let mempool = state.mempool.lock().unwrap();
+        let ledger = state.ledger.read().unwrap();
+        if let Some((height, index)) = state.locate_transaction(transaction_id) {
+            let (block, transaction) = (&ledger[height], &ledger[height].transactions[index]);
+            return Ok(format!(
+                    "Receipt of transaction {}:\n- Status: minted\n- Block: {}\n- Height: {}\n- Index: {} of {} transactions\n- Fee: {}\n- Confirmations: {}",
+                    transaction_id,
+                    block.hash,
+                    height,
+                    index,
+                    block.transactions.len(),
+                    transaction.fee,
+                ledger.len() - 1 - height
+            ));
+        }
+        let pending = mempool.transactions().find(|transaction| transaction.id() == transaction_id).map(|transaction| transaction.fee);
+        if let Some(fee) = pending {
+            return Ok(format!("Receipt of transaction {}:\n- Status: pending, not minted yet\n- Fee: {}", transaction_id, fee));
+        }
+    }
+    if let Some(pending) = state.approvals.lock().unwrap().get_mut(transaction_id) {
+        return Ok(format!("Receipt of transaction {}:\n- Status: pending, waiting for approvals\n- Fee: {}", transaction_id, pending.transaction.fee));
+    }
+    match state.rejected.lock().unwrap().get(transaction_id) {
+        Some((status, reason)) => Ok(format!("Receipt of transaction {}:\n- Status: {}, {}", transaction_id, status, reason)),
+        None => Err(format!("❌ Transaction {} not found.", transaction_id)),
+    }
+}
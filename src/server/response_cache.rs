@@ -0,0 +1,92 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Time a response is kept for retries and duplicates of its request
+const RESPONSE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Number of responses kept at most, the oldest are forgotten first
+const MAX_CACHED_RESPONSES: usize = 10000;
+
+/// Number of bytes of responses kept at most, as responses can be up to `MAX_MESSAGE_SIZE` bytes each
+const MAX_CACHED_BYTES: usize = 64 * 1024 * 1024;
+
+/// State of a request in the cache
+pub enum Lookup {
+    // The request wasn't seen before and should be processed
+    New,
+    // The request is still being processed, the retry should be dropped
+    InProgress,
+    // The request was processed already, the retry is answered with the same response
+    Done(Vec<u8>),
+}
+
+/// A request by client and request ID
+type Key = (IpAddr, u64);
+
+/// The encoded response to a request, None while the request is processed, and when the entry was added
+type Entry = (Option<Vec<u8>>, Instant);
+
+#[derive(Default)]
+struct Entries {
+    entries: HashMap<Key, Entry>,
+    // Keys in the order their entries were added, oldest first
+    order: VecDeque<Key>,
+    // Number of bytes of all cached responses
+    bytes: usize,
+}
+
+/// Responses to recent requests by client and request ID, so that a retried or duplicated request (e.g. because the
+/// response was lost, or a command was run twice with the same idempotency key) is answered with the original response
+/// instead of being processed again
+#[derive(Default)]
+pub struct ResponseCache {
+    responses: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    /// Looks up a request, marking it as in progress if it wasn't seen before
+    pub fn begin(&self, client: IpAddr, request_id: u64) -> Lookup {
+        let now = Instant::now();
+        let mut responses = self.responses.lock().unwrap();
+        responses.forget_old(now);
+
+        match responses.entries.get(&(client, request_id)) {
+            Some((Some(response), _)) => Lookup::Done(response.clone()),
+            Some((None, _)) => Lookup::InProgress,
+            None => {
+                responses.entries.insert((client, request_id), (None, now));
+                responses.order.push_back((client, request_id));
+                Lookup::New
+            }
+        }
+    }
+
+    /// Stores the response to a processed request
+    pub fn finish(&self, client: IpAddr, request_id: u64, response: Vec<u8>) {
+        let mut responses = self.responses.lock().unwrap();
+        let length = response.len();
+        // The entry keeps the time it was added, so it expires in the order it was added
+        let Some(entry) = responses.entries.get_mut(&(client, request_id)) else {
+            return;
+        };
+        let previous = entry.0.replace(response).map_or(0, |previous| previous.len());
+        responses.bytes = responses.bytes - previous + length;
+    }
+}
+
+impl Entries {
+    /// Forgets expired entries, and the oldest entries while there are too many or they take up too much memory
+    fn forget_old(&mut self, now: Instant) {
+        while let Some(key) = self.order.front() {
+            let full = self.entries.len() >= MAX_CACHED_RESPONSES || self.bytes > MAX_CACHED_BYTES;
+            if !full && now.duration_since(self.entries[key].1) < RESPONSE_TTL {
+                break;
+            }
+            let (response, _) = self.entries.remove(key).unwrap();
+            self.bytes -= response.map_or(0, |response| response.len());
+            self.order.pop_front();
+        }
+    }
+}
@@ -0,0 +1,206 @@
+//! The REST API of the node, so web frontends can integrate with plain HTTP requests instead of JSON-RPC envelopes or
+//! the bincode/UDP protocol. Resources are addressed by path, requests and responses are JSON, and errors are answered
+//! with an HTTP status and a body of `{"error": <message>}`. The routes are described by the OpenAPI document served at
+//! `/openapi.json`. Requests go through the same rate limiter, request order, validation and state as requests over
+//! UDP, JSON-RPC and gRPC.
+
+use std::sync::Arc;
+use serde_json::{json, Value};
+use crate::common::FundTransferOp;
+use super::allowlist::Unauthorized;
+use super::audit::{self, Audited};
+use super::explorer::percent_decode;
+use super::http::{self, HttpRequest, HttpResponse};
+use super::validation;
+use super::State;
+
+/// Routes the node serves, see `route`. They label the metrics and audit entries of the requests.
+const ROUTES: [&str; 4] = ["GET /accounts/{id}/balance", "POST /transfers", "GET /blocks/{height}", "GET /openapi.json"];
+
+/// An error response: its HTTP status and message
+type RestError = (u16, String);
+
+/// Starts the REST API on the given address
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    tracing::info!("REST API started on http://{}.", addr);
+    http::serve(addr, move |request| handle(&state, request));
+}
+
+/// Handles a single HTTP request to the REST API
+fn handle(state: &State, request: HttpRequest) -> HttpResponse {
+    let segments: Vec<String> = request.path.split('/').filter(|segment| !segment.is_empty()).map(percent_decode).collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let Some((route, argument)) = route(&request.method, &segments) else {
+        return error_response((404, "Not found.".to_string()));
+    };
+    let Some(route) = route else {
+        return error_response((405, format!("{} isn't supported on {}.", request.method, request.path)));
+    };
+
+    if let Err(retry_after) = state.rate_limiter.check(request.remote_addr.ip()) {
+        state.metrics.observe_rate_limited("rest");
+        let body = json!({ "error": "Rate limit exceeded.", "retry_after_ms": retry_after.as_millis().max(1) as u64 });
+        return HttpResponse::json(429, &body);
+    }
+
+    // REST clients can't authenticate with a key, so none is allowed in permissioned mode
+    if state.allowlist.is_permissioned() {
+        state.metrics.observe_unauthorized("rest");
+        let body = json!({
+            "error": "Unauthorized, the node is in permissioned mode and only accepts requests of allowed keys.",
+            "reason": Unauthorized::Unauthenticated.code(),
+        });
+        return HttpResponse::json(403, &body);
+    }
+
+    // Numbered before the request is parsed, like requests over UDP are, see [super::workers]
+    let ticket = state.sequencer.admit();
+    let transfer_info = match route {
+        "POST /transfers" => match serde_json::from_slice::<FundTransferOp>(&request.body) {
+            Ok(transfer_info) => Some(transfer_info),
+            Err(e) => return error_response((400, format!("Invalid transfer: {}", e))),
+        },
+        _ => None,
+    };
+
+    // Transactions created through the REST API are attributed to the address of the caller
+    let node_id = format!("rest:{}", request.remote_addr.ip());
+    let _span = tracing::info_span!("rest_request", from = %node_id, route).entered();
+    tracing::info!("Received REST request.");
+
+    let started = std::time::Instant::now();
+    // Transfers wait for the requests changing the from account that arrived before them
+    let changed_account = transfer_info.as_ref().map(|transfer_info| transfer_info.from_account_id.as_str());
+    ticket.wait_for_turn(changed_account.as_slice());
+    let worker = state.workers.acquire();
+    let result = match transfer_info {
+        Some(transfer_info) => transfer(state, node_id.clone(), &transfer_info),
+        None => get(state, route, argument.unwrap_or_default()),
+    };
+    drop(worker);
+    state.metrics.observe_request("rest", route, started.elapsed());
+    let outcome = match &result {
+        Ok(body) => Ok(body.get("message").and_then(Value::as_str).unwrap_or_default().to_string()),
+        Err((_, message)) => Err(message.clone()),
+    };
+    audit::record(state, Audited { transport: "rest", source: request.remote_addr, from_node: &node_id, operation: route, result: &outcome });
+
+    match result {
+        Ok(body) => HttpResponse::json(200, &body),
+        Err(error) => error_response(error),
+    }
+}
+
+/// Matches a request to a route
+///
+/// Returns: the route with the path segment it takes, None as the route if the path exists but not for the method, or
+/// None if the path doesn't exist
+fn route<'a>(method: &str, segments: &[&'a str]) -> Option<(Option<&'static str>, Option<&'a str>)> {
+    let (route, argument) = match segments {
+        ["accounts", id, "balance"] => (ROUTES[0], Some(*id)),
+        ["transfers"] => (ROUTES[1], None),
+        ["blocks", height] => (ROUTES[2], Some(*height)),
+        ["openapi.json"] => (ROUTES[3], None),
+        _ => return None,
+    };
+    Some((route.starts_with(&format!("{} ", method)).then_some(route), argument))
+}
+
+/// Makes a transfer, validated like the transfers of the other protocols
+fn transfer(state: &State, node_id: String, transfer_info: &FundTransferOp) -> Result<Value, RestError> {
+    validation::validate_transfer(transfer_info).map_err(|e| (400, e.to_string()))?;
+    // Rejected transfers, e.g. for insufficient funds, are well-formed requests the node can't process
+    let message = state.transfer_funds(node_id, transfer_info).map_err(|message| (422, message))?;
+    Ok(json!({ "message": message }))
+}
+
+/// Answers the GET routes
+fn get(state: &State, route: &str, argument: &str) -> Result<Value, RestError> {
+    match route {
+        "GET /accounts/{id}/balance" => {
+            let (account_id, alias) = state.resolve_account(argument);
+            let balance = state.get_balance(&account_id);
+            Ok(json!({ "account_id": account_id, "alias": alias, "balance": balance }))
+        }
+        "GET /blocks/{height}" => {
+            let height = argument.parse::<usize>().map_err(|_| (400, format!("Invalid block height {}.", argument)))?;
+            let (_, block) = state.find_block(Some(height), None).ok_or((404, format!("Block {} not found.", height)))?;
+            Ok(serde_json::to_value(block).unwrap())
+        }
+        _ => Ok(openapi()),
+    }
+}
+
+/// The OpenAPI document describing the routes
+fn openapi() -> Value {
+    let error = json!({ "description": "The error", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } });
+    json!({
+        "openapi": "3.0.3",
+        "info": { "title": "ToyChain node", "version": env!("CARGO_PKG_VERSION") },
+        "paths": {
+            "/accounts/{id}/balance": {
+                "get": {
+                    "summary": "Get the balance of an account",
+                    "parameters": [{ "name": "id", "in": "path", "required": true, "description": "The account ID or alias", "schema": { "type": "string" } }],
+                    "responses": {
+                        "200": { "description": "The balance", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Balance" } } } },
+                    },
+                },
+            },
+            "/transfers": {
+                "post": {
+                    "summary": "Transfer funds between accounts",
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Transfer" } } } },
+                    "responses": {
+                        "200": { "description": "The transfer is pending", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Message" } } } },
+                        "400": error,
+                        "422": error,
+                    },
+                },
+            },
+            "/blocks/{height}": {
+                "get": {
+                    "summary": "Get a block and its transactions",
+                    "parameters": [{ "name": "height", "in": "path", "required": true, "schema": { "type": "integer", "minimum": 0 } }],
+                    "responses": {
+                        "200": { "description": "The block", "content": { "application/json": { "schema": { "type": "object" } } } },
+                        "400": error,
+                        "404": error,
+                    },
+                },
+            },
+        },
+        "components": {
+            "schemas": {
+                "Balance": {
+                    "type": "object",
+                    "properties": {
+                        "account_id": { "type": "string" },
+                        "alias": { "type": "string", "nullable": true },
+                        "balance": { "type": "string", "description": "Decimal amount" },
+                    },
+                },
+                "Transfer": {
+                    "type": "object",
+                    "required": ["from_account_id", "to_account_id", "amount", "nonce"],
+                    "properties": {
+                        "from_account_id": { "type": "string" },
+                        "to_account_id": { "type": "string" },
+                        "amount": { "type": "string", "description": "Decimal amount, numbers are accepted as well" },
+                        "fee": { "type": "string", "description": "Decimal amount, numbers are accepted as well" },
+                        "nonce": { "type": "integer", "minimum": 0 },
+                        "memo": { "type": "string" },
+                    },
+                },
+                "Message": { "type": "object", "properties": { "message": { "type": "string" } } },
+                "Error": { "type": "object", "properties": { "error": { "type": "string" } } },
+            },
+        },
+    })
+}
+
+fn error_response((status, message): RestError) -> HttpResponse {
+    HttpResponse::json(status, &json!({ "error": message }))
+}
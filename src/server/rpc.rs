@@ -0,0 +1,208 @@
+use std::sync::Arc;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use crate::common::{AccountCreationOp, FundTransferOp, GetFundsOp, GetNonceOp};
+use super::allowlist::Unauthorized;
+use super::audit::{self, Audited};
+use super::validation;
+use super::http::{self, HttpRequest, HttpResponse};
+use super::State;
+
+// Error codes defined by the JSON-RPC 2.0 specification
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// Server defined error code for operations rejected by the node (e.g. insufficient funds)
+const OPERATION_REJECTED: i64 = -32000;
+// Server defined error code for requests rejected in permissioned mode
+const UNAUTHORIZED: i64 = -32003;
+// Server defined error code for requests rejected by the rate limiter
+const RATE_LIMITED: i64 = -32005;
+
+/// Methods the node serves, see `call`
+const METHODS: [&str; 7] = ["createAccount", "transfer", "getBalance", "getNonce", "getBlock", "getTransactionProof", "getChainInfo"];
+
+/// A JSON-RPC 2.0 request. Params are passed by name as an object.
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    #[serde(default)]
+    id: Value,
+}
+
+/// Params of the `getBlock` method, a block can be looked up either by its height or hash
+#[derive(Deserialize)]
+struct GetBlockParams {
+    height: Option<usize>,
+    hash: Option<String>,
+}
+
+/// Params of the `getTransactionProof` method
+#[derive(Deserialize)]
+struct GetTransactionProofParams {
+    transaction_id: String,
+}
+
+/// Starts the JSON-RPC server on the given address. Requests are JSON-RPC 2.0 objects POSTed to `/`.
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    tracing::info!("JSON-RPC server started on {}.", addr);
+    http::serve(addr, move |request| handle(&state, request));
+}
+
+/// Handles a single HTTP request carrying a JSON-RPC request
+fn handle(state: &State, request: HttpRequest) -> HttpResponse {
+    if request.path != "/" {
+        return HttpResponse::text(404, "Not found.");
+    }
+    if request.method != "POST" {
+        return HttpResponse::text(405, "JSON-RPC requests must be sent with POST.");
+    }
+
+    if let Err(retry_after) = state.rate_limiter.check(request.remote_addr.ip()) {
+        state.metrics.observe_rate_limited("rpc");
+        let mut response = error_response(Value::Null, RATE_LIMITED, "Rate limit exceeded.");
+        response["error"]["data"] = json!({ "retry_after_ms": retry_after.as_millis().max(1) as u64 });
+        return HttpResponse::json(429, &response);
+    }
+
+    // JSON-RPC clients can't authenticate with a key, so none is allowed in permissioned mode
+    if state.allowlist.is_permissioned() {
+        state.metrics.observe_unauthorized("rpc");
+        let mut response = error_response(Value::Null, UNAUTHORIZED, "Unauthorized, the node is in permissioned mode and only accepts requests of allowed keys.");
+        response["error"]["data"] = json!({ "reason": Unauthorized::Unauthenticated.code() });
+        return HttpResponse::json(403, &response);
+    }
+
+    // Numbered before the request is parsed, like requests over UDP are, see [super::workers]
+    let ticket = state.sequencer.admit();
+    let rpc_request: RpcRequest = match serde_json::from_slice(&request.body) {
+        Ok(rpc_request) => rpc_request,
+        Err(e) => return HttpResponse::json(200, &error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+    };
+
+    if rpc_request.jsonrpc != "2.0" {
+        return HttpResponse::json(200, &error_response(rpc_request.id, INVALID_REQUEST, "Only JSON-RPC 2.0 is supported."));
+    }
+
+    // Transactions created through RPC are attributed to the address of the caller
+    let node_id = format!("rpc:{}", request.remote_addr.ip());
+    let _span = tracing::info_span!("rpc_request", from = %node_id, method = %rpc_request.method).entered();
+    tracing::info!("Received RPC request.");
+
+    let started = std::time::Instant::now();
+    // Requests changing an account wait for the requests changing it that arrived before them
+    let changed_account = match rpc_request.method.as_str() {
+        "createAccount" => rpc_request.params.get("account_id"),
+        "transfer" => rpc_request.params.get("from_account_id"),
+        _ => None,
+    };
+    ticket.wait_for_turn(changed_account.and_then(Value::as_str).as_slice());
+    let worker = state.workers.acquire();
+    let result = call(state, node_id.clone(), &rpc_request.method, rpc_request.params);
+    drop(worker);
+    // Unknown methods share a label, so clients can't create any number of metric series
+    let method = METHODS.iter().copied().find(|&method| method == rpc_request.method).unwrap_or("unknown");
+    state.metrics.observe_request("rpc", method, started.elapsed());
+    let outcome = match &result {
+        Ok(result) => Ok(result.get("message").and_then(Value::as_str).unwrap_or_default().to_string()),
+        Err((_, message)) => Err(message.clone()),
+    };
+    audit::record(state, Audited { transport: "rpc", source: request.remote_addr, from_node: &node_id, operation: method, result: &outcome });
+
+    let response = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": rpc_request.id }),
+        Err((code, message)) => error_response(rpc_request.id, code, &message),
+    };
+
+    HttpResponse::json(200, &response)
+}
+
+/// Dispatches a JSON-RPC method call to the node state
+///
+/// Returns: the result of the call, or a JSON-RPC error code and message
+fn call(state: &State, node_id: String, method: &str, params: Value) -> Result<Value, (i64, String)> {
+    match method {
+        "createAccount" => {
+            let account_info: AccountCreationOp = parse_params(params)?;
+            validation::validate_account_creation(&account_info.account_id, account_info.starting_balance).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+            state
+                .create_account(node_id, &account_info)
+                .map(|message| json!({ "message": message }))
+                .map_err(|message| (OPERATION_REJECTED, message))
+        }
+        "transfer" => {
+            let transfer_info: FundTransferOp = parse_params(params)?;
+            validation::validate_transfer(&transfer_info).map_err(|e| (INVALID_PARAMS, e.to_string()))?;
+            state
+                .transfer_funds(node_id, &transfer_info)
+                .map(|message| json!({ "message": message }))
+                .map_err(|message| (OPERATION_REJECTED, message))
+        }
+        "getBalance" => {
+            let get_info: GetFundsOp = parse_params(params)?;
+            let balance = state.get_balance(&get_info.account_id);
+            Ok(json!({ "account_id": get_info.account_id, "balance": balance }))
+        }
+        "getNonce" => {
+            let get_info: GetNonceOp = parse_params(params)?;
+            let nonce = state.get_next_nonce(&get_info.account_id);
+            Ok(json!({ "account_id": get_info.account_id, "nonce": nonce }))
+        }
+        "getBlock" => {
+            let query: GetBlockParams = parse_params(params)?;
+            if query.height.is_none() && query.hash.is_none() {
+                return Err((INVALID_PARAMS, "Either height or hash must be given.".to_string()));
+            }
+            match state.find_block(query.height, query.hash.as_deref()) {
+                Some((_, block)) => Ok(serde_json::to_value(block).unwrap()),
+                None => Err((OPERATION_REJECTED, "Block not found.".to_string())),
+            }
+        }
+        "getTransactionProof" => {
+            let query: GetTransactionProofParams = parse_params(params)?;
+            let ledger = state.ledger.read().unwrap();
+            if let Some((height, index)) = state.locate_transaction(&query.transaction_id) {
+                let block = &ledger[height];
+                return Ok(json!({
+                    "transaction_id": query.transaction_id,
+                    "block_height": height,
+                    "block_hash": block.hash,
+                    "merkle_root": block.header.merkle_root,
+                    "proof": block.transaction_proof(index).unwrap(),
+                }));
+            }
+            Err((OPERATION_REJECTED, "Transaction not found in any minted block.".to_string()))
+        }
+        "getChainInfo" => {
+            let (blocks, tip_hash) = {
+                let ledger = state.ledger.read().unwrap();
+                (ledger.len(), ledger.last().map(|block| block.hash.clone()))
+            };
+            let pending_transactions = state.mempool.lock().unwrap().len();
+            Ok(json!({
+                "chain_id": state.genesis.chain_id,
+                "genesis_hash": state.ledger.read().unwrap()[0].hash,
+                "blocks": blocks,
+                "tip_hash": tip_hash,
+                "pending_transactions": pending_transactions,
+            }))
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("Method {} not found.", method))),
+    }
+}
+
+/// Deserializes the params of a call into the expected type
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, (i64, String)> {
+    serde_json::from_value(params).map_err(|e| (INVALID_PARAMS, format!("Invalid params: {}", e)))
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "error": { "code": code, "message": message }, "id": id })
+}
@@ -0,0 +1,98 @@
+//! Scheduled and recurring payments. `schedule-payment` records a schedule on the chain: a number of payments of an
+//! amount from the paying account to a recipient, due at a start height and every interval of blocks after it. The
+//! minter of the block at a due height adds the payment to the block, without a fee or nonce. A payment the paying
+//! account can't afford at that height, or that can't be made as an account is frozen or closed, is missed and not
+//! made up for later. Heights only advance as blocks are minted, so the minter keeps minting blocks while a schedule
+//! has payments left, even if there are no transactions to mint.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::common::Amount;
+
+/// Payments of an amount from one account to another every interval of blocks
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Schedule {
+    pub sender: String,
+    pub recipient: String,
+    pub amount: Amount,
+    pub start_height: u64,
+    pub interval: u64,
+    pub count: u64,
+    // Height of the block the latest payment was made in, so no payment is made twice in a block
+    pub paid_at: Option<u64>,
+}
+
+impl Schedule {
+    /// Returns the height the last payment is due at, None if it overflows
+    pub fn last_height(start_height: u64, interval: u64, count: u64) -> Option<u64> {
+        count.checked_sub(1)?.checked_mul(interval)?.checked_add(start_height)
+    }
+
+    /// Whether a payment is due at the given height
+    pub fn is_due(&self, height: u64) -> bool {
+        height >= self.start_height && (height - self.start_height).is_multiple_of(self.interval) && (height - self.start_height) / self.interval < self.count
+    }
+
+    /// Returns the height the next payment is due at from the given height on and the number of payments left by then,
+    /// None if no payment is left
+    pub fn next_payment(&self, height: u64) -> Option<(u64, u64)> {
+        let made = match height.checked_sub(self.start_height) {
+            None | Some(0) => 0,
+            Some(elapsed) => (elapsed - 1) / self.interval + 1,
+        };
+        let left = self.count.checked_sub(made).filter(|left| *left > 0)?;
+        Some((self.start_height + made * self.interval, left))
+    }
+}
+
+/// The payment schedules with payments left, by the ID of the transaction that created them
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Schedules {
+    schedules: HashMap<String, Schedule>,
+}
+
+impl Schedules {
+    pub fn get(&self, schedule_id: &str) -> Option<&Schedule> {
+        self.schedules.get(schedule_id)
+    }
+
+    /// Returns the schedules with a payment due at the given height, sorted by ID so every node makes the payments in
+    /// the same order
+    pub fn due(&self, height: u64) -> Vec<(&String, &Schedule)> {
+        let mut due: Vec<(&String, &Schedule)> = self.schedules.iter().filter(|(_, schedule)| schedule.is_due(height)).collect();
+        due.sort_by(|a, b| a.0.cmp(b.0));
+        due
+    }
+
+    /// Returns the schedules an account pays or is paid by, sorted by ID
+    pub fn involving(&self, account_id: &str) -> Vec<(&String, &Schedule)> {
+        let mut involving: Vec<(&String, &Schedule)> =
+            self.schedules.iter().filter(|(_, schedule)| schedule.sender == account_id || schedule.recipient == account_id).collect();
+        involving.sort_by(|a, b| a.0.cmp(b.0));
+        involving
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.schedules.is_empty()
+    }
+
+    pub fn add(&mut self, schedule_id: String, schedule: Schedule) {
+        self.schedules.insert(schedule_id, schedule);
+    }
+
+    pub fn cancel(&mut self, schedule_id: &str) {
+        self.schedules.remove(schedule_id);
+    }
+
+    /// Records the payment of a schedule in the block at the given height
+    pub fn pay(&mut self, schedule_id: &str, height: u64) {
+        if let Some(schedule) = self.schedules.get_mut(schedule_id) {
+            schedule.paid_at = Some(height);
+        }
+    }
+
+    /// Removes the schedules with no payment left after the block at the given height
+    pub fn finish(&mut self, height: u64) {
+        self.schedules.retain(|_, schedule| schedule.next_payment(height + 1).is_some());
+    }
+}
@@ -0,0 +1,122 @@
+//! The shard a node in sharded mode holds, see [crate::shard]. The node only accepts requests about the accounts of its
+//! shard, so its chain only ever holds those accounts, and it mints its blocks independently of the other shards.
+//! Plain transfers to accounts of other shards are made in two phases if the shard map lists the node IDs of the
+//! shards, see [super::cross_shard], other payments to accounts of other shards are rejected.
+
+use crate::common::{CreditCrossShardOp, Operation};
+use crate::shard::ShardMap;
+use super::genesis::Genesis;
+
+/// The shard of a node and the shard map of its cluster
+pub struct Shard {
+    map: ShardMap,
+    index: usize,
+}
+
+impl Shard {
+    /// Returns the shard with the given index, or why the map has no such shard
+    pub fn new(map: ShardMap, index: usize) -> Result<Shard, String> {
+        if index >= map.len() {
+            return Err(format!("Invalid shard {}, the shard map only has shards 0 to {}.", index, map.len() - 1));
+        }
+        Ok(Shard { map, index })
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns the address of the node of a shard
+    pub fn node(&self, shard: usize) -> &str {
+        self.map.node(shard)
+    }
+
+    /// Returns the node ID of the node of a shard, None if the shard map doesn't list the node IDs
+    pub fn node_id(&self, shard: usize) -> Option<&str> {
+        self.map.node_id(shard)
+    }
+
+    /// Whether funds can move between the shards, which needs the node IDs of the shards in the shard map
+    pub fn transfers_between_shards(&self) -> bool {
+        self.map.node_id(self.index).is_some()
+    }
+
+    /// Returns the shard of an account if it belongs to another shard
+    pub fn foreign_shard(&self, account_id: &str) -> Option<usize> {
+        let shard = self.map.shard_of(account_id);
+        (shard != self.index).then_some(shard)
+    }
+
+    /// Checks that the accounts of the genesis configuration belong to the shard, as every shard starts its own chain
+    pub fn check_genesis(&self, genesis: &Genesis) -> Result<(), String> {
+        match genesis.accounts.iter().find(|account| self.map.shard_of(&account.account_id) != self.index) {
+            Some(account) => Err(format!(
+                "Genesis account {} belongs to shard {}, the genesis configuration of shard {} can only hold its own accounts.",
+                account.account_id,
+                self.map.shard_of(&account.account_id),
+                self.index
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the accounts an operation is about, changes and pays belong to the shard
+    ///
+    /// Returns: a user-facing error naming the node of the account's shard if one doesn't
+    pub fn check(&self, operation: &Operation) -> Result<(), String> {
+        let mut accounts = operation.account().into_iter().chain(operation.changed_accounts());
+        if let Some(account_id) = accounts.find(|account_id| self.map.shard_of(account_id) != self.index) {
+            let shard = self.map.shard_of(account_id);
+            return Err(format!("❌ Account {} belongs to shard {} at {}, this node holds shard {}.", account_id, shard, self.map.node(shard), self.index));
+        }
+        // Plain transfers move funds between shards in two phases, other payments can't
+        if matches!(operation, Operation::TransferFunds(_)) && self.transfers_between_shards() {
+            return Ok(());
+        }
+        if let Some(account_id) = receiving_accounts(operation).into_iter().find(|account_id| self.map.shard_of(account_id) != self.index) {
+            let shard = self.map.shard_of(account_id);
+            return Err(match self.transfers_between_shards() {
+                true => format!("❌ Account {} belongs to shard {}, only `transfer` moves funds between shards.", account_id, shard),
+                false => format!("❌ Account {} belongs to shard {}, transfers between shards need the node IDs of the shards in the shard map.", account_id, shard),
+            });
+        }
+        Ok(())
+    }
+
+    /// Checks that a credit of a cross-shard transfer is sent by the node of the sender's shard to the shard of the
+    /// recipient
+    ///
+    /// Returns: a user-facing error if it isn't
+    pub fn check_credit(&self, from_node: &str, credit_info: &CreditCrossShardOp) -> Result<(), String> {
+        if credit_info.shard == self.index || credit_info.shard >= self.map.len() || self.map.shard_of(&credit_info.sender) != credit_info.shard {
+            return Err(format!("❌ Account {} doesn't belong to shard {}, or it isn't another shard than shard {}.", credit_info.sender, credit_info.shard, self.index));
+        }
+        if let Some(shard) = self.foreign_shard(&credit_info.recipient) {
+            return Err(format!("❌ Account {} belongs to shard {}, this node holds shard {}.", credit_info.recipient, shard, self.index));
+        }
+        if self.map.node_id(credit_info.shard) != Some(from_node) {
+            return Err(format!("❌ Only the node of shard {} credits its transfers, the shard map lists another node ID for it.", credit_info.shard));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the accounts an operation pays
+fn receiving_accounts(operation: &Operation) -> Vec<&str> {
+    match operation {
+        Operation::TransferFunds(op) | Operation::Simulate(op) => vec![&op.to_account_id],
+        Operation::BatchTransfer(op) => op.transfers.iter().map(|transfer| transfer.to_account_id.as_str()).collect(),
+        Operation::TransferToken(op) => vec![&op.transfer.to_account_id],
+        Operation::EscrowTransfer(op) => vec![&op.transfer.to_account_id],
+        Operation::ExpiringTransfer(op) => vec![&op.transfer.to_account_id],
+        Operation::SchedulePayment(op) => vec![&op.transfer.to_account_id],
+        Operation::OfferSwap(op) => vec![&op.transfer.to_account_id],
+        Operation::TransferNft(op) => vec![&op.to_account_id],
+        Operation::CloseAccount(op) => vec![&op.beneficiary_id],
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,128 @@
+//! Slashing of validators that sign conflicting blocks. A node started with `--validator-key` signs the blocks it mints
+//! for its reward account with that key, which has to be one of the keys of the reward account as a multisig account,
+//! so every node can attribute the block to the validator. An honest validator never mints two blocks at the same
+//! height, so two blocks signed for the same validator at the same height with different hashes prove that it minted
+//! competing chains. A node that receives such a pair keeps it as evidence and adds it to the next block it mints with
+//! a slash transaction, which moves the whole stake of the offender to the slashed account, where it can never be
+//! spent, so the offender neither mints in proof-of-stake mode nor earns staking rewards anymore. Every offence is only
+//! slashed once, evidence has to be of a height above the offence the validator was slashed for last.
+
+use std::collections::HashMap;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use crate::keys;
+use super::multisig::MultisigPolicy;
+use super::{Block, BlockHeader};
+
+/// Account holding the slashed stakes. Funds only get into it by slashing and never leave it.
+pub const SLASHED_ACCOUNT: &str = "@slashed";
+
+/// Signature of a block for the validator that minted it. The signature isn't covered by the hash of the block.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct BlockSignature {
+    // The account the block reward pays
+    pub validator: String,
+    // Hex encoded public key, one of the keys of the validator's multisig account
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// The header of a signed block, enough to check the signature as it signs the hash of the header
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SignedHeader {
+    pub header: BlockHeader,
+    pub signature: BlockSignature,
+}
+
+/// Two blocks signed for the same validator at the same height
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Evidence {
+    pub headers: [SignedHeader; 2],
+}
+
+impl BlockSignature {
+    /// Signs a block for the given validator
+    pub fn new(block: &Block, validator: &str, key: &SigningKey) -> BlockSignature {
+        BlockSignature {
+            validator: validator.to_string(),
+            public_key: keys::public_key_hex(key),
+            signature: keys::sign_block(key, &block.hash, validator),
+        }
+    }
+
+    /// Checks that the signature of the block with the given hash is valid and made with a key of its validator
+    fn check(&self, block_hash: &str, multisig: &HashMap<String, MultisigPolicy>) -> Result<(), String> {
+        match multisig.get(&self.validator) {
+            Some(policy) if policy.public_keys.contains(&self.public_key) => keys::verify_block(&self.public_key, block_hash, &self.validator, &self.signature),
+            _ => Err(format!("block {} is signed with {}, which isn't a key of the multisig account of validator {}", block_hash, self.public_key, self.validator)),
+        }
+    }
+}
+
+/// Checks the signature of a block, if it has one, against the multisig accounts before it. A block is signed for the
+/// account its block reward pays.
+///
+/// Returns: why the signature is invalid
+pub fn check_signature(block: &Block, multisig: &HashMap<String, MultisigPolicy>) -> Result<(), String> {
+    let Some(signature) = &block.signature else {
+        return Ok(());
+    };
+    match block.transactions.last().filter(|transaction| transaction.is_reward()) {
+        Some(reward) if reward.to_account_id == signature.validator => signature.check(&block.hash, multisig),
+        _ => Err(format!("it is signed for validator {}, but its block reward doesn't pay it", signature.validator)),
+    }
+}
+
+/// Checks that a block is signed by its validator if the validator is a multisig account, so its blocks can't be
+/// attributed to it without its keys
+///
+/// Returns: why the block isn't signed by its validator
+pub fn check_signed(block: &Block, multisig: &HashMap<String, MultisigPolicy>) -> Result<(), String> {
+    let validator = block.transactions.last().filter(|transaction| transaction.is_reward()).map(|reward| &reward.to_account_id);
+    match (validator, &block.signature) {
+        (Some(validator), None) if multisig.contains_key(validator) => Err(format!("it isn't signed by validator {}, which is a multisig account", validator)),
+        _ => Ok(()),
+    }
+}
+
+impl Evidence {
+    /// Returns the evidence of two different blocks signed for the same validator at the same height, None if they
+    /// don't conflict. The signatures aren't checked, see [Evidence::check].
+    pub fn of(block: &Block, other: &Block) -> Option<Evidence> {
+        match (&block.signature, &other.signature) {
+            (Some(signature), Some(other_signature))
+                if signature.validator == other_signature.validator && block.header.height == other.header.height && block.hash != other.hash =>
+            {
+                let signed = |block: &Block, signature: &BlockSignature| SignedHeader { header: block.header.clone(), signature: signature.clone() };
+                Some(Evidence { headers: [signed(block, signature), signed(other, other_signature)] })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn validator(&self) -> &str {
+        &self.headers[0].signature.validator
+    }
+
+    /// Returns the height of the offence
+    pub fn height(&self) -> u64 {
+        self.headers[0].header.height
+    }
+
+    /// Checks that the evidence proves an offence: both headers are at the same height with different hashes and
+    /// signed for the same validator with keys of its multisig account
+    ///
+    /// Returns: why the evidence doesn't prove an offence
+    pub fn check(&self, multisig: &HashMap<String, MultisigPolicy>) -> Result<(), String> {
+        let [first, second] = &self.headers;
+        if first.header.height != second.header.height || first.signature.validator != second.signature.validator {
+            return Err("its blocks aren't signed for the same validator at the same height".to_string());
+        }
+        let (first_hash, second_hash) = (first.header.hash(), second.header.hash());
+        if first_hash == second_hash {
+            return Err(format!("both of its blocks are block {}", first_hash));
+        }
+        first.signature.check(&first_hash, multisig)?;
+        second.signature.check(&second_hash, multisig)
+    }
+}
@@ -0,0 +1,273 @@
+//! Snapshots of the state of the accounts, taken every configured number of blocks: the balances, nonces, closed,
+//! frozen and multisig accounts and stakes after the block at the snapshot's height. A node started with `--prune`
+//! discards the transactions of the blocks before its latest snapshot, keeping only their headers, and restores and
+//! verifies its state from the snapshot instead of replaying every transaction since the genesis block. Once a data
+//! directory is pruned, it stays pruned.
+//!
+//! `snapshot create` writes the state of the accounts at a height to a snapshot file, together with the blocks up to it
+//! with the transactions of the blocks before it pruned, and `snapshot restore` starts a new data directory from it. A
+//! node started on it is pruned up to the snapshot and only syncs the blocks after it from its peers.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use super::storage::{Storage, FORMAT_VERSION};
+use super::verify::Accounts;
+use super::{load_genesis, verify, Block};
+use crate::common::Amount;
+use crate::vault::{Secret, Vault};
+
+/// Name of the file in the data directory the latest snapshot is stored in
+const SNAPSHOT_FILE: &str = "snapshot.bin";
+
+/// Prefix of a snapshot file, followed by the format version of the ledger (2 bytes, little endian) its blocks and
+/// accounts are encoded in
+const MAGIC: &[u8; 3] = b"TCS";
+
+/// The state of the accounts after the block at a height
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub height: usize,
+    // Hash of the block at the height
+    pub hash: String,
+    pub accounts: Accounts,
+    // Whether the transactions of the blocks before the snapshot were discarded
+    pub pruned: bool,
+}
+
+/// A snapshot file to start a new data directory from: the snapshot and the blocks up to its height, the blocks before
+/// it only with their headers
+#[derive(Serialize, Deserialize)]
+struct SnapshotFile {
+    chain_id: String,
+    genesis_hash: String,
+    snapshot: Snapshot,
+    blocks: Vec<Block>,
+}
+
+/// The latest snapshot of the chain
+pub struct Snapshots {
+    // Number of blocks between snapshots, no snapshots are taken if not set
+    interval: Option<usize>,
+    // Whether the blocks before the latest snapshot are pruned
+    prune: bool,
+    // File the latest snapshot is stored in, it is only kept in memory if not set
+    path: Option<PathBuf>,
+    latest: Option<Snapshot>,
+    // Seals the snapshot file if the data directory is encrypted
+    vault: Option<Vault>,
+}
+
+impl Snapshots {
+    /// Loads the latest snapshot stored in the given data directory, none if it has no snapshot file yet
+    pub fn load(data_dir: Option<&str>, interval: Option<usize>, prune: bool, vault: Option<&Vault>) -> Result<Snapshots, String> {
+        let path = data_dir.map(|data_dir| Path::new(data_dir).join(SNAPSHOT_FILE));
+        let latest = match &path {
+            Some(path) => match std::fs::read(path) {
+                Ok(bytes) => Some(
+                    Vault::open_with(vault, &bytes)
+                        .and_then(|bytes| bincode::deserialize(&bytes).map_err(|e| e.to_string()))
+                        .map_err(|e| format!("Invalid snapshot file {}: {}", path.display(), e))?,
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => return Err(format!("Failed to read snapshot file {}: {}", path.display(), e)),
+            },
+            None => None,
+        };
+        // The transactions of a pruned data directory can't be restored, so it is pruned further
+        let prune = prune || latest.as_ref().is_some_and(|snapshot: &Snapshot| snapshot.pruned);
+        Ok(Snapshots { interval, prune, path, latest, vault: vault.cloned() })
+    }
+
+    pub fn latest(&self) -> Option<&Snapshot> {
+        self.latest.as_ref()
+    }
+
+    /// Returns the snapshot the blocks before were pruned up to, which the state has to be restored from
+    pub fn pruned(&self) -> Option<&Snapshot> {
+        self.latest.as_ref().filter(|snapshot| snapshot.pruned)
+    }
+
+    pub fn is_pruning(&self) -> bool {
+        self.prune
+    }
+
+    /// Takes the snapshots among the blocks of the main chain from the given height onwards, and prunes the blocks
+    /// before the latest one if the node prunes. The state is replayed from the previous snapshot if it is still on
+    /// the chain, or from the genesis block otherwise, with the maximum supply of the chain.
+    ///
+    /// Returns: whether blocks were pruned, so the ledger file has to be rewritten
+    pub fn record(&mut self, ledger: &mut [Block], from_height: usize, max_supply: Option<Amount>) -> Result<bool, String> {
+        let Some(interval) = self.interval else {
+            return Ok(false);
+        };
+        // A snapshot of blocks that were replaced by a fork is of no use anymore
+        if self.latest.as_ref().is_some_and(|latest| ledger.get(latest.height).is_none_or(|block| block.hash != latest.hash)) {
+            self.latest = None;
+        }
+
+        // A snapshot taken before the node pruned prunes the blocks before it right away
+        let mut taken = false;
+        if let Some(latest) = self.latest.as_mut().filter(|latest| self.prune && !latest.pruned) {
+            latest.pruned = true;
+            taken = true;
+        }
+        for height in from_height.max(1)..ledger.len() {
+            if !height.is_multiple_of(interval) || self.latest.as_ref().is_some_and(|latest| latest.height >= height) {
+                continue;
+            }
+            let (mut accounts, start) = match &self.latest {
+                Some(latest) => (latest.accounts.clone(), latest.height + 1),
+                None => (Accounts::default(), 0),
+            };
+            for (block_height, block) in ledger.iter().enumerate().take(height + 1).skip(start) {
+                accounts
+                    .apply_block(block_height, block, max_supply)
+                    .map_err(|e| format!("Failed to take the snapshot at height {}, block {} is invalid: {}.", height, block_height, e))?;
+            }
+            self.latest = Some(Snapshot { height, hash: ledger[height].hash.clone(), accounts, pruned: self.prune });
+            tracing::info!(height, hash = %ledger[height].hash, "Took a snapshot of the accounts.");
+            taken = true;
+        }
+        if !taken {
+            return Ok(false);
+        }
+        self.save()?;
+
+        let Some(snapshot) = self.pruned() else {
+            return Ok(false);
+        };
+        let mut pruned = 0;
+        for block in &mut ledger[..snapshot.height] {
+            if !block.transactions.is_empty() {
+                block.transactions = Vec::new();
+                pruned += 1;
+            }
+        }
+        tracing::info!(pruned, height = snapshot.height, "Pruned the transactions of the blocks before the snapshot.");
+        Ok(pruned > 0)
+    }
+
+    /// Saves the latest snapshot, written next to the old file and then moved over it so a failed write keeps the old
+    /// file
+    fn save(&self) -> Result<(), String> {
+        let (Some(path), Some(latest)) = (&self.path, &self.latest) else {
+            return Ok(());
+        };
+        let temporary = path.with_extension("bin.tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&temporary)?;
+            file.write_all(&Vault::seal_with(self.vault.as_ref(), bincode::serialize(latest).unwrap()))?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, path)
+        };
+        write().map_err(|e| format!("Failed to save snapshot file {}: {}", path.display(), e))
+    }
+}
+
+/// Writes a snapshot of the chain stored in a data directory to a snapshot file, after verifying the chain up to it
+///
+/// # Arguments
+///
+/// * `data_dir`: the data directory of the node
+/// * `path`: the snapshot file to write
+/// * `height`: the height to take the snapshot at, the tip of the chain if not given
+/// * `genesis`: path to the genesis configuration the chain was started from, the default genesis if not given
+/// * `secret`: the passphrase or key file the data directory is encrypted with, if it is
+///
+/// Returns: a user-facing message, either confirming the snapshot or explaining why it can't be taken
+pub fn create_snapshot(data_dir: &str, path: &str, height: Option<usize>, genesis: Option<&str>, secret: Option<&Secret>) -> Result<String, String> {
+    let genesis = load_genesis(genesis)?;
+    let genesis_block = genesis.to_block();
+    let vault = Vault::unlock(data_dir, secret, false).map_err(|e| format!("❌ {}", e))?;
+    let mut blocks = Storage::read(data_dir, vault.as_ref())?;
+    let snapshots = Snapshots::load(Some(data_dir), None, false, vault.as_ref()).map_err(|e| format!("❌ {}", e))?;
+
+    let height = height.unwrap_or(blocks.len().saturating_sub(1));
+    if height >= blocks.len() {
+        return Err(format!("❌ The chain ends at height {}, it has no block at height {}.", blocks.len().saturating_sub(1), height));
+    }
+    if let Some(pruned) = snapshots.pruned().filter(|pruned| height < pruned.height) {
+        return Err(format!("❌ The transactions of the blocks before the snapshot at height {} are pruned, take the snapshot at or after it.", pruned.height));
+    }
+    blocks.truncate(height + 1);
+    let accounts = verify::verify_chain_with(&blocks, &genesis, snapshots.pruned(), |_, _, _| Ok(())).map_err(|e| format!("❌ {}", e))?;
+    // The blocks before the snapshot are only needed to link it to the genesis block
+    for block in &mut blocks[..height] {
+        block.transactions = Vec::new();
+    }
+
+    let file = SnapshotFile {
+        chain_id: genesis.chain_id,
+        genesis_hash: genesis_block.hash,
+        snapshot: Snapshot { height, hash: blocks[height].hash.clone(), accounts, pruned: true },
+        blocks,
+    };
+    let bytes = [MAGIC.as_slice(), &FORMAT_VERSION.to_le_bytes(), &bincode::serialize(&file).unwrap()].concat();
+    std::fs::write(path, bytes).map_err(|e| format!("❌ Failed to write {}: {}", path, e))?;
+
+    Ok(format!("✅ Wrote the snapshot of chain {} at height {} ({}) to {}.", file.chain_id, height, file.snapshot.hash, path))
+}
+
+/// Restores a snapshot file into an empty data directory. The blocks up to the snapshot are verified to link it to the
+/// genesis block of the given genesis configuration, but the accounts of the snapshot can't be verified without the
+/// transactions before it, so only snapshots of trusted nodes should be restored.
+///
+/// # Arguments
+///
+/// * `path`: the snapshot file
+/// * `data_dir`: the data directory to restore into
+/// * `genesis`: path to the genesis configuration of the chain, the default genesis if not given
+/// * `secret`: the passphrase or key file to encrypt a new data directory with, or the existing one is encrypted with
+///
+/// Returns: a user-facing message, either confirming the restore or explaining why it was rejected
+pub fn restore_snapshot(path: &str, data_dir: &str, genesis: Option<&str>, secret: Option<&Secret>) -> Result<String, String> {
+    let genesis = load_genesis(genesis)?;
+    let bytes = std::fs::read(path).map_err(|e| format!("❌ Failed to read {}: {}", path, e))?;
+    let file: SnapshotFile = match bytes.strip_prefix(MAGIC).and_then(|rest| rest.split_at_checked(2)) {
+        Some((version, rest)) if u16::from_le_bytes(version.try_into().unwrap()) == FORMAT_VERSION => {
+            bincode::deserialize(rest).map_err(|e| format!("❌ Failed to parse {}: {}", path, e))?
+        }
+        Some((version, _)) => {
+            return Err(format!(
+                "❌ {} is a snapshot of format version {}, but this node uses format version {}. Take the snapshot again with a node of this version.",
+                path,
+                u16::from_le_bytes(version.try_into().unwrap()),
+                FORMAT_VERSION
+            ))
+        }
+        None => return Err(format!("❌ {} is not a snapshot file.", path)),
+    };
+
+    if file.chain_id != genesis.chain_id {
+        return Err(format!("❌ {} holds chain {}, but the genesis configuration is for chain {}.", path, file.chain_id, genesis.chain_id));
+    }
+    let genesis_block = genesis.to_block();
+    if file.genesis_hash != genesis_block.hash {
+        return Err(format!("❌ {} starts with genesis block {}, but the genesis configuration gives {}.", path, file.genesis_hash, genesis_block.hash));
+    }
+    if file.blocks.len() != file.snapshot.height + 1 || !file.snapshot.pruned {
+        return Err(format!("❌ {} is invalid, it doesn't end with the block of its snapshot at height {}.", path, file.snapshot.height));
+    }
+    verify::verify_chain_with(&file.blocks, &genesis, Some(&file.snapshot), |_, _, _| Ok(())).map_err(|e| format!("❌ {}", e))?;
+
+    let vault = Vault::unlock(data_dir, secret, true).map_err(|e| format!("❌ {}", e))?;
+    let (mut storage, stored_blocks) = Storage::open(data_dir, vault.as_ref())?;
+    if !stored_blocks.is_empty() {
+        return Err(format!("❌ The data directory {} already holds a chain with {} blocks.", data_dir, stored_blocks.len()));
+    }
+    for block in &file.blocks {
+        storage.append(block)?;
+    }
+    storage.sync()?;
+    let mut snapshots = Snapshots::load(Some(data_dir), None, true, vault.as_ref()).map_err(|e| format!("❌ {}", e))?;
+    snapshots.latest = Some(file.snapshot);
+    snapshots.save().map_err(|e| format!("❌ {}", e))?;
+
+    Ok(format!(
+        "✅ Restored the snapshot of chain {} at height {} into {}. A node started on it syncs the blocks after it from its peers.",
+        file.chain_id,
+        file.blocks.len() - 1,
+        data_dir
+    ))
+}
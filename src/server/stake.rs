@@ -0,0 +1,96 @@
+//! Proof-of-stake minting, selected with `--consensus pos`, and staking rewards. Accounts register as validators by
+//! staking funds, which moves them to the staking account where they can't be spent until they are unstaked. Time is
+//! divided into slots of the mint interval, and every slot belongs to one validator, picked by the hash of the previous
+//! block and the slot weighted by stake. Only that validator mints a block in the slot, and its block reward names it,
+//! so other nodes can check that a block was minted by the validator of its slot.
+//!
+//! Independent of the consensus, the chain is divided into epochs of blocks. The block at the start of every epoch
+//! mints the staking reward decided by governance and distributes it to the stakers in proportion to their stake with
+//! staking reward transactions, which every node derives from the stakes before the block.
+
+use std::collections::HashMap;
+use crate::common::Amount;
+use crate::merkle::sha256_hex;
+use super::governance::Parameters;
+use super::Block;
+
+/// Account holding the staked funds. Funds only get into it by staking and only leave it by unstaking.
+pub const STAKE_ACCOUNT: &str = "@stake";
+
+/// Number of blocks in an epoch unless governance decided on another length
+pub const DEFAULT_EPOCH_LENGTH: u64 = 100;
+
+/// Picks the validator of a slot on top of the block with the given hash, with a chance proportional to its stake.
+/// The pick only depends on the chain, so every node picks the same validator.
+///
+/// Returns: None if no account staked anything yet
+pub fn validator_for<'a>(stakes: &'a HashMap<String, Amount>, previous_hash: &str, slot: u64) -> Option<&'a str> {
+    let mut validators: Vec<(&String, u128)> = stakes
+        .iter()
+        .filter(|(_, stake)| **stake > Amount::ZERO)
+        .map(|(account_id, stake)| (account_id, stake.minor_units() as u128))
+        .collect();
+    validators.sort();
+    let total: u128 = validators.iter().map(|(_, stake)| stake).sum();
+    if total == 0 {
+        return None;
+    }
+
+    let seed = sha256_hex(format!("toychain slot {} after {}", slot, previous_hash).as_bytes());
+    let mut target = u128::from_str_radix(&seed[..32], 16).unwrap() % total;
+    for (account_id, stake) in validators {
+        if target < stake {
+            return Some(account_id);
+        }
+        target -= stake;
+    }
+    unreachable!("The target is below the total stake.")
+}
+
+/// Returns the slot a block was minted in
+pub fn slot_of(block: &Block, slot_length: u64) -> u64 {
+    block.header.timestamp / slot_length.max(1)
+}
+
+/// Checks that a block was minted by the validator of its slot. Until an account staked anything, any node can mint,
+/// so that the first validators can register.
+///
+/// Returns: why the block isn't minted by its validator
+pub fn check_validator(block: &Block, stakes: &HashMap<String, Amount>, slot_length: u64) -> Result<(), String> {
+    let slot = slot_of(block, slot_length);
+    let Some(validator) = validator_for(stakes, &block.header.previous_hash, slot) else {
+        return Ok(());
+    };
+    match block.transactions.last().filter(|transaction| transaction.is_reward()) {
+        Some(reward) if reward.to_account_id == validator => Ok(()),
+        Some(reward) => Err(format!("it was minted by {} in slot {}, which belongs to validator {}", reward.to_account_id, slot, validator)),
+        None => Err(format!("it has no block reward naming the validator of slot {}", slot)),
+    }
+}
+
+/// Distributes the staking reward of the block at the given height to the stakers in proportion to their stake, if the
+/// block starts an epoch. The reward is cut to what is left to issue below the maximum supply, if there is one. Shares
+/// are rounded down to minor units, so the rounding remainder is never minted.
+///
+/// Returns: the stakers with their shares, sorted by account ID, empty if no reward is due at the height
+pub fn epoch_rewards(stakes: &HashMap<String, Amount>, parameters: &Parameters, height: u64, issuable: Option<Amount>) -> Vec<(String, Amount)> {
+    let reward = parameters.staking_reward.unwrap_or_default();
+    let reward = issuable.map_or(reward, |issuable| reward.min(issuable));
+    let epoch_length = parameters.epoch_length.unwrap_or(DEFAULT_EPOCH_LENGTH);
+    if height == 0 || !height.is_multiple_of(epoch_length) || reward <= Amount::ZERO {
+        return Vec::new();
+    }
+    let mut stakers: Vec<(&String, i128)> = stakes.iter().filter(|(_, stake)| **stake > Amount::ZERO).map(|(account_id, stake)| (account_id, stake.minor_units() as i128)).collect();
+    stakers.sort();
+    let total: i128 = stakers.iter().map(|(_, stake)| stake).sum();
+    stakers
+        .into_iter()
+        .map(|(account_id, stake)| (account_id.clone(), Amount::from_minor_units((reward.minor_units() as i128 * stake / total) as i64)))
+        .filter(|(_, share)| *share > Amount::ZERO)
+        .collect()
+}
+
+/// Returns the epoch the block at the given height starts
+pub fn epoch_of(parameters: &Parameters, height: u64) -> u64 {
+    height / parameters.epoch_length.unwrap_or(DEFAULT_EPOCH_LENGTH)
+}
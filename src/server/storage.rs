@@ -0,0 +1,484 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use crate::common::Amount;
+use crate::vault::Vault;
+use super::compaction::Archive;
+use super::migration;
+use super::slashing::BlockSignature;
+use super::{Block, BlockHeader, Transaction, TransactionKind};
+
+/// Name of the file in the data directory the blocks are stored in
+const LEDGER_FILE: &str = "ledger.bin";
+
+/// Prefix of ledger files, followed by the format version (2 bytes, little endian). Ledger files of format version 1
+/// have no header and start right with the first block.
+const MAGIC: &[u8; 3] = b"TCL";
+
+/// Format version of the ledger file. Version 1 had no header, and every later version is a migration of the blocks of
+/// the version before it, see [super::migration::MIGRATIONS].
+pub(super) const FORMAT_VERSION: u16 = 21;
+
+/// Length of the header of ledger files of the current format version
+const HEADER_LENGTH: usize = MAGIC.len() + 2;
+
+/// Append-only storage of the ledger in the node's data directory. After the header, every block is stored as its
+/// length (4 bytes, little endian) followed by the bincode encoded block, so blocks can be appended as they are minted.
+/// The oldest blocks may be compacted into the archive file, see [super::compaction], in which case the ledger file
+/// holds the blocks after them.
+pub struct Storage {
+    file: File,
+    path: PathBuf,
+    archive: Archive,
+    // Number of blocks in the ledger file
+    blocks: usize,
+    // Seals the blocks if the data directory is encrypted
+    vault: Option<Vault>,
+}
+
+impl Storage {
+    /// Opens the ledger file in the given data directory, creating the directory and file if they don't exist yet.
+    /// Ledger files of an earlier format version are upgraded to the current one.
+    ///
+    /// Returns: the storage and the blocks stored so far, oldest first
+    pub fn open(data_dir: &str, vault: Option<&Vault>) -> Result<(Storage, Vec<Block>), String> {
+        std::fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create data directory {}: {}", data_dir, e))?;
+        let path = Path::new(data_dir).join(LEDGER_FILE);
+        let mut file = open_ledger_file(&path)?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents).map_err(|e| format!("Failed to read ledger file {}: {}", path.display(), e))?;
+        let (mut blocks, offset, version) = parse_ledger(&contents, &path, vault)?;
+        let (mut archive, mut archived) = Archive::read(Path::new(data_dir), vault)?;
+        archive.upgrade(&archived)?;
+        let duplicates = skip_archived(&mut blocks, archived.len(), &path)?;
+
+        if contents.is_empty() {
+            file.write_all(&header()).map_err(|e| format!("Failed to write ledger file {}: {}", path.display(), e))?;
+        } else if version < FORMAT_VERSION {
+            file = write_ledger(&path, &blocks, vault)?;
+            for migration in migration::pending(version) {
+                tracing::debug!("Migrated ledger file {} to format version {}: {}.", path.display(), migration.version, migration.description);
+            }
+            tracing::info!("Upgraded ledger file {} from format version {} to {}.", path.display(), version, FORMAT_VERSION);
+        } else if duplicates > 0 {
+            // The node was stopped while compacting, after the blocks were archived but before they were removed
+            file = write_ledger(&path, &blocks, vault)?;
+            tracing::warn!("Removed {} blocks from ledger file {} that are already archived.", duplicates, path.display());
+        } else if offset < contents.len() {
+            // A block that was only partially written (e.g. the node was killed while writing it) was never
+            // acknowledged as stored, so it is dropped instead of failing to start
+            tracing::warn!("Dropping incomplete block at the end of ledger file {}.", path.display());
+            file.set_len(offset as u64).map_err(|e| format!("Failed to truncate ledger file {}: {}", path.display(), e))?;
+            file.seek(SeekFrom::End(0)).map_err(|e| format!("Failed to truncate ledger file {}: {}", path.display(), e))?;
+        }
+
+        let length = blocks.len();
+        archived.extend(blocks);
+        Ok((Storage { file, path, archive, blocks: length, vault: vault.cloned() }, archived))
+    }
+
+    /// Reads the blocks stored in the given data directory without modifying it
+    pub fn read(data_dir: &str, vault: Option<&Vault>) -> Result<Vec<Block>, String> {
+        let path = Path::new(data_dir).join(LEDGER_FILE);
+        let contents = std::fs::read(&path).map_err(|e| format!("Failed to read ledger file {}: {}", path.display(), e))?;
+        let (mut blocks, offset, _) = parse_ledger(&contents, &path, vault)?;
+        if offset < contents.len() {
+            tracing::warn!("Ignoring incomplete block at the end of ledger file {}.", path.display());
+        }
+        let (_, mut archived) = Archive::read(Path::new(data_dir), vault)?;
+        skip_archived(&mut blocks, archived.len(), &path)?;
+        archived.extend(blocks);
+        Ok(archived)
+    }
+
+    /// Reads the format version of the ledger file in the given data directory without modifying it. All its blocks are
+    /// parsed, so a file that can't be upgraded fails to be read.
+    ///
+    /// Returns: the format version and number of blocks of the ledger file, None if the data directory has none
+    pub fn format_version(data_dir: &str, vault: Option<&Vault>) -> Result<Option<(u16, usize)>, String> {
+        let path = Path::new(data_dir).join(LEDGER_FILE);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) if contents.is_empty() => return Ok(None),
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read ledger file {}: {}", path.display(), e)),
+        };
+        let (blocks, _, version) = parse_ledger(&contents, &path, vault)?;
+        Ok(Some((version, blocks.len())))
+    }
+
+    /// Appends a block to the end of the ledger file
+    pub fn append(&mut self, block: &Block) -> Result<(), String> {
+        self.file.write_all(&record(block, self.vault.as_ref())).map_err(|e| format!("Failed to write block {}: {}", block.hash, e))?;
+        self.blocks += 1;
+        Ok(())
+    }
+
+    /// Replaces all stored blocks, e.g. once the node switched to a fork whose blocks replace the last blocks
+    pub fn rewrite(&mut self, blocks: &[Block]) -> Result<(), String> {
+        let archived = self.archive.rewrite(blocks)?;
+        self.file = write_ledger(&self.path, &blocks[archived..], self.vault.as_ref())?;
+        self.blocks = blocks.len() - archived;
+        Ok(())
+    }
+
+    /// Returns the number of blocks compacted into the archive
+    pub fn archived(&self) -> usize {
+        self.archive.len()
+    }
+
+    /// Compacts the blocks of the ledger file before the given number of latest blocks into a new segment of the
+    /// archive, once there are at least as many of them. The blocks are archived before they are removed from the
+    /// ledger file, so a node stopped in between still has them.
+    ///
+    /// Returns: the number of blocks compacted
+    pub fn compact(&mut self, keep: usize) -> Result<usize, String> {
+        if self.blocks < 2 * keep {
+            return Ok(0);
+        }
+        let contents = std::fs::read(&self.path).map_err(|e| format!("Failed to read ledger file {}: {}", self.path.display(), e))?;
+        let (blocks, _, _) = parse_ledger(&contents, &self.path, self.vault.as_ref())?;
+        let count = blocks.len().saturating_sub(keep);
+        if count == 0 {
+            return Ok(0);
+        }
+        self.archive.append(&blocks[..count])?;
+        self.file = write_ledger(&self.path, &blocks[count..], self.vault.as_ref())?;
+        self.blocks = blocks.len() - count;
+        Ok(count)
+    }
+
+    /// Flushes all appended blocks to disk
+    pub fn sync(&mut self) -> Result<(), String> {
+        self.file.sync_all().map_err(|e| format!("Failed to flush ledger file: {}", e))
+    }
+}
+
+/// Removes the blocks at the start of the ledger file that are already in the archive, and checks that the ledger file
+/// continues right after the archived blocks
+///
+/// Returns: the number of blocks removed
+fn skip_archived(blocks: &mut Vec<Block>, archived: usize, path: &Path) -> Result<usize, String> {
+    if archived == 0 {
+        return Ok(0);
+    }
+    let duplicates = blocks.iter().take_while(|block| (block.header.height as usize) < archived).count();
+    blocks.drain(..duplicates);
+    match blocks.first() {
+        Some(block) if block.header.height as usize != archived => Err(format!(
+            "Ledger file {} continues at height {}, but the archive ends at height {}.",
+            path.display(),
+            block.header.height,
+            archived - 1
+        )),
+        _ => Ok(duplicates),
+    }
+}
+
+fn open_ledger_file(path: &Path) -> Result<File, String> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| format!("Failed to open ledger file {}: {}", path.display(), e))
+}
+
+fn header() -> Vec<u8> {
+    [MAGIC.as_slice(), &FORMAT_VERSION.to_le_bytes()].concat()
+}
+
+/// Encodes a block as it is stored in the ledger file, sealed if the data directory is encrypted
+fn record(block: &Block, vault: Option<&Vault>) -> Vec<u8> {
+    let bytes = Vault::seal_with(vault, bincode::serialize(block).unwrap());
+    let mut record = Vec::with_capacity(4 + bytes.len());
+    record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    record.extend_from_slice(&bytes);
+    record
+}
+
+/// Rewrites a ledger file with the given blocks in the current format version, e.g. to upgrade a file of an earlier
+/// one. The new file is written next to the old one and then moved over it, so the old file stays intact if the node
+/// is stopped while it is written.
+///
+/// Returns: the new file, opened for appending blocks
+fn write_ledger(path: &Path, blocks: &[Block], vault: Option<&Vault>) -> Result<File, String> {
+    let temporary = path.with_extension("bin.tmp");
+    let mut contents = header();
+    for block in blocks {
+        contents.extend_from_slice(&record(block, vault));
+    }
+
+    let write = || -> std::io::Result<()> {
+        let mut file = File::create(&temporary)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)
+    };
+    write().map_err(|e| format!("Failed to rewrite ledger file {}: {}", path.display(), e))?;
+    open_ledger_file(path)
+}
+
+/// Parses the complete blocks of a ledger file of any format version
+///
+/// Returns: the blocks, the number of bytes the header and blocks take up and the format version of the file
+fn parse_ledger(contents: &[u8], path: &Path, vault: Option<&Vault>) -> Result<(Vec<Block>, usize, u16), String> {
+    let Some(rest) = contents.strip_prefix(MAGIC) else {
+        let (blocks, offset) = parse_blocks::<BlockV1>(contents, 0, path, vault)?;
+        return Ok((upgrade_blocks(blocks), offset, 1));
+    };
+
+    let version = rest.get(..2).map(|version| u16::from_le_bytes(version.try_into().unwrap()));
+    match version {
+        Some(2) => {
+            let (blocks, offset) = parse_blocks::<BlockV2>(contents, HEADER_LENGTH, path, vault)?;
+            Ok((upgrade_blocks(blocks), offset, 2))
+        }
+        Some(3) => {
+            let (blocks, offset) = parse_blocks::<BlockV3>(contents, HEADER_LENGTH, path, vault)?;
+            Ok((upgrade_blocks(blocks), offset, 3))
+        }
+        // Versions 5 and 6 only added transaction kinds, so the blocks of version 4 to 6 files have the same format
+        Some(version @ 4..=6) => {
+            let (blocks, offset) = parse_blocks::<BlockV6>(contents, HEADER_LENGTH, path, vault)?;
+            Ok((upgrade_blocks(blocks), offset, version))
+        }
+        // Versions 8 to 17 only added transaction kinds, so the blocks of version 7 to 17 files have the same format
+        Some(version @ 7..=17) => {
+            let (blocks, offset) = parse_blocks::<BlockV17>(contents, HEADER_LENGTH, path, vault)?;
+            Ok((upgrade_blocks(blocks), offset, version))
+        }
+        Some(18) => {
+            let (blocks, offset) = parse_blocks::<BlockV18>(contents, HEADER_LENGTH, path, vault)?;
+            Ok((upgrade_blocks(blocks), offset, 18))
+        }
+        // Versions 20 and 21 only added transaction kinds, so the blocks of version 19 files have the current format
+        Some(version @ 19..=FORMAT_VERSION) => {
+            let (blocks, offset) = parse_blocks(contents, HEADER_LENGTH, path, vault)?;
+            Ok((blocks, offset, version))
+        }
+        Some(version) => Err(format!(
+            "Ledger file {} has format version {}, this node supports versions up to {}. Upgrade the node.",
+            path.display(),
+            version,
+            FORMAT_VERSION
+        )),
+        None => Err(format!("Invalid header of ledger file {}.", path.display())),
+    }
+}
+
+/// Parses the complete blocks in the contents of a ledger file, starting at the given offset, opening them if the
+/// data directory is encrypted
+///
+/// Returns: the blocks and the offset after the last complete block
+fn parse_blocks<B: DeserializeOwned>(contents: &[u8], start: usize, path: &Path, vault: Option<&Vault>) -> Result<(Vec<B>, usize), String> {
+    let mut blocks = Vec::new();
+    let mut offset = start;
+    while let Some(length) = contents.get(offset..offset + 4) {
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        let Some(bytes) = contents.get(offset + 4..offset + 4 + length) else { break };
+        let block = Vault::open_with(vault, bytes)
+            .and_then(|bytes| bincode::deserialize(&bytes).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to parse block {} of ledger file {}: {}", blocks.len(), path.display(), e))?;
+        blocks.push(block);
+        offset += 4 + length;
+    }
+    Ok((blocks, offset))
+}
+
+/// A block of an earlier format version, whose transactions are of the given format. Also the format of blocks in
+/// binary chain exports of nodes of that version.
+#[derive(Deserialize)]
+pub(super) struct LegacyBlock<T> {
+    header: LegacyBlockHeader,
+    transactions: Vec<T>,
+    hash: String,
+}
+
+/// Header of a block of format version 6 or earlier, before headers had a height and timestamp
+#[derive(Deserialize)]
+struct LegacyBlockHeader {
+    previous_hash: String,
+    merkle_root: String,
+}
+
+/// A block of format versions 7 to 17, before blocks were signed. Also the format of blocks in binary chain exports and
+/// archive files of nodes of those versions.
+#[derive(Deserialize)]
+pub(super) struct BlockV17 {
+    header: BlockHeader,
+    transactions: Vec<Transaction>,
+    hash: String,
+}
+
+/// A block of format version 18, before blocks were signed by the node that minted them. Also the format of blocks in
+/// binary chain exports and archive files of nodes of that version.
+#[derive(Deserialize)]
+pub(super) struct BlockV18 {
+    header: BlockHeader,
+    transactions: Vec<Transaction>,
+    hash: String,
+    signature: Option<BlockSignature>,
+}
+
+/// A block of any format version, which is converted to the current format once its height is known
+pub(super) trait AnyBlock {
+    fn into_block(self, height: usize) -> Block;
+}
+
+/// Converts the blocks of a chain of any format version to the current format, the first block being the genesis block
+pub(super) fn upgrade_blocks<B: AnyBlock>(blocks: Vec<B>) -> Vec<Block> {
+    blocks.into_iter().enumerate().map(|(height, block)| block.into_block(height)).collect()
+}
+
+/// A block of format version 1, before transfer memos
+pub(super) type BlockV1 = LegacyBlock<TransactionV1>;
+
+/// A block of format version 2, before block rewards
+pub(super) type BlockV2 = LegacyBlock<TransactionV2>;
+
+/// A block of format version 3, before transaction kinds
+pub(super) type BlockV3 = LegacyBlock<TransactionV3>;
+
+/// A block of format versions 4 to 6, before block heights and timestamps
+pub(super) type BlockV6 = LegacyBlock<Transaction>;
+
+#[derive(Deserialize)]
+pub(super) struct TransactionV1 {
+    node_id: String,
+    from_account_id: Option<String>,
+    to_account_id: String,
+    amount: Amount,
+    fee: Amount,
+    nonce: Option<u64>,
+    datetime: std::time::SystemTime,
+}
+
+#[derive(Deserialize)]
+pub(super) struct TransactionV2 {
+    node_id: String,
+    from_account_id: Option<String>,
+    to_account_id: String,
+    amount: Amount,
+    fee: Amount,
+    nonce: Option<u64>,
+    datetime: std::time::SystemTime,
+    memo: Option<String>,
+}
+
+// Fields that are missing in JSON exports of earlier nodes have defaults, so the exports of all earlier nodes can be
+// read as this format
+#[derive(Deserialize)]
+pub(super) struct TransactionV3 {
+    node_id: String,
+    from_account_id: Option<String>,
+    to_account_id: String,
+    amount: Amount,
+    fee: Amount,
+    nonce: Option<u64>,
+    datetime: std::time::SystemTime,
+    #[serde(default)]
+    memo: Option<String>,
+    #[serde(default)]
+    reward: bool,
+}
+
+impl From<TransactionV1> for TransactionV2 {
+    fn from(transaction: TransactionV1) -> TransactionV2 {
+        TransactionV2 {
+            node_id: transaction.node_id,
+            from_account_id: transaction.from_account_id,
+            to_account_id: transaction.to_account_id,
+            amount: transaction.amount,
+            fee: transaction.fee,
+            nonce: transaction.nonce,
+            datetime: transaction.datetime,
+            memo: None,
+        }
+    }
+}
+
+impl From<TransactionV1> for Transaction {
+    fn from(transaction: TransactionV1) -> Transaction {
+        TransactionV2::from(transaction).into()
+    }
+}
+
+impl From<TransactionV2> for TransactionV3 {
+    fn from(transaction: TransactionV2) -> TransactionV3 {
+        TransactionV3 {
+            node_id: transaction.node_id,
+            from_account_id: transaction.from_account_id,
+            to_account_id: transaction.to_account_id,
+            amount: transaction.amount,
+            fee: transaction.fee,
+            nonce: transaction.nonce,
+            datetime: transaction.datetime,
+            memo: transaction.memo,
+            reward: false,
+        }
+    }
+}
+
+impl From<TransactionV2> for Transaction {
+    fn from(transaction: TransactionV2) -> Transaction {
+        TransactionV3::from(transaction).into()
+    }
+}
+
+impl From<TransactionV3> for Transaction {
+    fn from(transaction: TransactionV3) -> Transaction {
+        Transaction {
+            node_id: transaction.node_id,
+            from_account_id: transaction.from_account_id,
+            to_account_id: transaction.to_account_id,
+            amount: transaction.amount,
+            fee: transaction.fee,
+            nonce: transaction.nonce,
+            datetime: transaction.datetime,
+            memo: transaction.memo,
+            kind: if transaction.reward { TransactionKind::Reward } else { TransactionKind::Transfer },
+        }
+    }
+}
+
+impl AnyBlock for Block {
+    fn into_block(self, _height: usize) -> Block {
+        self
+    }
+}
+
+impl AnyBlock for BlockV17 {
+    fn into_block(self, _height: usize) -> Block {
+        Block { header: self.header, transactions: self.transactions, hash: self.hash, signature: None, node_signature: None }
+    }
+}
+
+impl AnyBlock for BlockV18 {
+    fn into_block(self, _height: usize) -> Block {
+        Block { header: self.header, transactions: self.transactions, hash: self.hash, signature: self.signature, node_signature: None }
+    }
+}
+
+impl<T: Into<Transaction>> AnyBlock for LegacyBlock<T> {
+    fn into_block(self, height: usize) -> Block {
+        // Transaction IDs don't cover fields that are unset, so the Merkle root stays valid. The block keeps its hash,
+        // which doesn't cover the height and timestamp, see [BlockHeader::legacy_hash].
+        let transactions: Vec<Transaction> = self.transactions.into_iter().map(Into::into).collect();
+        Block {
+            header: BlockHeader {
+                previous_hash: self.header.previous_hash,
+                merkle_root: self.header.merkle_root,
+                height: height as u64,
+                timestamp: Block::legacy_timestamp(&transactions),
+            },
+            transactions,
+            hash: self.hash,
+            signature: None,
+            node_signature: None,
+        }
+    }
+}
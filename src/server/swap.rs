@@ -0,0 +1,60 @@
+//! Atomic swaps between two accounts. `offer-swap` records an offer on the chain: the maker gives an asset, an amount
+//! of a token or of the native coin, to the counterparty in exchange for another asset. Nothing moves until the
+//! counterparty accepts the offer with `accept-swap`, whose transaction makes both legs of the swap at once, so either
+//! both accounts get what they asked for or neither does. An offer expires at its expiry height, from the block at
+//! that height on it can't be accepted anymore.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::common::Asset;
+
+/// An offer to swap assets that wasn't accepted yet
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SwapOffer {
+    pub maker: String,
+    pub counterparty: String,
+    // What the maker gives the counterparty
+    pub give: Asset,
+    // What the counterparty gives the maker in exchange
+    pub take: Asset,
+    pub expiry_height: u64,
+}
+
+/// The swap offers that weren't accepted and didn't expire, by the ID of the transaction that made them
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Swaps {
+    offers: HashMap<String, SwapOffer>,
+}
+
+impl Swaps {
+    pub fn get(&self, offer_id: &str) -> Option<&SwapOffer> {
+        self.offers.get(offer_id)
+    }
+
+    pub fn offer(&mut self, offer_id: String, offer: SwapOffer) {
+        self.offers.insert(offer_id, offer);
+    }
+
+    pub fn accept(&mut self, offer_id: &str) {
+        self.offers.remove(offer_id);
+    }
+
+    /// Removes the offers that expire after the block at the given height
+    pub fn expire(&mut self, height: u64) {
+        self.offers.retain(|_, offer| offer.expiry_height > height + 1);
+    }
+
+    /// Checks that an offer can be accepted by an account in a block at the given height
+    ///
+    /// Returns: the offer, or why it can't be accepted, to follow the offer ID
+    pub fn check_acceptance(&self, offer_id: &str, account_id: &str, height: u64) -> Result<&SwapOffer, String> {
+        let offer = self.get(offer_id).ok_or_else(|| "doesn't exist, was accepted or expired".to_string())?;
+        if offer.counterparty != account_id {
+            return Err(format!("is made to account {}, only it can accept the offer", offer.counterparty));
+        }
+        if height >= offer.expiry_height {
+            return Err(format!("expires at height {}, it can't be accepted anymore", offer.expiry_height));
+        }
+        Ok(offer)
+    }
+}
@@ -0,0 +1,250 @@
+//! Initial block download. Every sync interval a node asks its connected peers for the blocks after its tip, and the
+//! first peer with a longer chain becomes its sync peer. The node then downloads the blocks of the sync peer in batches
+//! and adds each batch like blocks minted by other nodes until it reached the tip of the sync peer. A batch extending
+//! the tip is verified on top of the accounts of the tip and appended, so the downloaded segment is verified once
+//! rather than replaying the chain for every batch. If the chain of the sync peer forked off the node's chain, the node
+//! goes back a batch at a time until the blocks build on a block it knows, and switches to the longer chain once it has
+//! all of its blocks, which verifies the chain they end. The node
+//! doesn't mint while it is syncing, as its blocks would build on a stale tip. Once synced, the node keeps checking
+//! its peers every sync interval, so it also follows the blocks they mint later. Every sync interval the node also
+//! asks its peers for the missing parents of its orphan blocks, see [super::orphan].
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use crate::client::Client;
+use crate::common::{GetBlocksOp, Request};
+use crate::noise;
+use tracing::{debug, info, warn};
+use super::{Block, State};
+
+/// Interval at which the node checks whether a peer has a longer chain
+pub const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Number of blocks requested from the sync peer at once, fewer are sent if they don't fit into a response
+const BATCH_SIZE: usize = 100;
+
+/// The zstd level batches are compressed with
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// The response to a blocks request
+#[derive(Serialize, Deserialize)]
+pub struct BlockBatch {
+    // Height of the tip of the responding node
+    pub height: usize,
+    // The bincode encoded blocks, in the format of the ledger file, compressed with zstd and hex encoded
+    pub blocks: String,
+}
+
+/// How far the node is with syncing
+#[derive(Default)]
+pub enum SyncStatus {
+    // No peer had a longer chain so far
+    #[default]
+    Idle,
+    Syncing {
+        peer: String,
+        // Height of the next block to request from the peer
+        next: usize,
+        // Height of the peer's tip at its last response
+        target: usize,
+        // Height of the tip of the node when it started syncing
+        start: usize,
+        started: Instant,
+    },
+    // Reached the tip of the peer
+    Synced { peer: String, height: usize },
+}
+
+impl SyncStatus {
+    pub fn is_syncing(&self) -> bool {
+        matches!(self, SyncStatus::Syncing { .. })
+    }
+
+    /// Describes the status for the status response, given the height of the node's tip
+    pub fn describe(&self, height: usize) -> String {
+        match self {
+            SyncStatus::Idle => "no peer with a longer chain".to_string(),
+            SyncStatus::Syncing { peer, target, start, .. } => {
+                format!("syncing from peer {}, at height {} of {} ({}%)", peer, height, target, progress(height, *start, *target))
+            }
+            SyncStatus::Synced { peer, height } => format!("synced with peer {} at height {}", peer, height),
+        }
+    }
+}
+
+/// Returns the percentage of the blocks between the start and the target height that were synced
+fn progress(height: usize, start: usize, target: usize) -> usize {
+    match target.saturating_sub(start) {
+        0 => 100,
+        total => (height.saturating_sub(start) * 100 / total).min(100),
+    }
+}
+
+/// Encodes blocks for a blocks response
+fn encode(blocks: &[Block]) -> String {
+    noise::to_hex(&zstd::encode_all(bincode::serialize(blocks).unwrap().as_slice(), COMPRESSION_LEVEL).unwrap())
+}
+
+fn decode(encoded: &str) -> Result<Vec<Block>, String> {
+    let compressed = noise::from_hex(encoded)?;
+    let bytes = zstd::decode_all(compressed.as_slice()).map_err(|e| e.to_string())?;
+    bincode::deserialize(&bytes).map_err(|e| e.to_string())
+}
+
+/// Gets the blocks from a height onwards as the response to a blocks request, as many as fit into the response
+///
+/// Returns: the JSON encoded batch, or a user-facing error if the blocks are pruned or a block is too large to send
+pub fn get_blocks_response(state: &State, blocks_info: &GetBlocksOp, max_length: usize) -> Result<String, String> {
+    if let Some(snapshot) = state.snapshots.lock().unwrap().pruned().filter(|snapshot| blocks_info.from_height < snapshot.height) {
+        return Err(format!("❌ Node {} pruned the transactions before the snapshot at height {}, so it can't send the blocks before it.", state.node_id, snapshot.height));
+    }
+    let ledger = state.ledger.read().unwrap();
+    let height = ledger.len() - 1;
+    let count = blocks_info.count.min(BATCH_SIZE);
+    let mut blocks = ledger.get(blocks_info.from_height..).unwrap_or_default();
+    blocks = &blocks[..blocks.len().min(count)];
+    // Halves the batch until it fits, compressed blocks can't be sized beforehand
+    loop {
+        let response = serde_json::to_string(&BlockBatch { height, blocks: encode(blocks) }).unwrap();
+        if response.len() <= max_length {
+            return Ok(response);
+        }
+        if blocks.len() == 1 {
+            return Err(format!("❌ Block {} at height {} is too large to send.", blocks[0].hash, blocks_info.from_height));
+        }
+        blocks = &blocks[..blocks.len() / 2];
+    }
+}
+
+/// Requests up to the given number of blocks from a height onwards from a peer
+fn request_blocks(state: &State, peer: &str, from_height: usize, count: usize) -> Result<(usize, Vec<Block>), String> {
+    let request = Request::new_get_blocks_request(&state.identity, from_height, count);
+    let mut client = Client::new(peer.to_string(), Duration::from_secs(2), 2).quiet();
+    let response = client.send(&request)?.result?;
+    let batch: BlockBatch = serde_json::from_str(&response).map_err(|e| format!("invalid blocks from peer {}: {}", peer, e))?;
+    let blocks = decode(&batch.blocks).map_err(|e| format!("invalid blocks from peer {}: {}", peer, e))?;
+    Ok((batch.height, blocks))
+}
+
+/// Downloads and adds the next batch of blocks of a peer, starting at the given height
+///
+/// Returns: the height of the next block to request and the height of the peer's tip, None if the peer's chain isn't
+/// longer, or why syncing failed
+fn sync_batch(state: &State, peer: &str, next: usize) -> Result<Option<(usize, usize)>, String> {
+    let length = state.ledger.read().unwrap().len();
+    let (target, blocks) = request_blocks(state, peer, next, BATCH_SIZE)?;
+    if target < length {
+        return Ok(None);
+    }
+    let Some(first) = blocks.first() else {
+        // The peer switched to a shorter fork since the last batch, its blocks are requested again after the tip
+        return Ok(Some((length, target)));
+    };
+    let known = {
+        let ledger = state.ledger.read().unwrap();
+        ledger.iter().any(|block| block.hash == first.header.previous_hash) || state.forks.lock().unwrap().contains(&first.header.previous_hash)
+    };
+    if !known {
+        if next <= 1 {
+            return Err(format!("the chain of peer {} doesn't start with the genesis block of this node", peer));
+        }
+        debug!(peer, height = next, "Blocks of peer build on an unknown block, going back a batch.");
+        return Ok(Some((next.saturating_sub(BATCH_SIZE).max(1), target)));
+    }
+    let received = blocks.len();
+    state.receive_blocks(blocks)?;
+    Ok(Some((next + received, target)))
+}
+
+/// Asks the connected peers for the missing parents of the orphan blocks. A parent is only found on a peer that has it
+/// on its main chain, and if it is an orphan in turn, its own parent is asked for in the next sync round.
+fn request_orphan_parents(state: &State) {
+    let missing = state.orphans.lock().unwrap().missing_parents();
+    if missing.is_empty() {
+        return;
+    }
+    let peers = state.peers.lock().unwrap().shared("");
+    for (hash, height) in missing {
+        let found = peers.iter().find_map(|peer| match request_blocks(state, peer, height as usize, 1) {
+            Ok((_, mut blocks)) if blocks.first().is_some_and(|block| block.hash == hash) => blocks.pop(),
+            Ok(_) => None,
+            Err(e) => {
+                debug!(peer, "Requesting the parent of an orphan block failed: {}", e.trim_start_matches("❌ "));
+                None
+            }
+        });
+        match found.map(|parent| state.receive_block(parent)) {
+            Some(Ok(message)) => debug!(%hash, "Received the parent of an orphan block. {}", message),
+            Some(Err(e)) => warn!(%hash, "The parent of an orphan block is rejected: {}", e.trim_start_matches("❌ ")),
+            None => debug!(%hash, height, "No peer has the parent of an orphan block."),
+        }
+    }
+}
+
+/// Syncs the next batch from the sync peer, or looks for a peer with a longer chain if there is none
+///
+/// Returns: whether the node is syncing, so the next batch is requested right away
+fn sync_round(state: &State) -> bool {
+    let syncing = match &*state.sync.lock().unwrap() {
+        SyncStatus::Syncing { peer, next, .. } => Some((peer.clone(), *next)),
+        _ => None,
+    };
+    if syncing.is_none() {
+        request_orphan_parents(state);
+    }
+    let length = state.ledger.read().unwrap().len();
+    let candidates = match &syncing {
+        Some((peer, next)) => vec![(peer.clone(), *next)],
+        None => state.peers.lock().unwrap().shared("").into_iter().map(|peer| (peer, length)).collect(),
+    };
+
+    for (peer, next) in candidates {
+        let result = sync_batch(state, &peer, next);
+        let height = state.ledger.read().unwrap().len() - 1;
+        let mut status = state.sync.lock().unwrap();
+        match result {
+            Ok(Some((next, target))) if height < target => {
+                match &mut *status {
+                    SyncStatus::Syncing { next: status_next, target: status_target, start, .. } => {
+                        (*status_next, *status_target) = (next, target);
+                        info!(peer, height, target, progress = progress(height, *start, target), "Syncing blocks.");
+                    }
+                    _ => {
+                        info!(peer, height, target, "Syncing blocks from peer with a longer chain.");
+                        *status = SyncStatus::Syncing { peer, next, target, start: length - 1, started: Instant::now() };
+                    }
+                }
+                return true;
+            }
+            // Caught up with the peer, or no blocks to sync from it. A single batch catches up with the blocks a peer
+            // minted since the last check.
+            Ok(batch) => {
+                if let SyncStatus::Syncing { started, start, .. } = &*status {
+                    info!(peer, height, blocks = height - start, seconds = started.elapsed().as_secs(), "Synced with peer.");
+                    *status = SyncStatus::Synced { peer, height };
+                } else if batch.is_some() {
+                    *status = SyncStatus::Synced { peer, height };
+                }
+            }
+            Err(e) => {
+                warn!(peer, "Syncing blocks failed: {}", e.trim_start_matches("❌ "));
+                if status.is_syncing() {
+                    *status = SyncStatus::Idle;
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Syncs the chain with the peers of the node until it shuts down, see the module documentation
+pub async fn sync_blocks(state: Arc<State>) {
+    loop {
+        let shared_state = state.clone();
+        let syncing = tokio::task::spawn_blocking(move || sync_round(&shared_state)).await.unwrap();
+        if !syncing {
+            tokio::time::sleep(SYNC_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Tokens besides the native coin. Any account can create a token with `create-token`, which gives it the whole supply
+//! of the token, and transfer the token to other accounts with `transfer --token`. Token amounts have the same format
+//! as amounts of the native coin, which stays the default: balances, transfers and fees are in the native coin unless a
+//! token is given, and the fees of token transactions are always paid in the native coin.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::common::Amount;
+
+/// A token created on the chain
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Token {
+    // Account that created the token and received its supply
+    pub issuer: String,
+    pub supply: Amount,
+}
+
+/// The tokens created on the chain and the balances of the accounts in them
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Tokens {
+    tokens: HashMap<String, Token>,
+    // Balance of every account that holds or held a token, by token symbol
+    balances: HashMap<String, HashMap<String, Amount>>,
+}
+
+impl Tokens {
+    pub fn get(&self, token: &str) -> Option<&Token> {
+        self.tokens.get(token)
+    }
+
+    /// Gets the balance of an account in a token, 0 if it never held the token
+    pub fn balance(&self, token: &str, account_id: &str) -> Amount {
+        self.balances.get(token).and_then(|balances| balances.get(account_id)).copied().unwrap_or_default()
+    }
+
+    /// Returns the tokens with their supply and the balances of the accounts in them, for the consistency checks
+    pub fn supplies(&self) -> impl Iterator<Item = (&String, Amount, &HashMap<String, Amount>)> {
+        // Creating a token gives its issuer a balance in it, so every token has balances
+        self.tokens.iter().filter_map(|(token, created)| Some((token, created.supply, self.balances.get(token)?)))
+    }
+
+    /// Returns the tokens an account has a balance in with the balances, sorted by symbol
+    pub fn held_by(&self, account_id: &str) -> Vec<(&String, Amount)> {
+        let mut held: Vec<(&String, Amount)> = self
+            .balances
+            .iter()
+            .filter_map(|(token, balances)| Some((token, *balances.get(account_id)?)))
+            .filter(|(_, balance)| *balance != Amount::ZERO)
+            .collect();
+        held.sort_by(|a, b| a.0.cmp(b.0));
+        held
+    }
+
+    /// Creates a token, whose whole supply goes to its issuer
+    pub fn create(&mut self, token: &str, issuer: &str, supply: Amount) {
+        self.tokens.insert(token.to_string(), Token { issuer: issuer.to_string(), supply });
+        *self.balances.entry(token.to_string()).or_default().entry(issuer.to_string()).or_default() += supply;
+    }
+
+    /// Moves an amount of a token between accounts. The funds aren't checked, that is up to the caller.
+    pub fn transfer(&mut self, token: &str, from_account_id: &str, to_account_id: &str, amount: Amount) {
+        let balances = self.balances.entry(token.to_string()).or_default();
+        *balances.entry(from_account_id.to_string()).or_default() -= amount;
+        *balances.entry(to_account_id.to_string()).or_default() += amount;
+    }
+}
@@ -0,0 +1,265 @@
+//! Validation of requests before they are processed. Every account ID, amount and memo a request carries is checked,
+//! whatever the operation, so a request holding garbage, e.g. decoded from bincode by another client than `toychain`,
+//! is rejected with the field that is invalid before any state is locked. Amounts are fixed-point numbers, so they are
+//! always finite, but bincode carries them as plain integers that can be negative. Numbers the node computes with, like
+//! the page of a history, and the starting balances of new accounts are bounded so they can't overflow. The checks of
+//! the operations, e.g. that an account exists or can pay an amount, come after this.
+
+use std::fmt;
+use crate::common::{self, Amount, ErrorCode, FundTransferOp, Operation, Request, MAX_ACCOUNT_ID_LENGTH, MAX_MEMO_LENGTH, MAX_STARTING_BALANCE};
+
+/// Largest page of a history a request can ask for
+const MAX_HISTORY_PAGE: u64 = 1_000_000;
+/// Largest number of transactions a page of a history can hold
+const MAX_HISTORY_LIMIT: u64 = 1000;
+
+/// Why a request is invalid
+#[derive(Debug, PartialEq)]
+pub enum ValidationError {
+    // The encoded request is larger than its protocol version allows
+    TooLarge { size: usize, max: usize, version: u16 },
+    EmptyAccountId { field: String },
+    AccountIdTooLong { field: String, length: usize },
+    // The account ID contains control characters, e.g. line breaks
+    InvalidAccountId { field: String },
+    NegativeAmount { field: String, amount: Amount },
+    AmountTooLarge { field: String, amount: Amount, max: Amount },
+    MemoTooLong { field: String, length: usize },
+    InvalidMemo { field: String },
+    OutOfRange { field: String, value: u64, max: u64 },
+}
+
+impl ValidationError {
+    /// Returns the code of the error sent in the response, with the machine-readable reason and the invalid field
+    pub fn error_code(&self) -> ErrorCode {
+        let (reason, field) = match self {
+            ValidationError::TooLarge { .. } => ("too_large", None),
+            ValidationError::EmptyAccountId { field } => ("empty_account_id", Some(field)),
+            ValidationError::AccountIdTooLong { field, .. } => ("account_id_too_long", Some(field)),
+            ValidationError::InvalidAccountId { field } => ("invalid_account_id", Some(field)),
+            ValidationError::NegativeAmount { field, .. } => ("negative_amount", Some(field)),
+            ValidationError::AmountTooLarge { field, .. } => ("amount_too_large", Some(field)),
+            ValidationError::MemoTooLong { field, .. } => ("memo_too_long", Some(field)),
+            ValidationError::InvalidMemo { field } => ("invalid_memo", Some(field)),
+            ValidationError::OutOfRange { field, .. } => ("out_of_range", Some(field)),
+        };
+        ErrorCode::InvalidRequest { reason: reason.to_string(), field: field.cloned() }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::TooLarge { size, max, version } => {
+                write!(f, "❌ Invalid request: it is {} bytes large, requests of protocol version {} can be at most {} bytes.", size, version, max)
+            }
+            ValidationError::EmptyAccountId { field } => write!(f, "❌ Invalid request: {} is empty.", field),
+            ValidationError::AccountIdTooLong { field, length } => {
+                write!(f, "❌ Invalid request: {} is {} bytes long, account IDs can be at most {} bytes.", field, length, MAX_ACCOUNT_ID_LENGTH)
+            }
+            ValidationError::InvalidAccountId { field } => write!(f, "❌ Invalid request: {} contains control characters.", field),
+            ValidationError::NegativeAmount { field, amount } => write!(f, "❌ Invalid request: {} is {}, amounts can't be negative.", field, amount),
+            ValidationError::AmountTooLarge { field, amount, max } => write!(f, "❌ Invalid request: {} is {}, it can be at most {}.", field, amount, max),
+            ValidationError::MemoTooLong { field, length } => {
+                write!(f, "❌ Invalid request: {} is {} bytes long, memos can be at most {} bytes.", field, length, MAX_MEMO_LENGTH)
+            }
+            ValidationError::InvalidMemo { field } => write!(f, "❌ Invalid request: {} contains control characters such as line breaks.", field),
+            ValidationError::OutOfRange { field, value, max } => write!(f, "❌ Invalid request: {} is {}, it can be at most {}.", field, value, max),
+        }
+    }
+}
+
+/// The fields of a request to validate, by their names in the request
+#[derive(Default)]
+struct Fields<'a> {
+    account_ids: Vec<(String, &'a str)>,
+    amounts: Vec<(String, Amount)>,
+    // Amounts with the largest value they can have
+    bounded_amounts: Vec<(String, Amount, Amount)>,
+    memos: Vec<(String, &'a str)>,
+    // Numbers with the largest value they can have
+    numbers: Vec<(String, u64, u64)>,
+}
+
+impl<'a> Fields<'a> {
+    fn account_id(&mut self, field: &str, account_id: &'a str) -> &mut Self {
+        self.account_ids.push((field.to_string(), account_id));
+        self
+    }
+
+    fn amount(&mut self, field: &str, amount: Amount) -> &mut Self {
+        self.amounts.push((field.to_string(), amount));
+        self
+    }
+
+    fn bounded_amount(&mut self, field: &str, amount: Amount, max: Amount) -> &mut Self {
+        self.bounded_amounts.push((field.to_string(), amount, max));
+        self.amount(field, amount)
+    }
+
+    fn number(&mut self, field: &str, value: usize, max: u64) -> &mut Self {
+        self.numbers.push((field.to_string(), value as u64, max));
+        self
+    }
+
+    fn transfer(&mut self, prefix: &str, transfer: &'a FundTransferOp) -> &mut Self {
+        self.account_id(&format!("{}from_account_id", prefix), &transfer.from_account_id)
+            .account_id(&format!("{}to_account_id", prefix), &transfer.to_account_id)
+            .amount(&format!("{}amount", prefix), transfer.amount)
+            .amount(&format!("{}fee", prefix), transfer.fee);
+        if let Some(memo) = &transfer.memo {
+            self.memos.push((format!("{}memo", prefix), memo));
+        }
+        self
+    }
+
+    fn check(&self) -> Result<(), ValidationError> {
+        for (field, account_id) in &self.account_ids {
+            let field = field.clone();
+            if account_id.is_empty() {
+                return Err(ValidationError::EmptyAccountId { field });
+            }
+            if account_id.len() > MAX_ACCOUNT_ID_LENGTH {
+                return Err(ValidationError::AccountIdTooLong { field, length: account_id.len() });
+            }
+            if account_id.chars().any(char::is_control) {
+                return Err(ValidationError::InvalidAccountId { field });
+            }
+        }
+        if let Some((field, amount)) = self.amounts.iter().find(|(_, amount)| amount.is_negative()) {
+            return Err(ValidationError::NegativeAmount { field: field.clone(), amount: *amount });
+        }
+        if let Some((field, amount, max)) = self.bounded_amounts.iter().find(|(_, amount, max)| amount > max) {
+            return Err(ValidationError::AmountTooLarge { field: field.clone(), amount: *amount, max: *max });
+        }
+        for (field, memo) in &self.memos {
+            let field = field.clone();
+            if memo.len() > MAX_MEMO_LENGTH {
+                return Err(ValidationError::MemoTooLong { field, length: memo.len() });
+            }
+            if memo.chars().any(char::is_control) {
+                return Err(ValidationError::InvalidMemo { field });
+            }
+        }
+        if let Some((field, value, max)) = self.numbers.iter().find(|(_, value, max)| value > max) {
+            return Err(ValidationError::OutOfRange { field: field.clone(), value: *value, max: *max });
+        }
+        Ok(())
+    }
+}
+
+/// Validates a transfer, e.g. one made over JSON-RPC
+pub fn validate_transfer(transfer: &FundTransferOp) -> Result<(), ValidationError> {
+    Fields::default().transfer("", transfer).check()
+}
+
+/// Validates the creation of an account, e.g. one made over JSON-RPC
+pub fn validate_account_creation(account_id: &str, starting_balance: Amount) -> Result<(), ValidationError> {
+    Fields::default().account_id("account_id", account_id).bounded_amount("starting_balance", starting_balance, MAX_STARTING_BALANCE).check()
+}
+
+/// Validates a decoded request and the size it was received with
+pub fn validate(request: &Request, size: usize) -> Result<(), ValidationError> {
+    let max = common::max_message_size(request.version);
+    if size > max {
+        return Err(ValidationError::TooLarge { size, max, version: request.version });
+    }
+
+    let mut fields = Fields::default();
+    match &request.operation {
+        Operation::CreateAccount(op) => fields.account_id("account_id", &op.account_id).bounded_amount("starting_balance", op.starting_balance, MAX_STARTING_BALANCE),
+        Operation::CreateMultisigAccount(op) => fields.account_id("account_id", &op.account_id).bounded_amount("starting_balance", op.starting_balance, MAX_STARTING_BALANCE),
+        Operation::TransferFunds(op) | Operation::Simulate(op) => fields.transfer("", op),
+        Operation::BatchTransfer(op) => {
+            for (index, transfer) in op.transfers.iter().enumerate() {
+                fields.transfer(&format!("transfers[{}].", index), transfer);
+            }
+            &mut fields
+        }
+        Operation::TransferToken(op) => fields.transfer("transfer.", &op.transfer),
+        Operation::EscrowTransfer(op) => fields.transfer("transfer.", &op.transfer),
+        Operation::ExpiringTransfer(op) => fields.transfer("transfer.", &op.transfer),
+        Operation::SchedulePayment(op) => fields.transfer("transfer.", &op.transfer),
+        Operation::CallContract(op) => fields.transfer("transfer.", &op.transfer),
+        Operation::OfferSwap(op) => fields.transfer("transfer.", &op.transfer).amount("take.amount", op.take.amount),
+        Operation::GetFunds(op) => fields.account_id("account_id", &op.account_id),
+        Operation::GetNonce(op) => fields.account_id("account_id", &op.account_id),
+        Operation::History(op) => fields.account_id("account_id", &op.account_id).number("page", op.page, MAX_HISTORY_PAGE).number("limit", op.limit, MAX_HISTORY_LIMIT),
+        Operation::QueryTransactions(op) => {
+            fields.account_id("account_id", &op.account_id).number("page", op.page, MAX_HISTORY_PAGE).number("limit", op.limit, MAX_HISTORY_LIMIT);
+            for (field, amount) in [("min_amount", op.min_amount), ("max_amount", op.max_amount)] {
+                if let Some(amount) = amount {
+                    fields.amount(field, amount);
+                }
+            }
+            &mut fields
+        }
+        Operation::GetAccountProof(op) => fields.account_id("account_id", &op.account_id),
+        Operation::GetTokenBalance(op) => fields.account_id("account_id", &op.account_id),
+        Operation::TokensOf(op) => fields.account_id("account_id", &op.account_id),
+        Operation::GetSchedules(op) => fields.account_id("account_id", &op.account_id),
+        Operation::FreezeAccount(op) | Operation::UnfreezeAccount(op) => fields.account_id("account_id", &op.account_id),
+        Operation::AddWebhook(op) | Operation::RemoveWebhook(op) => fields.account_id("account_id", &op.account_id),
+        Operation::GetPending(op) => match &op.account_id {
+            Some(account_id) => fields.account_id("account_id", account_id),
+            None => &mut fields,
+        },
+        Operation::GetWebhooks(op) => match &op.account_id {
+            Some(account_id) => fields.account_id("account_id", account_id),
+            None => &mut fields,
+        },
+        Operation::CloseAccount(op) => fields.account_id("account_id", &op.account_id).account_id("beneficiary_id", &op.beneficiary_id).amount("fee", op.fee),
+        Operation::Stake(op) | Operation::Unstake(op) => fields.account_id("account_id", &op.account_id).amount("amount", op.amount).amount("fee", op.fee),
+        Operation::RegisterAlias(op) => fields.account_id("account_id", &op.account_id).amount("fee", op.fee),
+        Operation::CreateToken(op) => fields.account_id("account_id", &op.account_id).amount("supply", op.supply).amount("fee", op.fee),
+        Operation::MintNft(op) => fields.account_id("account_id", &op.account_id).amount("fee", op.fee),
+        Operation::TransferNft(op) => fields.account_id("from_account_id", &op.from_account_id).account_id("to_account_id", &op.to_account_id).amount("fee", op.fee),
+        Operation::CancelSchedule(op) => fields.account_id("account_id", &op.account_id).amount("fee", op.fee),
+        Operation::AcceptSwap(op) => fields.account_id("account_id", &op.account_id).amount("fee", op.fee),
+        Operation::DeployContract(op) => fields.account_id("account_id", &op.account_id).amount("fee", op.fee),
+        Operation::Propose(op) => {
+            fields.account_id("account_id", &op.account_id).amount("fee", op.fee);
+            match op.change {
+                common::ParameterChange::MinFee(amount) | common::ParameterChange::StakingReward(amount) => fields.amount("change", amount),
+                _ => &mut fields,
+            }
+        }
+        Operation::Vote(op) => fields.account_id("account_id", &op.account_id).amount("fee", op.fee),
+        Operation::CreditCrossShard(op) => fields.account_id("sender", &op.sender).account_id("recipient", &op.recipient).amount("amount", op.amount),
+        Operation::Faucet(op) => {
+            fields.account_id("account_id", &op.account_id);
+            match op.amount {
+                Some(amount) => fields.amount("amount", amount),
+                None => &mut fields,
+            }
+        }
+        // Operations without account IDs, amounts or memos, and those between nodes, which are validated when their
+        // blocks and transactions are
+        Operation::GetTransaction(_)
+        | Operation::GetReceipt(_)
+        | Operation::GetTransactionProof(_)
+        | Operation::ApproveTransfer(_)
+        | Operation::CancelTransaction(_)
+        | Operation::GetBlock(_)
+        | Operation::SubmitBlock(_)
+        | Operation::GetHeaders(_)
+        | Operation::OwnerOf(_)
+        | Operation::ClaimEscrow(_)
+        | Operation::RefundEscrow(_)
+        | Operation::GetContract(_)
+        | Operation::ExchangePeers(_)
+        | Operation::GetBlocks(_)
+        | Operation::RelayTransactions(_)
+        | Operation::AdvanceTime(_)
+        | Operation::MintNow(_)
+        | Operation::GetAuditLog(_)
+        | Operation::Shutdown
+        | Operation::Status
+        | Operation::GetValidators
+        | Operation::GetProposals
+        | Operation::GetSupply
+        | Operation::GetPeers
+        | Operation::Health => &mut fields,
+    };
+    fields.check()
+}
@@ -0,0 +1,919 @@
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use crate::common::{validate_alias, validate_nft_metadata, validate_token, Amount, MAX_STARTING_BALANCE};
+use crate::merkle::merkle_root;
+use super::admin::FrozenAccounts;
+use super::contract::{self, contract_id, Contracts};
+use super::cross_shard::{CrossShardTransfers, Lock, CROSS_SHARD_ACCOUNT};
+use super::escrow::{Escrow, Escrows, ESCROW_ACCOUNT};
+use super::genesis::Genesis;
+use super::governance::{self, Governance, Proposal};
+use super::identity;
+use super::monetary;
+use super::multisig::MultisigPolicy;
+use super::nft::{nft_id, Nfts};
+use super::schedule::{Schedule, Schedules};
+use super::slashing::{self, SLASHED_ACCOUNT};
+use super::snapshot::Snapshot;
+use super::stake::{self, STAKE_ACCOUNT};
+use super::swap::{SwapOffer, Swaps};
+use super::token::Tokens;
+use super::{Block, Transaction, TransactionKind};
+
+/// State of the accounts after the transactions replayed so far
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Accounts {
+    pub balances: HashMap<String, Amount>,
+    pub nonces: HashMap<String, u64>,
+    pub closed: HashSet<String>,
+    pub frozen: FrozenAccounts,
+    pub stakes: HashMap<String, Amount>,
+    // Height of the offence every slashed validator was slashed for last
+    pub slashed: HashMap<String, u64>,
+    pub multisig: HashMap<String, MultisigPolicy>,
+    // Account every alias belongs to
+    pub aliases: HashMap<String, String>,
+    // Tokens created and the balances of the accounts in them
+    pub tokens: Tokens,
+    pub nfts: Nfts,
+    // Escrows that weren't settled yet
+    pub escrows: Escrows,
+    // Cross-shard transfers that weren't settled yet and the transfers from other shards credited
+    pub cross_shard: CrossShardTransfers,
+    // Payment schedules with payments left
+    pub schedules: Schedules,
+    // Swap offers that weren't accepted and didn't expire
+    pub swaps: Swaps,
+    // Contracts deployed and their storage
+    pub contracts: Contracts,
+    // Proposals that weren't decided yet and the chain parameters decided so far
+    pub governance: Governance,
+}
+
+/// Verifies the integrity of a chain, starting at its genesis block:
+///
+/// * the hash of every block matches its header and its Merkle root matches its transactions
+/// * every block has its height in the chain, and blocks hashed without their height and timestamp (the genesis block
+///   and blocks minted before headers had them) come first and have the timestamp of their latest transaction
+/// * every block links to the hash of the block before it
+/// * every transaction is valid when replayed in order: accounts are created once, transfers are made with the
+///   next nonce of their account, and no balance ever becomes negative
+/// * an expiring transfer is minted in a block its timelock hasn't passed yet
+/// * a block reward is the last transaction of its block and pays exactly the fees of the block plus its subsidy by the
+///   monetary policy of the genesis configuration
+/// * the account creations and faucet payments of a block issue at most what is left below the maximum supply of the
+///   genesis configuration, its staking rewards are cut to what is left after them and its subsidy to what is left
+///   after those
+/// * a multisig account is created with distinct keys and a threshold that can be met
+/// * closing an account sweeps its whole balance, and no transfers are made from or to it afterwards
+/// * only existing accounts are frozen or unfrozen, each signature is later than the last one, and no transfers are
+///   made from an account while it is frozen
+/// * funds only move to the staking account by staking them, and only leave it by unstaking at most the stake of the
+///   unstaking account
+/// * a block starting an epoch pays exactly the staking reward shares of the stakers by their stakes before the block
+///   and the staking reward it can issue
+/// * a signed block is signed for the account its block reward pays with a key of its multisig account, and a slash
+///   moves the whole stake of a validator to the slashed account for evidence of two conflicting blocks signed for it
+///   at a height above the offence it was slashed for last
+/// * an alias is valid, registered once by an existing account for itself, and no account is created with its name
+/// * a token is valid and created once by an existing account with a positive supply, token transfers never make a
+///   token balance negative, and an account is only closed once it holds no tokens or NFTs
+/// * an NFT is minted by an existing account for itself with the ID derived from its nonce, and only transferred by
+///   its owner
+/// * funds only move to the escrow account by escrow transfers to an existing recipient, and only leave it by claims
+///   and refunds paying an open escrow to its recipient or sender in a block its timelocks allow, and an account is
+///   only closed once it is involved in no open escrow
+/// * funds only move to the cross-shard account by cross-shard transfers to another shard before their deadline, and
+///   only leave it by refunds of a locked transfer to its sender in a block after its deadline, a commit settles a
+///   locked transfer without moving its funds, and an account is only closed once it sent no locked transfer
+/// * a transfer from another shard is credited once to an existing account in a block before its deadline, without a
+///   fee or nonce
+/// * a payment schedule pays a positive amount to an existing account every interval of at least 1 block, its payments
+///   are made at the heights it is due at, at most once per block, and only its paying account cancels it and is
+///   closed once it pays no schedule
+/// * a swap offer exchanges positive amounts of two different assets with an existing account and expires after the
+///   block it is made in, and only its counterparty accepts it before it expires, swapping both assets at once while
+///   both accounts can afford what they give and neither is frozen
+/// * a contract is valid WebAssembly deployed by an existing account for itself with the ID derived from its nonce, and
+///   is only called by existing accounts with a gas limit and input within the limits, its calls executed in order
+/// * a faucet payment pays a positive amount to an existing account, without a fee or nonce
+/// * a proposal makes a valid change of a chain parameter by an existing account and takes effect after the block
+///   after it, and every existing account votes at most once on an open proposal before it takes effect, the proposals
+///   being decided by the balances and stakes of the voters after the block before their activation height
+///
+/// # Arguments
+///
+/// * `blocks`: the blocks of the chain, oldest first
+/// * `genesis`: the genesis configuration the chain must start with the genesis block of
+///
+/// Returns: the state of the accounts at the tip of the chain, or a description of the first inconsistency found
+pub fn verify_chain(blocks: &[Block], genesis: &Genesis) -> Result<Accounts, String> {
+    verify_chain_with(blocks, genesis, None, |_, _, _| Ok(()))
+}
+
+/// Verifies the integrity of a chain like [verify_chain], with an additional check of every block against the state
+/// of the accounts before it, e.g. that it was minted by the validator of its slot.
+///
+/// A chain whose blocks before a snapshot were pruned is verified from the snapshot: the blocks up to it only have to
+/// hash correctly and link up, the snapshot has to be of the block at its height, and the transactions after it are
+/// replayed on the state of the snapshot.
+pub fn verify_chain_with(
+    blocks: &[Block],
+    genesis: &Genesis,
+    snapshot: Option<&Snapshot>,
+    mut check: impl FnMut(usize, &Block, &Accounts) -> Result<(), String>,
+) -> Result<Accounts, String> {
+    let genesis_block = genesis.to_block();
+    match blocks.first() {
+        None => return Err("The chain has no blocks.".to_string()),
+        Some(first) if first.hash != genesis_block.hash => {
+            return Err(format!("Block 0 is {}, but the genesis configuration gives genesis block {}.", first.hash, genesis_block.hash));
+        }
+        Some(_) => {}
+    }
+    if let Some(snapshot) = snapshot.filter(|snapshot| snapshot.height >= blocks.len()) {
+        return Err(format!("The chain ends at height {}, before the snapshot at height {}.", blocks.len() - 1, snapshot.height));
+    }
+
+    let mut accounts = Accounts::default();
+    // Whether all blocks so far were hashed without their height and timestamp
+    let mut legacy = true;
+
+    for (height, block) in blocks.iter().enumerate() {
+        let invalid = |reason: String| format!("Block {} ({}) is invalid: {}.", height, block.hash, reason);
+
+        // The transactions of pruned blocks are gone, so their Merkle root can't be checked
+        let pruned = snapshot.is_some_and(|snapshot| height < snapshot.height);
+        let previous_hash = height.checked_sub(1).map(|previous| blocks[previous].hash.as_str());
+        check_header(height, block, previous_hash, &mut legacy, pruned).map_err(invalid)?;
+        if pruned {
+            continue;
+        }
+        if let Some(snapshot) = snapshot.filter(|snapshot| snapshot.height == height) {
+            if block.hash != snapshot.hash {
+                return Err(invalid(format!("the snapshot at its height is of block {}", snapshot.hash)));
+            }
+            accounts = snapshot.accounts.clone();
+            continue;
+        }
+        check(height, block, &accounts).map_err(invalid)?;
+        apply_checked(height, block, genesis, &mut accounts).map_err(invalid)?;
+    }
+    Ok(accounts)
+}
+
+/// Verifies a block extending a verified chain like [verify_chain_with] verifies the blocks of a chain, with the state
+/// of the accounts after the block before it, and applies it to the accounts. Adding a block to the tip of a chain this
+/// way doesn't replay the chain before it.
+///
+/// Returns: why the block is invalid, in which case the accounts may have been changed by some of its transactions
+pub fn verify_next_block(
+    block: &Block,
+    previous: &Block,
+    genesis: &Genesis,
+    accounts: &mut Accounts,
+    check: impl FnOnce(usize, &Block, &Accounts) -> Result<(), String>,
+) -> Result<(), String> {
+    let height = previous.header.height as usize + 1;
+    let invalid = |reason: String| format!("Block {} ({}) is invalid: {}.", height, block.hash, reason);
+    // Legacy blocks are only valid as long as all blocks before them were, which the block before them tells
+    let mut legacy = previous.hash != previous.header.hash();
+    check_header(height, block, Some(&previous.hash), &mut legacy, false).map_err(invalid)?;
+    check(height, block, accounts).map_err(invalid)?;
+    apply_checked(height, block, genesis, accounts).map_err(invalid)
+}
+
+/// Checks the hashes of a block at the given height and that it links to the block before it. Blocks are legacy, hashed
+/// without their height and timestamp, as long as all blocks before them were.
+///
+/// Returns: why the header of the block is invalid
+fn check_header(height: usize, block: &Block, previous_hash: Option<&str>, legacy: &mut bool, pruned: bool) -> Result<(), String> {
+    let transaction_ids = Block::transaction_ids(&block.transactions);
+    // The last leaf of an odd level is paired with itself, so a repeated last transaction gives the same Merkle root
+    let mut seen = HashSet::new();
+    if let Some(duplicate) = transaction_ids.iter().find(|transaction_id| !seen.insert(*transaction_id)) {
+        return Err(format!("it contains transaction {} more than once", duplicate));
+    }
+    let merkle_root = merkle_root(&transaction_ids);
+    if !pruned && block.header.merkle_root != merkle_root {
+        return Err(format!("its Merkle root is {}, but its transactions give {}", block.header.merkle_root, merkle_root));
+    }
+    if block.header.height != height as u64 {
+        return Err(format!("its header gives height {}", block.header.height));
+    }
+    let hash = block.header.hash();
+    if block.hash == hash {
+        *legacy = false;
+    } else if !*legacy || block.hash != block.header.legacy_hash() {
+        return Err(format!("its header hashes to {}", hash));
+    } else if !pruned && block.header.timestamp != Block::legacy_timestamp(&block.transactions) {
+        return Err(format!("its hash doesn't cover its timestamp {}, which isn't the time of its latest transaction", block.header.timestamp));
+    }
+    if previous_hash.is_some_and(|previous_hash| block.header.previous_hash != previous_hash) {
+        return Err(format!("its previous hash {} isn't the hash of block {}", block.header.previous_hash, height - 1));
+    }
+    Ok(())
+}
+
+/// Checks the signatures and block reward of a block whose header was checked and applies its transactions
+///
+/// Returns: why the block is invalid
+fn apply_checked(height: usize, block: &Block, genesis: &Genesis, accounts: &mut Accounts) -> Result<(), String> {
+    slashing::check_signature(block, &accounts.multisig)?;
+    identity::check_block(block)?;
+    let issuable = accounts.apply_block(height, block, genesis.parameters.max_supply)?;
+    monetary::check_reward(block, monetary::block_subsidy(&genesis.parameters, height as u64, issuable))
+}
+
+impl Accounts {
+    /// Validates the transactions of a block at the given height and applies them, checking that they issue no funds
+    /// beyond the given maximum supply
+    ///
+    /// Returns: what is left for the block reward to issue below the maximum supply, None if there is none, or why the
+    /// block issues too much or the first invalid transaction is invalid
+    pub fn apply_block(&mut self, height: usize, block: &Block, max_supply: Option<Amount>) -> Result<Option<Amount>, String> {
+        // The genesis block issues the funds of the genesis configuration, which are checked against it when it is
+        // loaded
+        let issuable = match height {
+            0 => None,
+            _ => monetary::issuable(max_supply, &self.balances),
+        };
+        let issuable_after = monetary::issuable_after(issuable, block.transactions.iter().filter(|transaction| !transaction.is_staking_reward()));
+        let expected = stake::epoch_rewards(&self.stakes, self.governance.parameters(), height as u64, issuable_after);
+        let paid: Vec<(String, Amount)> =
+            block.transactions.iter().filter(|transaction| transaction.is_staking_reward()).map(|transaction| (transaction.to_account_id.clone(), transaction.amount)).collect();
+        if paid != expected {
+            let describe = |rewards: &[(String, Amount)]| rewards.iter().map(|(account_id, share)| format!("{} to {}", share, account_id)).collect::<Vec<_>>().join(", ");
+            return Err(format!("it pays the staking rewards [{}], but the stakes before it give [{}]", describe(&paid), describe(&expected)));
+        }
+        let issuable = monetary::check_issuance(block, issuable)?;
+        let epoch = stake::epoch_of(self.governance.parameters(), height as u64);
+        for (index, transaction) in block.transactions.iter().enumerate() {
+            let reward = match transaction.kind {
+                TransactionKind::Reward => verify_reward(block, height, index),
+                TransactionKind::StakingReward { epoch: paid } if paid != epoch => Err(format!("is a staking reward for epoch {} in a block starting epoch {}", paid, epoch)),
+                _ => Ok(()),
+            };
+            reward
+                .and_then(|()| self.apply(transaction, height as u64, block.header.timestamp))
+                .map_err(|reason| format!("transaction {} {}", transaction.id(), reason))?;
+        }
+        self.schedules.finish(height as u64);
+        self.swaps.expire(height as u64);
+        let Accounts { balances, stakes, governance, .. } = self;
+        governance.decide(height as u64, |account_id| governance::weight(balances, stakes, account_id));
+        Ok(issuable)
+    }
+
+    /// Validates a transaction against the balances, nonces, closed and frozen accounts of all transactions before it
+    /// and applies it, the escrow timelocks and the expiry of transfers against the height and timestamp of its block
+    ///
+    /// Returns: why the transaction is invalid
+    fn apply(&mut self, transaction: &Transaction, height: u64, timestamp: u64) -> Result<(), String> {
+        let Accounts { balances, nonces, closed, frozen, stakes, slashed, multisig, aliases, tokens, nfts, escrows, cross_shard, schedules, swaps, contracts, governance } = self;
+        if transaction.amount.is_negative() || transaction.fee.is_negative() {
+            return Err(format!("has a negative amount {} or fee {}", transaction.amount, transaction.fee));
+        }
+        if let Some(valid_until) = transaction.valid_until().filter(|valid_until| valid_until.has_passed(height, timestamp)) {
+            return Err(format!("expired at {}", valid_until));
+        }
+        if let Some(account_id) = transaction.from_account_id.iter().chain(std::iter::once(&transaction.to_account_id)).find(|account_id| closed.contains(*account_id)) {
+            return Err(format!("involves account {} which was closed", account_id));
+        }
+
+        let Some(from_account_id) = &transaction.from_account_id else {
+            if transaction.is_reward() {
+                let to_balance = balances.entry(transaction.to_account_id.clone()).or_default();
+                *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the reward account")?;
+                return Ok(());
+            }
+            // The shares are checked against the stakes with the whole block, see Accounts::apply_block
+            if transaction.is_staking_reward() {
+                if transaction.fee != Amount::ZERO || transaction.nonce.is_some() {
+                    return Err("is a staking reward with a fee or nonce".to_string());
+                }
+                let to_balance = balances.entry(transaction.to_account_id.clone()).or_default();
+                *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the staker")?;
+                return Ok(());
+            }
+            if let Some(evidence) = transaction.slashes() {
+                let validator = evidence.validator();
+                if transaction.fee != Amount::ZERO || transaction.nonce.is_some() || transaction.to_account_id != validator {
+                    return Err(format!("slashes validator {} with a fee or nonce or for another account", validator));
+                }
+                evidence.check(multisig).map_err(|e| format!("slashes validator {} with evidence that doesn't prove an offence: {}", validator, e))?;
+                if let Some(last) = slashed.get(validator).filter(|last| evidence.height() <= **last) {
+                    return Err(format!("slashes validator {} for an offence at height {}, it was slashed for an offence at height {} already", validator, evidence.height(), last));
+                }
+                let stake = stakes.get(validator).copied().unwrap_or_default();
+                if stake == Amount::ZERO || transaction.amount != stake {
+                    return Err(format!("slashes {} of validator {} whose stake is {}", transaction.amount, validator, stake));
+                }
+                stakes.remove(validator);
+                *balances.entry(STAKE_ACCOUNT.to_string()).or_default() -= stake;
+                let slashed_balance = balances.entry(SLASHED_ACCOUNT.to_string()).or_default();
+                *slashed_balance = slashed_balance.checked_add(stake).ok_or("overflows the balance of the slashed stakes")?;
+                slashed.insert(validator.to_string(), evidence.height());
+                return Ok(());
+            }
+            if let Some((freeze, signed_at)) = transaction.freezes() {
+                if transaction.amount != Amount::ZERO || transaction.fee != Amount::ZERO || transaction.nonce.is_some() {
+                    return Err("is a freeze with an amount, fee or nonce".to_string());
+                }
+                if !balances.contains_key(&transaction.to_account_id) {
+                    return Err(format!("freezes or unfreezes account {} which doesn't exist", transaction.to_account_id));
+                }
+                frozen.check(&transaction.to_account_id, freeze, signed_at).map_err(|e| format!("can't freeze or unfreeze: {}", e))?;
+                frozen.apply(&transaction.to_account_id, freeze, signed_at);
+                return Ok(());
+            }
+
+            // The deadline of a credit is checked like the expiry of a transfer
+            if let Some(transfer_id) = transaction.credits_cross_shard() {
+                if transaction.fee != Amount::ZERO || transaction.nonce.is_some() || transaction.amount <= Amount::ZERO {
+                    return Err(format!("credits cross-shard transfer {} with a fee, nonce or without an amount", transfer_id));
+                }
+                if cross_shard.is_credited(transfer_id) {
+                    return Err(format!("credits cross-shard transfer {} which was credited already", transfer_id));
+                }
+                if [STAKE_ACCOUNT, ESCROW_ACCOUNT, SLASHED_ACCOUNT, CROSS_SHARD_ACCOUNT].contains(&transaction.to_account_id.as_str()) {
+                    return Err(format!("credits cross-shard transfer {} to the reserved account {}", transfer_id, transaction.to_account_id));
+                }
+                let Some(to_balance) = balances.get_mut(&transaction.to_account_id) else {
+                    return Err(format!("credits cross-shard transfer {} to account {}, which doesn't exist", transfer_id, transaction.to_account_id));
+                };
+                *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the credited account")?;
+                cross_shard.credit(transfer_id.clone());
+                return Ok(());
+            }
+
+            if transaction.kind == TransactionKind::Faucet {
+                if transaction.fee != Amount::ZERO || transaction.nonce.is_some() || transaction.amount <= Amount::ZERO {
+                    return Err("is a faucet payment with a fee, nonce or without an amount".to_string());
+                }
+                let Some(to_balance) = balances.get_mut(&transaction.to_account_id) else {
+                    return Err(format!("pays account {} from the faucet, which doesn't exist", transaction.to_account_id));
+                };
+                *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the account paid by the faucet")?;
+                return Ok(());
+            }
+
+            // Account creation
+            if let TransactionKind::CreateMultisig(policy) = &transaction.kind {
+                policy.validate().map_err(|e| format!("creates multisig account {} but {}", transaction.to_account_id, e))?;
+            }
+            if balances.contains_key(&transaction.to_account_id) {
+                return Err(format!("creates account {} which already exists", transaction.to_account_id));
+            }
+            // The genesis block allocates the balances of its configuration instead
+            if height > 0 && transaction.amount > MAX_STARTING_BALANCE {
+                return Err(format!("creates account {} with a balance of {}, more than the maximum starting balance {}", transaction.to_account_id, transaction.amount, MAX_STARTING_BALANCE));
+            }
+            if [STAKE_ACCOUNT, ESCROW_ACCOUNT, SLASHED_ACCOUNT, CROSS_SHARD_ACCOUNT].contains(&transaction.to_account_id.as_str()) {
+                return Err(format!("creates the reserved account {}", transaction.to_account_id));
+            }
+            if let Some(owner) = aliases.get(&transaction.to_account_id) {
+                return Err(format!("creates account {} which is registered as an alias of account {}", transaction.to_account_id, owner));
+            }
+            if let TransactionKind::CreateMultisig(policy) = &transaction.kind {
+                multisig.insert(transaction.to_account_id.clone(), policy.clone());
+            }
+            balances.insert(transaction.to_account_id.clone(), transaction.amount);
+            return Ok(());
+        };
+
+        // Settlements are paid from the escrow account, which has no nonce, to whom the escrow names
+        if let Some((escrow_id, refund)) = transaction.settles_escrow() {
+            if from_account_id != ESCROW_ACCOUNT || transaction.fee != Amount::ZERO || transaction.nonce.is_some() {
+                return Err(format!("settles escrow {} from account {} or with a fee or nonce", escrow_id, from_account_id));
+            }
+            let escrow = escrows.check_settlement(escrow_id, refund, height, timestamp).map_err(|e| format!("settles escrow {} which {}", escrow_id, e))?;
+            let to_account_id = if refund { &escrow.sender } else { &escrow.recipient };
+            if transaction.to_account_id != *to_account_id || transaction.amount != escrow.amount {
+                return Err(format!(
+                    "pays {} to account {} from escrow {}, which holds {} for account {}",
+                    transaction.amount, transaction.to_account_id, escrow_id, escrow.amount, to_account_id
+                ));
+            }
+            *balances.entry(ESCROW_ACCOUNT.to_string()).or_default() -= transaction.amount;
+            let to_balance = balances.entry(transaction.to_account_id.clone()).or_default();
+            *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the account paid from the escrow")?;
+            escrows.settle(escrow_id);
+            return Ok(());
+        }
+        // So are the settlements of cross-shard transfers, a commit to the cross-shard account itself
+        if let Some((transfer_id, refund)) = transaction.settles_cross_shard() {
+            if from_account_id != CROSS_SHARD_ACCOUNT || transaction.fee != Amount::ZERO || transaction.nonce.is_some() {
+                return Err(format!("settles cross-shard transfer {} from account {} or with a fee or nonce", transfer_id, from_account_id));
+            }
+            let lock = cross_shard.check_settlement(transfer_id, refund, timestamp).map_err(|e| format!("settles cross-shard transfer {} which {}", transfer_id, e))?;
+            let to_account_id = if refund { &lock.sender } else { CROSS_SHARD_ACCOUNT };
+            if transaction.to_account_id != to_account_id || transaction.amount != lock.amount {
+                return Err(format!(
+                    "pays {} to account {} from cross-shard transfer {}, which locks {} for account {}",
+                    transaction.amount, transaction.to_account_id, transfer_id, lock.amount, to_account_id
+                ));
+            }
+            if refund {
+                *balances.entry(CROSS_SHARD_ACCOUNT.to_string()).or_default() -= transaction.amount;
+                let to_balance = balances.entry(transaction.to_account_id.clone()).or_default();
+                *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the refunded account")?;
+            }
+            cross_shard.settle(transfer_id);
+            return Ok(());
+        }
+        // Scheduled payments are made by the minter without a fee or nonce, in the blocks their schedule is due in
+        if let Some(schedule_id) = transaction.pays_schedule() {
+            let Some(schedule) = schedules.get(schedule_id) else {
+                return Err(format!("pays schedule {} which doesn't exist or has no payments left", schedule_id));
+            };
+            if !schedule.is_due(height) || schedule.paid_at == Some(height) {
+                return Err(format!("pays schedule {} which isn't due at height {} or was paid in the block already", schedule_id, height));
+            }
+            if *from_account_id != schedule.sender || transaction.to_account_id != schedule.recipient || transaction.amount != schedule.amount {
+                return Err(format!(
+                    "pays {} from account {} to account {} by schedule {}, which pays {} from account {} to account {}",
+                    transaction.amount, from_account_id, transaction.to_account_id, schedule_id, schedule.amount, schedule.sender, schedule.recipient
+                ));
+            }
+            if transaction.fee != Amount::ZERO || transaction.nonce.is_some() {
+                return Err(format!("pays schedule {} with a fee or nonce", schedule_id));
+            }
+            if frozen.is_frozen(from_account_id) {
+                return Err(format!("pays schedule {} from account {} which is frozen", schedule_id, from_account_id));
+            }
+            let balance = balances.get(from_account_id).copied().unwrap_or_default();
+            if balance < transaction.amount {
+                return Err(format!("pays {} by schedule {} from account {} with a balance of only {}", transaction.amount, schedule_id, from_account_id, balance));
+            }
+            balances.insert(from_account_id.clone(), balance - transaction.amount);
+            let to_balance = balances.entry(transaction.to_account_id.clone()).or_default();
+            *to_balance = to_balance.checked_add(transaction.amount).ok_or("overflows the balance of the receiving account")?;
+            schedules.pay(schedule_id, height);
+            return Ok(());
+        }
+
+        if !matches!(
+            transaction.kind,
+            TransactionKind::Transfer | TransactionKind::Close | TransactionKind::Stake | TransactionKind::Unstake | TransactionKind::RegisterAlias(_) | TransactionKind::CreateToken { .. } | TransactionKind::TokenTransfer(_)
+                | TransactionKind::MintNft { .. } | TransactionKind::TransferNft(_) | TransactionKind::EscrowTransfer { .. }
+                | TransactionKind::SchedulePayment { .. } | TransactionKind::CancelSchedule(_) | TransactionKind::SwapOffer { .. }
+                | TransactionKind::SwapAccept { .. } | TransactionKind::DeployContract { .. } | TransactionKind::CallContract { .. }
+                | TransactionKind::Propose { .. } | TransactionKind::Vote { .. } | TransactionKind::ExpiringTransfer { .. }
+                | TransactionKind::CrossShardLock { .. }
+        ) {
+            return Err("is a block reward, account creation or freeze with a from account".to_string());
+        }
+        // An alias registration pays its fee from the account it registers the alias for, and moves no funds
+        let registers_alias = match &transaction.kind {
+            TransactionKind::RegisterAlias(alias) => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("registers alias {} with an amount or for another account", alias));
+                }
+                if validate_alias(alias).is_err() {
+                    return Err(format!("registers invalid alias {}", alias));
+                }
+                if let Some(owner) = aliases.get(alias) {
+                    return Err(format!("registers alias {} which belongs to account {}", alias, owner));
+                }
+                if balances.contains_key(alias) {
+                    return Err(format!("registers alias {} which is the ID of an account", alias));
+                }
+                if !balances.contains_key(from_account_id) {
+                    return Err(format!("registers alias {} for account {} which doesn't exist", alias, from_account_id));
+                }
+                Some(alias)
+            }
+            _ => None,
+        };
+        // So does the creation of a token, its supply goes to the account once the transaction is applied
+        let creates_token = match &transaction.kind {
+            TransactionKind::CreateToken { token, supply } => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("creates token {} with an amount or for another account", token));
+                }
+                if validate_token(token).is_err() || *supply <= Amount::ZERO {
+                    return Err(format!("creates invalid token {} or with a supply of {}", token, supply));
+                }
+                if let Some(existing) = tokens.get(token) {
+                    return Err(format!("creates token {} which was created by account {}", token, existing.issuer));
+                }
+                if !balances.contains_key(from_account_id) {
+                    return Err(format!("creates token {} for account {} which doesn't exist", token, from_account_id));
+                }
+                Some((token, *supply))
+            }
+            _ => None,
+        };
+        // And so does the minting of an NFT, which the account owns once the transaction is applied
+        let mints_nft = match &transaction.kind {
+            TransactionKind::MintNft { nft_id: id, metadata } => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("mints NFT {} with an amount or for another account", id));
+                }
+                if transaction.nonce.is_none_or(|nonce| *id != nft_id(from_account_id, nonce)) || nfts.get(id).is_some() {
+                    return Err(format!("mints NFT {} which isn't the ID of its nonce or exists already", id));
+                }
+                if validate_nft_metadata(metadata).is_err() {
+                    return Err(format!("mints NFT {} with invalid metadata", id));
+                }
+                if !balances.contains_key(from_account_id) {
+                    return Err(format!("mints NFT {} for account {} which doesn't exist", id, from_account_id));
+                }
+                Some((id, metadata))
+            }
+            _ => None,
+        };
+        // So do the deployment and the calls of a contract
+        let deploys_contract = match &transaction.kind {
+            TransactionKind::DeployContract { contract_id: id, code } => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("deploys contract {} with an amount or for another account", id));
+                }
+                if transaction.nonce.is_none_or(|nonce| *id != contract_id(from_account_id, nonce)) || contracts.get(id).is_some() {
+                    return Err(format!("deploys contract {} which isn't the ID of its nonce or exists already", id));
+                }
+                if let Err(e) = contract::validate_code(code) {
+                    return Err(format!("deploys contract {} which {}", id, e));
+                }
+                if !balances.contains_key(from_account_id) {
+                    return Err(format!("deploys contract {} for account {} which doesn't exist", id, from_account_id));
+                }
+                Some((id, code))
+            }
+            _ => None,
+        };
+        let calls_contract = match &transaction.kind {
+            TransactionKind::CallContract { contract_id: id, function, input, gas_limit } => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("calls contract {} with an amount or for another account", id));
+                }
+                if contracts.get(id).is_none() || !balances.contains_key(from_account_id) {
+                    return Err(format!("calls contract {} which doesn't exist, or from account {} which doesn't exist", id, from_account_id));
+                }
+                if *gas_limit == 0 || *gas_limit > contract::MAX_GAS_LIMIT || input.len() > contract::MAX_INPUT_LENGTH {
+                    return Err(format!("calls contract {} with a gas limit of {} or an input of {} bytes, which is out of bounds", id, gas_limit, input.len()));
+                }
+                Some((id, function, input, *gas_limit))
+            }
+            _ => None,
+        };
+        // Unstaking pays its fee from the unstaking account and moves the funds from the staking account back to it
+        let unstakes = match &transaction.kind {
+            TransactionKind::Unstake => {
+                if transaction.to_account_id != *from_account_id || transaction.amount <= Amount::ZERO {
+                    return Err(format!("unstakes {} for another account or nothing", transaction.amount));
+                }
+                let stake = stakes.get(from_account_id).copied().unwrap_or_default();
+                if stake < transaction.amount {
+                    return Err(format!("unstakes {} from account {} with a stake of only {}", transaction.amount, from_account_id, stake));
+                }
+                Some(transaction.amount)
+            }
+            _ => None,
+        };
+        // And the proposals and votes of governance
+        let proposes = match &transaction.kind {
+            TransactionKind::Propose { change, activation_height } => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("proposes changing {} with an amount or for another account", change));
+                }
+                if change.validate().is_err() {
+                    return Err(format!("proposes an invalid change of {}", change));
+                }
+                if *activation_height <= height + 1 || !balances.contains_key(from_account_id) {
+                    return Err(format!("proposes a change taking effect at height {}, before it can be voted on, or for account {} which doesn't exist", activation_height, from_account_id));
+                }
+                Some(Proposal { proposer: from_account_id.clone(), change: *change, activation_height: *activation_height, votes: Default::default() })
+            }
+            _ => None,
+        };
+        let votes = match &transaction.kind {
+            TransactionKind::Vote { proposal_id, approve } => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("votes on proposal {} with an amount or for another account", proposal_id));
+                }
+                if !balances.contains_key(from_account_id) {
+                    return Err(format!("votes on proposal {} from account {} which doesn't exist", proposal_id, from_account_id));
+                }
+                if let Err(e) = governance.check_vote(proposal_id, from_account_id, height) {
+                    return Err(format!("votes on proposal {} which {}", proposal_id, e));
+                }
+                Some((proposal_id, *approve))
+            }
+            _ => None,
+        };
+        let transfers_nft = match &transaction.kind {
+            TransactionKind::TransferNft(id) => {
+                match nfts.get(id) {
+                    None => return Err(format!("transfers NFT {} which doesn't exist", id)),
+                    Some(nft) if nft.owner != *from_account_id => return Err(format!("transfers NFT {} from account {} but it is owned by account {}", id, from_account_id, nft.owner)),
+                    Some(_) if transaction.amount != Amount::ZERO => return Err(format!("transfers NFT {} with an amount", id)),
+                    Some(_) => {}
+                }
+                Some(id)
+            }
+            _ => None,
+        };
+        let locks_cross_shard = match &transaction.kind {
+            TransactionKind::CrossShardLock { recipient, shard, deadline } => {
+                if transaction.amount <= Amount::ZERO || timestamp > *deadline {
+                    return Err(format!("locks {} for account {} on shard {} until {}, which passed", transaction.amount, recipient, shard, deadline));
+                }
+                Some(Lock { sender: from_account_id.clone(), recipient: recipient.clone(), shard: *shard, amount: transaction.amount, deadline: *deadline })
+            }
+            _ => None,
+        };
+        let opens_escrow = match &transaction.kind {
+            TransactionKind::EscrowTransfer { recipient, release, expiry } => {
+                if transaction.amount <= Amount::ZERO || release.is_before(expiry) == Some(false) {
+                    return Err(format!("escrows {} released at {}, which isn't before it expires at {}", transaction.amount, release, expiry));
+                }
+                if !balances.contains_key(recipient) || closed.contains(recipient) || recipient == from_account_id {
+                    return Err(format!("escrows funds for account {} which doesn't exist, was closed or sends them", recipient));
+                }
+                Some(Escrow { sender: from_account_id.clone(), recipient: recipient.clone(), amount: transaction.amount, release: *release, expiry: *expiry })
+            }
+            _ => None,
+        };
+        // A payment schedule pays the fee from the paying account to the recipient, and moves no funds itself
+        let creates_schedule = match &transaction.kind {
+            TransactionKind::SchedulePayment { amount, start_height, interval, count } => {
+                if transaction.amount != Amount::ZERO || *amount <= Amount::ZERO {
+                    return Err(format!("schedules payments of {} with an amount of {}", amount, transaction.amount));
+                }
+                if *interval == 0 || Schedule::last_height(*start_height, *interval, *count).is_none() {
+                    return Err(format!("schedules {} payments every {} blocks from height {}, which isn't a valid schedule", count, interval, start_height));
+                }
+                if !balances.contains_key(&transaction.to_account_id) {
+                    return Err(format!("schedules payments to account {} which doesn't exist", transaction.to_account_id));
+                }
+                let schedule = Schedule {
+                    sender: from_account_id.clone(),
+                    recipient: transaction.to_account_id.clone(),
+                    amount: *amount,
+                    start_height: *start_height,
+                    interval: *interval,
+                    count: *count,
+                    paid_at: None,
+                };
+                Some(schedule)
+            }
+            _ => None,
+        };
+        // The cancellation of a schedule pays its fee from the paying account, which is also the to account
+        let cancels_schedule = match &transaction.kind {
+            TransactionKind::CancelSchedule(schedule_id) => {
+                if transaction.to_account_id != *from_account_id || transaction.amount != Amount::ZERO {
+                    return Err(format!("cancels schedule {} with an amount or for another account", schedule_id));
+                }
+                if schedules.get(schedule_id).is_none_or(|schedule| schedule.sender != *from_account_id) {
+                    return Err(format!("cancels schedule {} which doesn't exist, has no payments left or isn't paid by account {}", schedule_id, from_account_id));
+                }
+                Some(schedule_id)
+            }
+            _ => None,
+        };
+        // A swap offer pays the fee from the maker to the counterparty, and moves no funds until it is accepted
+        let makes_offer = match &transaction.kind {
+            TransactionKind::SwapOffer { give, take, expiry_height } => {
+                if transaction.amount != Amount::ZERO || give.amount <= Amount::ZERO || take.amount <= Amount::ZERO || give.token == take.token {
+                    return Err(format!("offers {} for {} with an amount of {}", give, take, transaction.amount));
+                }
+                if let Some(token) = [&give.token, &take.token].into_iter().flatten().find(|token| tokens.get(token).is_none()) {
+                    return Err(format!("offers a swap in token {} which doesn't exist", token));
+                }
+                if *expiry_height <= height + 1 {
+                    return Err(format!("offers a swap that expires at height {}, before it can be accepted", expiry_height));
+                }
+                if !balances.contains_key(&transaction.to_account_id) {
+                    return Err(format!("offers a swap to account {} which doesn't exist", transaction.to_account_id));
+                }
+                let offer = SwapOffer {
+                    maker: from_account_id.clone(),
+                    counterparty: transaction.to_account_id.clone(),
+                    give: give.clone(),
+                    take: take.clone(),
+                    expiry_height: *expiry_height,
+                };
+                Some(offer)
+            }
+            _ => None,
+        };
+        // Its acceptance pays the fee from the counterparty to the maker, and swaps the assets of the offer
+        let accepts_swap = match &transaction.kind {
+            TransactionKind::SwapAccept { offer_id, give, take } => {
+                let offer = swaps.check_acceptance(offer_id, from_account_id, height).map_err(|e| format!("accepts swap offer {} which {}", offer_id, e))?;
+                if transaction.to_account_id != offer.maker || *give != offer.give || *take != offer.take || transaction.amount != Amount::ZERO {
+                    return Err(format!("accepts swap offer {} of {} from account {} for {}, which doesn't match the offer", offer_id, give, transaction.to_account_id, take));
+                }
+                if frozen.is_frozen(&offer.maker) {
+                    return Err(format!("accepts swap offer {} of account {} which is frozen", offer_id, offer.maker));
+                }
+                Some(offer_id)
+            }
+            _ => None,
+        };
+        if from_account_id == ESCROW_ACCOUNT {
+            return Err(format!("transfers escrowed funds out of the escrow account {} without settling an escrow", ESCROW_ACCOUNT));
+        }
+        if opens_escrow.is_some() != (transaction.to_account_id == ESCROW_ACCOUNT) {
+            return Err(format!("moves funds to an account other than the escrow account {} by an escrow transfer, or to it otherwise", ESCROW_ACCOUNT));
+        }
+        if from_account_id == CROSS_SHARD_ACCOUNT {
+            return Err(format!("transfers funds out of the cross-shard account {} without refunding a cross-shard transfer", CROSS_SHARD_ACCOUNT));
+        }
+        if locks_cross_shard.is_some() != (transaction.to_account_id == CROSS_SHARD_ACCOUNT) {
+            return Err(format!("moves funds to an account other than the cross-shard account {} by a cross-shard transfer, or to it otherwise", CROSS_SHARD_ACCOUNT));
+        }
+        let token = transaction.token();
+        if let Some(token) = token {
+            if tokens.get(token).is_none() {
+                return Err(format!("transfers token {} which doesn't exist", token));
+            }
+            let token_balance = tokens.balance(token, from_account_id);
+            if token_balance < transaction.amount {
+                return Err(format!("transfers {} {} from account {} with a balance of only {} {}", transaction.amount, token, from_account_id, token_balance, token));
+            }
+        }
+        if from_account_id == STAKE_ACCOUNT {
+            return Err(format!("transfers staked funds out of the staking account {}", STAKE_ACCOUNT));
+        }
+        if from_account_id == SLASHED_ACCOUNT || transaction.to_account_id == SLASHED_ACCOUNT {
+            return Err(format!("moves funds out of or into the slashed account {} without slashing", SLASHED_ACCOUNT));
+        }
+        let stakes_funds = transaction.kind == TransactionKind::Stake;
+        if stakes_funds != (transaction.to_account_id == STAKE_ACCOUNT) {
+            return Err(format!("moves funds to an account other than the staking account {} by staking, or to it without staking", STAKE_ACCOUNT));
+        }
+        if stakes_funds && transaction.amount == Amount::ZERO {
+            return Err("stakes nothing".to_string());
+        }
+        if frozen.is_frozen(from_account_id) {
+            return Err(format!("transfers from account {} which is frozen", from_account_id));
+        }
+        if from_account_id == &transaction.to_account_id && registers_alias.is_none() && creates_token.is_none() && mints_nft.is_none() && cancels_schedule.is_none()
+            && deploys_contract.is_none() && calls_contract.is_none() && proposes.is_none() && votes.is_none()
+            && unstakes.is_none()
+        {
+            return Err("transfers to the same account it transfers from".to_string());
+        }
+        let expected_nonce = nonces.get(from_account_id).copied().unwrap_or(0);
+        if transaction.nonce != Some(expected_nonce) {
+            return Err(format!("has nonce {:?} but the next nonce of account {} is {}", transaction.nonce, from_account_id, expected_nonce));
+        }
+
+        // Token transfers only spend the native coin for their fee
+        let amount = transaction.native_amount();
+        let total = amount.checked_add(transaction.fee).ok_or("has an amount and fee that overflow")?;
+        let balance = balances.get(from_account_id).copied().unwrap_or_default();
+        if balance < total {
+            return Err(format!("transfers {} with a fee of {} from account {} with a balance of only {}", amount, transaction.fee, from_account_id, balance));
+        }
+
+        // Both accounts of a swap have to afford what they give, the counterparty on top of the fee
+        for (giver, _, asset) in transaction.swap_legs().into_iter().flatten() {
+            let available = match &asset.token {
+                Some(token) => tokens.balance(token, giver),
+                None if giver == from_account_id => balance - total,
+                None => balances.get(giver).copied().unwrap_or_default(),
+            };
+            if available < asset.amount {
+                return Err(format!("swaps {} from account {} which only has {} of it", asset, giver, available));
+            }
+        }
+
+        if transaction.kind == TransactionKind::Close {
+            if balance != total {
+                return Err(format!("closes account {} with a balance of {} but only sweeps {} with a fee of {}", from_account_id, balance, transaction.amount, transaction.fee));
+            }
+            if let Some((token, token_balance)) = tokens.held_by(from_account_id).first() {
+                return Err(format!("closes account {} which still holds {} {}", from_account_id, token_balance, token));
+            }
+            if let Some((id, _)) = nfts.owned_by(from_account_id).first() {
+                return Err(format!("closes account {} which still owns NFT {}", from_account_id, id));
+            }
+            if escrows.involving(from_account_id) > 0 {
+                return Err(format!("closes account {} which is involved in an open escrow", from_account_id));
+            }
+            if cross_shard.involving(from_account_id) > 0 {
+                return Err(format!("closes account {} which sent a cross-shard transfer that wasn't settled", from_account_id));
+            }
+            if schedules.involving(from_account_id).iter().any(|(_, schedule)| schedule.sender == *from_account_id) {
+                return Err(format!("closes account {} which still pays a payment schedule", from_account_id));
+            }
+            closed.insert(from_account_id.clone());
+        }
+
+        balances.insert(from_account_id.clone(), balance - total);
+        let to_balance = balances.entry(transaction.to_account_id.clone()).or_default();
+        *to_balance = to_balance.checked_add(amount).ok_or("overflows the balance of the receiving account")?;
+        nonces.insert(from_account_id.clone(), expected_nonce + 1);
+        if stakes_funds {
+            let stake = stakes.entry(from_account_id.clone()).or_default();
+            *stake = stake.checked_add(transaction.amount).ok_or("overflows the stake of the staking account")?;
+        }
+        if let Some(amount) = unstakes {
+            let stake = stakes.entry(from_account_id.clone()).or_default();
+            *stake -= amount;
+            if *stake == Amount::ZERO {
+                stakes.remove(from_account_id);
+            }
+            *balances.entry(STAKE_ACCOUNT.to_string()).or_default() -= amount;
+            let from_balance = balances.entry(from_account_id.clone()).or_default();
+            *from_balance = from_balance.checked_add(amount).ok_or("overflows the balance of the unstaking account")?;
+        }
+        if let Some(alias) = registers_alias {
+            aliases.insert(alias.clone(), from_account_id.clone());
+        }
+        if let Some((token, supply)) = creates_token {
+            tokens.create(token, from_account_id, supply);
+        }
+        if let Some(token) = token {
+            tokens.transfer(token, from_account_id, &transaction.to_account_id, transaction.amount);
+        }
+        if let Some((id, metadata)) = mints_nft {
+            nfts.mint(id, from_account_id, metadata);
+        }
+        if let Some(id) = transfers_nft {
+            nfts.transfer(id, &transaction.to_account_id);
+        }
+        if let Some(escrow) = opens_escrow {
+            escrows.open(transaction.id(), escrow);
+        }
+        if let Some(lock) = locks_cross_shard {
+            cross_shard.lock(transaction.id(), lock);
+        }
+        if let Some(schedule) = creates_schedule {
+            schedules.add(transaction.id(), schedule);
+        }
+        if let Some(schedule_id) = cancels_schedule {
+            schedules.cancel(schedule_id);
+        }
+        if let Some((id, code)) = deploys_contract {
+            contracts.deploy(id, from_account_id, code);
+        }
+        if let Some((id, function, input, gas_limit)) = calls_contract {
+            contracts.call(id, from_account_id, function, input, gas_limit, height);
+        }
+        if let Some(proposal) = proposes {
+            governance.propose(transaction.id(), proposal);
+        }
+        if let Some((proposal_id, approve)) = votes {
+            governance.vote(proposal_id, from_account_id, approve);
+        }
+        if let Some(offer) = makes_offer {
+            swaps.offer(transaction.id(), offer);
+        }
+        if let Some(offer_id) = accepts_swap {
+            for (giver, receiver, asset) in transaction.swap_legs().into_iter().flatten() {
+                match &asset.token {
+                    Some(token) => tokens.transfer(token, giver, receiver, asset.amount),
+                    None => {
+                        *balances.entry(giver.to_string()).or_default() -= asset.amount;
+                        let receiver_balance = balances.entry(receiver.to_string()).or_default();
+                        *receiver_balance = receiver_balance.checked_add(asset.amount).ok_or("overflows the balance of an account of the swap")?;
+                    }
+                }
+            }
+            swaps.accept(offer_id);
+        }
+        Ok(())
+    }
+
+    /// Validates a pending transaction like [Accounts::apply] for a block at the given height with the given timestamp
+    /// and applies it, except that the funds it moves to the to account don't count until it is minted, like for
+    /// transactions accepted into the mempool
+    ///
+    /// Returns: why the transaction is invalid
+    pub fn apply_pending(&mut self, transaction: &Transaction, height: u64, timestamp: u64) -> Result<(), String> {
+        self.apply(transaction, height, timestamp)?;
+        if let Some(balance) = self.balances.get_mut(&transaction.to_account_id) {
+            *balance -= transaction.native_amount();
+        }
+        for (_, receiver, amount) in transaction.native_legs() {
+            if let Some(balance) = self.balances.get_mut(receiver) {
+                *balance -= amount;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Validates the block reward at the given index of a block
+///
+/// Returns: why the reward is invalid
+fn verify_reward(block: &Block, height: usize, index: usize) -> Result<(), String> {
+    let reward = &block.transactions[index];
+    if height == 0 {
+        return Err("is a block reward in the genesis block".to_string());
+    }
+    if index + 1 != block.transactions.len() {
+        return Err("is a block reward but not the last transaction of its block".to_string());
+    }
+    if reward.from_account_id.is_some() || reward.nonce.is_some() || reward.fee != Amount::ZERO {
+        return Err("is a block reward with a from account, nonce or fee".to_string());
+    }
+    // Its amount is checked against the fees and the subsidy of the block, see monetary::check_reward
+    Ok(())
+}
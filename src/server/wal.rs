@@ -0,0 +1,124 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use super::storage::FORMAT_VERSION;
+use super::Transaction;
+use crate::vault::Vault;
+
+/// Name of the file in the data directory the pending transactions are logged to
+const WAL_FILE: &str = "pending.wal";
+
+/// Prefix of the write-ahead log, followed by the format version of the ledger (2 bytes, little endian) its
+/// transactions are encoded in
+const MAGIC: &[u8; 3] = b"TCW";
+
+/// Length of the header of the write-ahead log
+const HEADER_LENGTH: usize = MAGIC.len() + 2;
+
+/// Write-ahead log of the transactions accepted into the mempool, so they survive a crash of the node before they are
+/// minted. Every transaction is appended as its length (4 bytes, little endian) followed by the bincode encoded
+/// transaction once it is accepted, and the log is rewritten with the transactions still pending once the block
+/// minting the others is flushed to the ledger file. A restarted node replays the log into its mempool. The
+/// transactions are sealed if the data directory is encrypted.
+pub struct Wal {
+    file: File,
+    path: PathBuf,
+    vault: Option<Vault>,
+}
+
+impl Wal {
+    /// Opens the write-ahead log in the given data directory, creating it if it doesn't exist yet. A log of another
+    /// format version is discarded, as its transactions can't be decoded.
+    ///
+    /// Returns: the log and the transactions logged in it, oldest first
+    pub fn open(data_dir: &str, vault: Option<&Vault>) -> Result<(Wal, Vec<Transaction>), String> {
+        let path = Path::new(data_dir).join(WAL_FILE);
+        let contents = match std::fs::read(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read write-ahead log {}: {}", path.display(), e)),
+        };
+
+        let transactions = match contents.strip_prefix(MAGIC).and_then(|rest| rest.get(..2)) {
+            Some(version) if u16::from_le_bytes(version.try_into().unwrap()) == FORMAT_VERSION => parse_transactions(&contents, &path, vault)?,
+            Some(version) => {
+                tracing::warn!("Discarding write-ahead log {} of format version {}, its pending transactions are lost.", path.display(), u16::from_le_bytes(version.try_into().unwrap()));
+                Vec::new()
+            }
+            None if contents.is_empty() => Vec::new(),
+            None => return Err(format!("Write-ahead log {} is not a write-ahead log of a ToyChain node.", path.display())),
+        };
+
+        // Rewritten right away, dropping an incomplete transaction at its end so that new ones are appended after the
+        // complete ones
+        let file = write_log(&path, &transactions, vault)?;
+        Ok((Wal { file, path, vault: vault.cloned() }, transactions))
+    }
+
+    /// Appends transactions accepted into the mempool to the end of the log
+    pub fn append(&mut self, transactions: &[Transaction]) -> Result<(), String> {
+        let contents: Vec<u8> = transactions.iter().flat_map(|transaction| record(transaction, self.vault.as_ref())).collect();
+        self.file.write_all(&contents).map_err(|e| format!("Failed to write to write-ahead log {}: {}", self.path.display(), e))
+    }
+
+    /// Replaces the logged transactions with the ones still pending, once the others are minted or dropped
+    pub fn truncate<'a>(&mut self, pending: impl Iterator<Item = &'a Transaction>) -> Result<(), String> {
+        let pending: Vec<Transaction> = pending.cloned().collect();
+        self.file = write_log(&self.path, &pending, self.vault.as_ref())?;
+        Ok(())
+    }
+}
+
+fn header() -> Vec<u8> {
+    [MAGIC.as_slice(), &FORMAT_VERSION.to_le_bytes()].concat()
+}
+
+/// Encodes a transaction as it is stored in the log
+fn record(transaction: &Transaction, vault: Option<&Vault>) -> Vec<u8> {
+    let bytes = Vault::seal_with(vault, bincode::serialize(transaction).unwrap());
+    let mut record = Vec::with_capacity(4 + bytes.len());
+    record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    record.extend_from_slice(&bytes);
+    record
+}
+
+/// Rewrites the log with the given transactions. Like the ledger file, the new log is written next to the old one and
+/// then moved over it, so the old log stays intact if the node is stopped while it is written.
+///
+/// Returns: the new log, opened for appending transactions
+fn write_log(path: &Path, transactions: &[Transaction], vault: Option<&Vault>) -> Result<File, String> {
+    let temporary = path.with_extension("wal.tmp");
+    let mut contents = header();
+    for transaction in transactions {
+        contents.extend_from_slice(&record(transaction, vault));
+    }
+
+    let write = || -> std::io::Result<File> {
+        let mut file = File::create(&temporary)?;
+        file.write_all(&contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)?;
+        OpenOptions::new().append(true).open(path)
+    };
+    write().map_err(|e| format!("Failed to rewrite write-ahead log {}: {}", path.display(), e))
+}
+
+/// Parses the complete transactions of the log, a transaction that was only partially written (e.g. the node was killed
+/// while writing it) was never acknowledged as accepted, so it is dropped
+fn parse_transactions(contents: &[u8], path: &Path, vault: Option<&Vault>) -> Result<Vec<Transaction>, String> {
+    let mut transactions = Vec::new();
+    let mut offset = HEADER_LENGTH;
+    while let Some(length) = contents.get(offset..offset + 4) {
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        let Some(bytes) = contents.get(offset + 4..offset + 4 + length) else {
+            tracing::warn!("Dropping incomplete transaction at the end of write-ahead log {}.", path.display());
+            break;
+        };
+        let transaction = Vault::open_with(vault, bytes)
+            .and_then(|bytes| bincode::deserialize(&bytes).map_err(|e| e.to_string()))
+            .map_err(|e| format!("Failed to parse transaction {} of write-ahead log {}: {}", transactions.len(), path.display(), e))?;
+        transactions.push(transaction);
+        offset += 4 + length;
+    }
+    Ok(transactions)
+}
@@ -0,0 +1,231 @@
+//! Webhooks notifying of account activity. A URL registered for an account with `toychain add-webhook` is sent a JSON
+//! payload with an HTTP POST whenever a block with transactions from or to the account is added to the main chain,
+//! with the balance of the account after the block and the transactions. A delivery that fails, because the URL
+//! can't be reached or doesn't answer with a 2xx status, is retried with a doubling delay before it is given up on.
+//! Webhooks are kept in the data directory, so they survive a restart of the node.
+
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::json;
+use tracing::{debug, warn};
+use super::events::Event;
+use super::State;
+
+/// Name of the file in the data directory the webhooks are stored in
+const WEBHOOK_FILE: &str = "webhooks.json";
+/// Number of webhooks the node keeps at most, over all accounts
+const MAX_WEBHOOKS: usize = 1000;
+/// Number of times a notification is sent before it is given up on
+const MAX_ATTEMPTS: u32 = 5;
+/// Delay before the first retry of a notification, doubled with every retry
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Time to wait for a webhook to accept the connection or to respond
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The URLs registered for accounts
+pub struct Webhooks {
+    // File the webhooks are stored in, they are only kept in memory if not set
+    path: Option<PathBuf>,
+    urls: BTreeMap<String, Vec<String>>,
+}
+
+impl Webhooks {
+    /// Loads the webhooks stored in the given data directory, none if it has no webhook file yet
+    pub fn load(data_dir: Option<&str>) -> Result<Webhooks, String> {
+        let path = data_dir.map(|data_dir| Path::new(data_dir).join(WEBHOOK_FILE));
+        let urls = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(text) => serde_json::from_str(&text).map_err(|e| format!("Invalid webhook file {}: {}", path.display(), e))?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+                Err(e) => return Err(format!("Failed to read webhook file {}: {}", path.display(), e)),
+            },
+            None => BTreeMap::new(),
+        };
+        Ok(Webhooks { path, urls })
+    }
+
+    /// Registers a URL to be notified of the activity of an account
+    ///
+    /// Returns: a user-facing message, an error if the URL is invalid or the node has too many webhooks
+    pub fn add(&mut self, account_id: &str, url: &str) -> Result<String, String> {
+        parse_url(url).map_err(|e| format!("❌ Invalid webhook URL {}: {}.", url, e))?;
+        if self.urls.get(account_id).is_some_and(|urls| urls.iter().any(|registered| registered == url)) {
+            return Ok(format!("✅ Webhook {} is registered for account {} already.", url, account_id));
+        }
+        if self.urls.values().map(Vec::len).sum::<usize>() >= MAX_WEBHOOKS {
+            return Err(format!("❌ The node has {} webhooks already, remove one first.", MAX_WEBHOOKS));
+        }
+        self.urls.entry(account_id.to_string()).or_default().push(url.to_string());
+        self.save().map_err(|e| format!("❌ {}", e))?;
+        Ok(format!("✅ Webhook {} registered for account {}.", url, account_id))
+    }
+
+    /// Removes a URL registered for an account
+    ///
+    /// Returns: a user-facing message, an error if the URL isn't registered for the account
+    pub fn remove(&mut self, account_id: &str, url: &str) -> Result<String, String> {
+        let Some(urls) = self.urls.get_mut(account_id).filter(|urls| urls.iter().any(|registered| registered == url)) else {
+            return Err(format!("❌ Webhook {} isn't registered for account {}.", url, account_id));
+        };
+        urls.retain(|registered| registered != url);
+        if urls.is_empty() {
+            self.urls.remove(account_id);
+        }
+        self.save().map_err(|e| format!("❌ {}", e))?;
+        Ok(format!("✅ Webhook {} removed from account {}.", url, account_id))
+    }
+
+    /// Returns the URLs registered for an account
+    pub fn urls(&self, account_id: &str) -> &[String] {
+        self.urls.get(account_id).map_or(&[], Vec::as_slice)
+    }
+
+    /// Describes the webhooks of an account, or of all accounts if none is given
+    pub fn describe(&self, account_id: Option<&str>) -> String {
+        let webhooks: Vec<String> = self
+            .urls
+            .iter()
+            .filter(|(registered, _)| account_id.is_none_or(|account_id| account_id == registered.as_str()))
+            .flat_map(|(registered, urls)| urls.iter().map(move |url| format!("- {}: {}", registered, url)))
+            .collect();
+        match (webhooks.is_empty(), account_id) {
+            (true, Some(account_id)) => format!("No webhooks are registered for account {}.", account_id),
+            (true, None) => "No webhooks are registered.".to_string(),
+            (false, _) => format!("Webhooks:\n{}", webhooks.join("\n")),
+        }
+    }
+
+    /// Saves the webhooks, written next to the old file and then moved over it so a failed write keeps the old file
+    fn save(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let temporary = path.with_extension("json.tmp");
+        let write = || -> std::io::Result<()> {
+            let mut file = std::fs::File::create(&temporary)?;
+            file.write_all(serde_json::to_string_pretty(&self.urls).unwrap().as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, path)
+        };
+        write().map_err(|e| format!("Failed to save webhook file {}: {}", path.display(), e))
+    }
+}
+
+/// Splits a webhook URL into the address to connect to and the path to post to, only plain `http://` URLs are
+/// supported
+fn parse_url(url: &str) -> Result<(String, String), String> {
+    let Some(rest) = url.strip_prefix("http://") else {
+        return Err("only http:// URLs are supported".to_string());
+    };
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if host.is_empty() || host.contains(char::is_whitespace) || path.contains(char::is_whitespace) {
+        return Err("expected http://HOST[:PORT][/PATH]".to_string());
+    }
+    let address = if host.rsplit_once(':').is_some_and(|(_, port)| port.parse::<u16>().is_ok()) { host.to_string() } else { format!("{}:80", host) };
+    Ok((address, path.to_string()))
+}
+
+/// Posts a JSON body to a webhook once
+///
+/// Returns: an error if the webhook can't be reached or doesn't respond with a 2xx status
+fn post(url: &str, body: &str) -> Result<(), String> {
+    let (address, path) = parse_url(url)?;
+    let socket_address = address.to_socket_addrs().map_err(|e| e.to_string())?.next().ok_or("the host has no address")?;
+    let mut stream = TcpStream::connect_timeout(&socket_address, TIMEOUT).map_err(|e| e.to_string())?;
+    stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        address,
+        body.len(),
+        body
+    );
+    stream.write_all(request.as_bytes()).map_err(|e| e.to_string())?;
+
+    // Only the status line is needed, e.g. "HTTP/1.1 200 OK"
+    let mut response = [0u8; 64];
+    let length = stream.read(&mut response).map_err(|e| e.to_string())?;
+    let status_line = String::from_utf8_lossy(&response[..length]);
+    match status_line.split_whitespace().nth(1).and_then(|status| status.parse::<u16>().ok()) {
+        Some(status) if (200..300).contains(&status) => Ok(()),
+        Some(status) => Err(format!("it responded with status {}", status)),
+        None => Err("it didn't respond with HTTP".to_string()),
+    }
+}
+
+/// Posts a notification to a webhook, retrying with a doubling delay until it is accepted or given up on
+fn deliver(url: String, body: String) {
+    let mut delay = FIRST_RETRY_DELAY;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match post(&url, &body) {
+            Ok(()) => {
+                debug!("Notified webhook {}.", url);
+                return;
+            }
+            Err(e) if attempt < MAX_ATTEMPTS => {
+                debug!("Failed to notify webhook {}, retrying in {}s: {}.", url, delay.as_secs(), e);
+                std::thread::sleep(delay);
+                delay *= 2;
+            }
+            Err(e) => warn!("Giving up on notifying webhook {} after {} attempts: {}.", url, MAX_ATTEMPTS, e),
+        }
+    }
+}
+
+/// Notifies the webhooks of the accounts that blocks added to the main chain touch, every notification is delivered
+/// on its own thread so a slow webhook doesn't hold up the others
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn notify(state: Arc<State>) {
+    let events = state.events.subscribe();
+    // The block the AccountChanged events that follow a NewBlock event are for
+    let mut block = None;
+    while let Ok(event) = events.recv() {
+        match event {
+            Event::NewBlock { height, hash, .. } => block = Some((height, hash)),
+            Event::AccountChanged { account_id, balance, height } => {
+                let Some((_, hash)) = block.as_ref().filter(|(block_height, _)| *block_height == height) else {
+                    continue;
+                };
+                let urls = state.webhooks.lock().unwrap().urls(&account_id).to_vec();
+                if urls.is_empty() {
+                    continue;
+                }
+                let ledger = state.ledger.read().unwrap();
+                // The block can be replaced by a fork before the event is handled
+                let Some(minted) = ledger.get(height).filter(|minted| &minted.hash == hash) else {
+                    continue;
+                };
+                let transactions: Vec<_> = minted
+                    .transactions
+                    .iter()
+                    .filter(|transaction| transaction.to_account_id == account_id || transaction.from_account_id.as_ref() == Some(&account_id))
+                    .map(|transaction| json!({ "id": transaction.id(), "transaction": transaction }))
+                    .collect();
+                let body = json!({
+                    "chain_id": state.genesis.chain_id,
+                    "account_id": account_id,
+                    "balance": balance,
+                    "height": height,
+                    "block": hash,
+                    "transactions": transactions,
+                })
+                .to_string();
+                drop(ledger);
+                for url in urls {
+                    let body = body.clone();
+                    std::thread::spawn(move || deliver(url, body));
+                }
+            }
+            Event::NewTransaction { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::time::Duration;
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+use tungstenite::{Message, WebSocket};
+use super::allowlist::Unauthorized;
+use super::events::Event;
+use super::State;
+
+/// How long a connection waits for a message from the client before checking for new events
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A message sent by a client to subscribe to or unsubscribe from a topic, e.g.
+/// `{"action": "subscribe", "topic": "account_changed", "account_id": "Alice"}`
+#[derive(Deserialize)]
+struct ClientMessage {
+    action: Action,
+    topic: Topic,
+    // The account to watch, only used by the account_changed topic
+    account_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    Subscribe,
+    Unsubscribe,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Topic {
+    NewBlock,
+    NewTransaction,
+    AccountChanged,
+}
+
+/// A topic a connection is subscribed to
+#[derive(PartialEq, Eq, Hash)]
+enum Subscription {
+    NewBlock,
+    NewTransaction,
+    Account(String),
+}
+
+impl Subscription {
+    /// Returns the subscription an event belongs to
+    fn of(event: &Event) -> Subscription {
+        match event {
+            Event::NewBlock { .. } => Subscription::NewBlock,
+            Event::NewTransaction { .. } => Subscription::NewTransaction,
+            Event::AccountChanged { account_id, .. } => Subscription::Account(account_id.clone()),
+        }
+    }
+}
+
+/// Starts the WebSocket subscription server on the given address. Every connection can subscribe to any number of
+/// topics and is sent the events of those topics as JSON messages as they happen.
+///
+/// Returns: This function runs indefinitely and should be called on a dedicated thread.
+pub fn serve(state: Arc<State>, addr: &str) {
+    let listener = TcpListener::bind(addr)
+        .unwrap_or_else(|e| panic!("Failed to bind WebSocket server to address {}. Error: {}", addr, e));
+    info!("WebSocket server started on ws://{}.", addr);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Failed to accept WebSocket connection: {}", e);
+                continue;
+            }
+        };
+
+        let state = state.clone();
+        std::thread::spawn(move || handle_connection(&state, stream));
+    }
+}
+
+fn handle_connection(state: &State, stream: TcpStream) {
+    let peer = stream.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+    let mut socket = match tungstenite::accept(stream) {
+        Ok(socket) => socket,
+        Err(e) => {
+            warn!("WebSocket handshake with {} failed: {}", peer, e);
+            return;
+        }
+    };
+
+    // WebSocket clients can't authenticate with a key, so none is allowed in permissioned mode
+    if state.allowlist.is_permissioned() {
+        state.metrics.observe_unauthorized("ws");
+        let error = json!({ "error": "Unauthorized, the node is in permissioned mode and only accepts requests of allowed keys.", "reason": Unauthorized::Unauthenticated.code() });
+        let _ = socket.send(Message::Text(error.to_string()));
+        let _ = socket.close(None);
+        return;
+    }
+
+    // Reads time out so that events can be sent while waiting for messages from the client
+    if let Err(e) = socket.get_ref().set_read_timeout(Some(POLL_INTERVAL)) {
+        error!("Failed to set WebSocket read timeout: {}", e);
+        return;
+    }
+
+    info!("WebSocket client {} connected.", peer);
+    let events = state.events.subscribe();
+    let mut subscriptions = HashSet::new();
+
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let reply = handle_message(&mut subscriptions, &text);
+                if socket.send(Message::Text(reply.to_string())).is_err() {
+                    break;
+                }
+            }
+            Ok(Message::Close(_)) => break,
+            Ok(_) => {}
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(_) => break,
+        }
+
+        if !send_events(&mut socket, &subscriptions, &events) {
+            break;
+        }
+    }
+
+    info!("WebSocket client {} disconnected.", peer);
+}
+
+/// Applies a subscribe or unsubscribe message from the client
+///
+/// Returns: the reply to send back to the client
+fn handle_message(subscriptions: &mut HashSet<Subscription>, text: &str) -> serde_json::Value {
+    let message: ClientMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => return json!({ "error": format!("Invalid message: {}", e) }),
+    };
+
+    let subscription = match (message.topic, message.account_id) {
+        (Topic::NewBlock, _) => Subscription::NewBlock,
+        (Topic::NewTransaction, _) => Subscription::NewTransaction,
+        (Topic::AccountChanged, Some(account_id)) => Subscription::Account(account_id),
+        (Topic::AccountChanged, None) => return json!({ "error": "The account_changed topic requires an account_id." }),
+    };
+
+    match message.action {
+        Action::Subscribe => {
+            subscriptions.insert(subscription);
+            json!({ "result": "subscribed" })
+        }
+        Action::Unsubscribe => {
+            subscriptions.remove(&subscription);
+            json!({ "result": "unsubscribed" })
+        }
+    }
+}
+
+/// Sends all events published since the last call that the connection is subscribed to
+///
+/// Returns: false if sending failed and the connection should be closed
+fn send_events(
+    socket: &mut WebSocket<TcpStream>,
+    subscriptions: &HashSet<Subscription>,
+    events: &std::sync::mpsc::Receiver<Event>,
+) -> bool {
+    while let Ok(event) = events.try_recv() {
+        if subscriptions.contains(&Subscription::of(&event))
+            && socket.send(Message::Text(serde_json::to_string(&event).unwrap())).is_err()
+        {
+            return false;
+        }
+    }
+    true
+}
@@ -0,0 +1,126 @@
+//! The worker pool processing requests, and the order requests changing the same account are processed in. Every
+//! request received is handled by its own task, and at most `--workers` of them are processed at once, so a slow
+//! request doesn't hold up the requests of other clients while the node isn't overloaded by a burst of them. Requests
+//! changing an account, e.g. transfers from it, are processed in the order they arrived in, so a client can send
+//! transfers with consecutive nonces without waiting for the responses.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Longest time a request changing an account waits for the requests that arrived before it to be decoded, after
+/// which it is processed without knowing whether they change the same account
+const DECODE_WAIT: Duration = Duration::from_secs(1);
+
+/// Bounds the number of requests processed at once
+pub struct Workers {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+/// A worker processing a request, freed when dropped
+pub struct Worker<'a> {
+    workers: &'a Workers,
+}
+
+impl Workers {
+    pub fn new(count: usize) -> Workers {
+        Workers { available: Mutex::new(count.max(1)), freed: Condvar::new() }
+    }
+
+    /// Waits for a free worker
+    pub fn acquire(&self) -> Worker<'_> {
+        let mut available = self.freed.wait_while(self.available.lock().unwrap(), |available| *available == 0).unwrap();
+        *available -= 1;
+        Worker { workers: self }
+    }
+}
+
+impl Drop for Worker<'_> {
+    fn drop(&mut self) {
+        *self.workers.available.lock().unwrap() += 1;
+        self.workers.freed.notify_one();
+    }
+}
+
+#[derive(Default)]
+struct Requests {
+    // Sequence number of the next request to arrive
+    next: u64,
+    // Requests that arrived but weren't decoded yet, so the accounts they change aren't known
+    undecoded: BTreeSet<u64>,
+    // The requests in progress changing every account
+    changing: HashMap<String, BTreeSet<u64>>,
+}
+
+/// Numbers requests in the order they arrive in and holds back a request changing an account until the requests
+/// changing it that arrived before it are processed
+#[derive(Default)]
+pub struct Sequencer {
+    requests: Mutex<Requests>,
+    processed: Condvar,
+}
+
+/// The place of a request in the order requests arrived in, given up when dropped
+pub struct Ticket {
+    sequencer: Arc<Sequencer>,
+    sequence: u64,
+    accounts: Mutex<Vec<String>>,
+}
+
+impl Sequencer {
+    /// Numbers a request that just arrived, before it is decoded
+    pub fn admit(self: &Arc<Self>) -> Ticket {
+        let mut requests = self.requests.lock().unwrap();
+        let sequence = requests.next;
+        requests.next += 1;
+        requests.undecoded.insert(sequence);
+        Ticket { sequencer: self.clone(), sequence, accounts: Mutex::new(Vec::new()) }
+    }
+}
+
+impl Ticket {
+    /// Records the accounts a decoded request changes and waits until the requests changing them that arrived before
+    /// it are processed. Requests that don't change an account don't wait.
+    pub fn wait_for_turn(&self, accounts: &[&str]) {
+        let sequencer = &self.sequencer;
+        let mut requests = sequencer.requests.lock().unwrap();
+        requests.undecoded.remove(&self.sequence);
+        for account_id in accounts {
+            requests.changing.entry(account_id.to_string()).or_default().insert(self.sequence);
+        }
+        *self.accounts.lock().unwrap() = accounts.iter().map(|account_id| account_id.to_string()).collect();
+        sequencer.processed.notify_all();
+        if accounts.is_empty() {
+            return;
+        }
+
+        let deadline = Instant::now() + DECODE_WAIT;
+        loop {
+            let now = Instant::now();
+            let undecoded_before = now < deadline && requests.undecoded.range(..self.sequence).next().is_some();
+            let changing_before = accounts.iter().any(|account_id| requests.changing.get(*account_id).is_some_and(|changing| changing.range(..self.sequence).next().is_some()));
+            if !undecoded_before && !changing_before {
+                return;
+            }
+            let timeout = if undecoded_before { deadline - now } else { DECODE_WAIT };
+            requests = sequencer.processed.wait_timeout(requests, timeout).unwrap().0;
+        }
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut requests = self.sequencer.requests.lock().unwrap();
+        requests.undecoded.remove(&self.sequence);
+        for account_id in self.accounts.lock().unwrap().iter() {
+            if let Some(changing) = requests.changing.get_mut(account_id) {
+                changing.remove(&self.sequence);
+                if changing.is_empty() {
+                    requests.changing.remove(account_id);
+                }
+            }
+        }
+        self.sequencer.processed.notify_all();
+    }
+}
@@ -0,0 +1,80 @@
+//! The shard map of a sharded cluster, for scale experiments: the accounts are partitioned across the nodes of the
+//! cluster by the hash of their ID, and every node only holds and mints the accounts of its shard. The nodes and their
+//! clients share the shard map, a JSON file listing the address of the node of every shard, so a client sends every
+//! request to the node holding its account:
+//!
+//! ```json
+//! { "shards": ["192.168.1.10:1337", "192.168.1.11:1337", "192.168.1.12:1337"] }
+//! ```
+//!
+//! Funds can only move between shards if the map also lists the node ID of the node of every shard in `node_ids`, in
+//! the order of the shards, as a shard only credits the transfers the node of another shard signed.
+
+use std::collections::HashSet;
+use serde::Deserialize;
+use sha2::Digest;
+use crate::keys;
+
+/// The addresses of the nodes of a sharded cluster, by shard
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ShardMap {
+    shards: Vec<String>,
+    // Node IDs of the nodes of the shards, empty if funds can't move between shards
+    #[serde(default)]
+    node_ids: Vec<String>,
+}
+
+impl ShardMap {
+    /// Reads, parses and validates the shard map at the given path
+    pub fn load(path: &str) -> Result<ShardMap, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Failed to read shard map {}: {}", path, e))?;
+        let map: ShardMap = serde_json::from_str(&contents).map_err(|e| format!("Failed to parse shard map {}: {}", path, e))?;
+        if map.shards.is_empty() {
+            return Err(format!("Invalid shard map {}: it has to list the node of at least 1 shard.", path));
+        }
+        let mut addresses = HashSet::new();
+        if let Some(address) = map.shards.iter().find(|address| !addresses.insert(*address)) {
+            return Err(format!("Invalid shard map {}: node {} holds more than one shard.", path, address));
+        }
+        if !map.node_ids.is_empty() && map.node_ids.len() != map.shards.len() {
+            return Err(format!("Invalid shard map {}: it lists {} node IDs for {} shards.", path, map.node_ids.len(), map.shards.len()));
+        }
+        if let Some(node_id) = map.node_ids.iter().find(|node_id| keys::parse_public_key(node_id).is_err()) {
+            return Err(format!("Invalid shard map {}: {} isn't a node ID.", path, node_id));
+        }
+        Ok(map)
+    }
+
+    /// Returns the number of shards
+    pub fn len(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns whether the map has no shards, which [ShardMap::load] doesn't accept
+    pub fn is_empty(&self) -> bool {
+        self.shards.is_empty()
+    }
+
+    /// Returns the shard of an account, from the first 8 bytes of the SHA-256 hash of its ID
+    pub fn shard_of(&self, account_id: &str) -> usize {
+        let hash = sha2::Sha256::digest(account_id.as_bytes());
+        let prefix = u64::from_be_bytes(hash[..8].try_into().unwrap());
+        (prefix % self.shards.len() as u64) as usize
+    }
+
+    /// Returns the address of the node of a shard
+    pub fn node(&self, shard: usize) -> &str {
+        &self.shards[shard]
+    }
+
+    /// Returns the node ID of the node of a shard, None if the map doesn't list the node IDs
+    pub fn node_id(&self, shard: usize) -> Option<&str> {
+        self.node_ids.get(shard).map(String::as_str)
+    }
+
+    /// Returns the address of the node holding an account
+    pub fn node_of(&self, account_id: &str) -> &str {
+        self.node(self.shard_of(account_id))
+    }
+}
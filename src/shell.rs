@@ -0,0 +1,407 @@
+//! The interactive shell of `toychain shell`. Every line is parsed like the arguments of the binary, e.g.
+//! `transfer alice bob 5`, and run with the same client, so the commands share its socket and encrypted session. The
+//! global options, e.g. `--timeout` or `--wallet`, are the ones the shell was started with. On a terminal the line can
+//! be edited, the up and down keys go through the history, which is kept in `~/.toychain_history` across shells, and
+//! tab completes the commands and their options. Lines read from a pipe are run one after another.
+
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::process::Stdio;
+use crate::client::Client;
+use crate::config::Config;
+use crate::vault::Secret;
+
+/// Name of the history file in the home directory
+const HISTORY_FILE: &str = ".toychain_history";
+
+/// Number of lines kept in the history at most, the oldest are forgotten first
+const MAX_HISTORY: usize = 1000;
+
+const PROMPT: &str = "toychain> ";
+
+/// Commands of the shell itself, which aren't commands of the binary
+const SHELL_COMMANDS: [&str; 3] = ["help", "exit", "quit"];
+
+/// Runs the shell until `exit` or the end of the input
+///
+/// # Arguments
+///
+/// * `config`: the config file
+/// * `node_id`: the node ID of the client
+/// * `client`: the client to send the requests with
+/// * `wallet_path`: the path of the wallet file
+/// * `secret`: the passphrase or key file the wallet is encrypted with, if any
+pub fn run(config: &Config, node_id: &str, client: &mut Client, wallet_path: &str, secret: Option<&Secret>) {
+    let mut editor = LineEditor::new();
+    if editor.terminal {
+        println!("Connected to {}, type `help` for the commands and `exit` to leave.", client.server_addr());
+    }
+    while let Some(line) = editor.read_line(PROMPT) {
+        editor.add_history(&line);
+        let mut words = match split(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        match words.first().map(String::as_str) {
+            None => continue,
+            Some("exit" | "quit") => break,
+            // `help` lists the commands, `help COMMAND` shows the usage of one
+            Some("help") => {
+                words.remove(0);
+                words.push("--help".to_string());
+            }
+            Some(command @ ("shell" | "start-node" | "monitor" | "bench")) => {
+                eprintln!("❌ {} can't be run in the shell.", command);
+                continue;
+            }
+            _ => {}
+        }
+        match crate::cli().no_binary_name(true).try_get_matches_from(words) {
+            Ok(matches) => {
+                crate::run_command(&matches, config, node_id.to_string(), client, wallet_path, secret);
+            }
+            Err(e) => {
+                let _ = e.print();
+            }
+        }
+    }
+}
+
+/// Splits a line into words at whitespace. Single and double quotes group words, e.g. for memos with spaces, and a
+/// backslash escapes the next character outside of single quotes.
+///
+/// Returns: the words, or a user-facing error if a quote isn't closed
+fn split(line: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some('\''), c) => word.get_or_insert_with(String::new).push(c),
+            (_, '\\') => {
+                if let Some(escaped) = chars.next() {
+                    word.get_or_insert_with(String::new).push(escaped);
+                }
+            }
+            (Some(_), c) => word.get_or_insert_with(String::new).push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => words.extend(word.take()),
+            (None, c) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(open) = quote {
+        return Err(format!("❌ Unclosed quote {} in the line.", open));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// A key pressed on the terminal
+enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    // Ctrl-U
+    ClearLine,
+    // Ctrl-C
+    Interrupt,
+    // Ctrl-D
+    Eof,
+    Other,
+}
+
+/// Reads the next key from the terminal
+///
+/// Returns: the key, None at the end of the input
+fn read_key(input: &mut impl Read) -> Option<Key> {
+    let mut byte = [0u8; 1];
+    let mut next = |input: &mut dyn Read| input.read_exact(&mut byte).ok().map(|()| byte[0]);
+    let key = match next(input)? {
+        1 => Key::Home,
+        3 => Key::Interrupt,
+        4 => Key::Eof,
+        5 => Key::End,
+        8 | 127 => Key::Backspace,
+        9 => Key::Tab,
+        10 | 13 => Key::Enter,
+        21 => Key::ClearLine,
+        // Escape sequences of the arrow, home, end and delete keys
+        0x1b => match next(input)? {
+            b'[' | b'O' => match next(input)? {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                b'C' => Key::Right,
+                b'D' => Key::Left,
+                b'H' => Key::Home,
+                b'F' => Key::End,
+                digit @ b'0'..=b'9' => {
+                    let mut code = vec![digit];
+                    loop {
+                        match next(input)? {
+                            b'~' => break,
+                            byte => code.push(byte),
+                        }
+                    }
+                    match code.as_slice() {
+                        b"1" | b"7" => Key::Home,
+                        b"3" => Key::Delete,
+                        b"4" | b"8" => Key::End,
+                        _ => Key::Other,
+                    }
+                }
+                _ => Key::Other,
+            },
+            _ => Key::Other,
+        },
+        byte if byte < 0x20 => Key::Other,
+        byte => {
+            // The remaining bytes of a UTF-8 encoded character
+            let length = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..length {
+                bytes.push(next(input)?);
+            }
+            match std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+                Some(c) => Key::Char(c),
+                None => Key::Other,
+            }
+        }
+    };
+    Some(key)
+}
+
+/// Runs `stty` on the terminal of the shell
+///
+/// Returns: the output of `stty`, None if it failed, e.g. as there is no `stty`
+fn stty(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("stty").args(args).stdin(Stdio::inherit()).stderr(Stdio::null()).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Switches the terminal to reading single keys without echoing them while it is alive, so the line editor can
+/// handle them. The settings of the terminal are restored when it is dropped.
+pub struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    /// Returns: None if the terminal can't be switched, e.g. as the input isn't a terminal
+    pub fn enable() -> Option<RawMode> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Some(RawMode { saved })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        stty(&[&self.saved]);
+    }
+}
+
+/// Reads the lines of the shell, with editing, history and completion on a terminal
+struct LineEditor {
+    // Whether the input is a terminal, otherwise lines are read as they are
+    terminal: bool,
+    // The lines entered before, oldest first
+    history: Vec<String>,
+    history_path: Option<PathBuf>,
+    // The commands with their options and subcommands, for completion
+    commands: Vec<(String, Vec<String>)>,
+}
+
+impl LineEditor {
+    fn new() -> LineEditor {
+        let home = std::env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" });
+        let history_path = home.map(|home| PathBuf::from(home).join(HISTORY_FILE));
+        let mut history: Vec<String> = match &history_path {
+            Some(path) => std::fs::read_to_string(path).unwrap_or_default().lines().map(str::to_string).collect(),
+            None => Vec::new(),
+        };
+        history.drain(..history.len().saturating_sub(MAX_HISTORY));
+        let commands = crate::cli()
+            .get_subcommands()
+            .map(|command| {
+                let options = command.get_subcommands().map(|subcommand| subcommand.get_name().to_string());
+                let flags = command.get_arguments().filter_map(|arg| arg.get_long()).map(|long| format!("--{}", long));
+                (command.get_name().to_string(), options.chain(flags).collect())
+            })
+            .collect();
+        LineEditor { terminal: std::io::stdin().is_terminal(), history, history_path, commands }
+    }
+
+    /// Adds a line to the history and appends it to the history file, unless it is empty or the same as the last one
+    fn add_history(&mut self, line: &str) {
+        if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > MAX_HISTORY {
+            self.history.remove(0);
+        }
+        if let Some(path) = &self.history_path {
+            // The history is only a convenience, so failing to save it doesn't interrupt the shell
+            let file = std::fs::OpenOptions::new().create(true).append(true).open(path);
+            if let Ok(mut file) = file {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /// Reads the next line
+    ///
+    /// Returns: the line, None at the end of the input
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        let raw_mode = match self.terminal {
+            true => RawMode::enable(),
+            false => None,
+        };
+        if raw_mode.is_none() {
+            if self.terminal {
+                print!("{}", prompt);
+                let _ = std::io::stdout().flush();
+            }
+            let mut line = String::new();
+            return match std::io::stdin().lock().read_line(&mut line) {
+                Ok(0) | Err(_) => None,
+                Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+            };
+        }
+
+        let mut stdin = std::io::stdin().lock();
+        let mut out = std::io::stdout();
+        let mut line: Vec<char> = Vec::new();
+        let mut cursor = 0;
+        // Position in the history, the history length while editing a new line, which is kept while going through it
+        let mut position = self.history.len();
+        let mut draft = Vec::new();
+        redraw(&mut out, prompt, &line, cursor);
+        loop {
+            match read_key(&mut stdin)? {
+                Key::Char(c) => {
+                    line.insert(cursor, c);
+                    cursor += 1;
+                }
+                Key::Enter => {
+                    println!();
+                    return Some(line.into_iter().collect());
+                }
+                Key::Backspace if cursor > 0 => {
+                    cursor -= 1;
+                    line.remove(cursor);
+                }
+                Key::Delete if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::Eof if line.is_empty() => {
+                    println!();
+                    return None;
+                }
+                Key::Eof if cursor < line.len() => {
+                    line.remove(cursor);
+                }
+                Key::Interrupt => {
+                    println!("^C");
+                    return Some(String::new());
+                }
+                Key::Left => cursor = cursor.saturating_sub(1),
+                Key::Right => cursor = (cursor + 1).min(line.len()),
+                Key::Home => cursor = 0,
+                Key::End => cursor = line.len(),
+                Key::ClearLine => {
+                    line.drain(..cursor);
+                    cursor = 0;
+                }
+                Key::Up if position > 0 => {
+                    if position == self.history.len() {
+                        draft = line.clone();
+                    }
+                    position -= 1;
+                    line = self.history[position].chars().collect();
+                    cursor = line.len();
+                }
+                Key::Down if position < self.history.len() => {
+                    position += 1;
+                    line = match self.history.get(position) {
+                        Some(entry) => entry.chars().collect(),
+                        None => std::mem::take(&mut draft),
+                    };
+                    cursor = line.len();
+                }
+                Key::Tab => self.complete(&mut out, prompt, &mut line, &mut cursor),
+                _ => {}
+            }
+            redraw(&mut out, prompt, &line, cursor);
+        }
+    }
+
+    /// Completes the word before the cursor: the first word with a command, the next ones with the options and
+    /// subcommands of the command. A single match is inserted, of several matches their common prefix, or they are
+    /// listed below the line if they have none.
+    fn complete(&self, out: &mut impl Write, prompt: &str, line: &mut Vec<char>, cursor: &mut usize) {
+        let before: String = line[..*cursor].iter().collect();
+        let start = before.rfind(char::is_whitespace).map_or(0, |index| index + 1);
+        let (previous, word) = before.split_at(start);
+        let previous: Vec<&str> = previous.split_whitespace().collect();
+        let names = || self.commands.iter().map(|(name, _)| name.as_str());
+        let candidates: Vec<&str> = match previous.as_slice() {
+            [] => SHELL_COMMANDS.into_iter().chain(names()).collect(),
+            ["help"] => names().collect(),
+            [command, ..] => match self.commands.iter().find(|(name, _)| name == command) {
+                Some((_, options)) => options.iter().map(String::as_str).collect(),
+                None => Vec::new(),
+            },
+        };
+        let matches: Vec<&str> = candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect();
+        let completion = match matches.as_slice() {
+            [] => return,
+            [single] => format!("{} ", single),
+            [first, rest @ ..] => {
+                let prefix = rest.iter().fold(first.len(), |length, other| {
+                    first.bytes().zip(other.bytes()).take(length).take_while(|(a, b)| a == b).count()
+                });
+                if prefix == word.len() {
+                    let _ = writeln!(out, "\r\n{}", matches.join("  "));
+                    redraw(out, prompt, line, *cursor);
+                    return;
+                }
+                first[..prefix].to_string()
+            }
+        };
+        for c in completion[word.len()..].chars() {
+            line.insert(*cursor, c);
+            *cursor += 1;
+        }
+    }
+}
+
+/// Draws the prompt and the line being edited over the current line of the terminal, with the cursor at its position
+fn redraw(out: &mut impl Write, prompt: &str, line: &[char], cursor: usize) {
+    let text: String = line.iter().collect();
+    let _ = write!(out, "\r{}{}\x1b[K", prompt, text);
+    if cursor < line.len() {
+        let _ = write!(out, "\x1b[{}D", line.len() - cursor);
+    }
+    let _ = out.flush();
+}
@@ -0,0 +1,225 @@
+//! Encryption at rest of the data directory and the wallet with AES-256-GCM. The key is derived from a passphrase with
+//! PBKDF2-HMAC-SHA256, or from the contents of a key file with SHA-256, and a random salt, so the same passphrase gives
+//! different keys for different data directories and wallets.
+//!
+//! An encrypted data directory holds its salt in `vault.json`, together with a known value sealed with its key that
+//! tells a wrong passphrase apart from corrupted data. Its ledger, archive, write-ahead log, snapshot and key files are
+//! then sealed record by record, every record with its own random nonce.
+
+use std::io::Write;
+use std::path::Path;
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::hmac::pbkdf2_sha256;
+use crate::noise::{from_hex, to_hex};
+
+/// Name of the file in the data directory the salt of an encrypted data directory is stored in
+const VAULT_FILE: &str = "vault.json";
+
+/// Number of random bytes the key is derived with
+const SALT_LENGTH: usize = 16;
+
+/// Number of bytes of the nonce every sealed record starts with
+const NONCE_LENGTH: usize = 12;
+
+/// Number of PBKDF2 iterations a key is derived from a passphrase with
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+/// Key files shorter than this are rejected, as they are likely not random keys
+const MIN_KEY_FILE_LENGTH: usize = 16;
+
+/// Value sealed in the vault file to check the key of a data directory
+const CHECK_VALUE: &[u8] = b"toychain";
+
+/// What the key of a vault is derived from
+#[derive(Clone)]
+pub enum Secret {
+    Passphrase(String),
+    KeyFile(Vec<u8>),
+}
+
+// Never prints the secret, e.g. when the node's configuration is logged
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Secret::Passphrase(_) => write!(f, "Passphrase(..)"),
+            Secret::KeyFile(_) => write!(f, "KeyFile(..)"),
+        }
+    }
+}
+
+impl Secret {
+    /// Returns the secret given: a passphrase, from the `TOYCHAIN_PASSPHRASE` environment variable, or the path of a
+    /// key file, None if neither is given
+    pub fn load(passphrase: Option<&str>, key_file: Option<&str>) -> Result<Option<Secret>, String> {
+        match (passphrase, key_file) {
+            (Some(_), Some(_)) => Err("❌ Pass either a passphrase or a key file, not both.".to_string()),
+            (Some(passphrase), None) => Ok(Some(Secret::Passphrase(passphrase.to_string()))),
+            (None, Some(path)) => {
+                let contents = std::fs::read(path).map_err(|e| format!("❌ Failed to read key file {}: {}", path, e))?;
+                if contents.len() < MIN_KEY_FILE_LENGTH {
+                    return Err(format!("❌ Key file {} has {} bytes, it needs at least {} random bytes.", path, contents.len(), MIN_KEY_FILE_LENGTH));
+                }
+                Ok(Some(Secret::KeyFile(contents)))
+            }
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Derives the key with the given salt
+    fn derive_key(&self, salt: &[u8; SALT_LENGTH]) -> [u8; 32] {
+        match self {
+            Secret::Passphrase(passphrase) => pbkdf2_sha256(passphrase.as_bytes(), salt, PBKDF2_ITERATIONS),
+            Secret::KeyFile(contents) => {
+                let mut hasher = Sha256::new();
+                hasher.update(salt);
+                hasher.update(contents);
+                hasher.finalize().into()
+            }
+        }
+    }
+}
+
+/// The key records are sealed and opened with, and the salt it was derived with
+#[derive(Clone)]
+pub struct Vault {
+    cipher: Aes256Gcm,
+    salt: [u8; SALT_LENGTH],
+}
+
+/// How a vault is stored: its salt and the check value sealed with its key, hex encoded
+#[derive(Serialize, Deserialize)]
+struct VaultFile {
+    salt: String,
+    check: String,
+}
+
+impl Vault {
+    /// Derives a new key from the secret with a random salt
+    pub fn new(secret: &Secret) -> Vault {
+        let mut salt = [0u8; SALT_LENGTH];
+        getrandom::getrandom(&mut salt).expect("Failed to generate a random salt.");
+        Vault::with_salt(secret, salt)
+    }
+
+    fn with_salt(secret: &Secret, salt: [u8; SALT_LENGTH]) -> Vault {
+        let key = secret.derive_key(&salt);
+        Vault {
+            cipher: Aes256Gcm::new_from_slice(&key).unwrap(),
+            salt,
+        }
+    }
+
+    /// Unlocks the vault of a data directory with the given secret
+    ///
+    /// # Arguments
+    ///
+    /// * `data_dir`: the data directory
+    /// * `secret`: the passphrase or key file given, if any
+    /// * `create`: whether a new, empty data directory is encrypted with the secret, otherwise the secret is ignored
+    ///   for unencrypted data directories
+    ///
+    /// Returns: the vault, None if the data directory isn't encrypted, or why it can't be unlocked
+    pub fn unlock(data_dir: &str, secret: Option<&Secret>, create: bool) -> Result<Option<Vault>, String> {
+        let path = Path::new(data_dir).join(VAULT_FILE);
+        let file = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str::<VaultFile>(&text).map_err(|e| format!("Invalid vault file {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return match secret {
+                Some(secret) if create => Vault::create(data_dir, secret).map(Some),
+                _ => Ok(None),
+            },
+            Err(e) => return Err(format!("Failed to read vault file {}: {}", path.display(), e)),
+        };
+
+        let Some(secret) = secret else {
+            return Err(format!("Data directory {} is encrypted, pass its passphrase in the TOYCHAIN_PASSPHRASE environment variable or its key file with --key-file.", data_dir));
+        };
+        let salt = from_hex(&file.salt).ok().and_then(|salt| salt.try_into().ok()).ok_or_else(|| format!("Invalid salt in vault file {}.", path.display()))?;
+        let vault = Vault::with_salt(secret, salt);
+        let check = from_hex(&file.check).map_err(|_| format!("Invalid check value in vault file {}.", path.display()))?;
+        match vault.open(&check) {
+            Ok(value) if value == CHECK_VALUE => Ok(Some(vault)),
+            _ => Err(format!("Wrong passphrase or key file for data directory {}.", data_dir)),
+        }
+    }
+
+    /// Encrypts a new data directory, encryption can't be enabled for one that holds unencrypted files already
+    fn create(data_dir: &str, secret: &Secret) -> Result<Vault, String> {
+        let is_empty = std::fs::read_dir(data_dir).map_or(true, |mut entries| entries.next().is_none());
+        if !is_empty {
+            return Err(format!(
+                "Data directory {} isn't encrypted, only new data directories can be. Export its chain with `export-chain` and import it into a new data directory with `import-chain` and the passphrase or key file.",
+                data_dir
+            ));
+        }
+        std::fs::create_dir_all(data_dir).map_err(|e| format!("Failed to create data directory {}: {}", data_dir, e))?;
+
+        let vault = Vault::new(secret);
+        let file = VaultFile { salt: to_hex(&vault.salt), check: to_hex(&vault.seal(CHECK_VALUE)) };
+        let path = Path::new(data_dir).join(VAULT_FILE);
+        let write = || -> std::io::Result<()> {
+            let mut file_handle = std::fs::File::create(&path)?;
+            file_handle.write_all(serde_json::to_string_pretty(&file).unwrap().as_bytes())?;
+            file_handle.sync_all()
+        };
+        write().map_err(|e| format!("Failed to write vault file {}: {}", path.display(), e))?;
+        tracing::info!("Encrypting data directory {} at rest.", data_dir);
+        Ok(vault)
+    }
+
+    /// Seals a record with a random nonce, which the sealed record starts with
+    pub fn seal(&self, plaintext: &[u8]) -> Vec<u8> {
+        let mut nonce = [0u8; NONCE_LENGTH];
+        getrandom::getrandom(&mut nonce).expect("Failed to generate a random nonce.");
+        let ciphertext = self.cipher.encrypt(Nonce::from_slice(&nonce), plaintext).expect("Failed to encrypt a record.");
+        [nonce.as_slice(), &ciphertext].concat()
+    }
+
+    /// Opens a sealed record
+    ///
+    /// Returns: the record, or an error if it wasn't sealed with this key or was modified
+    pub fn open(&self, sealed: &[u8]) -> Result<Vec<u8>, String> {
+        let (nonce, ciphertext) = sealed.split_at_checked(NONCE_LENGTH).ok_or("record is too short to be sealed")?;
+        self.cipher.decrypt(Nonce::from_slice(nonce), ciphertext).map_err(|_| "record can't be decrypted, it was modified or sealed with another key".to_string())
+    }
+
+    /// Seals a record if there is a vault, or returns it as it is
+    pub fn seal_with(vault: Option<&Vault>, plaintext: Vec<u8>) -> Vec<u8> {
+        match vault {
+            Some(vault) => vault.seal(&plaintext),
+            None => plaintext,
+        }
+    }
+
+    /// Opens a record if there is a vault, or returns it as it is
+    pub fn open_with(vault: Option<&Vault>, record: &[u8]) -> Result<Vec<u8>, String> {
+        match vault {
+            Some(vault) => vault.open(record),
+            None => Ok(record.to_vec()),
+        }
+    }
+}
+
+/// A wallet file sealed with a passphrase or key file: the salt of its key and the sealed wallet, hex encoded
+#[derive(Serialize, Deserialize)]
+pub struct SealedFile {
+    pub salt: String,
+    pub sealed: String,
+}
+
+impl SealedFile {
+    /// Seals the contents of a file with a new key derived from the secret
+    pub fn seal(secret: &Secret, contents: &[u8]) -> SealedFile {
+        let vault = Vault::new(secret);
+        SealedFile { salt: to_hex(&vault.salt), sealed: to_hex(&vault.seal(contents)) }
+    }
+
+    /// Opens the contents of the file with the key derived from the secret
+    pub fn open(&self, secret: &Secret) -> Result<Vec<u8>, String> {
+        let salt = from_hex(&self.salt).ok().and_then(|salt| salt.try_into().ok()).ok_or("invalid salt")?;
+        let sealed = from_hex(&self.sealed).map_err(|_| "invalid sealed contents")?;
+        Vault::with_salt(secret, salt).open(&sealed).map_err(|_| "wrong passphrase or key file, or the file was modified".to_string())
+    }
+}
@@ -0,0 +1,237 @@
+//! Local wallet holding the keys of the user's accounts. All keys are derived from a BIP39 mnemonic along the hardened
+//! SLIP-0010 path `m/44'/1337'/<index>'`, so the mnemonic alone restores every key of the wallet.
+//!
+//! Accounts created while a wallet file exists get the next key of the wallet as their only key (a multisig account
+//! with a threshold of 1), and transfers from them are approved with that key automatically.
+//!
+//! Given a passphrase or key file, the wallet file is encrypted when it is saved, see [crate::vault].
+
+use std::io::Write;
+use std::path::Path;
+use bip39::Mnemonic;
+use ed25519_dalek::SigningKey;
+use serde::{Deserialize, Serialize};
+use crate::hmac::hmac_sha512;
+use crate::keys;
+use crate::vault::{Secret, SealedFile};
+
+/// File the wallet is stored in if no other file is given
+pub const DEFAULT_WALLET_FILE: &str = "wallet.json";
+
+/// Number of bytes of entropy of a new mnemonic, which gives 12 words
+const ENTROPY_LENGTH: usize = 16;
+
+/// Hardened derivation path of the keys before the key index: purpose 44' and coin type 1337'
+const DERIVATION_PATH: [u32; 2] = [44, 1337];
+
+/// Offset of hardened indexes, SLIP-0010 only defines hardened derivation for Ed25519
+const HARDENED: u32 = 0x8000_0000;
+
+/// A key of the wallet
+#[derive(Serialize, Deserialize)]
+pub struct WalletKey {
+    // Index of the key in the derivation path
+    pub index: u32,
+    // Account the key was used to create, None while it is unused
+    pub account_id: Option<String>,
+    pub public_key: String,
+    pub private_key: String,
+}
+
+/// The mnemonic and the keys derived from it so far
+#[derive(Serialize, Deserialize)]
+pub struct Wallet {
+    mnemonic: String,
+    keys: Vec<WalletKey>,
+}
+
+impl Wallet {
+    /// Returns a new wallet with a random mnemonic and its first key
+    pub fn generate() -> Wallet {
+        let mut entropy = [0u8; ENTROPY_LENGTH];
+        getrandom::getrandom(&mut entropy).expect("Failed to generate a random mnemonic.");
+        let mnemonic = Mnemonic::from_entropy(&entropy).expect("Invalid mnemonic entropy length.");
+        Wallet::from_mnemonic(mnemonic)
+    }
+
+    /// Restores a wallet from its mnemonic. The mnemonic doesn't record which accounts the keys were used for, so the
+    /// accounts are given in the order they were created with the wallet and get its keys in that order.
+    pub fn restore(mnemonic: &str, accounts: &[String]) -> Result<Wallet, String> {
+        let mnemonic = Mnemonic::parse(mnemonic).map_err(|e| format!("❌ Invalid mnemonic: {}.", e))?;
+        let mut wallet = Wallet::from_mnemonic(mnemonic);
+        for account_id in accounts {
+            wallet.assign_key(account_id);
+        }
+        Ok(wallet)
+    }
+
+    fn from_mnemonic(mnemonic: Mnemonic) -> Wallet {
+        let mut wallet = Wallet {
+            mnemonic: mnemonic.to_string(),
+            keys: Vec::new(),
+        };
+        wallet.derive_next_key();
+        wallet
+    }
+
+    /// Loads the wallet from a file, decrypting it with the given secret if it is encrypted
+    ///
+    /// Returns: None if the file doesn't exist
+    pub fn load(path: &str, secret: Option<&Secret>) -> Result<Option<Wallet>, String> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read wallet file {}: {}", path, e)),
+        };
+        let Ok(sealed) = serde_json::from_str::<SealedFile>(&text) else {
+            return serde_json::from_str(&text).map(Some).map_err(|e| format!("Invalid wallet file {}: {}", path, e));
+        };
+        let Some(secret) = secret else {
+            return Err(format!("❌ Wallet file {} is encrypted, pass its passphrase in the TOYCHAIN_PASSPHRASE environment variable or its key file with --key-file.", path));
+        };
+        let contents = sealed.open(secret).map_err(|e| format!("❌ Failed to decrypt wallet file {}: {}.", path, e))?;
+        serde_json::from_slice(&contents).map(Some).map_err(|e| format!("Invalid wallet file {}: {}", path, e))
+    }
+
+    /// Saves the wallet to a file, readable only by the owner on Unix and encrypted if a secret is given. The file is
+    /// written next to the old one and then moved over it, so the old wallet stays intact if writing fails.
+    pub fn save(&self, path: &str, secret: Option<&Secret>) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).unwrap();
+        let contents = match secret {
+            Some(secret) => serde_json::to_string_pretty(&SealedFile::seal(secret, json.as_bytes())).unwrap(),
+            None => json,
+        };
+        let temporary = Path::new(path).with_extension("json.tmp");
+        let write = || -> std::io::Result<()> {
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options.open(&temporary)?;
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temporary, path)
+        };
+        write().map_err(|e| format!("Failed to save wallet file {}: {}", path, e))
+    }
+
+    pub fn mnemonic(&self) -> &str {
+        &self.mnemonic
+    }
+
+    pub fn keys(&self) -> &[WalletKey] {
+        &self.keys
+    }
+
+    /// Gets the key an account was created with
+    pub fn key_of(&self, account_id: &str) -> Option<SigningKey> {
+        let key = self.keys.iter().find(|key| key.account_id.as_deref() == Some(account_id))?;
+        keys::parse_private_key(&key.private_key).ok()
+    }
+
+    /// Assigns the first unused key to a new account, deriving the next key if all are used. An account that has a key
+    /// already, e.g. because creating it is retried, keeps it.
+    ///
+    /// Returns: the key
+    pub fn assign_key(&mut self, account_id: &str) -> SigningKey {
+        if let Some(key) = self.key_of(account_id) {
+            return key;
+        }
+        if self.keys.iter().all(|key| key.account_id.is_some()) {
+            self.derive_next_key();
+        }
+        let key = self.keys.iter_mut().find(|key| key.account_id.is_none()).unwrap();
+        key.account_id = Some(account_id.to_string());
+        keys::parse_private_key(&key.private_key).unwrap()
+    }
+
+    fn derive_next_key(&mut self) {
+        let index = self.keys.len() as u32;
+        let seed = Mnemonic::parse(&self.mnemonic).expect("Invalid mnemonic in wallet.").to_seed("");
+        let key = derive_key(&seed, index);
+        self.keys.push(WalletKey {
+            index,
+            account_id: None,
+            public_key: keys::public_key_hex(&key),
+            private_key: keys::private_key_hex(&key),
+        });
+    }
+}
+
+/// Derives the key with the given index from the seed of a mnemonic, following SLIP-0010 for Ed25519
+fn derive_key(seed: &[u8], index: u32) -> SigningKey {
+    let path: Vec<u32> = DERIVATION_PATH.into_iter().chain(std::iter::once(index)).collect();
+    let (key, _) = derive_path(seed, &path);
+    SigningKey::from_bytes(&key)
+}
+
+/// Derives the private key and chain code at the given path from a seed by SLIP-0010 for Ed25519, which only has
+/// hardened children, so every segment of the path is hardened
+fn derive_path(seed: &[u8], path: &[u32]) -> ([u8; 32], [u8; 32]) {
+    let split = |mac: [u8; 64]| -> ([u8; 32], [u8; 32]) { (mac[..32].try_into().unwrap(), mac[32..].try_into().unwrap()) };
+    let (mut key, mut chain_code) = split(hmac_sha512(b"ed25519 seed", seed));
+    for segment in path {
+        let data = [&[0u8][..], &key, &(segment | HARDENED).to_be_bytes()].concat();
+        (key, chain_code) = split(hmac_sha512(&chain_code, &data));
+    }
+    (key, chain_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::{from_hex, to_hex};
+
+    /// Checks the derivation against test vectors of SLIP-0010 for Ed25519, each a path with the chain code, private
+    /// key and public key derived at it
+    fn check_vectors(seed: &str, vectors: &[(&[u32], &str, &str, &str)]) {
+        let seed = from_hex(seed).unwrap();
+        for (path, chain_code, private_key, public_key) in vectors {
+            let (key, code) = derive_path(&seed, path);
+            assert_eq!(to_hex(&code), *chain_code, "chain code at {:?}", path);
+            assert_eq!(to_hex(&key), *private_key, "private key at {:?}", path);
+            assert_eq!(keys::public_key_hex(&SigningKey::from_bytes(&key)), *public_key, "public key at {:?}", path);
+        }
+    }
+
+    #[test]
+    fn derives_slip10_test_vector_1() {
+        check_vectors(
+            "000102030405060708090a0b0c0d0e0f",
+            &[
+                (&[], "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb", "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7", "a4b2856bfec510abab89753fac1ac0e1112364e7d250545963f135f2a33188ed"),
+                (&[0], "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69", "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3", "8c8a13df77a28f3445213a0f432fde644acaa215fc72dcdf300d5efaa85d350c"),
+                (&[0, 1], "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14", "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2", "1932a5270f335bed617d5b935c80aedb1a35bd9fc1e31acafd5372c30f5c1187"),
+                (&[0, 1, 2], "2e69929e00b5ab250f49c3fb1c12f252de4fed2c1db88387094a0f8c4c9ccd6c", "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9", "ae98736566d30ed0e9d2f4486a64bc95740d89c7db33f52121f8ea8f76ff0fc1"),
+                (&[0, 1, 2, 2], "8f6d87f93d750e0efccda017d662a1b31a266e4a6f5993b15f5c1f07f74dd5cc", "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662", "8abae2d66361c879b900d204ad2cc4984fa2aa344dd7ddc46007329ac76c429c"),
+                (&[0, 1, 2, 2, 1000000000], "68789923a0cac2cd5a29172a475fe9e0fb14cd6adb5ad98a3fa70333e7afa230", "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793", "3c24da049451555d51a7014a37337aa4e12d41e485abccfa46b47dfb2af54b7a"),
+            ],
+        );
+    }
+
+    #[test]
+    fn derives_slip10_test_vector_2() {
+        check_vectors(
+            "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a29f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542",
+            &[
+                (&[], "ef70a74db9c3a5af931b5fe73ed8e1a53464133654fd55e7a66f8570b8e33c3b", "171cb88b1b3c1db25add599712e36245d75bc65a1a5c9e18d76f9f2b1eab4012", "8fe9693f8fa62a4305a140b9764c5ee01e455963744fe18204b4fb948249308a"),
+                (&[0], "0b78a3226f915c082bf118f83618a618ab6dec793752624cbeb622acb562862d", "1559eb2bbec5790b0c65d8693e4d0875b1747f4970ae8b650486ed7470845635", "86fab68dcb57aa196c77c5f264f215a112c22a912c10d123b0d03c3c28ef1037"),
+                (&[0, 2147483647], "138f0b2551bcafeca6ff2aa88ba8ed0ed8de070841f0c4ef0165df8181eaad7f", "ea4f5bfe8694d8bb74b7b59404632fd5968b774ed545e810de9c32a4fb4192f4", "5ba3b9ac6e90e83effcd25ac4e58a1365a9e35a3d3ae5eb07b9e4d90bcf7506d"),
+                (&[0, 2147483647, 1], "73bd9fff1cfbde33a1b846c27085f711c0fe2d66fd32e139d3ebc28e5a4a6b90", "3757c7577170179c7868353ada796c839135b3d30554bbb74a4b1e4a5a58505c", "2e66aa57069c86cc18249aecf5cb5a9cebbfd6fadeab056254763874a9352b45"),
+                (&[0, 2147483647, 1, 2147483646], "0902fe8a29f9140480a00ef244bd183e8a13288e4412d8389d140aac1794825a", "5837736c89570de861ebc173b1086da4f505d4adb387c6a1b1342d5e4ac9ec72", "e33c0f7d81d843c572275f287498e8d408654fdf0d1e065b84e2e6f157aab09b"),
+                (&[0, 2147483647, 1, 2147483646, 2], "5d70af781f3a37b829f0d060924d5e960bdc02e85423494afc0b1a41bbe196d4", "551d333177df541ad876a60ea71f00447931c0a9da16f227c11ea080d7391b8d", "47150c75db263559a70d5778bf36abbab30fb061ad69f69ece61a72b0cfa4fc0"),
+            ],
+        );
+    }
+
+    /// The keys of a wallet are derived at m/44'/1337'/index' from the BIP-39 seed of its mnemonic, so a mnemonic
+    /// restores the same keys in any SLIP-0010 wallet given that path
+    #[test]
+    fn restores_keys_of_mnemonic() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let mut wallet = Wallet::restore(mnemonic, &[]).unwrap();
+        wallet.derive_next_key();
+        let public_keys: Vec<&str> = wallet.keys().iter().map(|key| key.public_key.as_str()).collect();
+        assert_eq!(public_keys, ["1a95e626e5a12b1b0b49228a6e52707ee1cb5918bc5a2807ecaece41c6692041", "36e4b1a9515aa4d71b11d19f0165854670d990d05dabf7048196aae52304178a"]);
+    }
+}
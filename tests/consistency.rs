@@ -0,0 +1,133 @@
+//! Sequences of requests generated from a seed, run against a node in process. Every block the node mints is checked
+//! against the invariants of the chain in debug builds, and the tests check that the funds of the accounts add up to
+//! the funds the accepted requests issued after every block, also with amounts at and beyond the largest ones allowed,
+//! and that nothing issues funds beyond the maximum supply of a chain.
+
+use std::str::FromStr;
+use toychain::common::{Amount, Request, MAX_STARTING_BALANCE};
+use toychain::config::NodeConfig;
+use toychain::server::{Node, NodeHandle};
+
+const NODE_ID: &str = "test";
+const FAUCET_AMOUNT: Amount = Amount::from_minor_units(10_000);
+
+/// Generates the requests of a sequence, xorshift64 so a failing seed can be replayed
+struct Generator(u64);
+
+impl Generator {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    /// An amount in minor units, mostly small ones but also the largest starting balance, the amounts around it and
+    /// the largest amount
+    fn amount(&mut self) -> Amount {
+        match self.below(10) {
+            0 => MAX_STARTING_BALANCE,
+            1 => MAX_STARTING_BALANCE.checked_add(Amount::from_minor_units(1)).unwrap(),
+            2 => Amount::MAX,
+            3 => Amount::ZERO,
+            _ => Amount::from_minor_units(self.below(1_000_000) as i64),
+        }
+    }
+}
+
+/// Runs a sequence of requests, checking the funds after every block
+fn run_sequence(seed: u64, steps: usize) {
+    let node = Node::spawn_in_process(NodeConfig { simulate: true, enable_faucet: true, faucet_amount: FAUCET_AMOUNT, ..NodeConfig::default() }).unwrap();
+    let mut generator = Generator(seed);
+    let mut accounts: Vec<String> = Vec::new();
+    let mut issued = Amount::ZERO;
+
+    for step in 0..steps {
+        match generator.below(8) {
+            0 | 1 => {
+                let account_id = format!("account-{}", step);
+                let starting_balance = generator.amount();
+                let request = Request::new_create_account_request(NODE_ID.to_string(), account_id.clone(), starting_balance);
+                if node.submit(request).is_ok() {
+                    assert!(starting_balance <= MAX_STARTING_BALANCE, "seed {}: created {} with {}", seed, account_id, starting_balance);
+                    issued = issued.checked_add(starting_balance).unwrap();
+                    accounts.push(account_id);
+                }
+            }
+            2..=4 if accounts.len() >= 2 => {
+                let from = accounts[generator.below(accounts.len() as u64) as usize].clone();
+                let to = accounts[generator.below(accounts.len() as u64) as usize].clone();
+                let fee = Amount::from_minor_units(generator.below(3) as i64);
+                let request = Request::new_transfer_funds_request(NODE_ID.to_string(), from.clone(), to, generator.amount(), fee, node.nonce(&from), None);
+                let _ = node.submit(request);
+            }
+            5 if !accounts.is_empty() => {
+                let account_id = accounts[generator.below(accounts.len() as u64) as usize].clone();
+                let amount = match generator.below(2) {
+                    0 => Some(Amount::from_minor_units(1 + generator.below(FAUCET_AMOUNT.minor_units() as u64 * 2) as i64)),
+                    _ => None,
+                };
+                if node.submit(Request::new_faucet_request(NODE_ID.to_string(), account_id, amount)).is_ok() {
+                    issued = issued.checked_add(amount.unwrap_or(FAUCET_AMOUNT)).unwrap();
+                }
+            }
+            _ => mint_and_check(&node, &accounts, issued, seed),
+        }
+    }
+    mint_and_check(&node, &accounts, issued, seed);
+    node.shutdown();
+}
+
+/// Mints the pending transactions and checks that the balances of the accounts and the fees paid to the node add up
+/// to the funds issued
+fn mint_and_check(node: &NodeHandle, accounts: &[String], issued: Amount, seed: u64) {
+    node.mint();
+    assert_eq!(node.pending(), 0, "seed {}: transactions were left out of the block", seed);
+    let balances = accounts.iter().map(|account_id| node.balance(account_id)).chain([node.balance(node.node_id())]);
+    let total = Amount::checked_sum(balances).unwrap();
+    assert_eq!(total, issued, "seed {}: the balances add up to {} at height {}, but {} were issued", seed, total, node.height(), issued);
+}
+
+#[test]
+fn conserves_funds_of_generated_sequences() {
+    for seed in 1..=20u64 {
+        run_sequence(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15), 200);
+    }
+}
+
+#[test]
+fn issues_nothing_beyond_max_supply() {
+    let dir = std::env::temp_dir().join(format!("toychain-max-supply-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let genesis = dir.join("genesis.json");
+    std::fs::write(
+        &genesis,
+        r#"{"chain_id": "capped", "timestamp": 1700000000, "accounts": [{"account_id": "alice", "balance": 100}], "parameters": {"block_reward": 10, "max_supply": 1000}}"#,
+    )
+    .unwrap();
+    let config = NodeConfig { simulate: true, enable_faucet: true, faucet_amount: FAUCET_AMOUNT, genesis: Some(genesis.to_string_lossy().into_owned()), ..NodeConfig::default() };
+    let node = Node::spawn_in_process(config).unwrap();
+    let amount = |amount: &str| Amount::from_str(amount).unwrap();
+    let create = |account_id: &str, balance: &str| node.submit(Request::new_create_account_request(NODE_ID.to_string(), account_id.to_string(), amount(balance)));
+    let supply = || Amount::checked_sum(["alice", "bob", "carol", node.node_id()].map(|account_id| node.balance(account_id))).unwrap();
+
+    // 900 is left to issue, and the pending creation of bob counts against it
+    create("bob", "850").unwrap();
+    assert!(create("carol", "100").is_err());
+    node.mint();
+    // The block reward is cut to what is left after the creation of bob
+    assert_eq!(supply(), amount("960"));
+
+    create("carol", "40").unwrap();
+    node.mint();
+    assert_eq!(node.balance(node.node_id()), amount("10"));
+    assert_eq!(supply(), amount("1000"));
+    assert!(node.submit(Request::new_faucet_request(NODE_ID.to_string(), "alice".to_string(), None)).is_err());
+    assert!(create("dave", "0.01").is_err());
+    node.shutdown();
+    std::fs::remove_dir_all(dir).unwrap();
+}
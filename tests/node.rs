@@ -0,0 +1,207 @@
+//! Nodes run in process through [NodeHandle], the way downstream crates write integration tests against ToyChain:
+//! requests are submitted and minted without networking, the ledger is read back right away, shutting down mints what
+//! is pending and persists it, and several nodes run side by side in one process, switching to the longer of their
+//! competing branches when their blocks are handed to each other, and queries answer with structured results.
+
+use std::path::PathBuf;
+use toychain::common::{Amount, Request};
+use toychain::config::NodeConfig;
+use toychain::server::{Node, NodeHandle};
+
+const NODE_ID: &str = "test";
+
+fn amount(amount: &str) -> Amount {
+    amount.parse().unwrap()
+}
+
+/// Starts a node on a simulated clock, so it only mints when the test asks it to
+fn spawn(data_dir: Option<&PathBuf>) -> NodeHandle {
+    let data_dir = data_dir.map(|data_dir| data_dir.to_string_lossy().into_owned());
+    Node::spawn_in_process(NodeConfig { simulate: true, data_dir, ..NodeConfig::default() }).unwrap()
+}
+
+/// Returns an empty directory for the data of a test, removing what an earlier run left behind
+fn data_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("toychain-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn create(node: &NodeHandle, account_id: &str, balance: &str) -> Result<String, String> {
+    node.submit(Request::new_create_account_request(NODE_ID.to_string(), account_id.to_string(), amount(balance)))
+}
+
+fn transfer(node: &NodeHandle, from: &str, to: &str, funds: &str) -> Result<String, String> {
+    node.submit(Request::new_transfer_funds_request(NODE_ID.to_string(), from.to_string(), to.to_string(), amount(funds), Amount::ZERO, node.nonce(from), None))
+}
+
+#[test]
+fn submits_mints_and_reads_back() {
+    let node = spawn(None);
+    create(&node, "alice", "100").unwrap();
+    create(&node, "bob", "0").unwrap();
+    assert_eq!(node.pending(), 2);
+    assert_eq!(node.height(), 0);
+    assert_eq!(node.balance("alice"), Amount::ZERO);
+
+    assert!(node.mint().is_some());
+    assert_eq!(node.pending(), 0);
+    assert_eq!(node.height(), 1);
+    assert_eq!(node.balance("alice"), amount("100"));
+    let block = node.block(1).unwrap();
+    assert_eq!(block["transactions"].as_array().unwrap().len(), 2);
+
+    assert_eq!(node.nonce("alice"), 0);
+    transfer(&node, "alice", "bob", "30").unwrap();
+    assert_eq!(node.nonce("alice"), 1);
+    node.mint();
+    assert_eq!(node.height(), 2);
+    assert_eq!(node.balance("alice"), amount("70"));
+    assert_eq!(node.balance("bob"), amount("30"));
+
+    // Nothing to mint
+    assert!(node.mint().is_none());
+    assert!(node.block(3).is_none());
+    node.shutdown();
+}
+
+#[test]
+fn rejects_invalid_requests() {
+    let node = spawn(None);
+    create(&node, "alice", "10").unwrap();
+    node.mint();
+
+    assert!(transfer(&node, "nobody", "alice", "1").is_err());
+    assert!(transfer(&node, "alice", "alice", "11").is_err());
+    assert!(create(&node, "alice", "5").is_err());
+    assert_eq!(node.pending(), 0);
+    assert!(node.mint().is_none());
+    assert_eq!(node.balance("alice"), amount("10"));
+    node.shutdown();
+}
+
+#[test]
+fn shutdown_mints_pending_transactions_and_persists_them() {
+    let dir = data_dir("shutdown");
+    let node = spawn(Some(&dir));
+    create(&node, "alice", "100").unwrap();
+    node.mint();
+    create(&node, "bob", "5").unwrap();
+    assert_eq!(node.pending(), 1);
+    node.shutdown();
+
+    let node = spawn(Some(&dir));
+    assert_eq!(node.height(), 2);
+    assert_eq!(node.pending(), 0);
+    assert_eq!(node.balance("alice"), amount("100"));
+    assert_eq!(node.balance("bob"), amount("5"));
+    node.shutdown();
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn runs_several_nodes_in_one_process() {
+    let first = spawn(None);
+    let second = spawn(None);
+    create(&first, "alice", "100").unwrap();
+    create(&second, "alice", "7").unwrap();
+    create(&second, "bob", "1").unwrap();
+    assert_eq!((first.pending(), second.pending()), (1, 2));
+
+    first.mint();
+    assert_eq!((first.height(), second.height()), (1, 0));
+    assert_eq!((first.balance("alice"), second.balance("alice")), (amount("100"), Amount::ZERO));
+
+    second.mint();
+    assert_eq!(second.balance("alice"), amount("7"));
+    assert_eq!(first.balance("bob"), Amount::ZERO);
+
+    // Dropping a node shuts it down without touching the other one
+    drop(first);
+    transfer(&second, "alice", "bob", "2").unwrap();
+    second.mint();
+    assert_eq!(second.balance("bob"), amount("3"));
+    second.shutdown();
+}
+
+/// Hands the blocks of one node from a height onwards to another node, like `submit-chain` does
+fn deliver(from: &NodeHandle, to: &NodeHandle, heights: std::ops::RangeInclusive<usize>) -> Result<String, String> {
+    let mut result = Err("no blocks to deliver".to_string());
+    for height in heights {
+        result = to.submit(Request::new_submit_block_request(NODE_ID.to_string(), from.encoded_block(height).unwrap()));
+        result.as_ref()?;
+    }
+    result
+}
+
+#[test]
+fn switches_to_a_longer_branch_and_restores_pending_transactions() {
+    let node = spawn(None);
+    let other = spawn(None);
+    create(&node, "alice", "100").unwrap();
+    create(&node, "bob", "0").unwrap();
+    create(&node, "carol", "50").unwrap();
+    node.mint();
+    // Blocks extending the tip are added on top of it
+    assert!(deliver(&node, &other, 1..=1).unwrap().contains("added to the main chain at height 1"));
+    assert_eq!(other.balance("carol"), amount("50"));
+
+    // The branch of the node spends alice's first nonce on another transfer than the branch of the other node
+    transfer(&node, "alice", "bob", "30").unwrap();
+    transfer(&node, "carol", "bob", "20").unwrap();
+    node.mint();
+    transfer(&node, "alice", "bob", "1").unwrap();
+    transfer(&other, "alice", "bob", "10").unwrap();
+    other.mint();
+    transfer(&other, "alice", "bob", "5").unwrap();
+    other.mint();
+    assert_eq!((node.height(), other.height()), (2, 3));
+
+    // A branch as long as the main chain stays a fork
+    assert!(deliver(&other, &node, 2..=2).unwrap().contains("added to a fork at height 2"));
+    assert_eq!(node.balance("bob"), amount("50"));
+
+    let switched = deliver(&other, &node, 3..=3).unwrap();
+    assert!(switched.contains("switched to its fork"), "{}", switched);
+    assert_eq!(node.height(), 3);
+    assert_eq!(node.balance("alice"), amount("85"));
+    assert_eq!(node.balance("bob"), amount("15"));
+    assert_eq!(node.balance("carol"), amount("50"));
+    // The transfers of alice conflict with the new branch and are dropped, carol's transfer is pending again
+    assert_eq!(node.pending(), 1);
+    assert_eq!(node.nonce("alice"), 2);
+    assert_eq!(node.nonce("carol"), 1);
+
+    node.mint();
+    assert_eq!(node.balance("carol"), amount("30"));
+    assert_eq!(node.balance("bob"), amount("35"));
+    assert_eq!(node.nonce("carol"), 1);
+    // The other node follows the block minted on top of its branch
+    deliver(&node, &other, 4..=4).unwrap();
+    assert_eq!(other.balance("bob"), amount("35"));
+    assert_eq!(other.nonce("alice"), 2);
+    node.shutdown();
+    other.shutdown();
+}
+
+#[test]
+fn answers_queries_with_structured_results() {
+    let node = spawn(None);
+    create(&node, "alice", "12.5").unwrap();
+    node.mint();
+    let query = |request: Request| serde_json::from_str::<serde_json::Value>(&node.submit(request.structured()).unwrap()).unwrap();
+
+    let balance = query(Request::new_get_funds_request(NODE_ID.to_string(), "alice".to_string()));
+    assert_eq!(balance["balance"], "12.5");
+    let history = query(Request::new_history_request(NODE_ID.to_string(), "alice".to_string(), 1, 10));
+    assert_eq!(history["total"], 1);
+    assert_eq!(history["transactions"][0]["height"], 1);
+    let block = query(Request::new_get_block_request(NODE_ID.to_string(), Some(1), None));
+    assert_eq!(block["transactions"][0]["to"], "alice");
+    assert_eq!(block["more"], 0);
+    let status = query(Request::new_status_request(NODE_ID.to_string()));
+    assert_eq!(status["height"], 1);
+    assert_eq!(status["tip"], block["hash"]);
+    node.shutdown();
+}